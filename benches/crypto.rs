@@ -0,0 +1,213 @@
+use ark_ec::pairing::Pairing;
+use ark_ec::Group;
+use ark_ff::UniformRand;
+use ark_poly::univariate::DensePolynomial;
+use ark_poly::{DenseUVPolynomial, Polynomial};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rand::{rngs::StdRng, SeedableRng};
+#[cfg(feature = "networking")]
+use std::collections::HashMap;
+
+#[cfg(feature = "networking")]
+use pok3r::common::{Codec, EvalNetMsg};
+use pok3r::common::{Curve, Gt, F, G1, G2, KZG};
+#[cfg(feature = "networking")]
+use pok3r::encoding::encode_f_as_bs58_str;
+use pok3r::encoding::{decode_many, encode_many, PayloadValue};
+use pok3r::group_ops::{GtTable, GT_TABLE_WINDOW};
+use pok3r::hash::{card_id, hash_to_g1_ctx, hash_to_g1_ctx_many, GameContext, IdCache};
+#[cfg(feature = "networking")]
+use pok3r::keys::generate_keypair;
+#[cfg(feature = "networking")]
+use pok3r::network::{check_signature, sign_msg};
+
+const NUM_ELEMENTS: usize = 64;
+
+fn bench_kzg(c: &mut Criterion) {
+    let mut rng = StdRng::from_seed([0u8; 32]);
+    let pp = KZG::setup(128, &mut rng);
+    let mut group = c.benchmark_group("kzg");
+    for degree in [64, 128] {
+        let poly = DensePolynomial::<F>::rand(degree, &mut rng);
+        let point = F::rand(&mut rng);
+        let comm = KZG::commit_g1(&pp, &poly).unwrap();
+        let proof = KZG::compute_opening_proof(&pp, &poly, &point).unwrap();
+        let value = poly.evaluate(&point);
+
+        group.bench_with_input(BenchmarkId::new("commit", degree), &poly, |b, poly| {
+            b.iter(|| KZG::commit_g1(&pp, poly).unwrap())
+        });
+        group.bench_with_input(BenchmarkId::new("open", degree), &poly, |b, poly| {
+            b.iter(|| KZG::compute_opening_proof(&pp, poly, &point).unwrap())
+        });
+        group.bench_function(BenchmarkId::new("verify", degree), |b| {
+            b.iter(|| {
+                assert!(KZG::verify_opening_proof(
+                    &pp, &comm, &point, &value, &proof
+                ))
+            })
+        });
+    }
+    group.finish();
+}
+
+/// the batch sets the hasher up once for all the identities of a deck
+fn bench_hash_to_g1(c: &mut Criterion) {
+    let ctx = GameContext::new([1u8; 32]);
+    let ids = (0..NUM_ELEMENTS as u64)
+        .map(|i| card_id(&ctx, i, 0))
+        .collect::<Vec<Vec<u8>>>();
+    let mut group = c.benchmark_group("hash_to_g1");
+    group.throughput(Throughput::Elements(NUM_ELEMENTS as u64));
+    group.bench_function("single", |b| {
+        b.iter(|| {
+            ids.iter()
+                .map(|id| hash_to_g1_ctx(&ctx, id))
+                .collect::<Vec<G1>>()
+        })
+    });
+    group.bench_function("batch", |b| b.iter(|| hash_to_g1_ctx_many(&ctx, &ids)));
+    group.finish();
+}
+
+/// a batch of cards raising the generator of Gt, as the IBE encryption of a deck
+/// does; the table is built once per evaluator, so its cost is timed on its own
+fn bench_gt_exp(c: &mut Criterion) {
+    let mut rng = StdRng::from_seed([4u8; 32]);
+    let g = Gt::generator();
+    let table = GtTable::new(g, GT_TABLE_WINDOW);
+    let scalars = (0..NUM_ELEMENTS)
+        .map(|_| F::rand(&mut rng))
+        .collect::<Vec<F>>();
+    let mut group = c.benchmark_group("gt_exp");
+    group.bench_function("build_table", |b| {
+        b.iter(|| GtTable::new(g, GT_TABLE_WINDOW))
+    });
+    group.throughput(Throughput::Elements(NUM_ELEMENTS as u64));
+    group.bench_function("naive", |b| {
+        b.iter(|| scalars.iter().map(|s| g * s).collect::<Vec<Gt>>())
+    });
+    group.bench_function("table", |b| {
+        b.iter(|| scalars.iter().map(|s| table.mul(*s)).collect::<Vec<Gt>>())
+    });
+    group.finish();
+}
+
+/// the part of c2 that every identity of a deck needs at its encryption,
+/// e(H(id), pk)^r; a second shuffle of the game finds the pairings in the cache
+fn bench_id_cache(c: &mut Criterion) {
+    let mut rng = StdRng::from_seed([5u8; 32]);
+    let ctx = GameContext::new([1u8; 32]);
+    let pk = G2::rand(&mut rng);
+    let r = F::rand(&mut rng);
+    let ids = (0..NUM_ELEMENTS as u64)
+        .map(|i| card_id(&ctx, i, 0))
+        .collect::<Vec<Vec<u8>>>();
+    let mut warm = IdCache::new(ctx, NUM_ELEMENTS);
+    for id in ids.iter() {
+        let _ = warm.pairing(id, &pk);
+    }
+
+    let mut group = c.benchmark_group("id_cache");
+    group.throughput(Throughput::Elements(NUM_ELEMENTS as u64));
+    group.bench_function("uncached", |b| {
+        b.iter(|| {
+            ids.iter()
+                .map(|id| Curve::pairing(hash_to_g1_ctx(&ctx, id) * r, pk))
+                .collect::<Vec<Gt>>()
+        })
+    });
+    group.bench_function("first_shuffle", |b| {
+        b.iter(|| {
+            let mut cache = IdCache::new(ctx, NUM_ELEMENTS);
+            ids.iter()
+                .map(|id| cache.pairing(id, &pk) * r)
+                .collect::<Vec<Gt>>()
+        })
+    });
+    group.bench_function("second_shuffle", |b| {
+        b.iter(|| {
+            ids.iter()
+                .map(|id| warm.pairing(id, &pk) * r)
+                .collect::<Vec<Gt>>()
+        })
+    });
+    group.finish();
+}
+
+fn bench_encoding_of<T: PayloadValue>(c: &mut Criterion, name: &str, values: Vec<T>) {
+    let encoded = encode_many(&values);
+    let mut group = c.benchmark_group(format!("encoding/{}", name));
+    group.throughput(Throughput::Elements(values.len() as u64));
+    group.bench_function("encode", |b| b.iter(|| encode_many(black_box(&values))));
+    group.bench_function("decode", |b| {
+        b.iter(|| decode_many::<T>(black_box(&encoded)).unwrap())
+    });
+    group.finish();
+}
+
+fn bench_encoding(c: &mut Criterion) {
+    let mut rng = StdRng::from_seed([2u8; 32]);
+    let fs = (0..NUM_ELEMENTS).map(|_| F::rand(&mut rng)).collect();
+    let g1s = (0..NUM_ELEMENTS).map(|_| G1::rand(&mut rng)).collect();
+    let g2s = (0..NUM_ELEMENTS).map(|_| G2::rand(&mut rng)).collect();
+    let gts = (0..NUM_ELEMENTS)
+        .map(|_| Curve::pairing(G1::rand(&mut rng), G2::generator()))
+        .collect::<Vec<Gt>>();
+
+    bench_encoding_of::<F>(c, "scalar", fs);
+    bench_encoding_of::<G1>(c, "g1", g1s);
+    bench_encoding_of::<G2>(c, "g2", g2s);
+    bench_encoding_of(c, "gt", gts);
+}
+
+/// the check of the signature on a batch of values, which every party runs on
+/// every batch it receives
+#[cfg(feature = "networking")]
+fn bench_signature(c: &mut Criterion) {
+    let (keypair, peer_id) = generate_keypair(&mut StdRng::from_seed([6u8; 32]));
+    let peer_keys = HashMap::from([(peer_id.clone(), keypair.public())]);
+    let msg = sign_msg(
+        &keypair,
+        EvalNetMsg::PublishBatchValue {
+            session: String::from("bench"),
+            sender: peer_id,
+            seq: 0,
+            attempt: 0,
+            round: 0,
+            handles: (0..NUM_ELEMENTS).map(|i| format!("h{}", i)).collect(),
+            values: (0..NUM_ELEMENTS)
+                .map(|i| encode_f_as_bs58_str(&F::from(i as u64)))
+                .collect(),
+            codec: Codec::Plain,
+            payload: vec![],
+            signature: String::new(),
+        },
+    );
+    let mut group = c.benchmark_group("signature");
+    group.bench_function("check_batch", |b| {
+        b.iter(|| check_signature(black_box(&msg), &peer_keys).unwrap())
+    });
+    group.finish();
+}
+
+#[cfg(feature = "networking")]
+criterion_group!(
+    benches,
+    bench_kzg,
+    bench_hash_to_g1,
+    bench_gt_exp,
+    bench_id_cache,
+    bench_encoding,
+    bench_signature
+);
+#[cfg(not(feature = "networking"))]
+criterion_group!(
+    benches,
+    bench_kzg,
+    bench_hash_to_g1,
+    bench_gt_exp,
+    bench_id_cache,
+    bench_encoding
+);
+criterion_main!(benches);
@@ -0,0 +1,63 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use std::time::Duration;
+
+use pok3r::common::{ResourceEstimate, F};
+use pok3r::evaluator::{BatchConfig, EvaluatorBuilder};
+use pok3r::simulator::{run_prepared, setup_parties, Latency, SimEvaluator};
+use pok3r::wire::Wire;
+
+const NUM_WIRES: usize = 4096;
+
+/// three parties opening NUM_WIRES wires, chunk_size scalars per message
+fn setup(chunk_size: usize, latency: Latency) -> Vec<(SimEvaluator, Vec<Wire>)> {
+    setup_parties(3, 0, latency, || {
+        EvaluatorBuilder::new()
+            .preprocessing(ResourceEstimate::default())
+            .batch_config(BatchConfig {
+                scalar_chunk: chunk_size,
+                ..BatchConfig::default()
+            })
+    })
+    .into_iter()
+    .map(|mut evaluator| {
+        let xs = (0..NUM_WIRES as u64)
+            .map(|i| evaluator.fixed_wire_handle(F::from(i)))
+            .collect::<Vec<Wire>>();
+        (evaluator, xs)
+    })
+    .collect()
+}
+
+/// a single chunk sends everything before receiving anything, as batch_output_wire
+/// used to; smaller chunks overlap the transfer with the reconstruction, which
+/// only pays off once the links hold messages
+fn bench_batch_output_wire(c: &mut Criterion) {
+    for (name, latency) in [
+        ("no_latency", Latency::None),
+        ("1ms_latency", Latency::Fixed(Duration::from_millis(1))),
+    ] {
+        let mut group = c.benchmark_group(format!("batch_output_wire_4096_wires/{}", name));
+        group.sample_size(10);
+        for chunk_size in [64, 256, NUM_WIRES] {
+            group.bench_with_input(
+                BenchmarkId::from_parameter(chunk_size),
+                &chunk_size,
+                |b, &chunk_size| {
+                    b.iter_batched(
+                        || setup(chunk_size, latency),
+                        |parties| {
+                            run_prepared(parties, |(mut evaluator, xs)| async move {
+                                evaluator.batch_output_wire(&xs).await.unwrap()
+                            })
+                        },
+                        BatchSize::PerIteration,
+                    )
+                },
+            );
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_batch_output_wire);
+criterion_main!(benches);
@@ -0,0 +1,91 @@
+use ark_ff::UniformRand;
+use ark_poly::univariate::DensePolynomial;
+use ark_poly::DenseUVPolynomial;
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use rand::{rngs::StdRng, SeedableRng};
+
+use pok3r::common::{ProtocolParams, ResourceEstimate, F};
+use pok3r::evaluator::EvaluatorBuilder;
+use pok3r::shuffler::{compute_params, compute_permutation_argument, resources, shuffle_deck};
+use pok3r::simulator::{run_prepared, setup_parties, Latency, SimEvaluator};
+
+/// three parties, each holding shares of two polynomials of degree perm_size - 1
+fn setup_share_poly_mult() -> Vec<(SimEvaluator, DensePolynomial<F>, DensePolynomial<F>)> {
+    let params = ProtocolParams::default();
+    let resources = ResourceEstimate {
+        triples: 2 * params.perm_size(),
+        rand_sharings: 0,
+    };
+    setup_parties(3, 0, Latency::None, || {
+        EvaluatorBuilder::new()
+            .params(params)
+            .preprocessing(resources)
+    })
+    .into_iter()
+    .enumerate()
+    .map(|(i, evaluator)| {
+        let mut rng = StdRng::from_seed([i as u8; 32]);
+        let mut share_poly = || {
+            DensePolynomial::from_coefficients_vec(
+                (0..params.perm_size()).map(|_| F::rand(&mut rng)).collect(),
+            )
+        };
+        let (f, g) = (share_poly(), share_poly());
+        (evaluator, f, g)
+    })
+    .collect()
+}
+
+fn bench_share_poly_mult(c: &mut Criterion) {
+    let mut group = c.benchmark_group("share_poly_mult");
+    group.sample_size(10);
+    group.bench_function("3_parties", |b| {
+        b.iter_batched(
+            setup_share_poly_mult,
+            |parties| {
+                run_prepared(parties, |(mut evaluator, f, g)| async move {
+                    evaluator.share_poly_mult(f, g).await.unwrap()
+                })
+            },
+            BatchSize::PerIteration,
+        )
+    });
+    group.finish();
+}
+
+/// shuffles the default deck and proves the permutation, preprocessing untimed
+fn bench_shuffle(c: &mut Criterion) {
+    let pp = compute_params();
+    let params = ProtocolParams::default();
+    let mut group = c.benchmark_group("shuffle");
+    group.sample_size(10);
+    for n in [4, 8] {
+        group.bench_with_input(BenchmarkId::from_parameter(n), &n, |b, &n| {
+            b.iter_batched(
+                || {
+                    setup_parties(n, 0, Latency::None, || {
+                        EvaluatorBuilder::new()
+                            .params(params)
+                            .preprocessing(resources(&params))
+                    })
+                },
+                |parties| {
+                    run_prepared(parties, |mut evaluator| {
+                        let pp = pp.clone();
+                        async move {
+                            let cards = shuffle_deck(&mut evaluator).await.unwrap();
+                            compute_permutation_argument(&pp, &mut evaluator, &cards)
+                                .await
+                                .unwrap()
+                        }
+                    })
+                },
+                BatchSize::PerIteration,
+            )
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_share_poly_mult, bench_shuffle);
+criterion_main!(benches);
@@ -0,0 +1,65 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use std::time::Duration;
+
+use pok3r::common::{ResourceEstimate, F};
+use pok3r::evaluator::{EvaluatorBuilder, RandSource};
+use pok3r::simulator::{run_prepared, setup_parties, Latency, SimEvaluator};
+use pok3r::wire::Wire;
+
+const LATENCIES: [(&str, Latency); 2] = [
+    ("no_latency", Latency::None),
+    ("1ms_latency", Latency::Fixed(Duration::from_millis(1))),
+];
+
+/// two parties, each with inputs on num_wires wires and one triple per product;
+/// two parties cannot make triples jointly, so they take those of the shared seed
+fn setup(num_wires: usize, latency: Latency) -> Vec<(SimEvaluator, Vec<Wire>, Vec<Wire>)> {
+    let resources = ResourceEstimate {
+        triples: num_wires,
+        rand_sharings: 0,
+    };
+    setup_parties(2, 0, latency, || {
+        EvaluatorBuilder::new()
+            .preprocessing(resources)
+            .rand_source(RandSource::SharedSeed)
+    })
+    .into_iter()
+    .map(|mut evaluator| {
+        let xs = (0..num_wires as u64)
+            .map(|i| evaluator.fixed_wire_handle(F::from(i)))
+            .collect::<Vec<Wire>>();
+        let ys = (0..num_wires as u64)
+            .map(|i| evaluator.fixed_wire_handle(F::from(i + 1)))
+            .collect::<Vec<Wire>>();
+        (evaluator, xs, ys)
+    })
+    .collect()
+}
+
+fn bench_batch_mult(c: &mut Criterion) {
+    for (name, latency) in LATENCIES {
+        let mut group = c.benchmark_group(format!("batch_mult/{}", name));
+        group.sample_size(10);
+        for num_wires in [64, 1024, 8192] {
+            group.bench_with_input(
+                BenchmarkId::from_parameter(num_wires),
+                &num_wires,
+                |b, &num_wires| {
+                    b.iter_batched(
+                        || setup(num_wires, latency),
+                        |parties| {
+                            run_prepared(parties, |(mut evaluator, xs, ys)| async move {
+                                evaluator.batch_mult(&xs, &ys).await.unwrap()
+                            })
+                        },
+                        BatchSize::PerIteration,
+                    )
+                },
+            );
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_batch_mult);
+criterion_main!(benches);
@@ -0,0 +1,70 @@
+/*
+ * Memory: every input is a (pointer, length) pair of bytes owned by the caller.
+ * The library only reads it during the call and keeps no reference, so it may be
+ * freed as soon as the call returns. A null pointer is accepted only with a
+ * length of zero. The string pok3r_last_error_message returns is owned by the
+ * library and must not be freed; it stays valid until the next call into the
+ * library on the same thread.
+ */
+
+#ifndef POK3R_H
+#define POK3R_H
+
+/* Generated by cbindgen from src/ffi.rs; do not edit. */
+
+#include <stddef.h>
+#include <stdint.h>
+
+// the proof holds
+#define POK3R_VALID 1
+
+// the proof was read but does not hold
+#define POK3R_INVALID 0
+
+// the call failed; pok3r_last_error_message tells why
+#define POK3R_ERROR -1
+
+// Checks a PermutationProof, that its f_com commits to a shuffle of the deck.
+// Returns POK3R_VALID, POK3R_INVALID, or POK3R_ERROR for malformed input.
+//
+// # Safety
+// Each pointer must be null with a length of zero, or point to that many
+// readable bytes. The buffers stay owned by the caller.
+int32_t pok3r_verify_permutation_proof(const uint8_t *proof_ptr,
+                                       size_t proof_len,
+                                       const uint8_t *params_ptr,
+                                       size_t params_len);
+
+// Checks an EncryptionProof, that the ciphertext encrypts the cards its
+// card_commitment holds under the identities of the game game_id_ptr points to,
+// 32 bytes long. Returns POK3R_VALID, POK3R_INVALID, or POK3R_ERROR for
+// malformed input.
+//
+// # Safety
+// Each pointer must be null with a length of zero, or point to that many
+// readable bytes, and game_id_ptr to 32 readable bytes. The buffers stay owned
+// by the caller.
+int32_t pok3r_verify_encryption_proof(const uint8_t *proof_ptr,
+                                      size_t proof_len,
+                                      const uint8_t *ciphertext_ptr,
+                                      size_t ciphertext_len,
+                                      const uint8_t *game_id_ptr,
+                                      const uint8_t *params_ptr,
+                                      size_t params_len);
+
+// Decodes an opened card value. Returns the index of the card, from 0 for the
+// two of clubs to 51 for the ace of spades, or POK3R_ERROR if the value is
+// malformed, padding or no card.
+//
+// # Safety
+// f_ptr must be null with f_len zero, or point to f_len readable bytes. The
+// buffer stays owned by the caller.
+int32_t pok3r_decode_card(const uint8_t *f_ptr, size_t f_len);
+
+// The message of the last call on this thread that returned POK3R_ERROR, as a
+// nul-terminated UTF-8 string, or null if that call succeeded. The string is
+// owned by the library: it must not be freed, and stays valid until the next
+// call into the library on the same thread.
+const char *pok3r_last_error_message(void);
+
+#endif  /* POK3R_H */
@@ -0,0 +1,265 @@
+//! Evidence against a party that deviated from the protocol. Evidence holds the
+//! values the party broadcast, as they went over the wire, so that every other
+//! party received the same values and can check the evidence on its own.
+
+use ark_ec::pairing::PairingOutput;
+use ark_ec::CurveGroup;
+use serde::{Deserialize, Serialize};
+
+use crate::common::{CurveConfig, KzgOn, PayloadKind};
+use crate::encoding::{decode_bs58_str_as, DecodeError};
+use crate::evaluator::open_commitment;
+use crate::kzg::UniversalParams;
+use crate::reconstruct::Shared;
+use crate::shamir;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Evidence {
+    /// in OpenMode::Committed, the party opened handle to something else than what
+    /// it had committed to
+    CommitmentMismatch {
+        handle: String,
+        commitment: String,
+        opening: String,
+    },
+    /// the party's shares of a commitment, of its evaluation at point and of the
+    /// opening proof under handle do not satisfy the KZG equation by themselves.
+    /// Every share is linear in the party's share of the polynomial, so an honest
+    /// party's shares always do.
+    OpeningShare {
+        handle: String,
+        point: String,
+        commitment: String,
+        value: String,
+        proof: String,
+    },
+    /// in threshold mode, the share node_id sent under handle after the opening was
+    /// done does not lie on the degree-t sharing through the t + 1 shares it was
+    /// opened from. Either node_id or one of those parties lied, and the evidence
+    /// does not tell which.
+    InconsistentShare {
+        handle: String,
+        kind: PayloadKind,
+        reference: Vec<(u64, String)>,
+        node_id: u64,
+        share: String,
+    },
+    /// the party broadcast share under handle, which does not decode as the kind
+    /// of value opened
+    MalformedShare {
+        handle: String,
+        kind: PayloadKind,
+        share: String,
+    },
+}
+
+impl Evidence {
+    /// the handle of the opening the party cheated in
+    pub fn handle(&self) -> &str {
+        match self {
+            Evidence::CommitmentMismatch { handle, .. } => handle,
+            Evidence::OpeningShare { handle, .. } => handle,
+            Evidence::InconsistentShare { handle, .. } => handle,
+            Evidence::MalformedShare { handle, .. } => handle,
+        }
+    }
+
+    /// whether the evidence shows misbehavior, for evidence of an evaluator on E
+    pub fn verify<E: CurveConfig>(&self, pp: &UniversalParams<E>) -> bool {
+        match self {
+            Evidence::CommitmentMismatch {
+                commitment,
+                opening,
+                ..
+            } => open_commitment(opening, commitment).is_none(),
+            Evidence::OpeningShare {
+                point,
+                commitment,
+                value,
+                proof,
+                ..
+            } => {
+                let decoded = (
+                    decode_bs58_str_as::<E::ScalarField>(point),
+                    decode_bs58_str_as::<E::G1>(commitment),
+                    decode_bs58_str_as::<E::ScalarField>(value),
+                    decode_bs58_str_as::<E::G1>(proof),
+                );
+                match decoded {
+                    (Ok(point), Ok(commitment), Ok(value), Ok(proof)) => {
+                        !KzgOn::<E>::verify_opening_proof(
+                            pp,
+                            &commitment.into_affine(),
+                            &point,
+                            &value,
+                            &proof.into_affine(),
+                        )
+                    }
+                    _ => false,
+                }
+            }
+            Evidence::InconsistentShare {
+                kind,
+                reference,
+                node_id,
+                share,
+                ..
+            } => share_on_sharing::<E>(*kind, reference, *node_id, share) == Some(false),
+            Evidence::MalformedShare { kind, share, .. } => !decodes::<E>(*kind, share),
+        }
+    }
+}
+
+/// whether share decodes as the kind of value
+fn decodes<E: CurveConfig>(kind: PayloadKind, share: &str) -> bool {
+    match kind {
+        PayloadKind::Scalar => decode_bs58_str_as::<E::ScalarField>(share).is_ok(),
+        PayloadKind::G1 => decode_bs58_str_as::<E::G1>(share).is_ok(),
+        PayloadKind::G2 => decode_bs58_str_as::<E::G2>(share).is_ok(),
+        PayloadKind::Gt => decode_bs58_str_as::<PairingOutput<E>>(share).is_ok(),
+    }
+}
+
+/// whether share, as node_id's share of the kind of value, lies on the sharing
+/// through reference; None if some share does not decode
+pub(crate) fn share_on_sharing<E: CurveConfig>(
+    kind: PayloadKind,
+    reference: &[(u64, String)],
+    node_id: u64,
+    share: &str,
+) -> Option<bool> {
+    match kind {
+        PayloadKind::Scalar => on_sharing(
+            reference,
+            node_id,
+            share,
+            decode_bs58_str_as::<E::ScalarField>,
+        ),
+        PayloadKind::G1 => on_sharing(reference, node_id, share, decode_bs58_str_as::<E::G1>),
+        PayloadKind::G2 => on_sharing(reference, node_id, share, decode_bs58_str_as::<E::G2>),
+        PayloadKind::Gt => on_sharing(
+            reference,
+            node_id,
+            share,
+            decode_bs58_str_as::<PairingOutput<E>>,
+        ),
+    }
+}
+
+fn on_sharing<V>(
+    reference: &[(u64, String)],
+    node_id: u64,
+    share: &str,
+    decode: impl Fn(&str) -> Result<V, DecodeError>,
+) -> Option<bool>
+where
+    V: Shared + PartialEq,
+{
+    let ids = reference.iter().map(|(id, _)| *id).collect::<Vec<u64>>();
+    if ids.contains(&node_id) {
+        return None;
+    }
+    let mut expected = V::zero();
+    for ((_, value), coeff) in reference
+        .iter()
+        .zip(shamir::lagrange_coeffs_at::<V::Scalar>(&ids, node_id))
+    {
+        expected = expected + decode(value).ok()?.scale(coeff);
+    }
+    Some(decode(share).ok()? == expected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Evidence;
+    use crate::common::{PayloadKind, F, G1};
+    use crate::encoding::{encode_as_bytes, encode_f_as_bs58_str, encode_g1_as_bs58_str};
+    use crate::evaluator::commit_to_share;
+    use crate::shamir;
+    use crate::shuffler::compute_params;
+    use ark_ec::Group;
+    use ark_std::UniformRand;
+
+    #[test]
+    fn test_evidence_checks_out_only_against_cheaters() {
+        let pp = compute_params();
+        let share = encode_as_bytes(&F::from(5u64));
+        let salt = [3u8; 32];
+        let commitment = commit_to_share(&share, &salt);
+        let opening = |share: &[u8]| {
+            format!(
+                "{}.{}",
+                bs58::encode(share).into_string(),
+                bs58::encode(salt).into_string()
+            )
+        };
+
+        let honest = Evidence::CommitmentMismatch {
+            handle: String::from("x"),
+            commitment: commitment.clone(),
+            opening: opening(&share),
+        };
+        assert!(!honest.verify(&pp));
+        let cheat = Evidence::CommitmentMismatch {
+            handle: String::from("x"),
+            commitment,
+            opening: opening(&encode_as_bytes(&F::from(4u64))),
+        };
+        assert!(cheat.verify(&pp));
+
+        // the zero polynomial opens to zero anywhere, with the identity as proof
+        let zero = encode_g1_as_bs58_str(&G1::default());
+        let opening_share = |value: u64| Evidence::OpeningShare {
+            handle: String::from("pi"),
+            point: encode_f_as_bs58_str(&F::from(7u64)),
+            commitment: zero.clone(),
+            value: encode_f_as_bs58_str(&F::from(value)),
+            proof: zero.clone(),
+        };
+        assert!(!opening_share(0).verify(&pp));
+        assert!(opening_share(1).verify(&pp));
+
+        let malformed = Evidence::OpeningShare {
+            handle: String::from("pi"),
+            point: String::from("not a point"),
+            commitment: encode_g1_as_bs58_str(&G1::generator()),
+            value: encode_f_as_bs58_str(&F::from(1u64)),
+            proof: zero,
+        };
+        assert!(!malformed.verify(&pp));
+    }
+
+    #[test]
+    fn test_inconsistent_share_evidence() {
+        let pp = compute_params();
+        let mut rng = rand::thread_rng();
+        let shares = shamir::share(&F::rand(&mut rng), (2, 4), &mut rng);
+        let encoded = |y: F| encode_f_as_bs58_str(&y);
+        let reference = vec![(1, encoded(shares[0].1)), (2, encoded(shares[1].1))];
+        let evidence = |share: F| Evidence::InconsistentShare {
+            handle: String::from("x"),
+            kind: PayloadKind::Scalar,
+            reference: reference.clone(),
+            node_id: 4,
+            share: encoded(share),
+        };
+
+        assert!(!evidence(shares[3].1).verify(&pp));
+        assert!(evidence(shares[3].1 + F::from(1u64)).verify(&pp));
+    }
+
+    #[test]
+    fn test_malformed_share_evidence() {
+        let pp = compute_params();
+        let evidence = |kind: PayloadKind, share: String| Evidence::MalformedShare {
+            handle: String::from("x"),
+            kind,
+            share,
+        };
+
+        let scalar = encode_f_as_bs58_str(&F::from(5u64));
+        assert!(!evidence(PayloadKind::Scalar, scalar.clone()).verify(&pp));
+        assert!(evidence(PayloadKind::G1, scalar).verify(&pp));
+        assert!(evidence(PayloadKind::Scalar, String::from("not a share")).verify(&pp));
+    }
+}
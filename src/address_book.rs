@@ -1,7 +1,21 @@
-use serde_json::json;
-use std::{collections::HashMap, fmt};
+use libp2p::{identity, Multiaddr};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    error, fmt, fs, io,
+    path::Path,
+};
 
-pub const ADDRESSES: [&str; 32] = [
+/// largest committee an addr book may describe
+pub const MAX_PARTIES: usize = 256;
+
+/// smallest committee an addr book file may describe: a single member would hold
+/// every secret in the clear
+pub const MIN_COMMITTEE: usize = 2;
+
+/// built-in peers for tests and local runs: ADDRESSES[i] is the peer id of
+/// keypair_from_seed(i + 1)
+pub const ADDRESSES: &[&str] = &[
     "12D3KooWPjceQrSwdWXPyLLeABRXmuqt69Rg3sBYbU1Nft9HyQ6X",
     "12D3KooWH3uVF6wv47WnArKHk5p6cvgCJEb74UTmxztmQDc298L3",
     "12D3KooWQYhTNQdmr3ArTeUHRYzFg94BKyTkoWBDWez9kSCVe2Xo",
@@ -34,140 +48,59 @@ pub const ADDRESSES: [&str; 32] = [
     "12D3KooWNjR7M1659fBQXPpEs9tj959tgpD5T118vLojZKci9d4x",
     "12D3KooWLcqHxG25dqsQqZAPz2zofcLrDga83pzsKAxy1G7GVbzg",
     "12D3KooWDrAvsiX8hM5yVpDMrPEwSFRfQguLdBCVKgsYbVnqk2P4",
+    "12D3KooWPEF7YrJx5bNKRr57s45UmEBV4pzpND2bpZDVZLzxsYLi",
+    "12D3KooWMAXwrRcBdK3hFECY7b69PVW5rfHRa2WQPmbmMezZnEVG",
+    "12D3KooWPMogJdb3k6PsLyaKwUXLmQJ2GBFTo656pSpGjAjHcfp9",
+    "12D3KooWG7n1i8ZaMpj8d4UanqU6bnccmxkG1xgXsZWUE9191MZS",
+    "12D3KooWKVWTrj63w9fYjPB8g5tGMyXDzaYJXX57gBMpWSc6rJiw",
+    "12D3KooWHnBg5VSrypsNtoct6DGmd5CWg9ihxo9hxHXzxUYru3rw",
+    "12D3KooWNzjpBvGcuFM3mGmDigzoyACZunz9qNieTbZaMWaC31uY",
+    "12D3KooWQQmeaydZewRjdG1GUo8wrVSm6N9oigxjh769pPtGT3rp",
+    "12D3KooWSahP5pFRCEfaziPEba7urXGeif6T1y8jmodzdFUvzBHj",
+    "12D3KooWR2KSRQWyanR1dPvnZkXt296xgf3FFn8135szya3zYYwY",
+    "12D3KooWBgJMyM6Akfx5hZcaa3F6zXVCpQykNXGqs96pDi4L71DR",
+    "12D3KooWSY3udBzEcr8m838kxdcAZESH4jAmTvdvMKGgPNiQyJwu",
+    "12D3KooWRrGbJ2SCwvmhLi3ESnAuEehg5A1UXzsLSNF6auKYNcks",
+    "12D3KooWCPq8audTqV5k7W76JuNNSdpvU3fsMs42PkJY5hz3mu5T",
+    "12D3KooWEGy5nh4CaFhiqbgvF31XmKwTa54a8XtFJoNz7yEBaBrP",
+    "12D3KooWA768LzHMatxkjD1f9DrYW375GZJr6MHPCNEdDtHeTNRt",
+    "12D3KooWRhFCXBhmsMnur3up3vJsDoqWh4c39PKXgSWwzAzDHNLn",
+    "12D3KooWFFehYddGiX86tLFYPQ7BvWxhz6jNq4zQTBKgAGjDhuD3",
+    "12D3KooWJj8KtUk7ie25RzJWikPXrEXmkWWLcC7MrD27PZZcmChi",
+    "12D3KooWL3Q1jWvi5NNQAayzx5LCQr8SbnhGGAR6FBbh3zedzzNb",
+    "12D3KooWMi16FDcmYbWsZ3WpWsLozmyz1X32CRisZoo5HzfUQnPn",
+    "12D3KooWFV5G2smxejwXkXrHh8jqbqkPWdTHAwjfanWpmfDQLoaa",
+    "12D3KooWCM74tY32ueDPKwEoqzdgdgSttSXt4vNkcUqE7v1BRGPK",
+    "12D3KooWD8ws6HaggH9viHgi7FuCm4MdbAehiALBSUdcojPbD2i9",
+    "12D3KooWHj7FJaFfC7ppoN2dnbUN1rfJq7BvSBzGXns5c5uXAhDM",
+    "12D3KooWR8Ve6aQQRRnvfP9XzAYBL1fCybKc2eMmbiKY4eY9Bhzf",
+    "12D3KooWAoztSYrzkFDTt7gc4dEyHnEgFi5HNfdjwjWn198e159K",
+    "12D3KooWGFtv2Za5hLSpdc5piWKqgDvHJnydRoctVHhf6NDuZUEs",
+    "12D3KooWNn92KJu4UCdp7WnqDrWhhXzAz1qknXvJYNNVgoNJPJpV",
+    "12D3KooWSK6f2ZJLRX8Q3LiuVnj9y3yXqJgFguJh7gdjtsSomnS8",
+    "12D3KooWHV2zfje5uXRV5nPsqArHdrVrh7GaAJVyhwr8ffZZ16om",
 ];
 
-/*
-Seed 1 peer id: 12D3KooWPjceQrSwdWXPyLLeABRXmuqt69Rg3sBYbU1Nft9HyQ6X
-Seed 2 peer id: 12D3KooWH3uVF6wv47WnArKHk5p6cvgCJEb74UTmxztmQDc298L3
-Seed 3 peer id: 12D3KooWQYhTNQdmr3ArTeUHRYzFg94BKyTkoWBDWez9kSCVe2Xo
-Seed 4 peer id: 12D3KooWLJtG8fd2hkQzTn96MrLvThmnNQjTUFZwGEsLRz5EmSzc
-Seed 5 peer id: 12D3KooWSHj3RRbBjD15g6wekV8y3mm57Pobmps2g2WJm6F67Lay
-Seed 6 peer id: 12D3KooWDMCQbZZvLgHiHntG1KwcHoqHPAxL37KvhgibWqFtpqUY
-Seed 7 peer id: 12D3KooWLnZUpcaBwbz9uD1XsyyHnbXUrJRmxnsMiRnuCmvPix67
-Seed 8 peer id: 12D3KooWQ8vrERR8bnPByEjjtqV6hTWehaf8TmK7qR1cUsyrPpfZ
-Seed 9 peer id: 12D3KooWNRk8VBuTJTYyTbnJC7Nj2UN5jij4dJMo8wtSGT2hRzRP
-Seed 10 peer id: 12D3KooWFHNBwTxUgeHRcD3g4ieiXBmZGVyp6TKGWRKKEqYgCC1C
-Seed 11 peer id: 12D3KooWHbEputWi1fJAxoYgmvvDe3yP7acTACqmXKGYwMgN2daQ
-Seed 12 peer id: 12D3KooWCxnyz1JxC9y1RniRQVFe2cLaLHsYNc2SnXbM7yq5JBbJ
-Seed 13 peer id: 12D3KooWFNisMCMFB4sxKjQ4VLoTrMYh7fUJqXr1FMwhqAwfdxPS
-Seed 14 peer id: 12D3KooW9ubkfzRCQrUvcgvSqL2Cpri5pPV9DuyoHptvshVcNE9h
-Seed 15 peer id: 12D3KooWRVJCFqFBrasjtcGHnRuuut9fQLsfcUNLfWFFqjMm2p4n
-Seed 16 peer id: 12D3KooWGtVQAq3A8GPyq5ZuwBoE4V278EkDpETijz1dm7cY4LsG
-Seed 17 peer id: 12D3KooWGjxVp88DuWx6P6cN5ZLtud51TNWK6a7K1h9cYb8qDuci
-Seed 18 peer id: 12D3KooWDWC9G1REgGwHTzVNtXL8x6okkRQzsYb7V9mw9UGKhC1H
-Seed 19 peer id: 12D3KooWE92WS4t4UBFxryqsx78hSaFaZMLaAkRwkynjsL1mdt8h
-Seed 20 peer id: 12D3KooWPcbijTPjNkihfs3DcJiMb1iQC1B2BCzP3vSggGvUgZsC
-Seed 21 peer id: 12D3KooWE1hRi1pECQ6bfxmeybMFEtYcTjJuhjxc75dZZLXwrdwy
-Seed 22 peer id: 12D3KooWCxkD42pVy9VZXGPQgBmL2ekc9kxME5YwriN3xTN6aBMx
-Seed 23 peer id: 12D3KooWFYZ24pnTgzhPJmznbMQTv8g9xdJANuM8wjkbCGrhWDvP
-Seed 24 peer id: 12D3KooWSM6emJRiK1AzUG39eFW42k8AUKLCk3fTFLh7GU1hPMFs
-Seed 25 peer id: 12D3KooWM7du63Ft3U51pDpJqNyiGRVU3Us2f4iuiwUEyxsB5P2M
-Seed 26 peer id: 12D3KooWCTvrtiEPSzY2UixVRuxVc81TGZjYHGU8YkJ7wuBrRRU8
-Seed 27 peer id: 12D3KooWNLMpwyVysPSUj93RqpTDMxv5V9AsXc7NPgZPRUg4qD28
-Seed 28 peer id: 12D3KooWJQK2dHWVMKPm9e1RPYgtQeix1hmS84B87rzhCP3uBep1
-Seed 29 peer id: 12D3KooWP37FF5aY62MjcP5UJr1e3KJyu9cuARGFnFnTEkVdz6eh
-Seed 30 peer id: 12D3KooWNjR7M1659fBQXPpEs9tj959tgpD5T118vLojZKci9d4x
-Seed 31 peer id: 12D3KooWLcqHxG25dqsQqZAPz2zofcLrDga83pzsKAxy1G7GVbzg
-Seed 32 peer id: 12D3KooWDrAvsiX8hM5yVpDMrPEwSFRfQguLdBCVKgsYbVnqk2P4
-Seed 33 peer id: 12D3KooWPEF7YrJx5bNKRr57s45UmEBV4pzpND2bpZDVZLzxsYLi
-Seed 34 peer id: 12D3KooWMAXwrRcBdK3hFECY7b69PVW5rfHRa2WQPmbmMezZnEVG
-Seed 35 peer id: 12D3KooWPMogJdb3k6PsLyaKwUXLmQJ2GBFTo656pSpGjAjHcfp9
-Seed 36 peer id: 12D3KooWG7n1i8ZaMpj8d4UanqU6bnccmxkG1xgXsZWUE9191MZS
-Seed 37 peer id: 12D3KooWKVWTrj63w9fYjPB8g5tGMyXDzaYJXX57gBMpWSc6rJiw
-Seed 38 peer id: 12D3KooWHnBg5VSrypsNtoct6DGmd5CWg9ihxo9hxHXzxUYru3rw
-Seed 39 peer id: 12D3KooWNzjpBvGcuFM3mGmDigzoyACZunz9qNieTbZaMWaC31uY
-Seed 40 peer id: 12D3KooWQQmeaydZewRjdG1GUo8wrVSm6N9oigxjh769pPtGT3rp
-Seed 41 peer id: 12D3KooWSahP5pFRCEfaziPEba7urXGeif6T1y8jmodzdFUvzBHj
-Seed 42 peer id: 12D3KooWR2KSRQWyanR1dPvnZkXt296xgf3FFn8135szya3zYYwY
-Seed 43 peer id: 12D3KooWBgJMyM6Akfx5hZcaa3F6zXVCpQykNXGqs96pDi4L71DR
-Seed 44 peer id: 12D3KooWSY3udBzEcr8m838kxdcAZESH4jAmTvdvMKGgPNiQyJwu
-Seed 45 peer id: 12D3KooWRrGbJ2SCwvmhLi3ESnAuEehg5A1UXzsLSNF6auKYNcks
-Seed 46 peer id: 12D3KooWCPq8audTqV5k7W76JuNNSdpvU3fsMs42PkJY5hz3mu5T
-Seed 47 peer id: 12D3KooWEGy5nh4CaFhiqbgvF31XmKwTa54a8XtFJoNz7yEBaBrP
-Seed 48 peer id: 12D3KooWA768LzHMatxkjD1f9DrYW375GZJr6MHPCNEdDtHeTNRt
-Seed 49 peer id: 12D3KooWRhFCXBhmsMnur3up3vJsDoqWh4c39PKXgSWwzAzDHNLn
-Seed 50 peer id: 12D3KooWFFehYddGiX86tLFYPQ7BvWxhz6jNq4zQTBKgAGjDhuD3
-Seed 51 peer id: 12D3KooWJj8KtUk7ie25RzJWikPXrEXmkWWLcC7MrD27PZZcmChi
-Seed 52 peer id: 12D3KooWL3Q1jWvi5NNQAayzx5LCQr8SbnhGGAR6FBbh3zedzzNb
-Seed 53 peer id: 12D3KooWMi16FDcmYbWsZ3WpWsLozmyz1X32CRisZoo5HzfUQnPn
-Seed 54 peer id: 12D3KooWFV5G2smxejwXkXrHh8jqbqkPWdTHAwjfanWpmfDQLoaa
-Seed 55 peer id: 12D3KooWCM74tY32ueDPKwEoqzdgdgSttSXt4vNkcUqE7v1BRGPK
-Seed 56 peer id: 12D3KooWD8ws6HaggH9viHgi7FuCm4MdbAehiALBSUdcojPbD2i9
-Seed 57 peer id: 12D3KooWHj7FJaFfC7ppoN2dnbUN1rfJq7BvSBzGXns5c5uXAhDM
-Seed 58 peer id: 12D3KooWR8Ve6aQQRRnvfP9XzAYBL1fCybKc2eMmbiKY4eY9Bhzf
-Seed 59 peer id: 12D3KooWAoztSYrzkFDTt7gc4dEyHnEgFi5HNfdjwjWn198e159K
-Seed 60 peer id: 12D3KooWGFtv2Za5hLSpdc5piWKqgDvHJnydRoctVHhf6NDuZUEs
-Seed 61 peer id: 12D3KooWNn92KJu4UCdp7WnqDrWhhXzAz1qknXvJYNNVgoNJPJpV
-Seed 62 peer id: 12D3KooWSK6f2ZJLRX8Q3LiuVnj9y3yXqJgFguJh7gdjtsSomnS8
-Seed 63 peer id: 12D3KooWHV2zfje5uXRV5nPsqArHdrVrh7GaAJVyhwr8ffZZ16om
-*/
-
-pub fn parse_addr_book_from_json(num_parties: u64) -> Pok3rAddrBook {
-    let config = json!({
-        "addr_book": [ //addr_book is a list of ed25519 pubkeys
-            ADDRESSES[0],  //"12D3KooWPjceQrSwdWXPyLLeABRXmuqt69Rg3sBYbU1Nft9HyQ6X",
-            ADDRESSES[1],  //"12D3KooWH3uVF6wv47WnArKHk5p6cvgCJEb74UTmxztmQDc298L3",
-            ADDRESSES[2],  //"12D3KooWQYhTNQdmr3ArTeUHRYzFg94BKyTkoWBDWez9kSCVe2Xo",
-            ADDRESSES[3],  //"12D3KooWLJtG8fd2hkQzTn96MrLvThmnNQjTUFZwGEsLRz5EmSzc",
-            ADDRESSES[4],  //"12D3KooWSHj3RRbBjD15g6wekV8y3mm57Pobmps2g2WJm6F67Lay",
-            ADDRESSES[5],  //"12D3KooWDMCQbZZvLgHiHntG1KwcHoqHPAxL37KvhgibWqFtpqUY",
-            ADDRESSES[6],  //"12D3KooWLnZUpcaBwbz9uD1XsyyHnbXUrJRmxnsMiRnuCmvPix67",
-            ADDRESSES[7],  //"12D3KooWQ8vrERR8bnPByEjjtqV6hTWehaf8TmK7qR1cUsyrPpfZ",
-            ADDRESSES[8],  //"12D3KooWNRk8VBuTJTYyTbnJC7Nj2UN5jij4dJMo8wtSGT2hRzRP",
-            ADDRESSES[9],  //"12D3KooWFHNBwTxUgeHRcD3g4ieiXBmZGVyp6TKGWRKKEqYgCC1C",
-            ADDRESSES[10],  //"12D3KooWHbEputWi1fJAxoYgmvvDe3yP7acTACqmXKGYwMgN2daQ",
-            ADDRESSES[11],  //"12D3KooWCxnyz1JxC9y1RniRQVFe2cLaLHsYNc2SnXbM7yq5JBbJ",
-            ADDRESSES[12],  //"12D3KooWFNisMCMFB4sxKjQ4VLoTrMYh7fUJqXr1FMwhqAwfdxPS",
-            ADDRESSES[13],  //"12D3KooW9ubkfzRCQrUvcgvSqL2Cpri5pPV9DuyoHptvshVcNE9h",
-            ADDRESSES[14],  //"12D3KooWRVJCFqFBrasjtcGHnRuuut9fQLsfcUNLfWFFqjMm2p4n",
-            ADDRESSES[15],  //"12D3KooWGtVQAq3A8GPyq5ZuwBoE4V278EkDpETijz1dm7cY4LsG",
-            ADDRESSES[16],  //"12D3KooWGjxVp88DuWx6P6cN5ZLtud51TNWK6a7K1h9cYb8qDuci",
-            ADDRESSES[17],  //"12D3KooWDWC9G1REgGwHTzVNtXL8x6okkRQzsYb7V9mw9UGKhC1H",
-            ADDRESSES[18],  //"12D3KooWE92WS4t4UBFxryqsx78hSaFaZMLaAkRwkynjsL1mdt8h",
-            ADDRESSES[19],  //"12D3KooWPcbijTPjNkihfs3DcJiMb1iQC1B2BCzP3vSggGvUgZsC",
-            ADDRESSES[20],  //"12D3KooWE1hRi1pECQ6bfxmeybMFEtYcTjJuhjxc75dZZLXwrdwy",
-            ADDRESSES[21],  //"12D3KooWCxkD42pVy9VZXGPQgBmL2ekc9kxME5YwriN3xTN6aBMx",
-            ADDRESSES[22],  //"12D3KooWFYZ24pnTgzhPJmznbMQTv8g9xdJANuM8wjkbCGrhWDvP",
-            ADDRESSES[23],  //"12D3KooWSM6emJRiK1AzUG39eFW42k8AUKLCk3fTFLh7GU1hPMFs",
-            ADDRESSES[24],  //"12D3KooWM7du63Ft3U51pDpJqNyiGRVU3Us2f4iuiwUEyxsB5P2M",
-            ADDRESSES[25],  //"12D3KooWCTvrtiEPSzY2UixVRuxVc81TGZjYHGU8YkJ7wuBrRRU8",
-            ADDRESSES[26],  //"12D3KooWNLMpwyVysPSUj93RqpTDMxv5V9AsXc7NPgZPRUg4qD28",
-            ADDRESSES[27],  //"12D3KooWJQK2dHWVMKPm9e1RPYgtQeix1hmS84B87rzhCP3uBep1",
-            ADDRESSES[28],  //"12D3KooWP37FF5aY62MjcP5UJr1e3KJyu9cuARGFnFnTEkVdz6eh",
-            ADDRESSES[29],  //"12D3KooWNjR7M1659fBQXPpEs9tj959tgpD5T118vLojZKci9d4x",
-            ADDRESSES[30],  //"12D3KooWLcqHxG25dqsQqZAPz2zofcLrDga83pzsKAxy1G7GVbzg",
-            ADDRESSES[31],  //"12D3KooWDrAvsiX8hM5yVpDMrPEwSFRfQguLdBCVKgsYbVnqk2P4",
-        ]
-    });
-    let peers: Vec<String> = config["addr_book"]
-        .as_array()
-        .unwrap()
-        .iter()
-        .map(|o| String::from(o.as_str().unwrap()))
-        .collect();
-
-    let mut output: Pok3rAddrBook = HashMap::new();
-    let mut counter = 1;
-    for peer in &peers[0..num_parties as usize] {
-        let pok3rpeer = Pok3rPeer {
-            peer_id: peer.to_owned(),
-            node_id: counter,
-        };
-
-        output.insert(peer.to_owned(), pok3rpeer);
-        counter += 1;
-    }
-
-    output
+/// builds the addr book of the built-in peers of seeds 1..=num_parties, for tests
+pub fn parse_addr_book_from_json(num_parties: u64) -> Result<Pok3rAddrBook, AddrBookError> {
+    let peers = ADDRESSES
+        .get(..num_parties as usize)
+        .ok_or(AddrBookError::NotEnoughBuiltinPeers(num_parties))?;
+    AddrBook::from_peer_ids(peers.iter().map(|peer_id| peer_id.to_string()))
 }
 
 pub type Pok3rPeerId = String;
 
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Pok3rPeer {
     // base58 encoding of ed25519 pub key
     pub peer_id: Pok3rPeerId,
-    // unique index between 1 and size of addr book (not used in SPDZ)
+    // unique index between 1 and size of addr book (not used in SPDZ); players
+    // are numbered after the committee
     pub node_id: u64,
+    // where to dial the peer; left to mdns discovery when absent
+    pub multiaddr: Option<Multiaddr>,
+    pub role: PeerRole,
 }
 
 impl fmt::Display for Pok3rPeer {
@@ -176,8 +109,741 @@ impl fmt::Display for Pok3rPeer {
     }
 }
 
-pub type Pok3rAddrBook = HashMap<Pok3rPeerId, Pok3rPeer>;
+/// what a peer does at the table
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PeerRole {
+    /// holds shares and takes part in every protocol
+    #[default]
+    Committee,
+    /// a client at the table: it holds no shares, and only receives what is
+    /// sent to it, such as its hole cards
+    Player,
+    /// follows the published values and proofs of a game, but holds no shares
+    /// and is never waited on
+    Verifier,
+}
+
+/// AddrBook maps peer ids to node ids and back; iteration goes by node id,
+/// so that it is the same at every party. Players and verifiers are listed
+/// apart: they hold no shares, so len, get, contains, node_of, peer_of and the
+/// iterators only cover the committee.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "Vec<AddrBookEntry>", into = "Vec<AddrBookEntry>")]
+pub struct AddrBook {
+    by_peer: HashMap<Pok3rPeerId, Pok3rPeer>,
+    by_node: BTreeMap<u64, Pok3rPeerId>,
+    /// players, by node id
+    players: BTreeMap<u64, Pok3rPeer>,
+    /// verifiers and where to dial them
+    verifiers: BTreeMap<Pok3rPeerId, Option<Multiaddr>>,
+}
+
+pub type Pok3rAddrBook = AddrBook;
+
+impl AddrBook {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// adds peer to the committee, replacing whatever was listed under its peer id
+    /// or node id
+    pub fn insert(&mut self, peer: Pok3rPeer) {
+        if let Some(old) = self.by_peer.remove(&peer.peer_id) {
+            self.by_node.remove(&old.node_id);
+        }
+        if let Some(old) = self.by_node.insert(peer.node_id, peer.peer_id.clone()) {
+            self.by_peer.remove(&old);
+        }
+        self.by_peer.insert(peer.peer_id.clone(), peer);
+    }
+
+    pub fn get(&self, peer_id: &Pok3rPeerId) -> Option<&Pok3rPeer> {
+        self.by_peer.get(peer_id)
+    }
+
+    pub fn peer_of(&self, node_id: u64) -> Option<&Pok3rPeerId> {
+        self.by_node.get(&node_id)
+    }
+
+    pub fn node_of(&self, peer_id: &Pok3rPeerId) -> Option<u64> {
+        self.by_peer.get(peer_id).map(|p| p.node_id)
+    }
+
+    pub fn contains(&self, peer_id: &Pok3rPeerId) -> bool {
+        self.by_peer.contains_key(peer_id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_peer.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_peer.is_empty()
+    }
+
+    /// all peers, by increasing node id
+    pub fn iter_ordered(&self) -> impl Iterator<Item = &Pok3rPeer> {
+        self.by_node
+            .values()
+            .map(move |peer_id| &self.by_peer[peer_id])
+    }
+
+    /// all peer ids, by increasing node id of their peer
+    pub fn peer_ids(&self) -> impl Iterator<Item = &Pok3rPeerId> {
+        self.by_node.values()
+    }
+
+    /// lists peer_id as a player, numbered after the committee and the players
+    /// before it, unless it is listed already
+    pub fn add_player(&mut self, peer_id: Pok3rPeerId, multiaddr: Option<Multiaddr>) {
+        if self.role_of(&peer_id).is_some() {
+            return;
+        }
+        let node_id = (self.len() + self.players.len()) as u64 + 1;
+        self.players.insert(
+            node_id,
+            Pok3rPeer {
+                peer_id,
+                node_id,
+                multiaddr,
+                role: PeerRole::Player,
+            },
+        );
+    }
+
+    pub fn is_player(&self, peer_id: &Pok3rPeerId) -> bool {
+        self.players().any(|peer| peer.peer_id.eq(peer_id))
+    }
+
+    /// the players, by increasing node id
+    pub fn players(&self) -> impl Iterator<Item = &Pok3rPeer> {
+        self.players.values()
+    }
+
+    /// lists peer_id as a verifier, unless it is listed already
+    pub fn add_verifier(&mut self, peer_id: Pok3rPeerId, multiaddr: Option<Multiaddr>) {
+        if self.role_of(&peer_id).is_none() {
+            self.verifiers.insert(peer_id, multiaddr);
+        }
+    }
+
+    pub fn is_verifier(&self, peer_id: &Pok3rPeerId) -> bool {
+        self.verifiers.contains_key(peer_id)
+    }
+
+    /// the peer ids of the verifiers, in order
+    pub fn verifiers(&self) -> impl Iterator<Item = &Pok3rPeerId> {
+        self.verifiers.keys()
+    }
+
+    /// the role of peer_id, if the addr book lists it
+    pub fn role_of(&self, peer_id: &Pok3rPeerId) -> Option<PeerRole> {
+        if self.contains(peer_id) {
+            Some(PeerRole::Committee)
+        } else if self.is_player(peer_id) {
+            Some(PeerRole::Player)
+        } else if self.is_verifier(peer_id) {
+            Some(PeerRole::Verifier)
+        } else {
+            None
+        }
+    }
+
+    /// the peer ids of role, the committee and players by node id and the
+    /// verifiers in order
+    pub fn peer_ids_with_role(&self, role: PeerRole) -> Vec<&Pok3rPeerId> {
+        match role {
+            PeerRole::Committee => self.peer_ids().collect(),
+            PeerRole::Player => self.players().map(|peer| &peer.peer_id).collect(),
+            PeerRole::Verifier => self.verifiers().collect(),
+        }
+    }
+
+    /// the addr book of the given peers, with node ids 1, 2, ... in that order
+    pub fn from_peer_ids(
+        peer_ids: impl IntoIterator<Item = Pok3rPeerId>,
+    ) -> Result<Self, AddrBookError> {
+        AddrBook::try_from(
+            peer_ids
+                .into_iter()
+                .map(|peer_id| AddrBookEntry {
+                    peer_id,
+                    node_id: None,
+                    multiaddr: None,
+                    role: PeerRole::Committee,
+                })
+                .collect::<Vec<AddrBookEntry>>(),
+        )
+    }
+}
+
+impl From<HashMap<Pok3rPeerId, Pok3rPeer>> for AddrBook {
+    fn from(peers: HashMap<Pok3rPeerId, Pok3rPeer>) -> Self {
+        let mut addr_book = AddrBook::new();
+        for peer in peers.into_values() {
+            addr_book.insert(peer);
+        }
+        addr_book
+    }
+}
+
+#[derive(Debug)]
+pub enum AddrBookError {
+    /// the file could not be read
+    Io(io::Error),
+    /// the file is not a well-formed addr book document
+    Parse(String),
+    /// only JSON addr books are supported
+    UnsupportedFormat(String),
+    /// the addr book lists no committee members
+    Empty,
+    /// the addr book lists fewer than MIN_COMMITTEE committee members
+    CommitteeTooSmall(usize),
+    /// the addr book lists more than MAX_PARTIES committee members
+    TooManyParties(usize),
+    /// the peer id is not the base58 encoding of an ed25519 public key
+    BadPeerId(String),
+    DuplicatePeerId(String),
+    DuplicateNodeId(u64),
+    /// verifiers take no part in the sharing, so they have no node id
+    VerifierWithNodeId(String),
+    /// players are numbered after the committee, in the order they are listed
+    PlayerWithNodeId(String),
+    /// node ids must be exactly 1..=n for a committee of n
+    NodeIdOutOfRange {
+        node_id: u64,
+        num_parties: usize,
+    },
+    BadMultiaddr {
+        peer_id: String,
+        multiaddr: String,
+    },
+    /// more parties were asked of parse_addr_book_from_json than ADDRESSES lists
+    NotEnoughBuiltinPeers(u64),
+}
+
+impl fmt::Display for AddrBookError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AddrBookError::Io(err) => write!(f, "could not read addr book: {}", err),
+            AddrBookError::Parse(err) => write!(f, "malformed addr book: {}", err),
+            AddrBookError::UnsupportedFormat(ext) => {
+                write!(f, "unsupported addr book format {:?}, expected json", ext)
+            }
+            AddrBookError::Empty => write!(f, "addr book lists no committee members"),
+            AddrBookError::CommitteeTooSmall(count) => write!(
+                f,
+                "addr book lists {} committee members, at least {} are needed",
+                count, MIN_COMMITTEE
+            ),
+            AddrBookError::TooManyParties(count) => write!(
+                f,
+                "addr book lists {} committee members, at most {} are supported",
+                count, MAX_PARTIES
+            ),
+            AddrBookError::BadPeerId(peer_id) => {
+                write!(f, "{} is not a valid ed25519 peer id", peer_id)
+            }
+            AddrBookError::DuplicatePeerId(peer_id) => {
+                write!(f, "peer {} is listed more than once", peer_id)
+            }
+            AddrBookError::DuplicateNodeId(node_id) => {
+                write!(f, "node id {} is assigned more than once", node_id)
+            }
+            AddrBookError::VerifierWithNodeId(peer_id) => {
+                write!(f, "verifier {} must not have a node id", peer_id)
+            }
+            AddrBookError::PlayerWithNodeId(peer_id) => {
+                write!(f, "player {} must not have a node id", peer_id)
+            }
+            AddrBookError::NodeIdOutOfRange {
+                node_id,
+                num_parties,
+            } => write!(
+                f,
+                "node id {} is outside 1..={} for {} peers",
+                node_id, num_parties, num_parties
+            ),
+            AddrBookError::BadMultiaddr { peer_id, multiaddr } => {
+                write!(f, "invalid multiaddr {} for peer {}", multiaddr, peer_id)
+            }
+            AddrBookError::NotEnoughBuiltinPeers(num_parties) => write!(
+                f,
+                "asked for {} built-in peers, only {} exist",
+                num_parties,
+                ADDRESSES.len()
+            ),
+        }
+    }
+}
+
+impl error::Error for AddrBookError {}
+
+impl From<io::Error> for AddrBookError {
+    fn from(err: io::Error) -> Self {
+        AddrBookError::Io(err)
+    }
+}
+
+/// one peer as listed in an addr book file
+#[derive(Serialize, Deserialize)]
+pub struct AddrBookEntry {
+    pub peer_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node_id: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub multiaddr: Option<String>,
+    #[serde(default, skip_serializing_if = "PeerRole::is_committee")]
+    pub role: PeerRole,
+}
+
+impl PeerRole {
+    fn is_committee(&self) -> bool {
+        *self == PeerRole::Committee
+    }
+}
+
+#[derive(Deserialize)]
+struct AddrBookDocument {
+    addr_book: Vec<AddrBookEntry>,
+}
+
+impl From<AddrBook> for Vec<AddrBookEntry> {
+    fn from(addr_book: AddrBook) -> Self {
+        let committee = addr_book.iter_ordered().map(|peer| AddrBookEntry {
+            peer_id: peer.peer_id.clone(),
+            node_id: Some(peer.node_id),
+            multiaddr: peer.multiaddr.as_ref().map(|addr| addr.to_string()),
+            role: PeerRole::Committee,
+        });
+        let players = addr_book.players().map(|peer| AddrBookEntry {
+            peer_id: peer.peer_id.clone(),
+            node_id: None,
+            multiaddr: peer.multiaddr.as_ref().map(|addr| addr.to_string()),
+            role: PeerRole::Player,
+        });
+        let verifiers = addr_book
+            .verifiers
+            .iter()
+            .map(|(peer_id, multiaddr)| AddrBookEntry {
+                peer_id: peer_id.clone(),
+                node_id: None,
+                multiaddr: multiaddr.as_ref().map(|addr| addr.to_string()),
+                role: PeerRole::Verifier,
+            });
+        committee.chain(players).chain(verifiers).collect()
+    }
+}
+
+/// loads the addr book from a JSON file of the form
+/// { "addr_book": [ { "peer_id": ..., "node_id": ..., "multiaddr": ..., "role": ... }, ... ] },
+/// where node_id, multiaddr and role are optional. The role is "committee" unless
+/// it says "player" or "verifier"; committee members without a node_id get their
+/// position in the committee (counting from 1), players are numbered after the
+/// committee in the order they are listed, and verifiers get none. There must be
+/// at least MIN_COMMITTEE committee members.
+pub fn parse_addr_book_from_file(path: &Path) -> Result<Pok3rAddrBook, AddrBookError> {
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") | None => (),
+        Some(ext) => return Err(AddrBookError::UnsupportedFormat(ext.to_string())),
+    }
+    parse_addr_book_document(&fs::read_to_string(path)?)
+}
+
+fn parse_addr_book_document(contents: &str) -> Result<Pok3rAddrBook, AddrBookError> {
+    let document: AddrBookDocument =
+        serde_json::from_str(contents).map_err(|e| AddrBookError::Parse(e.to_string()))?;
+    AddrBook::try_from(document.addr_book)
+}
+
+impl TryFrom<Vec<AddrBookEntry>> for AddrBook {
+    type Error = AddrBookError;
+
+    /// validates the entries of an addr book file
+    fn try_from(entries: Vec<AddrBookEntry>) -> Result<Self, Self::Error> {
+        let num_parties = entries
+            .iter()
+            .filter(|entry| entry.role == PeerRole::Committee)
+            .count();
+        if num_parties == 0 {
+            return Err(AddrBookError::Empty);
+        }
+        if num_parties > MAX_PARTIES {
+            return Err(AddrBookError::TooManyParties(num_parties));
+        }
+
+        let mut output = AddrBook::new();
+        let mut node_ids = HashSet::new();
+        let mut position = 0;
+        let mut players = vec![];
+        for entry in entries.into_iter() {
+            if public_key_of(&entry.peer_id).is_none() {
+                return Err(AddrBookError::BadPeerId(entry.peer_id));
+            }
+            let listed = |peer_id: &Pok3rPeerId| {
+                output.role_of(peer_id).is_some()
+                    || players.iter().any(|(player, _)| player == peer_id)
+            };
+            if listed(&entry.peer_id) {
+                return Err(AddrBookError::DuplicatePeerId(entry.peer_id));
+            }
+
+            let multiaddr = match entry.multiaddr {
+                Some(addr) => match addr.parse::<Multiaddr>() {
+                    Ok(multiaddr) => Some(multiaddr),
+                    Err(_) => {
+                        return Err(AddrBookError::BadMultiaddr {
+                            peer_id: entry.peer_id,
+                            multiaddr: addr,
+                        })
+                    }
+                },
+                None => None,
+            };
+
+            match entry.role {
+                PeerRole::Verifier if entry.node_id.is_some() => {
+                    return Err(AddrBookError::VerifierWithNodeId(entry.peer_id))
+                }
+                PeerRole::Player if entry.node_id.is_some() => {
+                    return Err(AddrBookError::PlayerWithNodeId(entry.peer_id))
+                }
+                PeerRole::Verifier => {
+                    output.add_verifier(entry.peer_id, multiaddr);
+                    continue;
+                }
+                // numbered once the committee is complete
+                PeerRole::Player => {
+                    players.push((entry.peer_id, multiaddr));
+                    continue;
+                }
+                PeerRole::Committee => (),
+            }
+
+            position += 1;
+            let node_id = entry.node_id.unwrap_or(position);
+            if node_id == 0 || node_id > num_parties as u64 {
+                return Err(AddrBookError::NodeIdOutOfRange {
+                    node_id,
+                    num_parties,
+                });
+            }
+            if !node_ids.insert(node_id) {
+                return Err(AddrBookError::DuplicateNodeId(node_id));
+            }
+
+            output.insert(Pok3rPeer {
+                peer_id: entry.peer_id,
+                node_id,
+                multiaddr,
+                role: PeerRole::Committee,
+            });
+        }
+        if num_parties < MIN_COMMITTEE {
+            return Err(AddrBookError::CommitteeTooSmall(num_parties));
+        }
+        for (peer_id, multiaddr) in players {
+            output.add_player(peer_id, multiaddr);
+        }
+
+        Ok(output)
+    }
+}
+
+/// derives the keypair whose peer id is listed as "Seed n" above
+pub fn keypair_from_seed(secret_key_seed: u8) -> identity::Keypair {
+    // for now we are using a single byte as the seed
+    // this is not secure obviously,
+    // but we are not using it to make life easy
+    let mut bytes = [0u8; 32];
+    bytes[0] = secret_key_seed;
+
+    identity::Keypair::ed25519_from_bytes(bytes).expect("only errors on wrong length")
+}
+
+/// loads an ed25519 keypair from a file holding the base58 encoded 32 byte secret key
+pub fn load_keypair_from_file(path: &Path) -> io::Result<identity::Keypair> {
+    let contents = fs::read_to_string(path)?;
+    let secret = bs58::decode(contents.trim())
+        .into_vec()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    identity::Keypair::ed25519_from_bytes(secret)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// recovers the public key of a peer, which ed25519 peer ids carry inline
+pub fn public_key_of(peer_id: &Pok3rPeerId) -> Option<identity::PublicKey> {
+    let bytes = bs58::decode(peer_id).into_vec().ok()?;
+    // identity multihash: code 0x00, digest length, protobuf encoded key
+    match bytes.as_slice() {
+        [0x00, len, key @ ..] if *len as usize == key.len() => {
+            identity::PublicKey::try_decode_protobuf(key).ok()
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entry(peer_id: &str, node_id: Option<u64>) -> serde_json::Value {
+        match node_id {
+            Some(node_id) => json!({ "peer_id": peer_id, "node_id": node_id }),
+            None => json!({ "peer_id": peer_id }),
+        }
+    }
+
+    fn parse(entries: Vec<serde_json::Value>) -> Result<Pok3rAddrBook, AddrBookError> {
+        parse_addr_book_document(&json!({ "addr_book": entries }).to_string())
+    }
+
+    #[test]
+    fn test_parse_addr_book_from_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("addr-book.json");
+        let document = json!({
+            "addr_book": [
+                { "peer_id": ADDRESSES[0], "multiaddr": "/ip4/127.0.0.1/tcp/9000" },
+                { "peer_id": ADDRESSES[1] },
+                { "peer_id": ADDRESSES[2] },
+            ]
+        });
+        fs::write(&path, document.to_string()).unwrap();
+        let addr_book = parse_addr_book_from_file(&path).unwrap();
+
+        assert_eq!(addr_book.len(), 3);
+        for (i, peer_id) in ADDRESSES[..3].iter().enumerate() {
+            assert_eq!(addr_book.node_of(&peer_id.to_string()), Some(i as u64 + 1));
+        }
+        let peer = |i: usize| addr_book.get(&ADDRESSES[i].to_string()).unwrap();
+        assert!(peer(0).multiaddr.is_some());
+        assert!(peer(1).multiaddr.is_none());
+    }
 
-pub fn get_node_id_via_peer_id(addr_book: &Pok3rAddrBook, peer_id: &Pok3rPeerId) -> Option<u64> {
-    addr_book.get(peer_id).map(|p| p.node_id)
+    #[test]
+    fn test_explicit_node_ids() {
+        let addr_book = parse(vec![
+            entry(ADDRESSES[0], Some(2)),
+            entry(ADDRESSES[1], Some(1)),
+        ])
+        .unwrap();
+        assert_eq!(addr_book.node_of(&ADDRESSES[0].to_string()), Some(2));
+        assert_eq!(addr_book.peer_of(1), Some(&ADDRESSES[1].to_string()));
+    }
+
+    #[test]
+    fn test_rejects_malformed_documents() {
+        assert!(matches!(
+            parse_addr_book_document("not json"),
+            Err(AddrBookError::Parse(_))
+        ));
+        assert!(matches!(parse(vec![]), Err(AddrBookError::Empty)));
+        assert!(matches!(
+            parse_addr_book_from_file(Path::new("addr_book.toml")),
+            Err(AddrBookError::UnsupportedFormat(_))
+        ));
+        assert!(matches!(
+            parse_addr_book_from_file(Path::new("/nonexistent/addr_book.json")),
+            Err(AddrBookError::Io(_))
+        ));
+
+        let entries = (0..=MAX_PARTIES)
+            .map(|_| entry(ADDRESSES[0], None))
+            .collect();
+        assert!(matches!(
+            parse(entries),
+            Err(AddrBookError::TooManyParties(count)) if count == MAX_PARTIES + 1
+        ));
+    }
+
+    #[test]
+    fn test_rejects_bad_peer_ids() {
+        assert!(matches!(
+            parse(vec![entry("not-base58!", None)]),
+            Err(AddrBookError::BadPeerId(_))
+        ));
+        // valid base58, but not an inlined ed25519 key
+        assert!(matches!(
+            parse(vec![entry(
+                "QmYyQSo1c1Ym7orWxLYvCrM2EmxFTANf8wXmmE7DWjhx5N",
+                None
+            )]),
+            Err(AddrBookError::BadPeerId(_))
+        ));
+        assert!(matches!(
+            parse(vec![entry(ADDRESSES[0], None), entry(ADDRESSES[0], None)]),
+            Err(AddrBookError::DuplicatePeerId(_))
+        ));
+    }
+
+    #[test]
+    fn test_rejects_bad_node_ids() {
+        assert!(matches!(
+            parse(vec![
+                entry(ADDRESSES[0], Some(1)),
+                entry(ADDRESSES[1], Some(1)),
+            ]),
+            Err(AddrBookError::DuplicateNodeId(1))
+        ));
+        assert!(matches!(
+            parse(vec![
+                entry(ADDRESSES[0], Some(0)),
+                entry(ADDRESSES[1], None),
+            ]),
+            Err(AddrBookError::NodeIdOutOfRange { node_id: 0, .. })
+        ));
+        assert!(matches!(
+            parse(vec![
+                entry(ADDRESSES[0], None),
+                entry(ADDRESSES[1], Some(3)),
+            ]),
+            Err(AddrBookError::NodeIdOutOfRange { node_id: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn test_rejects_bad_multiaddr() {
+        let entries = vec![json!({ "peer_id": ADDRESSES[0], "multiaddr": "localhost:9000" })];
+        assert!(matches!(
+            parse(entries),
+            Err(AddrBookError::BadMultiaddr { .. })
+        ));
+    }
+
+    #[test]
+    fn test_iteration_order_ignores_insertion_order() {
+        let peers = |order: &[usize]| {
+            order
+                .iter()
+                .map(|&i| {
+                    let peer = Pok3rPeer {
+                        peer_id: ADDRESSES[i].to_string(),
+                        node_id: i as u64 + 1,
+                        multiaddr: None,
+                        role: PeerRole::Committee,
+                    };
+                    (peer.peer_id.clone(), peer)
+                })
+                .collect::<HashMap<Pok3rPeerId, Pok3rPeer>>()
+        };
+        let forward = AddrBook::from(peers(&[0, 1, 2, 3, 4]));
+        let backward = AddrBook::from(peers(&[4, 3, 2, 1, 0]));
+
+        let node_ids =
+            |book: &AddrBook| book.iter_ordered().map(|p| p.node_id).collect::<Vec<u64>>();
+        assert_eq!(node_ids(&forward), vec![1, 2, 3, 4, 5]);
+        assert_eq!(node_ids(&backward), vec![1, 2, 3, 4, 5]);
+        assert!(forward.peer_ids().eq(backward.peer_ids()));
+        assert_eq!(forward, backward);
+    }
+
+    #[test]
+    fn test_addr_book_serde_roundtrip() {
+        let addr_book = parse_addr_book_from_json(4).unwrap();
+        let json = serde_json::to_string(&addr_book).unwrap();
+        assert_eq!(serde_json::from_str::<AddrBook>(&json).unwrap(), addr_book);
+
+        // deserializing validates like parse_addr_book_from_file does
+        let duplicate = json!([{ "peer_id": ADDRESSES[0] }, { "peer_id": ADDRESSES[0] }]);
+        assert!(serde_json::from_value::<AddrBook>(duplicate).is_err());
+    }
+
+    #[test]
+    fn test_verifiers_are_listed_apart_from_players() {
+        let addr_book = parse(vec![
+            json!({ "peer_id": ADDRESSES[0], "role": "verifier" }),
+            entry(ADDRESSES[1], None),
+            entry(ADDRESSES[2], None),
+        ])
+        .unwrap();
+        assert_eq!(addr_book.len(), 2);
+        assert_eq!(addr_book.node_of(&ADDRESSES[1].to_string()), Some(1));
+        assert!(addr_book.is_verifier(&ADDRESSES[0].to_string()));
+        assert!(!addr_book.contains(&ADDRESSES[0].to_string()));
+
+        let json = serde_json::to_string(&addr_book).unwrap();
+        assert_eq!(serde_json::from_str::<AddrBook>(&json).unwrap(), addr_book);
+
+        assert!(matches!(
+            parse(vec![
+                json!({ "peer_id": ADDRESSES[0], "role": "verifier", "node_id": 2 }),
+                entry(ADDRESSES[1], None),
+            ]),
+            Err(AddrBookError::VerifierWithNodeId(_))
+        ));
+    }
+
+    #[test]
+    fn test_players_are_numbered_after_the_committee() {
+        let addr_book = parse(vec![
+            json!({ "peer_id": ADDRESSES[0], "role": "player" }),
+            entry(ADDRESSES[1], None),
+            json!({ "peer_id": ADDRESSES[2], "role": "committee" }),
+            json!({ "peer_id": ADDRESSES[3], "role": "verifier" }),
+            json!({ "peer_id": ADDRESSES[4], "role": "player" }),
+        ])
+        .unwrap();
+        assert_eq!(addr_book.len(), 2);
+        assert_eq!(addr_book.node_of(&ADDRESSES[2].to_string()), Some(2));
+        let players: Vec<(u64, &str)> = addr_book
+            .players()
+            .map(|peer| (peer.node_id, peer.peer_id.as_str()))
+            .collect();
+        assert_eq!(players, vec![(3, ADDRESSES[0]), (4, ADDRESSES[4])]);
+        assert!(!addr_book.contains(&ADDRESSES[0].to_string()));
+        for (i, role) in [
+            PeerRole::Player,
+            PeerRole::Committee,
+            PeerRole::Committee,
+            PeerRole::Verifier,
+            PeerRole::Player,
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            assert_eq!(addr_book.role_of(&ADDRESSES[i].to_string()), Some(role));
+        }
+        assert_eq!(addr_book.peer_ids_with_role(PeerRole::Player).len(), 2);
+
+        let json = serde_json::to_string(&addr_book).unwrap();
+        assert_eq!(serde_json::from_str::<AddrBook>(&json).unwrap(), addr_book);
+
+        assert!(matches!(
+            parse(vec![
+                json!({ "peer_id": ADDRESSES[0], "role": "player", "node_id": 3 }),
+                entry(ADDRESSES[1], None),
+                entry(ADDRESSES[2], None),
+            ]),
+            Err(AddrBookError::PlayerWithNodeId(_))
+        ));
+        assert!(matches!(
+            parse(vec![
+                json!({ "peer_id": ADDRESSES[0], "role": "player" }),
+                entry(ADDRESSES[1], None),
+            ]),
+            Err(AddrBookError::CommitteeTooSmall(1))
+        ));
+        assert!(matches!(
+            parse(vec![
+                json!({ "peer_id": ADDRESSES[0], "role": "player" }),
+                entry(ADDRESSES[1], None),
+                json!({ "peer_id": ADDRESSES[0], "role": "verifier" }),
+            ]),
+            Err(AddrBookError::DuplicatePeerId(_))
+        ));
+    }
+
+    #[test]
+    fn test_builtin_addr_book_bounds() {
+        let addr_book = parse_addr_book_from_json(ADDRESSES.len() as u64).unwrap();
+        assert_eq!(addr_book.len(), 63);
+        assert_eq!(addr_book.node_of(&ADDRESSES[47].to_string()), Some(48));
+        assert!(matches!(
+            parse_addr_book_from_json(64),
+            Err(AddrBookError::NotEnoughBuiltinPeers(64))
+        ));
+    }
 }
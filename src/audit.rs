@@ -0,0 +1,459 @@
+//! Audit transcripts: every value a party saw opened and every proof it helped
+//! produce, in order, hash-chained and signed with the party's key, so that
+//! operators can show after the fact that a hand was played correctly.
+
+use ark_ec::pairing::{Pairing, PairingOutput};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::{error, fmt};
+
+use crate::address_book::{public_key_of, Pok3rAddrBook};
+use crate::common::{
+    Ciphertext, CurveConfig, EncryptionProof, PayloadKind, PermutationProof, PermutationProofV2,
+    ProtocolParams, ProtocolParamsError, ReshuffleProof,
+};
+use crate::encoding::decode_bs58_str_as;
+use crate::hash::GameContext;
+use crate::kzg::UniversalParams;
+use crate::shuffler;
+
+/// version of the layout written by AuditLog::write
+const FORMAT_VERSION: u32 = 1;
+
+/// AuditError describes why a transcript could not be written, or where it fails
+/// verification
+#[derive(Debug)]
+pub enum AuditError {
+    Io(io::Error),
+    Json(serde_json::Error),
+    /// the transcript was written in another layout
+    UnsupportedVersion(u32),
+    /// the deck and permutation sizes of the transcript are not valid params
+    Params(ProtocolParamsError),
+    /// the signer is not in the addr book, or its peer id carries no key
+    UnknownSigner(u64),
+    /// the chain is intact but not signed by the party it names
+    BadSignature,
+    /// the entry at index was changed after it was recorded
+    Tampered {
+        index: usize,
+    },
+    /// the entry at index does not decode, or names senders outside the committee
+    Malformed {
+        index: usize,
+    },
+    /// the proof at index does not verify
+    InvalidProof {
+        index: usize,
+    },
+}
+
+impl fmt::Display for AuditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditError::Io(err) => write!(f, "cannot access the transcript: {}", err),
+            AuditError::Json(err) => write!(f, "cannot decode the transcript: {}", err),
+            AuditError::UnsupportedVersion(version) => {
+                write!(f, "unsupported transcript format version {}", version)
+            }
+            AuditError::Params(err) => write!(f, "bad params in transcript: {}", err),
+            AuditError::UnknownSigner(node_id) => {
+                write!(f, "no key for the signer, party {}", node_id)
+            }
+            AuditError::BadSignature => write!(f, "the transcript signature does not verify"),
+            AuditError::Tampered { index } => {
+                write!(f, "entry {} of the transcript was tampered with", index)
+            }
+            AuditError::Malformed { index } => {
+                write!(f, "entry {} of the transcript is malformed", index)
+            }
+            AuditError::InvalidProof { index } => {
+                write!(f, "the proof of entry {} does not verify", index)
+            }
+        }
+    }
+}
+
+impl error::Error for AuditError {}
+
+impl From<io::Error> for AuditError {
+    fn from(err: io::Error) -> Self {
+        AuditError::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for AuditError {
+    fn from(err: serde_json::Error) -> Self {
+        AuditError::Json(err)
+    }
+}
+
+impl From<ProtocolParamsError> for AuditError {
+    fn from(err: ProtocolParamsError) -> Self {
+        AuditError::Params(err)
+    }
+}
+
+/// AuditEntry is one event of a transcript. Values and proofs are kept in their
+/// base58 encodings, as they went over the wire.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AuditEntry {
+    /// a value reconstructed under handle from the shares of senders, ourselves
+    /// included
+    Opening {
+        handle: String,
+        kind: PayloadKind,
+        value: String,
+        senders: Vec<u64>,
+    },
+    PermutationProof {
+        proof: String,
+    },
+    PermutationProofV2 {
+        proof: String,
+    },
+    ReshuffleProof {
+        proof: String,
+    },
+    EncryptionProof {
+        game_id: String,
+        ciphertext: String,
+        proof: String,
+    },
+}
+
+impl AuditEntry {
+    pub fn permutation_proof<E: Pairing>(proof: &PermutationProof<E>) -> Self {
+        AuditEntry::PermutationProof {
+            proof: encode_bs58(proof),
+        }
+    }
+
+    pub fn permutation_proof_v2<E: Pairing>(proof: &PermutationProofV2<E>) -> Self {
+        AuditEntry::PermutationProofV2 {
+            proof: encode_bs58(proof),
+        }
+    }
+
+    pub fn reshuffle_proof<E: Pairing>(proof: &ReshuffleProof<E>) -> Self {
+        AuditEntry::ReshuffleProof {
+            proof: encode_bs58(proof),
+        }
+    }
+
+    pub fn encryption_proof<E: Pairing>(
+        ctx: &GameContext,
+        ctxt: &Ciphertext<E>,
+        proof: &EncryptionProof<E>,
+    ) -> Self {
+        AuditEntry::EncryptionProof {
+            game_id: bs58::encode(ctx.game_id).into_string(),
+            ciphertext: encode_bs58(ctxt),
+            proof: encode_bs58(proof),
+        }
+    }
+}
+
+/// what a transcript is about; it seeds the hash chain
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditHeader {
+    pub version: u32,
+    pub session_id: String,
+    /// the party that recorded and signed the transcript
+    pub node_id: u64,
+    pub perm_size: usize,
+    pub deck_size: usize,
+}
+
+/// an entry with the hash of the chain up to and including it
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    pub entry: AuditEntry,
+    pub digest: String,
+}
+
+/// AuditFile is the exported transcript: the records, and our signature on the
+/// digest of the last one
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AuditFile {
+    pub header: AuditHeader,
+    pub records: Vec<AuditRecord>,
+    pub signature: String,
+}
+
+/// AuditLog records the entries of a transcript as they happen
+#[derive(Clone, Debug)]
+pub struct AuditLog {
+    header: AuditHeader,
+    records: Vec<AuditRecord>,
+    digest: [u8; 32],
+}
+
+impl AuditLog {
+    pub fn new(session_id: &str, node_id: u64, params: &ProtocolParams) -> Self {
+        let header = AuditHeader {
+            version: FORMAT_VERSION,
+            session_id: session_id.to_string(),
+            node_id,
+            perm_size: params.perm_size(),
+            deck_size: params.deck_size(),
+        };
+        let digest = first_digest(&header);
+        AuditLog {
+            header,
+            records: Vec::new(),
+            digest,
+        }
+    }
+
+    pub fn record(&mut self, entry: AuditEntry) {
+        self.digest = next_digest(&self.digest, &entry);
+        self.records.push(AuditRecord {
+            entry,
+            digest: bs58::encode(self.digest).into_string(),
+        });
+    }
+
+    pub fn records(&self) -> &[AuditRecord] {
+        &self.records
+    }
+
+    /// writes the transcript as JSON, with the signature sign makes of the last digest
+    pub fn write(
+        &self,
+        path: &Path,
+        sign: impl FnOnce(&[u8]) -> Vec<u8>,
+    ) -> Result<(), AuditError> {
+        let file = AuditFile {
+            header: self.header.clone(),
+            records: self.records.clone(),
+            signature: bs58::encode(sign(&self.digest)).into_string(),
+        };
+        let mut writer = BufWriter::new(File::create(path)?);
+        serde_json::to_writer_pretty(&mut writer, &file)?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// checks a transcript without any secret: that no entry was changed since it was
+/// recorded, that the party in the header signed it, that every opening decodes
+/// and comes from parties of addr_book, and that every proof verifies under pp.
+/// Fails on the first entry that does not pass.
+pub fn verify_transcript<E: CurveConfig>(
+    path: &Path,
+    addr_book: &Pok3rAddrBook,
+    pp: &UniversalParams<E>,
+) -> Result<(), AuditError> {
+    let file: AuditFile = serde_json::from_reader(BufReader::new(File::open(path)?))?;
+    if file.header.version != FORMAT_VERSION {
+        return Err(AuditError::UnsupportedVersion(file.header.version));
+    }
+    let params = ProtocolParams::new(file.header.perm_size, file.header.deck_size)?;
+
+    let mut digest = first_digest(&file.header);
+    for (index, record) in file.records.iter().enumerate() {
+        digest = next_digest(&digest, &record.entry);
+        if bs58::encode(digest).into_string() != record.digest {
+            return Err(AuditError::Tampered { index });
+        }
+    }
+
+    let signer = file.header.node_id;
+    let key = addr_book
+        .peer_of(signer)
+        .and_then(public_key_of)
+        .ok_or(AuditError::UnknownSigner(signer))?;
+    let signature = bs58::decode(&file.signature)
+        .into_vec()
+        .map_err(|_| AuditError::BadSignature)?;
+    if !key.verify(&digest, &signature) {
+        return Err(AuditError::BadSignature);
+    }
+
+    for (index, record) in file.records.iter().enumerate() {
+        verify_entry(&record.entry, addr_book, pp, &params, index)?;
+    }
+    Ok(())
+}
+
+pub(crate) fn verify_entry<E: CurveConfig>(
+    entry: &AuditEntry,
+    addr_book: &Pok3rAddrBook,
+    pp: &UniversalParams<E>,
+    params: &ProtocolParams,
+    index: usize,
+) -> Result<(), AuditError> {
+    let malformed = AuditError::Malformed { index };
+    let valid = match entry {
+        AuditEntry::Opening {
+            kind,
+            value,
+            senders,
+            ..
+        } => {
+            let decodes = match kind {
+                PayloadKind::Scalar => decode_bs58_str_as::<E::ScalarField>(value).is_ok(),
+                PayloadKind::G1 => decode_bs58_str_as::<E::G1>(value).is_ok(),
+                PayloadKind::G2 => decode_bs58_str_as::<E::G2>(value).is_ok(),
+                PayloadKind::Gt => decode_bs58_str_as::<PairingOutput<E>>(value).is_ok(),
+            };
+            let known = senders
+                .iter()
+                .all(|sender| addr_book.peer_of(*sender).is_some());
+            if !decodes || !known || senders.is_empty() {
+                return Err(malformed);
+            }
+            true
+        }
+        AuditEntry::PermutationProof { proof } => {
+            let proof: PermutationProof<E> = decode_bs58(proof).ok_or(malformed)?;
+            shuffler::verify_permutation_argument(pp, params, &proof)
+        }
+        AuditEntry::PermutationProofV2 { proof } => {
+            let proof: PermutationProofV2<E> = decode_bs58(proof).ok_or(malformed)?;
+            shuffler::verify_permutation_argument_v2(pp, params, &proof)
+        }
+        AuditEntry::ReshuffleProof { proof } => {
+            let proof: ReshuffleProof<E> = decode_bs58(proof).ok_or(malformed)?;
+            shuffler::verify_reshuffle(pp, &proof)
+        }
+        AuditEntry::EncryptionProof {
+            game_id,
+            ciphertext,
+            proof,
+        } => {
+            let game_id = bs58::decode(game_id)
+                .into_vec()
+                .ok()
+                .and_then(|bytes| <[u8; 32]>::try_from(bytes).ok());
+            let ciphertext: Option<Ciphertext<E>> = decode_bs58(ciphertext);
+            let proof: Option<EncryptionProof<E>> = decode_bs58(proof);
+            match (game_id, ciphertext, proof) {
+                (Some(game_id), Some(ciphertext), Some(proof)) => {
+                    let ctx = GameContext::new(game_id);
+                    shuffler::verify_encryption_argument(pp, params, &ctx, &ciphertext, &proof)
+                }
+                _ => return Err(malformed),
+            }
+        }
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(AuditError::InvalidProof { index })
+    }
+}
+
+/// the senders of an opening, in order
+pub(crate) fn senders_of<V>(shares: &HashMap<u64, V>) -> Vec<u64> {
+    let mut senders: Vec<u64> = shares.keys().copied().collect();
+    senders.sort_unstable();
+    senders
+}
+
+fn encode_bs58<T: CanonicalSerialize>(value: &T) -> String {
+    let mut buffer: Vec<u8> = Vec::new();
+    value.serialize_compressed(&mut buffer).unwrap();
+    bs58::encode(buffer).into_string()
+}
+
+fn decode_bs58<T: CanonicalDeserialize>(value: &str) -> Option<T> {
+    let bytes = bs58::decode(value).into_vec().ok()?;
+    T::deserialize_compressed(bytes.as_slice()).ok()
+}
+
+fn first_digest(header: &AuditHeader) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"pok3r-audit");
+    hasher.update(serde_json::to_vec(header).unwrap());
+    hasher.finalize().into()
+}
+
+fn next_digest(previous: &[u8; 32], entry: &AuditEntry) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(previous);
+    hasher.update(serde_json::to_vec(entry).unwrap());
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{verify_transcript, AuditEntry, AuditError, AuditFile};
+    use crate::common::ProtocolParams;
+    use crate::hash::{card_id, GameContext, IdCache};
+    use crate::shuffler::{
+        compute_keyper_keys, compute_params, compute_permutation_argument, encrypt_and_prove,
+        shuffle_deck,
+    };
+    use crate::simulator::{run_parties_with_params, sim_seed};
+    use crate::transport::Transport;
+
+    #[test]
+    fn test_transcript_of_a_hand_verifies_until_an_entry_is_changed() {
+        let params = ProtocolParams::new(16, 10).unwrap();
+        let pp = compute_params();
+        let (_, mpk) = compute_keyper_keys();
+        let ctx = GameContext::new([5u8; 32]);
+        let ids = (0..params.perm_size())
+            .map(|i| card_id(&ctx, i as u64, 0))
+            .collect::<Vec<Vec<u8>>>();
+        let dir = tempfile::tempdir().unwrap();
+
+        let outputs = run_parties_with_params(3, sim_seed(), params, |mut evaluator| {
+            let pp = pp.clone();
+            let ids = ids.clone();
+            let dir = dir.path().to_path_buf();
+            async move {
+                evaluator.enable_audit();
+                let cards = shuffle_deck(&mut evaluator).await.unwrap();
+                let (perm_proof, alpha1) =
+                    compute_permutation_argument(&pp, &mut evaluator, &cards)
+                        .await
+                        .unwrap();
+                let _ = encrypt_and_prove(
+                    &pp,
+                    &mut evaluator,
+                    &mut IdCache::new(ctx, ids.len()),
+                    cards,
+                    perm_proof.f_com,
+                    alpha1,
+                    mpk,
+                    ids,
+                )
+                .await
+                .unwrap();
+
+                let path = dir.join(format!("audit-{}", evaluator.node_id()));
+                evaluator.export_audit(&path).unwrap();
+                (path, evaluator.into_messaging().addr_book().clone())
+            }
+        });
+
+        for (path, addr_book) in outputs.iter() {
+            verify_transcript(path, addr_book, &pp).unwrap();
+        }
+
+        let (path, addr_book) = &outputs[0];
+        let mut file: AuditFile =
+            serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        let index = file
+            .records
+            .iter()
+            .position(|record| matches!(record.entry, AuditEntry::Opening { .. }))
+            .unwrap();
+        if let AuditEntry::Opening { senders, .. } = &mut file.records[index].entry {
+            senders.pop();
+        }
+        std::fs::write(path, serde_json::to_string(&file).unwrap()).unwrap();
+        assert!(matches!(
+            verify_transcript(path, addr_book, &pp),
+            Err(AuditError::Tampered { index: i }) if i == index
+        ));
+    }
+}
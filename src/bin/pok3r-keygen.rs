@@ -0,0 +1,69 @@
+//! Generates the identities of a committee: an ed25519 keypair per party, saved
+//! to <out>/node<i>.key encrypted under the passphrase read from an environment
+//! variable, and <out>/addr_book.json listing their peer ids. Node ids follow the
+//! order of the peer ids.
+
+use clap::Parser;
+use serde_json::json;
+use std::path::PathBuf;
+use std::{env, fs};
+
+use pok3r::keys::{addr_book_from_keys, generate_keypair, save_keypair};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// number of keypairs to generate
+    #[arg(short, long)]
+    parties: usize,
+
+    /// directory the key files and the addr book are written to
+    #[arg(short, long)]
+    out: PathBuf,
+
+    /// environment variable holding the passphrase the key files are encrypted under
+    #[arg(long, default_value = "POK3R_PASSPHRASE")]
+    passphrase_env: String,
+}
+
+fn fail(message: String) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1)
+}
+
+fn main() {
+    let args = Args::parse();
+    let passphrase = env::var(&args.passphrase_env)
+        .unwrap_or_else(|_| fail(format!("{} is not set", args.passphrase_env)));
+    if passphrase.is_empty() {
+        fail(format!("{} is empty", args.passphrase_env));
+    }
+
+    let mut rng = rand::thread_rng();
+    let mut keys = (0..args.parties)
+        .map(|_| generate_keypair(&mut rng))
+        .collect::<Vec<_>>();
+    keys.sort_by(|(_, a), (_, b)| a.cmp(b));
+    let peer_ids = keys
+        .iter()
+        .map(|(_, peer_id)| peer_id.clone())
+        .collect::<Vec<_>>();
+    let addr_book = addr_book_from_keys(&peer_ids).unwrap_or_else(|err| fail(err.to_string()));
+
+    fs::create_dir_all(&args.out)
+        .unwrap_or_else(|err| fail(format!("{}: {}", args.out.display(), err)));
+    for (keypair, peer_id) in keys.iter() {
+        let node_id = addr_book
+            .node_of(peer_id)
+            .expect("every peer id is in the book");
+        let path = args.out.join(format!("node{}.key", node_id));
+        save_keypair(&path, keypair, &passphrase)
+            .unwrap_or_else(|err| fail(format!("{}: {}", path.display(), err)));
+        println!("{} {}", node_id, peer_id);
+    }
+
+    let path = args.out.join("addr_book.json");
+    let document = serde_json::to_string_pretty(&json!({ "addr_book": addr_book }))
+        .expect("an addr book always serializes");
+    fs::write(&path, document).unwrap_or_else(|err| fail(format!("{}: {}", path.display(), err)));
+}
@@ -0,0 +1,70 @@
+//! Runs one party of a game from a config file: commands are read from stdin,
+//! one per line, and their outcomes written to stdout as JSON lines. See
+//! pok3r::node for the commands and the config. SIGINT and SIGTERM abort the
+//! session for every party.
+
+use async_std::io::{prelude::BufReadExt, stdin, stdout, BufReader};
+use async_std::task;
+use clap::Parser;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use pok3r::node::{Node, NodeConfig};
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// TOML file naming the keypair, addr book, session and game of the node
+    #[arg(short, long)]
+    config: PathBuf,
+}
+
+/// how long the networking daemon is given to send our last messages, e.g. the
+/// abort, before it is stopped
+const LINGER: Duration = Duration::from_millis(500);
+
+#[async_std::main]
+async fn main() {
+    let args = Args::parse();
+    let config = NodeConfig::read(&args.config).unwrap_or_else(|err| {
+        eprintln!("{}: {}", args.config.display(), err);
+        std::process::exit(1)
+    });
+    let (mut node, daemon) = Node::start(&config).await.unwrap_or_else(|err| {
+        eprintln!("could not start the node: {}", err);
+        std::process::exit(1)
+    });
+
+    let commands = BufReader::new(stdin()).lines();
+    let result = node.run(commands, &mut stdout(), interrupts()).await;
+
+    task::sleep(LINGER).await;
+    daemon.stop();
+    if let Err(err) = result {
+        eprintln!("{}", err);
+        std::process::exit(1)
+    }
+}
+
+/// SIGINT and SIGTERM, as a stream
+#[cfg(unix)]
+fn interrupts() -> futures::channel::mpsc::UnboundedReceiver<()> {
+    use signal_hook::consts::{SIGINT, SIGTERM};
+    use signal_hook::iterator::Signals;
+
+    let (tx, rx) = futures::channel::mpsc::unbounded();
+    let mut signals = Signals::new([SIGINT, SIGTERM]).expect("installing the signal handlers");
+    std::thread::spawn(move || {
+        for _ in signals.forever() {
+            if tx.unbounded_send(()).is_err() {
+                break;
+            }
+        }
+    });
+    rx
+}
+
+#[cfg(not(unix))]
+fn interrupts() -> futures::stream::Pending<()> {
+    futures::stream::pending()
+}
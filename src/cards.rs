@@ -0,0 +1,200 @@
+//! The standard deck of 52 cards in the protocol's encoding: with the default
+//! ProtocolParams, the card of index i is ω^i for ω a PERM_SIZE-th root of unity,
+//! and the roots past DECK_SIZE are padding.
+
+use serde::{Deserialize, Serialize};
+use std::{error, fmt};
+
+use crate::common::{DeckSpec, DECK_SIZE, F, PERM_SIZE};
+use crate::utils;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Rank {
+    Two,
+    Three,
+    Four,
+    Five,
+    Six,
+    Seven,
+    Eight,
+    Nine,
+    Ten,
+    Jack,
+    Queen,
+    King,
+    Ace,
+}
+
+impl Rank {
+    pub const ALL: [Rank; 13] = [
+        Rank::Two,
+        Rank::Three,
+        Rank::Four,
+        Rank::Five,
+        Rank::Six,
+        Rank::Seven,
+        Rank::Eight,
+        Rank::Nine,
+        Rank::Ten,
+        Rank::Jack,
+        Rank::Queen,
+        Rank::King,
+        Rank::Ace,
+    ];
+}
+
+impl fmt::Display for Rank {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Rank::Two => "2",
+            Rank::Three => "3",
+            Rank::Four => "4",
+            Rank::Five => "5",
+            Rank::Six => "6",
+            Rank::Seven => "7",
+            Rank::Eight => "8",
+            Rank::Nine => "9",
+            Rank::Ten => "10",
+            Rank::Jack => "J",
+            Rank::Queen => "Q",
+            Rank::King => "K",
+            Rank::Ace => "A",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Suit {
+    Clubs,
+    Diamonds,
+    Hearts,
+    Spades,
+}
+
+impl Suit {
+    pub const ALL: [Suit; 4] = [Suit::Clubs, Suit::Diamonds, Suit::Hearts, Suit::Spades];
+}
+
+impl fmt::Display for Suit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            Suit::Clubs => "♣",
+            Suit::Diamonds => "♦",
+            Suit::Hearts => "♥",
+            Suit::Spades => "♠",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct Card {
+    pub rank: Rank,
+    pub suit: Suit,
+}
+
+impl Card {
+    pub fn new(rank: Rank, suit: Suit) -> Self {
+        Card { rank, suit }
+    }
+
+    /// the index of the card in the deck: the thirteen clubs from two to ace, then
+    /// the diamonds, the hearts and the spades
+    pub fn index(&self) -> usize {
+        self.suit as usize * Rank::ALL.len() + self.rank as usize
+    }
+
+    pub fn from_index(index: usize) -> Option<Card> {
+        if index >= DECK_SIZE {
+            return None;
+        }
+        Some(Card {
+            rank: Rank::ALL[index % Rank::ALL.len()],
+            suit: Suit::ALL[index / Rank::ALL.len()],
+        })
+    }
+}
+
+impl fmt::Display for Card {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.rank, self.suit)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CardError {
+    /// the value is one of the padding cards, or DeckSpec::SENTINEL
+    Padding,
+    /// the value is not in the encoding of the deck
+    NotACard(F),
+}
+
+impl fmt::Display for CardError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CardError::Padding => write!(f, "the value is padding, not a card"),
+            CardError::NotACard(value) => write!(f, "{} does not encode a card", value),
+        }
+    }
+}
+
+impl error::Error for CardError {}
+
+pub fn encode(card: Card) -> F {
+    utils::domain_info(PERM_SIZE).powers[card.index()]
+}
+
+pub fn decode(value: F) -> Result<Card, CardError> {
+    if value == DeckSpec::SENTINEL {
+        return Err(CardError::Padding);
+    }
+    let index = utils::domain_info(PERM_SIZE)
+        .powers
+        .iter()
+        .position(|power| *power == value)
+        .ok_or(CardError::NotACard(value))?;
+    Card::from_index(index).ok_or(CardError::Padding)
+}
+
+/// the encodings of the 52 cards, in the order of their indices; the shuffler
+/// commits to a permutation of these followed by the padding
+pub fn full_deck() -> Vec<F> {
+    utils::domain_info(PERM_SIZE).powers[..DECK_SIZE].to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decode, encode, full_deck, Card, CardError, Rank, Suit};
+    use crate::common::{DeckSpec, DECK_SIZE, F, PERM_SIZE};
+    use crate::utils;
+
+    #[test]
+    fn test_every_card_round_trips() {
+        let deck = full_deck();
+        assert_eq!(deck.len(), DECK_SIZE);
+        for (index, value) in deck.iter().enumerate() {
+            let card = decode(*value).unwrap();
+            assert_eq!(card.index(), index);
+            assert_eq!(Card::from_index(index), Some(card));
+            assert_eq!(encode(card), *value);
+        }
+
+        let queen = Card::new(Rank::Queen, Suit::Spades);
+        assert_eq!(queen.to_string(), "Q♠");
+        assert_eq!(decode(encode(queen)), Ok(queen));
+    }
+
+    #[test]
+    fn test_decode_rejects_junk() {
+        let padding = utils::domain_info(PERM_SIZE).powers[DECK_SIZE];
+        assert_eq!(decode(padding), Err(CardError::Padding));
+        assert_eq!(decode(DeckSpec::SENTINEL), Err(CardError::Padding));
+
+        let junk = F::from(2u64);
+        assert_eq!(decode(junk), Err(CardError::NotACard(junk)));
+        assert_eq!(Card::from_index(DECK_SIZE), None);
+    }
+}
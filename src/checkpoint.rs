@@ -0,0 +1,431 @@
+//! Snapshots of an evaluator, so that a party whose process crashed mid-hand can
+//! pick up where it was instead of losing the whole table.
+
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::{error, fmt};
+use zeroize::Zeroize;
+
+use crate::common::{Curve, CurveConfig, ProtocolParamsError};
+use crate::secret::Scrub;
+
+/// version of the layout written by EvaluatorSnapshot::write
+const FORMAT_VERSION: u32 = 4;
+
+/// CheckpointError describes why a snapshot could not be written or restored
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io(io::Error),
+    Serialization(SerializationError),
+    /// the snapshot was written in another layout
+    UnsupportedVersion(u32),
+    /// the snapshot holds shares over the scalar field of another curve
+    WrongCurve {
+        expected: u8,
+        found: u8,
+    },
+    /// the snapshot holds the state of another party
+    WrongParty {
+        expected: u64,
+        found: u64,
+    },
+    /// the snapshot was taken in another committee
+    WrongCommittee,
+    /// the snapshot was taken in another session
+    WrongSession {
+        expected: String,
+        found: String,
+    },
+    /// the snapshot was taken while reconstructions were under way; the other
+    /// parties may or may not have our shares of them, so they must be retried
+    /// by the protocol rather than resumed
+    InFlight {
+        started: u64,
+        finished: u64,
+    },
+    /// the deck and permutation sizes in the snapshot are not valid params
+    Params(ProtocolParamsError),
+    /// the snapshot holds a wire made after its gate counter, so fresh labels
+    /// would collide with it
+    GateCounterBehind {
+        gate_counter: u64,
+        wire_counter: u64,
+    },
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckpointError::Io(err) => write!(f, "cannot access the checkpoint: {}", err),
+            CheckpointError::Serialization(err) => {
+                write!(f, "cannot decode the checkpoint: {}", err)
+            }
+            CheckpointError::UnsupportedVersion(version) => {
+                write!(f, "unsupported checkpoint format version {}", version)
+            }
+            CheckpointError::WrongCurve { expected, found } => write!(
+                f,
+                "checkpoint on curve {} cannot be restored on curve {}",
+                found, expected
+            ),
+            CheckpointError::WrongParty { expected, found } => write!(
+                f,
+                "checkpoint of party {} cannot be restored by party {}",
+                found, expected
+            ),
+            CheckpointError::WrongCommittee => {
+                write!(f, "the checkpoint was taken in another committee")
+            }
+            CheckpointError::WrongSession { expected, found } => write!(
+                f,
+                "checkpoint of session {} cannot be restored in session {}",
+                found, expected
+            ),
+            CheckpointError::InFlight { started, finished } => write!(
+                f,
+                "{} of {} reconstructions were in flight at the checkpoint",
+                started - finished,
+                started
+            ),
+            CheckpointError::Params(err) => write!(f, "bad params in checkpoint: {}", err),
+            CheckpointError::GateCounterBehind {
+                gate_counter,
+                wire_counter,
+            } => write!(
+                f,
+                "the checkpoint's gate counter {} is behind its wire of gate {}",
+                gate_counter, wire_counter
+            ),
+        }
+    }
+}
+
+impl error::Error for CheckpointError {}
+
+impl From<io::Error> for CheckpointError {
+    fn from(err: io::Error) -> Self {
+        CheckpointError::Io(err)
+    }
+}
+
+impl From<SerializationError> for CheckpointError {
+    fn from(err: SerializationError) -> Self {
+        CheckpointError::Serialization(err)
+    }
+}
+
+impl From<ProtocolParamsError> for CheckpointError {
+    fn from(err: ProtocolParamsError) -> Self {
+        CheckpointError::Params(err)
+    }
+}
+
+/// EvaluatorSnapshot is what Evaluator::checkpoint takes and Evaluator::restore
+/// resumes from: our wire shares, the counters that keep handles in step with the
+/// other parties, the preprocessing not used yet, and the settings the evaluator
+/// was built with. It holds secret shares, so
+/// it must be stored like key material.
+#[derive(Clone, CanonicalSerialize, CanonicalDeserialize)]
+pub struct EvaluatorSnapshot<E: CurveConfig = Curve> {
+    pub(crate) node_id: u64,
+    pub(crate) session_id: Vec<u8>,
+    /// node id and peer id of every party, by node id
+    pub(crate) committee: Vec<(u64, Vec<u8>)>,
+    pub(crate) threshold: Option<u64>,
+    pub(crate) perm_size: u64,
+    pub(crate) deck_size: u64,
+    /// wire labels and our shares, by label
+    pub(crate) wire_shares: Vec<(Vec<u8>, E::ScalarField)>,
+    pub(crate) gate_counter: u64,
+    /// how many triples and random sharings had been used, for bookkeeping only:
+    /// the snapshot holds just the ones after those
+    pub(crate) triples_consumed: u64,
+    pub(crate) rand_sharings_consumed: u64,
+    pub(crate) reconstructions_started: u64,
+    pub(crate) reconstructions_finished: u64,
+    pub(crate) beaver_triples: Vec<(E::ScalarField, E::ScalarField, E::ScalarField)>,
+    pub(crate) rand_sharings: Vec<E::ScalarField>,
+    /// the key shared with each other party, if agreed on
+    pub(crate) pairwise_keys: Vec<(u64, Vec<u8>)>,
+    /// the PRSS seed shared with each other party, if derived
+    pub(crate) prss_seeds: Vec<(u64, Vec<u8>)>,
+    pub(crate) prss_counter: u64,
+    /// the settings of EvaluatorBuilder, which the other parties expect us to
+    /// keep; the modes and the source by their tag
+    pub(crate) open_mode: u8,
+    pub(crate) quorum_policy: u8,
+    pub(crate) rand_source: u8,
+    /// the scalar, G1, G2 and Gt chunks of the batch config
+    pub(crate) batch_chunks: Vec<u64>,
+    pub(crate) max_message_bytes: Option<u64>,
+    pub(crate) dealer: Option<u64>,
+    pub(crate) verify_triples: bool,
+}
+
+impl<E: CurveConfig> EvaluatorSnapshot<E> {
+    /// writes the snapshot next to path and moves it there, so that a crash while
+    /// writing leaves the previous snapshot intact
+    pub fn write(&self, path: &Path) -> Result<(), CheckpointError> {
+        let tmp_path = path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        FORMAT_VERSION.serialize_compressed(&mut writer)?;
+        E::CURVE_ID.serialize_compressed(&mut writer)?;
+        self.serialize_compressed(&mut writer)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        drop(writer);
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    pub fn read(path: &Path) -> Result<Self, CheckpointError> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let version = u32::deserialize_compressed(&mut reader)?;
+        if version != FORMAT_VERSION {
+            return Err(CheckpointError::UnsupportedVersion(version));
+        }
+        let curve_id = u8::deserialize_compressed(&mut reader)?;
+        if curve_id != E::CURVE_ID {
+            return Err(CheckpointError::WrongCurve {
+                expected: E::CURVE_ID,
+                found: curve_id,
+            });
+        }
+        Ok(Self::deserialize_compressed(&mut reader)?)
+    }
+
+    /// checks that the snapshot was taken by the party node_id of committee in
+    /// session_id, with no reconstruction in flight
+    pub(crate) fn check_binding(
+        &self,
+        node_id: u64,
+        committee: &[(u64, Vec<u8>)],
+        session_id: &str,
+    ) -> Result<(), CheckpointError> {
+        if self.node_id != node_id {
+            return Err(CheckpointError::WrongParty {
+                expected: node_id,
+                found: self.node_id,
+            });
+        }
+        if self.committee != committee {
+            return Err(CheckpointError::WrongCommittee);
+        }
+        if self.session_id != session_id.as_bytes() {
+            return Err(CheckpointError::WrongSession {
+                expected: session_id.to_string(),
+                found: String::from_utf8_lossy(&self.session_id).into_owned(),
+            });
+        }
+        if self.reconstructions_started != self.reconstructions_finished {
+            return Err(CheckpointError::InFlight {
+                started: self.reconstructions_started,
+                finished: self.reconstructions_finished,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl<E: CurveConfig> Drop for EvaluatorSnapshot<E> {
+    fn drop(&mut self) {
+        self.wire_shares.scrub();
+        self.beaver_triples.scrub();
+        self.rand_sharings.scrub();
+        for (_, key) in self.pairwise_keys.iter_mut() {
+            key.zeroize();
+        }
+        for (_, seed) in self.prss_seeds.iter_mut() {
+            seed.zeroize();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CheckpointError, EvaluatorSnapshot};
+    use crate::common::F;
+    use crate::evaluator::{Evaluator, EvaluatorBuilder, OpenMode};
+    use crate::transport::InMemoryTransport;
+    use crate::wire::Wire;
+    use futures::future::{join_all, try_join_all};
+
+    /// node id 2 crashes between dealing and revealing, and restores from its
+    /// checkpoint, with every party opening in mode
+    fn restored_party_completes_the_reveal(mode: OpenMode) {
+        async_std::task::block_on(async {
+            let transports = InMemoryTransport::new_network(3).await;
+            let evaluators = try_join_all(
+                transports
+                    .into_iter()
+                    .map(|transport| EvaluatorBuilder::new().open_mode(mode).build(transport)),
+            )
+            .await
+            .unwrap();
+
+            let outputs = join_all(evaluators.into_iter().enumerate().map(
+                |(i, mut evaluator)| async move {
+                    let xs: Vec<Wire> = (1..=4u64)
+                        .map(|i| evaluator.fixed_wire_handle(F::from(i)))
+                        .collect();
+                    let ys: Vec<Wire> = (1..=4u64)
+                        .map(|i| evaluator.fixed_wire_handle(F::from(i + 10)))
+                        .collect();
+                    evaluator.begin_phase("deal");
+                    let zs = evaluator.batch_mult(&xs, &ys).await.unwrap();
+                    evaluator.end_phase("deal").unwrap();
+
+                    // node id 2 crashes after dealing
+                    if i == 1 {
+                        let dir = tempfile::tempdir().unwrap();
+                        let path = dir.path().join("restore-2");
+                        evaluator.checkpoint().write(&path).unwrap();
+                        let messaging = evaluator.into_messaging();
+
+                        let snapshot = EvaluatorSnapshot::read(&path).unwrap();
+                        evaluator = Evaluator::restore(messaging, snapshot).unwrap();
+                        assert_eq!(evaluator.open_mode(), mode);
+                    }
+                    evaluator.batch_output_wire(&zs).await.unwrap()
+                },
+            ))
+            .await;
+
+            let expected: Vec<F> = (1..=4u64).map(|i| F::from(i * (i + 10))).collect();
+            for zs in outputs {
+                assert_eq!(zs, expected);
+            }
+        });
+    }
+
+    #[test]
+    fn test_restored_party_completes_the_reveal() {
+        restored_party_completes_the_reveal(OpenMode::Plain);
+    }
+
+    #[test]
+    fn test_restored_party_completes_a_committed_reveal() {
+        restored_party_completes_the_reveal(OpenMode::Committed);
+    }
+
+    #[test]
+    fn test_restored_party_keeps_its_pairwise_keys() {
+        async_std::task::block_on(async {
+            let transports = InMemoryTransport::new_network(3).await;
+            let evaluators = try_join_all(transports.into_iter().map(Evaluator::new))
+                .await
+                .unwrap();
+
+            let outputs = join_all(evaluators.into_iter().enumerate().map(
+                |(i, mut evaluator)| async move {
+                    let x = evaluator.fixed_wire_handle(F::from(5u64));
+                    // masking the zero sharings needs the pairwise keys
+                    let x = evaluator.refresh_wire(&x).await.unwrap();
+
+                    if i == 1 {
+                        let dir = tempfile::tempdir().unwrap();
+                        let path = dir.path().join("restore-keys-2");
+                        evaluator.checkpoint().write(&path).unwrap();
+                        let messaging = evaluator.into_messaging();
+
+                        let snapshot = EvaluatorSnapshot::read(&path).unwrap();
+                        assert!(!snapshot.pairwise_keys.is_empty());
+                        evaluator = Evaluator::restore(messaging, snapshot).unwrap();
+                    }
+                    let x = evaluator.refresh_wire(&x).await.unwrap();
+                    evaluator.output_wire(&x).await.unwrap()
+                },
+            ))
+            .await;
+
+            for x in outputs {
+                assert_eq!(x, F::from(5u64));
+            }
+        });
+    }
+
+    #[test]
+    fn test_in_flight_or_foreign_snapshots_are_refused() {
+        async_std::task::block_on(async {
+            // every party builds, the random sharings need the seeds of all of them
+            let transports = InMemoryTransport::new_network(3).await;
+            let mut evaluators = try_join_all(transports.into_iter().map(Evaluator::new))
+                .await
+                .unwrap();
+            let other = evaluators.pop().unwrap().into_messaging();
+            let evaluator = evaluators.pop().unwrap();
+            let snapshot = evaluator.checkpoint();
+            let messaging = evaluator.into_messaging();
+
+            assert!(matches!(
+                Evaluator::restore(other, snapshot.clone()),
+                Err(CheckpointError::WrongParty {
+                    expected: 3,
+                    found: 2
+                })
+            ));
+
+            let mut in_flight = snapshot;
+            in_flight.reconstructions_started += 1;
+            assert!(matches!(
+                Evaluator::restore(messaging, in_flight),
+                Err(CheckpointError::InFlight { .. })
+            ));
+        });
+    }
+
+    #[test]
+    fn test_snapshots_behind_their_wires_are_refused() {
+        async_std::task::block_on(async {
+            let transports = InMemoryTransport::new_network(3).await;
+            let mut evaluators = try_join_all(transports.into_iter().map(Evaluator::new))
+                .await
+                .unwrap();
+            let mut evaluator = evaluators.pop().unwrap();
+            evaluator.fixed_wire_handle(F::from(7u64));
+            let mut snapshot = evaluator.checkpoint();
+            let messaging = evaluator.into_messaging();
+
+            // the next fresh label would be the one of the wire above
+            let gate_counter = snapshot.gate_counter - 1;
+            snapshot.gate_counter = gate_counter;
+            let wire_counter = gate_counter + 1;
+            assert!(matches!(
+                Evaluator::restore(messaging, snapshot),
+                Err(CheckpointError::GateCounterBehind { gate_counter: g, wire_counter: w })
+                    if g == gate_counter && w == wire_counter
+            ));
+        });
+    }
+
+    #[cfg(all(feature = "bls12_381", feature = "bls12_377"))]
+    #[test]
+    fn test_snapshots_of_another_curve_are_refused() {
+        use crate::common::CurveConfig;
+        use ark_bls12_377::Bls12_377;
+        use ark_bls12_381::Bls12_381;
+
+        async_std::task::block_on(async {
+            let transports = InMemoryTransport::new_network(3).await;
+            let evaluators = try_join_all(
+                transports
+                    .into_iter()
+                    .map(|transport| EvaluatorBuilder::new().build_on::<Bls12_377, _>(transport)),
+            )
+            .await
+            .unwrap();
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("restore-377");
+            evaluators[0].checkpoint().write(&path).unwrap();
+
+            assert!(EvaluatorSnapshot::<Bls12_377>::read(&path).is_ok());
+            assert!(matches!(
+                EvaluatorSnapshot::<Bls12_381>::read(&path),
+                Err(CheckpointError::WrongCurve { expected, found })
+                    if expected == Bls12_381::CURVE_ID && found == Bls12_377::CURVE_ID
+            ));
+        });
+    }
+}
@@ -0,0 +1,642 @@
+//! The gates a circuit is written against, so that the same code runs either
+//! under MPC on an Evaluator, or in the clear on a ClearEvaluator, which keeps
+//! plaintext values and is used to check the MPC results against.
+
+use ark_ec::Group;
+use ark_std::{One, UniformRand, Zero};
+use async_trait::async_trait;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::collections::{HashMap, HashSet};
+use std::slice::from_ref;
+
+use crate::common::{Curve, CurveConfig, ProtocolParams, ResourceEstimate, F, G1};
+use crate::evaluator::{Evaluator, EvaluatorError};
+use crate::transport::Transport;
+use crate::utils;
+use crate::wire::Wire;
+
+/// gates of an arithmetic circuit over the scalars of E; under MPC a wire holds
+/// our share, in the clear it holds the value itself
+#[async_trait(?Send)]
+pub trait CircuitBuilder<E: CurveConfig = Curve> {
+    fn params(&self) -> &ProtocolParams;
+    fn assert_resources_remaining(&self, needed: &ResourceEstimate) -> Result<(), EvaluatorError>;
+
+    fn begin_scope(&mut self);
+    fn end_scope(&mut self);
+    fn keep(&mut self, handle: &Wire);
+
+    /// a random value nobody knows
+    fn ran(&mut self) -> Wire;
+    fn fixed_wire_handle(&mut self, value: E::ScalarField) -> Wire;
+    fn add(&mut self, handle_x: &Wire, handle_y: &Wire) -> Wire;
+    fn sub(&mut self, handle_x: &Wire, handle_y: &Wire) -> Wire;
+    fn clear_add(&mut self, handle_x: &Wire, y: E::ScalarField) -> Wire;
+    fn scale(&mut self, handle_in: &Wire, scalar: E::ScalarField) -> Wire;
+
+    async fn batch_mult(
+        &mut self,
+        x_handles: &[Wire],
+        y_handles: &[Wire],
+    ) -> Result<Vec<Wire>, EvaluatorError>;
+    async fn batch_inv(&mut self, input_handles: &[Wire]) -> Result<Vec<Wire>, EvaluatorError>;
+    /// len random roots of unity of the domain of params().perm_size()
+    async fn batch_ran_64(&mut self, len: usize) -> Result<Vec<Wire>, EvaluatorError>;
+    async fn batch_output_wire(
+        &mut self,
+        wire_handles: &[Wire],
+    ) -> Result<Vec<E::ScalarField>, EvaluatorError>;
+    async fn batch_output_wire_in_exponent(
+        &mut self,
+        wire_handles: &[Wire],
+    ) -> Result<Vec<E::G1>, EvaluatorError>;
+
+    async fn mult(&mut self, handle_x: &Wire, handle_y: &Wire) -> Result<Wire, EvaluatorError> {
+        let mut output = self
+            .batch_mult(
+                std::slice::from_ref(handle_x),
+                std::slice::from_ref(handle_y),
+            )
+            .await?;
+        Ok(output.remove(0))
+    }
+
+    async fn output_wire(&mut self, wire_handle: &Wire) -> Result<E::ScalarField, EvaluatorError> {
+        Ok(self
+            .batch_output_wire(std::slice::from_ref(wire_handle))
+            .await?[0])
+    }
+
+    /// [x] times a public constant; like scale, it is local and takes no triple
+    fn mul_public(&mut self, handle: &Wire, constant: E::ScalarField) -> Wire {
+        self.scale(handle, constant)
+    }
+
+    /// the product of a sparse public matrix with the wires, locally: output i is
+    /// the sum of c [wires[j]] over the entries (j, c) of rows[i], and zero for an
+    /// empty row. Panics if an entry points past the wires.
+    fn apply_public_matrix(
+        &mut self,
+        rows: &[Vec<(usize, E::ScalarField)>],
+        wires: &[Wire],
+    ) -> Vec<Wire> {
+        self.begin_scope();
+        let outputs = rows
+            .iter()
+            .map(|row| {
+                let mut output = self.fixed_wire_handle(E::ScalarField::zero());
+                for (j, c) in row {
+                    assert!(*j < wires.len(), "entry {} of {} wires", j, wires.len());
+                    let term = self.mul_public(&wires[*j], *c);
+                    output = self.add(&output, &term);
+                }
+                output
+            })
+            .collect::<Vec<Wire>>();
+        outputs.iter().for_each(|h| self.keep(h));
+        self.end_scope();
+        outputs
+    }
+
+    /// the wires moved under a public permutation, locally: output i is
+    /// wires[perm[i]]. Panics if perm is not a permutation of the wires.
+    fn apply_public_permutation(&mut self, perm: &[usize], wires: &[Wire]) -> Vec<Wire> {
+        let mut seen = vec![false; wires.len()];
+        assert!(
+            perm.len() == wires.len()
+                && perm
+                    .iter()
+                    .all(|j| *j < wires.len() && !std::mem::replace(&mut seen[*j], true)),
+            "not a permutation of {} wires",
+            wires.len()
+        );
+        let rows = perm
+            .iter()
+            .map(|j| vec![(*j, E::ScalarField::one())])
+            .collect::<Vec<Vec<(usize, E::ScalarField)>>>();
+        self.apply_public_matrix(&rows, wires)
+    }
+
+    /// the index of the first wire not holding a bit, where that can be told
+    /// without revealing them; only the ClearEvaluator tells, under MPC it is None
+    fn first_non_bit(&self, _handles: &[Wire]) -> Option<usize> {
+        None
+    }
+
+    /// bit ? x : y, for a wire holding a bit, with one triple
+    async fn select(&mut self, bit: &Wire, x: &Wire, y: &Wire) -> Result<Wire, EvaluatorError> {
+        let mut output = self
+            .batch_select(from_ref(bit), from_ref(x), from_ref(y))
+            .await?;
+        Ok(output.remove(0))
+    }
+
+    /// bits[i] ? xs[i] : ys[i], computed as y + b (x - y) with a triple per value,
+    /// all in one round. The intermediates are freed.
+    async fn batch_select(
+        &mut self,
+        bits: &[Wire],
+        xs: &[Wire],
+        ys: &[Wire],
+    ) -> Result<Vec<Wire>, EvaluatorError> {
+        if let Some(err) = length_mismatch(bits, xs, ys) {
+            return Err(err);
+        }
+        if let Some(index) = self.first_non_bit(bits) {
+            return Err(EvaluatorError::NotABit { index });
+        }
+        self.assert_resources_remaining(&select_resources(bits.len()))?;
+
+        self.begin_scope();
+        let differences = xs
+            .iter()
+            .zip(ys)
+            .map(|(x, y)| self.sub(x, y))
+            .collect::<Vec<Wire>>();
+        let result = match self.batch_mult(bits, &differences).await {
+            Ok(products) => Ok(ys
+                .iter()
+                .zip(&products)
+                .map(|(y, product)| self.add(y, product))
+                .collect::<Vec<Wire>>()),
+            Err(err) => Err(err),
+        };
+        if let Ok(selected) = &result {
+            selected.iter().for_each(|h| self.keep(h));
+        }
+        self.end_scope();
+        result
+    }
+
+    /// (x, y) if bit is 0 and (y, x) if it is 1, with one triple
+    async fn obliv_swap(
+        &mut self,
+        bit: &Wire,
+        x: &Wire,
+        y: &Wire,
+    ) -> Result<(Wire, Wire), EvaluatorError> {
+        let (mut firsts, mut seconds) = self
+            .batch_obliv_swap(from_ref(bit), from_ref(x), from_ref(y))
+            .await?;
+        Ok((firsts.remove(0), seconds.remove(0)))
+    }
+
+    /// the pairs (xs[i], ys[i]), swapped where bits[i] is 1: with d = b (y - x),
+    /// they are x + d and y - d, with a triple per pair, all in one round. The
+    /// intermediates are freed.
+    async fn batch_obliv_swap(
+        &mut self,
+        bits: &[Wire],
+        xs: &[Wire],
+        ys: &[Wire],
+    ) -> Result<(Vec<Wire>, Vec<Wire>), EvaluatorError> {
+        if let Some(err) = length_mismatch(bits, xs, ys) {
+            return Err(err);
+        }
+        if let Some(index) = self.first_non_bit(bits) {
+            return Err(EvaluatorError::NotABit { index });
+        }
+        self.assert_resources_remaining(&select_resources(bits.len()))?;
+
+        self.begin_scope();
+        let differences = xs
+            .iter()
+            .zip(ys)
+            .map(|(x, y)| self.sub(y, x))
+            .collect::<Vec<Wire>>();
+        let result = match self.batch_mult(bits, &differences).await {
+            Ok(deltas) => {
+                let firsts = xs
+                    .iter()
+                    .zip(&deltas)
+                    .map(|(x, delta)| self.add(x, delta))
+                    .collect::<Vec<Wire>>();
+                let seconds = ys
+                    .iter()
+                    .zip(&deltas)
+                    .map(|(y, delta)| self.sub(y, delta))
+                    .collect::<Vec<Wire>>();
+                Ok((firsts, seconds))
+            }
+            Err(err) => Err(err),
+        };
+        if let Ok((firsts, seconds)) = &result {
+            firsts.iter().chain(seconds).for_each(|h| self.keep(h));
+        }
+        self.end_scope();
+        result
+    }
+}
+
+/// preprocessing consumed by batch_select or batch_obliv_swap over len values
+pub fn select_resources(len: usize) -> ResourceEstimate {
+    ResourceEstimate {
+        triples: len,
+        rand_sharings: 0,
+    }
+}
+
+fn length_mismatch(bits: &[Wire], xs: &[Wire], ys: &[Wire]) -> Option<EvaluatorError> {
+    [xs.len(), ys.len()]
+        .into_iter()
+        .find(|len| *len != bits.len())
+        .map(|actual| EvaluatorError::LengthMismatch {
+            expected: bits.len(),
+            actual,
+        })
+}
+
+#[async_trait(?Send)]
+impl<T: Transport, E: CurveConfig> CircuitBuilder<E> for Evaluator<T, E> {
+    fn params(&self) -> &ProtocolParams {
+        Evaluator::params(self)
+    }
+
+    fn assert_resources_remaining(&self, needed: &ResourceEstimate) -> Result<(), EvaluatorError> {
+        Evaluator::assert_resources_remaining(self, needed)
+    }
+
+    fn begin_scope(&mut self) {
+        Evaluator::begin_scope(self)
+    }
+
+    fn end_scope(&mut self) {
+        Evaluator::end_scope(self)
+    }
+
+    fn keep(&mut self, handle: &Wire) {
+        Evaluator::keep(self, handle)
+    }
+
+    fn ran(&mut self) -> Wire {
+        Evaluator::ran(self)
+    }
+
+    fn fixed_wire_handle(&mut self, value: E::ScalarField) -> Wire {
+        Evaluator::fixed_wire_handle(self, value)
+    }
+
+    fn add(&mut self, handle_x: &Wire, handle_y: &Wire) -> Wire {
+        Evaluator::add(self, handle_x, handle_y)
+    }
+
+    fn sub(&mut self, handle_x: &Wire, handle_y: &Wire) -> Wire {
+        Evaluator::sub(self, handle_x, handle_y)
+    }
+
+    fn clear_add(&mut self, handle_x: &Wire, y: E::ScalarField) -> Wire {
+        Evaluator::clear_add(self, handle_x, y)
+    }
+
+    fn scale(&mut self, handle_in: &Wire, scalar: E::ScalarField) -> Wire {
+        Evaluator::scale(self, handle_in, scalar)
+    }
+
+    fn mul_public(&mut self, handle: &Wire, constant: E::ScalarField) -> Wire {
+        Evaluator::mult_by_public(self, handle, constant)
+    }
+
+    async fn batch_mult(
+        &mut self,
+        x_handles: &[Wire],
+        y_handles: &[Wire],
+    ) -> Result<Vec<Wire>, EvaluatorError> {
+        Evaluator::batch_mult(self, x_handles, y_handles).await
+    }
+
+    async fn batch_inv(&mut self, input_handles: &[Wire]) -> Result<Vec<Wire>, EvaluatorError> {
+        Evaluator::batch_inv(self, input_handles).await
+    }
+
+    async fn batch_ran_64(&mut self, len: usize) -> Result<Vec<Wire>, EvaluatorError> {
+        Evaluator::batch_ran_64(self, len).await
+    }
+
+    async fn batch_output_wire(
+        &mut self,
+        wire_handles: &[Wire],
+    ) -> Result<Vec<E::ScalarField>, EvaluatorError> {
+        Evaluator::batch_output_wire(self, wire_handles).await
+    }
+
+    async fn batch_output_wire_in_exponent(
+        &mut self,
+        wire_handles: &[Wire],
+    ) -> Result<Vec<E::G1>, EvaluatorError> {
+        Evaluator::batch_output_wire_in_exponent(self, wire_handles).await
+    }
+
+    async fn mult(&mut self, handle_x: &Wire, handle_y: &Wire) -> Result<Wire, EvaluatorError> {
+        Evaluator::mult(self, handle_x, handle_y).await
+    }
+
+    async fn output_wire(&mut self, wire_handle: &Wire) -> Result<E::ScalarField, EvaluatorError> {
+        Evaluator::output_wire(self, wire_handle).await
+    }
+}
+
+#[derive(Default)]
+struct Scope {
+    wires: Vec<Wire>,
+    kept: HashSet<Wire>,
+}
+
+/// reference evaluator: every wire holds its plaintext value, and nothing is sent
+/// anywhere. Not secure, it is meant for differential tests and debugging
+pub struct ClearEvaluator {
+    params: ProtocolParams,
+    values: HashMap<Wire, F>,
+    gate_counter: u64,
+    scopes: Vec<Scope>,
+    rng: StdRng,
+}
+
+impl ClearEvaluator {
+    pub fn new(params: ProtocolParams) -> Self {
+        Self::new_with_seed(params, rand::random())
+    }
+
+    pub fn new_with_seed(params: ProtocolParams, seed: [u8; 32]) -> Self {
+        ClearEvaluator {
+            params,
+            values: HashMap::new(),
+            gate_counter: 0,
+            scopes: Vec::new(),
+            rng: StdRng::from_seed(seed),
+        }
+    }
+
+    /// the value on a wire
+    pub fn get_wire(&self, handle: &Wire) -> F {
+        *self.values.get(handle).unwrap()
+    }
+
+    /// number of wires currently holding a value
+    pub fn num_wires(&self) -> usize {
+        self.values.len()
+    }
+
+    fn new_wire(&mut self, value: F) -> Wire {
+        self.gate_counter += 1;
+        let handle = Wire::from(format!("clear-{}", self.gate_counter));
+        self.values.insert(handle.clone(), value);
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.wires.push(handle.clone());
+        }
+        handle
+    }
+}
+
+#[async_trait(?Send)]
+impl CircuitBuilder for ClearEvaluator {
+    fn params(&self) -> &ProtocolParams {
+        &self.params
+    }
+
+    fn assert_resources_remaining(&self, _needed: &ResourceEstimate) -> Result<(), EvaluatorError> {
+        Ok(())
+    }
+
+    fn begin_scope(&mut self) {
+        self.scopes.push(Scope::default());
+    }
+
+    fn end_scope(&mut self) {
+        let scope = self.scopes.pop().expect("end_scope without begin_scope");
+        for wire in scope.wires {
+            if scope.kept.contains(&wire) {
+                if let Some(parent) = self.scopes.last_mut() {
+                    parent.wires.push(wire);
+                }
+            } else {
+                self.values.remove(&wire);
+            }
+        }
+    }
+
+    fn keep(&mut self, handle: &Wire) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.kept.insert(handle.clone());
+        }
+    }
+
+    fn ran(&mut self) -> Wire {
+        let value = F::rand(&mut self.rng);
+        self.new_wire(value)
+    }
+
+    fn fixed_wire_handle(&mut self, value: F) -> Wire {
+        self.new_wire(value)
+    }
+
+    fn add(&mut self, handle_x: &Wire, handle_y: &Wire) -> Wire {
+        let value = self.get_wire(handle_x) + self.get_wire(handle_y);
+        self.new_wire(value)
+    }
+
+    fn sub(&mut self, handle_x: &Wire, handle_y: &Wire) -> Wire {
+        let value = self.get_wire(handle_x) - self.get_wire(handle_y);
+        self.new_wire(value)
+    }
+
+    fn clear_add(&mut self, handle_x: &Wire, y: F) -> Wire {
+        let value = self.get_wire(handle_x) + y;
+        self.new_wire(value)
+    }
+
+    fn scale(&mut self, handle_in: &Wire, scalar: F) -> Wire {
+        let value = self.get_wire(handle_in) * scalar;
+        self.new_wire(value)
+    }
+
+    async fn batch_mult(
+        &mut self,
+        x_handles: &[Wire],
+        y_handles: &[Wire],
+    ) -> Result<Vec<Wire>, EvaluatorError> {
+        assert_eq!(x_handles.len(), y_handles.len());
+        Ok(x_handles
+            .iter()
+            .zip(y_handles)
+            .map(|(x, y)| {
+                let value = self.get_wire(x) * self.get_wire(y);
+                self.new_wire(value)
+            })
+            .collect())
+    }
+
+    async fn batch_inv(&mut self, input_handles: &[Wire]) -> Result<Vec<Wire>, EvaluatorError> {
+        let values: Vec<F> = input_handles.iter().map(|h| self.get_wire(h)).collect();
+        if let Some(index) = values.iter().position(|v| v.is_zero()) {
+            return Err(EvaluatorError::NotInvertible { index });
+        }
+        Ok(values
+            .iter()
+            .map(|v| {
+                let inv = F::one() / v;
+                self.new_wire(inv)
+            })
+            .collect())
+    }
+
+    async fn batch_ran_64(&mut self, len: usize) -> Result<Vec<Wire>, EvaluatorError> {
+        let perm_size = self.params.perm_size();
+        let powers = &utils::domain_info(perm_size).powers;
+        Ok((0..len)
+            .map(|_| {
+                let root = powers[self.rng.gen_range(0..perm_size)];
+                self.new_wire(root)
+            })
+            .collect())
+    }
+
+    async fn batch_output_wire(&mut self, wire_handles: &[Wire]) -> Result<Vec<F>, EvaluatorError> {
+        Ok(wire_handles.iter().map(|h| self.get_wire(h)).collect())
+    }
+
+    async fn batch_output_wire_in_exponent(
+        &mut self,
+        wire_handles: &[Wire],
+    ) -> Result<Vec<G1>, EvaluatorError> {
+        Ok(wire_handles
+            .iter()
+            .map(|h| G1::generator() * self.get_wire(h))
+            .collect())
+    }
+
+    fn first_non_bit(&self, handles: &[Wire]) -> Option<usize> {
+        handles.iter().position(|h| {
+            let value = self.get_wire(h);
+            !value.is_zero() && !value.is_one()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CircuitBuilder, ClearEvaluator};
+    use crate::common::{ProtocolParams, F};
+    use crate::evaluator::EvaluatorError;
+    use crate::shuffler::shuffle_deck;
+    use crate::utils;
+    use crate::wire::Wire;
+    use ark_std::{One, Zero};
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_clear_shuffle_is_a_permutation() {
+        let params = ProtocolParams::new(16, 10).unwrap();
+        let mut evaluator = ClearEvaluator::new_with_seed(params, [7u8; 32]);
+        let cards = async_std::task::block_on(shuffle_deck(&mut evaluator)).unwrap();
+
+        // only the cards outlive the shuffle's scope
+        assert_eq!(evaluator.num_wires(), params.perm_size());
+        let opened: HashSet<F> = cards.iter().map(|c| evaluator.get_wire(c)).collect();
+        let roots: HashSet<F> = utils::domain_info(params.perm_size())
+            .powers
+            .iter()
+            .copied()
+            .collect();
+        assert_eq!(opened, roots);
+    }
+
+    #[test]
+    fn test_clear_batch_inv_rejects_zero() {
+        let mut evaluator = ClearEvaluator::new(ProtocolParams::default());
+        let x = evaluator.fixed_wire_handle(F::from(3u64));
+        let zero = evaluator.sub(&x, &x);
+        assert!(matches!(
+            async_std::task::block_on(evaluator.batch_inv(&[x, zero])),
+            Err(EvaluatorError::NotInvertible { index: 1 })
+        ));
+    }
+
+    #[test]
+    fn test_clear_public_maps_keep_only_their_outputs() {
+        let mut evaluator = ClearEvaluator::new(ProtocolParams::default());
+        let xs = (0..4u64)
+            .map(|i| evaluator.fixed_wire_handle(F::from(i + 1)))
+            .collect::<Vec<Wire>>();
+        let rows = vec![
+            vec![(3, F::from(2u64)), (0, F::from(5u64)), (3, F::one())],
+            vec![],
+        ];
+        let mapped = evaluator.apply_public_matrix(&rows, &xs);
+        assert_eq!(evaluator.num_wires(), 6);
+        assert_eq!(evaluator.get_wire(&mapped[0]), F::from(17u64));
+        assert_eq!(evaluator.get_wire(&mapped[1]), F::zero());
+
+        let permuted = evaluator.apply_public_permutation(&[1, 3, 0, 2], &xs);
+        let values = permuted
+            .iter()
+            .map(|h| evaluator.get_wire(h))
+            .collect::<Vec<F>>();
+        assert_eq!(values, [2u64, 4, 1, 3].map(F::from));
+        assert_eq!(evaluator.num_wires(), 10);
+    }
+
+    #[test]
+    fn test_clear_select_and_swap() {
+        let mut evaluator = ClearEvaluator::new(ProtocolParams::default());
+        let wires = |evaluator: &mut ClearEvaluator, values: &[u64]| {
+            values
+                .iter()
+                .map(|v| evaluator.fixed_wire_handle(F::from(*v)))
+                .collect::<Vec<Wire>>()
+        };
+        let bits = wires(&mut evaluator, &[0, 1, 1, 0]);
+        let xs = wires(&mut evaluator, &[10, 11, 12, 13]);
+        let ys = wires(&mut evaluator, &[20, 21, 22, 23]);
+        let values = |evaluator: &ClearEvaluator, handles: &[Wire]| {
+            handles
+                .iter()
+                .map(|h| evaluator.get_wire(h))
+                .collect::<Vec<F>>()
+        };
+
+        async_std::task::block_on(async {
+            let one = evaluator.select(&bits[1], &xs[1], &ys[1]).await.unwrap();
+            let zero = evaluator.select(&bits[0], &xs[0], &ys[0]).await.unwrap();
+            assert_eq!(evaluator.get_wire(&one), F::from(11u64));
+            assert_eq!(evaluator.get_wire(&zero), F::from(20u64));
+
+            let num_wires = evaluator.num_wires();
+            let selected = evaluator.batch_select(&bits, &xs, &ys).await.unwrap();
+            // only the outputs outlive the gadget
+            assert_eq!(evaluator.num_wires(), num_wires + 4);
+            let expected = [20u64, 11, 12, 23].map(F::from);
+            assert_eq!(values(&evaluator, &selected), expected);
+
+            let (firsts, seconds) = evaluator.batch_obliv_swap(&bits, &xs, &ys).await.unwrap();
+            assert_eq!(
+                values(&evaluator, &firsts),
+                [10u64, 21, 22, 13].map(F::from)
+            );
+            assert_eq!(
+                values(&evaluator, &seconds),
+                [20u64, 11, 12, 23].map(F::from)
+            );
+            let (first, second) = evaluator
+                .obliv_swap(&bits[2], &xs[2], &ys[2])
+                .await
+                .unwrap();
+            assert_eq!(evaluator.get_wire(&first), F::from(22u64));
+            assert_eq!(evaluator.get_wire(&second), F::from(12u64));
+
+            // the clear evaluator sees the bits, and rejects those that are not
+            let two = evaluator.fixed_wire_handle(F::from(2u64));
+            let not_bits = [bits[0].clone(), two];
+            assert!(matches!(
+                evaluator.batch_select(&not_bits, &xs[..2], &ys[..2]).await,
+                Err(EvaluatorError::NotABit { index: 1 })
+            ));
+            assert!(matches!(
+                evaluator.batch_obliv_swap(&bits, &xs, &ys[..3]).await,
+                Err(EvaluatorError::LengthMismatch {
+                    expected: 4,
+                    actual: 3
+                })
+            ));
+        });
+    }
+}
@@ -1,85 +1,795 @@
+use crate::encoding::PayloadValue;
+#[cfg(any(feature = "bls12_381", feature = "bls12_377"))]
+use crate::hash::FrHasher;
+#[cfg(feature = "bn254")]
+use crate::hash::IncrementHasher;
 use crate::kzg::KZG10;
+use crate::reconstruct::Shared;
+use crate::secret::Scrub;
+use crate::transcript::FsHasher;
+use ark_crypto_primitives::sponge::Absorb;
+use ark_ec::hashing::HashToCurve;
+#[cfg(any(feature = "bls12_381", feature = "bls12_377"))]
+use ark_ec::hashing::{curve_maps::wb::WBMap, map_to_curve_hasher::MapToCurveBasedHasher};
 use ark_ec::pairing::{Pairing, PairingOutput};
+use ark_ec::short_weierstrass::Projective;
+use ark_ff::FftField;
 use ark_poly::univariate::DensePolynomial;
 use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
 use serde::{Deserialize, Serialize};
+use std::ops::Add;
+use std::{error, fmt};
 
+/// sizes of ProtocolParams::default()
 pub const PERM_SIZE: usize = 64;
 pub const DECK_SIZE: usize = 52;
-pub const LOG_PERM_SIZE: usize = 6;
-pub const NUM_SAMPLES: usize = 420;
-pub const NUM_BEAVER_TRIPLES: usize = 3466;
-pub const NUM_RAND_SHARINGS: usize = 987;
 
-#[cfg(feature = "bls12_377")]
-pub type Curve = ark_bls12_377::Bls12_377;
+/// shuffle_deck fails if some card is never sampled; the number of samples is
+/// picked so that this happens with probability at most 2^-SAMPLING_SECURITY
+pub const SAMPLING_SECURITY: u32 = 20;
+
+// Curve is the default instantiation: bls12_381 if its feature is on, else
+// bls12_377, else bn254
 #[cfg(feature = "bls12_381")]
-pub type Curve = ark_bls12_381::Bls12_381;
+mod default_curve {
+    pub use ark_bls12_381::{g1, g2, Bls12_381 as Curve, Fq, Fq2, Fr};
+}
+#[cfg(all(feature = "bls12_377", not(feature = "bls12_381")))]
+mod default_curve {
+    pub use ark_bls12_377::{g1, g2, Bls12_377 as Curve, Fq, Fq2, Fr};
+}
+#[cfg(all(
+    feature = "bn254",
+    not(any(feature = "bls12_381", feature = "bls12_377"))
+))]
+mod default_curve {
+    pub use ark_bn254::{g1, g2, Bn254 as Curve, Fq, Fq2, Fr};
+}
+
+pub use default_curve::Curve;
+use default_curve::{g1, g2, Fr};
+/// base field of G1 and its quadratic extension, the base field of G2
+pub(crate) use default_curve::{Fq, Fq2};
 
-pub type F = <Curve as Pairing>::ScalarField;
-pub type G1 = <Curve as Pairing>::G1;
-pub type G2 = <Curve as Pairing>::G2;
+// named through the curve configs rather than as projections of Curve, so that
+// coherence can tell these types apart when traits are implemented for them
+pub type F = Fr;
+pub type G1 = Projective<g1::Config>;
+pub type G2 = Projective<g2::Config>;
 pub type Gt = PairingOutput<Curve>;
 pub type KZG = KZG10<Curve, DensePolynomial<F>>;
+/// KzgOn is KZG on the curve E, for code that is generic over the curve
+pub type KzgOn<E> = KZG10<E, DensePolynomial<<E as Pairing>::ScalarField>>;
+
+/// CurveConfig is what the protocol needs to know about a pairing-friendly curve
+/// beyond the pairing itself: how identities are hashed onto its groups, and how
+/// it is named on the wire. The evaluator, the shuffler, the proofs and the wire
+/// are written against E: CurveConfig, with Curve above as the default, so that
+/// one build can play on every curve whose feature is on.
+pub trait CurveConfig:
+    Pairing<
+    ScalarField: PayloadValue + Shared<Scalar = Self::ScalarField> + Scrub + Absorb,
+    G1: PayloadValue + Shared<Scalar = Self::ScalarField>,
+    G2: PayloadValue + Shared<Scalar = Self::ScalarField>,
+>
+{
+    /// identifies the curve in encoding::encoding_tag
+    const CURVE_ID: u8;
+    /// hash-to-curve domain strings for identities in G1 and G2
+    const DOMAIN_STRING_HASH_ID: &'static [u8];
+    const DOMAIN_STRING_HASH_ID_G2: &'static [u8];
+    /// S-box exponent and partial rounds of Poseidon of width 3 over the scalar
+    /// field, the numbers the Poseidon paper gives for 128-bit security
+    const POSEIDON_ALPHA: u64;
+    const POSEIDON_PARTIAL_ROUNDS: usize;
+
+    type G1Hasher: HashToCurve<Self::G1>;
+    type G2Hasher: HashToCurve<Self::G2>;
+}
+
+#[cfg(feature = "bls12_381")]
+impl CurveConfig for ark_bls12_381::Bls12_381 {
+    const CURVE_ID: u8 = 1;
+    const DOMAIN_STRING_HASH_ID: &'static [u8] =
+        b"SUPRA_POKER_ID-hashtoG1-with-BLS12381G1_XMD:SHA-256_SSWU_RO";
+    const DOMAIN_STRING_HASH_ID_G2: &'static [u8] =
+        b"SUPRA_POKER_ID-hashtoG2-with-BLS12381G2_XMD:SHA-256_SSWU_RO";
+    const POSEIDON_ALPHA: u64 = 5;
+    const POSEIDON_PARTIAL_ROUNDS: usize = 57;
+
+    type G1Hasher = MapToCurveBasedHasher<
+        Projective<ark_bls12_381::g1::Config>,
+        FrHasher,
+        WBMap<ark_bls12_381::g1::Config>,
+    >;
+    type G2Hasher = MapToCurveBasedHasher<
+        Projective<ark_bls12_381::g2::Config>,
+        FrHasher,
+        WBMap<ark_bls12_381::g2::Config>,
+    >;
+}
+
+#[cfg(feature = "bls12_377")]
+impl CurveConfig for ark_bls12_377::Bls12_377 {
+    const CURVE_ID: u8 = 2;
+    const DOMAIN_STRING_HASH_ID: &'static [u8] =
+        b"SUPRA_POKER_ID-hashtoG1-with-BLS12377G1_XMD:SHA-256_SSWU_RO";
+    const DOMAIN_STRING_HASH_ID_G2: &'static [u8] =
+        b"SUPRA_POKER_ID-hashtoG2-with-BLS12377G2_XMD:SHA-256_SSWU_RO";
+    // 5 divides r - 1 on BLS12-377, so x^5 is not a permutation there
+    const POSEIDON_ALPHA: u64 = 17;
+    const POSEIDON_PARTIAL_ROUNDS: usize = 31;
+
+    type G1Hasher = MapToCurveBasedHasher<
+        Projective<ark_bls12_377::g1::Config>,
+        FrHasher,
+        WBMap<ark_bls12_377::g1::Config>,
+    >;
+    type G2Hasher = MapToCurveBasedHasher<
+        Projective<ark_bls12_377::g2::Config>,
+        FrHasher,
+        WBMap<ark_bls12_377::g2::Config>,
+    >;
+}
+
+// arkworks has no isogeny map for BN254, whose G1 has a = 0, so identities are
+// hashed onto it by try-and-increment
+#[cfg(feature = "bn254")]
+impl CurveConfig for ark_bn254::Bn254 {
+    const CURVE_ID: u8 = 3;
+    const DOMAIN_STRING_HASH_ID: &'static [u8] =
+        b"SUPRA_POKER_ID-hashtoG1-with-BN254G1_XMD:SHA-256_TAI_RO";
+    const DOMAIN_STRING_HASH_ID_G2: &'static [u8] =
+        b"SUPRA_POKER_ID-hashtoG2-with-BN254G2_XMD:SHA-256_TAI_RO";
+    const POSEIDON_ALPHA: u64 = 5;
+    const POSEIDON_PARTIAL_ROUNDS: usize = 57;
+
+    type G1Hasher = IncrementHasher<ark_bn254::g1::Config>;
+    type G2Hasher = IncrementHasher<ark_bn254::g2::Config>;
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProtocolParamsError {
+    /// cards are encoded as perm_size-th roots of unity
+    NotPowerOfTwo(usize),
+    /// products of polynomials over the domain need 2 * perm_size roots of unity
+    DomainTooLarge(usize),
+    /// the deck must be nonempty and fit in the permutation
+    BadDeckSize { deck_size: usize, perm_size: usize },
+    /// the card value at index is the sentinel that marks padding
+    SentinelCard(usize),
+}
+
+impl fmt::Display for ProtocolParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProtocolParamsError::NotPowerOfTwo(n) => {
+                write!(f, "permutation size {} is not a power of two", n)
+            }
+            ProtocolParamsError::DomainTooLarge(n) => {
+                write!(f, "permutation size {} is too large for the field", n)
+            }
+            ProtocolParamsError::BadDeckSize {
+                deck_size,
+                perm_size,
+            } => write!(
+                f,
+                "deck of {} cards does not fit in a permutation of size {}",
+                deck_size, perm_size
+            ),
+            ProtocolParamsError::SentinelCard(index) => {
+                write!(f, "card {} has the value reserved for padding", index)
+            }
+        }
+    }
+}
+
+impl error::Error for ProtocolParamsError {}
+
+/// ProtocolParams fixes the sizes of a game: the deck of deck_size cards is padded
+/// with dummy cards to perm_size, a power of two, so that cards can be encoded
+/// as roots of unity. All parties and verifiers must use the same params.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProtocolParams {
+    perm_size: usize,
+    deck_size: usize,
+}
+
+impl Default for ProtocolParams {
+    fn default() -> Self {
+        ProtocolParams {
+            perm_size: PERM_SIZE,
+            deck_size: DECK_SIZE,
+        }
+    }
+}
+
+impl ProtocolParams {
+    pub fn new(perm_size: usize, deck_size: usize) -> Result<Self, ProtocolParamsError> {
+        if perm_size < 2 || !perm_size.is_power_of_two() {
+            return Err(ProtocolParamsError::NotPowerOfTwo(perm_size));
+        }
+        if (2 * perm_size).trailing_zeros() > <F as FftField>::TWO_ADICITY {
+            return Err(ProtocolParamsError::DomainTooLarge(perm_size));
+        }
+        if deck_size == 0 || deck_size > perm_size {
+            return Err(ProtocolParamsError::BadDeckSize {
+                deck_size,
+                perm_size,
+            });
+        }
+        Ok(ProtocolParams {
+            perm_size,
+            deck_size,
+        })
+    }
+
+    pub fn perm_size(&self) -> usize {
+        self.perm_size
+    }
+
+    pub fn deck_size(&self) -> usize {
+        self.deck_size
+    }
+
+    pub fn log_perm_size(&self) -> usize {
+        self.perm_size.trailing_zeros() as usize
+    }
+
+    /// random roots of unity shuffle_deck draws: each of the deck_size cards is
+    /// missed with probability (1 - 1/perm_size)^samples, so by a union bound
+    /// samples >= (SAMPLING_SECURITY ln 2 + ln deck_size) / -ln(1 - 1/perm_size)
+    pub fn num_samples(&self) -> usize {
+        let p = self.perm_size as f64;
+        let bound =
+            SAMPLING_SECURITY as f64 * std::f64::consts::LN_2 + (self.deck_size as f64).ln();
+        (bound / -(1.0 - 1.0 / p).ln()).ceil() as usize
+    }
+}
+
+/// version of the messages peers exchange, announced in PeerInfo
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// features a peer may announce in PeerInfo
+pub const FEATURE_BINARY_PAYLOADS: &str = "binary_payloads";
+pub const FEATURE_COMMITTED_OPENS: &str = "committed_opens";
+pub const FEATURE_THRESHOLD: &str = "threshold";
+pub const FEATURE_ZSTD: &str = "zstd";
+
+/// what a peer tells the others about its build and game when it connects, so that
+/// a peer that would not understand us is turned away before the game starts
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PeerInfo {
+    pub protocol_version: u32,
+    /// encoding::encoding_tag of the peer
+    pub curve_tag: u8,
+    pub perm_size: usize,
+    pub deck_size: usize,
+    /// the FEATURE_* the peer runs with
+    pub features: Vec<String>,
+}
+
+impl PeerInfo {
+    pub fn new(params: &ProtocolParams) -> Self {
+        PeerInfo {
+            protocol_version: PROTOCOL_VERSION,
+            curve_tag: crate::encoding::encoding_tag(),
+            perm_size: params.perm_size(),
+            deck_size: params.deck_size(),
+            features: vec![],
+        }
+    }
+
+    pub fn with_feature(mut self, feature: &str) -> Self {
+        if !self.has_feature(feature) {
+            self.features.push(feature.to_string());
+        }
+        self
+    }
+
+    pub fn has_feature(&self, feature: &str) -> bool {
+        self.features.iter().any(|f| f == feature)
+    }
+
+    /// the first field in which theirs cannot play with us, with our value and
+    /// theirs. Committed opens and threshold mode change what every party sends, so
+    /// both sides must agree on them; binary payloads and compression are used only
+    /// with the peers that announce them.
+    pub fn mismatch(&self, theirs: &PeerInfo) -> Option<(&'static str, String, String)> {
+        if self.protocol_version != theirs.protocol_version {
+            return Some((
+                "protocol_version",
+                self.protocol_version.to_string(),
+                theirs.protocol_version.to_string(),
+            ));
+        }
+        if self.curve_tag != theirs.curve_tag {
+            return Some((
+                "curve_tag",
+                format!("{:#04x}", self.curve_tag),
+                format!("{:#04x}", theirs.curve_tag),
+            ));
+        }
+        if self.perm_size != theirs.perm_size {
+            return Some((
+                "perm_size",
+                self.perm_size.to_string(),
+                theirs.perm_size.to_string(),
+            ));
+        }
+        if self.deck_size != theirs.deck_size {
+            return Some((
+                "deck_size",
+                self.deck_size.to_string(),
+                theirs.deck_size.to_string(),
+            ));
+        }
+        for feature in [FEATURE_COMMITTED_OPENS, FEATURE_THRESHOLD] {
+            if self.has_feature(feature) != theirs.has_feature(feature) {
+                return Some((
+                    feature,
+                    self.has_feature(feature).to_string(),
+                    theirs.has_feature(feature).to_string(),
+                ));
+            }
+        }
+        None
+    }
+}
+
+/// DeckSpec lists the values of the cards of a deck, possibly with repeats as in a
+/// shoe of several decks. The shuffle permutes the perm_size-th roots of unity, and
+/// the card ω^i stands for card_values[i]; the roots past num_cards are padding,
+/// worth DeckSpec::SENTINEL, and are never dealt.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeckSpec {
+    num_cards: usize,
+    card_values: Vec<F>,
+}
+
+impl DeckSpec {
+    /// the value of the padding cards
+    pub const SENTINEL: F = ark_ff::MontFp!("0");
+
+    pub fn new(card_values: Vec<F>) -> Result<Self, ProtocolParamsError> {
+        if let Some(index) = card_values.iter().position(|v| *v == Self::SENTINEL) {
+            return Err(ProtocolParamsError::SentinelCard(index));
+        }
+        let spec = DeckSpec {
+            num_cards: card_values.len(),
+            card_values,
+        };
+        // checks that the deck is nonempty and the domain fits in the field
+        ProtocolParams::new(spec.perm_size(), spec.num_cards)?;
+        Ok(spec)
+    }
+
+    /// the 52 cards of a standard deck, in the encoding of the cards module
+    pub fn standard() -> Self {
+        Self::new(crate::cards::full_deck()).unwrap()
+    }
+
+    /// num_decks copies of the cards of self, as in a blackjack shoe
+    pub fn repeated(&self, num_decks: usize) -> Result<Self, ProtocolParamsError> {
+        Self::new(self.card_values.repeat(num_decks))
+    }
+
+    pub fn num_cards(&self) -> usize {
+        self.num_cards
+    }
+
+    pub fn card_values(&self) -> &[F] {
+        &self.card_values
+    }
+
+    /// the smallest permutation that fits the deck
+    pub fn perm_size(&self) -> usize {
+        self.num_cards.next_power_of_two().max(2)
+    }
+
+    /// the params to build the evaluators of a game with this deck
+    pub fn params(&self) -> ProtocolParams {
+        ProtocolParams::new(self.perm_size(), self.num_cards).unwrap()
+    }
+
+    /// the value of the card ω^index, or SENTINEL for padding
+    pub fn value_at(&self, index: usize) -> F {
+        self.card_values
+            .get(index)
+            .copied()
+            .unwrap_or(Self::SENTINEL)
+    }
+
+    /// the value of an opened card, or None if card is not a perm_size-th root of unity
+    pub fn value_of(&self, card: &F) -> Option<F> {
+        crate::utils::domain_info(self.perm_size())
+            .powers
+            .iter()
+            .position(|w| w == card)
+            .map(|index| self.value_at(index))
+    }
+}
+
+/// ResourceEstimate counts the preprocessed material a computation consumes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ResourceEstimate {
+    pub triples: usize,
+    pub rand_sharings: usize,
+}
+
+impl ResourceEstimate {
+    /// whether other fits in what self counts
+    pub fn covers(&self, other: &ResourceEstimate) -> bool {
+        self.triples >= other.triples && self.rand_sharings >= other.rand_sharings
+    }
+}
+
+impl Add for ResourceEstimate {
+    type Output = ResourceEstimate;
+
+    fn add(self, other: ResourceEstimate) -> ResourceEstimate {
+        ResourceEstimate {
+            triples: self.triples + other.triples,
+            rand_sharings: self.rand_sharings + other.rand_sharings,
+        }
+    }
+}
+
+impl fmt::Display for ResourceEstimate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} beaver triples and {} random sharings",
+            self.triples, self.rand_sharings
+        )
+    }
+}
 
 /// EvalNetMsg represents the types of messages that
 /// we expect to flow between the evaluator and networkd
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 #[serde(tag = "type")]
 pub enum EvalNetMsg {
     ConnectionEstablished {
         success: bool,
+        /// set when a peer (rather than the local networkd) announces itself
+        #[serde(default)]
+        sender: Option<String>,
+        /// whether the announcing party accepts PublishBatchBinary messages
+        #[serde(default)]
+        binary_payloads: bool,
+        /// session of the announcing peer; empty when coming from the networkd
+        #[serde(default)]
+        session: String,
+        /// encoding::encoding_tag of the announcing peer, so that a peer built for
+        /// another curve is turned away at connect time; 0 from the networkd
+        #[serde(default)]
+        encoding_tag: u8,
+        /// build and game of the announcing peer; None from the networkd and from
+        /// peers of earlier releases
+        #[serde(default)]
+        info: Option<PeerInfo>,
+        /// set while the announcing peer still waits for the info of others, who
+        /// answer with an announcement of their own
+        #[serde(default)]
+        waiting: bool,
+        /// base58 ed25519 signature of sender over the other fields; empty from
+        /// the networkd
+        #[serde(default)]
+        signature: String,
     },
     Greeting {
         message: String,
+        session: String,
     },
     PublishValue {
+        /// the game (or run) this message belongs to
+        session: String,
         sender: String,
+        /// per-sender sequence number, acknowledged by the recipients
+        seq: u64,
+        /// bumped on every retransmission; not signed
+        #[serde(default)]
+        attempt: u32,
+        /// the round of the sender's evaluator when it was sent; values of rounds
+        /// the recipient has left behind are dropped
+        #[serde(default)]
+        round: u64,
         handle: String,
         value: String,
+        /// base58 ed25519 signature of sender over the other fields
+        signature: String,
     },
     PublishBatchValue {
+        session: String,
         sender: String,
+        seq: u64,
+        #[serde(default)]
+        attempt: u32,
+        #[serde(default)]
+        round: u64,
         handles: Vec<String>,
+        /// empty when compressed into payload
         values: Vec<String>,
+        /// how payload is compressed; values are sent as they are when plain
+        #[serde(default, skip_serializing_if = "Codec::is_plain")]
+        codec: Codec,
+        /// the length prefixed values, compressed with codec; empty when plain
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        payload: Vec<u8>,
+        signature: String,
+    },
+    PublishBatchBinary {
+        session: String,
+        sender: String,
+        seq: u64,
+        #[serde(default)]
+        attempt: u32,
+        #[serde(default)]
+        round: u64,
+        handles: Vec<String>,
+        /// concatenation of the compressed canonical encodings of the values,
+        /// compressed as a whole with codec
+        payload: Vec<u8>,
+        kind: PayloadKind,
+        #[serde(default, skip_serializing_if = "Codec::is_plain")]
+        codec: Codec,
+        signature: String,
+    },
+    /// a value meant only for recipient, carried on the sender/recipient topic
+    DirectValue {
+        session: String,
+        sender: String,
+        seq: u64,
+        #[serde(default)]
+        attempt: u32,
+        #[serde(default)]
+        round: u64,
+        recipient: String,
+        handle: String,
+        value: String,
+        signature: String,
+    },
+    /// values meant only for recipient, in the layout of PublishBatchBinary
+    DirectBatchBinary {
+        session: String,
+        sender: String,
+        seq: u64,
+        #[serde(default)]
+        attempt: u32,
+        #[serde(default)]
+        round: u64,
+        recipient: String,
+        handles: Vec<String>,
+        payload: Vec<u8>,
+        kind: PayloadKind,
+        signature: String,
+    },
+    /// tells recipient that its message with sequence number seq arrived
+    Ack {
+        session: String,
+        sender: String,
+        recipient: String,
+        seq: u64,
+        signature: String,
     },
+    /// sender gives up on the session; everyone else should stop as well
+    Abort {
+        session: String,
+        sender: String,
+        reason: String,
+        signature: String,
+    },
+    /// a liveness probe: broadcast with no recipient every heartbeat interval and
+    /// answered to its sender with sent_at echoed, so that the sender learns the
+    /// round trip time
+    Heartbeat {
+        session: String,
+        sender: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        recipient: Option<String>,
+        /// milliseconds since the prober started its messaging system
+        sent_at: u64,
+        signature: String,
+    },
+}
+
+/// PayloadKind identifies the type of the values packed in a binary payload
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PayloadKind {
+    Scalar,
+    G1,
+    G2,
+    Gt,
+}
+
+/// Codec tells how the payload of a batch message is compressed
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Codec {
+    #[default]
+    Plain,
+    Zstd,
+}
+
+impl Codec {
+    pub fn is_plain(&self) -> bool {
+        *self == Codec::Plain
+    }
 }
 
 /// PermutationProof is a structure for the permutation proofs
 #[derive(Clone, Debug, CanonicalSerialize, CanonicalDeserialize)]
-pub struct PermutationProof {
-    pub y1: F,
-    pub y2: F,
-    pub y3: F,
-    pub y4: F,
-    pub y5: F,
-    pub pi_1: G1,
-    pub pi_2: G1,
-    pub pi_3: G1,
-    pub pi_4: G1,
-    pub pi_5: G1,
-    pub f_com: G1,
-    pub q_com: G1,
-    pub t_com: G1,
-}
-
-pub type Ciphertext = (G2, Vec<Gt>);
+pub struct PermutationProof<E: Pairing = Curve> {
+    pub y1: E::ScalarField,
+    pub y2: E::ScalarField,
+    pub y3: E::ScalarField,
+    pub y4: E::ScalarField,
+    pub y5: E::ScalarField,
+    pub pi_1: E::G1,
+    pub pi_2: E::G1,
+    pub pi_3: E::G1,
+    pub pi_4: E::G1,
+    pub pi_5: E::G1,
+    pub f_com: E::G1,
+    pub q_com: E::G1,
+    pub t_com: E::G1,
+    /// hash the challenges were derived with
+    pub hasher: FsHasher,
+}
+
+/// ReshuffleProof links a deck reshuffled mid-game to the deck it was drawn from:
+/// the permutation applied to the cards left is proven, and the masked difference
+/// of the products of (γ - card) over the old and new cards, under a challenge γ
+/// hashed from both commitments, was opened as zero
+#[derive(Clone, Debug, CanonicalSerialize, CanonicalDeserialize)]
+pub struct ReshuffleProof<E: Pairing = Curve> {
+    /// the positions of the old deck that were reshuffled
+    pub positions: Vec<u64>,
+    pub old_commitment: E::G1,
+    pub commitment: E::G1,
+    pub permutation: PermutationProof<E>,
+    pub masked_difference: E::ScalarField,
+    /// hash the challenge was derived with
+    pub hasher: FsHasher,
+}
+
+/// PermutationProofV2 is PermutationProof with the openings of t, g and q at y2
+/// aggregated into the single proof pi_batch
+#[derive(Clone, Debug, CanonicalSerialize, CanonicalDeserialize)]
+pub struct PermutationProofV2<E: Pairing = Curve> {
+    pub y1: E::ScalarField,
+    pub y2: E::ScalarField,
+    pub y3: E::ScalarField,
+    pub y4: E::ScalarField,
+    pub y5: E::ScalarField,
+    pub pi_1: E::G1,
+    pub pi_3: E::G1,
+    pub pi_batch: E::G1,
+    pub f_com: E::G1,
+    pub q_com: E::G1,
+    pub t_com: E::G1,
+    pub hasher: FsHasher,
+}
+
+pub type Ciphertext<E = Curve> = (<E as Pairing>::G2, Vec<PairingOutput<E>>);
+
+/// DealtCard is the card at a position of a committed deck, encrypted to one
+/// player, with a proof that anyone can check without learning the card
+#[derive(Clone, Debug, CanonicalSerialize, CanonicalDeserialize)]
+pub struct DealtCard<E: Pairing = Curve> {
+    pub position: u64,
+    pub recipient: Vec<u8>,
+    pub pk: E::G2,
+    pub card_commitment: E::G1,
+    pub c1: E::G2,
+    pub c2: PairingOutput<E>,
+    /// opening proof of the commitment at the position, blinded so that it
+    /// does not give the card away
+    pub eval_proof: E::G1,
+    pub proof: DealProof<E>,
+    pub hasher: FsHasher,
+}
+
+impl<E: Pairing> DealtCard<E> {
+    /// the ciphertext in the form decrypt_one_card takes, with the card at index 0
+    pub fn ciphertext(&self) -> Ciphertext<E> {
+        (self.c1, vec![self.c2])
+    }
+}
+
+/// DealProof shows knowledge of the card m, the encryption mask r and the
+/// blinding s of a DealtCard, that is c1 = g2^r, c2 = gt^m e(H(id), pk)^r,
+/// and that the commitment opens to m with the proof unblinded by s
+#[derive(Clone, Debug, CanonicalSerialize, CanonicalDeserialize)]
+pub struct DealProof<E: Pairing = Curve> {
+    pub a1: E::G2,
+    pub a2: PairingOutput<E>,
+    pub a3: PairingOutput<E>,
+    pub y_m: E::ScalarField,
+    pub y_r: E::ScalarField,
+    pub y_s: E::ScalarField,
+}
 
 #[derive(Clone, Debug, CanonicalSerialize, CanonicalDeserialize)]
-pub struct EncryptionProof {
-    pub pk: G2,
+pub struct EncryptionProof<E: Pairing = Curve> {
+    pub pk: E::G2,
     pub ids: Vec<Vec<u8>>,
-    pub card_commitment: G1, //same as f_com above
-    pub card_poly_eval: F,
-    pub eval_proof: G1,
-    pub hiding_ciphertext: Gt,
-    pub t: Gt,
-    pub sigma_proof: Option<SigmaProof>,
+    pub card_commitment: E::G1, //same as f_com above
+    pub card_poly_eval: E::ScalarField,
+    pub eval_proof: E::G1,
+    pub hiding_ciphertext: PairingOutput<E>,
+    pub t: PairingOutput<E>,
+    pub sigma_proof: Option<SigmaProof<E>>,
+    pub hasher: FsHasher,
 }
 
 #[derive(Clone, Debug, CanonicalSerialize, CanonicalDeserialize)]
-pub struct SigmaProof {
-    pub a1: G2,
-    pub a2: Gt,
-    pub y: F,
+pub struct SigmaProof<E: Pairing = Curve> {
+    pub a1: E::G2,
+    pub a2: PairingOutput<E>,
+    pub y: E::ScalarField,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DeckSpec, ProtocolParams, ProtocolParamsError, DECK_SIZE, F, PERM_SIZE};
+
+    #[test]
+    fn test_protocol_params_validation() {
+        let default = ProtocolParams::default();
+        assert_eq!(ProtocolParams::new(PERM_SIZE, DECK_SIZE), Ok(default));
+        assert_eq!(default.log_perm_size(), 6);
+
+        assert_eq!(
+            ProtocolParams::new(48, 32),
+            Err(ProtocolParamsError::NotPowerOfTwo(48))
+        );
+        assert_eq!(
+            ProtocolParams::new(1, 1),
+            Err(ProtocolParamsError::NotPowerOfTwo(1))
+        );
+        assert!(matches!(
+            ProtocolParams::new(16, 17),
+            Err(ProtocolParamsError::BadDeckSize { .. })
+        ));
+        assert!(matches!(
+            ProtocolParams::new(1 << 50, 52),
+            Err(ProtocolParamsError::DomainTooLarge(_))
+        ));
+    }
+
+    #[test]
+    fn test_deck_spec_picks_the_smallest_domain() {
+        assert_eq!(DeckSpec::standard().params(), ProtocolParams::default());
+
+        let piquet = DeckSpec::new((1..=32).map(F::from).collect()).unwrap();
+        assert_eq!(piquet.params(), ProtocolParams::new(32, 32).unwrap());
+
+        let shoe = DeckSpec::standard().repeated(6).unwrap();
+        assert_eq!(shoe.num_cards(), 312);
+        assert_eq!(shoe.perm_size(), 512);
+        assert_eq!(shoe.value_at(52), crate::cards::full_deck()[0]);
+        assert_eq!(shoe.value_at(400), DeckSpec::SENTINEL);
+
+        assert_eq!(
+            DeckSpec::new(vec![F::from(1u64), DeckSpec::SENTINEL]),
+            Err(ProtocolParamsError::SentinelCard(1))
+        );
+        assert!(matches!(
+            DeckSpec::new(vec![]),
+            Err(ProtocolParamsError::BadDeckSize { .. })
+        ));
+    }
+
+    #[test]
+    fn test_num_samples_bounds_the_failure_probability() {
+        let small = ProtocolParams::new(16, 10).unwrap();
+        let double = ProtocolParams::new(128, 104).unwrap();
+        for params in [small, ProtocolParams::default(), double] {
+            // a single card is missed with probability at most 2^-20 / deck_size
+            let p = params.perm_size() as f64;
+            let miss = (1.0 - 1.0 / p).powi(params.num_samples() as i32);
+            assert!(miss * params.deck_size() as f64 <= 2f64.powi(-20));
+        }
+    }
 }
@@ -0,0 +1,91 @@
+//! zstd compression of the payloads of large batch messages. Decompression is
+//! always bounded, since the compressed bytes come from peers that may lie about
+//! what they expand to.
+
+use std::{error, fmt};
+
+/// CompressionError explains why a compressed payload was refused
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompressionError {
+    /// the payload is not a zstd frame that announces its expanded size
+    Malformed,
+    /// the payload would expand to size bytes, more than the limit we accept
+    TooLarge { size: u64, limit: usize },
+}
+
+impl fmt::Display for CompressionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CompressionError::Malformed => write!(f, "malformed compressed payload"),
+            CompressionError::TooLarge { size, limit } => write!(
+                f,
+                "compressed payload expands to {} bytes, over the limit of {}",
+                size, limit
+            ),
+        }
+    }
+}
+
+impl error::Error for CompressionError {}
+
+/// compresses data into a single zstd frame that records its expanded size
+pub fn compress(data: &[u8], level: i32) -> Vec<u8> {
+    zstd::bulk::compress(data, level).expect("compressing into memory does not fail")
+}
+
+/// the size data expands to, as announced by its frame header
+pub fn expanded_size(data: &[u8]) -> Option<u64> {
+    zstd::zstd_safe::get_frame_content_size(data).ok().flatten()
+}
+
+/// expands data, refusing frames that announce more than limit bytes (or none at
+/// all) before any memory is spent on them; a frame that expands to more than
+/// it announced is refused as well
+pub fn decompress(data: &[u8], limit: usize) -> Result<Vec<u8>, CompressionError> {
+    let size = expanded_size(data).ok_or(CompressionError::Malformed)?;
+    if size > limit as u64 {
+        return Err(CompressionError::TooLarge { size, limit });
+    }
+    let expanded =
+        zstd::bulk::decompress(data, size as usize).map_err(|_| CompressionError::Malformed)?;
+    if expanded.len() as u64 != size {
+        return Err(CompressionError::Malformed);
+    }
+    Ok(expanded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compress, decompress, expanded_size, CompressionError};
+    use rand::{rngs::StdRng, RngCore, SeedableRng};
+
+    #[test]
+    fn test_round_trip() {
+        let text = "3mJr7AoUXx2Wqd".repeat(1000).into_bytes();
+        let mut random = vec![0u8; 4096];
+        StdRng::seed_from_u64(1).fill_bytes(&mut random);
+
+        for data in [text, random, vec![]] {
+            let compressed = compress(&data, 3);
+            assert_eq!(expanded_size(&compressed), Some(data.len() as u64));
+            assert_eq!(decompress(&compressed, data.len()), Ok(data));
+        }
+    }
+
+    #[test]
+    fn test_bombs_are_refused() {
+        let bomb = compress(&vec![0u8; 1 << 20], 3);
+        assert!(bomb.len() < 1024);
+        assert_eq!(
+            decompress(&bomb, 1 << 16),
+            Err(CompressionError::TooLarge {
+                size: 1 << 20,
+                limit: 1 << 16
+            })
+        );
+        assert_eq!(
+            decompress(b"not a frame", 1 << 16),
+            Err(CompressionError::Malformed)
+        );
+    }
+}
@@ -1,48 +1,807 @@
-use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
-use ark_std::io::Cursor;
+use ark_ec::pairing::PairingOutput;
+use ark_ec::short_weierstrass::Projective;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+use ark_std::Zero;
+use std::fmt;
 
-use crate::common::{Gt, F, G1, G2};
+use crate::common::{
+    Curve, CurveConfig, DealtCard, EncryptionProof, Gt, PayloadKind, PermutationProof,
+    PermutationProofV2, SigmaProof, F, G1, G2,
+};
+
+/// version byte prepended to every serialized proof, bump on format changes
+pub const PROOF_FORMAT_VERSION: u8 = 3;
+
+/// version of the encoding of single values, carried in their tag; at most 3,
+/// since the tag keeps two more bits for the kind
+pub const VALUE_FORMAT_VERSION: u8 = 1;
+
+#[derive(Debug)]
+pub enum DecodeError {
+    /// input is not a valid base58 string
+    InvalidBase58,
+    /// input is empty, so there is not even a version byte
+    Empty,
+    /// envelope carries a version we do not know how to parse
+    UnsupportedVersion(u8),
+    /// arkworks rejected the bytes (truncated, off-curve, wrong subgroup, ...)
+    Serialization(SerializationError),
+    /// bytes were left over after the value was parsed
+    TrailingBytes(usize),
+    /// decoded byte length does not match the compressed size of the type
+    WrongLength { expected: usize, actual: usize },
+    /// field element encoding is not reduced modulo the field characteristic
+    NonCanonical,
+    /// group element is not on the curve or not in the prime-order subgroup
+    InvalidGroupElement,
+    /// a vector holds a different number of values than expected
+    WrongCount { expected: usize, actual: usize },
+    /// the tag names another curve, kind or format than the one expected, e.g.
+    /// because the sender was built with another curve feature
+    CurveMismatch { expected: u8, got: u8 },
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::InvalidBase58 => write!(f, "invalid base58 string"),
+            DecodeError::Empty => write!(f, "empty input"),
+            DecodeError::UnsupportedVersion(v) => write!(f, "unsupported format version {}", v),
+            DecodeError::Serialization(e) => write!(f, "malformed encoding: {}", e),
+            DecodeError::TrailingBytes(n) => write!(f, "{} trailing bytes after value", n),
+            DecodeError::WrongLength { expected, actual } => {
+                write!(f, "expected {} bytes, got {}", expected, actual)
+            }
+            DecodeError::NonCanonical => write!(f, "non-canonical field element"),
+            DecodeError::InvalidGroupElement => write!(f, "invalid group element"),
+            DecodeError::WrongCount { expected, actual } => {
+                write!(f, "expected {} values, got {}", expected, actual)
+            }
+            DecodeError::CurveMismatch { expected, got } => write!(
+                f,
+                "tag {:#04x} does not match {:#04x}; is the sender built for another curve?",
+                got, expected
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+impl From<SerializationError> for DecodeError {
+    fn from(e: SerializationError) -> Self {
+        DecodeError::Serialization(e)
+    }
+}
+
+/// Transportable encodings for the proof structures.
+/// Bytes are a version byte followed by the compressed canonical encoding;
+/// decoding performs on-curve and subgroup checks and never panics.
+pub trait ProofEncoding: CanonicalSerialize + CanonicalDeserialize {
+    /// encoding_tag of the curve the proof is on
+    const TAG: u8;
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut buffer: Vec<u8> = vec![PROOF_FORMAT_VERSION, Self::TAG];
+        self.serialize_compressed(&mut buffer)
+            .expect("serializing into a Vec does not fail");
+        buffer
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let (version, body) = bytes.split_first().ok_or(DecodeError::Empty)?;
+        if *version != PROOF_FORMAT_VERSION {
+            return Err(DecodeError::UnsupportedVersion(*version));
+        }
+        let (tag, body) = body.split_first().ok_or(DecodeError::Empty)?;
+        if *tag != Self::TAG {
+            return Err(DecodeError::CurveMismatch {
+                expected: Self::TAG,
+                got: *tag,
+            });
+        }
+
+        let mut reader = body;
+        let value = Self::deserialize_compressed(&mut reader)?;
+        if !reader.is_empty() {
+            return Err(DecodeError::TrailingBytes(reader.len()));
+        }
+        Ok(value)
+    }
+
+    fn to_bs58(&self) -> String {
+        bs58::encode(self.to_bytes()).into_string()
+    }
+
+    fn from_bs58(msg: &str) -> Result<Self, DecodeError> {
+        let bytes = bs58::decode(msg)
+            .into_vec()
+            .map_err(|_| DecodeError::InvalidBase58)?;
+        Self::from_bytes(&bytes)
+    }
+}
+
+impl<E: CurveConfig> ProofEncoding for PermutationProof<E> {
+    const TAG: u8 = encoding_tag_of::<E>();
+}
+impl<E: CurveConfig> ProofEncoding for PermutationProofV2<E> {
+    const TAG: u8 = encoding_tag_of::<E>();
+}
+impl<E: CurveConfig> ProofEncoding for EncryptionProof<E> {
+    const TAG: u8 = encoding_tag_of::<E>();
+}
+impl<E: CurveConfig> ProofEncoding for SigmaProof<E> {
+    const TAG: u8 = encoding_tag_of::<E>();
+}
+impl<E: CurveConfig> ProofEncoding for DealtCard<E> {
+    const TAG: u8 = encoding_tag_of::<E>();
+}
+
+/// the values exchanged between the parties, each encoded behind the tag of its
+/// kind and curve
+pub trait PayloadValue: CanonicalSerialize + CanonicalDeserialize + Zero {
+    const KIND: PayloadKind;
+    type Curve: CurveConfig;
+}
+
+// the scalars and the points of G1 and G2 of every curve whose feature is on;
+// Gt is PairingOutput, which is generic over the curve
+macro_rules! impl_payload_values {
+    ($curve:ident, $engine:ident) => {
+        impl PayloadValue for $curve::Fr {
+            const KIND: PayloadKind = PayloadKind::Scalar;
+            type Curve = $curve::$engine;
+        }
+
+        impl PayloadValue for Projective<$curve::g1::Config> {
+            const KIND: PayloadKind = PayloadKind::G1;
+            type Curve = $curve::$engine;
+        }
+
+        impl PayloadValue for Projective<$curve::g2::Config> {
+            const KIND: PayloadKind = PayloadKind::G2;
+            type Curve = $curve::$engine;
+        }
+    };
+}
+
+#[cfg(feature = "bls12_381")]
+impl_payload_values!(ark_bls12_381, Bls12_381);
+#[cfg(feature = "bls12_377")]
+impl_payload_values!(ark_bls12_377, Bls12_377);
+#[cfg(feature = "bn254")]
+impl_payload_values!(ark_bn254, Bn254);
+
+impl<E: CurveConfig> PayloadValue for PairingOutput<E> {
+    const KIND: PayloadKind = PayloadKind::Gt;
+    type Curve = E;
+}
+
+/// the curve and value format of this build, as peers announce it when they
+/// connect: the format version in the high four bits, the curve in the low ones
+pub const fn encoding_tag() -> u8 {
+    encoding_tag_of::<Curve>()
+}
+
+/// encoding_tag of a build for the curve E
+pub const fn encoding_tag_of<E: CurveConfig>() -> u8 {
+    VALUE_FORMAT_VERSION << 4 | E::CURVE_ID
+}
+
+/// the byte in front of every encoded value of kind: encoding_tag, then two bits
+/// for the kind, so that a value is never decoded as another kind or curve
+pub fn value_tag(kind: PayloadKind) -> u8 {
+    value_tag_of::<Curve>(kind)
+}
+
+/// value_tag of a value of kind on the curve E
+pub fn value_tag_of<E: CurveConfig>(kind: PayloadKind) -> u8 {
+    let kind_bits = match kind {
+        PayloadKind::Scalar => 0,
+        PayloadKind::G1 => 1,
+        PayloadKind::G2 => 2,
+        PayloadKind::Gt => 3,
+    };
+    encoding_tag_of::<E>() << 2 | kind_bits
+}
+
+/// the tagged encoding of any value, in base58; the functions below name the
+/// type for values of Curve
+pub fn encode_as_bs58_str<T: PayloadValue>(value: &T) -> String {
+    bs58::encode(encode_as_bytes(value)).into_string()
+}
+
+/// decodes a string made by encode_as_bs58_str, for any type of value
+pub fn decode_bs58_str_as<T: PayloadValue>(msg: &str) -> Result<T, DecodeError> {
+    let bytes = bs58::decode(msg)
+        .into_vec()
+        .map_err(|_| DecodeError::InvalidBase58)?;
+    decode_from_bytes(&bytes)
+}
 
 pub fn encode_f_as_bs58_str(value: &F) -> String {
-    let mut buffer: Vec<u8> = Vec::new();
-    value.serialize_compressed(&mut buffer).unwrap();
-    bs58::encode(buffer).into_string()
+    encode_as_bs58_str(value)
 }
 
-pub fn decode_bs58_str_as_f(msg: &String) -> F {
-    let buf: Vec<u8> = bs58::decode(msg).into_vec().unwrap();
-    F::deserialize_compressed(buf.as_slice()).unwrap()
+pub fn decode_bs58_str_as_f(msg: &str) -> Result<F, DecodeError> {
+    decode_bs58_str_as(msg)
 }
 
 pub fn encode_g1_as_bs58_str(value: &G1) -> String {
-    let mut serialized_msg: Vec<u8> = Vec::new();
-    value.serialize_compressed(&mut serialized_msg).unwrap();
-    bs58::encode(serialized_msg).into_string()
+    encode_as_bs58_str(value)
 }
 
-pub fn decode_bs58_str_as_g1(msg: &String) -> G1 {
-    let decoded = bs58::decode(msg).into_vec().unwrap();
-    G1::deserialize_compressed(&mut Cursor::new(decoded)).unwrap()
+pub fn decode_bs58_str_as_g1(msg: &str) -> Result<G1, DecodeError> {
+    decode_bs58_str_as(msg)
 }
 
 pub fn encode_g2_as_bs58_str(value: &G2) -> String {
-    let mut serialized_msg: Vec<u8> = Vec::new();
-    value.serialize_compressed(&mut serialized_msg).unwrap();
-    bs58::encode(serialized_msg).into_string()
+    encode_as_bs58_str(value)
 }
 
-pub fn decode_bs58_str_as_g2(msg: &String) -> G2 {
-    let decoded = bs58::decode(msg).into_vec().unwrap();
-    G2::deserialize_compressed(&mut Cursor::new(decoded)).unwrap()
+pub fn decode_bs58_str_as_g2(msg: &str) -> Result<G2, DecodeError> {
+    decode_bs58_str_as(msg)
 }
 
 pub fn encode_gt_as_bs58_str(value: &Gt) -> String {
-    let mut serialized_msg: Vec<u8> = Vec::new();
-    value.serialize_compressed(&mut serialized_msg).unwrap();
-    bs58::encode(serialized_msg).into_string()
+    encode_as_bs58_str(value)
+}
+
+pub fn decode_bs58_str_as_gt(msg: &str) -> Result<Gt, DecodeError> {
+    decode_bs58_str_as(msg)
+}
+
+/// returns the tag of the kind of value followed by its compressed canonical encoding
+pub fn encode_as_bytes<T: PayloadValue>(value: &T) -> Vec<u8> {
+    let mut buffer: Vec<u8> = Vec::with_capacity(1 + value.compressed_size());
+    buffer.push(value_tag_of::<T::Curve>(T::KIND));
+    value
+        .serialize_compressed(&mut buffer)
+        .expect("serializing into a Vec does not fail");
+    buffer
+}
+
+/// decodes bytes made by encode_as_bytes, checking the tag before anything else
+pub fn decode_from_bytes<T: PayloadValue>(bytes: &[u8]) -> Result<T, DecodeError> {
+    let (tag, body) = bytes.split_first().ok_or(DecodeError::Empty)?;
+    let expected = value_tag_of::<T::Curve>(T::KIND);
+    if *tag != expected {
+        return Err(DecodeError::CurveMismatch {
+            expected,
+            got: *tag,
+        });
+    }
+    let size = T::zero().compressed_size();
+    if body.len() != size {
+        return Err(DecodeError::WrongLength {
+            expected: size + 1,
+            actual: bytes.len(),
+        });
+    }
+    T::deserialize_compressed(body).map_err(|_| match T::KIND {
+        PayloadKind::Scalar => DecodeError::NonCanonical,
+        _ => DecodeError::InvalidGroupElement,
+    })
+}
+
+/// returns the size in bytes of one value of the given kind inside a binary payload
+pub fn payload_element_size(kind: PayloadKind) -> usize {
+    payload_element_size_of::<Curve>(kind)
 }
 
-pub fn decode_bs58_str_as_gt(msg: &String) -> Gt {
-    let decoded = bs58::decode(msg).into_vec().unwrap();
-    Gt::deserialize_compressed(&mut Cursor::new(decoded)).unwrap()
+/// payload_element_size of a value of kind on the curve E
+pub fn payload_element_size_of<E: CurveConfig>(kind: PayloadKind) -> usize {
+    1 + match kind {
+        PayloadKind::Scalar => E::ScalarField::zero().compressed_size(),
+        PayloadKind::G1 => E::G1::zero().compressed_size(),
+        PayloadKind::G2 => E::G2::zero().compressed_size(),
+        PayloadKind::Gt => PairingOutput::<E>::zero().compressed_size(),
+    }
+}
+
+/// byte strings of equal length as one base58 string: their number as 4
+/// little-endian bytes, followed by their concatenation
+pub fn pack_bytes(values: &[Vec<u8>]) -> String {
+    debug_assert!(values.windows(2).all(|w| w[0].len() == w[1].len()));
+    let mut buffer = (values.len() as u32).to_le_bytes().to_vec();
+    for value in values {
+        buffer.extend_from_slice(value);
+    }
+    bs58::encode(buffer).into_string()
+}
+
+/// the byte strings packed into msg by pack_bytes; their length follows from
+/// their number, so that nothing is returned unless all of msg splits evenly
+pub fn unpack_bytes(msg: &str) -> Result<Vec<Vec<u8>>, DecodeError> {
+    let decoded = bs58::decode(msg)
+        .into_vec()
+        .map_err(|_| DecodeError::InvalidBase58)?;
+    if decoded.len() < 4 {
+        return Err(DecodeError::WrongLength {
+            expected: 4,
+            actual: decoded.len(),
+        });
+    }
+    let (prefix, body) = decoded.split_at(4);
+    let count = u32::from_le_bytes([prefix[0], prefix[1], prefix[2], prefix[3]]) as usize;
+    if count == 0 {
+        return match body.len() {
+            0 => Ok(Vec::new()),
+            n => Err(DecodeError::TrailingBytes(n)),
+        };
+    }
+    if body.is_empty() || body.len() % count != 0 {
+        return Err(DecodeError::WrongLength {
+            expected: count * (body.len() / count).max(1),
+            actual: body.len(),
+        });
+    }
+    Ok(body
+        .chunks(body.len() / count)
+        .map(|chunk| chunk.to_vec())
+        .collect())
+}
+
+/// the tagged encodings of values, packed into one base58 string
+pub fn encode_many<T: PayloadValue>(values: &[T]) -> String {
+    pack_bytes(&values.iter().map(encode_as_bytes).collect::<Vec<Vec<u8>>>())
+}
+
+/// decodes every value packed into msg, or none of them
+pub fn decode_many<T: PayloadValue>(msg: &str) -> Result<Vec<T>, DecodeError> {
+    unpack_bytes(msg)?
+        .iter()
+        .map(|bytes| decode_from_bytes(bytes))
+        .collect()
+}
+
+pub fn encode_fs(values: &[F]) -> String {
+    encode_many(values)
+}
+
+pub fn decode_fs(msg: &str) -> Result<Vec<F>, DecodeError> {
+    decode_many(msg)
+}
+
+pub fn encode_g1s(values: &[G1]) -> String {
+    encode_many(values)
+}
+
+pub fn decode_g1s(msg: &str) -> Result<Vec<G1>, DecodeError> {
+    decode_many(msg)
+}
+
+pub fn encode_g2s(values: &[G2]) -> String {
+    encode_many(values)
+}
+
+pub fn decode_g2s(msg: &str) -> Result<Vec<G2>, DecodeError> {
+    decode_many(msg)
+}
+
+pub fn encode_gts(values: &[Gt]) -> String {
+    encode_many(values)
+}
+
+pub fn decode_gts(msg: &str) -> Result<Vec<Gt>, DecodeError> {
+    decode_many(msg)
+}
+
+/// the per-handle base58 values of a PublishBatchValue for count handles. The
+/// values come as one packed vector; one value per handle is the format of
+/// earlier releases, still accepted for now
+pub fn unpack_batch_values(count: usize, values: &[String]) -> Result<Vec<String>, DecodeError> {
+    if values.len() == count {
+        return Ok(values.to_vec());
+    }
+    if values.len() != 1 {
+        return Err(DecodeError::WrongCount {
+            expected: count,
+            actual: values.len(),
+        });
+    }
+    let elements = unpack_bytes(&values[0])?;
+    if elements.len() != count {
+        return Err(DecodeError::WrongCount {
+            expected: count,
+            actual: elements.len(),
+        });
+    }
+    Ok(elements
+        .iter()
+        .map(|bytes| bs58::encode(bytes).into_string())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        decode_bs58_str_as_f, decode_bs58_str_as_g1, decode_bs58_str_as_g2, decode_bs58_str_as_gt,
+        decode_fs, decode_g1s, decode_g2s, decode_gts, encode_f_as_bs58_str, encode_fs,
+        encode_g1_as_bs58_str, encode_g1s, encode_g2_as_bs58_str, encode_g2s,
+        encode_gt_as_bs58_str, encode_gts, encoding_tag, pack_bytes, unpack_batch_values,
+        value_tag, DecodeError, ProofEncoding, PROOF_FORMAT_VERSION,
+    };
+    use crate::common::{
+        Curve, EncryptionProof, Gt, PayloadKind, PermutationProof, SigmaProof, F, G1, G2,
+    };
+    use crate::transcript::FsHasher;
+    use ark_std::UniformRand;
+    use rand::Rng;
+    use rand_chacha::rand_core::SeedableRng;
+
+    fn random_sigma_proof<R: Rng>(rng: &mut R) -> SigmaProof {
+        SigmaProof {
+            a1: G2::rand(rng),
+            a2: Gt::rand(rng),
+            y: F::rand(rng),
+        }
+    }
+
+    fn random_permutation_proof<R: Rng>(rng: &mut R) -> PermutationProof {
+        PermutationProof {
+            y1: F::rand(rng),
+            y2: F::rand(rng),
+            y3: F::rand(rng),
+            y4: F::rand(rng),
+            y5: F::rand(rng),
+            pi_1: G1::rand(rng),
+            pi_2: G1::rand(rng),
+            pi_3: G1::rand(rng),
+            pi_4: G1::rand(rng),
+            pi_5: G1::rand(rng),
+            f_com: G1::rand(rng),
+            q_com: G1::rand(rng),
+            t_com: G1::rand(rng),
+            hasher: FsHasher::Poseidon,
+        }
+    }
+
+    fn random_encryption_proof<R: Rng>(rng: &mut R) -> EncryptionProof {
+        EncryptionProof {
+            pk: G2::rand(rng),
+            ids: (0..4u8).map(|i| vec![i; 3]).collect(),
+            card_commitment: G1::rand(rng),
+            card_poly_eval: F::rand(rng),
+            eval_proof: G1::rand(rng),
+            hiding_ciphertext: Gt::rand(rng),
+            t: Gt::rand(rng),
+            sigma_proof: Some(random_sigma_proof(rng)),
+            hasher: FsHasher::Sha256,
+        }
+    }
+
+    #[test]
+    fn test_proof_round_trip() {
+        let mut rng = rand_chacha::ChaCha8Rng::from_seed([7u8; 32]);
+
+        let perm = random_permutation_proof(&mut rng);
+        let decoded: PermutationProof = PermutationProof::from_bytes(&perm.to_bytes()).unwrap();
+        assert_eq!(perm.to_bytes(), decoded.to_bytes());
+        let decoded: PermutationProof = PermutationProof::from_bs58(&perm.to_bs58()).unwrap();
+        assert_eq!(perm.f_com, decoded.f_com);
+        assert_eq!(perm.y5, decoded.y5);
+        assert_eq!(perm.hasher, decoded.hasher);
+
+        let enc = random_encryption_proof(&mut rng);
+        let decoded: EncryptionProof = EncryptionProof::from_bs58(&enc.to_bs58()).unwrap();
+        assert_eq!(enc.to_bytes(), decoded.to_bytes());
+        assert_eq!(enc.ids, decoded.ids);
+
+        let sigma = random_sigma_proof(&mut rng);
+        let decoded = SigmaProof::from_bytes(&sigma.to_bytes()).unwrap();
+        assert_eq!(sigma.a1, decoded.a1);
+        assert_eq!(sigma.a2, decoded.a2);
+        assert_eq!(sigma.y, decoded.y);
+    }
+
+    #[test]
+    fn test_proof_rejects_bad_envelope() {
+        let mut rng = rand_chacha::ChaCha8Rng::from_seed([8u8; 32]);
+        let bytes = random_permutation_proof(&mut rng).to_bytes();
+        assert_eq!(bytes[0], PROOF_FORMAT_VERSION);
+        assert_eq!(bytes[1], encoding_tag());
+
+        assert!(matches!(
+            PermutationProof::<Curve>::from_bytes(&[]),
+            Err(DecodeError::Empty)
+        ));
+
+        let mut wrong_version = bytes.clone();
+        wrong_version[0] = PROOF_FORMAT_VERSION + 1;
+        assert!(matches!(
+            PermutationProof::<Curve>::from_bytes(&wrong_version),
+            Err(DecodeError::UnsupportedVersion(_))
+        ));
+
+        // a proof made for another curve
+        let mut wrong_curve = bytes.clone();
+        wrong_curve[1] ^= 0x03;
+        assert!(matches!(
+            PermutationProof::<Curve>::from_bytes(&wrong_curve),
+            Err(DecodeError::CurveMismatch { .. })
+        ));
+
+        let mut trailing = bytes.clone();
+        trailing.push(0);
+        assert!(matches!(
+            PermutationProof::<Curve>::from_bytes(&trailing),
+            Err(DecodeError::TrailingBytes(1))
+        ));
+
+        // the hasher is the last byte, and must be one we know
+        let mut unknown_hasher = bytes.clone();
+        *unknown_hasher.last_mut().unwrap() = 7;
+        assert!(matches!(
+            PermutationProof::<Curve>::from_bytes(&unknown_hasher),
+            Err(DecodeError::Serialization(_))
+        ));
+
+        assert!(matches!(
+            PermutationProof::<Curve>::from_bs58("0OIl"),
+            Err(DecodeError::InvalidBase58)
+        ));
+    }
+
+    #[test]
+    fn test_proof_decoding_never_panics() {
+        let mut rng = rand_chacha::ChaCha8Rng::from_seed([9u8; 32]);
+        let bytes = random_encryption_proof(&mut rng).to_bytes();
+
+        // every strict prefix must be rejected
+        for len in 0..bytes.len() {
+            assert!(EncryptionProof::<Curve>::from_bytes(&bytes[..len]).is_err());
+        }
+
+        // random bit flips must either be rejected or decode to a different value
+        for _ in 0..256 {
+            let mut corrupted = bytes.clone();
+            let pos = rng.gen_range(0..corrupted.len());
+            corrupted[pos] ^= 1 << rng.gen_range(0..8);
+            if let Ok(proof) = EncryptionProof::<Curve>::from_bytes(&corrupted) {
+                assert_ne!(proof.to_bytes(), bytes);
+            }
+        }
+    }
+
+    #[test]
+    fn test_decode_rejects_malformed_values() {
+        let mut rng = rand_chacha::ChaCha8Rng::from_seed([10u8; 32]);
+
+        // round trips still work
+        let f = F::rand(&mut rng);
+        assert_eq!(decode_bs58_str_as_f(&encode_f_as_bs58_str(&f)).unwrap(), f);
+        let g1 = G1::rand(&mut rng);
+        assert_eq!(
+            decode_bs58_str_as_g1(&encode_g1_as_bs58_str(&g1)).unwrap(),
+            g1
+        );
+        let g2 = G2::rand(&mut rng);
+        assert_eq!(
+            decode_bs58_str_as_g2(&encode_g2_as_bs58_str(&g2)).unwrap(),
+            g2
+        );
+        let gt = Gt::rand(&mut rng);
+        assert_eq!(
+            decode_bs58_str_as_gt(&encode_gt_as_bs58_str(&gt)).unwrap(),
+            gt
+        );
+
+        // invalid base58 alphabet
+        assert!(matches!(
+            decode_bs58_str_as_f("0OIl"),
+            Err(DecodeError::InvalidBase58)
+        ));
+        assert!(matches!(
+            decode_bs58_str_as_g1("0OIl"),
+            Err(DecodeError::InvalidBase58)
+        ));
+        assert!(matches!(
+            decode_bs58_str_as_g2("0OIl"),
+            Err(DecodeError::InvalidBase58)
+        ));
+        assert!(matches!(
+            decode_bs58_str_as_gt("0OIl"),
+            Err(DecodeError::InvalidBase58)
+        ));
+
+        // truncated values
+        let short = |kind| {
+            let mut bytes = vec![value_tag(kind)];
+            bytes.extend_from_slice(&[1u8; 5]);
+            bs58::encode(bytes).into_string()
+        };
+        assert!(matches!(
+            decode_bs58_str_as_f(&short(PayloadKind::Scalar)),
+            Err(DecodeError::WrongLength { .. })
+        ));
+        assert!(matches!(
+            decode_bs58_str_as_g1(&short(PayloadKind::G1)),
+            Err(DecodeError::WrongLength { .. })
+        ));
+        assert!(matches!(
+            decode_bs58_str_as_g2(&short(PayloadKind::G2)),
+            Err(DecodeError::WrongLength { .. })
+        ));
+        assert!(matches!(
+            decode_bs58_str_as_gt(&short(PayloadKind::Gt)),
+            Err(DecodeError::WrongLength { .. })
+        ));
+
+        // all-ones bytes exceed the modulus and are not valid curve points, as long
+        // as the infinity flag of a point is clear and the rest is looked at
+        let f_len = bs58::decode(encode_f_as_bs58_str(&f))
+            .into_vec()
+            .unwrap()
+            .len();
+        let mut bytes = vec![0xffu8; f_len];
+        bytes[0] = value_tag(PayloadKind::Scalar);
+        let saturated = bs58::encode(bytes).into_string();
+        assert!(matches!(
+            decode_bs58_str_as_f(&saturated),
+            Err(DecodeError::NonCanonical)
+        ));
+
+        let g1_len = bs58::decode(encode_g1_as_bs58_str(&g1))
+            .into_vec()
+            .unwrap()
+            .len();
+        let mut bytes = vec![0xffu8; g1_len];
+        bytes[0] = value_tag(PayloadKind::G1);
+        bytes[1] = 0x9f;
+        let saturated = bs58::encode(bytes).into_string();
+        assert!(matches!(
+            decode_bs58_str_as_g1(&saturated),
+            Err(DecodeError::InvalidGroupElement)
+        ));
+
+        let g2_len = bs58::decode(encode_g2_as_bs58_str(&g2))
+            .into_vec()
+            .unwrap()
+            .len();
+        let mut bytes = vec![0xffu8; g2_len];
+        bytes[0] = value_tag(PayloadKind::G2);
+        bytes[1] = 0x9f;
+        let saturated = bs58::encode(bytes).into_string();
+        assert!(matches!(
+            decode_bs58_str_as_g2(&saturated),
+            Err(DecodeError::InvalidGroupElement)
+        ));
+
+        // a random Fq12 element is almost surely outside the order-r subgroup
+        let gt_len = bs58::decode(encode_gt_as_bs58_str(&gt))
+            .into_vec()
+            .unwrap()
+            .len();
+        let mut bytes = vec![0u8; gt_len];
+        bytes[0] = value_tag(PayloadKind::Gt);
+        bytes[1] = 2;
+        let outside = bs58::encode(bytes).into_string();
+        assert!(matches!(
+            decode_bs58_str_as_gt(&outside),
+            Err(DecodeError::InvalidGroupElement)
+        ));
+    }
+
+    #[test]
+    fn test_vectors_round_trip() {
+        let mut rng = rand_chacha::ChaCha8Rng::from_seed([11u8; 32]);
+
+        let fs: Vec<F> = (0..5).map(|_| F::rand(&mut rng)).collect();
+        assert_eq!(decode_fs(&encode_fs(&fs)).unwrap(), fs);
+        let g1s: Vec<G1> = (0..3).map(|_| G1::rand(&mut rng)).collect();
+        assert_eq!(decode_g1s(&encode_g1s(&g1s)).unwrap(), g1s);
+        let g2s: Vec<G2> = (0..3).map(|_| G2::rand(&mut rng)).collect();
+        assert_eq!(decode_g2s(&encode_g2s(&g2s)).unwrap(), g2s);
+        let gts: Vec<Gt> = (0..2).map(|_| Gt::rand(&mut rng)).collect();
+        assert_eq!(decode_gts(&encode_gts(&gts)).unwrap(), gts);
+        assert_eq!(decode_fs(&encode_fs(&[])).unwrap(), vec![]);
+
+        // the per-handle values of a packed batch are the single-value encodings
+        let per_handle: Vec<String> = fs.iter().map(encode_f_as_bs58_str).collect();
+        assert_eq!(
+            unpack_batch_values(5, &[encode_fs(&fs)]).unwrap(),
+            per_handle
+        );
+        assert_eq!(unpack_batch_values(5, &per_handle).unwrap(), per_handle);
+        assert!(matches!(
+            unpack_batch_values(4, &[encode_fs(&fs)]),
+            Err(DecodeError::WrongCount {
+                expected: 4,
+                actual: 5
+            })
+        ));
+    }
+
+    #[test]
+    fn test_vectors_decode_all_or_nothing() {
+        let mut rng = rand_chacha::ChaCha8Rng::from_seed([12u8; 32]);
+        let fs: Vec<F> = (0..4).map(|_| F::rand(&mut rng)).collect();
+        let mut elements: Vec<Vec<u8>> = fs.iter().map(super::encode_as_bytes).collect();
+
+        // one value out of range spoils the whole vector
+        let len = elements[2].len();
+        elements[2] = vec![0xffu8; len];
+        elements[2][0] = value_tag(PayloadKind::Scalar);
+        assert!(matches!(
+            decode_fs(&pack_bytes(&elements)),
+            Err(DecodeError::NonCanonical)
+        ));
+
+        // a vector of another type is caught by the tags of its values
+        assert!(matches!(
+            decode_g1s(&encode_fs(&fs)),
+            Err(DecodeError::CurveMismatch { .. })
+        ));
+
+        let bytes = bs58::decode(encode_fs(&fs)).into_vec().unwrap();
+        for len in 0..bytes.len() {
+            let truncated = bs58::encode(&bytes[..len]).into_string();
+            assert!(decode_fs(&truncated).is_err());
+        }
+
+        let mut wrong_count = bytes.clone();
+        wrong_count[0] = 3;
+        assert!(decode_fs(&bs58::encode(wrong_count).into_string()).is_err());
+        let mut empty_with_body = bytes;
+        empty_with_body[0] = 0;
+        assert!(matches!(
+            decode_fs(&bs58::encode(empty_with_body).into_string()),
+            Err(DecodeError::TrailingBytes(_))
+        ));
+        assert!(matches!(decode_fs("0OIl"), Err(DecodeError::InvalidBase58)));
+    }
+
+    // builds for different curves tell their encodings apart by the low bits
+    #[test]
+    fn test_curves_have_distinct_tags() {
+        let curve_id = if cfg!(feature = "bls12_381") {
+            1
+        } else if cfg!(feature = "bls12_377") {
+            2
+        } else {
+            3
+        };
+        assert_eq!(encoding_tag() & 0x0f, curve_id);
+    }
+
+    #[test]
+    fn test_values_reject_wrong_tag() {
+        let mut rng = rand_chacha::ChaCha8Rng::from_seed([13u8; 32]);
+        let f = F::rand(&mut rng);
+        let bytes = bs58::decode(encode_f_as_bs58_str(&f)).into_vec().unwrap();
+        assert_eq!(bytes[0], value_tag(PayloadKind::Scalar));
+
+        // what a peer built for the other curve would send: the scalar bytes may
+        // well be canonical there too, so only the tag can tell
+        let mut other_curve = bytes.clone();
+        other_curve[0] = value_tag(PayloadKind::Scalar) ^ 0x0c;
+        match decode_bs58_str_as_f(&bs58::encode(&other_curve).into_string()) {
+            Err(DecodeError::CurveMismatch { expected, got }) => {
+                assert_eq!(expected, value_tag(PayloadKind::Scalar));
+                assert_eq!(got, other_curve[0]);
+            }
+            _ => panic!("a value tagged for another curve was accepted"),
+        }
+
+        // a scalar is never taken for a group element, nor the other way round
+        assert!(matches!(
+            decode_bs58_str_as_g1(&encode_f_as_bs58_str(&f)),
+            Err(DecodeError::CurveMismatch { .. })
+        ));
+        let g1 = G1::rand(&mut rng);
+        assert!(matches!(
+            decode_bs58_str_as_f(&encode_g1_as_bs58_str(&g1)),
+            Err(DecodeError::CurveMismatch { .. })
+        ));
+
+        // the same holds inside a packed vector
+        let mut elements: Vec<Vec<u8>> = vec![bytes.clone(), other_curve];
+        assert!(matches!(
+            decode_fs(&pack_bytes(&elements)),
+            Err(DecodeError::CurveMismatch { .. })
+        ));
+        elements.pop();
+        assert_eq!(decode_fs(&pack_bytes(&elements)).unwrap(), vec![f]);
+    }
 }
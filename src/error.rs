@@ -0,0 +1,245 @@
+//! One error type for everything the crate can fail with, for integrators who
+//! drive several components and want a single `?`. Each component keeps its own
+//! error, re-exported here, and Pok3rError wraps whichever one failed.
+
+use std::{error, fmt};
+
+#[cfg(feature = "networking")]
+pub use crate::address_book::AddrBookError;
+pub use crate::encoding::DecodeError;
+#[cfg(feature = "networking")]
+pub use crate::evaluator::{BuildError, EvaluatorError};
+pub use crate::kzg::{KzgError, ParamsError};
+#[cfg(feature = "networking")]
+pub use crate::network::NetworkError;
+#[cfg(feature = "networking")]
+pub use crate::preprocessing::PreprocessingError;
+pub use crate::proofs::ProofError;
+#[cfg(feature = "networking")]
+pub use crate::shuffler::DealError;
+
+#[derive(Debug)]
+pub enum Pok3rError {
+    /// sending or receiving failed, or a party aborted
+    #[cfg(feature = "networking")]
+    Network(NetworkError),
+    /// a gate, an opening or the preprocessing failed
+    #[cfg(feature = "networking")]
+    Evaluator(EvaluatorError),
+    /// the settings of an evaluator do not fit together
+    #[cfg(feature = "networking")]
+    Build(BuildError),
+    /// a card could not be dealt or revealed
+    #[cfg(feature = "networking")]
+    Deal(DealError),
+    /// a proof does not verify
+    Proof(ProofError),
+    /// a commitment or an opening proof could not be computed
+    Kzg(KzgError),
+    /// the KZG params could not be loaded or saved
+    Params(ParamsError),
+    /// a value, proof or message is malformed
+    Decode(DecodeError),
+    /// a preprocessing file could not be exported or loaded
+    #[cfg(feature = "networking")]
+    Preprocessing(PreprocessingError),
+    /// the addr book is malformed
+    #[cfg(feature = "networking")]
+    AddrBook(AddrBookError),
+}
+
+impl fmt::Display for Pok3rError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            #[cfg(feature = "networking")]
+            Pok3rError::Network(err) => write!(f, "network error: {}", err),
+            #[cfg(feature = "networking")]
+            Pok3rError::Evaluator(err) => write!(f, "evaluator error: {}", err),
+            #[cfg(feature = "networking")]
+            Pok3rError::Build(err) => write!(f, "cannot build the evaluator: {}", err),
+            #[cfg(feature = "networking")]
+            Pok3rError::Deal(err) => write!(f, "deal error: {}", err),
+            Pok3rError::Proof(err) => write!(f, "invalid proof: {}", err),
+            Pok3rError::Kzg(err) => write!(f, "commitment error: {}", err),
+            Pok3rError::Params(err) => write!(f, "params error: {}", err),
+            Pok3rError::Decode(err) => write!(f, "decode error: {}", err),
+            #[cfg(feature = "networking")]
+            Pok3rError::Preprocessing(err) => write!(f, "preprocessing error: {}", err),
+            #[cfg(feature = "networking")]
+            Pok3rError::AddrBook(err) => write!(f, "addr book error: {}", err),
+        }
+    }
+}
+
+impl error::Error for Pok3rError {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "networking")]
+            Pok3rError::Network(err) => Some(err),
+            #[cfg(feature = "networking")]
+            Pok3rError::Evaluator(err) => Some(err),
+            #[cfg(feature = "networking")]
+            Pok3rError::Build(err) => Some(err),
+            #[cfg(feature = "networking")]
+            Pok3rError::Deal(err) => Some(err),
+            Pok3rError::Proof(err) => Some(err),
+            Pok3rError::Kzg(err) => Some(err),
+            Pok3rError::Params(err) => Some(err),
+            Pok3rError::Decode(err) => Some(err),
+            #[cfg(feature = "networking")]
+            Pok3rError::Preprocessing(err) => Some(err),
+            #[cfg(feature = "networking")]
+            Pok3rError::AddrBook(err) => Some(err),
+        }
+    }
+}
+
+#[cfg(feature = "networking")]
+impl From<NetworkError> for Pok3rError {
+    fn from(err: NetworkError) -> Self {
+        Pok3rError::Network(err)
+    }
+}
+
+#[cfg(feature = "networking")]
+impl From<EvaluatorError> for Pok3rError {
+    fn from(err: EvaluatorError) -> Self {
+        Pok3rError::Evaluator(err)
+    }
+}
+
+#[cfg(feature = "networking")]
+impl From<BuildError> for Pok3rError {
+    fn from(err: BuildError) -> Self {
+        Pok3rError::Build(err)
+    }
+}
+
+#[cfg(feature = "networking")]
+impl From<DealError> for Pok3rError {
+    fn from(err: DealError) -> Self {
+        Pok3rError::Deal(err)
+    }
+}
+
+impl From<ProofError> for Pok3rError {
+    fn from(err: ProofError) -> Self {
+        Pok3rError::Proof(err)
+    }
+}
+
+impl From<KzgError> for Pok3rError {
+    fn from(err: KzgError) -> Self {
+        Pok3rError::Kzg(err)
+    }
+}
+
+impl From<ParamsError> for Pok3rError {
+    fn from(err: ParamsError) -> Self {
+        Pok3rError::Params(err)
+    }
+}
+
+impl From<DecodeError> for Pok3rError {
+    fn from(err: DecodeError) -> Self {
+        Pok3rError::Decode(err)
+    }
+}
+
+#[cfg(feature = "networking")]
+impl From<PreprocessingError> for Pok3rError {
+    fn from(err: PreprocessingError) -> Self {
+        Pok3rError::Preprocessing(err)
+    }
+}
+
+#[cfg(feature = "networking")]
+impl From<AddrBookError> for Pok3rError {
+    fn from(err: AddrBookError) -> Self {
+        Pok3rError::AddrBook(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{Curve, F, G1};
+    use crate::encoding::decode_from_bytes;
+    use crate::kzg::{UniversalParams, KZG10};
+    use ark_poly::univariate::DensePolynomial;
+    use ark_poly::DenseUVPolynomial;
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::error::Error;
+
+    /// everything below fails without a network, the first thing through `?`
+    fn commit_then_decode(pp: &UniversalParams<Curve>, bytes: &[u8]) -> Result<G1, Pok3rError> {
+        let poly = DensePolynomial::from_coefficients_vec(vec![F::from(1u64); 4]);
+        let _ = KZG10::<Curve, DensePolynomial<F>>::commit_g1(pp, &poly)?;
+        Ok(decode_from_bytes(bytes)?)
+    }
+
+    #[test]
+    fn test_components_convert_with_question_mark() {
+        let mut rng = StdRng::from_seed([5u8; 32]);
+        let pp = KZG10::<Curve, DensePolynomial<F>>::setup(8, &mut rng);
+        let small = KZG10::<Curve, DensePolynomial<F>>::setup(2, &mut rng);
+
+        let err = commit_then_decode(&small, &[]).unwrap_err();
+        assert!(matches!(
+            err,
+            Pok3rError::Kzg(KzgError::TooFewPowers {
+                needed: 4,
+                available: 3
+            })
+        ));
+        assert!(err.source().is_some());
+
+        let err = commit_then_decode(&pp, &[]).unwrap_err();
+        assert!(matches!(err, Pok3rError::Decode(DecodeError::Empty)));
+        assert_eq!(err.to_string(), "decode error: empty input");
+
+        let err = Pok3rError::from(ProofError::MissingSigmaProof);
+        assert!(matches!(
+            err,
+            Pok3rError::Proof(ProofError::MissingSigmaProof)
+        ));
+        assert_eq!(
+            err.source().map(|source| source.to_string()),
+            Some(ProofError::MissingSigmaProof.to_string())
+        );
+
+        let err = Pok3rError::from(ParamsError::Inconsistent);
+        assert!(matches!(err, Pok3rError::Params(ParamsError::Inconsistent)));
+    }
+
+    #[cfg(feature = "networking")]
+    #[test]
+    fn test_networking_components_convert() {
+        use crate::common::ProtocolParamsError;
+
+        let err = Pok3rError::from(NetworkError::NotInAddrBook);
+        assert!(matches!(
+            err,
+            Pok3rError::Network(NetworkError::NotInAddrBook)
+        ));
+        let err = Pok3rError::from(EvaluatorError::NotInvertible { index: 2 });
+        assert!(matches!(
+            err,
+            Pok3rError::Evaluator(EvaluatorError::NotInvertible { index: 2 })
+        ));
+        let err = Pok3rError::from(BuildError::EmptyChunk);
+        assert!(matches!(err, Pok3rError::Build(BuildError::EmptyChunk)));
+        let err = Pok3rError::from(DealError::NoCardsLeft);
+        assert!(matches!(err, Pok3rError::Deal(DealError::NoCardsLeft)));
+        let err = Pok3rError::from(PreprocessingError::Params(
+            ProtocolParamsError::NotPowerOfTwo(3),
+        ));
+        assert!(matches!(err, Pok3rError::Preprocessing(_)));
+        let err = Pok3rError::from(AddrBookError::Empty);
+        assert!(matches!(err, Pok3rError::AddrBook(AddrBookError::Empty)));
+        assert_eq!(
+            err.to_string(),
+            "addr book error: addr book lists no committee members"
+        );
+    }
+}
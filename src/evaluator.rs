@@ -1,961 +1,5960 @@
-use ark_ec::{pairing::Pairing, Group};
+use ark_ec::pairing::PairingOutput;
+use ark_ec::Group;
+use ark_ff::{FftField, Field};
 use ark_poly::univariate::{DenseOrSparsePolynomial, DensePolynomial};
-use ark_poly::DenseUVPolynomial;
-use ark_std::{One, UniformRand, Zero};
-use rand::thread_rng;
-use rand::{rngs::StdRng, SeedableRng};
-use std::collections::HashMap;
+use ark_poly::{DenseUVPolynomial, EvaluationDomain};
+use ark_serialize::SerializationError;
+use ark_std::{cfg_iter, One, UniformRand, Zero};
+use futures::channel::mpsc;
+use rand::{rngs::StdRng, CryptoRng, RngCore, SeedableRng};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::ops::{Add, Mul};
-
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{error, fmt};
+use zeroize::Zeroize;
+
+use crate::accountability::{self, Evidence};
+use crate::address_book::{PeerRole, Pok3rAddrBook, Pok3rPeerId};
+use crate::audit::{self, AuditEntry, AuditError, AuditLog};
+use crate::checkpoint::{CheckpointError, EvaluatorSnapshot};
 use crate::common::{
-    Curve, Gt, F, G1, G2, KZG, LOG_PERM_SIZE, NUM_BEAVER_TRIPLES, NUM_RAND_SHARINGS, PERM_SIZE,
+    Curve, CurveConfig, KzgOn, PayloadKind, PeerInfo, ProtocolParams, ResourceEstimate,
+    FEATURE_COMMITTED_OPENS, FEATURE_THRESHOLD,
 };
 use crate::encoding::{
-    decode_bs58_str_as_f, decode_bs58_str_as_g1, decode_bs58_str_as_g2, decode_bs58_str_as_gt,
-    encode_f_as_bs58_str, encode_g1_as_bs58_str, encode_g2_as_bs58_str, encode_gt_as_bs58_str,
+    decode_bs58_str_as, encode_as_bs58_str, encode_as_bytes, payload_element_size_of, DecodeError,
+    PayloadValue,
 };
-use crate::hash::hash_to_g1;
-use crate::kzg::UniversalParams;
-use crate::network;
-use crate::shamir;
+use crate::group_ops::{GtTable, GT_TABLE_WINDOW};
+use crate::hash::{hash_to_g2_ctx_on, GameContext, IdCache};
+use crate::kzg::{KzgError, UniversalParams};
+use crate::metrics::{Metrics, MetricsSnapshot};
+use crate::network::{self, NetworkError, PeerStatus};
+use crate::pairwise::PairwiseKeys;
+use crate::preprocessing::{self, PreprocessingError, PreprocessingFile};
+use crate::prss::Prss;
+use crate::reconstruct::{reconstruct_checked_by_sender, ReconstructError, Shared};
+use crate::reservoir::{Reservoir, ReservoirStatus};
+use crate::secret::{zeroize_f, Scrub, Secret};
+use crate::shamir::{self, ShamirError};
+use crate::shuffler;
+use crate::transcript::FsHasher;
+use crate::transport::Transport;
 use crate::utils;
+use crate::wire::{derived_label, Wire};
+
+/// triples and random sharings made before the constructors return; the rest
+/// are made in the background, up to RESERVOIR_HIGH_WATER ahead of their use
+const RESERVOIR_BOOTSTRAP: usize = 64;
+const RESERVOIR_HIGH_WATER: usize = 1024;
+
+/// openings whose late shares are still checked against the sharing; the
+/// oldest are dropped past this, as well as the late shares nobody waits for
+const MAX_PENDING_CHECKS: usize = 4096;
+
+/// our shares (a, b, c) of a multiplication triple, c = ab
+type Triple<F> = (F, F, F);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EvaluatorError {
+    /// the transport failed to deliver or receive a value
+    Network(NetworkError),
+    /// the party with node id by (possibly us) aborted the session
+    Aborted { by: u64, reason: String },
+    /// the requested sharing does not fit the committee
+    Sharing(ShamirError),
+    /// the value at index of a batch inversion was opened as zero
+    NotInvertible { index: usize },
+    /// the party with node id deviated from the protocol, as evidence shows
+    Misbehavior {
+        node_id: u64,
+        evidence: Box<Evidence>,
+    },
+    /// the proof opened under handle does not verify, and no party could be blamed
+    InvalidProof { handle: String },
+    /// the pair of triples at index failed the sacrifice check
+    TripleCheckFailed { index: usize },
+    /// the party with node id dealt something that is not a share under handle
+    MalformedDealing { node_id: u64, handle: String },
+    /// the party with node id announced something that is not a pairwise key
+    MalformedKey { node_id: u64 },
+    /// the preprocessing left cannot cover the next phase
+    OutOfPreprocessing {
+        needed: ResourceEstimate,
+        remaining: ResourceEstimate,
+    },
+    /// an opening proof could not be computed
+    Kzg(KzgError),
+    /// the linear combination at index is empty, or its wires and coefficients
+    /// differ in number
+    InvalidCombination {
+        index: usize,
+        handles: usize,
+        coeffs: usize,
+    },
+    /// no share is set for the wire, e.g. because it was freed
+    UnknownWire(Wire),
+    /// the arguments of a gate differ in number
+    LengthMismatch { expected: usize, actual: usize },
+    /// the polynomials of a product have more coefficients than the domain holds
+    DegreeTooLarge { coeffs: usize, max: usize },
+    /// degree-threshold sharings need more than threshold parties
+    ThresholdTooLarge { threshold: u64, parties: usize },
+    /// triples made jointly need an honest majority, so at least needed parties;
+    /// fewer need a dealer
+    TooFewPartiesForTriples { needed: usize, parties: usize },
+    /// the dealer of the triples is not in the committee we rotate to; another
+    /// dealer must be chosen explicitly rather than whoever gets its node id
+    DealerLeft { node_id: u64 },
+    /// the shuffle drew too few distinct cards to fill the permutation; shuffling
+    /// again draws fresh ones
+    TooFewCards { drawn: usize, needed: usize },
+    /// a shared permutation needs at least 2 elements, and no more than the
+    /// domain of the evaluator holds
+    BadPermutationSize { size: usize, max: usize },
+    /// a comparison of keys wider than the field leaves room for under its masks
+    KeyTooWide { bits: usize, max: usize },
+    /// the selector of a select or a swap is not 0 or 1; only the ClearEvaluator
+    /// can tell
+    NotABit { index: usize },
+    /// the shares of an opening do not come from the committee, or are too few
+    Reconstruct(ReconstructError),
+    /// the evaluator was built for found, not for the deck and permutation sizes
+    /// expected of the deck to shuffle
+    WrongDeck {
+        expected: ProtocolParams,
+        found: ProtocolParams,
+    },
+}
 
-pub struct Evaluator {
-    /// local peer id
-    messaging: network::MessagingSystem,
-    /// pre-processed beaver triples
-    beaver_triples: Vec<(F, F, F)>, // (a, b, c) share
-    /// pre-processed random sharings
-    rand_sharings: Vec<F>,
-    /// stores the share associated with each wire
-    wire_shares: HashMap<String, F>,
-    /// keep track of gates
-    gate_counter: u64,
-    /// keep track of the number of beaver triples consumed
-    beaver_counter: u64,
-    /// keep track of the number of rand sharings consumed
-    rand_counter: u64,
+impl fmt::Display for EvaluatorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvaluatorError::Network(err) => write!(f, "network error: {}", err),
+            EvaluatorError::Aborted { by, reason } => {
+                write!(f, "party {} aborted the session: {}", by, reason)
+            }
+            EvaluatorError::Sharing(err) => write!(f, "sharing error: {}", err),
+            EvaluatorError::NotInvertible { index } => {
+                write!(f, "value {} of the batch inversion is zero", index)
+            }
+            EvaluatorError::Misbehavior { node_id, evidence } => {
+                write!(f, "party {} misbehaved in {}", node_id, evidence.handle())
+            }
+            EvaluatorError::InvalidProof { handle } => {
+                write!(f, "the proof opened as {} does not verify", handle)
+            }
+            EvaluatorError::TripleCheckFailed { index } => {
+                write!(f, "triples of pair {} do not multiply", index)
+            }
+            EvaluatorError::MalformedDealing { node_id, handle } => {
+                write!(
+                    f,
+                    "party {} dealt a malformed share for {}",
+                    node_id, handle
+                )
+            }
+            EvaluatorError::MalformedKey { node_id } => {
+                write!(f, "party {} announced a malformed pairwise key", node_id)
+            }
+            EvaluatorError::OutOfPreprocessing { needed, remaining } => write!(
+                f,
+                "out of preprocessing: needed {}, {} remaining",
+                needed, remaining
+            ),
+            EvaluatorError::Kzg(err) => write!(f, "opening proof error: {}", err),
+            EvaluatorError::InvalidCombination {
+                index,
+                handles,
+                coeffs,
+            } => write!(
+                f,
+                "linear combination {} has {} wires and {} coefficients",
+                index, handles, coeffs
+            ),
+            EvaluatorError::UnknownWire(wire) => write!(f, "wire {} holds no share", wire),
+            EvaluatorError::LengthMismatch { expected, actual } => {
+                write!(f, "expected {} arguments, got {}", expected, actual)
+            }
+            EvaluatorError::DegreeTooLarge { coeffs, max } => write!(
+                f,
+                "the product needs {} coefficients, the domain holds {}",
+                coeffs, max
+            ),
+            EvaluatorError::ThresholdTooLarge { threshold, parties } => write!(
+                f,
+                "threshold {} needs more than {} parties",
+                threshold, parties
+            ),
+            EvaluatorError::TooFewPartiesForTriples { needed, parties } => write!(
+                f,
+                "making triples jointly needs {} parties, only {} take part; use a dealer",
+                needed, parties
+            ),
+            EvaluatorError::DealerLeft { node_id } => {
+                write!(f, "the dealer {} is not in the new committee", node_id)
+            }
+            EvaluatorError::TooFewCards { drawn, needed } => write!(
+                f,
+                "the shuffle drew {} distinct cards of the {} needed",
+                drawn, needed
+            ),
+            EvaluatorError::BadPermutationSize { size, max } => write!(
+                f,
+                "a shared permutation of {} elements does not fit between 2 and {}",
+                size, max
+            ),
+            EvaluatorError::KeyTooWide { bits, max } => write!(
+                f,
+                "keys of {} bits cannot be compared, at most {} bits can",
+                bits, max
+            ),
+            EvaluatorError::NotABit { index } => {
+                write!(f, "the selector at index {} is not a bit", index)
+            }
+            EvaluatorError::Reconstruct(err) => write!(f, "cannot reconstruct: {}", err),
+            EvaluatorError::WrongDeck { expected, found } => write!(
+                f,
+                "the evaluator was built for {} cards permuted over {}, not {} over {}",
+                found.deck_size(),
+                found.perm_size(),
+                expected.deck_size(),
+                expected.perm_size()
+            ),
+        }
+    }
 }
 
-impl Evaluator {
-    pub async fn new(messaging: network::MessagingSystem) -> Self {
-        let mut evaluator = Evaluator {
-            wire_shares: HashMap::new(),
-            beaver_triples: Vec::new(),
-            rand_sharings: Vec::new(),
-            messaging,
-            gate_counter: 0,
-            beaver_counter: 0,
-            rand_counter: 0,
-        };
-        evaluator.preprocess_triples(NUM_BEAVER_TRIPLES).await;
-        evaluator.preprocess_rand_sharings(NUM_RAND_SHARINGS).await;
-        evaluator
+impl error::Error for EvaluatorError {}
+
+impl From<KzgError> for EvaluatorError {
+    fn from(err: KzgError) -> Self {
+        EvaluatorError::Kzg(err)
+    }
+}
+
+impl From<ReconstructError> for EvaluatorError {
+    fn from(err: ReconstructError) -> Self {
+        EvaluatorError::Reconstruct(err)
     }
+}
 
-    /// returns a unique wire label in the circuit
-    fn compute_fresh_wire_label(&mut self) -> String {
-        self.gate_counter += 1;
-        bs58::encode(&self.gate_counter.to_be_bytes()).into_string()
+impl From<ShamirError> for EvaluatorError {
+    fn from(err: ShamirError) -> Self {
+        EvaluatorError::Sharing(err)
     }
+}
 
-    /// returns the (secret-shared) wire value associated with the given handle
-    pub fn get_wire(&self, handle: &String) -> F {
-        *self.wire_shares.get(handle).unwrap()
+impl From<NetworkError> for EvaluatorError {
+    fn from(err: NetworkError) -> Self {
+        match err {
+            NetworkError::Aborted { by, reason } => EvaluatorError::Aborted { by, reason },
+            err => EvaluatorError::Network(err),
+        }
     }
+}
 
-    /// asks the pre-processor to generate an additive sharing of a random value
-    /// returns a string handle, which can be used to access the share in future
-    pub fn ran(&mut self) -> String {
-        let handle = self.compute_fresh_wire_label();
-        self.wire_shares.insert(
-            handle.clone(),
-            self.rand_sharings[self.rand_counter as usize],
-        );
+/// how many values a batched opening puts in one message
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BatchConfig {
+    pub scalar_chunk: usize,
+    pub g1_chunk: usize,
+    pub g2_chunk: usize,
+    /// Gt elements are much larger than the others
+    pub gt_chunk: usize,
+    /// if set, a message carries no more values than fit in this many bytes,
+    /// serialized, and at least one
+    pub max_message_bytes: Option<usize>,
+}
 
-        self.rand_counter += 1;
+impl BatchConfig {
+    /// the number of values of kind per message
+    pub fn chunk_len(&self, kind: PayloadKind) -> usize {
+        self.chunk_len_on::<Curve>(kind)
+    }
 
-        handle
+    /// chunk_len for values on the curve E, whose sizes bound max_message_bytes
+    pub fn chunk_len_on<E: CurveConfig>(&self, kind: PayloadKind) -> usize {
+        let chunk = match kind {
+            PayloadKind::Scalar => self.scalar_chunk,
+            PayloadKind::G1 => self.g1_chunk,
+            PayloadKind::G2 => self.g2_chunk,
+            PayloadKind::Gt => self.gt_chunk,
+        };
+        match self.max_message_bytes {
+            Some(max) => chunk.min(max / payload_element_size_of::<E>(kind)).max(1),
+            None => chunk,
+        }
     }
 
-    pub async fn batch_ran_64(&mut self, len: usize) -> Vec<String> {
-        let mut h_c = Vec::new();
-        let h_as = (0..len).map(|_| self.ran()).collect::<Vec<String>>();
+    /// whether every chunk size is positive
+    pub fn is_valid(&self) -> bool {
+        self.scalar_chunk > 0 && self.g1_chunk > 0 && self.g2_chunk > 0 && self.gt_chunk > 0
+    }
+}
 
-        let h_a_exp_64s = self.batch_exp(&h_as).await;
-        let a_exp_64s = self.batch_output_wire(&h_a_exp_64s).await;
+impl Default for BatchConfig {
+    fn default() -> Self {
+        BatchConfig {
+            scalar_chunk: 256,
+            g1_chunk: 256,
+            g2_chunk: 128,
+            gt_chunk: 64,
+            max_message_bytes: None,
+        }
+    }
+}
 
-        for i in 0..len {
-            if a_exp_64s[i] == F::from(0) {
-                panic!("Highly improbable event occurred. Abort!");
-            }
+/// how parties reveal their shares when a value is opened
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OpenMode {
+    /// everyone broadcasts its share, and the shares are added up as they arrive
+    #[default]
+    Plain,
+    /// everyone first broadcasts a salted hash of its share, and its share only once
+    /// it has all the hashes, so that nobody can choose its share after seeing the
+    /// others'. Costs an extra round per opening; batch_output_wire_committed opens
+    /// a single batch this way under either mode.
+    Committed,
+}
 
-            let mut l = a_exp_64s[i];
-            for _ in 0..LOG_PERM_SIZE {
-                l = utils::compute_root(&l);
-            }
+/// how many shares an opening waits for in threshold mode; additive sharings
+/// always need every share
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum QuorumPolicy {
+    /// the t shares of the first other parties, which with ours make t + 1; the
+    /// others are checked against the sharing as they come, see misbehavior_flags
+    #[default]
+    Threshold,
+    /// the shares of every other party, all checked against the sharing
+    All,
+}
 
-            let handle = self.compute_fresh_wire_label();
-            let share_c = self.get_wire(&h_as[i]) / l;
-            self.wire_shares.insert(handle.clone(), share_c);
-            h_c.push(handle);
+/// where the random sharings behind ran, and the triples, come from
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RandSource {
+    /// random sharings derived from the key every pair of parties agrees on, see
+    /// prss, or drawn locally by each party with fewer than three parties, or dealt
+    /// jointly in threshold mode; triples made jointly by the parties unless a
+    /// dealer deals them
+    #[default]
+    Prss,
+    /// sharings and triples of a fixed seed every party knows, for tests and
+    /// benchmarks only
+    SharedSeed,
+}
+
+impl OpenMode {
+    /// how checkpoint stores the mode
+    pub(crate) fn tag(self) -> u8 {
+        match self {
+            OpenMode::Plain => 0,
+            OpenMode::Committed => 1,
+        }
+    }
+
+    pub(crate) fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(OpenMode::Plain),
+            1 => Some(OpenMode::Committed),
+            _ => None,
         }
+    }
+}
 
-        h_c
+impl QuorumPolicy {
+    /// how checkpoint stores the policy
+    pub(crate) fn tag(self) -> u8 {
+        match self {
+            QuorumPolicy::Threshold => 0,
+            QuorumPolicy::All => 1,
+        }
     }
 
-    /// outputs the wire label denoting the [x] + [y]
-    pub fn add(&mut self, handle_x: &String, handle_y: &String) -> String {
-        let handle = self.compute_fresh_wire_label();
+    pub(crate) fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(QuorumPolicy::Threshold),
+            1 => Some(QuorumPolicy::All),
+            _ => None,
+        }
+    }
+}
 
-        let share_x = self.get_wire(handle_x);
-        let share_y = self.get_wire(handle_y);
+impl RandSource {
+    /// how checkpoint stores the source
+    pub(crate) fn tag(self) -> u8 {
+        match self {
+            RandSource::Prss => 0,
+            RandSource::SharedSeed => 1,
+        }
+    }
 
-        self.wire_shares.insert(handle.clone(), share_x + share_y);
-        handle
+    pub(crate) fn from_tag(tag: u8) -> Option<Self> {
+        match tag {
+            0 => Some(RandSource::Prss),
+            1 => Some(RandSource::SharedSeed),
+            _ => None,
+        }
     }
+}
 
-    /// outputs the wire label denoting the [x] - [y]
-    pub fn sub(&mut self, handle_x: &String, handle_y: &String) -> String {
-        let handle = self.compute_fresh_wire_label();
+/// how far the preprocessing of the current committee got
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct PreprocessingStatus {
+    pub triples: ReservoirStatus,
+    pub rand_sharings: ReservoirStatus,
+}
 
-        let share_x = self.get_wire(handle_x);
-        let share_y = self.get_wire(handle_y);
+/// why EvaluatorBuilder::build refused to build an evaluator
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BuildError {
+    /// degree-threshold sharings need more than threshold parties
+    ThresholdTooLarge { threshold: u64, parties: usize },
+    /// the transport carries another session than the one asked for
+    SessionMismatch { expected: String, found: String },
+    /// committed openings need a session id, or commitments another game made
+    /// under the same handles could be passed off as ours
+    CommittedWithoutSession,
+    /// some kind of value has a chunk size of zero
+    EmptyChunk,
+    /// the dealer of the triples is not in the committee
+    UnknownDealer(u64),
+    /// a dealer deals additive triples, which threshold sharings cannot use
+    DealerWithThreshold,
+    /// the generator given to EvaluatorBuilder::rng could not seed ours
+    Rng(String),
+    /// the preprocessing could not be made
+    Preprocessing(EvaluatorError),
+}
 
-        self.wire_shares.insert(handle.clone(), share_x - share_y);
-        handle
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BuildError::ThresholdTooLarge { threshold, parties } => write!(
+                f,
+                "threshold {} needs more than {} parties",
+                threshold, parties
+            ),
+            BuildError::SessionMismatch { expected, found } => write!(
+                f,
+                "expected session {}, the transport carries {}",
+                expected, found
+            ),
+            BuildError::CommittedWithoutSession => {
+                write!(f, "committed openings need a session id")
+            }
+            BuildError::EmptyChunk => write!(f, "batch chunk sizes must be positive"),
+            BuildError::UnknownDealer(node_id) => {
+                write!(f, "the dealer {} is not in the committee", node_id)
+            }
+            BuildError::DealerWithThreshold => {
+                write!(f, "a dealer cannot deal threshold triples")
+            }
+            BuildError::Rng(reason) => write!(f, "cannot seed from rng: {}", reason),
+            BuildError::Preprocessing(err) => write!(f, "preprocessing failed: {}", err),
+        }
     }
+}
 
-    pub async fn batch_inv(&mut self, input_handles: &[String]) -> Vec<String> {
-        // goal: compute inv([s])
-        // step 1: invoke ran_p to obtain [r]
-        // step 2: invoke mult to get [q] = [r . s]
-        // step 3: reconstruct q = r . s
-        // step 4: return [r] / q
+impl error::Error for BuildError {}
 
-        let rand_handles: Vec<String> = (0..input_handles.len()).map(|_| self.ran()).collect();
+impl From<EvaluatorError> for BuildError {
+    fn from(err: EvaluatorError) -> Self {
+        BuildError::Preprocessing(err)
+    }
+}
 
-        let masked_handles = self.batch_mult(input_handles, &rand_handles).await;
+/// every setting of an evaluator, checked together by build; what is not set is
+/// the same as for Evaluator::new
+#[derive(Debug, Default)]
+pub struct EvaluatorBuilder {
+    params: ProtocolParams,
+    preprocessing: Option<ResourceEstimate>,
+    batch_config: BatchConfig,
+    open_mode: OpenMode,
+    threshold: Option<u64>,
+    rand_source: RandSource,
+    dealer: Option<u64>,
+    verify_triples: bool,
+    /// the generator seeded from the one given to rng, or why it could not be
+    rng: Option<Result<StdRng, String>>,
+    session: Option<String>,
+}
 
-        let masked_values = self.batch_output_wire(&masked_handles).await;
+impl EvaluatorBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-        let mut output: Vec<String> = vec![];
-        for i in 0..input_handles.len() {
-            let q_inv = F::from(1) / masked_values[i];
-            let wire_out = q_inv * self.get_wire(&rand_handles[i]);
+    /// deck and permutation sizes
+    pub fn params(mut self, params: ProtocolParams) -> Self {
+        self.params = params;
+        self
+    }
 
-            let handle_out = self.compute_fresh_wire_label();
-            self.wire_shares.insert(handle_out.clone(), wire_out);
+    /// triples and random sharings to make before build returns; a full shuffle
+    /// under params by default
+    pub fn preprocessing(mut self, resources: ResourceEstimate) -> Self {
+        self.preprocessing = Some(resources);
+        self
+    }
 
-            output.push(handle_out);
-        }
+    pub fn batch_config(mut self, config: BatchConfig) -> Self {
+        self.batch_config = config;
+        self
+    }
 
-        output
+    pub fn open_mode(mut self, mode: OpenMode) -> Self {
+        self.open_mode = mode;
+        self
     }
 
-    // Adds [x] to y in the clear and outputs handle to the resulting share
-    pub fn clear_add(&mut self, handle_x: &String, y: F) -> String {
-        let x = self.get_wire(handle_x);
-        let clear_add_share: F = match self.messaging.get_my_id() {
-            1 => x + y,
-            _ => x,
-        };
+    /// degree-t Shamir sharings instead of additive ones, see new_with_threshold
+    pub fn threshold(mut self, t: u64) -> Self {
+        self.threshold = Some(t);
+        self
+    }
 
-        let handle_out = self.compute_fresh_wire_label();
-        self.wire_shares.insert(handle_out.clone(), clear_add_share);
+    /// where the random sharings come from, see new_with_rand_source
+    pub fn rand_source(mut self, source: RandSource) -> Self {
+        self.rand_source = source;
+        self
+    }
 
-        handle_out
+    /// the party with node id dealer draws every triple and sends the others their
+    /// shares, instead of the parties making them jointly; e.g. for two parties,
+    /// which cannot. The dealer learns every triple, and through them every value
+    /// they mask, so it must be trusted not to share them with anyone
+    pub fn dealer(mut self, dealer: u64) -> Self {
+        self.dealer = Some(dealer);
+        self
     }
 
-    // Scales [x] by scalar and outputs handle to the resulting share
-    pub fn scale(&mut self, handle_in: &String, scalar: F) -> String {
-        let handle_out = self.compute_fresh_wire_label();
+    /// the triples are checked by sacrificing as many again, see
+    /// preprocessing::verify_triples; build fails if some do not multiply. Catches
+    /// dealt triples that do not multiply, not a dealer that leaks them
+    pub fn verify_triples(mut self, verify: bool) -> Self {
+        self.verify_triples = verify;
+        self
+    }
 
-        let x = self.get_wire(handle_in);
+    /// local randomness is drawn from a generator seeded from rng rather than from
+    /// the OS, see new_with_rng; build fails if rng cannot give a seed
+    pub fn rng<R: RngCore + CryptoRng>(mut self, mut rng: R) -> Self {
+        self.rng = Some(StdRng::from_rng(&mut rng).map_err(|err| err.to_string()));
+        self
+    }
 
-        self.wire_shares.insert(handle_out.clone(), x * scalar);
+    /// the session the transport must carry
+    pub fn session(mut self, session_id: &str) -> Self {
+        self.session = Some(session_id.to_string());
+        self
+    }
 
-        handle_out
+    /// checks the settings against each other and against messaging, then
+    /// preprocesses
+    pub async fn build<T: Transport>(self, messaging: T) -> Result<Evaluator<T>, BuildError> {
+        self.build_on::<Curve, T>(messaging).await
     }
 
-    /// given: triple ([a], [b], [c]) and inputs ([x], [y])
-    /// reveals: x + a, y + b
-    /// computes [x.y] = (x+a).(y+b) - (x+a).[b] - (y+b).[a] + [c]
-    /// outputs the wire label denoting [x.y]
-    pub async fn mult(&mut self, handle_x: &String, handle_y: &String) -> String {
-        let (h_a, h_b, h_c) = self.beaver().await;
+    /// same as build, but the evaluator computes over the scalars of E rather than
+    /// of Curve, e.g. on another curve whose feature is on
+    pub async fn build_on<E: CurveConfig, T: Transport>(
+        self,
+        messaging: T,
+    ) -> Result<Evaluator<T, E>, BuildError> {
+        self.validate(&messaging)?;
+        let resources = self
+            .preprocessing
+            .unwrap_or_else(|| shuffler::resources(&self.params));
+        let rng = match self.rng {
+            Some(rng) => rng.map_err(BuildError::Rng)?,
+            None => StdRng::from_entropy(),
+        };
 
-        let share_a = self.get_wire(&h_a);
-        let share_b = self.get_wire(&h_b);
-        let share_c = self.get_wire(&h_c);
+        let mut evaluator =
+            Evaluator::unprocessed(messaging, rng, self.threshold, self.params, resources);
+        evaluator.batch_config = self.batch_config;
+        evaluator.open_mode = self.open_mode;
+        evaluator.rand_source = self.rand_source;
+        evaluator.dealer = self.dealer;
+        evaluator.verify_triples = self.verify_triples;
+        evaluator.try_preprocess().await?;
+        Ok(evaluator)
+    }
 
-        // our strategy would be to re-use other components
-        //construct adder gates for the padded wires
-        let handle_x_plus_a = self.add(handle_x, &h_a);
-        let handle_y_plus_b = self.add(handle_y, &h_b);
+    fn validate<T: Transport>(&self, messaging: &T) -> Result<(), BuildError> {
+        if let Some(threshold) = self.threshold {
+            let parties = messaging.addr_book().len();
+            if threshold as usize >= parties {
+                return Err(BuildError::ThresholdTooLarge { threshold, parties });
+            }
+        }
+        if let Some(expected) = &self.session {
+            if expected != messaging.session_id() {
+                return Err(BuildError::SessionMismatch {
+                    expected: expected.clone(),
+                    found: messaging.session_id().to_string(),
+                });
+            }
+        }
+        if self.open_mode == OpenMode::Committed && messaging.session_id().is_empty() {
+            return Err(BuildError::CommittedWithoutSession);
+        }
+        if !self.batch_config.is_valid() {
+            return Err(BuildError::EmptyChunk);
+        }
+        if let Some(dealer) = self.dealer {
+            if messaging.addr_book().peer_of(dealer).is_none() {
+                return Err(BuildError::UnknownDealer(dealer));
+            }
+            if self.threshold.is_some() {
+                return Err(BuildError::DealerWithThreshold);
+            }
+        }
+        Ok(())
+    }
+}
 
-        //reconstruct the padded wires in the clear
-        let x_plus_a = self.output_wire(&handle_x_plus_a).await;
-        let y_plus_b = self.output_wire(&handle_y_plus_b).await;
+#[derive(Default)]
+struct Scope {
+    wires: Vec<Wire>,
+    kept: HashSet<Wire>,
+}
 
-        let handle = self.compute_fresh_wire_label();
+pub struct Evaluator<T: Transport = network::MessagingSystem, E: CurveConfig = Curve> {
+    /// local peer id
+    messaging: T,
+    /// pre-processed beaver triples
+    beaver_triples: Secret<Vec<Triple<E::ScalarField>>>, // (a, b, c) share
+    /// pre-processed random sharings
+    rand_sharings: Secret<Vec<E::ScalarField>>,
+    /// stores the share associated with each wire
+    wire_shares: Secret<HashMap<Wire, E::ScalarField>>,
+    /// public value of every wire known to hold a constant
+    constants: HashMap<Wire, E::ScalarField>,
+    /// keep track of gates
+    gate_counter: u64,
+    /// wires created within each open scope, innermost last
+    scopes: Vec<Scope>,
+    /// derived from the session id and prefixed to every wire label,
+    /// so that handles of different sessions never collide
+    session_tag: [u8; 8],
+    /// keep track of the number of beaver triples consumed
+    beaver_counter: u64,
+    /// keep track of the number of rand sharings consumed
+    rand_counter: u64,
+    /// node ids of peers that sent us malformed values
+    flagged_senders: HashSet<u64>,
+    /// local randomness used during preprocessing
+    rng: StdRng,
+    /// cost counters, shared with the transport
+    metrics: Arc<Metrics>,
+    /// None for n-of-n additive sharings; Some(t) for degree-t Shamir sharings,
+    /// which any t + 1 parties can reconstruct
+    threshold: Option<u64>,
+    /// hash for the Fiat-Shamir challenges of the proofs we produce
+    fs_hasher: FsHasher,
+    /// deck and permutation sizes
+    params: ProtocolParams,
+    /// message sizes of the batched openings
+    batch_config: BatchConfig,
+    /// how every opening reveals our shares
+    open_mode: OpenMode,
+    /// how many shares an opening waits for in threshold mode
+    quorum_policy: QuorumPolicy,
+    /// whether openings check, with an echo round, that every party received the
+    /// same shares
+    consistent_openings: bool,
+    /// the t + 1 shares every threshold opening was reconstructed from, by handle,
+    /// until the shares of the other parties have been checked against them
+    pending_checks: HashMap<String, (PayloadKind, Vec<(u64, String)>)>,
+    pending_order: VecDeque<String>,
+    /// late shares of openings we have not reconstructed yet
+    late_shares: VecDeque<(String, u64, String)>,
+    /// evidence of every share that did not fit its sharing
+    misbehavior_flags: Vec<Evidence>,
+    /// whether preprocessing checks the triples by sacrificing as many again
+    verify_triples: bool,
+    /// node id of the party dealing the triples, if any; None if they are made
+    /// jointly or come from the shared seed
+    dealer: Option<u64>,
+    /// where the random sharings come from
+    rand_source: RandSource,
+    /// the keys we share with each party of the current committee, once agreed
+    /// on; whatever we send a single party is masked under them
+    pairwise: Option<PairwiseKeys>,
+    /// the pairwise seeds of the current committee, once derived
+    prss: Option<Prss>,
+    /// the next PRSS value to draw
+    prss_counter: u64,
+    /// triples and random sharings made in the background, moved into
+    /// beaver_triples and rand_sharings as they are needed
+    triple_reservoir: Option<Reservoir<Triple<E::ScalarField>>>,
+    rand_reservoir: Option<Reservoir<E::ScalarField>>,
+    /// reconstructions we sent our shares for, and those that completed; they
+    /// differ while one is in flight, or once one failed halfway. The number
+    /// started is our round, see round
+    reconstructions_started: u64,
+    reconstructions_finished: u64,
+    /// where end_phase writes a checkpoint, if anywhere
+    write_ahead: Option<PathBuf>,
+    /// every opening and proof so far, once enable_audit was called
+    audit: Option<AuditLog>,
+    /// every party's share of every opening so far, base58 encoded as received,
+    /// once enable_accountability was called
+    contributions: Option<HashMap<String, HashMap<u64, String>>>,
+    /// whether the shuffler's proofs are broadcast for verifiers to check
+    publish_proofs: bool,
+    /// proofs published so far, which numbers their handles
+    proof_counter: u64,
+    /// preprocessing generated for each committee
+    resources: ResourceEstimate,
+    /// powers of the generator of Gt, built on first use
+    gt_generator_table: Option<Arc<GtTable<E>>>,
+}
 
-        //only one party should add the constant term
-        let share_x_mul_y: F = match self.messaging.get_my_id() {
-            1 => x_plus_a * y_plus_b - x_plus_a * share_b - y_plus_b * share_a + share_c,
-            _ => F::from(0) - x_plus_a * share_b - y_plus_b * share_a + share_c,
-        };
-        self.wire_shares.insert(handle.clone(), share_x_mul_y);
-        handle
+impl<T: Transport> Evaluator<T> {
+    /// the session id is taken from the transport; fails if the preprocessing
+    /// cannot be made
+    pub async fn new(messaging: T) -> Result<Self, BuildError> {
+        EvaluatorBuilder::new().build(messaging).await
     }
 
-    pub async fn batch_mult(&mut self, x_handles: &[String], y_handles: &[String]) -> Vec<String> {
-        assert_eq!(x_handles.len(), y_handles.len());
-        let len: usize = x_handles.len();
+    /// settings beyond those of the constructors below, see EvaluatorBuilder
+    pub fn builder() -> EvaluatorBuilder {
+        EvaluatorBuilder::new()
+    }
 
-        // store all beaver triples for use later in this function
-        let mut bookkeeping_a: Vec<F> = Vec::new();
-        let mut bookkeeping_b: Vec<F> = Vec::new();
-        let mut bookkeeping_c: Vec<F> = Vec::new();
-        // store all handles for [x+a] and [y+b]
-        let mut x_plus_a_handles: Vec<String> = Vec::new();
-        let mut y_plus_b_handles: Vec<String> = Vec::new();
+    /// same as new, but preprocesses for the deck and permutation sizes of params
+    pub async fn new_with_params(messaging: T, params: ProtocolParams) -> Result<Self, BuildError> {
+        EvaluatorBuilder::new()
+            .params(params)
+            .build(messaging)
+            .await
+    }
 
-        let beavers = self.batch_beaver(len);
+    /// same as new_with_params, but preprocesses what resources estimates for the
+    /// circuit to run, plus margin triples and random sharings
+    pub async fn new_for(
+        messaging: T,
+        params: ProtocolParams,
+        resources: ResourceEstimate,
+        margin: usize,
+    ) -> Result<Self, BuildError> {
+        let margin = ResourceEstimate {
+            triples: margin,
+            rand_sharings: margin,
+        };
+        EvaluatorBuilder::new()
+            .params(params)
+            .preprocessing(resources + margin)
+            .build(messaging)
+            .await
+    }
 
-        for i in 0..len {
-            let (h_a, h_b, h_c) = beavers[i].clone();
+    /// same as new, but every local random choice, such as the PRSS seeds, the shares
+    /// we deal and the salts of committed openings, is drawn from a generator seeded
+    /// from rng rather than from the OS; seeding rng makes a run reproducible. Fails
+    /// with BuildError::Rng if rng cannot give a seed
+    pub async fn new_with_rng<R: RngCore + CryptoRng>(
+        messaging: T,
+        rng: R,
+    ) -> Result<Self, BuildError> {
+        EvaluatorBuilder::new().rng(rng).build(messaging).await
+    }
 
-            bookkeeping_a.push(self.get_wire(&h_a));
-            bookkeeping_b.push(self.get_wire(&h_b));
-            bookkeeping_c.push(self.get_wire(&h_c));
+    /// same as new, but with reproducible local randomness (for tests and simulations)
+    pub async fn new_with_seed(messaging: T, seed: [u8; 32]) -> Result<Self, BuildError> {
+        EvaluatorBuilder::new()
+            .rng(StdRng::from_seed(seed))
+            .build(messaging)
+            .await
+    }
 
-            let handle_x_plus_a = self.add(&x_handles[i], &h_a);
-            let handle_y_plus_b = self.add(&y_handles[i], &h_b);
+    /// new_with_seed and new_with_params together
+    pub async fn new_with_seed_and_params(
+        messaging: T,
+        seed: [u8; 32],
+        params: ProtocolParams,
+    ) -> Result<Self, BuildError> {
+        EvaluatorBuilder::new()
+            .params(params)
+            .rng(StdRng::from_seed(seed))
+            .build(messaging)
+            .await
+    }
 
-            x_plus_a_handles.push(handle_x_plus_a);
-            y_plus_b_handles.push(handle_y_plus_b);
-        }
+    /// same as new, but takes the random sharings from source
+    pub async fn new_with_rand_source(
+        messaging: T,
+        source: RandSource,
+    ) -> Result<Self, BuildError> {
+        EvaluatorBuilder::new()
+            .rand_source(source)
+            .build(messaging)
+            .await
+    }
 
-        let mut batch_handles = vec![];
-        batch_handles.extend_from_slice(&x_plus_a_handles);
-        batch_handles.extend_from_slice(&y_plus_b_handles);
+    /// same as new, but the batched openings split their messages as config says
+    pub async fn new_with_batch_config(
+        messaging: T,
+        config: BatchConfig,
+    ) -> Result<Self, BuildError> {
+        EvaluatorBuilder::new()
+            .batch_config(config)
+            .build(messaging)
+            .await
+    }
 
-        let x_plus_a_and_y_plus_b = self.batch_output_wire(&batch_handles).await;
+    /// same as new, but every opening, including those inside the gates, uses mode
+    pub async fn new_with_open_mode(messaging: T, mode: OpenMode) -> Result<Self, BuildError> {
+        EvaluatorBuilder::new()
+            .open_mode(mode)
+            .build(messaging)
+            .await
+    }
 
-        let mut output: Vec<String> = vec![];
+    /// same as new_with_params, but the triples are checked by sacrificing as many
+    /// again, see preprocessing::verify_triples; fails if some do not multiply
+    pub async fn new_with_verified_triples(
+        messaging: T,
+        params: ProtocolParams,
+    ) -> Result<Self, BuildError> {
+        EvaluatorBuilder::new()
+            .params(params)
+            .verify_triples(true)
+            .build(messaging)
+            .await
+    }
 
-        for i in 0..len {
-            let x_plus_a_reconstructed = x_plus_a_and_y_plus_b[i];
-            let y_plus_b_reconstructed = x_plus_a_and_y_plus_b[x_plus_a_handles.len() + i];
+    /// same as new, but the party with node id dealer_id deals the triples, and
+    /// everyone else receives theirs from it; with verify, the triples are then
+    /// checked as in new_with_verified_triples. The check catches triples that do
+    /// not multiply, not a dealer that leaks them: the dealer must be trusted, see
+    /// EvaluatorBuilder::dealer
+    pub async fn new_with_dealer(
+        messaging: T,
+        dealer_id: u64,
+        verify: bool,
+    ) -> Result<Self, BuildError> {
+        EvaluatorBuilder::new()
+            .dealer(dealer_id)
+            .verify_triples(verify)
+            .build(messaging)
+            .await
+    }
 
-            //only one party should add the constant term
-            let share_x_mul_y: F = match self.messaging.get_my_id() {
-                1 => {
-                    x_plus_a_reconstructed * y_plus_b_reconstructed
-                        - x_plus_a_reconstructed * bookkeeping_b[i]
-                        - y_plus_b_reconstructed * bookkeeping_a[i]
-                        + bookkeeping_c[i]
-                }
-                _ => {
-                    F::from(0)
-                        - x_plus_a_reconstructed * bookkeeping_b[i]
-                        - y_plus_b_reconstructed * bookkeeping_a[i]
-                        + bookkeeping_c[i]
-                }
-            };
+    /// same as new, but values are shared with degree-t Shamir sharings, so that
+    /// outputs only need t + 1 parties and up to n - t - 1 of them may go offline;
+    /// fails unless t is below the number of parties
+    pub async fn new_with_threshold(messaging: T, t: u64) -> Result<Self, BuildError> {
+        EvaluatorBuilder::new().threshold(t).build(messaging).await
+    }
+}
 
-            let h = self.compute_fresh_wire_label();
-            self.wire_shares.insert(h.clone(), share_x_mul_y);
+impl<T: Transport, E: CurveConfig> Evaluator<T, E> {
+    /// same as new, but loads the triples and random sharings that an evaluator of
+    /// this party, committee and session exported to path, instead of generating
+    /// them. The file is marked consumed before its material is used, and refused
+    /// if it already is.
+    pub async fn new_from_preprocessing(
+        messaging: T,
+        path: &Path,
+    ) -> Result<Self, PreprocessingError> {
+        Self::load_preprocessing(messaging, path, None).await
+    }
 
-            output.push(h.clone());
+    /// same as new_from_preprocessing, but refuses a file made for other deck and
+    /// permutation sizes than params before marking it consumed
+    pub async fn new_from_preprocessing_for(
+        messaging: T,
+        path: &Path,
+        params: ProtocolParams,
+    ) -> Result<Self, PreprocessingError> {
+        Self::load_preprocessing(messaging, path, Some(params)).await
+    }
+
+    async fn load_preprocessing(
+        messaging: T,
+        path: &Path,
+        expected: Option<ProtocolParams>,
+    ) -> Result<Self, PreprocessingError> {
+        let mut file = PreprocessingFile::<E>::read(path)?;
+        file.check_binding(
+            messaging.get_my_id(),
+            messaging.addr_book().len(),
+            messaging.session_id(),
+        )?;
+        let params = ProtocolParams::new(file.perm_size as usize, file.deck_size as usize)?;
+        if let Some(expected) = expected.filter(|expected| *expected != params) {
+            return Err(PreprocessingError::WrongParams {
+                expected,
+                found: params,
+            });
         }
+        let (beaver_triples, rand_sharings) = file.consume(path)?;
 
-        output
+        let mut evaluator = Self::unprocessed(
+            messaging,
+            StdRng::from_entropy(),
+            file.threshold,
+            params,
+            shuffler::resources(&params),
+        );
+        evaluator.beaver_triples = Secret::new(beaver_triples);
+        evaluator.rand_sharings = Secret::new(rand_sharings);
+        evaluator.handshake().await?;
+        Ok(evaluator)
     }
 
-    pub fn fixed_wire_handle(&mut self, value: F) -> String {
-        let handle = self.compute_fresh_wire_label();
+    /// resumes from a snapshot that checkpoint took before this party went down; the
+    /// transport must be of the same party, committee and session, and no
+    /// reconstruction may have been in flight when the snapshot was taken
+    pub fn restore(
+        messaging: T,
+        mut snapshot: EvaluatorSnapshot<E>,
+    ) -> Result<Self, CheckpointError> {
+        snapshot.check_binding(
+            messaging.get_my_id(),
+            &committee_of(messaging.addr_book()),
+            messaging.session_id(),
+        )?;
+        let params = ProtocolParams::new(snapshot.perm_size as usize, snapshot.deck_size as usize)?;
+
+        let mut evaluator = Self::unprocessed(
+            messaging,
+            StdRng::from_entropy(),
+            snapshot.threshold,
+            params,
+            shuffler::resources(&params),
+        );
+        evaluator.wire_shares = Secret::new(
+            snapshot
+                .wire_shares
+                .iter()
+                .map(|(label, share)| {
+                    let label = String::from_utf8(label.clone())
+                        .map_err(|_| SerializationError::InvalidData)?;
+                    Ok((Wire::from(label), *share))
+                })
+                .collect::<Result<_, SerializationError>>()?,
+        );
+        evaluator.gate_counter = snapshot.gate_counter;
+        // the next fresh label would collide with a wire made after gate_counter
+        let latest = evaluator
+            .wire_shares
+            .keys()
+            .filter_map(|label| evaluator.label_counter(label))
+            .max();
+        if let Some(wire_counter) = latest.filter(|c| *c > snapshot.gate_counter) {
+            return Err(CheckpointError::GateCounterBehind {
+                gate_counter: snapshot.gate_counter,
+                wire_counter,
+            });
+        }
+        evaluator.reconstructions_started = snapshot.reconstructions_started;
+        evaluator
+            .messaging
+            .set_round(snapshot.reconstructions_started);
+        evaluator.reconstructions_finished = snapshot.reconstructions_finished;
+        evaluator.beaver_triples = Secret::new(std::mem::take(&mut snapshot.beaver_triples));
+        evaluator.rand_sharings = Secret::new(std::mem::take(&mut snapshot.rand_sharings));
+        if !snapshot.pairwise_keys.is_empty() {
+            let keys = key_map(std::mem::take(&mut snapshot.pairwise_keys))?;
+            evaluator.pairwise = Some(PairwiseKeys::new(snapshot.node_id, keys));
+        }
+        if !snapshot.prss_seeds.is_empty() {
+            let seeds = key_map(std::mem::take(&mut snapshot.prss_seeds))?;
+            evaluator.prss = Some(Prss::new(snapshot.node_id, seeds));
+            evaluator.prss_counter = snapshot.prss_counter;
+        }
 
-        let share: F = match self.messaging.get_my_id() {
-            1 => value,
-            _ => F::from(0),
+        evaluator.open_mode =
+            OpenMode::from_tag(snapshot.open_mode).ok_or(SerializationError::InvalidData)?;
+        evaluator.quorum_policy = QuorumPolicy::from_tag(snapshot.quorum_policy)
+            .ok_or(SerializationError::InvalidData)?;
+        evaluator.rand_source =
+            RandSource::from_tag(snapshot.rand_source).ok_or(SerializationError::InvalidData)?;
+        let batch_config = match snapshot.batch_chunks[..] {
+            [scalar_chunk, g1_chunk, g2_chunk, gt_chunk] => BatchConfig {
+                scalar_chunk: scalar_chunk as usize,
+                g1_chunk: g1_chunk as usize,
+                g2_chunk: g2_chunk as usize,
+                gt_chunk: gt_chunk as usize,
+                max_message_bytes: snapshot.max_message_bytes.map(|bytes| bytes as usize),
+            },
+            _ => return Err(SerializationError::InvalidData.into()),
         };
+        if !batch_config.is_valid() {
+            return Err(SerializationError::InvalidData.into());
+        }
+        evaluator.batch_config = batch_config;
+        evaluator.dealer = snapshot.dealer;
+        evaluator.verify_triples = snapshot.verify_triples;
+        Ok(evaluator)
+    }
 
-        self.wire_shares.insert(handle.clone(), share);
-        handle
+    /// an evaluator that has not preprocessed anything yet
+    fn unprocessed(
+        messaging: T,
+        rng: StdRng,
+        threshold: Option<u64>,
+        params: ProtocolParams,
+        resources: ResourceEstimate,
+    ) -> Self {
+        let mut session_tag = [0u8; 8];
+        session_tag.copy_from_slice(&Sha256::digest(messaging.session_id().as_bytes())[..8]);
+
+        let metrics = messaging.metrics();
+        Evaluator {
+            wire_shares: Secret::default(),
+            constants: HashMap::new(),
+            beaver_triples: Secret::default(),
+            rand_sharings: Secret::default(),
+            messaging,
+            gate_counter: 0,
+            scopes: Vec::new(),
+            session_tag,
+            beaver_counter: 0,
+            rand_counter: 0,
+            flagged_senders: HashSet::new(),
+            rng,
+            metrics,
+            threshold,
+            fs_hasher: FsHasher::default(),
+            params,
+            resources,
+            batch_config: BatchConfig::default(),
+            open_mode: OpenMode::default(),
+            quorum_policy: QuorumPolicy::default(),
+            consistent_openings: false,
+            pending_checks: HashMap::new(),
+            pending_order: VecDeque::new(),
+            late_shares: VecDeque::new(),
+            misbehavior_flags: Vec::new(),
+            verify_triples: false,
+            dealer: None,
+            rand_source: RandSource::default(),
+            pairwise: None,
+            prss: None,
+            prss_counter: 0,
+            triple_reservoir: None,
+            rand_reservoir: None,
+            reconstructions_started: 0,
+            reconstructions_finished: 0,
+            write_ahead: None,
+            audit: None,
+            contributions: None,
+            publish_proofs: false,
+            proof_counter: 0,
+            gt_generator_table: None,
+        }
     }
 
-    /// PolyEval takes as input a shared polynomial f(x) and a point x and returns share of f(x)
-    pub fn share_poly_eval(&mut self, f_poly_share: &DensePolynomial<F>, x: F) -> String {
-        let handle_out = self.compute_fresh_wire_label();
+    /// starts preprocessing what resources says; fails if the pairwise keys cannot be
+    /// agreed on
+    async fn try_preprocess(&mut self) -> Result<(), EvaluatorError> {
+        self.handshake().await?;
+        let resources = self.resources;
+        self.preprocess_triples(resources.triples).await?;
+        self.preprocess_rand_sharings(resources.rand_sharings).await
+    }
 
-        let mut sum = F::zero();
-        let mut x_pow = F::one();
-        for coeff in f_poly_share.coeffs.iter() {
-            sum += coeff * &x_pow;
-            x_pow *= x;
+    /// makes sure every party runs the same protocol, params and modes as we do
+    async fn handshake(&mut self) -> Result<(), NetworkError> {
+        let mut info = PeerInfo::new(&self.params);
+        if self.open_mode == OpenMode::Committed {
+            info = info.with_feature(FEATURE_COMMITTED_OPENS);
         }
+        if self.threshold.is_some() {
+            info = info.with_feature(FEATURE_THRESHOLD);
+        }
+        self.messaging.handshake(&info).await
+    }
 
-        self.wire_shares.insert(handle_out.clone(), sum);
-        handle_out
+    /// returns a unique wire label in the circuit
+    fn compute_fresh_wire_label(&mut self) -> Wire {
+        self.gate_counter += 1;
+        let mut label = self.session_tag.to_vec();
+        label.extend_from_slice(&self.gate_counter.to_be_bytes());
+        let wire = Wire::from(bs58::encode(label).into_string());
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.wires.push(wire.clone());
+        }
+        wire
     }
 
-    /// Should multiply two polynomials with shared coefficients to get a larger degree polynomial with shared coefficients
-    pub async fn share_poly_mult(
-        &mut self,
-        f_poly_share: DensePolynomial<F>,
-        g_poly_share: DensePolynomial<F>,
-    ) -> DensePolynomial<F> {
-        let alpha = utils::multiplicative_subgroup_of_size(2 * PERM_SIZE as u64);
-        let powers_of_alpha: Vec<F> = (0..2 * PERM_SIZE)
-            .map(|i| utils::compute_power(&alpha, i as u64))
-            .collect();
+    /// sets the share of a fresh wire. Fresh labels never repeat, and restore
+    /// refuses snapshots that would make them repeat, so a wire set twice is a bug
+    /// of ours rather than something peers or snapshots can cause
+    fn insert_share(&mut self, handle: &Wire, share: E::ScalarField) {
+        let previous = self.wire_shares.insert(handle.clone(), share);
+        debug_assert!(previous.is_none(), "wire {} was already set", handle);
+        if let Some(mut previous) = previous {
+            zeroize_f(&mut previous);
+        }
+    }
 
-        let mut f_evals = Vec::new();
-        let mut g_evals = Vec::new();
+    /// the gate counter compute_fresh_wire_label made label at, if it made it
+    fn label_counter(&self, label: &Wire) -> Option<u64> {
+        let bytes = bs58::decode(label.as_str()).into_vec().ok()?;
+        let counter = bytes.strip_prefix(&self.session_tag[..])?;
+        Some(u64::from_be_bytes(counter.try_into().ok()?))
+    }
 
-        for i in 0..2 * PERM_SIZE {
-            f_evals.push(self.share_poly_eval(&f_poly_share, powers_of_alpha[i]));
-            g_evals.push(self.share_poly_eval(&g_poly_share, powers_of_alpha[i]));
+    /// drops the share of a wire that is no longer needed
+    pub fn free_wire(&mut self, handle: &Wire) {
+        if let Some(mut share) = self.wire_shares.remove(handle) {
+            zeroize_f(&mut share);
         }
+        self.constants.remove(handle);
+    }
 
-        // Compute h_evals from f_evals and g_evals using Beaver mult
-        let h_evals = self
-            .batch_mult(&f_evals, &g_evals)
-            .await
-            .into_iter()
-            .map(|x| self.get_wire(&x))
-            .collect::<Vec<F>>();
+    pub fn free_wires(&mut self, handles: &[Wire]) {
+        for handle in handles {
+            self.free_wire(handle);
+        }
+    }
 
-        // Interpolate h_evals to get h_poly_share
+    /// runs f, then frees every wire created while it ran, except those passed to
+    /// keep. For async code, see begin_scope and end_scope.
+    pub fn with_scope<R>(&mut self, f: impl FnOnce(&mut Self) -> R) -> R {
+        self.begin_scope();
+        let result = f(self);
+        self.end_scope();
+        result
+    }
 
-        utils::interpolate_poly_over_mult_subgroup(&h_evals)
+    /// opens a scope; wires created from now on are freed by the matching
+    /// end_scope, unless kept. Scopes nest.
+    pub fn begin_scope(&mut self) {
+        self.scopes.push(Scope::default());
     }
 
-    pub async fn beaver(&mut self) -> (String, String, String) {
-        let handle_a = self.compute_fresh_wire_label();
-        let handle_b = self.compute_fresh_wire_label();
-        let handle_c = self.compute_fresh_wire_label();
+    /// frees the wires created in the innermost scope and not kept; kept wires
+    /// belong to the enclosing scope from now on
+    pub fn end_scope(&mut self) {
+        let scope = self.scopes.pop().expect("end_scope without begin_scope");
+        for wire in scope.wires {
+            if scope.kept.contains(&wire) {
+                if let Some(parent) = self.scopes.last_mut() {
+                    parent.wires.push(wire);
+                }
+            } else {
+                self.free_wire(&wire);
+            }
+        }
+    }
 
-        self.wire_shares.insert(
-            handle_a.clone(),
-            self.beaver_triples[self.beaver_counter as usize].0,
-        );
-        self.wire_shares.insert(
-            handle_b.clone(),
-            self.beaver_triples[self.beaver_counter as usize].1,
-        );
-        self.wire_shares.insert(
-            handle_c.clone(),
-            self.beaver_triples[self.beaver_counter as usize].2,
-        );
+    /// keeps handle alive past the end of the innermost scope
+    pub fn keep(&mut self, handle: &Wire) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.kept.insert(handle.clone());
+        }
+    }
 
-        // Update beaver counter
-        self.beaver_counter += 1;
+    /// the number of wires holding a share
+    pub fn wire_count(&self) -> usize {
+        self.wire_shares.len()
+    }
 
-        (handle_a, handle_b, handle_c)
+    /// approximate heap size of the wire shares, in bytes
+    pub fn memory_bytes(&self) -> usize {
+        // one control byte per bucket, and a reference count pair per label
+        let buckets =
+            self.wire_shares.capacity() * (std::mem::size_of::<(Wire, E::ScalarField)>() + 1);
+        let labels: usize = self
+            .wire_shares
+            .keys()
+            .map(|wire| wire.as_str().len() + 2 * std::mem::size_of::<usize>())
+            .sum();
+        buckets + labels
     }
 
-    pub fn batch_beaver(&mut self, num_beavers: usize) -> Vec<(String, String, String)> {
-        let mut output = Vec::new();
+    /// waits until everything we sent has reached the other parties;
+    /// call this once done, before dropping the evaluator
+    pub async fn flush(&mut self) -> Result<(), EvaluatorError> {
+        Ok(self.messaging.flush().await?)
+    }
 
-        for i in 0..num_beavers {
-            let handle_a = self.compute_fresh_wire_label();
-            let handle_b = self.compute_fresh_wire_label();
-            let handle_c = self.compute_fresh_wire_label();
+    /// gives up on the session and tells the other parties, whose pending
+    /// and later network operations then fail with EvaluatorError::Aborted
+    pub async fn abort(&mut self, reason: &str) {
+        self.messaging.broadcast_abort(reason).await;
+    }
 
-            self.wire_shares.insert(
-                handle_a.clone(),
-                self.beaver_triples[self.beaver_counter as usize + i].0,
-            );
-            self.wire_shares.insert(
-                handle_b.clone(),
-                self.beaver_triples[self.beaver_counter as usize + i].1,
-            );
-            self.wire_shares.insert(
-                handle_c.clone(),
-                self.beaver_triples[self.beaver_counter as usize + i].2,
-            );
+    /// switches to the committee of new_book between hands, e.g. once a player has left
+    /// for good: wires, scopes, pending checks and preprocessing material of the old
+    /// committee are dropped, and preprocessing runs again for the new one. Every party
+    /// of the new committee must call this at the same time, as they agree on new
+    /// pairwise keys. Fails if a receive was left unfinished.
+    pub async fn rotate_committee(
+        &mut self,
+        new_book: Pok3rAddrBook,
+    ) -> Result<(), EvaluatorError> {
+        if let Some(threshold) = self.threshold {
+            if threshold as usize >= new_book.len() {
+                return Err(EvaluatorError::ThresholdTooLarge {
+                    threshold,
+                    parties: new_book.len(),
+                });
+            }
+        }
+        // node ids may be reassigned, so the dealer is followed by its peer id
+        let dealer = match self.dealer {
+            Some(node_id) => {
+                let peer = self.messaging.addr_book().peer_of(node_id);
+                match peer.and_then(|peer| new_book.node_of(peer)) {
+                    Some(dealer) => Some(dealer),
+                    None => return Err(EvaluatorError::DealerLeft { node_id }),
+                }
+            }
+            None => None,
+        };
+        self.messaging.update_addr_book(new_book)?;
+        self.dealer = dealer;
+
+        self.wire_shares.scrub();
+        self.constants.clear();
+        self.scopes.clear();
+        self.beaver_triples.scrub();
+        self.rand_sharings.scrub();
+        self.beaver_counter = 0;
+        self.rand_counter = 0;
+        // node ids may have been reassigned, so neither flags nor the shares still
+        // to check of the old committee say anything about the new one
+        self.flagged_senders.clear();
+        self.pending_checks.clear();
+        self.pending_order.clear();
+        self.late_shares.clear();
+        self.messaging.take_late_values();
+
+        // the keys and seeds were shared with the old committee
+        self.pairwise = None;
+        self.prss = None;
+        self.triple_reservoir = None;
+        self.rand_reservoir = None;
+
+        self.preprocess_triples(self.resources.triples).await?;
+        self.preprocess_rand_sharings(self.resources.rand_sharings)
+            .await?;
+        Ok(())
+    }
 
-            output.push((handle_a, handle_b, handle_c));
+    /// selects the hash the shuffler derives proof challenges with; proofs record
+    /// the choice, so verifiers need no configuration
+    pub fn set_fs_hasher(&mut self, hasher: FsHasher) {
+        self.fs_hasher = hasher;
+    }
+
+    pub fn fs_hasher(&self) -> FsHasher {
+        self.fs_hasher
+    }
+
+    pub fn params(&self) -> &ProtocolParams {
+        &self.params
+    }
+
+    /// fails if some chunk size is zero
+    pub fn set_batch_config(&mut self, config: BatchConfig) -> Result<(), BuildError> {
+        if !config.is_valid() {
+            return Err(BuildError::EmptyChunk);
         }
+        self.batch_config = config;
+        Ok(())
+    }
+
+    pub fn batch_config(&self) -> &BatchConfig {
+        &self.batch_config
+    }
 
-        // Update beaver counter
-        self.beaver_counter += num_beavers as u64;
+    pub fn open_mode(&self) -> OpenMode {
+        self.open_mode
+    }
 
-        output
+    /// None for additive sharings, Some(t) for degree-t Shamir sharings
+    pub fn threshold(&self) -> Option<u64> {
+        self.threshold
     }
 
-    /// performs reconstruction on a wire
-    pub async fn output_wire(&mut self, wire_handle: &String) -> F {
-        let my_share = self.get_wire(wire_handle);
+    /// the mode of every opening from now on, see new_with_open_mode
+    pub fn set_open_mode(&mut self, mode: OpenMode) {
+        self.open_mode = mode;
+    }
+
+    pub fn quorum_policy(&self) -> QuorumPolicy {
+        self.quorum_policy
+    }
+
+    /// how many shares the openings wait for from now on, in threshold mode
+    pub fn set_quorum_policy(&mut self, policy: QuorumPolicy) {
+        self.quorum_policy = policy;
+    }
+
+    pub fn consistent_openings(&self) -> bool {
+        self.consistent_openings
+    }
+
+    /// makes every opening from now on wait for the shares of all parties, and
+    /// then echo a digest of them, see Transport::echo_received; an opening fails
+    /// with NetworkError::Equivocation if a party sent different shares to
+    /// different parties. Costs an extra round per opening.
+    pub fn set_consistent_openings(&mut self, consistent: bool) {
+        self.consistent_openings = consistent;
+    }
+
+    /// the round we are in; every reconstruction starts the next one, and what we
+    /// send carries it, so that values replayed from earlier rounds are dropped
+    pub fn round(&self) -> u64 {
+        self.reconstructions_started
+    }
+
+    /// counts a reconstruction as started, and moves the transport on to its round
+    fn start_reconstruction(&mut self) {
+        self.reconstructions_started += 1;
+        self.messaging.set_round(self.reconstructions_started);
+    }
+
+    /// evidence of every share, so far, that did not lie on the sharing its
+    /// opening was reconstructed from; they do not fail the opening
+    pub fn misbehavior_flags(&self) -> &[Evidence] {
+        &self.misbehavior_flags
+    }
 
+    /// waits up to timeout for the shares the openings went on without, and checks
+    /// them against their sharings; returns every flag so far
+    pub async fn check_late_shares(
+        &mut self,
+        timeout: Duration,
+    ) -> Result<&[Evidence], EvaluatorError> {
         self.messaging
-            .send_to_all([wire_handle.clone()], [encode_f_as_bs58_str(&my_share)])
-            .await;
+            .wait_for_late_values(Instant::now() + timeout)
+            .await?;
+        self.check_shares_against_sharings();
+        Ok(&self.misbehavior_flags)
+    }
 
-        let mut incoming_values: HashMap<u64, F> = self
-            .messaging
-            .recv_from_all(wire_handle)
-            .await
-            .into_iter()
-            .map(|(x, y)| (x, decode_bs58_str_as_f(&y)))
-            .collect();
-        incoming_values.insert(self.messaging.get_my_id(), my_share);
+    /// whether the triples of later committees are checked, see new_with_verified_triples
+    pub fn set_verify_triples(&mut self, verify: bool) {
+        self.verify_triples = verify;
+    }
 
-        reconstruct_scalar(&incoming_values)
+    pub(crate) fn session_id(&self) -> &str {
+        self.messaging.session_id()
     }
 
-    /*
-     * outputs the reconstructed value of all wires
-     */
-    pub async fn batch_output_wire(&mut self, wire_handles: &[String]) -> Vec<F> {
-        let mut outputs = Vec::new();
+    /// preprocessing consumed since the committee was set up
+    pub fn consumed(&self) -> ResourceEstimate {
+        ResourceEstimate {
+            triples: self.beaver_counter as usize,
+            rand_sharings: self.rand_counter as usize,
+        }
+    }
 
-        let mut handles = Vec::new();
-        let mut values = Vec::new();
+    /// preprocessing not consumed yet, including what is still being made
+    pub fn remaining(&self) -> ResourceEstimate {
+        let status = self.preprocessing_status();
+        ResourceEstimate {
+            triples: status.triples.ready + status.triples.pending,
+            rand_sharings: status.rand_sharings.ready + status.rand_sharings.pending,
+        }
+    }
 
-        let len = wire_handles.len();
+    /// how far the background preprocessing got
+    pub fn preprocessing_status(&self) -> PreprocessingStatus {
+        fn status_of<V: Scrub + Clone + Send + 'static>(
+            reservoir: &Option<Reservoir<V>>,
+            unused: usize,
+            taken: u64,
+        ) -> ReservoirStatus {
+            let status = reservoir
+                .as_ref()
+                .map(Reservoir::status)
+                .unwrap_or_default();
+            ReservoirStatus {
+                ready: status.ready + unused,
+                pending: status.pending,
+                taken: taken as usize,
+            }
+        }
 
-        for i in 0..len {
-            handles.push(wire_handles[i].clone());
-            values.push(encode_f_as_bs58_str(&self.get_wire(&wire_handles[i])));
+        PreprocessingStatus {
+            triples: status_of(
+                &self.triple_reservoir,
+                self.beaver_triples.len() - self.beaver_counter as usize,
+                self.beaver_counter,
+            ),
+            rand_sharings: status_of(
+                &self.rand_reservoir,
+                self.rand_sharings.len() - self.rand_counter as usize,
+                self.rand_counter,
+            ),
+        }
+    }
+
+    /// moves triples out of the reservoir until count of them are left unused
+    fn fill_triples(&mut self, count: usize) {
+        let unused = self.beaver_triples.len() - self.beaver_counter as usize;
+        if let (Some(reservoir), true) = (&self.triple_reservoir, unused < count) {
+            self.beaver_triples.extend(reservoir.take(count - unused));
+        }
+    }
+
+    /// same as fill_triples, for random sharings
+    fn fill_rand_sharings(&mut self, count: usize) {
+        let unused = self.rand_sharings.len() - self.rand_counter as usize;
+        if let (Some(reservoir), true) = (&self.rand_reservoir, unused < count) {
+            self.rand_sharings.extend(reservoir.take(count - unused));
         }
+    }
 
-        // let's try to send in batches when possible
-        if len > 256 {
-            let mut processed_len = 0;
+    /// writes the triples and random sharings not used yet to path, for
+    /// new_from_preprocessing, and gives them up: they count as consumed here from
+    /// then on, so that only the evaluator loading them ever uses them
+    pub fn export_preprocessing(&mut self, path: &Path) -> Result<(), PreprocessingError> {
+        let remaining = self.remaining();
+        self.fill_triples(remaining.triples);
+        self.fill_rand_sharings(remaining.rand_sharings);
+
+        let file = PreprocessingFile::<E> {
+            consumed: false,
+            node_id: self.messaging.get_my_id(),
+            num_parties: self.messaging.addr_book().len() as u64,
+            session_id: self.messaging.session_id().as_bytes().to_vec(),
+            threshold: self.threshold,
+            perm_size: self.params.perm_size() as u64,
+            deck_size: self.params.deck_size() as u64,
+            triples_consumed: self.beaver_counter,
+            rand_sharings_consumed: self.rand_counter,
+            beaver_triples: self.beaver_triples[self.beaver_counter as usize..].to_vec(),
+            rand_sharings: self.rand_sharings[self.rand_counter as usize..].to_vec(),
+        };
+        file.write(path)?;
 
-            while processed_len < len {
-                let this_iter_len = std::cmp::min(len - processed_len, 256);
-                let handles_bucket =
-                    &handles[processed_len..processed_len + this_iter_len].to_vec();
-                let values_bucket = &values[processed_len..processed_len + this_iter_len].to_vec();
+        self.beaver_counter = self.beaver_triples.len() as u64;
+        self.rand_counter = self.rand_sharings.len() as u64;
+        Ok(())
+    }
 
-                self.messaging
-                    .send_to_all(handles_bucket, values_bucket)
-                    .await;
+    /// our wire shares, counters, unused preprocessing and settings, for restore
+    pub fn checkpoint(&self) -> EvaluatorSnapshot<E> {
+        let mut wire_shares: Vec<(Vec<u8>, E::ScalarField)> = self
+            .wire_shares
+            .iter()
+            .map(|(wire, share)| (wire.as_str().as_bytes().to_vec(), *share))
+            .collect();
+        wire_shares.sort();
+
+        let mut beaver_triples = self.beaver_triples[self.beaver_counter as usize..].to_vec();
+        if let Some(reservoir) = &self.triple_reservoir {
+            beaver_triples.extend(reservoir.unused());
+        }
+        let mut rand_sharings = self.rand_sharings[self.rand_counter as usize..].to_vec();
+        if let Some(reservoir) = &self.rand_reservoir {
+            rand_sharings.extend(reservoir.unused());
+        }
+        let pairwise_keys = self
+            .pairwise
+            .as_ref()
+            .map_or(Vec::new(), |keys| key_list(keys.keys()));
+        let prss_seeds = self
+            .prss
+            .as_ref()
+            .map_or(Vec::new(), |prss| key_list(prss.seeds()));
+
+        EvaluatorSnapshot {
+            node_id: self.messaging.get_my_id(),
+            session_id: self.messaging.session_id().as_bytes().to_vec(),
+            committee: committee_of(self.messaging.addr_book()),
+            threshold: self.threshold,
+            perm_size: self.params.perm_size() as u64,
+            deck_size: self.params.deck_size() as u64,
+            wire_shares,
+            gate_counter: self.gate_counter,
+            triples_consumed: self.beaver_counter,
+            rand_sharings_consumed: self.rand_counter,
+            reconstructions_started: self.reconstructions_started,
+            reconstructions_finished: self.reconstructions_finished,
+            beaver_triples,
+            rand_sharings,
+            pairwise_keys,
+            prss_seeds,
+            prss_counter: self.prss_counter,
+            open_mode: self.open_mode.tag(),
+            quorum_policy: self.quorum_policy.tag(),
+            rand_source: self.rand_source.tag(),
+            batch_chunks: vec![
+                self.batch_config.scalar_chunk as u64,
+                self.batch_config.g1_chunk as u64,
+                self.batch_config.g2_chunk as u64,
+                self.batch_config.gt_chunk as u64,
+            ],
+            max_message_bytes: self
+                .batch_config
+                .max_message_bytes
+                .map(|bytes| bytes as u64),
+            dealer: self.dealer,
+            verify_triples: self.verify_triples,
+        }
+    }
+
+    /// makes end_phase write a checkpoint to path, or stops it with None
+    pub fn set_write_ahead(&mut self, path: Option<PathBuf>) {
+        self.write_ahead = path;
+    }
+
+    /// gives the transport back, e.g. to restore another evaluator over it
+    pub fn into_messaging(self) -> T {
+        self.messaging
+    }
+
+    pub fn node_id(&self) -> u64 {
+        self.messaging.get_my_id()
+    }
+
+    /// starts recording every opening and every proof the shuffler makes, for
+    /// export_audit
+    pub fn enable_audit(&mut self) {
+        self.audit = Some(AuditLog::new(
+            self.messaging.session_id(),
+            self.messaging.get_my_id(),
+            &self.params,
+        ));
+    }
 
-                processed_len += this_iter_len;
+    /// the transcript recorded since enable_audit, if it was called
+    pub fn audit_log(&self) -> Option<&AuditLog> {
+        self.audit.as_ref()
+    }
+
+    /// starts keeping every party's share of every opening, so that a failed proof
+    /// can be pinned on the party whose shares do not verify
+    pub fn enable_accountability(&mut self) {
+        self.contributions = Some(HashMap::new());
+    }
+
+    /// every party's share of the opening under handle, if enable_accountability
+    /// was called before it
+    pub fn contributions(&self, handle: &str) -> Option<&HashMap<u64, String>> {
+        self.contributions.as_ref()?.get(handle)
+    }
+
+    /// once the proof opened under proof_handle failed to show that the value opened
+    /// under value_handle is the evaluation at point of the commitment opened under
+    /// commitment_handle, checks every party's shares of the three on their own,
+    /// and returns Misbehavior for the first one that fails
+    pub fn blame_opening_proof(
+        &self,
+        pp: &UniversalParams<E>,
+        commitment_handle: &str,
+        value_handle: &str,
+        proof_handle: &str,
+        point: E::ScalarField,
+    ) -> Option<EvaluatorError> {
+        let commitments = self.contributions(commitment_handle)?;
+        let values = self.contributions(value_handle)?;
+        let proofs = self.contributions(proof_handle)?;
+        let mut senders = proofs.keys().copied().collect::<Vec<u64>>();
+        senders.sort_unstable();
+        for sender in senders {
+            let (commitment, value) = match (commitments.get(&sender), values.get(&sender)) {
+                (Some(commitment), Some(value)) => (commitment, value),
+                _ => continue,
+            };
+            let evidence = Evidence::OpeningShare {
+                handle: proof_handle.to_string(),
+                point: encode_as_bs58_str(&point),
+                commitment: commitment.clone(),
+                value: value.clone(),
+                proof: proofs[&sender].clone(),
+            };
+            if evidence.verify(pp) {
+                return Some(EvaluatorError::Misbehavior {
+                    node_id: sender,
+                    evidence: Box::new(evidence),
+                });
             }
-        } else {
-            self.messaging.send_to_all(handles, values).await;
         }
+        None
+    }
 
-        for i in 0..len {
-            let mut incoming_values: HashMap<u64, F> = self
-                .messaging
-                .recv_from_all(&wire_handles[i])
-                .await
-                .into_iter()
-                .map(|(x, y)| (x, decode_bs58_str_as_f(&y)))
+    /// adds entry to the transcript, if one is being recorded
+    pub fn record_audit(&mut self, entry: AuditEntry) {
+        if let Some(log) = &mut self.audit {
+            log.record(entry);
+        }
+    }
+
+    /// writes the transcript to path, signed with our identity key, for
+    /// audit::verify_transcript; writes an empty one if enable_audit was not called
+    pub fn export_audit(&self, path: &Path) -> Result<(), AuditError> {
+        let empty;
+        let log = match &self.audit {
+            Some(log) => log,
+            None => {
+                empty = AuditLog::new(
+                    self.messaging.session_id(),
+                    self.messaging.get_my_id(),
+                    &self.params,
+                );
+                &empty
+            }
+        };
+        log.write(path, |digest| self.messaging.sign(digest))
+    }
+
+    /// makes the shuffler broadcast its proofs, so that verifiers, who hold no
+    /// shares, can check them as the game goes
+    pub fn set_publish_proofs(&mut self, publish: bool) {
+        self.publish_proofs = publish;
+    }
+
+    /// sends entry to the verifiers under the next proof handle, if proofs are
+    /// published; the handles are not wire labels, so that the gate counter stays
+    /// in step with parties that do not publish
+    pub async fn publish_proof(&mut self, entry: &AuditEntry) -> Result<(), NetworkError> {
+        if !self.publish_proofs {
+            return Ok(());
+        }
+        let handle = format!("{}{}", PROOF_HANDLE_PREFIX, self.proof_counter);
+        self.proof_counter += 1;
+        let entry = serde_json::to_vec(entry).expect("an audit entry serializes to JSON");
+        let value = bs58::encode(entry).into_string();
+        self.messaging
+            .send_to_role(PeerRole::Verifier, &[handle], &[value])
+            .await
+    }
+
+    /// sends value to the player with peer id player under handle, and to nobody
+    /// else, e.g. a card dealt to it
+    pub async fn send_to_player(
+        &mut self,
+        player: &Pok3rPeerId,
+        handle: &str,
+        value: &str,
+    ) -> Result<(), NetworkError> {
+        self.messaging
+            .send_to_player(player, &[handle], &[value])
+            .await
+    }
+
+    fn record_opening<V: PayloadValue>(
+        &mut self,
+        handle: &str,
+        kind: PayloadKind,
+        value: &V,
+        shares: &HashMap<u64, V>,
+    ) {
+        if let Some(contributions) = &mut self.contributions {
+            let encoded = shares
+                .iter()
+                .map(|(sender, share)| {
+                    let share = bs58::encode(encode_as_bytes(share)).into_string();
+                    (*sender, share)
+                })
                 .collect();
-            incoming_values.insert(self.messaging.get_my_id(), self.get_wire(&wire_handles[i]));
+            contributions.insert(handle.to_string(), encoded);
+        }
+        if let (Some(t), OpenMode::Plain) = (self.threshold, self.open_mode) {
+            let mut encoded = shares
+                .iter()
+                .map(|(sender, share)| {
+                    (*sender, bs58::encode(encode_as_bytes(share)).into_string())
+                })
+                .collect::<Vec<(u64, String)>>();
+            encoded.sort_unstable();
+            let rest = encoded.split_off(encoded.len().min(t as usize + 1));
+            self.add_pending_check(handle, kind, encoded, rest);
+        }
+        if self.audit.is_none() {
+            return;
+        }
+        self.record_audit(AuditEntry::Opening {
+            handle: handle.to_string(),
+            kind,
+            value: bs58::encode(encode_as_bytes(value)).into_string(),
+            senders: audit::senders_of(shares),
+        });
+    }
 
-            outputs.push(reconstruct_scalar(&incoming_values));
+    /// keeps the t + 1 shares of reference, which the opening under handle was
+    /// reconstructed from, to check the shares of the other parties against, and
+    /// checks the ones we already have
+    fn add_pending_check(
+        &mut self,
+        handle: &str,
+        kind: PayloadKind,
+        reference: Vec<(u64, String)>,
+        rest: Vec<(u64, String)>,
+    ) {
+        if self.pending_order.len() >= MAX_PENDING_CHECKS {
+            if let Some(oldest) = self.pending_order.pop_front() {
+                self.pending_checks.remove(&oldest);
+            }
         }
+        self.pending_checks
+            .insert(handle.to_string(), (kind, reference));
+        self.pending_order.push_back(handle.to_string());
+        self.late_shares.extend(
+            rest.into_iter()
+                .map(|(sender, share)| (handle.to_string(), sender, share)),
+        );
+        self.check_shares_against_sharings();
+    }
 
-        outputs
+    /// checks every late share whose opening is done against the sharing it was
+    /// reconstructed from, and flags those that do not fit
+    fn check_shares_against_sharings(&mut self) {
+        self.late_shares.extend(self.messaging.take_late_values());
+        let mut waiting = VecDeque::new();
+        while let Some((handle, sender, share)) = self.late_shares.pop_front() {
+            let (kind, reference) = match self.pending_checks.get(&handle) {
+                Some(pending) => pending,
+                None => {
+                    waiting.push_back((handle, sender, share));
+                    continue;
+                }
+            };
+            // a share that does not decode is no evidence, see flagged_senders
+            if accountability::share_on_sharing::<E>(*kind, reference, sender, &share)
+                != Some(false)
+            {
+                continue;
+            }
+            self.misbehavior_flags.push(Evidence::InconsistentShare {
+                handle,
+                kind: *kind,
+                reference: reference.clone(),
+                node_id: sender,
+                share,
+            });
+        }
+        while waiting.len() > MAX_PENDING_CHECKS {
+            waiting.pop_front();
+        }
+        self.late_shares = waiting;
     }
 
-    /// reveals the value of g^[x] for the given wire handles, and adds them up
-    pub async fn batch_output_wire_in_exponent(&mut self, wire_handles: &[String]) -> Vec<G1> {
-        let mut my_share_exps = Vec::new();
-        let g = G1::generator();
-        for i in 0..wire_handles.len() {
-            let my_share = self.get_wire(&wire_handles[i]);
-            let my_share_exp = g.mul(my_share);
-            my_share_exps.push(my_share_exp);
+    /// fails before a phase starts, rather than halfway through it, if the
+    /// preprocessing left does not cover needed
+    pub fn assert_resources_remaining(
+        &self,
+        needed: &ResourceEstimate,
+    ) -> Result<(), EvaluatorError> {
+        let remaining = self.remaining();
+        if remaining.covers(needed) {
+            Ok(())
+        } else {
+            Err(EvaluatorError::OutOfPreprocessing {
+                needed: *needed,
+                remaining,
+            })
         }
+    }
 
-        self.batch_add_g1_elements_from_all_parties(&my_share_exps, wire_handles)
-            .await
+    /// constants must enter a sharing exactly once: additive sharings put them in
+    /// the share of party 1, while Shamir sharings put them in every share
+    pub(crate) fn adds_constants(&self) -> bool {
+        self.threshold.is_some() || self.messaging.get_my_id() == 1
     }
 
-    // //on input wire [x], this outputs g^[x], and reconstructs and outputs g^x
-    pub async fn add_g1_elements_from_all_parties(
+    /// our share of the public value: the value itself if we add constants, and
+    /// zero otherwise
+    pub(crate) fn constant_share(&self, value: E::ScalarField) -> E::ScalarField {
+        if self.adds_constants() {
+            value
+        } else {
+            E::ScalarField::zero()
+        }
+    }
+
+    /// collects the other parties' values for identifier: all of them for additive
+    /// sharings, and for threshold sharings the first t, which with ours make t + 1,
+    /// unless the quorum policy says otherwise or the openings are consistent
+    async fn recv_shares(
         &mut self,
-        value: &G1,
         identifier: &String,
-    ) -> G1 {
-        self.messaging
-            .send_to_all([identifier.clone()], [encode_g1_as_bs58_str(value)])
-            .await;
+    ) -> Result<HashMap<u64, String>, EvaluatorError> {
+        if self.consistent_openings {
+            let mut messages = self.recv_consistent(&[identifier]).await?;
+            return Ok(messages.remove(identifier).unwrap_or_default());
+        }
+        match self.threshold {
+            Some(_) if self.quorum_policy == QuorumPolicy::All => {
+                Ok(self.messaging.recv_from_all(identifier).await?)
+            }
+            // committed openings come with a salt, and cannot be checked late
+            Some(t) if self.open_mode == OpenMode::Committed => Ok(self
+                .messaging
+                .recv_from_some(identifier, t as usize)
+                .await?),
+            Some(t) => Ok(self.messaging.recv_quorum(identifier, t as usize).await?),
+            None => Ok(self.messaging.recv_from_all(identifier).await?),
+        }
+    }
 
-        let mut incoming_values: HashMap<u64, G1> = self
-            .messaging
-            .recv_from_all(identifier)
-            .await
-            .into_iter()
-            .map(|(x, y)| (x, decode_bs58_str_as_g1(&y)))
-            .collect();
-        incoming_values.insert(self.messaging.get_my_id(), *value);
+    /// recv_shares for every one of identifiers, keyed by identifier
+    async fn recv_many_shares<I: AsRef<str> + Sync>(
+        &mut self,
+        identifiers: &[I],
+    ) -> Result<HashMap<String, HashMap<u64, String>>, EvaluatorError> {
+        if self.consistent_openings {
+            return self.recv_consistent(identifiers).await;
+        }
+        match self.threshold {
+            Some(_) => {
+                let mut messages = HashMap::with_capacity(identifiers.len());
+                for identifier in identifiers {
+                    let identifier = identifier.as_ref().to_string();
+                    let values = self.recv_shares(&identifier).await?;
+                    messages.insert(identifier, values);
+                }
+                Ok(messages)
+            }
+            None => Ok(self.messaging.recv_many_from_all(identifiers).await?),
+        }
+    }
 
-        reconstruct_g1(&incoming_values)
+    /// the values of every other party for identifiers, once the echo round has
+    /// shown that they sent every party the same
+    async fn recv_consistent<I: AsRef<str> + Sync>(
+        &mut self,
+        identifiers: &[I],
+    ) -> Result<HashMap<String, HashMap<u64, String>>, EvaluatorError> {
+        let messages = self.messaging.recv_many_from_all(identifiers).await?;
+        self.messaging.echo_received(identifiers, &messages).await?;
+        Ok(messages)
     }
 
-    pub async fn batch_add_g1_elements_from_all_parties(
+    /// broadcasts our shares under identifiers, chunk_size of them per message, and
+    /// reconstructs every value from the shares of the others. The next chunk is
+    /// sent before we wait on the current one, so that it travels while we reconstruct.
+    async fn open_pipelined<I, V>(
         &mut self,
-        inputs: &[G1],
-        identifiers: &[String],
-    ) -> Vec<G1> {
-        assert_eq!(inputs.len(), identifiers.len());
-        let len = inputs.len();
+        identifiers: &[I],
+        my_shares: &[V],
+        kind: PayloadKind,
+        chunk_size: usize,
+        decode: impl Fn(&str) -> Result<V, DecodeError>,
+    ) -> Result<Vec<V>, EvaluatorError>
+    where
+        I: AsRef<str> + Sync,
+        V: PayloadValue + Shared,
+    {
+        check_lengths(identifiers.len(), my_shares.len())?;
+        if self.open_mode == OpenMode::Committed {
+            return self
+                .open_committed(identifiers, my_shares, kind, chunk_size, decode)
+                .await;
+        }
 
-        let mut outputs = Vec::new();
+        self.start_reconstruction();
+        let my_id = self.messaging.get_my_id();
+        let committee = self.committee_ids();
+        let chunks: Vec<(&[I], &[V])> = identifiers
+            .chunks(chunk_size)
+            .zip(my_shares.chunks(chunk_size))
+            .collect();
+
+        let mut outputs = Vec::with_capacity(my_shares.len());
+        for k in 0..chunks.len() {
+            let first_to_send = if k == 0 { 0 } else { k + 1 };
+            for (handles, shares) in chunks.iter().take(k + 2).skip(first_to_send) {
+                let values = shares.iter().map(encode_as_bytes).collect::<Vec<Vec<u8>>>();
+                self.messaging
+                    .send_batch_to_all(handles, kind, &values)
+                    .await?;
+            }
+
+            let (handles, shares) = chunks[k];
+            let mut incoming = self.recv_many_shares(handles).await?;
+            for (handle, share) in handles.iter().map(AsRef::as_ref).zip(shares) {
+                let incoming_msgs = incoming.remove(handle).unwrap_or_default();
+                let mut incoming_values =
+                    self.decode_from_senders(handle, kind, incoming_msgs, &decode)?;
+                incoming_values.insert(my_id, *share);
+                let value =
+                    reconstruct_checked_by_sender(&incoming_values, self.threshold, &committee)?;
+                self.record_opening(handle, kind, &value, &incoming_values);
+                outputs.push(value);
+            }
+        }
+
+        self.reconstructions_finished += 1;
+        Ok(outputs)
+    }
 
-        let values = inputs
+    /// the node ids of the committee, whose shares every opening is made of
+    fn committee_ids(&self) -> Vec<u64> {
+        self.messaging
+            .addr_book()
+            .iter_ordered()
+            .map(|peer| peer.node_id)
+            .collect()
+    }
+
+    /// opens shares that are not on wires
+    pub(crate) async fn open_shares(
+        &mut self,
+        shares: &[E::ScalarField],
+    ) -> Result<Vec<E::ScalarField>, EvaluatorError> {
+        let identifiers: Vec<Wire> = shares
             .iter()
-            .map(encode_g1_as_bs58_str)
-            .collect::<Vec<String>>();
+            .map(|_| self.compute_fresh_wire_label())
+            .collect();
+        let chunk_size = self.batch_config.chunk_len_on::<E>(PayloadKind::Scalar);
+        self.open_pipelined(
+            &identifiers,
+            shares,
+            PayloadKind::Scalar,
+            chunk_size,
+            decode_bs58_str_as::<E::ScalarField>,
+        )
+        .await
+    }
 
-        if len > 256 {
-            let mut processed_len = 0;
+    /// random bytes that no party can choose or predict on its own, by the two rounds
+    /// of OpenMode::Committed: every party broadcasts a salted hash of fresh bytes of
+    /// its own, and the bytes only once it has every hash. Returns every party's
+    /// bytes, by node id.
+    pub(crate) async fn joint_coin(&mut self) -> Result<BTreeMap<u64, Vec<u8>>, EvaluatorError> {
+        let handle = self.compute_fresh_wire_label().to_string();
+        let commit_id = format!("{}/commit", handle);
+        let mut coin = [0u8; 32];
+        let mut salt = [0u8; 32];
+        self.rng.fill_bytes(&mut coin);
+        self.rng.fill_bytes(&mut salt);
+        let commitment = commit_to_share(&coin, &salt);
+        let opening = format!(
+            "{}.{}",
+            bs58::encode(coin).into_string(),
+            bs58::encode(salt).into_string()
+        );
 
-            while processed_len < len {
-                let this_iter_len = std::cmp::min(len - processed_len, 256);
-                let handles_bucket =
-                    &identifiers[processed_len..processed_len + this_iter_len].to_vec();
-                let values_bucket = &values[processed_len..processed_len + this_iter_len].to_vec();
-                self.messaging
-                    .send_to_all(handles_bucket.to_owned(), values_bucket.to_owned())
-                    .await;
+        self.messaging
+            .send_to_all(&[&commit_id], &[commitment])
+            .await?;
+        let commitments = self.messaging.recv_from_all(&commit_id).await?;
+        self.messaging.send_to_all(&[&handle], &[opening]).await?;
+        let mut openings = self.messaging.recv_from_all(&handle).await?;
+
+        let mut coins = BTreeMap::new();
+        coins.insert(self.messaging.get_my_id(), coin.to_vec());
+        for (sender, commitment) in commitments {
+            let opening = openings
+                .remove(&sender)
+                .ok_or_else(|| NetworkError::Timeout {
+                    handle: handle.clone(),
+                    missing_senders: vec![sender],
+                })?;
+            let revealed = open_commitment(&opening, &commitment)
+                .and_then(|coin| bs58::decode(coin).into_vec().ok());
+            match revealed {
+                Some(coin) => {
+                    coins.insert(sender, coin);
+                }
+                None => {
+                    return Err(EvaluatorError::Misbehavior {
+                        node_id: sender,
+                        evidence: Box::new(Evidence::CommitmentMismatch {
+                            handle,
+                            commitment,
+                            opening,
+                        }),
+                    })
+                }
+            }
+        }
+        Ok(coins)
+    }
+
+    /// the two rounds of OpenMode::Committed: a salted hash of every share, and then
+    /// every share with its salt, which must match the hash the sender committed to
+    async fn open_committed<I, V>(
+        &mut self,
+        identifiers: &[I],
+        my_shares: &[V],
+        kind: PayloadKind,
+        chunk_size: usize,
+        decode: impl Fn(&str) -> Result<V, DecodeError>,
+    ) -> Result<Vec<V>, EvaluatorError>
+    where
+        I: AsRef<str> + Sync,
+        V: PayloadValue + Shared,
+    {
+        self.start_reconstruction();
+        let commit_ids: Vec<String> = identifiers
+            .iter()
+            .map(|id| format!("{}/commit", id.as_ref()))
+            .collect();
+        let mut commitments = Vec::with_capacity(my_shares.len());
+        let mut openings = Vec::with_capacity(my_shares.len());
+        for share in my_shares {
+            let share_bytes = encode_as_bytes(share);
+            let mut salt = [0u8; 32];
+            self.rng.fill_bytes(&mut salt);
+            commitments.push(commit_to_share(&share_bytes, &salt));
+            openings.push(format!(
+                "{}.{}",
+                bs58::encode(share_bytes).into_string(),
+                bs58::encode(salt).into_string()
+            ));
+        }
 
-                processed_len += this_iter_len;
+        for (ids, values) in commit_ids
+            .chunks(chunk_size)
+            .zip(commitments.chunks(chunk_size))
+        {
+            self.messaging.send_to_all(ids, values).await?;
+        }
+        // every commitment, even with a threshold, since the openings we end up
+        // using may come from any t of the others
+        let mut received_commitments = HashMap::new();
+        for ids in commit_ids.chunks(chunk_size) {
+            received_commitments.extend(self.messaging.recv_many_from_all(ids).await?);
+        }
+
+        for (ids, values) in identifiers
+            .chunks(chunk_size)
+            .zip(openings.chunks(chunk_size))
+        {
+            self.messaging.send_to_all(ids, values).await?;
+        }
+        let my_id = self.messaging.get_my_id();
+        let committee = self.committee_ids();
+        let mut outputs = Vec::with_capacity(my_shares.len());
+        for ((ids, commit_ids), shares) in identifiers
+            .chunks(chunk_size)
+            .zip(commit_ids.chunks(chunk_size))
+            .zip(my_shares.chunks(chunk_size))
+        {
+            let mut incoming = self.recv_many_shares(ids).await?;
+            for ((id, commit_id), share) in
+                ids.iter().map(AsRef::as_ref).zip(commit_ids).zip(shares)
+            {
+                let committed = received_commitments.remove(commit_id).unwrap_or_default();
+                let mut incoming_msgs = HashMap::new();
+                for (sender, opening) in incoming.remove(id).unwrap_or_default() {
+                    // a sender we have no commitment from cannot be checked
+                    let commitment =
+                        committed
+                            .get(&sender)
+                            .ok_or_else(|| NetworkError::Timeout {
+                                handle: commit_id.clone(),
+                                missing_senders: vec![sender],
+                            })?;
+                    match open_commitment(&opening, commitment) {
+                        Some(share) => {
+                            incoming_msgs.insert(sender, share);
+                        }
+                        None => {
+                            return Err(EvaluatorError::Misbehavior {
+                                node_id: sender,
+                                evidence: Box::new(Evidence::CommitmentMismatch {
+                                    handle: id.to_string(),
+                                    commitment: commitment.clone(),
+                                    opening,
+                                }),
+                            })
+                        }
+                    }
+                }
+                let mut incoming_values =
+                    self.decode_from_senders(id, kind, incoming_msgs, &decode)?;
+                incoming_values.insert(my_id, *share);
+                let value =
+                    reconstruct_checked_by_sender(&incoming_values, self.threshold, &committee)?;
+                self.record_opening(id, kind, &value, &incoming_values);
+                outputs.push(value);
             }
-        } else {
-            self.messaging.send_to_all(identifiers, values).await;
         }
 
-        for i in 0..inputs.len() {
-            let incoming_msgs = self.messaging.recv_from_all(&identifiers[i]).await;
-            let mut shares: HashMap<u64, G1> = incoming_msgs
-                .into_iter()
-                .map(|(x, y)| (x, decode_bs58_str_as_g1(&y)))
-                .collect();
-            shares.insert(self.messaging.get_my_id(), inputs[i]);
+        self.reconstructions_finished += 1;
+        Ok(outputs)
+    }
+
+    /// returns a snapshot of the communication and preprocessing counters
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    /// the counters behind metrics, for gadgets to count their own phases without
+    /// the checkpoints of end_phase
+    pub(crate) fn counters(&self) -> &Metrics {
+        &self.metrics
+    }
+
+    /// starts timing a labeled phase, e.g. "shuffle"; see end_phase
+    pub fn begin_phase(&self, name: &str) {
+        self.metrics.begin_phase(name);
+    }
 
-            outputs.push(reconstruct_g1(&shares));
+    /// stops timing the phase and adds the elapsed time to its total; in write-ahead
+    /// mode, also writes a checkpoint, see set_write_ahead
+    pub fn end_phase(&self, name: &str) -> Result<(), CheckpointError> {
+        self.metrics.end_phase(name);
+        match &self.write_ahead {
+            Some(path) => self.checkpoint().write(path),
+            None => Ok(()),
         }
+    }
 
-        outputs
+    /// returns the node ids of peers that have sent malformed values so far
+    pub fn flagged_senders(&self) -> &HashSet<u64> {
+        &self.flagged_senders
     }
 
-    pub async fn add_g2_elements_from_all_parties(
+    /// what the heartbeats of the transport say about every other party, by node id
+    pub fn peer_status(&mut self) -> HashMap<u64, PeerStatus> {
+        self.messaging.peer_status()
+    }
+
+    /// node ids of the parties as the transport finds them dead, e.g. to abort or
+    /// rotate the committee before the next open waits for them
+    pub fn on_peer_down(&mut self) -> mpsc::UnboundedReceiver<u64> {
+        self.messaging.on_peer_down()
+    }
+
+    /// decodes the shares opened under handle; fails, flagging the sender, if some
+    /// share is malformed, as the value cannot be reconstructed without it
+    fn decode_from_senders<V>(
         &mut self,
-        value: &G2,
-        identifier: &String,
-    ) -> G2 {
-        self.messaging
-            .send_to_all([identifier.clone()], [encode_g2_as_bs58_str(value)])
-            .await;
+        handle: &str,
+        kind: PayloadKind,
+        incoming_msgs: HashMap<u64, String>,
+        decode: impl Fn(&str) -> Result<V, DecodeError>,
+    ) -> Result<HashMap<u64, V>, EvaluatorError> {
+        let mut decoded = HashMap::with_capacity(incoming_msgs.len());
+        // the malformed share of the lowest node id, so that every party blames the same
+        let mut malformed: Option<(u64, String)> = None;
+        for (sender, msg) in incoming_msgs {
+            match decode(&msg) {
+                Ok(value) => {
+                    decoded.insert(sender, value);
+                }
+                Err(_err) => {
+                    #[cfg(feature = "print")]
+                    println!("malformed value from party {}: {}", sender, _err);
+                    self.flagged_senders.insert(sender);
+                    if malformed
+                        .as_ref()
+                        .map_or(true, |(lowest, _)| sender < *lowest)
+                    {
+                        malformed = Some((sender, msg));
+                    }
+                }
+            }
+        }
+        match malformed {
+            Some((node_id, share)) => Err(EvaluatorError::Misbehavior {
+                node_id,
+                evidence: Box::new(Evidence::MalformedShare {
+                    handle: handle.to_string(),
+                    kind,
+                    share,
+                }),
+            }),
+            None => Ok(decoded),
+        }
+    }
 
-        let mut incoming_values: HashMap<u64, G2> = self
-            .messaging
-            .recv_from_all(identifier)
-            .await
-            .into_iter()
-            .map(|(x, y)| (x, decode_bs58_str_as_g2(&y)))
-            .collect();
-        incoming_values.insert(self.messaging.get_my_id(), *value);
+    /// returns the (secret-shared) wire value associated with the given handle;
+    /// panics if there is no such wire, see try_get_wire
+    pub fn get_wire(&self, handle: &Wire) -> E::ScalarField {
+        self.try_get_wire(handle)
+            .expect("no wire under this handle")
+    }
+
+    /// same as get_wire, but fails if the wire was never made or has been freed
+    pub fn try_get_wire(&self, handle: &Wire) -> Result<E::ScalarField, EvaluatorError> {
+        self.wire_shares
+            .get(handle)
+            .copied()
+            .ok_or_else(|| EvaluatorError::UnknownWire(handle.clone()))
+    }
+
+    /// asks the pre-processor to generate an additive sharing of a random value
+    /// returns a string handle, which can be used to access the share in future
+    pub fn ran(&mut self) -> Wire {
+        self.fill_rand_sharings(1);
+        let handle = self.compute_fresh_wire_label();
+        // scrubbed here as the triples are in next_triple
+        let slot = &mut self.rand_sharings[self.rand_counter as usize];
+        let share = *slot;
+        slot.scrub();
+        self.rand_counter += 1;
+        self.insert_share(&handle, share);
+
+        handle
+    }
+
+    /// same as ran, but draws from the PRSS right away when there is one, so that
+    /// callers that cannot tell in advance how many they need do not run out
+    fn ran_on_demand(&mut self) -> Wire {
+        let share = match &self.prss {
+            Some(prss) => prss.next_sharing(self.prss_counter),
+            None => return self.ran(),
+        };
+        self.prss_counter += 1;
 
-        reconstruct_g2(&incoming_values)
+        let handle = self.compute_fresh_wire_label();
+        self.insert_share(&handle, share);
+        handle
+    }
+
+    /// agrees on a key with every other party of the committee, unless it did
+    /// already; every party must get here at the same point
+    async fn ensure_pairwise(&mut self) -> Result<(), EvaluatorError> {
+        if self.pairwise.is_none() {
+            let handle = self.compute_fresh_wire_label().to_string();
+            let keys =
+                PairwiseKeys::setup::<E, _, _>(&mut self.messaging, &handle, &mut self.rng).await?;
+            self.pairwise = Some(keys);
+        }
+        Ok(())
+    }
+
+    /// values, one per handle, masked for recipient so that nobody else listening on
+    /// the direct topic learns them
+    async fn mask_for(
+        &mut self,
+        recipient: u64,
+        handles: &[String],
+        values: &[E::ScalarField],
+    ) -> Result<Vec<E::ScalarField>, EvaluatorError> {
+        self.ensure_pairwise().await?;
+        let keys = self.pairwise.as_ref().expect("agreed on above");
+        Ok(handles
+            .iter()
+            .zip(values)
+            .map(|(handle, value)| keys.mask(recipient, handle, value))
+            .collect::<Option<Vec<E::ScalarField>>>()
+            .ok_or(NetworkError::UnknownParty(recipient))?)
+    }
+
+    /// sends recipient its values, one per handle, masked as in mask_for
+    async fn send_masked(
+        &mut self,
+        recipient: u64,
+        handles: &[String],
+        values: &[E::ScalarField],
+    ) -> Result<(), EvaluatorError> {
+        let values = self
+            .mask_for(recipient, handles, values)
+            .await?
+            .iter()
+            .map(encode_as_bs58_str)
+            .collect::<Vec<String>>();
+        self.messaging
+            .send_to_party(recipient, handles, &values)
+            .await?;
+        Ok(())
+    }
+
+    /// the value sender masked for us under handle, or None if it is not a value
+    async fn recv_masked(
+        &mut self,
+        sender: u64,
+        handle: &str,
+    ) -> Result<Option<E::ScalarField>, EvaluatorError> {
+        self.ensure_pairwise().await?;
+        let value = self.messaging.recv_from_party(sender, handle).await?;
+        let keys = self.pairwise.as_ref().expect("agreed on above");
+        Ok(decode_bs58_str_as::<E::ScalarField>(&value)
+            .ok()
+            .and_then(|masked| keys.unmask(sender, handle, &masked)))
+    }
+
+    /// returns handles to len fresh sharings of zero: every party deals a sharing
+    /// of zero to the others and sums what it receives. every party must be online.
+    pub async fn batch_zero_share(&mut self, len: usize) -> Result<Vec<Wire>, EvaluatorError> {
+        let n = self.messaging.addr_book().len() as u64;
+        let my_id = self.messaging.get_my_id();
+
+        let handles: Vec<Wire> = (0..len).map(|_| self.compute_fresh_wire_label()).collect();
+        if handles.is_empty() {
+            return Ok(handles);
+        }
+        let labels: Vec<String> = handles.iter().map(Wire::to_string).collect();
+
+        // party j gets dealt[j - 1]
+        let mut dealt: Vec<Vec<E::ScalarField>> = vec![Vec::with_capacity(len); n as usize];
+        for _ in 0..len {
+            let shares: Vec<E::ScalarField> = match self.threshold {
+                Some(t) => shamir::share(&E::ScalarField::zero(), (t + 1, n), &mut self.rng)
+                    .into_iter()
+                    .map(|(_, share)| share)
+                    .collect(),
+                None => utils::compute_additive_shares(
+                    &E::ScalarField::zero(),
+                    n as usize,
+                    &mut self.rng,
+                ),
+            };
+            for (j, share) in shares.into_iter().enumerate() {
+                dealt[j].push(share);
+            }
+        }
+
+        for recipient in (1..=n).filter(|j| *j != my_id) {
+            self.send_masked(recipient, &labels, &dealt[recipient as usize - 1])
+                .await?;
+        }
+
+        for (i, handle) in handles.iter().enumerate() {
+            let mut share = dealt[my_id as usize - 1][i];
+            for sender in (1..=n).filter(|j| *j != my_id) {
+                match self.recv_masked(sender, handle.as_str()).await? {
+                    Some(value) => share += value,
+                    None => {
+                        // a sum without the sender's share would not be zero
+                        self.flagged_senders.insert(sender);
+                        return Err(EvaluatorError::MalformedDealing {
+                            node_id: sender,
+                            handle: handle.to_string(),
+                        });
+                    }
+                }
+            }
+            self.insert_share(handle, share);
+        }
+
+        Ok(handles)
+    }
+
+    /// outputs a wire label for the same value as handle, re-randomized with a fresh
+    /// sharing of zero so that its shares are independent of the old ones
+    pub async fn refresh_wire(&mut self, handle: &Wire) -> Result<Wire, EvaluatorError> {
+        let zero = self.batch_zero_share(1).await?;
+        let refreshed = self.add(handle, &zero[0]);
+        self.free_wires(&zero);
+        Ok(refreshed)
+    }
+
+    pub async fn batch_ran_64(&mut self, len: usize) -> Result<Vec<Wire>, EvaluatorError> {
+        let mut h_c: Vec<Option<Wire>> = vec![None; len];
+        // outputs still missing; every party opens the same values, so all of them
+        // retry the same positions
+        let mut pending: Vec<usize> = (0..len).collect();
+
+        while !pending.is_empty() {
+            let h_as = pending
+                .iter()
+                .map(|_| self.ran_on_demand())
+                .collect::<Vec<Wire>>();
+
+            let h_a_exp_64s = self.batch_exp(&h_as).await?;
+            let a_exp_64s = self.batch_output_wire(&h_a_exp_64s).await?;
+
+            let mut retry = Vec::new();
+            for (j, &i) in pending.iter().enumerate() {
+                // a zero, or a root that does not exist, is retried with a fresh [a]
+                let mut l = Some(a_exp_64s[j]).filter(|a| !a.is_zero());
+                for _ in 0..self.params.log_perm_size() {
+                    l = l.and_then(|l| utils::compute_root(&l));
+                }
+
+                match l {
+                    Some(l) => {
+                        let handle = self.compute_fresh_wire_label();
+                        let share_c = self.get_wire(&h_as[j]) / l;
+                        self.insert_share(&handle, share_c);
+                        h_c[i] = Some(handle);
+                    }
+                    None => retry.push(i),
+                }
+            }
+            pending = retry;
+        }
+
+        Ok(h_c.into_iter().map(Option::unwrap).collect())
+    }
+
+    /// outputs the wire label denoting the [x] + [y]
+    pub fn add(&mut self, handle_x: &Wire, handle_y: &Wire) -> Wire {
+        let handle = self.compute_fresh_wire_label();
+
+        let share_x = self.get_wire(handle_x);
+        let share_y = self.get_wire(handle_y);
+
+        self.insert_share(&handle, share_x + share_y);
+        handle
+    }
+
+    /// outputs the wire label denoting the [x] - [y]
+    pub fn sub(&mut self, handle_x: &Wire, handle_y: &Wire) -> Wire {
+        let handle = self.compute_fresh_wire_label();
+
+        let share_x = self.get_wire(handle_x);
+        let share_y = self.get_wire(handle_y);
+
+        self.insert_share(&handle, share_x - share_y);
+        handle
+    }
+
+    pub async fn batch_inv(&mut self, input_handles: &[Wire]) -> Result<Vec<Wire>, EvaluatorError> {
+        // goal: compute inv([s])
+        // step 1: invoke ran_p to obtain [r]
+        // step 2: invoke mult to get [q] = [r . s]
+        // step 3: reconstruct q = r . s
+        // step 4: return [r] / q
+
+        let rand_handles: Vec<Wire> = (0..input_handles.len()).map(|_| self.ran()).collect();
+
+        let masked_handles = self.batch_mult(input_handles, &rand_handles).await?;
+
+        let mut masked_values = self.batch_output_wire(&masked_handles).await?;
+        utils::batch_invert(&mut masked_values)
+            .map_err(|index| EvaluatorError::NotInvertible { index })?;
+
+        let mut output: Vec<Wire> = vec![];
+        for i in 0..input_handles.len() {
+            let q_inv = masked_values[i];
+            let wire_out = q_inv * self.get_wire(&rand_handles[i]);
+
+            let handle_out = self.compute_fresh_wire_label();
+            self.insert_share(&handle_out, wire_out);
+
+            output.push(handle_out);
+        }
+        self.free_wires(&rand_handles);
+        self.free_wires(&masked_handles);
+
+        Ok(output)
+    }
+
+    // Adds [x] to y in the clear and outputs handle to the resulting share
+    pub fn clear_add(&mut self, handle_x: &Wire, y: E::ScalarField) -> Wire {
+        let clear_add_share = self.get_wire(handle_x) + self.constant_share(y);
+
+        let handle_out = self.compute_fresh_wire_label();
+        self.insert_share(&handle_out, clear_add_share);
+
+        handle_out
+    }
+
+    // Scales [x] by scalar and outputs handle to the resulting share
+    pub fn scale(&mut self, handle_in: &Wire, scalar: E::ScalarField) -> Wire {
+        let handle_out = self.compute_fresh_wire_label();
+
+        let x = self.get_wire(handle_in);
+
+        self.insert_share(&handle_out, x * scalar);
+
+        handle_out
+    }
+
+    /// given: triple ([a], [b], [c]) and inputs ([x], [y])
+    /// reveals: x + a, y + b, together in one round
+    /// computes [x.y] = (x+a).(y+b) - (x+a).[b] - (y+b).[a] + [c]
+    /// outputs the wire label denoting [x.y]
+    pub async fn mult(&mut self, handle_x: &Wire, handle_y: &Wire) -> Result<Wire, EvaluatorError> {
+        let mut output = self
+            .batch_mult(
+                std::slice::from_ref(handle_x),
+                std::slice::from_ref(handle_y),
+            )
+            .await?;
+        Ok(output.remove(0))
+    }
+
+    pub async fn batch_mult(
+        &mut self,
+        x_handles: &[Wire],
+        y_handles: &[Wire],
+    ) -> Result<Vec<Wire>, EvaluatorError> {
+        let xs = x_handles
+            .iter()
+            .map(|h| self.try_get_wire(h))
+            .collect::<Result<Vec<E::ScalarField>, _>>()?;
+        let ys = y_handles
+            .iter()
+            .map(|h| self.try_get_wire(h))
+            .collect::<Result<Vec<E::ScalarField>, _>>()?;
+
+        let products = self.batch_mult_shares(&xs, &ys).await?;
+
+        let mut output: Vec<Wire> = vec![];
+        for share_x_mul_y in products {
+            let h = self.compute_fresh_wire_label();
+            self.insert_share(&h, share_x_mul_y);
+
+            output.push(h.clone());
+        }
+
+        Ok(output)
+    }
+
+    /// multiplies shares that are not on wires, pairwise, with one beaver triple each
+    pub(crate) async fn batch_mult_shares(
+        &mut self,
+        xs: &[E::ScalarField],
+        ys: &[E::ScalarField],
+    ) -> Result<Vec<E::ScalarField>, EvaluatorError> {
+        check_lengths(xs.len(), ys.len())?;
+        let len: usize = xs.len();
+
+        // store all beaver triples for use later in this function
+        let mut bookkeeping_a: Vec<E::ScalarField> = Vec::new();
+        let mut bookkeeping_b: Vec<E::ScalarField> = Vec::new();
+        let mut bookkeeping_c: Vec<E::ScalarField> = Vec::new();
+        // store all handles for [x+a] and [y+b]
+        let mut x_plus_a_handles: Vec<Wire> = Vec::new();
+        let mut y_plus_b_handles: Vec<Wire> = Vec::new();
+
+        let beavers = self.batch_beaver(len);
+
+        for i in 0..len {
+            let (h_a, h_b, h_c) = beavers[i].clone();
+
+            bookkeeping_a.push(self.get_wire(&h_a));
+            bookkeeping_b.push(self.get_wire(&h_b));
+            bookkeeping_c.push(self.get_wire(&h_c));
+
+            let handle_x_plus_a = self.compute_fresh_wire_label();
+            self.insert_share(&handle_x_plus_a, xs[i] + bookkeeping_a[i]);
+            let handle_y_plus_b = self.compute_fresh_wire_label();
+            self.insert_share(&handle_y_plus_b, ys[i] + bookkeeping_b[i]);
+
+            x_plus_a_handles.push(handle_x_plus_a);
+            y_plus_b_handles.push(handle_y_plus_b);
+        }
+
+        let mut batch_handles = vec![];
+        batch_handles.extend_from_slice(&x_plus_a_handles);
+        batch_handles.extend_from_slice(&y_plus_b_handles);
+
+        let x_plus_a_and_y_plus_b = self.batch_output_wire(&batch_handles).await?;
+
+        let mut output: Vec<E::ScalarField> = vec![];
+        let adds_constants = self.adds_constants();
+
+        for i in 0..len {
+            let x_plus_a_reconstructed = x_plus_a_and_y_plus_b[i];
+            let y_plus_b_reconstructed = x_plus_a_and_y_plus_b[x_plus_a_handles.len() + i];
+
+            //the constant term must be added exactly once
+            let share_x_mul_y: E::ScalarField = if adds_constants {
+                x_plus_a_reconstructed * y_plus_b_reconstructed
+                    - x_plus_a_reconstructed * bookkeeping_b[i]
+                    - y_plus_b_reconstructed * bookkeeping_a[i]
+                    + bookkeeping_c[i]
+            } else {
+                E::ScalarField::zero()
+                    - x_plus_a_reconstructed * bookkeeping_b[i]
+                    - y_plus_b_reconstructed * bookkeeping_a[i]
+                    + bookkeeping_c[i]
+            };
+
+            output.push(share_x_mul_y);
+        }
+
+        for (h_a, h_b, h_c) in beavers {
+            self.free_wires(&[h_a, h_b, h_c]);
+        }
+        self.free_wires(&batch_handles);
+
+        Ok(output)
+    }
+
+    /// puts our share of a value computed off the wires, such as a linear
+    /// combination of shares, on a fresh wire
+    pub(crate) fn wire_from_share(&mut self, share: E::ScalarField) -> Wire {
+        let handle = self.compute_fresh_wire_label();
+        self.insert_share(&handle, share);
+        handle
+    }
+
+    pub fn fixed_wire_handle(&mut self, value: E::ScalarField) -> Wire {
+        let handle = self.compute_fresh_wire_label();
+        self.insert_share(&handle, self.constant_share(value));
+        self.constants.insert(handle.clone(), value);
+        handle
+    }
+
+    /// fixed_wire_handle for every one of values
+    pub fn batch_fixed_wire_handles(&mut self, values: &[E::ScalarField]) -> Vec<Wire> {
+        self.wire_shares.reserve(values.len());
+        self.constants.reserve(values.len());
+        values
+            .iter()
+            .map(|value| self.fixed_wire_handle(*value))
+            .collect()
+    }
+
+    /// wires holding the coefficients of the public polynomial poly, constant first
+    pub fn const_poly_wires(&mut self, poly: &DensePolynomial<E::ScalarField>) -> Vec<Wire> {
+        self.batch_fixed_wire_handles(&poly.coeffs)
+    }
+
+    /// the public value of handle, if it holds a constant
+    pub fn is_constant(&self, handle: &Wire) -> Option<E::ScalarField> {
+        self.constants.get(handle).copied()
+    }
+
+    /// [x] times a public constant, which unlike mult consumes no triple; the
+    /// result is a constant if [x] is
+    pub fn mult_by_public(&mut self, handle: &Wire, constant: E::ScalarField) -> Wire {
+        let product = self.scale(handle, constant);
+        if let Some(value) = self.is_constant(handle) {
+            self.constants.insert(product.clone(), value * constant);
+        }
+        product
+    }
+
+    /// PolyEval takes as input a shared polynomial f(x) and a point x and returns share of f(x)
+    pub fn share_poly_eval(
+        &mut self,
+        f_poly_share: &DensePolynomial<E::ScalarField>,
+        x: E::ScalarField,
+    ) -> Wire {
+        let handle_out = self.compute_fresh_wire_label();
+        self.insert_share(&handle_out, eval_share_poly(f_poly_share, x));
+        handle_out
+    }
+
+    /// evaluates a polynomial with shared coefficients at each of points, returning
+    /// the handles in the order of points
+    pub fn share_poly_eval_many(
+        &mut self,
+        f_poly_share: &DensePolynomial<E::ScalarField>,
+        points: &[E::ScalarField],
+    ) -> Vec<Wire> {
+        self.wire_shares.reserve(points.len());
+        points
+            .iter()
+            .map(|x| self.share_poly_eval(f_poly_share, *x))
+            .collect()
+    }
+
+    /// our share of the evaluation, without putting it on a wire
+    pub fn share_poly_eval_no_wire(
+        &self,
+        f_poly_share: &DensePolynomial<E::ScalarField>,
+        x: E::ScalarField,
+    ) -> E::ScalarField {
+        eval_share_poly(f_poly_share, x)
+    }
+
+    /// Should multiply two polynomials with shared coefficients to get a larger degree polynomial with shared coefficients
+    pub async fn share_poly_mult(
+        &mut self,
+        f_poly_share: DensePolynomial<E::ScalarField>,
+        g_poly_share: DensePolynomial<E::ScalarField>,
+    ) -> Result<DensePolynomial<E::ScalarField>, EvaluatorError> {
+        let h_evals = self
+            .share_poly_mult_evals(&f_poly_share, &g_poly_share)
+            .await?;
+
+        // Interpolate h_evals to get h_poly_share
+        Ok(utils::interpolate_poly_over_mult_subgroup(&h_evals))
+    }
+
+    /// same as share_poly_mult, but returns the shares of the product evaluated at the
+    /// 2 * perm_size roots of unity, skipping the inverse FFT
+    pub async fn share_poly_mult_evals(
+        &mut self,
+        f_poly_share: &DensePolynomial<E::ScalarField>,
+        g_poly_share: &DensePolynomial<E::ScalarField>,
+    ) -> Result<Vec<E::ScalarField>, EvaluatorError> {
+        // the product must be determined by its evaluations on the domain
+        let n = 2 * self.params.perm_size();
+        let coeffs = f_poly_share.coeffs.len() + g_poly_share.coeffs.len();
+        if coeffs > n + 1 {
+            return Err(EvaluatorError::DegreeTooLarge { coeffs, max: n + 1 });
+        }
+
+        let f_evals = share_poly_evals_on_domain(f_poly_share, n);
+        let g_evals = share_poly_evals_on_domain(g_poly_share, n);
+
+        // Compute h_evals from f_evals and g_evals using Beaver mult
+        self.batch_mult_shares(&f_evals, &g_evals).await
+    }
+
+    pub async fn beaver(&mut self) -> (Wire, Wire, Wire) {
+        self.fill_triples(1);
+        let handle_a = self.compute_fresh_wire_label();
+        let handle_b = self.compute_fresh_wire_label();
+        let handle_c = self.compute_fresh_wire_label();
+
+        let (a, b, c) = self.next_triple();
+        self.insert_share(&handle_a, a);
+        self.insert_share(&handle_b, b);
+        self.insert_share(&handle_c, c);
+        self.metrics.record_beaver_triples(1);
+
+        (handle_a, handle_b, handle_c)
+    }
+
+    pub fn batch_beaver(&mut self, num_beavers: usize) -> Vec<(Wire, Wire, Wire)> {
+        self.fill_triples(num_beavers);
+        let mut output = Vec::new();
+
+        for _ in 0..num_beavers {
+            let handle_a = self.compute_fresh_wire_label();
+            let handle_b = self.compute_fresh_wire_label();
+            let handle_c = self.compute_fresh_wire_label();
+
+            let (a, b, c) = self.next_triple();
+            self.insert_share(&handle_a, a);
+            self.insert_share(&handle_b, b);
+            self.insert_share(&handle_c, c);
+
+            output.push((handle_a, handle_b, handle_c));
+        }
+        self.metrics.record_beaver_triples(num_beavers as u64);
+
+        output
+    }
+
+    /// our shares of the next triple, which are scrubbed from beaver_triples as
+    /// they are taken: from then on they only live on the wires
+    fn next_triple(&mut self) -> Triple<E::ScalarField> {
+        let slot = &mut self.beaver_triples[self.beaver_counter as usize];
+        let triple = *slot;
+        slot.scrub();
+        self.beaver_counter += 1;
+        triple
+    }
+
+    /// performs reconstruction on a wire
+    pub async fn output_wire(
+        &mut self,
+        wire_handle: &Wire,
+    ) -> Result<E::ScalarField, EvaluatorError> {
+        if self.open_mode == OpenMode::Committed {
+            return Ok(self
+                .batch_output_wire_committed(std::slice::from_ref(wire_handle))
+                .await?[0]);
+        }
+        let my_share = self.try_get_wire(wire_handle)?;
+        let label = wire_handle.to_string();
+
+        self.start_reconstruction();
+        self.messaging
+            .send_to_all(
+                std::slice::from_ref(&label),
+                &[encode_as_bs58_str(&my_share)],
+            )
+            .await?;
+
+        let incoming_msgs = self.recv_shares(&label).await?;
+        let mut incoming_values: HashMap<u64, E::ScalarField> = self.decode_from_senders(
+            &label,
+            PayloadKind::Scalar,
+            incoming_msgs,
+            decode_bs58_str_as::<E::ScalarField>,
+        )?;
+        incoming_values.insert(self.messaging.get_my_id(), my_share);
+
+        self.reconstructions_finished += 1;
+        let committee = self.committee_ids();
+        let value = reconstruct_checked_by_sender(&incoming_values, self.threshold, &committee)?;
+        self.record_opening(&label, PayloadKind::Scalar, &value, &incoming_values);
+        Ok(value)
+    }
+
+    /// opens sum_i coeffs[i] * handles[i]; only a temporary wire holding the
+    /// combination is revealed, never the handles themselves
+    pub async fn output_linear_combination(
+        &mut self,
+        handles: &[Wire],
+        coeffs: &[E::ScalarField],
+    ) -> Result<E::ScalarField, EvaluatorError> {
+        let combination = (handles.to_vec(), coeffs.to_vec());
+        Ok(self
+            .batch_output_linear_combinations(&[combination])
+            .await?[0])
+    }
+
+    /// output_linear_combination for every one of combinations, in one round
+    pub async fn batch_output_linear_combinations(
+        &mut self,
+        combinations: &[(Vec<Wire>, Vec<E::ScalarField>)],
+    ) -> Result<Vec<E::ScalarField>, EvaluatorError> {
+        for (index, (handles, coeffs)) in combinations.iter().enumerate() {
+            if handles.is_empty() || handles.len() != coeffs.len() {
+                return Err(EvaluatorError::InvalidCombination {
+                    index,
+                    handles: handles.len(),
+                    coeffs: coeffs.len(),
+                });
+            }
+        }
+
+        let mut combined: Vec<Wire> = Vec::with_capacity(combinations.len());
+        for (handles, coeffs) in combinations {
+            let mut share = E::ScalarField::zero();
+            for (h, c) in handles.iter().zip(coeffs) {
+                share += self.try_get_wire(h)? * *c;
+            }
+            combined.push(self.wire_from_share(share));
+        }
+        let output = self.batch_output_wire(&combined).await;
+        self.free_wires(&combined);
+        output
+    }
+
+    /*
+     * outputs the reconstructed value of all wires
+     */
+    pub async fn batch_output_wire(
+        &mut self,
+        wire_handles: &[Wire],
+    ) -> Result<Vec<E::ScalarField>, EvaluatorError> {
+        if self.open_mode == OpenMode::Committed {
+            return self.batch_output_wire_committed(wire_handles).await;
+        }
+        let my_shares = wire_handles
+            .iter()
+            .map(|h| self.try_get_wire(h))
+            .collect::<Result<Vec<E::ScalarField>, _>>()?;
+        let chunk_size = self.batch_config.chunk_len_on::<E>(PayloadKind::Scalar);
+
+        // the wires are their own identifiers, so none are copied
+        self.open_pipelined(
+            wire_handles,
+            &my_shares,
+            PayloadKind::Scalar,
+            chunk_size,
+            decode_bs58_str_as::<E::ScalarField>,
+        )
+        .await
+    }
+
+    /// same as batch_output_wire, but with the two rounds of OpenMode::Committed
+    /// whatever the open mode of the evaluator, so that a batch whose value a party
+    /// could bias can be opened that way without slowing down every other opening
+    pub async fn batch_output_wire_committed(
+        &mut self,
+        wire_handles: &[Wire],
+    ) -> Result<Vec<E::ScalarField>, EvaluatorError> {
+        let my_shares = wire_handles
+            .iter()
+            .map(|h| self.try_get_wire(h))
+            .collect::<Result<Vec<E::ScalarField>, _>>()?;
+        let chunk_size = self.batch_config.chunk_len_on::<E>(PayloadKind::Scalar);
+
+        // how shares are received and checked late goes by the open mode
+        let mode = std::mem::replace(&mut self.open_mode, OpenMode::Committed);
+        let output = self
+            .open_committed(
+                wire_handles,
+                &my_shares,
+                PayloadKind::Scalar,
+                chunk_size,
+                decode_bs58_str_as::<E::ScalarField>,
+            )
+            .await;
+        self.open_mode = mode;
+        output
+    }
+
+    /// same as batch_output_wire, but opens the wires in packs of k: every party
+    /// reshares its shares of a pack as one packed Shamir sharing among all n parties,
+    /// so that opening the pack broadcasts one value instead of k. The resharing costs
+    /// n - 1 direct values per pack, and k must be below n. Threshold sharings, and
+    /// consistent openings, are opened the regular way.
+    pub async fn batch_output_wire_packed(
+        &mut self,
+        wire_handles: &[Wire],
+        k: usize,
+    ) -> Result<Vec<E::ScalarField>, EvaluatorError> {
+        if self.threshold.is_some() || self.consistent_openings {
+            return self.batch_output_wire(wire_handles).await;
+        }
+
+        let n = self.messaging.addr_book().len() as u64;
+        let my_id = self.messaging.get_my_id();
+        let k = k as u64;
+        if k == 0 || k >= n {
+            return Err(ShamirError::TooFewParties {
+                needed: k + 1,
+                parties: n,
+            }
+            .into());
+        }
+        // as much randomness as the degree allows, so that only all n shares
+        // of a party's pack reveal it
+        let t = n - k;
+
+        self.start_reconstruction();
+        let packs: Vec<&[Wire]> = wire_handles.chunks(k as usize).collect();
+        let reshare_handles: Vec<String> = packs
+            .iter()
+            .map(|_| self.compute_fresh_wire_label().to_string())
+            .collect();
+        let pack_handles: Vec<String> = packs
+            .iter()
+            .map(|_| self.compute_fresh_wire_label().to_string())
+            .collect();
+
+        // reshare our shares of every pack; party j gets outgoing[j - 1], and we
+        // keep what we dealt ourselves in packed_shares
+        let mut outgoing: Vec<Vec<E::ScalarField>> = vec![Vec::new(); n as usize];
+        let mut packed_shares: Vec<E::ScalarField> = Vec::new();
+        for pack in packs.iter() {
+            let mut secrets = pack
+                .iter()
+                .map(|h| self.try_get_wire(h))
+                .collect::<Result<Vec<E::ScalarField>, _>>()?;
+            secrets.resize(k as usize, E::ScalarField::zero());
+            let shares = shamir::share_packed(&secrets, (t, n, k), &mut self.rng)?;
+            for (j, (_, share)) in shares.into_iter().enumerate() {
+                if j as u64 + 1 == my_id {
+                    packed_shares.push(share);
+                } else {
+                    outgoing[j].push(share);
+                }
+            }
+        }
+        for recipient in (1..=n).filter(|j| *j != my_id) {
+            self.send_masked(
+                recipient,
+                &reshare_handles,
+                &outgoing[recipient as usize - 1],
+            )
+            .await?;
+        }
+
+        // our packed share is the sum of what every party dealt us
+        for (i, handle) in reshare_handles.iter().enumerate() {
+            for sender in (1..=n).filter(|j| *j != my_id) {
+                match self.recv_masked(sender, handle).await? {
+                    Some(share) => packed_shares[i] += share,
+                    None => {
+                        self.flagged_senders.insert(sender);
+                        return Err(EvaluatorError::MalformedDealing {
+                            node_id: sender,
+                            handle: handle.clone(),
+                        });
+                    }
+                }
+            }
+        }
+
+        // open the packed sharings; t + k shares determine each of them
+        let values = packed_shares
+            .iter()
+            .map(encode_as_bytes)
+            .collect::<Vec<Vec<u8>>>();
+        let chunk_size = self.batch_config.chunk_len_on::<E>(PayloadKind::Scalar);
+        for (handles, values) in pack_handles
+            .chunks(chunk_size)
+            .zip(values.chunks(chunk_size))
+        {
+            self.messaging
+                .send_batch_to_all(handles, PayloadKind::Scalar, values)
+                .await?;
+        }
+
+        let mut outputs = Vec::new();
+        for (i, handle) in pack_handles.iter().enumerate() {
+            let incoming_msgs = self
+                .messaging
+                .recv_from_some(handle, (t + k - 1) as usize)
+                .await?;
+            let mut shares: HashMap<u64, E::ScalarField> = self.decode_from_senders(
+                handle,
+                PayloadKind::Scalar,
+                incoming_msgs,
+                decode_bs58_str_as::<E::ScalarField>,
+            )?;
+            shares.insert(my_id, packed_shares[i]);
+            let senders = shares.clone();
+
+            let shares = shares.into_iter().collect::<Vec<(u64, E::ScalarField)>>();
+            let secrets = shamir::reconstruct_packed(&shares, t, k)?;
+            for (wire, secret) in packs[i].iter().zip(secrets) {
+                self.record_opening(wire.as_str(), PayloadKind::Scalar, &secret, &senders);
+                outputs.push(secret);
+            }
+        }
+
+        self.reconstructions_finished += 1;
+        Ok(outputs)
+    }
+
+    /// reveals the value of g^[x] for the given wire handles, and adds them up
+    pub async fn batch_output_wire_in_exponent(
+        &mut self,
+        wire_handles: &[Wire],
+    ) -> Result<Vec<E::G1>, EvaluatorError> {
+        let mut my_share_exps = Vec::new();
+        let g = E::G1::generator();
+        for handle in wire_handles {
+            let my_share = self.try_get_wire(handle)?;
+            let my_share_exp = g.mul(my_share);
+            my_share_exps.push(my_share_exp);
+        }
+
+        self.batch_add_g1_elements_from_all_parties(&my_share_exps, wire_handles)
+            .await
+    }
+
+    // //on input wire [x], this outputs g^[x], and reconstructs and outputs g^x
+    pub async fn add_g1_elements_from_all_parties(
+        &mut self,
+        value: &E::G1,
+        identifier: &String,
+    ) -> Result<E::G1, EvaluatorError> {
+        if self.open_mode == OpenMode::Committed {
+            let opened = self
+                .open_committed(
+                    &[identifier],
+                    &[*value],
+                    PayloadKind::G1,
+                    1,
+                    decode_bs58_str_as::<E::G1>,
+                )
+                .await?;
+            return Ok(opened[0]);
+        }
+        self.start_reconstruction();
+        self.messaging
+            .send_to_all(&[identifier], &[encode_as_bs58_str(value)])
+            .await?;
+
+        let incoming_msgs = self.recv_shares(identifier).await?;
+        let mut incoming_values: HashMap<u64, E::G1> = self.decode_from_senders(
+            identifier,
+            PayloadKind::G1,
+            incoming_msgs,
+            decode_bs58_str_as::<E::G1>,
+        )?;
+        incoming_values.insert(self.messaging.get_my_id(), *value);
+
+        self.reconstructions_finished += 1;
+        let committee = self.committee_ids();
+        let value = reconstruct_checked_by_sender(&incoming_values, self.threshold, &committee)?;
+        self.record_opening(identifier, PayloadKind::G1, &value, &incoming_values);
+        Ok(value)
+    }
+
+    pub async fn batch_add_g1_elements_from_all_parties<I: AsRef<str> + Sync>(
+        &mut self,
+        inputs: &[E::G1],
+        identifiers: &[I],
+    ) -> Result<Vec<E::G1>, EvaluatorError> {
+        let chunk_size = self.batch_config.chunk_len_on::<E>(PayloadKind::G1);
+        self.open_pipelined(
+            identifiers,
+            inputs,
+            PayloadKind::G1,
+            chunk_size,
+            decode_bs58_str_as::<E::G1>,
+        )
+        .await
+    }
+
+    pub async fn add_g2_elements_from_all_parties(
+        &mut self,
+        value: &E::G2,
+        identifier: &String,
+    ) -> Result<E::G2, EvaluatorError> {
+        if self.open_mode == OpenMode::Committed {
+            let opened = self
+                .open_committed(
+                    &[identifier],
+                    &[*value],
+                    PayloadKind::G2,
+                    1,
+                    decode_bs58_str_as::<E::G2>,
+                )
+                .await?;
+            return Ok(opened[0]);
+        }
+        self.start_reconstruction();
+        self.messaging
+            .send_to_all(&[identifier], &[encode_as_bs58_str(value)])
+            .await?;
+
+        let incoming_msgs = self.recv_shares(identifier).await?;
+        let mut incoming_values: HashMap<u64, E::G2> = self.decode_from_senders(
+            identifier,
+            PayloadKind::G2,
+            incoming_msgs,
+            decode_bs58_str_as::<E::G2>,
+        )?;
+        incoming_values.insert(self.messaging.get_my_id(), *value);
+
+        self.reconstructions_finished += 1;
+        let committee = self.committee_ids();
+        let value = reconstruct_checked_by_sender(&incoming_values, self.threshold, &committee)?;
+        self.record_opening(identifier, PayloadKind::G2, &value, &incoming_values);
+        Ok(value)
+    }
+
+    // //on input wire [x], this outputs g^[x], and reconstructs and outputs g^x
+    pub async fn add_gt_elements_from_all_parties(
+        &mut self,
+        value: &PairingOutput<E>,
+        identifier: &String,
+    ) -> Result<PairingOutput<E>, EvaluatorError> {
+        if self.open_mode == OpenMode::Committed {
+            let opened = self
+                .open_committed(
+                    &[identifier],
+                    &[*value],
+                    PayloadKind::Gt,
+                    1,
+                    decode_bs58_str_as::<PairingOutput<E>>,
+                )
+                .await?;
+            return Ok(opened[0]);
+        }
+        self.start_reconstruction();
+        self.messaging
+            .send_to_all(&[identifier], &[encode_as_bs58_str(value)])
+            .await?;
+
+        let incoming_msgs = self.recv_shares(identifier).await?;
+        let mut incoming_values: HashMap<u64, PairingOutput<E>> = self.decode_from_senders(
+            identifier,
+            PayloadKind::Gt,
+            incoming_msgs,
+            decode_bs58_str_as::<PairingOutput<E>>,
+        )?;
+        incoming_values.insert(self.messaging.get_my_id(), *value);
+
+        self.reconstructions_finished += 1;
+        let committee = self.committee_ids();
+        let value = reconstruct_checked_by_sender(&incoming_values, self.threshold, &committee)?;
+        self.record_opening(identifier, PayloadKind::Gt, &value, &incoming_values);
+        Ok(value)
+    }
+
+    pub async fn batch_add_gt_elements_from_all_parties<I: AsRef<str> + Sync>(
+        &mut self,
+        inputs: &[PairingOutput<E>],
+        identifiers: &[I],
+    ) -> Result<Vec<PairingOutput<E>>, EvaluatorError> {
+        let chunk_size = self.batch_config.chunk_len_on::<E>(PayloadKind::Gt);
+        self.open_pipelined(
+            identifiers,
+            inputs,
+            PayloadKind::Gt,
+            chunk_size,
+            decode_bs58_str_as::<PairingOutput<E>>,
+        )
+        .await
+    }
+
+    /// the table of the generator of Gt, built the first time it is asked for
+    pub fn gt_generator_table(&mut self) -> Arc<GtTable<E>> {
+        self.gt_generator_table
+            .get_or_insert_with(|| {
+                Arc::new(GtTable::new(
+                    PairingOutput::<E>::generator(),
+                    GT_TABLE_WINDOW,
+                ))
+            })
+            .clone()
+    }
+
+    /// base^exponent, through the table if base is the generator of Gt
+    fn gt_mul(&mut self, base: &PairingOutput<E>, exponent: E::ScalarField) -> PairingOutput<E> {
+        let table = self.gt_generator_table();
+        if *base == table.base() {
+            table.mul(exponent)
+        } else {
+            base.mul(exponent)
+        }
+    }
+
+    // secret-shared MSM, where scalars are secret shares. Outputs MSM in the clear.
+    pub async fn exp_and_reveal_gt(
+        &mut self,
+        bases: Vec<PairingOutput<E>>,
+        exponent_handles: Vec<Wire>,
+        func_name: &String,
+    ) -> Result<PairingOutput<E>, EvaluatorError> {
+        let mut sum = PairingOutput::<E>::zero();
+
+        // Compute \sum_i g_i^[x_i]
+        for (base, exponent_handle) in bases.iter().zip(exponent_handles.iter()) {
+            let exponent = self.try_get_wire(exponent_handle)?;
+            sum = sum.add(self.gt_mul(base, exponent));
+        }
+
+        self.add_gt_elements_from_all_parties(&sum, func_name).await
+    }
+
+    pub async fn batch_exp_and_reveal_gt(
+        &mut self,
+        bases: Vec<Vec<PairingOutput<E>>>,
+        exponent_handles: Vec<Vec<Wire>>,
+        identifiers: Vec<String>,
+    ) -> Result<Vec<PairingOutput<E>>, EvaluatorError> {
+        let len = bases.len();
+
+        check_lengths(len, exponent_handles.len())?;
+        check_lengths(len, identifiers.len())?;
+
+        let mut group_elements = vec![];
+
+        for i in 0..len {
+            let msm_input = bases[i].iter().zip(exponent_handles[i].iter());
+            let mut sum = PairingOutput::<E>::zero();
+
+            for (base, exponent_handle) in msm_input {
+                let exponent = self.try_get_wire(exponent_handle)?;
+                sum = sum.add(self.gt_mul(base, exponent));
+            }
+
+            group_elements.push(sum);
+        }
+
+        self.batch_add_gt_elements_from_all_parties(&group_elements, &identifiers)
+            .await
+    }
+
+    // secret-shared MSM, where scalars are secret shares. Outputs MSM in the clear.
+    pub async fn exp_and_reveal_g1(
+        &mut self,
+        bases: Vec<E::G1>,
+        exponent_handles: Vec<Wire>,
+        identifier: &String,
+    ) -> Result<E::G1, EvaluatorError> {
+        let mut sum = E::G1::zero();
+
+        // Compute \sum_i g_i^[x_i]
+        for (base, exponent_handle) in bases.iter().zip(exponent_handles.iter()) {
+            let my_share = self.try_get_wire(exponent_handle)?;
+            let exponentiated = (*base).mul(my_share);
+
+            sum = sum.add(exponentiated);
+        }
+
+        self.add_g1_elements_from_all_parties(&sum, identifier)
+            .await
+    }
+
+    pub async fn exp_and_reveal_g2(
+        &mut self,
+        bases: Vec<E::G2>,
+        exponent_handles: Vec<Wire>,
+        identifier: &String,
+    ) -> Result<E::G2, EvaluatorError> {
+        let mut sum = E::G2::zero();
+
+        // Compute \sum_i g_i^[x_i]
+        for (base, exponent_handle) in bases.iter().zip(exponent_handles.iter()) {
+            let my_share = self.try_get_wire(exponent_handle)?;
+            let exponentiated = (*base).mul(my_share);
+
+            sum = sum.add(exponentiated);
+        }
+
+        self.add_g2_elements_from_all_parties(&sum, identifier)
+            .await
+    }
+
+    pub async fn batch_exp(&mut self, input_labels: &[Wire]) -> Result<Vec<Wire>, EvaluatorError> {
+        let mut tmp = input_labels.to_vec();
+        for _i in 0..self.params.log_perm_size() {
+            tmp = self.batch_mult(&tmp, &tmp).await?;
+        }
+
+        let mut output = Vec::new();
+        for label in tmp.iter() {
+            let handle = self.compute_fresh_wire_label();
+            let share = self.get_wire(label);
+            self.insert_share(&handle, share);
+            output.push(handle);
+        }
+
+        Ok(output)
+    }
+
+    /// our share of the proof that the shared polynomial opens to its value at z
+    pub async fn eval_proof_with_share_poly(
+        &mut self,
+        pp: &UniversalParams<E>,
+        share_poly: DensePolynomial<E::ScalarField>,
+        z: E::ScalarField,
+    ) -> Result<E::G1, KzgError> {
+        let divisor = DensePolynomial::from_coefficients_vec(vec![-z, E::ScalarField::one()]);
+        commit_to_quotient(pp, &share_poly, &divisor)
+    }
+
+    pub async fn batch_eval_proof_with_share_poly(
+        &mut self,
+        pp: &UniversalParams<E>,
+        share_polys: &[DensePolynomial<E::ScalarField>],
+        z_s: &[E::ScalarField],
+    ) -> Result<Vec<E::G1>, EvaluatorError> {
+        check_lengths(share_polys.len(), z_s.len())?;
+
+        // every proof is independent of the others, so they can run in parallel
+        let proofs = cfg_iter!(share_polys)
+            .zip(cfg_iter!(z_s))
+            .map(|(f_poly, z)| {
+                let divisor =
+                    DensePolynomial::from_coefficients_vec(vec![-*z, E::ScalarField::one()]);
+                commit_to_quotient(pp, f_poly, &divisor)
+            })
+            .collect::<Result<Vec<E::G1>, KzgError>>()?;
+        Ok(proofs)
+    }
+
+    /// our shares of the proofs of every one of share_polys at the same point z
+    pub fn batch_eval_proof_at_single_point(
+        &self,
+        pp: &UniversalParams<E>,
+        share_polys: &[DensePolynomial<E::ScalarField>],
+        z: E::ScalarField,
+    ) -> Result<Vec<E::G1>, KzgError> {
+        let divisor = DensePolynomial::from_coefficients_vec(vec![-z, E::ScalarField::one()]);
+        cfg_iter!(share_polys)
+            .map(|f_poly| commit_to_quotient(pp, f_poly, &divisor))
+            .collect()
+    }
+
+    /// opens the point z on z_handle, then gives our share of the proof of the
+    /// shared polynomial at z; for points derived from a shared value
+    pub async fn eval_proof_at_wire(
+        &mut self,
+        pp: &UniversalParams<E>,
+        share_poly: &DensePolynomial<E::ScalarField>,
+        z_handle: &Wire,
+    ) -> Result<(E::ScalarField, E::G1), EvaluatorError> {
+        let z = self.output_wire(z_handle).await?;
+        let pi_share = self
+            .eval_proof_with_share_poly(pp, share_poly.clone(), z)
+            .await?;
+        Ok((z, pi_share))
+    }
+
+    /// our share of one proof opening share_poly at all of z_s, to be checked with
+    /// KZG::verify_multi; batch_eval_proof_with_share_poly gives one proof per point instead.
+    /// Fails if z_s is empty or repeats a point.
+    pub fn multi_eval_proof_with_share_poly(
+        &self,
+        pp: &UniversalParams<E>,
+        share_poly: &DensePolynomial<E::ScalarField>,
+        z_s: &[E::ScalarField],
+    ) -> Result<E::G1, KzgError> {
+        let (pi, _remainder) = KzgOn::<E>::open_multi(pp, share_poly, z_s)?;
+        Ok(pi.into())
+    }
+
+    /// an identifier for a value opened under label that no other opening uses
+    fn fresh_opening_label(&mut self, label: &str) -> String {
+        let counter = self.compute_fresh_wire_label();
+        derived_label(label, &[&counter])
+    }
+
+    /// the KZG commitment to the polynomial of which share_poly is our share;
+    /// only the commitment is revealed
+    pub async fn commit_shared_poly(
+        &mut self,
+        pp: &UniversalParams<E>,
+        share_poly: &DensePolynomial<E::ScalarField>,
+        label: &str,
+    ) -> Result<E::G1, EvaluatorError> {
+        let mut commitments = self
+            .batch_commit_shared_polys(pp, std::slice::from_ref(share_poly), label)
+            .await?;
+        Ok(commitments.remove(0))
+    }
+
+    /// commit_shared_poly for every one of share_polys, in one round
+    pub async fn batch_commit_shared_polys(
+        &mut self,
+        pp: &UniversalParams<E>,
+        share_polys: &[DensePolynomial<E::ScalarField>],
+        label: &str,
+    ) -> Result<Vec<E::G1>, EvaluatorError> {
+        let com_shares = cfg_iter!(share_polys)
+            .map(|share_poly| KzgOn::<E>::commit_g1(pp, share_poly).map(E::G1::from))
+            .collect::<Result<Vec<E::G1>, KzgError>>()?;
+        let identifiers: Vec<String> = share_polys
+            .iter()
+            .map(|_| self.fresh_opening_label(label))
+            .collect();
+        self.batch_add_g1_elements_from_all_parties(&com_shares, &identifiers)
+            .await
+    }
+
+    /// opens the shared polynomial at z, along with the KZG proof of the value
+    pub async fn open_shared_poly_at(
+        &mut self,
+        pp: &UniversalParams<E>,
+        share_poly: &DensePolynomial<E::ScalarField>,
+        z: E::ScalarField,
+        label: &str,
+    ) -> Result<(E::ScalarField, E::G1), EvaluatorError> {
+        let h_value = self.share_poly_eval(share_poly, z);
+        let value = self.output_wire(&h_value).await;
+        self.free_wire(&h_value);
+        // a failed opening takes no further round
+        let value = value?;
+
+        let pi_share: E::G1 = KzgOn::<E>::compute_opening_proof(pp, share_poly, &z)?.into();
+        let identifier = self.fresh_opening_label(label);
+        let pi = self
+            .add_g1_elements_from_all_parties(&pi_share, &identifier)
+            .await?;
+        Ok((value, pi))
+    }
+
+    /// encrypts to an identity of the game of cache, which keeps its pairing with pk
+    pub async fn dist_ibe_encrypt(
+        &mut self,
+        cache: &mut IdCache<E>,
+        msg_share_handle: &Wire,  // [z1]
+        mask_share_handle: &Wire, // [r]
+        pk: &E::G2,
+        id: Vec<u8>,
+    ) -> Result<(E::G1, PairingOutput<E>), EvaluatorError> {
+        let ctx = *cache.context();
+        let h = cache.pairing(&id, pk);
+
+        let c1 = self
+            .exp_and_reveal_g1(
+                vec![E::G1::generator()],
+                vec![mask_share_handle.clone()],
+                &ctx.label(&derived_label(
+                    "ibe_c1",
+                    &[msg_share_handle, mask_share_handle],
+                )),
+            )
+            .await?;
+
+        let g = self.gt_generator_table().base();
+        let c2 = self
+            .exp_and_reveal_gt(
+                vec![g, h],
+                vec![msg_share_handle.clone(), mask_share_handle.clone()],
+                &ctx.label(&derived_label(
+                    "ibe_c2",
+                    &[msg_share_handle, mask_share_handle],
+                )),
+            )
+            .await?;
+
+        Ok((c1, c2))
+    }
+
+    /// same as dist_ibe_encrypt, but for public keys in G1: identities are hashed
+    /// into G2, and are decrypted with H_2(id)^msk paired with c1
+    pub async fn dist_ibe_encrypt_g1pk(
+        &mut self,
+        ctx: &GameContext,
+        msg_share_handle: &Wire,  // [z1]
+        mask_share_handle: &Wire, // [r]
+        pk: &E::G1,
+        id: Vec<u8>,
+    ) -> Result<(E::G1, PairingOutput<E>), EvaluatorError> {
+        let hash_id = hash_to_g2_ctx_on::<E>(ctx, &id);
+
+        let h = E::pairing(pk, hash_id);
+
+        let c1 = self
+            .exp_and_reveal_g1(
+                vec![E::G1::generator()],
+                vec![mask_share_handle.clone()],
+                &ctx.label(&derived_label(
+                    "ibe_g1pk_c1",
+                    &[msg_share_handle, mask_share_handle],
+                )),
+            )
+            .await?;
+
+        let g = self.gt_generator_table().base();
+        let c2 = self
+            .exp_and_reveal_gt(
+                vec![g, h],
+                vec![msg_share_handle.clone(), mask_share_handle.clone()],
+                &ctx.label(&derived_label(
+                    "ibe_g1pk_c2",
+                    &[msg_share_handle, mask_share_handle],
+                )),
+            )
+            .await?;
+
+        Ok((c1, c2))
+    }
+
+    /// Same as dist_batch_ibe_encrypt, but with common mask
+    pub async fn batch_dist_ibe_encrypt_with_common_mask(
+        &mut self,
+        cache: &mut IdCache<E>,
+        msg_share_handles: &[Wire], // [z1]
+        mask_share_handle: &Wire,   // [r]
+        pk: &E::G2,
+        ids: &[Vec<u8>],
+    ) -> Result<(E::G2, Vec<PairingOutput<E>>), EvaluatorError> {
+        check_lengths(msg_share_handles.len(), ids.len())?;
+        let ctx = *cache.context();
+        let c1 = self
+            .exp_and_reveal_g2(
+                vec![E::G2::generator()],
+                vec![mask_share_handle.clone()],
+                &ctx.label(&derived_label("ibe_c1", &[mask_share_handle])),
+            )
+            .await?;
+
+        // our part of c2_i is g^[m_i] · e(H(id_i), pk)^[r]; the exponents are our
+        // shares, so the parts combine into g^m_i · e(H(id_i), pk)^r. The pairings
+        // come from the cache, so a later encryption to the same ids skips them.
+        let mask_share = self.try_get_wire(mask_share_handle)?;
+        let g = self.gt_generator_table();
+        let my_c2s = msg_share_handles
+            .iter()
+            .zip(ids)
+            .map(|(msg_share_handle, id)| {
+                let e_i = cache.pairing(id, pk) * mask_share;
+                Ok(g.mul(self.try_get_wire(msg_share_handle)?) + e_i)
+            })
+            .collect::<Result<Vec<PairingOutput<E>>, EvaluatorError>>()?;
+
+        let identifiers = msg_share_handles
+            .iter()
+            .map(|h| ctx.label(&derived_label("ibe_c2", &[h])))
+            .collect::<Vec<String>>();
+        let c2s = self
+            .batch_add_gt_elements_from_all_parties(&my_c2s, &identifiers)
+            .await?;
+
+        Ok((c1, c2s))
+    }
+
+    /// draws num_sharings random sharings from the PRSS, deriving the seeds from the
+    /// pairwise keys first if this committee has none yet, or takes them from the
+    /// shared seed; they are made in the background as they are used up. Threshold
+    /// sharings are instead dealt jointly up front, see joint_rand_sharings
+    async fn preprocess_rand_sharings(
+        &mut self,
+        num_sharings: usize,
+    ) -> Result<(), EvaluatorError> {
+        if let (RandSource::Prss, Some(t)) = (self.rand_source, self.threshold) {
+            let sharings = Secret::new(self.joint_rand_sharings(num_sharings, t).await?);
+            self.rand_sharings.extend(sharings.iter());
+            self.rand_reservoir = None;
+            return Ok(());
+        }
+
+        let n = self.messaging.addr_book().len();
+        if self.rand_source == RandSource::Prss
+            && self.threshold.is_none()
+            && n >= 3
+            && self.prss.is_none()
+        {
+            self.ensure_pairwise().await?;
+            let keys = self.pairwise.as_ref().expect("agreed on above");
+            self.prss = Some(Prss::from_keys(keys));
+            self.prss_counter = 0;
+        }
+
+        let generate = self.rand_sharing_generator();
+        if self.prss.is_some() {
+            // the reservoir owns these counters, ran_on_demand draws after them
+            self.prss_counter += num_sharings as u64;
+        }
+        self.rand_reservoir = Some(Reservoir::new(
+            generate,
+            num_sharings,
+            RESERVOIR_BOOTSTRAP,
+            RESERVOIR_HIGH_WATER,
+        ));
+        Ok(())
+    }
+
+    /// our shares of the random sharings of the committee, one at a time
+    fn rand_sharing_generator(&mut self) -> Box<dyn FnMut() -> E::ScalarField + Send> {
+        if let Some(prss) = &self.prss {
+            let prss = prss.clone();
+            let mut counter = self.prss_counter;
+            return Box::new(move || {
+                counter += 1;
+                prss.next_sharing(counter - 1)
+            });
+        }
+
+        if self.rand_source == RandSource::Prss {
+            // whatever we draw, our share and those of the others add up to a
+            // value nobody knows
+            let mut rng = StdRng::from_rng(&mut self.rng).expect("cannot seed from rng");
+            return Box::new(move || E::ScalarField::rand(&mut rng));
+        }
+
+        // sharings of the shared seed
+        let n: u64 = self.messaging.addr_book().len() as u64;
+        let index = (self.messaging.get_my_id() - 1) as usize;
+        // the number of coefficients of the sharing polynomials
+        let coeffs = self.threshold.map_or(n, |t| t + 1);
+        let mut rng = rand_chacha::ChaCha8Rng::from_seed([1u8; 32]);
+        Box::new(move || {
+            let secret = E::ScalarField::rand(&mut rng);
+            shamir::share(&secret, (coeffs, n), &mut rng)[index].1
+        })
+    }
+
+    /// our shares of the triples of the shared seed, one triple at a time: additive
+    /// ones, or degree-t Shamir sharings in threshold mode. Every party can draw
+    /// every triple, so this only serves RandSource::SharedSeed
+    fn shared_seed_triple_generator(
+        &mut self,
+    ) -> Box<dyn FnMut() -> Triple<E::ScalarField> + Send> {
+        let n = self.messaging.addr_book().len();
+        let my_id = self.messaging.get_my_id() as usize;
+        let threshold = self.threshold;
+
+        let mut rng = rand_chacha::ChaCha8Rng::from_seed([1u8; 32]);
+        Box::new(move || {
+            let a = E::ScalarField::rand(&mut rng);
+            let b = E::ScalarField::rand(&mut rng);
+            let c = a * b;
+            let mut share = |secret: &E::ScalarField| match threshold {
+                Some(t) => shamir::share(secret, (t + 1, n as u64), &mut rng)[my_id - 1].1,
+                None => utils::compute_additive_shares(secret, n, &mut rng)[my_id - 1],
+            };
+            (share(&a), share(&b), share(&c))
+        })
+    }
+
+    /// whether the triples come from the shared seed rather than being made jointly
+    fn shared_seed_triples(&self) -> bool {
+        self.rand_source == RandSource::SharedSeed
+    }
+
+    /// makes num_beavers triples up front, or in the background if they come from
+    /// the shared seed; with verify_triples, twice as many are made, and half of
+    /// them are sacrificed to check the other half
+    async fn preprocess_triples(&mut self, num_beavers: usize) -> Result<(), EvaluatorError> {
+        if self.dealer.is_none() && !self.verify_triples && self.shared_seed_triples() {
+            let generate = self.shared_seed_triple_generator();
+            self.triple_reservoir = Some(Reservoir::new(
+                generate,
+                num_beavers,
+                RESERVOIR_BOOTSTRAP,
+                RESERVOIR_HIGH_WATER,
+            ));
+            return Ok(());
+        }
+
+        let count = if self.verify_triples {
+            2 * num_beavers
+        } else {
+            num_beavers
+        };
+        let start = self.beaver_triples.len();
+        match self.dealer {
+            Some(dealer_id) => self.deal_triples(dealer_id, count).await?,
+            None => self.generate_triples(count).await?,
+        }
+        if !self.verify_triples {
+            return Ok(());
+        }
+
+        let candidates = Secret::new(self.beaver_triples.split_off(start));
+        let verified = preprocessing::verify_triples(self, &candidates).await?;
+        self.beaver_triples.extend(verified);
+        Ok(())
+    }
+
+    /// the dealer deals count triples and sends every other party its shares, masked
+    /// and in chunks of the batch config; the other parties wait for theirs
+    async fn deal_triples(&mut self, dealer_id: u64, count: usize) -> Result<(), EvaluatorError> {
+        // every share gets a handle of its own, the same at every party
+        let handles: Vec<String> = (0..3 * count)
+            .map(|_| self.compute_fresh_wire_label().to_string())
+            .collect();
+        let my_id = self.messaging.get_my_id();
+
+        if my_id != dealer_id {
+            let mut shares = Vec::with_capacity(handles.len());
+            for handle in handles.iter() {
+                let share = self.recv_masked(dealer_id, handle).await?.ok_or_else(|| {
+                    EvaluatorError::MalformedDealing {
+                        node_id: dealer_id,
+                        handle: handle.clone(),
+                    }
+                })?;
+                shares.push(share);
+            }
+            self.beaver_triples
+                .extend(shares.chunks(3).map(|s| (s[0], s[1], s[2])));
+            return Ok(());
+        }
+
+        let node_ids: Vec<u64> = self
+            .messaging
+            .addr_book()
+            .iter_ordered()
+            .map(|peer| peer.node_id)
+            .collect();
+        let dealt = preprocessing::deal_triples(node_ids.len(), count, &mut self.rng);
+        for (node_id, triples) in node_ids.into_iter().zip(dealt) {
+            if node_id == my_id {
+                self.beaver_triples.extend(triples);
+                continue;
+            }
+            let shares: Vec<E::ScalarField> =
+                triples.iter().flat_map(|(a, b, c)| [*a, *b, *c]).collect();
+            self.send_masked_batch(node_id, &handles, &shares).await?;
+        }
+        Ok(())
+    }
+
+    /// sends recipient its values, one per handle, masked as in mask_for and in
+    /// binary chunks of the batch config
+    async fn send_masked_batch(
+        &mut self,
+        recipient: u64,
+        handles: &[String],
+        values: &[E::ScalarField],
+    ) -> Result<(), EvaluatorError> {
+        let values: Vec<Vec<u8>> = self
+            .mask_for(recipient, handles, values)
+            .await?
+            .iter()
+            .map(encode_as_bytes)
+            .collect();
+        let chunk_size = self.batch_config.chunk_len_on::<E>(PayloadKind::Scalar);
+        for (handles, values) in handles.chunks(chunk_size).zip(values.chunks(chunk_size)) {
+            self.messaging
+                .send_batch_to_party(recipient, handles, PayloadKind::Scalar, values)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// makes num_beavers triples: from the shared seed under RandSource::SharedSeed,
+    /// and jointly otherwise, as degree-t sharings in threshold mode, or as degree
+    /// (n - 1) / 2 sharings turned additive. Additive triples made jointly stay
+    /// private as long as at most (n - 1) / 2 parties collude; a dealer or a
+    /// preprocessing file made elsewhere is needed to tolerate more, or to make
+    /// triples for two parties
+    async fn generate_triples(&mut self, num_beavers: usize) -> Result<(), EvaluatorError> {
+        if self.shared_seed_triples() {
+            let mut generate = self.shared_seed_triple_generator();
+            self.beaver_triples
+                .extend((0..num_beavers).map(|_| generate()));
+            return Ok(());
+        }
+        if let Some(t) = self.threshold {
+            let triples = Secret::new(self.joint_triples(num_beavers, t).await?);
+            self.beaver_triples.extend(triples.iter());
+            return Ok(());
+        }
+
+        let n = self.messaging.addr_book().len() as u64;
+        let my_id = self.messaging.get_my_id();
+        // degree 0 sharings would be the values themselves
+        let t = ((n - 1) / 2).max(1);
+        let triples = Secret::new(self.joint_triples(num_beavers, t).await?);
+        // a sharing of degree below n is the sum of its shares, each weighted by
+        // the Lagrange coefficient of its party
+        let ids: Vec<u64> = (1..=n).collect();
+        let weight = shamir::lagrange_coeffs_at_zero::<E::ScalarField>(&ids)[my_id as usize - 1];
+        self.beaver_triples.extend(
+            triples
+                .iter()
+                .map(|(a, b, c)| (weight * a, weight * b, weight * c)),
+        );
+        Ok(())
+    }
+
+    /// count triples made jointly as degree-t Shamir sharings: every party deals
+    /// sharings of random values, which add up to sharings of a and b; our shares
+    /// of a and b multiply to our share of a degree-2t sharing of c = a.b, which
+    /// every party reshares at degree t, and the reshares are recombined at zero
+    /// (the degree reduction of BGW). Nobody learns a, b or c unless more than t
+    /// parties collude; needs 2t + 1 parties
+    async fn joint_triples(
+        &mut self,
+        count: usize,
+        t: u64,
+    ) -> Result<Vec<Triple<E::ScalarField>>, EvaluatorError> {
+        let n = self.messaging.addr_book().len() as u64;
+        if 2 * t + 1 > n {
+            return Err(EvaluatorError::TooFewPartiesForTriples {
+                needed: 2 * t as usize + 1,
+                parties: n as usize,
+            });
+        }
+
+        let secrets = Secret::new(
+            (0..2 * count)
+                .map(|_| E::ScalarField::rand(&mut self.rng))
+                .collect::<Vec<E::ScalarField>>(),
+        );
+        let ones = vec![E::ScalarField::one(); n as usize];
+        let ab = Secret::new(self.deal_and_combine(&secrets, t, &ones).await?);
+
+        let products = Secret::new(
+            ab.chunks(2)
+                .map(|ab| ab[0] * ab[1])
+                .collect::<Vec<E::ScalarField>>(),
+        );
+        let ids: Vec<u64> = (1..=n).collect();
+        let recombine = shamir::lagrange_coeffs_at_zero(&ids);
+        let c = self.deal_and_combine(&products, t, &recombine).await?;
+
+        Ok(ab
+            .chunks(2)
+            .zip(c)
+            .map(|(ab, c)| (ab[0], ab[1], c))
+            .collect())
+    }
+
+    /// count degree-t random sharings made jointly: every party deals a sharing of
+    /// a random value of its own, and the sharings are added up, so that the value
+    /// stays unknown unless every party, or more than t of them, collude
+    async fn joint_rand_sharings(
+        &mut self,
+        count: usize,
+        t: u64,
+    ) -> Result<Vec<E::ScalarField>, EvaluatorError> {
+        let secrets = Secret::new(
+            (0..count)
+                .map(|_| E::ScalarField::rand(&mut self.rng))
+                .collect::<Vec<E::ScalarField>>(),
+        );
+        let ones = vec![E::ScalarField::one(); self.messaging.addr_book().len()];
+        self.deal_and_combine(&secrets, t, &ones).await
+    }
+
+    /// deals every party a degree-t sharing of each of secrets, and returns for
+    /// each of them the sum of the shares dealt to us, the one of party j weighted
+    /// by weights[j - 1]. Goes one chunk of the batch config at a time, taking in
+    /// the chunk of every other party before dealing the next, so that the inboxes
+    /// never hold more than a chunk from each of them
+    async fn deal_and_combine(
+        &mut self,
+        secrets: &[E::ScalarField],
+        t: u64,
+        weights: &[E::ScalarField],
+    ) -> Result<Vec<E::ScalarField>, EvaluatorError> {
+        let n = self.messaging.addr_book().len() as u64;
+        let my_id = self.messaging.get_my_id();
+        // every share gets a handle of its own, the same at every party
+        let handles: Vec<String> = secrets
+            .iter()
+            .map(|_| self.compute_fresh_wire_label().to_string())
+            .collect();
+
+        let chunk_size = self.batch_config.chunk_len_on::<E>(PayloadKind::Scalar);
+        let mut combined = Vec::with_capacity(secrets.len());
+        for (secrets, handles) in secrets.chunks(chunk_size).zip(handles.chunks(chunk_size)) {
+            // party j gets dealt[j - 1]
+            let mut dealt = Secret::new(vec![Vec::with_capacity(secrets.len()); n as usize]);
+            for secret in secrets {
+                let shares = shamir::share(secret, (t + 1, n), &mut self.rng);
+                for (j, (_, share)) in shares.into_iter().enumerate() {
+                    dealt[j].push(share);
+                }
+            }
+            for recipient in (1..=n).filter(|j| *j != my_id) {
+                self.send_masked_batch(recipient, handles, &dealt[recipient as usize - 1])
+                    .await?;
+            }
+
+            let mine = weights[my_id as usize - 1];
+            let mut sums: Vec<E::ScalarField> = dealt[my_id as usize - 1]
+                .iter()
+                .map(|share| mine * share)
+                .collect();
+            drop(dealt);
+            for sender in (1..=n).filter(|j| *j != my_id) {
+                for (i, handle) in handles.iter().enumerate() {
+                    let share = self.recv_masked(sender, handle).await?.ok_or_else(|| {
+                        EvaluatorError::MalformedDealing {
+                            node_id: sender,
+                            handle: handle.clone(),
+                        }
+                    })?;
+                    sums[i] += weights[sender as usize - 1] * share;
+                }
+            }
+            combined.append(&mut sums);
+        }
+        Ok(combined)
+    }
+}
+
+/// shims for callers that still hold handles as strings; to be removed in the next release
+impl<T: Transport, E: CurveConfig> Evaluator<T, E> {
+    #[deprecated(note = "use get_wire with a Wire")]
+    pub fn get_wire_by_label(&self, label: &str) -> E::ScalarField {
+        *self
+            .wire_shares
+            .get(label)
+            .expect("no wire under this label")
+    }
+
+    #[deprecated(note = "use output_wire with a Wire")]
+    pub async fn output_wire_by_label(
+        &mut self,
+        label: &str,
+    ) -> Result<E::ScalarField, EvaluatorError> {
+        self.output_wire(&Wire::from(label)).await
+    }
+
+    #[deprecated(note = "use batch_output_wire with Wires")]
+    pub async fn batch_output_wire_by_label(
+        &mut self,
+        labels: &[String],
+    ) -> Result<Vec<E::ScalarField>, EvaluatorError> {
+        let wires: Vec<Wire> = labels.iter().map(Wire::from).collect();
+        self.batch_output_wire(&wires).await
+    }
+}
+
+/// handles of published proofs are this followed by a counter
+pub(crate) const PROOF_HANDLE_PREFIX: &str = "proof/";
+
+/// the node id and peer id of every party, as checkpoints record the committee
+fn committee_of(addr_book: &Pok3rAddrBook) -> Vec<(u64, Vec<u8>)> {
+    addr_book
+        .iter_ordered()
+        .map(|peer| (peer.node_id, peer.peer_id.as_bytes().to_vec()))
+        .collect()
+}
+
+/// keys or seeds by node id, as checkpoints record them
+fn key_list(keys: &BTreeMap<u64, [u8; 32]>) -> Vec<(u64, Vec<u8>)> {
+    keys.iter()
+        .map(|(node_id, key)| (*node_id, key.to_vec()))
+        .collect()
+}
+
+/// the keys or seeds a checkpoint recorded with key_list
+fn key_map(list: Vec<(u64, Vec<u8>)>) -> Result<BTreeMap<u64, [u8; 32]>, SerializationError> {
+    list.into_iter()
+        .map(|(node_id, mut key)| {
+            let parsed = <[u8; 32]>::try_from(key.as_slice());
+            key.zeroize();
+            Ok((
+                node_id,
+                parsed.map_err(|_| SerializationError::InvalidData)?,
+            ))
+        })
+        .collect()
+}
+
+/// fails unless a gate got as many of its second arguments as of its first
+fn check_lengths(expected: usize, actual: usize) -> Result<(), EvaluatorError> {
+    if expected != actual {
+        return Err(EvaluatorError::LengthMismatch { expected, actual });
+    }
+    Ok(())
+}
+
+/// the salted hash a party broadcasts before revealing its share
+pub(crate) fn commit_to_share(share_bytes: &[u8], salt: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(share_bytes);
+    hasher.update(salt);
+    bs58::encode(hasher.finalize()).into_string()
+}
+
+/// splits an opening into the share and its salt, and returns the share, encoded
+/// as in a plain opening, if the opening matches commitment
+pub(crate) fn open_commitment(opening: &str, commitment: &str) -> Option<String> {
+    let (share, salt) = opening.split_once('.')?;
+    let share_bytes = bs58::decode(share).into_vec().ok()?;
+    let salt = bs58::decode(salt).into_vec().ok()?;
+    if commit_to_share(&share_bytes, &salt) == commitment {
+        Some(share.to_string())
+    } else {
+        None
+    }
+}
+
+/// the commitment to the quotient of share_poly by divisor; the remainder is
+/// dropped, as it only shifts the opened value
+fn commit_to_quotient<E: CurveConfig>(
+    pp: &UniversalParams<E>,
+    share_poly: &DensePolynomial<E::ScalarField>,
+    divisor: &DensePolynomial<E::ScalarField>,
+) -> Result<E::G1, KzgError> {
+    let (quotient, _remainder) =
+        DenseOrSparsePolynomial::divide_with_q_and_r(&share_poly.into(), &divisor.into())
+            .ok_or(KzgError::ZeroDivisor)?;
+    Ok(KzgOn::<E>::commit_g1(pp, &quotient)?.into())
+}
+
+/// evaluates a polynomial with shared coefficients at a public point, by Horner's rule
+fn eval_share_poly<F: Field>(f_poly_share: &DensePolynomial<F>, x: F) -> F {
+    f_poly_share
+        .coeffs
+        .iter()
+        .rev()
+        .fold(F::zero(), |acc, coeff| acc * x + coeff)
+}
+
+/// evaluates a polynomial with shared coefficients at the n-th roots of unity
+fn share_poly_evals_on_domain<F: FftField>(f_poly_share: &DensePolynomial<F>, n: usize) -> Vec<F> {
+    utils::domain_info_of::<F>(n)
+        .domain
+        .fft(&f_poly_share.coeffs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        commit_to_share, eval_share_poly, share_poly_evals_on_domain, BatchConfig, BuildError,
+        Evaluator, EvaluatorBuilder, EvaluatorError, OpenMode, RandSource,
+    };
+    use crate::accountability::Evidence;
+    use crate::address_book::parse_addr_book_from_json;
+    use crate::common::{
+        Curve, EvalNetMsg, Gt, PayloadKind, PeerInfo, ProtocolParams, ResourceEstimate, F,
+        FEATURE_COMMITTED_OPENS, G1, G2, KZG, PERM_SIZE,
+    };
+    use crate::encoding::{encode_as_bytes, encode_g1_as_bs58_str};
+    use crate::hash::{card_id, hash_to_g1_ctx, hash_to_g2_ctx, GameContext, IdCache};
+    use crate::kzg::KzgError;
+    use crate::network::{NetworkError, RetransmitConfig, DEFAULT_ROUND_HORIZON};
+    use crate::simulator::{build_parties, run_built_parties};
+    use crate::transport::{InMemoryTransport, LinkTamper, Transport};
+    use crate::utils;
+    use crate::wire::Wire;
+    use ark_ec::{pairing::Pairing, Group};
+    use ark_poly::{univariate::DensePolynomial, DenseUVPolynomial, Polynomial};
+    use ark_std::{UniformRand, Zero};
+    use futures::future::{join_all, try_join_all};
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::alloc::{GlobalAlloc, Layout, System};
+    use std::cell::Cell;
+    use std::collections::{HashMap, HashSet};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    /// counts the allocations of the threads that turned counting on, so that the
+    /// tests running alongside do not add to them
+    struct CountingAllocator;
+
+    thread_local! {
+        static COUNTING: Cell<bool> = const { Cell::new(false) };
+        static ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+    }
+
+    fn count_allocation() {
+        if COUNTING.try_with(Cell::get).unwrap_or(false) {
+            let _ = ALLOCATIONS.try_with(|count| count.set(count.get() + 1));
+        }
+    }
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            count_allocation();
+            System.alloc(layout)
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            System.dealloc(ptr, layout)
+        }
+
+        unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+            count_allocation();
+            System.realloc(ptr, layout, new_size)
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+    #[test]
+    fn test_builder_rejects_incompatible_settings() {
+        async_std::task::block_on(async {
+            let mut transports = InMemoryTransport::new_network(4).await;
+            let result = EvaluatorBuilder::new()
+                .threshold(4)
+                .build(transports.pop().unwrap())
+                .await;
+            assert_eq!(
+                result.err(),
+                Some(BuildError::ThresholdTooLarge {
+                    threshold: 4,
+                    parties: 4
+                })
+            );
+
+            let result = EvaluatorBuilder::new()
+                .session("another game")
+                .build(transports.pop().unwrap())
+                .await;
+            assert_eq!(
+                result.err(),
+                Some(BuildError::SessionMismatch {
+                    expected: String::from("another game"),
+                    found: String::from("in-memory"),
+                })
+            );
+
+            let config = BatchConfig {
+                g2_chunk: 0,
+                ..BatchConfig::default()
+            };
+            let result = EvaluatorBuilder::new()
+                .batch_config(config)
+                .build(transports.pop().unwrap())
+                .await;
+            assert_eq!(result.err(), Some(BuildError::EmptyChunk));
+
+            let mut unnamed = InMemoryTransport::new_overlapping_networks(2, &[""]).await;
+            let result = EvaluatorBuilder::new()
+                .open_mode(OpenMode::Committed)
+                .build(unnamed[0].pop().unwrap())
+                .await;
+            assert_eq!(result.err(), Some(BuildError::CommittedWithoutSession));
+
+            let result = EvaluatorBuilder::new()
+                .dealer(9)
+                .build(transports.pop().unwrap())
+                .await;
+            assert_eq!(result.err(), Some(BuildError::UnknownDealer(9)));
+
+            let mut others = InMemoryTransport::new_network(3).await;
+            let result = EvaluatorBuilder::new()
+                .dealer(1)
+                .threshold(1)
+                .build(others.pop().unwrap())
+                .await;
+            assert_eq!(result.err(), Some(BuildError::DealerWithThreshold));
+
+            let result = EvaluatorBuilder::new()
+                .rng(BrokenRng)
+                .build(others.pop().unwrap())
+                .await;
+            assert!(matches!(result.err(), Some(BuildError::Rng(_))));
+        });
+    }
+
+    /// a generator whose source has gone away
+    struct BrokenRng;
+
+    impl rand::RngCore for BrokenRng {
+        fn next_u32(&mut self) -> u32 {
+            unimplemented!()
+        }
+
+        fn next_u64(&mut self) -> u64 {
+            unimplemented!()
+        }
+
+        fn fill_bytes(&mut self, _dest: &mut [u8]) {
+            unimplemented!()
+        }
+
+        fn try_fill_bytes(&mut self, _dest: &mut [u8]) -> Result<(), rand::Error> {
+            Err(rand::Error::new("the source has gone away"))
+        }
+    }
+
+    impl rand::CryptoRng for BrokenRng {}
+
+    #[test]
+    fn test_parties_of_another_open_mode_are_turned_away() {
+        async_std::task::block_on(async {
+            let transports = InMemoryTransport::new_network(3).await;
+            let committee: Vec<String> = transports[0].addr_book().peer_ids().cloned().collect();
+            let results = join_all(transports.into_iter().map(|transport| {
+                let mode = if transport.get_my_id() == 3 {
+                    OpenMode::Committed
+                } else {
+                    OpenMode::Plain
+                };
+                EvaluatorBuilder::new().open_mode(mode).build(transport)
+            }))
+            .await;
+
+            let rejected = |peer: &String, ours: &str, theirs: &str| {
+                Some(BuildError::Preprocessing(EvaluatorError::Network(
+                    NetworkError::IncompatiblePeer {
+                        peer: peer.clone(),
+                        field: String::from("committed_opens"),
+                        ours: String::from(ours),
+                        theirs: String::from(theirs),
+                    },
+                )))
+            };
+            assert_eq!(
+                results[0].as_ref().err(),
+                rejected(&committee[2], "false", "true").as_ref()
+            );
+            assert_eq!(
+                results[1].as_ref().err(),
+                rejected(&committee[2], "false", "true").as_ref()
+            );
+            // party 3 names whichever of the others it heard from first
+            let third = results[2].as_ref().err();
+            assert!(
+                third == rejected(&committee[0], "true", "false").as_ref()
+                    || third == rejected(&committee[1], "true", "false").as_ref()
+            );
+        });
+    }
+
+    #[test]
+    fn test_two_parties_cannot_make_triples_jointly() {
+        async_std::task::block_on(async {
+            let transports = InMemoryTransport::new_network(2).await;
+            let results = join_all(transports.into_iter().map(Evaluator::new)).await;
+            for result in results {
+                assert_eq!(
+                    result.err(),
+                    Some(BuildError::Preprocessing(
+                        EvaluatorError::TooFewPartiesForTriples {
+                            needed: 3,
+                            parties: 2
+                        }
+                    ))
+                );
+            }
+        });
+    }
+
+    #[test]
+    fn test_joint_triples_multiply_and_differ_between_committees() {
+        async fn open_triples(n: usize) -> Vec<(F, F, F)> {
+            let resources = ResourceEstimate {
+                triples: 6,
+                rand_sharings: 0,
+            };
+            let build = |transport| {
+                EvaluatorBuilder::new()
+                    .preprocessing(resources)
+                    .build(transport)
+            };
+            let outputs = run_built_parties(n, build, |mut evaluator| async move {
+                let triples = evaluator.batch_beaver(6);
+                let handles: Vec<Wire> = triples
+                    .iter()
+                    .flat_map(|(a, b, c)| [a.clone(), b.clone(), c.clone()])
+                    .collect();
+                let opened = evaluator.batch_output_wire(&handles).await.unwrap();
+                opened
+                    .chunks(3)
+                    .map(|t| (t[0], t[1], t[2]))
+                    .collect::<Vec<_>>()
+            })
+            .await;
+            assert!(outputs.iter().all(|triples| *triples == outputs[0]));
+            outputs[0].clone()
+        }
+
+        async_std::task::block_on(async {
+            let (first, second) = (open_triples(3).await, open_triples(4).await);
+            for (a, b, c) in first.iter().chain(second.iter()) {
+                assert_eq!(*c, *a * *b);
+                assert!(!a.is_zero() && !b.is_zero());
+            }
+            // nothing every party knows in advance is behind them
+            assert_ne!(first, second);
+            assert_ne!(first, open_triples(3).await);
+        });
+    }
+
+    #[test]
+    fn test_threshold_masks_are_not_fixed_by_one_party() {
+        // party 1 draws the same randomness in every committee, the others do not
+        async fn open_masks(others: u64) -> (Vec<F>, Vec<F>) {
+            let resources = ResourceEstimate {
+                triples: 4,
+                rand_sharings: 4,
+            };
+            let transports = InMemoryTransport::new_network(3).await;
+            let evaluators = join_all(transports.into_iter().enumerate().map(|(i, transport)| {
+                let seed = if i == 0 { 0 } else { others + i as u64 };
+                EvaluatorBuilder::new()
+                    .preprocessing(resources)
+                    .threshold(1)
+                    .rng(StdRng::seed_from_u64(seed))
+                    .build(transport)
+            }))
+            .await;
+
+            let outputs = join_all(evaluators.into_iter().map(|evaluator| async move {
+                let mut evaluator = evaluator.unwrap();
+                let rs: Vec<Wire> = (0..4).map(|_| evaluator.ran()).collect();
+                let abs: Vec<Wire> = evaluator
+                    .batch_beaver(4)
+                    .into_iter()
+                    .flat_map(|(a, b, _)| [a, b])
+                    .collect();
+                let rs = evaluator.batch_output_wire(&rs).await.unwrap();
+                (rs, evaluator.batch_output_wire(&abs).await.unwrap())
+            }))
+            .await;
+            assert!(outputs.iter().all(|masks| *masks == outputs[0]));
+            outputs[0].clone()
+        }
+
+        async_std::task::block_on(async {
+            let (first, second) = (open_masks(100).await, open_masks(200).await);
+            for (x, y) in first.0.iter().zip(second.0.iter()) {
+                assert_ne!(x, y);
+            }
+            for (x, y) in first.1.iter().zip(second.1.iter()) {
+                assert_ne!(x, y);
+            }
+        });
+    }
+
+    #[test]
+    fn test_builder_settings_are_honored() {
+        async_std::task::block_on(async {
+            let params = ProtocolParams::new(16, 10).unwrap();
+            let resources = ResourceEstimate {
+                triples: 40,
+                rand_sharings: 20,
+            };
+            let config = BatchConfig {
+                scalar_chunk: 3,
+                ..BatchConfig::default()
+            };
+            let transports = InMemoryTransport::new_network(3).await;
+            let evaluators = join_all(transports.into_iter().enumerate().map(|(i, transport)| {
+                EvaluatorBuilder::new()
+                    .params(params)
+                    .preprocessing(resources)
+                    .batch_config(config)
+                    .open_mode(OpenMode::Committed)
+                    .threshold(1)
+                    .rng(StdRng::seed_from_u64(i as u64))
+                    .session("in-memory")
+                    .build(transport)
+            }))
+            .await;
+
+            let outputs = join_all(evaluators.into_iter().map(|evaluator| async move {
+                let mut evaluator = evaluator.unwrap();
+                assert_eq!(*evaluator.params(), params);
+                assert_eq!(evaluator.remaining(), resources);
+                assert_eq!(*evaluator.batch_config(), config);
+                assert_eq!(evaluator.open_mode(), OpenMode::Committed);
+                assert_eq!(evaluator.threshold(), Some(1));
+
+                let xs = (0..8u64)
+                    .map(|i| evaluator.fixed_wire_handle(F::from(i)))
+                    .collect::<Vec<Wire>>();
+                let zs = evaluator.batch_mult(&xs, &xs).await.unwrap();
+                evaluator.batch_output_wire(&zs).await.unwrap()
+            }))
+            .await;
+
+            let expected = (0..8u64).map(|i| F::from(i * i)).collect::<Vec<F>>();
+            for output in outputs {
+                assert_eq!(output, expected);
+            }
+        });
+    }
+
+    #[test]
+    fn test_mult_by_public_consumes_no_triple() {
+        async_std::task::block_on(async {
+            let outputs = run_built_parties(3, Evaluator::new, |mut evaluator| async move {
+                let coeffs = [3u64, 0, 5].map(F::from).to_vec();
+                let poly = DensePolynomial::from_coefficients_vec(coeffs.clone());
+                let cs = evaluator.const_poly_wires(&poly);
+                assert_eq!(cs.len(), 3);
+                assert_eq!(evaluator.is_constant(&cs[2]), Some(coeffs[2]));
+
+                let x = evaluator.ran();
+                assert_eq!(evaluator.is_constant(&x), None);
+                let before = evaluator.consumed().triples;
+                let by_public = evaluator.mult_by_public(&x, coeffs[2]);
+                assert_eq!(evaluator.consumed().triples, before);
+                let by_mult = evaluator.mult(&x, &cs[2]).await.unwrap();
+                assert_eq!(evaluator.consumed().triples, before + 1);
+
+                let both = evaluator.mult_by_public(&cs[0], F::from(7u64));
+                assert_eq!(evaluator.is_constant(&both), Some(F::from(21u64)));
+                evaluator.free_wire(&both);
+                assert_eq!(evaluator.is_constant(&both), None);
+
+                let opened = evaluator
+                    .batch_output_wire(&[by_public, by_mult])
+                    .await
+                    .unwrap();
+                assert_eq!(opened[0], opened[1]);
+            })
+            .await;
+            assert_eq!(outputs.len(), 3);
+        });
+    }
+
+    #[test]
+    fn test_linear_combination_opens_only_the_combination() {
+        async_std::task::block_on(async {
+            let (transports, mut verifier_rxs) =
+                InMemoryTransport::new_network_with_verifiers(4, 1).await;
+            let evaluators = try_join_all(transports.into_iter().map(Evaluator::new))
+                .await
+                .unwrap();
+
+            let outputs = join_all(evaluators.into_iter().map(|mut evaluator| async move {
+                let x = evaluator.fixed_wire_handle(F::from(3u64));
+                let five = evaluator.fixed_wire_handle(F::from(5u64));
+                let y = evaluator.mult(&x, &five).await.unwrap();
+                let (a, b) = (F::from(2u64), -F::from(7u64));
+
+                assert_eq!(
+                    evaluator
+                        .output_linear_combination(std::slice::from_ref(&x), &[])
+                        .await
+                        .err(),
+                    Some(EvaluatorError::InvalidCombination {
+                        index: 0,
+                        handles: 1,
+                        coeffs: 0
+                    })
+                );
+                let empty = (Vec::new(), Vec::new());
+                assert!(evaluator
+                    .batch_output_linear_combinations(&[empty])
+                    .await
+                    .is_err());
+
+                let single = evaluator
+                    .output_linear_combination(&[x.clone(), y.clone()], &[a, b])
+                    .await
+                    .unwrap();
+                let batched = evaluator
+                    .batch_output_linear_combinations(&[
+                        (vec![x.clone(), y.clone()], vec![a, b]),
+                        (vec![y.clone()], vec![F::from(2u64)]),
+                    ])
+                    .await
+                    .unwrap();
+                (vec![x.to_string(), y.to_string()], single, batched)
+            }))
+            .await;
+
+            let expected = F::from(2u64 * 3) - F::from(7u64 * 15);
+            let mut secret_handles = HashSet::new();
+            for (handles, single, batched) in outputs {
+                assert_eq!(single, expected);
+                assert_eq!(batched, vec![expected, F::from(30u64)]);
+                secret_handles.extend(handles);
+            }
+
+            let mut verifier_rx = verifier_rxs.pop().unwrap();
+            let mut published = 0;
+            while let Ok(Some(msg)) = verifier_rx.try_next() {
+                let handles = match msg {
+                    EvalNetMsg::PublishValue { handle, .. } => vec![handle],
+                    EvalNetMsg::PublishBatchValue { handles, .. }
+                    | EvalNetMsg::PublishBatchBinary { handles, .. } => handles,
+                    _ => continue,
+                };
+                published += handles.len();
+                assert!(handles.iter().all(|h| !secret_handles.contains(h)));
+            }
+            assert!(published > 0);
+        });
+    }
+
+    #[test]
+    fn test_in_memory_batch_mult_and_output() {
+        async_std::task::block_on(async {
+            let outputs = run_built_parties(4, Evaluator::new, |mut evaluator| async move {
+                let xs = (0..16u64)
+                    .map(|i| evaluator.fixed_wire_handle(F::from(i)))
+                    .collect::<Vec<Wire>>();
+                let ys = (0..16u64)
+                    .map(|i| evaluator.fixed_wire_handle(F::from(i + 100)))
+                    .collect::<Vec<Wire>>();
+
+                let zs = evaluator.batch_mult(&xs, &ys).await.unwrap();
+                evaluator.batch_output_wire(&zs).await.unwrap()
+            })
+            .await;
+
+            let expected = (0..16u64)
+                .map(|i| F::from(i * (i + 100)))
+                .collect::<Vec<F>>();
+            for output in outputs {
+                assert_eq!(output, expected);
+            }
+        });
+    }
+
+    #[test]
+    fn test_batch_output_wire_allocations() {
+        const NUM_WIRES: usize = 4096;
+
+        let allocations = async_std::task::block_on(async {
+            let evaluators = build_parties(3, |transport| {
+                EvaluatorBuilder::new()
+                    .preprocessing(ResourceEstimate::default())
+                    .build(transport)
+            })
+            .await;
+            let parties = evaluators
+                .into_iter()
+                .map(|mut evaluator| {
+                    let xs = (0..NUM_WIRES as u64)
+                        .map(|i| evaluator.fixed_wire_handle(F::from(i)))
+                        .collect::<Vec<Wire>>();
+                    (evaluator, xs)
+                })
+                .collect::<Vec<_>>();
+
+            // every party runs on this thread, only the relays between them do not
+            ALLOCATIONS.with(|count| count.set(0));
+            COUNTING.with(|counting| counting.set(true));
+            let outputs = join_all(parties.into_iter().map(|(mut evaluator, xs)| async move {
+                evaluator.batch_output_wire(&xs).await.unwrap()
+            }))
+            .await;
+            COUNTING.with(|counting| counting.set(false));
+
+            let expected = (0..NUM_WIRES as u64).map(F::from).collect::<Vec<F>>();
+            for output in outputs {
+                assert_eq!(output, expected);
+            }
+            ALLOCATIONS.with(Cell::get)
+        });
+
+        // copying the identifiers and values on their way took over 20 per party
+        // and wire; 13 are left, mostly the strings the mailbox keeps per sender
+        let per_wire = allocations as f64 / (3 * NUM_WIRES) as f64;
+        assert!(
+            per_wire < 16.0,
+            "{} allocations per party and wire",
+            per_wire
+        );
+    }
+
+    #[test]
+    fn test_batch_output_wire_over_lossy_network() {
+        async_std::task::block_on(async {
+            let mut transports = InMemoryTransport::new_lossy_network(4, 0.2, 7).await;
+            for transport in transports.iter_mut() {
+                transport.set_retransmit_config(RetransmitConfig {
+                    initial_backoff: Duration::from_millis(20),
+                    max_backoff: Duration::from_millis(160),
+                    max_attempts: 30,
+                });
+            }
+            // a party retransmits only while it runs, so each one goes on from its
+            // build at once rather than waiting for the others to be built
+            let outputs = join_all(transports.into_iter().map(|transport| async move {
+                let mut evaluator = Evaluator::new(transport).await.unwrap();
+                let xs = (0..1000u64)
+                    .map(|i| evaluator.fixed_wire_handle(F::from(i)))
+                    .collect::<Vec<Wire>>();
+
+                let output = evaluator.batch_output_wire(&xs).await.unwrap();
+                evaluator.flush().await.unwrap();
+                output
+            }))
+            .await;
+
+            let expected = (0..1000u64).map(F::from).collect::<Vec<F>>();
+            for output in outputs {
+                assert_eq!(output, expected);
+            }
+        });
+    }
+
+    #[test]
+    fn test_pipelined_openings_with_small_chunks() {
+        async_std::task::block_on(async {
+            let transports =
+                InMemoryTransport::new_network_with_latency(3, Duration::from_millis(1)).await;
+            let evaluators = try_join_all(transports.into_iter().map(Evaluator::new))
+                .await
+                .unwrap();
+
+            let outputs = join_all(evaluators.into_iter().map(|mut evaluator| async move {
+                evaluator
+                    .set_batch_config(BatchConfig {
+                        scalar_chunk: 7,
+                        g1_chunk: 7,
+                        g2_chunk: 7,
+                        gt_chunk: 3,
+                        max_message_bytes: None,
+                    })
+                    .unwrap();
+                let xs = (0..50u64)
+                    .map(|i| evaluator.fixed_wire_handle(F::from(i)))
+                    .collect::<Vec<Wire>>();
+
+                let opened = evaluator.batch_output_wire(&xs).await.unwrap();
+                let in_exponent = evaluator.batch_output_wire_in_exponent(&xs).await.unwrap();
+
+                let my_id = F::from(evaluator.messaging.get_my_id());
+                let gts = (0..10u64)
+                    .map(|i| Gt::generator() * (my_id * F::from(i)))
+                    .collect::<Vec<Gt>>();
+                let ids = (0..10)
+                    .map(|i| format!("gt_{}", i))
+                    .collect::<Vec<String>>();
+                let summed = evaluator
+                    .batch_add_gt_elements_from_all_parties(&gts, &ids)
+                    .await
+                    .unwrap();
+                (opened, in_exponent, summed)
+            }))
+            .await;
+
+            for (opened, in_exponent, summed) in outputs {
+                for i in 0..50u64 {
+                    assert_eq!(opened[i as usize], F::from(i));
+                    assert_eq!(in_exponent[i as usize], G1::generator() * F::from(i));
+                }
+                for i in 0..10u64 {
+                    assert_eq!(summed[i as usize], Gt::generator() * F::from(6 * i));
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn test_batched_openings_do_not_depend_on_chunk_sizes() {
+        let open = |config: BatchConfig| {
+            async_std::task::block_on(async move {
+                run_built_parties(
+                    3,
+                    |transport| Evaluator::new_with_batch_config(transport, config),
+                    |mut evaluator| async move {
+                        let xs = (0..30u64)
+                            .map(|i| evaluator.fixed_wire_handle(F::from(i)))
+                            .collect::<Vec<Wire>>();
+                        let opened = evaluator.batch_output_wire(&xs).await.unwrap();
+                        let in_exponent =
+                            evaluator.batch_output_wire_in_exponent(&xs).await.unwrap();
+
+                        let my_id = F::from(evaluator.messaging.get_my_id());
+                        let gts = (0..10u64)
+                            .map(|i| Gt::generator() * (my_id * F::from(i)))
+                            .collect::<Vec<Gt>>();
+                        let ids = (0..10)
+                            .map(|i| format!("gt_{}", i))
+                            .collect::<Vec<String>>();
+                        let summed = evaluator
+                            .batch_add_gt_elements_from_all_parties(&gts, &ids)
+                            .await
+                            .unwrap();
+                        (opened, in_exponent, summed)
+                    },
+                )
+                .await
+            })
+        };
+
+        let sized = |chunk: usize| BatchConfig {
+            scalar_chunk: chunk,
+            g1_chunk: chunk,
+            g2_chunk: chunk,
+            gt_chunk: chunk,
+            max_message_bytes: None,
+        };
+        let expected = open(BatchConfig::default());
+        for config in [
+            sized(1),
+            sized(7),
+            sized(10_000),
+            BatchConfig {
+                max_message_bytes: Some(200),
+                ..sized(10_000)
+            },
+        ] {
+            assert_eq!(open(config), expected);
+        }
+    }
+
+    #[test]
+    fn test_chunk_len_fits_max_message_bytes() {
+        let config = BatchConfig {
+            max_message_bytes: Some(1000),
+            ..BatchConfig::default()
+        };
+        // 33 byte scalars, 49 byte G1 elements, 577 byte Gt elements, tags included
+        assert_eq!(config.chunk_len(PayloadKind::Scalar), 30);
+        assert_eq!(config.chunk_len(PayloadKind::G1), 20);
+        assert_eq!(config.chunk_len(PayloadKind::Gt), 1);
+        assert_eq!(BatchConfig::default().chunk_len(PayloadKind::Gt), 64);
+    }
+
+    #[test]
+    fn test_committed_openings_match_plain_openings() {
+        let open = |mode| {
+            async_std::task::block_on(async move {
+                run_built_parties(
+                    3,
+                    |transport| Evaluator::new_with_open_mode(transport, mode),
+                    |mut evaluator| async move {
+                        assert_eq!(evaluator.open_mode(), mode);
+                        let xs = (0..20u64)
+                            .map(|i| evaluator.fixed_wire_handle(F::from(i)))
+                            .collect::<Vec<Wire>>();
+                        let ys = (0..20u64)
+                            .map(|i| evaluator.fixed_wire_handle(F::from(i + 3)))
+                            .collect::<Vec<Wire>>();
+                        let zs = evaluator.batch_mult(&xs, &ys).await.unwrap();
+                        let mut opened = evaluator.batch_output_wire(&zs).await.unwrap();
+                        opened.push(evaluator.output_wire(&xs[7]).await.unwrap());
+                        opened
+                    },
+                )
+                .await
+            })
+        };
+
+        let plain = open(OpenMode::Plain);
+        let committed = open(OpenMode::Committed);
+        let mut expected = (0..20u64).map(|i| F::from(i * (i + 3))).collect::<Vec<F>>();
+        expected.push(F::from(7u64));
+        for (plain, committed) in plain.into_iter().zip(committed) {
+            assert_eq!(plain, expected);
+            assert_eq!(committed, expected);
+        }
+    }
+
+    #[test]
+    fn test_committed_batch_opens_in_plain_mode() {
+        let outputs = async_std::task::block_on(run_built_parties(
+            3,
+            |transport| {
+                EvaluatorBuilder::new()
+                    .batch_config(BatchConfig {
+                        scalar_chunk: 8,
+                        ..BatchConfig::default()
+                    })
+                    .build(transport)
+            },
+            |mut evaluator| async move {
+                let xs = (0..20u64)
+                    .map(|i| evaluator.fixed_wire_handle(F::from(i * i)))
+                    .collect::<Vec<Wire>>();
+                let committed = evaluator.batch_output_wire_committed(&xs).await.unwrap();
+                // the evaluator keeps opening the plain way afterwards
+                assert_eq!(evaluator.open_mode(), OpenMode::Plain);
+                let plain = evaluator.output_wire(&xs[5]).await.unwrap();
+                (committed, plain)
+            },
+        ));
+
+        let expected = (0..20u64).map(|i| F::from(i * i)).collect::<Vec<F>>();
+        for (committed, plain) in outputs {
+            assert_eq!(committed, expected);
+            assert_eq!(plain, F::from(25u64));
+        }
+    }
+
+    #[test]
+    fn test_committed_opening_identifies_a_party_changing_its_share() {
+        async_std::task::block_on(async {
+            let mut transports = InMemoryTransport::new_network(3).await;
+            // party 3 does not run an evaluator, but speaks the protocol by hand
+            let mut cheater = transports.pop().unwrap();
+            let info =
+                PeerInfo::new(&ProtocolParams::default()).with_feature(FEATURE_COMMITTED_OPENS);
+            // the shared seed spares the cheater agreeing on pairwise keys
+            let (evaluators, handshake) = futures::join!(
+                try_join_all(transports.into_iter().map(|transport| {
+                    EvaluatorBuilder::new()
+                        .open_mode(OpenMode::Committed)
+                        .rand_source(RandSource::SharedSeed)
+                        .build(transport)
+                })),
+                cheater.handshake(&info)
+            );
+            let evaluators = evaluators.unwrap();
+            handshake.unwrap();
+            let handle = String::from("x");
+
+            let honest = join_all(evaluators.into_iter().map(|mut evaluator| {
+                let handle = handle.clone();
+                async move {
+                    evaluator
+                        .batch_add_g1_elements_from_all_parties(&[G1::generator()], &[handle])
+                        .await
+                }
+            }));
+            let cheat = async {
+                // the opening is the first reconstruction of the honest parties
+                cheater.set_round(1);
+                let salt = [9u8; 32];
+                let committed = G1::generator();
+                let commitment = commit_to_share(&encode_as_bytes(&committed), &salt);
+                cheater
+                    .send_to_all(&[format!("{}/commit", handle)], &[commitment])
+                    .await
+                    .unwrap();
+
+                // waits for the honest commitments, then opens something else
+                cheater
+                    .recv_from_all(&format!("{}/commit", handle))
+                    .await
+                    .unwrap();
+                let opened = committed + G1::generator();
+                let opening = format!(
+                    "{}.{}",
+                    encode_g1_as_bs58_str(&opened),
+                    bs58::encode(salt).into_string()
+                );
+                cheater
+                    .send_to_all(std::slice::from_ref(&handle), &[opening])
+                    .await
+                    .unwrap();
+            };
+            let (results, _) = futures::join!(honest, cheat);
+
+            let pp = crate::shuffler::compute_params();
+            for result in results {
+                match result {
+                    Err(EvaluatorError::Misbehavior { node_id, evidence }) => {
+                        assert_eq!(node_id, 3);
+                        assert_eq!(evidence.handle(), handle);
+                        assert!(evidence.verify(&pp));
+                    }
+                    other => panic!("expected misbehavior, got {:?}", other),
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn test_malformed_share_fails_the_opening() {
+        async_std::task::block_on(async {
+            let mut transports = InMemoryTransport::new_network(3).await;
+            // party 3 does not run an evaluator, but speaks the protocol by hand
+            let mut cheater = transports.pop().unwrap();
+            let info = PeerInfo::new(&ProtocolParams::default());
+            let (evaluators, handshake) = futures::join!(
+                try_join_all(transports.into_iter().map(|transport| {
+                    EvaluatorBuilder::new()
+                        .rand_source(RandSource::SharedSeed)
+                        .build(transport)
+                })),
+                cheater.handshake(&info)
+            );
+            let evaluators = evaluators.unwrap();
+            handshake.unwrap();
+            let handle = String::from("x");
+
+            let honest = join_all(evaluators.into_iter().map(|mut evaluator| {
+                let handle = handle.clone();
+                async move {
+                    evaluator
+                        .add_g1_elements_from_all_parties(&G1::generator(), &handle)
+                        .await
+                }
+            }));
+            let cheat = async {
+                cheater.set_round(1);
+                cheater
+                    .send_to_all(&[&handle], &[String::from("not a point")])
+                    .await
+                    .unwrap();
+            };
+            let (results, _) = futures::join!(honest, cheat);
+
+            let pp = crate::shuffler::compute_params();
+            for result in results {
+                match result {
+                    Err(EvaluatorError::Misbehavior { node_id, evidence }) => {
+                        assert_eq!(node_id, 3);
+                        assert!(matches!(*evidence, Evidence::MalformedShare { .. }));
+                        assert!(evidence.verify(&pp));
+                    }
+                    other => panic!("expected misbehavior, got {:?}", other),
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn test_committed_openings_over_several_chunks() {
+        async_std::task::block_on(async {
+            // 7 does not divide 30, so the last chunk is a short one
+            let config = BatchConfig {
+                scalar_chunk: 7,
+                g1_chunk: 7,
+                g2_chunk: 7,
+                gt_chunk: 7,
+                max_message_bytes: None,
+            };
+            let build = |transport| {
+                EvaluatorBuilder::new()
+                    .batch_config(config)
+                    .open_mode(OpenMode::Committed)
+                    .build(transport)
+            };
+            let opened = run_built_parties(3, build, |mut evaluator| async move {
+                // every party holds a share of a random value, so an opening
+                // checked against the commitment of another chunk cannot pass
+                let rs = (0..30).map(|_| evaluator.ran()).collect::<Vec<Wire>>();
+                let shifted = rs
+                    .iter()
+                    .enumerate()
+                    .map(|(i, r)| {
+                        let shift = evaluator.fixed_wire_handle(F::from(i as u64));
+                        evaluator.add(r, &shift)
+                    })
+                    .collect::<Vec<Wire>>();
+                let wires = [rs, shifted].concat();
+                let values = evaluator.batch_output_wire(&wires).await.unwrap();
+                let in_exponent = evaluator
+                    .batch_output_wire_in_exponent(&wires)
+                    .await
+                    .unwrap();
+                (values, in_exponent)
+            })
+            .await;
+
+            let (values, in_exponent) = &opened[0];
+            for (i, (r, shifted)) in values[..30].iter().zip(&values[30..]).enumerate() {
+                assert_eq!(*shifted - r, F::from(i as u64));
+            }
+            for (value, point) in values.iter().zip(in_exponent) {
+                assert_eq!(G1::generator() * value, *point);
+            }
+            for other in &opened[1..] {
+                assert_eq!(other, &opened[0]);
+            }
+        });
     }
 
-    // //on input wire [x], this outputs g^[x], and reconstructs and outputs g^x
-    pub async fn add_gt_elements_from_all_parties(
-        &mut self,
-        value: &Gt,
-        identifier: &String,
-    ) -> Gt {
-        self.messaging
-            .send_to_all([identifier.clone()], [encode_gt_as_bs58_str(value)])
+    #[test]
+    fn test_batch_mult_metrics() {
+        async_std::task::block_on(async {
+            let snapshots = run_built_parties(4, Evaluator::new, |mut evaluator| async move {
+                let xs = (0..64u64)
+                    .map(|i| evaluator.fixed_wire_handle(F::from(i)))
+                    .collect::<Vec<Wire>>();
+                let ys = (0..64u64)
+                    .map(|i| evaluator.fixed_wire_handle(F::from(i + 1)))
+                    .collect::<Vec<Wire>>();
+
+                let before = evaluator.metrics();
+                evaluator.begin_phase("mult");
+                evaluator.batch_mult(&xs, &ys).await.unwrap();
+                evaluator.end_phase("mult").unwrap();
+                (before, evaluator.metrics())
+            })
             .await;
 
-        let mut incoming_values: HashMap<u64, Gt> = self
-            .messaging
-            .recv_from_all(identifier)
-            .await
-            .into_iter()
-            .map(|(x, y)| (x, decode_bs58_str_as_gt(&y)))
-            .collect();
-        incoming_values.insert(self.messaging.get_my_id(), *value);
-
-        reconstruct_gt(&incoming_values)
+            for (before, after) in snapshots {
+                assert_eq!(after.rounds - before.rounds, 1);
+                assert_eq!(
+                    after.beaver_triples_consumed - before.beaver_triples_consumed,
+                    64
+                );
+                assert!(after.messages_sent > before.messages_sent);
+                assert!(after.phases.contains_key("mult"));
+                assert_eq!(after.phase_rounds["mult"], 1);
+                assert_eq!(after.phase_beaver_triples["mult"], 64);
+
+                let json: serde_json::Value = serde_json::from_str(&after.to_json()).unwrap();
+                assert_eq!(json["beaver_triples_consumed"], 64);
+            }
+        });
     }
 
-    pub async fn batch_add_gt_elements_from_all_parties(
-        &mut self,
-        inputs: &[Gt],
-        identifiers: &[String],
-    ) -> Vec<Gt> {
-        assert_eq!(inputs.len(), identifiers.len());
-
-        let len = inputs.len();
+    #[test]
+    fn test_mult_takes_one_round() {
+        async_std::task::block_on(async {
+            let outputs = run_built_parties(3, Evaluator::new, |mut evaluator| async move {
+                let x = evaluator.fixed_wire_handle(F::from(6u64));
+                let y = evaluator.fixed_wire_handle(F::from(7u64));
+
+                let before = evaluator.metrics();
+                let z = evaluator.mult(&x, &y).await.unwrap();
+                let after = evaluator.metrics();
+                (before, after, evaluator.output_wire(&z).await.unwrap())
+            })
+            .await;
 
-        let mut outputs = Vec::new();
+            for (before, after, z) in outputs {
+                assert_eq!(after.rounds - before.rounds, 1);
+                assert_eq!(
+                    after.beaver_triples_consumed - before.beaver_triples_consumed,
+                    1
+                );
+                assert_eq!(z, F::from(42u64));
+            }
+        });
+    }
 
-        let values = inputs
-            .iter()
-            .map(encode_gt_as_bs58_str)
-            .collect::<Vec<String>>();
+    #[test]
+    fn test_replayed_and_duplicated_values_are_dropped() {
+        // every value party 1 sends arrives twice, followed by a replay of the
+        // first value it sent to the same party
+        let first = Arc::new(Mutex::new(HashMap::new()));
+        let tamper: LinkTamper = Arc::new(move |sender, recipient, msg: &EvalNetMsg| {
+            let carries_values = matches!(
+                msg,
+                EvalNetMsg::PublishValue { .. }
+                    | EvalNetMsg::PublishBatchValue { .. }
+                    | EvalNetMsg::PublishBatchBinary { .. }
+                    | EvalNetMsg::DirectValue { .. }
+                    | EvalNetMsg::DirectBatchBinary { .. }
+            );
+            if sender != 1 || !carries_values {
+                return vec![msg.clone()];
+            }
+            let mut first = first.lock().unwrap();
+            let replay = first
+                .entry(recipient)
+                .or_insert_with(|| msg.clone())
+                .clone();
+            vec![msg.clone(), msg.clone(), replay]
+        });
+
+        async_std::task::block_on(async {
+            let transports = InMemoryTransport::new_tampered_network(4, tamper).await;
+            let evaluators = try_join_all(transports.into_iter().map(Evaluator::new))
+                .await
+                .unwrap();
 
-        if len > 64 {
-            let mut processed_len = 0;
+            let results = join_all(evaluators.into_iter().map(|mut evaluator| async move {
+                let mut z = evaluator.fixed_wire_handle(F::from(1u64));
+                let mut outputs = vec![];
+                for i in 2..6u64 {
+                    let x = evaluator.fixed_wire_handle(F::from(i));
+                    z = evaluator.mult(&z, &x).await.unwrap();
+                    outputs.push(evaluator.output_wire(&z).await.unwrap());
+                }
+                let my_id = evaluator.messaging.get_my_id();
+                (my_id, evaluator.round(), evaluator.metrics(), outputs)
+            }))
+            .await;
 
-            while processed_len < len {
-                let this_iter_len = std::cmp::min(len - processed_len, 64);
-                let handles_bucket =
-                    &identifiers[processed_len..processed_len + this_iter_len].to_vec();
-                let values_bucket = &values[processed_len..processed_len + this_iter_len].to_vec();
+            for (my_id, round, metrics, outputs) in results {
+                assert_eq!(outputs, [2u64, 6, 24, 120].map(F::from));
+                assert_eq!(round, 8);
+                assert_eq!(metrics.round_horizon, DEFAULT_ROUND_HORIZON);
+                assert_eq!(metrics.messages_beyond_horizon, 0);
+                if my_id != 1 {
+                    assert!(metrics.duplicates_dropped > 0);
+                    assert!(metrics.stale_messages > 0);
+                }
+            }
+        });
+    }
 
-                self.messaging
-                    .send_to_all(handles_bucket, values_bucket)
-                    .await;
+    #[test]
+    fn test_verified_triples_multiply() {
+        async_std::task::block_on(async {
+            let build = |transport| {
+                Evaluator::new_with_verified_triples(transport, ProtocolParams::default())
+            };
+            let outputs = run_built_parties(3, build, |mut evaluator| async move {
+                assert_eq!(evaluator.remaining().triples, evaluator.resources.triples);
+                let x = evaluator.fixed_wire_handle(F::from(6u64));
+                let y = evaluator.fixed_wire_handle(F::from(7u64));
+                let z = evaluator.mult(&x, &y).await.unwrap();
+                evaluator.output_wire(&z).await.unwrap()
+            })
+            .await;
 
-                processed_len += this_iter_len;
+            for z in outputs {
+                assert_eq!(z, F::from(42u64));
             }
-        } else {
-            self.messaging.send_to_all(identifiers, values).await;
-        }
-
-        for i in 0..inputs.len() {
-            let mut incoming_values: HashMap<u64, Gt> = self
-                .messaging
-                .recv_from_all(&identifiers[i])
-                .await
-                .into_iter()
-                .map(|(x, y)| (x, decode_bs58_str_as_gt(&y)))
-                .collect();
-            incoming_values.insert(self.messaging.get_my_id(), inputs[i]);
+        });
+    }
 
-            outputs.push(reconstruct_gt(&incoming_values));
-        }
+    #[test]
+    fn test_dealt_triples_multiply() {
+        async_std::task::block_on(async {
+            let build = |transport| Evaluator::new_with_dealer(transport, 1, true);
+            let outputs = run_built_parties(4, build, |mut evaluator| async move {
+                assert_eq!(evaluator.remaining().triples, evaluator.resources.triples);
+                let xs: Vec<Wire> = (1..=8u64)
+                    .map(|i| evaluator.fixed_wire_handle(F::from(i)))
+                    .collect();
+                let ys: Vec<Wire> = (1..=8u64)
+                    .map(|i| evaluator.fixed_wire_handle(F::from(i + 1)))
+                    .collect();
+                let zs = evaluator.batch_mult(&xs, &ys).await.unwrap();
+                evaluator.batch_output_wire(&zs).await.unwrap()
+            })
+            .await;
 
-        outputs
+            let expected: Vec<F> = (1..=8u64).map(|i| F::from(i * (i + 1))).collect();
+            for zs in outputs {
+                assert_eq!(zs, expected);
+            }
+        });
     }
 
-    // secret-shared MSM, where scalars are secret shares. Outputs MSM in the clear.
-    pub async fn exp_and_reveal_gt(
-        &mut self,
-        bases: Vec<Gt>,
-        exponent_handles: Vec<String>,
-        func_name: &String,
-    ) -> Gt {
-        let mut sum = Gt::zero();
+    #[test]
+    fn test_new_with_dealer_checks_the_dealer() {
+        async_std::task::block_on(async {
+            let mut transports = InMemoryTransport::new_network(3).await;
+            let result = Evaluator::new_with_dealer(transports.pop().unwrap(), 9, false).await;
+            assert_eq!(result.err(), Some(BuildError::UnknownDealer(9)));
+        });
+    }
 
-        // Compute \sum_i g_i^[x_i]
-        for (base, exponent_handle) in bases.iter().zip(exponent_handles.iter()) {
-            sum = sum.add(base.mul(self.get_wire(exponent_handle)));
-        }
+    #[test]
+    fn test_two_parties_take_their_triples_from_an_explicit_dealer() {
+        async_std::task::block_on(async {
+            let resources = ResourceEstimate {
+                triples: 8,
+                rand_sharings: 0,
+            };
+            let build = |transport| {
+                EvaluatorBuilder::new()
+                    .preprocessing(resources)
+                    .dealer(1)
+                    .build(transport)
+            };
+            let outputs = run_built_parties(2, build, |mut evaluator| async move {
+                let xs: Vec<Wire> = (1..=8u64)
+                    .map(|i| evaluator.fixed_wire_handle(F::from(i)))
+                    .collect();
+                let ys: Vec<Wire> = (1..=8u64)
+                    .map(|i| evaluator.fixed_wire_handle(F::from(i + 1)))
+                    .collect();
+                let zs = evaluator.batch_mult(&xs, &ys).await.unwrap();
+                evaluator.batch_output_wire(&zs).await.unwrap()
+            })
+            .await;
 
-        self.add_gt_elements_from_all_parties(&sum, func_name).await
+            let expected: Vec<F> = (1..=8u64).map(|i| F::from(i * (i + 1))).collect();
+            for zs in outputs {
+                assert_eq!(zs, expected);
+            }
+        });
     }
 
-    pub async fn batch_exp_and_reveal_gt(
-        &mut self,
-        bases: Vec<Vec<Gt>>,
-        exponent_handles: Vec<Vec<String>>,
-        identifiers: Vec<String>,
-    ) -> Vec<Gt> {
-        let len = bases.len();
-
-        assert!(len == exponent_handles.len() && len == identifiers.len());
+    #[test]
+    fn test_batch_mult_while_the_reservoir_refills() {
+        async_std::task::block_on(async {
+            let resources = ResourceEstimate {
+                triples: 4096,
+                rand_sharings: 0,
+            };
+            // only the triples of the shared seed are made in the background
+            let build = |transport| {
+                EvaluatorBuilder::new()
+                    .preprocessing(resources)
+                    .rand_source(RandSource::SharedSeed)
+                    .build(transport)
+            };
+            run_built_parties(3, build, |mut evaluator| async move {
+                let mut taken = HashSet::new();
+                for round in 0..32u64 {
+                    // the triples batch_mult is about to take
+                    evaluator.fill_triples(64);
+                    let next = evaluator.beaver_counter as usize;
+                    taken.extend(
+                        evaluator.beaver_triples[next..next + 64]
+                            .iter()
+                            .map(|t| t.0),
+                    );
+
+                    let xs: Vec<Wire> = (0..64u64)
+                        .map(|i| evaluator.fixed_wire_handle(F::from(round * 64 + i)))
+                        .collect();
+                    let ys: Vec<Wire> = (0..64u64)
+                        .map(|_| evaluator.fixed_wire_handle(F::from(3u64)))
+                        .collect();
+                    let zs = evaluator.batch_mult(&xs, &ys).await.unwrap();
+                    let expected: Vec<F> =
+                        (0..64u64).map(|i| F::from(3 * (round * 64 + i))).collect();
+                    assert_eq!(evaluator.batch_output_wire(&zs).await.unwrap(), expected);
+                }
 
-        let mut group_elements = vec![];
+                let status = evaluator.preprocessing_status();
+                assert_eq!(status.triples.taken, 2048);
+                assert_eq!(status.triples.ready + status.triples.pending, 2048);
+                // no triple was handed out twice, and none stays around once used
+                assert_eq!(taken.len(), 2048);
+                assert!(evaluator.beaver_triples[..2048]
+                    .iter()
+                    .all(|(a, b, c)| a.is_zero() && b.is_zero() && c.is_zero()));
+            })
+            .await;
+        });
+    }
 
-        for i in 0..len {
-            let msm_input = bases[i].iter().zip(exponent_handles[i].iter());
-            let mut sum = Gt::zero();
+    #[test]
+    fn test_abort_reaches_all_parties() {
+        async_std::task::block_on(async {
+            let timeout = Duration::from_secs(5);
+            let mut transports = InMemoryTransport::new_network(4).await;
+            for transport in transports.iter_mut() {
+                transport.set_recv_timeout(timeout);
+            }
+            let evaluators = try_join_all(transports.into_iter().map(Evaluator::new))
+                .await
+                .unwrap();
 
-            for (base, exponent_handle) in msm_input {
-                let exponent = self.get_wire(exponent_handle);
+            let start = Instant::now();
+            let results = join_all(evaluators.into_iter().enumerate().map(
+                |(i, mut evaluator)| async move {
+                    let xs = (0..16u64)
+                        .map(|i| evaluator.fixed_wire_handle(F::from(i)))
+                        .collect::<Vec<Wire>>();
+                    if i == 1 {
+                        evaluator.abort("bad input").await;
+                        None
+                    } else {
+                        Some(evaluator.batch_output_wire(&xs).await)
+                    }
+                },
+            ))
+            .await;
 
-                if exponent == F::from(1) {
-                    sum = sum.add(base);
-                } else {
-                    sum = sum.add(base.mul(self.get_wire(exponent_handle)));
-                }
+            assert!(start.elapsed() < timeout);
+            for result in results.into_iter().flatten() {
+                assert_eq!(
+                    result,
+                    Err(EvaluatorError::Aborted {
+                        by: 2,
+                        reason: "bad input".to_string()
+                    })
+                );
             }
+        });
+    }
 
-            group_elements.push(sum);
+    #[test]
+    fn test_rotate_committee_after_a_party_leaves() {
+        async fn play_hand(evaluator: &mut Evaluator<InMemoryTransport>) -> Vec<F> {
+            let xs = (0..8u64)
+                .map(|i| evaluator.fixed_wire_handle(F::from(i)))
+                .collect::<Vec<Wire>>();
+            let ys = (0..8u64)
+                .map(|i| evaluator.fixed_wire_handle(F::from(i + 2)))
+                .collect::<Vec<Wire>>();
+            let zs = evaluator.batch_mult(&xs, &ys).await.unwrap();
+            evaluator.batch_output_wire(&zs).await.unwrap()
         }
+        let expected = (0..8u64).map(|i| F::from(i * (i + 2))).collect::<Vec<F>>();
 
-        self.batch_add_gt_elements_from_all_parties(&group_elements, &identifiers)
-            .await
-    }
-
-    // secret-shared MSM, where scalars are secret shares. Outputs MSM in the clear.
-    pub async fn exp_and_reveal_g1(
-        &mut self,
-        bases: Vec<G1>,
-        exponent_handles: Vec<String>,
-        identifier: &String,
-    ) -> G1 {
-        let mut sum = G1::zero();
+        async_std::task::block_on(async {
+            let mut evaluators = build_parties(5, Evaluator::new).await;
 
-        // Compute \sum_i g_i^[x_i]
-        for (base, exponent_handle) in bases.iter().zip(exponent_handles.iter()) {
-            let my_share = self.get_wire(exponent_handle);
-            let exponentiated = (*base).mul(my_share);
+            let outputs = join_all(evaluators.iter_mut().map(play_hand)).await;
+            for output in outputs {
+                assert_eq!(output, expected);
+            }
 
-            sum = sum.add(exponentiated);
-        }
+            // party 5 leaves for good
+            evaluators.pop();
+            let rotated = join_all(evaluators.iter_mut().map(|evaluator| {
+                let new_book = parse_addr_book_from_json(4).unwrap();
+                evaluator.rotate_committee(new_book)
+            }))
+            .await;
+            for result in rotated {
+                result.unwrap();
+            }
 
-        self.add_g1_elements_from_all_parties(&sum, identifier)
-            .await
+            let outputs = join_all(evaluators.iter_mut().map(play_hand)).await;
+            for output in outputs {
+                assert_eq!(output, expected);
+            }
+        });
     }
 
-    pub async fn exp_and_reveal_g2(
-        &mut self,
-        bases: Vec<G2>,
-        exponent_handles: Vec<String>,
-        identifier: &String,
-    ) -> G2 {
-        let mut sum = G2::zero();
+    #[test]
+    fn test_rotate_committee_forgets_the_old_wires() {
+        async_std::task::block_on(async {
+            let mut evaluators = build_parties(4, |transport| {
+                EvaluatorBuilder::new().threshold(1).build(transport)
+            })
+            .await;
 
-        // Compute \sum_i g_i^[x_i]
-        for (base, exponent_handle) in bases.iter().zip(exponent_handles.iter()) {
-            let my_share = self.get_wire(exponent_handle);
-            let exponentiated = (*base).mul(my_share);
+            let handles = join_all(evaluators.iter_mut().map(|evaluator| async move {
+                let x = evaluator.fixed_wire_handle(F::from(5u64));
+                evaluator.begin_scope();
+                assert_eq!(evaluator.output_wire(&x).await.unwrap(), F::from(5u64));
+                x
+            }))
+            .await;
 
-            sum = sum.add(exponentiated);
-        }
+            evaluators.pop();
+            let rotated = join_all(evaluators.iter_mut().map(|evaluator| {
+                let new_book = parse_addr_book_from_json(3).unwrap();
+                evaluator.rotate_committee(new_book)
+            }))
+            .await;
+            for result in rotated {
+                result.unwrap();
+            }
 
-        self.add_g2_elements_from_all_parties(&sum, identifier)
-            .await
+            for (evaluator, x) in evaluators.iter().zip(handles) {
+                assert_eq!(evaluator.is_constant(&x), None);
+                assert!(evaluator.try_get_wire(&x).is_err());
+                assert!(evaluator.scopes.is_empty());
+                assert!(evaluator.pending_checks.is_empty());
+                assert!(evaluator.late_shares.is_empty());
+            }
+        });
     }
 
-    pub async fn batch_exp(&mut self, input_labels: &[String]) -> Vec<String> {
-        let mut tmp = input_labels.to_vec();
-        for _i in 0..LOG_PERM_SIZE {
-            tmp = self.batch_mult(&tmp, &tmp).await;
-        }
-
-        let mut output = Vec::new();
-        for i in 0..input_labels.len() {
-            let handle = self.compute_fresh_wire_label();
-            self.wire_shares
-                .insert(handle.clone(), self.get_wire(&tmp[i]));
-            output.push(handle);
-        }
-
-        output
+    #[test]
+    fn test_rotate_committee_refuses_to_lose_the_dealer() {
+        async_std::task::block_on(async {
+            let resources = ResourceEstimate {
+                triples: 4,
+                rand_sharings: 0,
+            };
+            let build = |transport| {
+                EvaluatorBuilder::new()
+                    .preprocessing(resources)
+                    .dealer(3)
+                    .build(transport)
+            };
+            let mut evaluators = build_parties(3, build).await;
+
+            // without the dealer, whoever became node 3 would deal silently
+            evaluators.pop();
+            let new_book = parse_addr_book_from_json(2).unwrap();
+            assert!(matches!(
+                evaluators[0].rotate_committee(new_book).await,
+                Err(EvaluatorError::DealerLeft { node_id: 3 })
+            ));
+        });
     }
 
-    pub async fn eval_proof_with_share_poly(
-        &mut self,
-        pp: &UniversalParams<Curve>,
-        share_poly: DensePolynomial<F>,
-        z: F,
-    ) -> G1 {
-        // Compute f_polynomial
-        let f_poly = share_poly;
-
-        let divisor = DensePolynomial::from_coefficients_vec(vec![-z, F::from(1)]);
+    #[test]
+    fn test_rotate_committee_refuses_during_a_receive() {
+        async_std::task::block_on(async {
+            let mut evaluators = build_parties(3, Evaluator::new).await;
+            let mut evaluator = evaluators.remove(0);
+
+            let x = evaluator.fixed_wire_handle(F::from(1u64));
+            // cancel the opening halfway, since the other party never takes part
+            let opening = evaluator.output_wire(&x);
+            let _ = async_std::future::timeout(Duration::from_millis(50), opening).await;
+
+            let new_book = parse_addr_book_from_json(3).unwrap();
+            assert!(matches!(
+                evaluator.rotate_committee(new_book).await,
+                Err(EvaluatorError::Network(NetworkError::ReceivePending(_)))
+            ));
+        });
+    }
 
-        // Divide by (X-z)
-        let (quotient, _remainder) =
-            DenseOrSparsePolynomial::divide_with_q_and_r(&(&f_poly).into(), &(&divisor).into())
-                .unwrap();
+    #[test]
+    fn test_threshold_output_with_two_parties_offline() {
+        async_std::task::block_on(async {
+            let mut evaluators =
+                build_parties(5, |transport| Evaluator::new_with_threshold(transport, 2)).await;
+
+            // parties 4 and 5 go offline once preprocessing is done
+            evaluators.truncate(3);
+
+            let outputs = join_all(evaluators.into_iter().map(|mut evaluator| async move {
+                let x = evaluator.fixed_wire_handle(F::from(7u64));
+                let r = evaluator.ran();
+                let xr = evaluator.mult(&x, &r).await.unwrap();
+                let opened = evaluator.batch_output_wire(&[r, xr]).await.unwrap();
+                (evaluator.output_wire(&x).await.unwrap(), opened)
+            }))
+            .await;
 
-        KZG::commit_g1(pp, &quotient).into()
+            for (x, opened) in outputs.iter() {
+                assert_eq!(*x, F::from(7u64));
+                assert_eq!(opened[1], F::from(7u64) * opened[0]);
+                assert_eq!(*opened, outputs[0].1);
+            }
+        });
     }
 
-    pub async fn batch_eval_proof_with_share_poly(
-        &mut self,
-        pp: &UniversalParams<Curve>,
-        share_polys: &Vec<DensePolynomial<F>>,
-        z_s: &Vec<F>,
-    ) -> Vec<G1> {
-        let len = share_polys.len();
-        // assert_eq!(len, f_names.len());
+    #[test]
+    fn test_packed_output_broadcasts_less() {
+        async_std::task::block_on(async {
+            // just the random sharings behind the wires, rather than the triples of
+            // a full shuffle made jointly by sixteen parties
+            let resources = ResourceEstimate {
+                triples: 0,
+                rand_sharings: 64,
+            };
+            let build = |transport| {
+                EvaluatorBuilder::new()
+                    .preprocessing(resources)
+                    .build(transport)
+            };
+            let evaluators = build_parties(16, build).await;
 
-        let mut pi_share_vec = Vec::new();
-        for i in 0..len {
-            // Compute f_polynomial
-            let f_poly = share_polys[i].clone();
+            let broadcast_bytes = |evaluator: &Evaluator<InMemoryTransport>| {
+                let sent = evaluator.metrics().bytes_sent;
+                ["value", "batch_value", "batch_binary"]
+                    .iter()
+                    .map(|kind| sent[*kind])
+                    .sum::<u64>()
+            };
 
-            let divisor = DensePolynomial::from_coefficients_vec(vec![-z_s[i], F::from(1)]);
+            let outputs = join_all(evaluators.into_iter().map(|mut evaluator| async move {
+                let xs: Vec<Wire> = (0..64).map(|_| evaluator.ran()).collect();
 
-            // Divide by (X-z_i)
-            let (quotient, _remainder) =
-                DenseOrSparsePolynomial::divide_with_q_and_r(&(&f_poly).into(), &(&divisor).into())
+                let before = broadcast_bytes(&evaluator);
+                let unpacked = evaluator.batch_output_wire(&xs).await.unwrap();
+                let unpacked_bytes = broadcast_bytes(&evaluator) - before;
+
+                let partial = evaluator
+                    .batch_output_wire_packed(&xs[..5], 8)
+                    .await
                     .unwrap();
 
-            let pi_poly = KZG::commit_g1(pp, &quotient);
-            pi_share_vec.push(pi_poly.into());
-        }
+                let before = broadcast_bytes(&evaluator);
+                let packed = evaluator.batch_output_wire_packed(&xs, 8).await.unwrap();
+                let packed_bytes = broadcast_bytes(&evaluator) - before;
+
+                (unpacked, partial, packed, unpacked_bytes, packed_bytes)
+            }))
+            .await;
 
-        pi_share_vec
+            for (unpacked, partial, packed, unpacked_bytes, packed_bytes) in outputs {
+                assert_eq!(packed, unpacked);
+                assert_eq!(partial, unpacked[..5].to_vec());
+                assert!(packed_bytes * 4 < unpacked_bytes);
+            }
+        });
     }
 
-    pub async fn dist_ibe_encrypt(
-        &mut self,
-        msg_share_handle: &String,  // [z1]
-        mask_share_handle: &String, // [r]
-        pk: &G2,
-        id: Vec<u8>,
-    ) -> (G1, Gt) {
-        let hash_id = hash_to_g1(&id);
+    #[test]
+    fn test_refresh_wire_keeps_value_and_changes_shares() {
+        async_std::task::block_on(async {
+            let outputs = run_built_parties(3, Evaluator::new, |mut evaluator| async move {
+                let zeros = evaluator.batch_zero_share(4).await.unwrap();
+                let opened_zeros = evaluator.batch_output_wire(&zeros).await.unwrap();
 
-        let h = <Curve as Pairing>::pairing(hash_id, pk);
+                let x = evaluator.ran();
+                let y = evaluator.refresh_wire(&x).await.unwrap();
+                let shares = (evaluator.get_wire(&x), evaluator.get_wire(&y));
+                let opened = evaluator.batch_output_wire(&[x, y]).await.unwrap();
 
-        let c1 = self
-            .exp_and_reveal_g1(
-                vec![G1::generator()],
-                vec![mask_share_handle.clone()],
-                &("ibe_c1_".to_owned() + msg_share_handle + mask_share_handle),
-            )
+                (opened_zeros, shares, opened)
+            })
             .await;
 
-        let c2 = self
-            .exp_and_reveal_gt(
-                vec![Gt::generator(), h],
-                vec![msg_share_handle.clone(), mask_share_handle.clone()],
-                &("ibe_c2".to_owned() + msg_share_handle + mask_share_handle),
+            for (opened_zeros, (old_share, new_share), opened) in outputs {
+                assert!(opened_zeros.iter().all(|z| z.is_zero()));
+                assert_ne!(old_share, new_share);
+                assert_eq!(opened[0], opened[1]);
+            }
+        });
+    }
+
+    #[test]
+    fn test_malformed_zero_share_fails_the_refresh() {
+        async_std::task::block_on(async {
+            let transports = InMemoryTransport::new_network(3).await;
+            let mut evaluators = try_join_all(
+                transports
+                    .into_iter()
+                    .map(|transport| EvaluatorBuilder::new().build(transport)),
             )
+            .await
+            .unwrap();
+            // party 3 deals the others something that is not a masked share
+            let mut cheater = evaluators.pop().unwrap();
+
+            let honest = join_all(evaluators.into_iter().map(|mut evaluator| async move {
+                let x = evaluator.fixed_wire_handle(F::from(3u64));
+                evaluator.refresh_wire(&x).await
+            }));
+            let cheat = async {
+                cheater.fixed_wire_handle(F::from(3u64));
+                let label = cheater.compute_fresh_wire_label().to_string();
+                cheater.ensure_pairwise().await.unwrap();
+                for recipient in [1, 2] {
+                    cheater
+                        .messaging
+                        .send_to_party(recipient, &[&label], &[String::from("garbage")])
+                        .await
+                        .unwrap();
+                }
+            };
+            let (results, _) = futures::join!(honest, cheat);
+
+            for result in results {
+                match result {
+                    Err(EvaluatorError::MalformedDealing { node_id, .. }) => {
+                        assert_eq!(node_id, 3)
+                    }
+                    other => panic!("expected a malformed dealing, got {:?}", other),
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn test_scopes_free_what_is_not_kept() {
+        async_std::task::block_on(async {
+            let outputs = run_built_parties(3, Evaluator::new, |mut evaluator| async move {
+                let x = evaluator.fixed_wire_handle(F::from(3u64));
+                let before = evaluator.wire_count();
+
+                let y = evaluator.with_scope(|scope| {
+                    let doubled = scope.add(&x, &x);
+                    let y = scope.scale(&doubled, F::from(5u64));
+                    scope.keep(&y);
+                    y
+                });
+                assert_eq!(evaluator.wire_count(), before + 1);
+
+                evaluator.begin_scope();
+                let z = evaluator.mult(&x, &y).await.unwrap();
+                evaluator.keep(&z);
+                evaluator.ran();
+                evaluator.end_scope();
+                assert_eq!(evaluator.wire_count(), before + 2);
+
+                let bytes = evaluator.memory_bytes();
+                evaluator.free_wires(&[x, y]);
+                assert_eq!(evaluator.wire_count(), before);
+                assert!(evaluator.memory_bytes() < bytes);
+
+                evaluator.output_wire(&z).await.unwrap()
+            })
             .await;
 
-        (c1, c2)
+            for z in outputs {
+                assert_eq!(z, F::from(90u64));
+            }
+        });
     }
 
-    /// Same as dist_batch_ibe_encrypt, but with common mask
-    pub async fn batch_dist_ibe_encrypt_with_common_mask(
-        &mut self,
-        msg_share_handles: &[String], // [z1]
-        mask_share_handle: &String,   // [r]
-        pk: &G2,
-        ids: &[Vec<u8>],
-    ) -> (G2, Vec<Gt>) {
-        // Compute e_i^r
-        let e_is = ids
-            .iter()
-            .map(|id| {
-                let hash_id_pow_r = hash_to_g1(&id.as_ref()) * self.get_wire(&mask_share_handle);
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "already set")]
+    fn test_setting_a_wire_twice_panics() {
+        async_std::task::block_on(async {
+            let mut evaluators = build_parties(3, Evaluator::new).await;
+            let evaluator = &mut evaluators[0];
+            let x = evaluator.fixed_wire_handle(F::from(1u64));
+            evaluator.insert_share(&x, F::from(2u64));
+        });
+    }
 
-                <Curve as Pairing>::pairing(hash_id_pow_r, pk)
+    #[test]
+    fn test_misused_gates_are_errors() {
+        async_std::task::block_on(async {
+            run_built_parties(3, Evaluator::new, |mut evaluator| async move {
+                let x = evaluator.fixed_wire_handle(F::from(3u64));
+                evaluator.free_wire(&x);
+                assert_eq!(
+                    evaluator.output_wire(&x).await,
+                    Err(EvaluatorError::UnknownWire(x.clone()))
+                );
+
+                let y = evaluator.fixed_wire_handle(F::from(4u64));
+                assert_eq!(
+                    evaluator.batch_mult(&[y.clone(), y.clone()], &[y]).await,
+                    Err(EvaluatorError::LengthMismatch {
+                        expected: 2,
+                        actual: 1
+                    })
+                );
+
+                let n = 2 * evaluator.params().perm_size();
+                let poly = DensePolynomial::from_coefficients_vec(vec![F::from(1u64); n]);
+                assert_eq!(
+                    evaluator.share_poly_mult_evals(&poly, &poly).await,
+                    Err(EvaluatorError::DegreeTooLarge {
+                        coeffs: 2 * n,
+                        max: n + 1
+                    })
+                );
+
+                let config = BatchConfig {
+                    scalar_chunk: 0,
+                    ..BatchConfig::default()
+                };
+                assert_eq!(
+                    evaluator.set_batch_config(config),
+                    Err(BuildError::EmptyChunk)
+                );
             })
-            .collect::<Vec<Gt>>();
-
-        let c1 = self
-            .exp_and_reveal_g2(
-                vec![G2::generator()],
-                vec![mask_share_handle.clone()],
-                &("ibe_c1_".to_owned() + mask_share_handle),
-            )
             .await;
+        });
+    }
 
-        // Vector of 64 elements, where the i^th element is a vector [g, e_i^r]
-        let gt_with_e_is = (0..msg_share_handles.len())
-            .map(|i| vec![Gt::generator(), e_is[i]])
-            .collect::<Vec<Vec<Gt>>>();
+    #[test]
+    fn test_batch_eval_proofs_match_single_proofs() {
+        async_std::task::block_on(async {
+            let mut evaluators = build_parties(3, Evaluator::new).await;
+            let evaluator = &mut evaluators[0];
 
-        // Vector of 64 elements, where the i^th element is a vector [msg_i, 1]
-        let one_wire_handle = self.compute_fresh_wire_label();
-        self.wire_shares.insert(one_wire_handle.clone(), F::one());
+            let mut rng = StdRng::from_seed([3u8; 32]);
+            let pp = KZG::setup(2 * PERM_SIZE, &mut rng);
+            let polys: Vec<DensePolynomial<F>> = (0..5)
+                .map(|_| DensePolynomial::rand(2 * PERM_SIZE - 1, &mut rng))
+                .collect();
+            let z_s: Vec<F> = (0..5).map(|_| F::rand(&mut rng)).collect();
 
-        let msg_mask_interleaved = msg_share_handles
-            .iter()
-            .map(|m| vec![m.clone(), one_wire_handle.clone()])
-            .collect::<Vec<Vec<String>>>();
+            let batch = evaluator
+                .batch_eval_proof_with_share_poly(&pp, &polys, &z_s)
+                .await
+                .unwrap();
+            for i in 0..5 {
+                let single = evaluator
+                    .eval_proof_with_share_poly(&pp, polys[i].clone(), z_s[i])
+                    .await
+                    .unwrap();
+                assert_eq!(batch[i], single);
+            }
 
-        let c2s = self
-            .batch_exp_and_reveal_gt(
-                gt_with_e_is,
-                msg_mask_interleaved,
-                msg_share_handles
-                    .iter()
-                    .map(|h| ("ibe_c2".to_owned() + h))
-                    .collect::<Vec<String>>(),
+            let at_z0 = evaluator
+                .batch_eval_proof_at_single_point(&pp, &polys, z_s[0])
+                .unwrap();
+            for (poly, pi) in polys.iter().zip(at_z0) {
+                let single = evaluator
+                    .eval_proof_with_share_poly(&pp, poly.clone(), z_s[0])
+                    .await
+                    .unwrap();
+                assert_eq!(pi, single);
+            }
+        });
+    }
+
+    #[test]
+    fn test_eval_proofs_of_edge_cases() {
+        async_std::task::block_on(async {
+            let mut evaluators = build_parties(3, Evaluator::new).await;
+            let evaluator = &mut evaluators[0];
+
+            let mut rng = StdRng::from_seed([8u8; 32]);
+            let pp = KZG::setup(PERM_SIZE, &mut rng);
+            let verifies = |poly: &DensePolynomial<F>, z: F, pi: G1| {
+                let com = KZG::commit_g1(&pp, poly).unwrap();
+                KZG::verify_opening_proof(&pp, &com, &z, &poly.evaluate(&z), &pi.into())
+            };
+
+            // a constant opens everywhere with the trivial proof
+            let constant = DensePolynomial::from_coefficients_vec(vec![F::from(5u64)]);
+            let z = F::rand(&mut rng);
+            let pi = evaluator
+                .eval_proof_with_share_poly(&pp, constant.clone(), z)
+                .await
+                .unwrap();
+            assert!(pi.is_zero());
+            assert!(verifies(&constant, z, pi));
+
+            // the proof at a root of unity is the one of the divided polynomial,
+            // even though the vanishing polynomial is zero there
+            let poly = DensePolynomial::<F>::rand(PERM_SIZE - 1, &mut rng);
+            let root = utils::domain_info(PERM_SIZE).powers[3];
+            let pi = evaluator
+                .eval_proof_with_share_poly(&pp, poly.clone(), root)
+                .await
+                .unwrap();
+            assert!(verifies(&poly, root, pi));
+
+            // a quotient beyond the params is an error rather than a panic
+            let too_large = DensePolynomial::<F>::rand(PERM_SIZE + 2, &mut rng);
+            assert_eq!(
+                evaluator
+                    .batch_eval_proof_at_single_point(&pp, &[poly, too_large], root)
+                    .err(),
+                Some(KzgError::TooFewPowers {
+                    needed: PERM_SIZE + 2,
+                    available: PERM_SIZE + 1
+                })
+            );
+        });
+    }
+
+    #[test]
+    fn test_shared_poly_commitments_match_the_clear_ones() {
+        let pp = KZG::setup(2 * PERM_SIZE, &mut StdRng::from_seed([6u8; 32]));
+        let share_polys = |i: u8| {
+            let mut rng = StdRng::from_seed([i + 10; 32]);
+            (0..3)
+                .map(|_| DensePolynomial::<F>::rand(PERM_SIZE - 1, &mut rng))
+                .collect::<Vec<_>>()
+        };
+        let z = F::from(17u64);
+
+        async_std::task::block_on(async {
+            let evaluators = build_parties(3, Evaluator::new).await;
+
+            let outputs = join_all(
+                evaluators
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, mut evaluator)| {
+                        let pp = pp.clone();
+                        async move {
+                            let polys = share_polys(i as u8);
+                            let single = evaluator
+                                .commit_shared_poly(&pp, &polys[0], "poly")
+                                .await
+                                .unwrap();
+                            // the same label twice must not mix up the openings
+                            let batch = evaluator
+                                .batch_commit_shared_polys(&pp, &polys, "poly")
+                                .await
+                                .unwrap();
+                            let opening = evaluator
+                                .open_shared_poly_at(&pp, &polys[1], z, "poly")
+                                .await
+                                .unwrap();
+                            (single, batch, opening)
+                        }
+                    }),
             )
             .await;
 
-        (c1, c2s)
+            let clear: Vec<DensePolynomial<F>> = (0..3)
+                .map(|j| {
+                    (0..3).fold(DensePolynomial::zero(), |acc, i| {
+                        &acc + &share_polys(i as u8)[j]
+                    })
+                })
+                .collect();
+            let coms: Vec<G1> = clear
+                .iter()
+                .map(|poly| KZG::commit_g1(&pp, poly).unwrap().into())
+                .collect();
+            for (single, batch, (value, pi)) in outputs {
+                assert_eq!(single, coms[0]);
+                assert_eq!(batch, coms);
+                assert_eq!(value, clear[1].evaluate(&z));
+                assert!(KZG::verify_opening_proof(
+                    &pp,
+                    &coms[1].into(),
+                    &z,
+                    &value,
+                    &pi.into()
+                ));
+            }
+        });
     }
 
-    async fn preprocess_rand_sharings(&mut self, num_sharings: usize) {
-        let n: u64 = self.messaging.addr_book.len() as u64;
-        let index = (self.messaging.get_my_id() - 1) as usize;
+    #[test]
+    fn test_share_poly_mult() {
+        async_std::task::block_on(async {
+            let evaluators = build_parties(3, Evaluator::new).await;
 
-        let mut rng = rand_chacha::ChaCha8Rng::from_seed([1u8; 32]);
+            let share_polys = |i: u8| {
+                let mut rng = StdRng::from_seed([i; 32]);
+                let f = DensePolynomial::<F>::rand(PERM_SIZE - 1, &mut rng);
+                let g = DensePolynomial::<F>::rand(PERM_SIZE - 1, &mut rng);
+                (f, g)
+            };
 
-        for _i in 0..num_sharings {
-            let secret = F::rand(&mut rng);
-            let shares = crate::shamir::share(&secret, (n, n), &mut rng);
-            self.rand_sharings.push(shares[index].1);
-        }
+            let h_shares = join_all(evaluators.into_iter().enumerate().map(
+                |(i, mut evaluator)| async move {
+                    let (f, g) = share_polys(i as u8);
+                    evaluator.share_poly_mult(f, g).await.unwrap()
+                },
+            ))
+            .await;
+
+            let (mut f, mut g) = (DensePolynomial::zero(), DensePolynomial::zero());
+            for i in 0..3 {
+                let (f_i, g_i) = share_polys(i);
+                f = &f + &f_i;
+                g = &g + &g_i;
+            }
+            let h = h_shares
+                .iter()
+                .fold(DensePolynomial::zero(), |acc, h_i| &acc + h_i);
+            assert_eq!(h, &f * &g);
+        });
     }
 
-    async fn _preprocess_triples(&mut self, num_beavers: usize) {
-        let n: u64 = self.messaging.addr_book.len() as u64;
-        let index = (self.messaging.get_my_id() - 1) as usize;
+    #[test]
+    fn test_fft_evals_match_pointwise_evals() {
+        let mut rng = StdRng::from_seed([5u8; 32]);
+        let poly = DensePolynomial::<F>::rand(PERM_SIZE - 1, &mut rng);
 
-        let mut rng = rand_chacha::ChaCha8Rng::from_seed([1u8; 32]);
+        let alpha = utils::multiplicative_subgroup_of_size(2 * PERM_SIZE as u64);
+        let pointwise: Vec<F> = (0..2 * PERM_SIZE)
+            .map(|i| eval_share_poly(&poly, utils::compute_power(&alpha, i as u64)))
+            .collect();
 
-        for _i in 0..num_beavers {
-            let a = F::rand(&mut rng);
-            let b = F::rand(&mut rng);
-            let c = a * b;
+        assert_eq!(share_poly_evals_on_domain(&poly, 2 * PERM_SIZE), pointwise);
+    }
+
+    #[test]
+    fn test_share_poly_eval_many() {
+        async_std::task::block_on(async {
+            let mut evaluators = build_parties(3, Evaluator::new).await;
+            let evaluator = &mut evaluators[0];
+
+            let mut rng = StdRng::from_seed([9u8; 32]);
+            let poly = DensePolynomial::<F>::rand(2 * PERM_SIZE - 1, &mut rng);
+            let points: Vec<F> = (0..16).map(|_| F::rand(&mut rng)).collect();
+
+            let handles = evaluator.share_poly_eval_many(&poly, &points);
+            for (x, handle) in points.iter().zip(handles.iter()) {
+                let single = evaluator.share_poly_eval(&poly, *x);
+                assert_eq!(evaluator.get_wire(handle), poly.evaluate(x));
+                assert_eq!(evaluator.get_wire(handle), evaluator.get_wire(&single));
+                assert_eq!(
+                    evaluator.share_poly_eval_no_wire(&poly, *x),
+                    poly.evaluate(x)
+                );
+            }
+        });
+    }
 
-            let s_a = shamir::share(&a, (n, n), &mut rng)[index].1;
-            let s_b = shamir::share(&b, (n, n), &mut rng)[index].1;
-            let s_c = shamir::share(&c, (n, n), &mut rng)[index].1;
+    #[test]
+    fn test_batch_inv_reports_zero() {
+        async_std::task::block_on(async {
+            let results = run_built_parties(3, Evaluator::new, |mut evaluator| async move {
+                let xs = vec![
+                    evaluator.ran(),
+                    evaluator.fixed_wire_handle(F::zero()),
+                    evaluator.ran(),
+                ];
+                evaluator.batch_inv(&xs).await
+            })
+            .await;
 
-            self.beaver_triples.push((s_a, s_b, s_c));
-        }
+            for result in results {
+                assert!(matches!(
+                    result,
+                    Err(EvaluatorError::NotInvertible { index: 1 })
+                ));
+            }
+        });
     }
 
-    async fn preprocess_triples(&mut self, num_beavers: usize) {
-        let n: usize = self.messaging.addr_book.len();
-        let my_id = self.messaging.get_my_id();
+    #[test]
+    fn test_batch_ran_64_parties_agree() {
+        async_std::task::block_on(async {
+            let outputs = run_built_parties(3, Evaluator::new, |mut evaluator| async move {
+                let cs = evaluator.batch_ran_64(8).await.unwrap();
+                evaluator.batch_output_wire(&cs).await.unwrap()
+            })
+            .await;
 
-        let mut seeded_rng = StdRng::from_seed([42u8; 32]);
+            assert_eq!(outputs[0], outputs[1]);
+            for c in outputs[0].iter() {
+                assert_eq!(utils::compute_power(c, PERM_SIZE as u64), F::from(1u64));
+            }
+        });
+    }
 
-        let mut sum_a = vec![F::from(0); num_beavers];
-        let mut sum_b = vec![F::from(0); num_beavers];
-        let mut sum_c = vec![F::from(0); num_beavers];
+    #[test]
+    fn test_ibe_with_g1_public_key_decrypts() {
+        async_std::task::block_on(async {
+            let evaluators = build_parties(3, Evaluator::new).await;
+
+            let msk = F::from(11u64);
+            let pk = G1::generator() * msk;
+            let ctx = GameContext::new([3u8; 32]);
+            let id = card_id(&ctx, 7, 0);
+
+            let outputs = join_all(evaluators.into_iter().map(|mut evaluator| {
+                let id = id.clone();
+                async move {
+                    let msg = evaluator.fixed_wire_handle(F::from(5u64));
+                    let mask = evaluator.ran();
+                    evaluator
+                        .dist_ibe_encrypt_g1pk(&ctx, &msg, &mask, &pk, id)
+                        .await
+                        .unwrap()
+                }
+            }))
+            .await;
 
-        for i in 0..num_beavers {
-            let a = F::rand(&mut thread_rng());
-            let b = F::rand(&mut thread_rng());
+            let dec_key = hash_to_g2_ctx(&ctx, &id) * msk;
+            for (c1, c2) in outputs {
+                let mask = <Curve as Pairing>::pairing(c1, dec_key);
+                assert_eq!(c2 - mask, Gt::generator() * F::from(5u64));
+            }
+        });
+    }
 
-            for j in 1..n {
-                let party_j_share_a = F::rand(&mut seeded_rng);
-                let party_j_share_b = F::rand(&mut seeded_rng);
-                let party_j_share_c = F::rand(&mut seeded_rng);
+    #[test]
+    fn test_common_mask_ciphertexts_match_the_clear_computation() {
+        let ctx = GameContext::new([4u8; 32]);
+        let pk = G2::generator() * F::from(11u64);
+        let (msgs, r) = ([5u64, 1, 0, 9], F::from(23u64));
+        let ids: Vec<Vec<u8>> = (0..msgs.len() as u64)
+            .map(|i| card_id(&ctx, i, 0))
+            .collect();
 
-                sum_a[i] += party_j_share_a;
-                sum_b[i] += party_j_share_b;
-                sum_c[i] += party_j_share_c;
+        let expected_c2s: Vec<Gt> = msgs
+            .iter()
+            .zip(&ids)
+            .map(|(m, id)| {
+                Gt::generator() * F::from(*m)
+                    + <Curve as Pairing>::pairing(hash_to_g1_ctx(&ctx, id), pk) * r
+            })
+            .collect();
 
-                if j == (my_id as usize) {
-                    self.beaver_triples
-                        .push((party_j_share_a, party_j_share_b, party_j_share_c));
-                }
-            }
+        for threshold in [None, Some(1)] {
+            let outputs = async_std::task::block_on(async {
+                let build = |transport| {
+                    let builder = EvaluatorBuilder::new();
+                    match threshold {
+                        Some(t) => builder.threshold(t),
+                        None => builder,
+                    }
+                    .build(transport)
+                };
+                run_built_parties(3, build, |mut evaluator| {
+                    let ids = ids.clone();
+                    async move {
+                        let cards: Vec<Wire> = msgs
+                            .iter()
+                            .map(|m| evaluator.fixed_wire_handle(F::from(*m)))
+                            .collect();
+                        let mask = evaluator.fixed_wire_handle(r);
+                        // the second encryption takes the pairings from the cache
+                        let mut cache = IdCache::new(ctx, ids.len());
+                        let mut ctxts = Vec::new();
+                        for _ in 0..2 {
+                            let ctxt = evaluator
+                                .batch_dist_ibe_encrypt_with_common_mask(
+                                    &mut cache, &cards, &mask, &pk, &ids,
+                                )
+                                .await
+                                .unwrap();
+                            ctxts.push(ctxt);
+                        }
+                        ctxts
+                    }
+                })
+                .await
+            });
 
-            if n == (my_id as usize) {
-                self.beaver_triples
-                    .push((a - sum_a[i], b - sum_b[i], a * b - sum_c[i]));
+            for (c1, c2s) in outputs.into_iter().flatten() {
+                assert_eq!(c1, G2::generator() * r);
+                assert_eq!(c2s, expected_c2s);
             }
         }
     }
-}
-
-fn reconstruct_scalar(shares: &HashMap<u64, F>) -> F {
-    shares.values().fold(F::from(0), |acc, share| acc + share)
-}
-
-fn reconstruct_g1(shares: &HashMap<u64, G1>) -> G1 {
-    shares.values().fold(G1::zero(), |acc, share| acc + share)
-}
 
-fn reconstruct_g2(shares: &HashMap<u64, G2>) -> G2 {
-    shares.values().fold(G2::zero(), |acc, share| acc + share)
-}
+    #[test]
+    fn test_ibe_ciphertexts_are_bound_to_the_game() {
+        async_std::task::block_on(async {
+            let evaluators = build_parties(3, Evaluator::new).await;
+
+            let (game_a, game_b) = (GameContext::new([1u8; 32]), GameContext::new([2u8; 32]));
+            let pk = G2::generator() * F::from(11u64);
+
+            let outputs = join_all(evaluators.into_iter().map(|mut evaluator| async move {
+                let msg = evaluator.fixed_wire_handle(F::from(5u64));
+                let mask = evaluator.ran();
+                let mut ctxts = Vec::new();
+                for ctx in [game_a, game_b] {
+                    let id = card_id(&ctx, 17, 0);
+                    let ctxt = evaluator
+                        .dist_ibe_encrypt(&mut IdCache::new(ctx, 1), &msg, &mask, &pk, id)
+                        .await
+                        .unwrap();
+                    ctxts.push(ctxt);
+                }
+                ctxts
+            }))
+            .await;
 
-fn reconstruct_gt(shares: &HashMap<u64, Gt>) -> Gt {
-    shares.values().fold(Gt::zero(), |acc, share| acc + share)
+            for ctxts in outputs {
+                // same mask, so the same c1, but card 17 of each game gets its own c2
+                assert_eq!(ctxts[0].0, ctxts[1].0);
+                assert_ne!(ctxts[0].1, ctxts[1].1);
+            }
+        });
+    }
 }
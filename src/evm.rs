@@ -0,0 +1,298 @@
+//! Calldata for verifying proofs in an Ethereum contract. The alt_bn128
+//! precompiles (EIP-196 and EIP-197) take points as their uncompressed affine
+//! coordinates, each one 32-byte big-endian word, with zeros for the point at
+//! infinity and the coordinates of G2 points over Fq2 as (c1, c0). Built with
+//! the bn254 feature as the default curve, proofs come out in exactly that form.
+
+use ark_ec::short_weierstrass::{Projective, SWCurveConfig};
+use ark_ec::CurveGroup;
+use ark_ff::{BigInteger, Field, PrimeField, Zero};
+use ark_serialize::CanonicalSerialize;
+
+use crate::common::{
+    EncryptionProof, Gt, PermutationProof, PermutationProofV2, SigmaProof, F, G1, G2,
+};
+use crate::transcript::FsHasher;
+
+/// size of an EVM word
+pub const WORD: usize = 32;
+
+/// appends x as big-endian words, the coefficients of an extension field element
+/// highest first. A prime field element wider than a word, as on the BLS12
+/// curves, takes as many words as it needs and is left-padded with zeros.
+pub fn push_field<Fe: Field>(out: &mut Vec<u8>, x: &Fe) {
+    let coeffs: Vec<Fe::BasePrimeField> = x.to_base_prime_field_elements().collect();
+    for c in coeffs.iter().rev() {
+        let bytes = c.into_bigint().to_bytes_be();
+        let padded = bytes.len().div_ceil(WORD) * WORD;
+        out.resize(out.len() + padded - bytes.len(), 0);
+        out.extend_from_slice(&bytes);
+    }
+}
+
+/// appends the affine coordinates of p, or zeros for the point at infinity
+pub fn push_point<P: SWCurveConfig>(out: &mut Vec<u8>, p: &Projective<P>) {
+    let p = p.into_affine();
+    if p.infinity {
+        push_field(out, &P::BaseField::zero());
+        push_field(out, &P::BaseField::zero());
+    } else {
+        push_field(out, &p.x);
+        push_field(out, &p.y);
+    }
+}
+
+/// appends n as one word
+pub fn push_u64(out: &mut Vec<u8>, n: u64) {
+    out.extend_from_slice(&[0u8; WORD - 8]);
+    out.extend_from_slice(&n.to_be_bytes());
+}
+
+/// appends the length of bytes as a word, then bytes right-padded to whole words
+fn push_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    push_u64(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+    out.resize(out.len() + (WORD - bytes.len() % WORD) % WORD, 0);
+}
+
+/// EvmEncoding lays values out as calldata for a verifier contract: fields in
+/// declaration order, each in whole words
+pub trait EvmEncoding {
+    fn write_evm(&self, out: &mut Vec<u8>);
+
+    fn to_evm_calldata(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        self.write_evm(&mut out);
+        out
+    }
+}
+
+impl EvmEncoding for F {
+    fn write_evm(&self, out: &mut Vec<u8>) {
+        push_field(out, self);
+    }
+}
+
+impl EvmEncoding for G1 {
+    fn write_evm(&self, out: &mut Vec<u8>) {
+        push_point(out, self);
+    }
+}
+
+impl EvmEncoding for G2 {
+    fn write_evm(&self, out: &mut Vec<u8>) {
+        push_point(out, self);
+    }
+}
+
+/// no precompile takes target group elements; they are laid out as the Fq12
+/// coefficients, highest first, for contracts that compare them
+impl EvmEncoding for Gt {
+    fn write_evm(&self, out: &mut Vec<u8>) {
+        push_field(out, &self.0);
+    }
+}
+
+impl EvmEncoding for FsHasher {
+    fn write_evm(&self, out: &mut Vec<u8>) {
+        let mut tag = Vec::new();
+        self.serialize_compressed(&mut tag).unwrap();
+        push_u64(out, tag[0] as u64);
+    }
+}
+
+impl EvmEncoding for PermutationProof {
+    fn write_evm(&self, out: &mut Vec<u8>) {
+        for y in [&self.y1, &self.y2, &self.y3, &self.y4, &self.y5] {
+            y.write_evm(out);
+        }
+        for pi in [
+            &self.pi_1,
+            &self.pi_2,
+            &self.pi_3,
+            &self.pi_4,
+            &self.pi_5,
+            &self.f_com,
+            &self.q_com,
+            &self.t_com,
+        ] {
+            pi.write_evm(out);
+        }
+        self.hasher.write_evm(out);
+    }
+}
+
+impl EvmEncoding for PermutationProofV2 {
+    fn write_evm(&self, out: &mut Vec<u8>) {
+        for y in [&self.y1, &self.y2, &self.y3, &self.y4, &self.y5] {
+            y.write_evm(out);
+        }
+        for pi in [
+            &self.pi_1,
+            &self.pi_3,
+            &self.pi_batch,
+            &self.f_com,
+            &self.q_com,
+            &self.t_com,
+        ] {
+            pi.write_evm(out);
+        }
+        self.hasher.write_evm(out);
+    }
+}
+
+impl EvmEncoding for SigmaProof {
+    fn write_evm(&self, out: &mut Vec<u8>) {
+        self.a1.write_evm(out);
+        self.a2.write_evm(out);
+        self.y.write_evm(out);
+    }
+}
+
+/// the identities come as their number, then each one length-prefixed; the sigma
+/// proof as a word that is 1 if it is there, followed by it
+impl EvmEncoding for EncryptionProof {
+    fn write_evm(&self, out: &mut Vec<u8>) {
+        self.pk.write_evm(out);
+        push_u64(out, self.ids.len() as u64);
+        for id in &self.ids {
+            push_bytes(out, id);
+        }
+        self.card_commitment.write_evm(out);
+        self.card_poly_eval.write_evm(out);
+        self.eval_proof.write_evm(out);
+        self.hiding_ciphertext.write_evm(out);
+        self.t.write_evm(out);
+        match &self.sigma_proof {
+            Some(sigma) => {
+                push_u64(out, 1);
+                sigma.write_evm(out);
+            }
+            None => push_u64(out, 0),
+        }
+        self.hasher.write_evm(out);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{push_field, push_point, EvmEncoding, WORD};
+    use crate::common::{PermutationProof, F, G1};
+    use crate::transcript::FsHasher;
+    use ark_ff::{BigInteger, PrimeField};
+    use ark_std::UniformRand;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_permutation_proof_layout() {
+        let mut rng = StdRng::from_seed([5u8; 32]);
+        let proof = PermutationProof {
+            y1: F::rand(&mut rng),
+            y2: F::rand(&mut rng),
+            y3: F::rand(&mut rng),
+            y4: F::rand(&mut rng),
+            y5: F::rand(&mut rng),
+            pi_1: G1::rand(&mut rng),
+            pi_2: G1::rand(&mut rng),
+            pi_3: G1::rand(&mut rng),
+            pi_4: G1::rand(&mut rng),
+            pi_5: G1::rand(&mut rng),
+            f_com: G1::rand(&mut rng),
+            q_com: G1::rand(&mut rng),
+            t_com: G1::rand(&mut rng),
+            hasher: FsHasher::Poseidon,
+        };
+        let calldata = proof.to_evm_calldata();
+
+        let y1 = proof.y1.into_bigint().to_bytes_be();
+        assert_eq!(&calldata[WORD - y1.len()..WORD], &y1[..]);
+        let scalar_len = proof.y1.to_evm_calldata().len();
+        let point_len = proof.pi_1.to_evm_calldata().len();
+        assert_eq!(calldata.len(), 5 * scalar_len + 8 * point_len + WORD);
+        assert_eq!(calldata.len() % WORD, 0);
+        assert_eq!(*calldata.last().unwrap(), 1);
+
+        // the point at infinity is all zeros
+        let mut zero = Vec::new();
+        push_point(&mut zero, &G1::default());
+        assert!(zero.iter().all(|b| *b == 0));
+        let mut one = Vec::new();
+        push_field(&mut one, &F::from(1u64));
+        assert_eq!(one.len(), scalar_len);
+        assert_eq!(*one.last().unwrap(), 1);
+    }
+
+    /// checks a KZG opening the way a contract would with the alt_bn128
+    /// precompiles: ecMul and ecAdd give comm - v g, and ecPairing checks
+    /// e(comm - v g, h) e(-proof, beta_h - z h) = 1 on the words it is handed
+    #[cfg(feature = "bn254")]
+    #[test]
+    fn test_kzg_opening_as_the_precompile_checks_it() {
+        use crate::kzg::KZG10;
+        use ark_bn254::{g1, g2, Bn254, Fq, Fq2, Fr};
+        use ark_ec::pairing::Pairing;
+        use ark_ec::short_weierstrass::Affine;
+        use ark_ec::AffineRepr;
+        use ark_poly::{univariate::DensePolynomial, DenseUVPolynomial, Polynomial};
+        use ark_std::Zero;
+
+        type Kzg = KZG10<Bn254, DensePolynomial<Fr>>;
+
+        fn read_fq(word: &[u8]) -> Fq {
+            let x = Fq::from_be_bytes_mod_order(word);
+            // the precompile rejects coordinates that are not reduced
+            assert_eq!(x.into_bigint().to_bytes_be(), word);
+            x
+        }
+
+        fn read_g1(words: &[u8]) -> Affine<g1::Config> {
+            let (x, y) = (read_fq(&words[..WORD]), read_fq(&words[WORD..2 * WORD]));
+            if x.is_zero() && y.is_zero() {
+                return Affine::identity();
+            }
+            let p = Affine::new_unchecked(x, y);
+            assert!(p.is_on_curve());
+            p
+        }
+
+        fn read_g2(words: &[u8]) -> Affine<g2::Config> {
+            let w = |i: usize| read_fq(&words[i * WORD..(i + 1) * WORD]);
+            let (x, y) = (Fq2::new(w(1), w(0)), Fq2::new(w(3), w(2)));
+            let p = Affine::new_unchecked(x, y);
+            assert!(p.is_on_curve() && p.is_in_correct_subgroup_assuming_on_curve());
+            p
+        }
+
+        fn ecpairing(input: &[u8]) -> bool {
+            assert_eq!(input.len() % (6 * WORD), 0);
+            let (a, b): (Vec<_>, Vec<_>) = input
+                .chunks(6 * WORD)
+                .map(|pair| (read_g1(&pair[..2 * WORD]), read_g2(&pair[2 * WORD..])))
+                .unzip();
+            Bn254::multi_pairing(a, b).is_zero()
+        }
+
+        let mut rng = StdRng::from_seed([6u8; 32]);
+        let params = Kzg::setup(16, &mut rng);
+        let poly = DensePolynomial::<Fr>::rand(16, &mut rng);
+        let z = Fr::rand(&mut rng);
+        let comm = Kzg::commit_g1(&params, &poly).unwrap();
+        let proof = Kzg::compute_opening_proof(&params, &poly, &z).unwrap();
+
+        let calldata = |value: Fr| {
+            let g = params.powers_of_g[0];
+            let (h, beta_h) = (params.powers_of_h[0], params.powers_of_h[1]);
+            let mut input = Vec::new();
+            push_point(&mut input, &(comm.into_group() - g * value));
+            push_point(&mut input, &h.into_group());
+            push_point(&mut input, &-proof.into_group());
+            push_point::<g2::Config>(&mut input, &(beta_h.into_group() - h * z));
+            input
+        };
+
+        let input = calldata(poly.evaluate(&z));
+        assert_eq!(input.len(), 2 * 6 * WORD);
+        assert!(ecpairing(&input));
+        assert!(!ecpairing(&calldata(poly.evaluate(&z) + Fr::from(1u64))));
+    }
+}
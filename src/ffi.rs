@@ -0,0 +1,435 @@
+//! A C ABI over the proof checks and the card codec, for game servers written in
+//! other languages. Every function takes its inputs as (pointer, length) pairs of
+//! bytes the caller owns: they are only read, never kept past the call, and may
+//! be freed as soon as it returns. A null pointer is accepted only with a length
+//! of zero. Proofs are in their ProofEncoding, scalars as encode_as_bytes leaves
+//! them, ciphertexts in the compressed canonical encoding, and the KZG params as
+//! write_to_file saves them or as a .ptau file. The checks are those of the
+//! default ProtocolParams. No panic crosses the boundary: a call that fails,
+//! for malformed input or otherwise, returns POK3R_ERROR and leaves a message for
+//! pok3r_last_error_message.
+//!
+//! include/pok3r.h is generated from this module, as cbindgen.toml tells.
+
+use ark_serialize::CanonicalDeserialize;
+use rand::rngs::OsRng;
+use std::cell::RefCell;
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::panic::{self, AssertUnwindSafe};
+use std::{ptr, slice};
+
+use crate::cards;
+use crate::common::{Ciphertext, Curve, EncryptionProof, PermutationProof, ProtocolParams, F};
+use crate::encoding::{decode_from_bytes, ProofEncoding};
+use crate::hash::GameContext;
+use crate::kzg::UniversalParams;
+
+/// the proof holds
+pub const POK3R_VALID: i32 = 1;
+/// the proof was read but does not hold
+pub const POK3R_INVALID: i32 = 0;
+/// the call failed; pok3r_last_error_message tells why
+pub const POK3R_ERROR: i32 = -1;
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    // a message with an interior nul is cut there
+    let message = CString::new(message).unwrap_or_else(|e| {
+        let end = e.nul_position();
+        CString::new(&e.into_vec()[..end]).unwrap()
+    });
+    LAST_ERROR.with(|last| *last.borrow_mut() = Some(message));
+}
+
+/// runs f, turning its errors and panics into POK3R_ERROR
+fn guard(f: impl FnOnce() -> Result<i32, String>) -> i32 {
+    LAST_ERROR.with(|last| *last.borrow_mut() = None);
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(code)) => code,
+        Ok(Err(message)) => {
+            set_last_error(message);
+            POK3R_ERROR
+        }
+        Err(_) => {
+            set_last_error("internal error: the call panicked".to_string());
+            POK3R_ERROR
+        }
+    }
+}
+
+/// # Safety
+/// ptr must be null with len 0, or point to len readable bytes
+unsafe fn input<'a>(ptr: *const u8, len: usize, name: &str) -> Result<&'a [u8], String> {
+    if ptr.is_null() {
+        return if len == 0 {
+            Ok(&[])
+        } else {
+            Err(format!("{} is null", name))
+        };
+    }
+    Ok(slice::from_raw_parts(ptr, len))
+}
+
+fn params_from(bytes: &[u8], params: &ProtocolParams) -> Result<UniversalParams<Curve>, String> {
+    UniversalParams::from_bytes(bytes, 2 * params.perm_size(), &mut OsRng)
+        .map_err(|e| format!("params: {}", e))
+}
+
+fn validity(valid: bool) -> i32 {
+    if valid {
+        POK3R_VALID
+    } else {
+        POK3R_INVALID
+    }
+}
+
+/// Checks a PermutationProof, that its f_com commits to a shuffle of the deck.
+/// Returns POK3R_VALID, POK3R_INVALID, or POK3R_ERROR for malformed input.
+///
+/// # Safety
+/// Each pointer must be null with a length of zero, or point to that many
+/// readable bytes. The buffers stay owned by the caller.
+#[no_mangle]
+pub unsafe extern "C" fn pok3r_verify_permutation_proof(
+    proof_ptr: *const u8,
+    proof_len: usize,
+    params_ptr: *const u8,
+    params_len: usize,
+) -> i32 {
+    guard(|| {
+        let proof_bytes = input(proof_ptr, proof_len, "proof")?;
+        let params_bytes = input(params_ptr, params_len, "params")?;
+
+        let params = ProtocolParams::default();
+        let proof =
+            PermutationProof::from_bytes(proof_bytes).map_err(|e| format!("proof: {}", e))?;
+        let pp = params_from(params_bytes, &params)?;
+        Ok(validity(proof.verify(&pp, &params)))
+    })
+}
+
+/// Checks an EncryptionProof, that the ciphertext encrypts the cards its
+/// card_commitment holds under the identities of the game game_id_ptr points to,
+/// 32 bytes long. Returns POK3R_VALID, POK3R_INVALID, or POK3R_ERROR for
+/// malformed input.
+///
+/// # Safety
+/// Each pointer must be null with a length of zero, or point to that many
+/// readable bytes, and game_id_ptr to 32 readable bytes. The buffers stay owned
+/// by the caller.
+#[no_mangle]
+pub unsafe extern "C" fn pok3r_verify_encryption_proof(
+    proof_ptr: *const u8,
+    proof_len: usize,
+    ciphertext_ptr: *const u8,
+    ciphertext_len: usize,
+    game_id_ptr: *const u8,
+    params_ptr: *const u8,
+    params_len: usize,
+) -> i32 {
+    guard(|| {
+        let proof_bytes = input(proof_ptr, proof_len, "proof")?;
+        let ciphertext_bytes = input(ciphertext_ptr, ciphertext_len, "ciphertext")?;
+        let game_id = input(game_id_ptr, 32, "game id")?;
+        let params_bytes = input(params_ptr, params_len, "params")?;
+
+        let params = ProtocolParams::default();
+        let proof =
+            EncryptionProof::from_bytes(proof_bytes).map_err(|e| format!("proof: {}", e))?;
+        let ciphertext = Ciphertext::deserialize_compressed(ciphertext_bytes)
+            .map_err(|e| format!("ciphertext: {}", e))?;
+        let ctx = GameContext::new(game_id.try_into().unwrap());
+        let pp = params_from(params_bytes, &params)?;
+        Ok(validity(proof.verify(&pp, &params, &ctx, &ciphertext)))
+    })
+}
+
+/// Decodes an opened card value. Returns the index of the card, from 0 for the
+/// two of clubs to 51 for the ace of spades, or POK3R_ERROR if the value is
+/// malformed, padding or no card.
+///
+/// # Safety
+/// f_ptr must be null with f_len zero, or point to f_len readable bytes. The
+/// buffer stays owned by the caller.
+#[no_mangle]
+pub unsafe extern "C" fn pok3r_decode_card(f_ptr: *const u8, f_len: usize) -> i32 {
+    guard(|| {
+        let f_bytes = input(f_ptr, f_len, "value")?;
+        let value: F = decode_from_bytes(f_bytes).map_err(|e| format!("value: {}", e))?;
+        let card = cards::decode(value).map_err(|e| e.to_string())?;
+        Ok(card.index() as i32)
+    })
+}
+
+/// The message of the last call on this thread that returned POK3R_ERROR, as a
+/// nul-terminated UTF-8 string, or null if that call succeeded. The string is
+/// owned by the library: it must not be freed, and stays valid until the next
+/// call into the library on the same thread.
+#[no_mangle]
+pub extern "C" fn pok3r_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last| match &*last.borrow() {
+        Some(message) => message.as_ptr(),
+        None => ptr::null(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{POK3R_ERROR, POK3R_INVALID};
+    use crate::cards::{self, Card, Rank, Suit};
+    use crate::common::{Curve, PermutationProof, F, G1};
+    use crate::encoding::{encode_as_bytes, ProofEncoding};
+    use crate::kzg::KZG10;
+    use crate::transcript::FsHasher;
+    use ark_poly::univariate::DensePolynomial;
+    use ark_serialize::CanonicalSerialize;
+    use ark_std::UniformRand;
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::ffi::CStr;
+    use std::os::raw::c_char;
+    use std::ptr;
+
+    // the functions as C code sees them, linked by their exported names
+    extern "C" {
+        fn pok3r_verify_permutation_proof(
+            proof_ptr: *const u8,
+            proof_len: usize,
+            params_ptr: *const u8,
+            params_len: usize,
+        ) -> i32;
+        fn pok3r_verify_encryption_proof(
+            proof_ptr: *const u8,
+            proof_len: usize,
+            ciphertext_ptr: *const u8,
+            ciphertext_len: usize,
+            game_id_ptr: *const u8,
+            params_ptr: *const u8,
+            params_len: usize,
+        ) -> i32;
+        fn pok3r_decode_card(f_ptr: *const u8, f_len: usize) -> i32;
+        fn pok3r_last_error_message() -> *const c_char;
+    }
+
+    fn last_error() -> Option<String> {
+        let message = unsafe { pok3r_last_error_message() };
+        if message.is_null() {
+            return None;
+        }
+        Some(
+            unsafe { CStr::from_ptr(message) }
+                .to_str()
+                .unwrap()
+                .to_string(),
+        )
+    }
+
+    fn decode_card(bytes: &[u8]) -> i32 {
+        unsafe { pok3r_decode_card(bytes.as_ptr(), bytes.len()) }
+    }
+
+    #[test]
+    fn test_decode_card() {
+        let queen = Card::new(Rank::Queen, Suit::Spades);
+        let bytes = encode_as_bytes(&cards::encode(queen));
+        assert_eq!(decode_card(&bytes), queen.index() as i32);
+        assert_eq!(last_error(), None);
+
+        assert_eq!(decode_card(&encode_as_bytes(&F::from(2u64))), POK3R_ERROR);
+        assert!(last_error().unwrap().contains("does not encode a card"));
+        assert_eq!(decode_card(&bytes[..bytes.len() - 1]), POK3R_ERROR);
+        assert!(last_error().unwrap().starts_with("value"));
+        assert_eq!(decode_card(&encode_as_bytes(&G1::default())), POK3R_ERROR);
+        assert_eq!(decode_card(&[]), POK3R_ERROR);
+
+        assert_eq!(unsafe { pok3r_decode_card(ptr::null(), 32) }, POK3R_ERROR);
+        assert_eq!(last_error().unwrap(), "value is null");
+    }
+
+    #[test]
+    fn test_verify_rejects_malformed_buffers() {
+        let mut rng = StdRng::from_seed([10u8; 32]);
+        let pp = KZG10::<Curve, DensePolynomial<F>>::setup(128, &mut rng);
+        let mut pp_bytes = Vec::new();
+        pp.serialize_compressed(&mut pp_bytes).unwrap();
+
+        let proof: PermutationProof = PermutationProof {
+            y1: F::rand(&mut rng),
+            y2: F::rand(&mut rng),
+            y3: F::rand(&mut rng),
+            y4: F::rand(&mut rng),
+            y5: F::rand(&mut rng),
+            pi_1: G1::rand(&mut rng),
+            pi_2: G1::rand(&mut rng),
+            pi_3: G1::rand(&mut rng),
+            pi_4: G1::rand(&mut rng),
+            pi_5: G1::rand(&mut rng),
+            f_com: G1::rand(&mut rng),
+            q_com: G1::rand(&mut rng),
+            t_com: G1::rand(&mut rng),
+            hasher: FsHasher::Poseidon,
+        };
+        let proof_bytes = proof.to_bytes();
+        let verify = |proof: &[u8], params: &[u8]| unsafe {
+            pok3r_verify_permutation_proof(
+                proof.as_ptr(),
+                proof.len(),
+                params.as_ptr(),
+                params.len(),
+            )
+        };
+
+        // well-formed, but not a proof of anything
+        assert_eq!(verify(&proof_bytes, &pp_bytes), POK3R_INVALID);
+        assert_eq!(last_error(), None);
+
+        assert_eq!(verify(&proof_bytes[..40], &pp_bytes), POK3R_ERROR);
+        assert!(last_error().unwrap().starts_with("proof"));
+        assert_eq!(verify(&proof_bytes, &pp_bytes[..64]), POK3R_ERROR);
+        assert!(last_error().unwrap().starts_with("params"));
+        let mut flipped = proof_bytes.clone();
+        flipped[0] ^= 0xff;
+        assert_eq!(verify(&flipped, &pp_bytes), POK3R_ERROR);
+        assert_eq!(
+            unsafe { pok3r_verify_permutation_proof(ptr::null(), 8, ptr::null(), 0) },
+            POK3R_ERROR
+        );
+
+        let game_id = [3u8; 32];
+        let ciphertext = [0u8; 16];
+        let code = unsafe {
+            pok3r_verify_encryption_proof(
+                proof_bytes.as_ptr(),
+                proof_bytes.len(),
+                ciphertext.as_ptr(),
+                ciphertext.len(),
+                game_id.as_ptr(),
+                pp_bytes.as_ptr(),
+                pp_bytes.len(),
+            )
+        };
+        assert_eq!(code, POK3R_ERROR);
+        assert!(last_error().unwrap().starts_with("proof"));
+    }
+
+    /// the params, and the proofs and ciphertext a party of a simulated shuffle of
+    /// the default deck produces, all encoded as a server hands them out
+    #[cfg(feature = "networking")]
+    fn shuffled_deck(game_id: [u8; 32]) -> (Vec<u8>, Vec<u8>, Vec<u8>, Vec<u8>) {
+        use crate::common::PERM_SIZE;
+        use crate::hash::{card_id, GameContext, IdCache};
+        use crate::shuffler::{
+            compute_keyper_keys, compute_params, compute_permutation_argument, encrypt_and_prove,
+            shuffle_deck,
+        };
+        use crate::simulator::run_parties_with_seed;
+
+        let pp = compute_params();
+        let (_, mpk) = compute_keyper_keys();
+        let ctx = GameContext::new(game_id);
+        let ids = (0..PERM_SIZE)
+            .map(|i| card_id(&ctx, i as u64, 0))
+            .collect::<Vec<Vec<u8>>>();
+
+        let mut outputs = run_parties_with_seed(3, 11, |mut evaluator| {
+            let pp = pp.clone();
+            let ids = ids.clone();
+            async move {
+                let cards = shuffle_deck(&mut evaluator).await.unwrap();
+                let (perm_proof, alpha1) =
+                    compute_permutation_argument(&pp, &mut evaluator, &cards)
+                        .await
+                        .unwrap();
+                let (ciphertext, encryption_proof) = encrypt_and_prove(
+                    &pp,
+                    &mut evaluator,
+                    &mut IdCache::new(ctx, ids.len()),
+                    cards,
+                    perm_proof.f_com,
+                    alpha1,
+                    mpk,
+                    ids,
+                )
+                .await
+                .unwrap();
+                (perm_proof, ciphertext, encryption_proof)
+            }
+        });
+        let (perm_proof, ciphertext, encryption_proof) = outputs.remove(0);
+
+        let mut pp_bytes = Vec::new();
+        pp.serialize_compressed(&mut pp_bytes).unwrap();
+        let mut ciphertext_bytes = Vec::new();
+        ciphertext
+            .serialize_compressed(&mut ciphertext_bytes)
+            .unwrap();
+        (
+            pp_bytes,
+            perm_proof.to_bytes(),
+            ciphertext_bytes,
+            encryption_proof.to_bytes(),
+        )
+    }
+
+    #[cfg(feature = "networking")]
+    #[test]
+    fn test_verify_accepts_proofs_of_a_shuffle() {
+        use super::POK3R_VALID;
+        use crate::common::EncryptionProof;
+
+        let game_id = [5u8; 32];
+        let (pp_bytes, perm_bytes, ciphertext_bytes, encryption_bytes) = shuffled_deck(game_id);
+        let verify_permutation = |proof: &[u8]| unsafe {
+            pok3r_verify_permutation_proof(
+                proof.as_ptr(),
+                proof.len(),
+                pp_bytes.as_ptr(),
+                pp_bytes.len(),
+            )
+        };
+        let verify_encryption = |proof: &[u8], ciphertext: &[u8], game_id: &[u8; 32]| unsafe {
+            pok3r_verify_encryption_proof(
+                proof.as_ptr(),
+                proof.len(),
+                ciphertext.as_ptr(),
+                ciphertext.len(),
+                game_id.as_ptr(),
+                pp_bytes.as_ptr(),
+                pp_bytes.len(),
+            )
+        };
+
+        assert_eq!(verify_permutation(&perm_bytes), POK3R_VALID);
+        assert_eq!(last_error(), None);
+        assert_eq!(
+            verify_encryption(&encryption_bytes, &ciphertext_bytes, &game_id),
+            POK3R_VALID
+        );
+        assert_eq!(last_error(), None);
+
+        // the same proofs of another game, or with a byte of an evaluation flipped
+        assert_eq!(
+            verify_encryption(&encryption_bytes, &ciphertext_bytes, &[6u8; 32]),
+            POK3R_INVALID
+        );
+        // after the version and curve bytes, y1 comes first in a permutation proof;
+        // scalars are little-endian, so flipping the low bit keeps them in range
+        let mut flipped = perm_bytes.clone();
+        flipped[2] ^= 1;
+        assert_eq!(verify_permutation(&flipped), POK3R_INVALID);
+        let proof = EncryptionProof::<Curve>::from_bytes(&encryption_bytes).unwrap();
+        let eval_at = 2
+            + proof.pk.compressed_size()
+            + proof.ids.compressed_size()
+            + proof.card_commitment.compressed_size();
+        let mut flipped = encryption_bytes.clone();
+        flipped[eval_at] ^= 1;
+        assert_eq!(
+            verify_encryption(&flipped, &ciphertext_bytes, &game_id),
+            POK3R_INVALID
+        );
+    }
+}
@@ -0,0 +1,396 @@
+//! A Texas Hold'em hand on top of the shuffler: shuffle and commit to the deck,
+//! deal two hole cards to every player encrypted to that player, reveal the board
+//! street by street, and open the hole cards of the players left at showdown.
+
+use std::{error, fmt};
+
+use crate::common::{Ciphertext, Curve, PermutationProof, G1, G2};
+use crate::evaluator::{Evaluator, EvaluatorError};
+use crate::hash::{player_card_id, GameContext, IdCache};
+use crate::kzg::UniversalParams;
+use crate::network::MessagingSystem;
+use crate::shuffler::{compute_permutation_argument, shuffle_deck};
+use crate::transport::Transport;
+use crate::utils;
+use crate::wire::Wire;
+
+/// hole cards every player is dealt
+pub const HOLE_CARDS: usize = 2;
+
+/// the community cards, revealed in this order
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Street {
+    Flop,
+    Turn,
+    River,
+}
+
+impl Street {
+    /// how many cards the street reveals
+    pub fn num_cards(&self) -> usize {
+        match self {
+            Street::Flop => 3,
+            Street::Turn | Street::River => 1,
+        }
+    }
+}
+
+impl fmt::Display for Street {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Street::Flop => write!(f, "flop"),
+            Street::Turn => write!(f, "turn"),
+            Street::River => write!(f, "river"),
+        }
+    }
+}
+
+/// GameError describes why a step of a hand was refused or failed
+#[derive(Debug)]
+pub enum GameError {
+    Evaluator(EvaluatorError),
+    /// the deck must be shuffled before cards are drawn from it
+    NotShuffled,
+    /// the deck was shuffled already
+    AlreadyShuffled,
+    /// the step needs more cards than are left in the deck
+    NotEnoughCards {
+        needed: usize,
+        remaining: usize,
+    },
+    /// hole cards are dealt once per hand
+    AlreadyDealt,
+    /// showdown needs the hole cards dealt
+    NotDealt,
+    StreetRevealed(Street),
+    /// the player with this (base58 encoded) id is not at the table
+    UnknownPlayer(String),
+    /// an opened card is not a card of the deck
+    NotACard,
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GameError::Evaluator(err) => write!(f, "evaluator error: {}", err),
+            GameError::NotShuffled => write!(f, "the deck is not shuffled yet"),
+            GameError::AlreadyShuffled => write!(f, "the deck is shuffled already"),
+            GameError::NotEnoughCards { needed, remaining } => write!(
+                f,
+                "{} cards are needed, but {} are left in the deck",
+                needed, remaining
+            ),
+            GameError::AlreadyDealt => write!(f, "the hole cards are dealt already"),
+            GameError::NotDealt => write!(f, "the hole cards are not dealt yet"),
+            GameError::StreetRevealed(street) => write!(f, "the {} is revealed already", street),
+            GameError::UnknownPlayer(id) => write!(f, "player {} is not at the table", id),
+            GameError::NotACard => write!(f, "an opened value is not a card of the deck"),
+        }
+    }
+}
+
+impl error::Error for GameError {}
+
+impl From<EvaluatorError> for GameError {
+    fn from(err: EvaluatorError) -> Self {
+        GameError::Evaluator(err)
+    }
+}
+
+/// DeckCommitment is what players check the hand against: the commitment to the
+/// shuffled deck, and the proof that it is a permutation
+#[derive(Clone, Debug)]
+pub struct DeckCommitment {
+    pub commitment: G1,
+    pub proof: PermutationProof,
+}
+
+/// HoldemGame plays one hand among players, identified by the ids their hole
+/// cards are encrypted to. Cards are drawn from the top of the real part of the
+/// shuffled deck, and never twice.
+pub struct HoldemGame<T: Transport = MessagingSystem> {
+    evaluator: Evaluator<T>,
+    pp: UniversalParams<Curve>,
+    pk: G2,
+    players: Vec<Vec<u8>>,
+    /// the identities of the game, and their pairings with pk
+    id_cache: IdCache,
+    /// the shuffled permutation, once shuffle_and_commit ran
+    deck: Option<Vec<Wire>>,
+    /// deck positions drawn so far
+    consumed: usize,
+    /// the hole cards of every player, in the order of players
+    hole_cards: Option<Vec<[Wire; HOLE_CARDS]>>,
+    revealed: Vec<Street>,
+    board: Vec<usize>,
+}
+
+impl<T: Transport> HoldemGame<T> {
+    /// pp are the KZG params of the deck commitment, and pk the key hole cards are
+    /// encrypted under
+    pub fn new(
+        evaluator: Evaluator<T>,
+        pp: UniversalParams<Curve>,
+        pk: G2,
+        players: Vec<Vec<u8>>,
+        game_id: [u8; 32],
+    ) -> Self {
+        let id_cache = IdCache::new(GameContext::new(game_id), evaluator.params().deck_size());
+        HoldemGame {
+            evaluator,
+            pp,
+            pk,
+            players,
+            id_cache,
+            deck: None,
+            consumed: 0,
+            hole_cards: None,
+            revealed: vec![],
+            board: vec![],
+        }
+    }
+
+    pub fn evaluator(&mut self) -> &mut Evaluator<T> {
+        &mut self.evaluator
+    }
+
+    pub fn into_evaluator(self) -> Evaluator<T> {
+        self.evaluator
+    }
+
+    pub fn context(&self) -> &GameContext {
+        self.id_cache.context()
+    }
+
+    /// the community cards revealed so far
+    pub fn board(&self) -> &[usize] {
+        &self.board
+    }
+
+    /// cards of the deck not drawn yet
+    pub fn remaining(&self) -> usize {
+        self.evaluator.params().deck_size() - self.consumed
+    }
+
+    /// shuffles the deck and proves that it is a permutation
+    pub async fn shuffle_and_commit(&mut self) -> Result<DeckCommitment, GameError> {
+        if self.deck.is_some() {
+            return Err(GameError::AlreadyShuffled);
+        }
+        let deck = shuffle_deck(&mut self.evaluator).await?;
+        let (proof, _) = compute_permutation_argument(&self.pp, &mut self.evaluator, &deck).await?;
+        self.deck = Some(deck);
+        Ok(DeckCommitment {
+            commitment: proof.f_com,
+            proof,
+        })
+    }
+
+    /// deals HOLE_CARDS cards to every player, the k-th one encrypted to
+    /// player_card_id(ctx, player, k); the ciphertext of a player holds its cards in order
+    pub async fn deal_hole_cards(&mut self) -> Result<Vec<Ciphertext>, GameError> {
+        if self.hole_cards.is_some() {
+            return Err(GameError::AlreadyDealt);
+        }
+        let cards = self.draw(HOLE_CARDS * self.players.len())?;
+
+        let ctx = *self.id_cache.context();
+        let ids: Vec<Vec<u8>> = self
+            .players
+            .iter()
+            .flat_map(|player| (0..HOLE_CARDS as u64).map(move |k| player_card_id(&ctx, player, k)))
+            .collect();
+        let mask = self.evaluator.ran();
+        let (c1, c2s) = self
+            .evaluator
+            .batch_dist_ibe_encrypt_with_common_mask(
+                &mut self.id_cache,
+                &cards,
+                &mask,
+                &self.pk,
+                &ids,
+            )
+            .await?;
+
+        self.hole_cards = Some(
+            cards
+                .chunks(HOLE_CARDS)
+                .map(|hand| [hand[0].clone(), hand[1].clone()])
+                .collect(),
+        );
+        Ok(c2s
+            .chunks(HOLE_CARDS)
+            .map(|hand| (c1, hand.to_vec()))
+            .collect())
+    }
+
+    /// reveals the cards of street to everyone
+    pub async fn reveal_board(&mut self, street: Street) -> Result<Vec<usize>, GameError> {
+        if self.revealed.contains(&street) {
+            return Err(GameError::StreetRevealed(street));
+        }
+        let cards = self.draw(street.num_cards())?;
+        let values = self.open_cards(&cards).await?;
+        self.revealed.push(street);
+        self.board.extend_from_slice(&values);
+        Ok(values)
+    }
+
+    /// opens the hole cards of the given players to everyone
+    pub async fn showdown(
+        &mut self,
+        player_ids: &[Vec<u8>],
+    ) -> Result<Vec<[usize; HOLE_CARDS]>, GameError> {
+        let hole_cards = self.hole_cards.as_ref().ok_or(GameError::NotDealt)?;
+        let mut cards = Vec::with_capacity(HOLE_CARDS * player_ids.len());
+        for id in player_ids {
+            let seat = self
+                .players
+                .iter()
+                .position(|player| player == id)
+                .ok_or_else(|| GameError::UnknownPlayer(bs58::encode(id).into_string()))?;
+            cards.extend_from_slice(&hole_cards[seat]);
+        }
+
+        let values = self.open_cards(&cards).await?;
+        Ok(values
+            .chunks(HOLE_CARDS)
+            .map(|hand| [hand[0], hand[1]])
+            .collect())
+    }
+
+    /// takes the next count cards of the deck
+    fn draw(&mut self, count: usize) -> Result<Vec<Wire>, GameError> {
+        if count > self.remaining() {
+            return Err(GameError::NotEnoughCards {
+                needed: count,
+                remaining: self.remaining(),
+            });
+        }
+        let deck = self.deck.as_ref().ok_or(GameError::NotShuffled)?;
+        // the dummy cards fill the first positions of the permutation
+        let params = self.evaluator.params();
+        let first = params.perm_size() - params.deck_size() + self.consumed;
+        let cards = deck[first..first + count].to_vec();
+        self.consumed += count;
+        Ok(cards)
+    }
+
+    /// opens cards, and returns their indices in 0..deck_size
+    async fn open_cards(&mut self, cards: &[Wire]) -> Result<Vec<usize>, GameError> {
+        let values = self.evaluator.batch_output_wire(cards).await?;
+        let params = *self.evaluator.params();
+        let powers = &utils::domain_info(params.perm_size()).powers;
+        values
+            .iter()
+            .map(|value| {
+                powers
+                    .iter()
+                    .position(|power| power == value)
+                    .filter(|index| *index < params.deck_size())
+                    .ok_or(GameError::NotACard)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GameError, HoldemGame, Street};
+    use crate::common::ProtocolParams;
+    use crate::hash::player_card_id;
+    use crate::shuffler::{
+        compute_decryption_cache, compute_decryption_key, compute_keyper_keys, compute_params,
+        decrypt_one_card, verify_permutation_argument,
+    };
+    use crate::simulator::run_parties;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_sim_full_hand() {
+        let params = ProtocolParams::default();
+        let pp = compute_params();
+        let (msk, mpk) = compute_keyper_keys();
+        let players = vec![b"alice".to_vec(), b"bob".to_vec()];
+
+        let outputs = run_parties(3, |evaluator| {
+            let pp = pp.clone();
+            let players = players.clone();
+            async move {
+                let mut game = HoldemGame::new(evaluator, pp, mpk, players.clone(), [9u8; 32]);
+                let commitment = game.shuffle_and_commit().await.unwrap();
+                let ciphertexts = game.deal_hole_cards().await.unwrap();
+                assert!(matches!(
+                    game.deal_hole_cards().await,
+                    Err(GameError::AlreadyDealt)
+                ));
+
+                for street in [Street::Flop, Street::Turn, Street::River] {
+                    game.reveal_board(street).await.unwrap();
+                }
+                assert!(matches!(
+                    game.reveal_board(Street::Flop).await,
+                    Err(GameError::StreetRevealed(Street::Flop))
+                ));
+
+                let hands = game.showdown(&players).await.unwrap();
+                (
+                    commitment,
+                    ciphertexts,
+                    game.board().to_vec(),
+                    hands,
+                    *game.context(),
+                )
+            }
+        });
+
+        let cache = compute_decryption_cache(&params);
+        for (commitment, ciphertexts, board, hands, ctx) in outputs.iter() {
+            assert!(verify_permutation_argument(&pp, &params, &commitment.proof));
+            assert_eq!(board, &outputs[0].2);
+            assert_eq!(board.len(), 5);
+
+            // every player can read its own cards, which are the ones shown down
+            for ((player, ctxt), hand) in players.iter().zip(ciphertexts).zip(hands) {
+                for (k, card) in hand.iter().enumerate() {
+                    let key =
+                        compute_decryption_key(ctx, &player_card_id(ctx, player, k as u64), msk);
+                    assert_eq!(decrypt_one_card(k, &key, ctxt, &cache), Some(*card));
+                }
+            }
+
+            let revealed: HashSet<usize> = board
+                .iter()
+                .chain(hands.iter().flatten())
+                .copied()
+                .collect();
+            assert_eq!(revealed.len(), 9);
+            assert!(revealed.iter().all(|card| *card < params.deck_size()));
+        }
+    }
+
+    #[test]
+    fn test_refuses_to_deal_more_cards_than_the_deck_has() {
+        let pp = compute_params();
+        let (_, mpk) = compute_keyper_keys();
+        let players: Vec<Vec<u8>> = (0..27u8).map(|i| vec![i]).collect();
+
+        let outputs = run_parties(3, |evaluator| {
+            let pp = pp.clone();
+            let players = players.clone();
+            async move {
+                let mut game = HoldemGame::new(evaluator, pp, mpk, players, [9u8; 32]);
+                game.deal_hole_cards().await
+            }
+        });
+        for output in outputs {
+            assert!(matches!(
+                output,
+                Err(GameError::NotEnoughCards {
+                    needed: 54,
+                    remaining: 52
+                })
+            ));
+        }
+    }
+}
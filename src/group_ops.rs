@@ -0,0 +1,91 @@
+use ark_ec::pairing::{Pairing, PairingOutput};
+use ark_ff::{BigInteger, PrimeField};
+use ark_std::Zero;
+
+use crate::common::Curve;
+
+/// window of the tables the evaluator keeps; 2^4 powers per window of a scalar,
+/// about 600 kB for the generator of Gt
+pub const GT_TABLE_WINDOW: usize = 4;
+
+/// GtTable raises a fixed base to many exponents. It holds base^(d · 2^(window · i))
+/// for every window i of a scalar and every digit d of window bits, so that an
+/// exponentiation is one product per window and needs no squarings.
+pub struct GtTable<E: Pairing = Curve> {
+    window: usize,
+    /// rows[i][d] = base^(d · 2^(window · i))
+    rows: Vec<Vec<PairingOutput<E>>>,
+}
+
+impl<E: Pairing> GtTable<E> {
+    /// precomputes the powers of base for windows of window bits, between 1 and 16
+    pub fn new(base: PairingOutput<E>, window: usize) -> Self {
+        assert!(
+            (1..=16).contains(&window),
+            "window of {} bits is not between 1 and 16",
+            window
+        );
+        let num_windows = (E::ScalarField::MODULUS_BIT_SIZE as usize).div_ceil(window);
+        let mut rows = Vec::with_capacity(num_windows);
+        let mut row_base = base;
+        for _ in 0..num_windows {
+            let mut row = Vec::with_capacity(1 << window);
+            let mut power = PairingOutput::<E>::zero();
+            for _ in 0..(1 << window) {
+                row.push(power);
+                power += row_base;
+            }
+            // power went one past the row, to the base of the next one
+            row_base = power;
+            rows.push(row);
+        }
+        GtTable { window, rows }
+    }
+
+    pub fn base(&self) -> PairingOutput<E> {
+        self.rows[0][1]
+    }
+
+    pub fn window(&self) -> usize {
+        self.window
+    }
+
+    /// base^scalar, the same as base * scalar
+    pub fn mul(&self, scalar: E::ScalarField) -> PairingOutput<E> {
+        let bits = scalar.into_bigint().to_bits_le();
+        bits.chunks(self.window)
+            .zip(self.rows.iter())
+            .map(|(digit, row)| {
+                let digit = digit
+                    .iter()
+                    .rev()
+                    .fold(0usize, |d, bit| (d << 1) | usize::from(*bit));
+                row[digit]
+            })
+            .fold(PairingOutput::<E>::zero(), |acc, power| acc + power)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GtTable, GT_TABLE_WINDOW};
+    use crate::common::{Gt, F};
+    use ark_std::{One, UniformRand, Zero};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_table_matches_naive_multiplication() {
+        let mut rng = StdRng::from_seed([3u8; 32]);
+        let base = Gt::rand(&mut rng);
+        let mut scalars = vec![F::zero(), F::one(), -F::one(), F::from(1u64 << 40)];
+        scalars.extend((0..8).map(|_| F::rand(&mut rng)));
+
+        for window in [1, 3, GT_TABLE_WINDOW, 8] {
+            let table = GtTable::new(base, window);
+            assert_eq!(table.base(), base);
+            for scalar in scalars.iter() {
+                assert_eq!(table.mul(*scalar), base * *scalar);
+            }
+        }
+    }
+}
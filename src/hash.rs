@@ -1,30 +1,432 @@
-#[cfg(feature = "bls12_377")]
-use ark_bls12_377::g1;
-#[cfg(feature = "bls12_381")]
-use ark_bls12_381::g1;
-
-use crate::common::G1;
+use crate::common::{Curve, CurveConfig, G1, G2};
 use ark_crypto_primitives::crh::sha256::Sha256;
-use ark_ec::hashing::{
-    curve_maps::wb::WBMap, map_to_curve_hasher::MapToCurveBasedHasher, HashToCurve,
-};
-use ark_ff::field_hashers::DefaultFieldHasher;
+use ark_ec::hashing::{HashToCurve, HashToCurveError};
+use ark_ec::pairing::PairingOutput;
+use ark_ec::short_weierstrass::{Affine, Projective, SWCurveConfig};
+use ark_ec::AffineRepr;
+use ark_ff::field_hashers::{DefaultFieldHasher, HashToField};
+use sha2::Digest;
+use std::collections::HashMap;
+use std::marker::PhantomData;
 
 pub type FrHasher = DefaultFieldHasher<Sha256>;
-pub type G1Hasher = MapToCurveBasedHasher<G1, FrHasher, WBMap<g1::Config>>;
+pub type G1Hasher = <Curve as CurveConfig>::G1Hasher;
+pub type G2Hasher = <Curve as CurveConfig>::G2Hasher;
+
+pub const DOMAIN_STRING_HASH_ID: &[u8] = <Curve as CurveConfig>::DOMAIN_STRING_HASH_ID;
+pub const DOMAIN_STRING_HASH_ID_G2: &[u8] = <Curve as CurveConfig>::DOMAIN_STRING_HASH_ID_G2;
+
+/// IncrementHasher hashes onto curves that arkworks has no map for, e.g. BN254:
+/// the message and a counter are hashed to the base field until they give the
+/// x-coordinate of a point, which is then moved into the prime-order subgroup.
+/// It does not run in constant time, which is fine for the public identities
+/// hashed here.
+pub struct IncrementHasher<P: SWCurveConfig> {
+    field_hasher: FrHasher,
+    _curve: PhantomData<P>,
+}
+
+/// attempts before IncrementHasher gives up; each succeeds with probability about 1/2
+const INCREMENT_ATTEMPTS: u8 = u8::MAX;
+
+impl<P: SWCurveConfig> HashToCurve<Projective<P>> for IncrementHasher<P> {
+    fn new(domain: &[u8]) -> Result<Self, HashToCurveError> {
+        Ok(IncrementHasher {
+            field_hasher: <FrHasher as HashToField<P::BaseField>>::new(domain),
+            _curve: PhantomData,
+        })
+    }
+
+    fn hash(&self, message: &[u8]) -> Result<Affine<P>, HashToCurveError> {
+        let mut input = message.to_vec();
+        input.push(0);
+        for counter in 0..INCREMENT_ATTEMPTS {
+            *input.last_mut().unwrap() = counter;
+            let x: Vec<P::BaseField> = self.field_hasher.hash_to_field(&input, 1);
+            if let Some(point) = Affine::<P>::get_point_from_x_unchecked(x[0], false) {
+                let point = point.clear_cofactor();
+                if !point.is_zero() {
+                    return Ok(point);
+                }
+            }
+        }
+        Err(HashToCurveError::MapToCurveError(format!(
+            "no point after {} attempts",
+            INCREMENT_ATTEMPTS
+        )))
+    }
+}
+
+/// GameContext identifies one game (or table), so that card identities, and the
+/// identity keys extracted to reveal them, never carry over to another game
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct GameContext {
+    pub game_id: [u8; 32],
+}
+
+impl GameContext {
+    pub fn new(game_id: [u8; 32]) -> Self {
+        GameContext { game_id }
+    }
+
+    /// the context of a game known by a human-readable label, e.g. the session id
+    pub fn from_label(label: &str) -> Self {
+        let mut game_id = [0u8; 32];
+        game_id.copy_from_slice(&sha2::Sha256::digest(label.as_bytes()));
+        GameContext { game_id }
+    }
+
+    /// the hash-to-curve domain string base, specialized to this game
+    pub fn domain(&self, base: &[u8]) -> Vec<u8> {
+        let mut domain = base.to_vec();
+        domain.extend_from_slice(b"_GAME_");
+        domain.extend_from_slice(bs58::encode(self.game_id).into_string().as_bytes());
+        domain
+    }
+
+    /// name prefixed with a tag of this game, for the identifiers of revealed values
+    pub fn label(&self, name: &str) -> String {
+        bs58::encode(&self.game_id[..8]).into_string() + "_" + name
+    }
+}
+
+/// the identity of the card at index in the given round of a game
+pub fn card_id(ctx: &GameContext, index: u64, round: u64) -> Vec<u8> {
+    let mut id = ctx.game_id.to_vec();
+    id.extend_from_slice(&index.to_le_bytes());
+    id.extend_from_slice(&round.to_le_bytes());
+    id
+}
 
-#[cfg(feature = "bls12_377")]
-pub const DOMAIN_STRING_HASH_ID: &'static [u8] =
-    b"SUPRA_POKER_ID-hashtoG1-with-BLS12377G1_XMD:SHA-256_SSWU_RO";
-#[cfg(feature = "bls12_381")]
-pub const DOMAIN_STRING_HASH_ID: &'static [u8] =
-    b"SUPRA_POKER_ID-hashtoG1-with-BLS12381G1_XMD:SHA-256_SSWU_RO";
+/// the identity of the card at index that only player may decrypt in a game
+pub fn player_card_id(ctx: &GameContext, player: &[u8], index: u64) -> Vec<u8> {
+    let mut id = ctx.game_id.to_vec();
+    id.extend_from_slice(&(player.len() as u64).to_le_bytes());
+    id.extend_from_slice(player);
+    id.extend_from_slice(&index.to_le_bytes());
+    id
+}
 
 pub fn hash_to_g1(inp: &[u8]) -> G1 {
     hash_to_g1_domain(DOMAIN_STRING_HASH_ID, inp)
 }
 
 pub fn hash_to_g1_domain(dom: &[u8], inp: &[u8]) -> G1 {
-    let hasher = <G1Hasher as HashToCurve<G1>>::new(dom).expect("failed to create hasher");
+    hash_to_g1_on::<Curve>(dom, inp)
+}
+
+/// hashes an identity of the game ctx into G1
+pub fn hash_to_g1_ctx(ctx: &GameContext, inp: &[u8]) -> G1 {
+    hash_to_g1_ctx_on::<Curve>(ctx, inp)
+}
+
+/// hash_to_g1_ctx of every one of inps, setting the hasher up only once
+pub fn hash_to_g1_ctx_many<I: AsRef<[u8]>>(ctx: &GameContext, inps: &[I]) -> Vec<G1> {
+    hash_to_g1_many_on::<Curve, I>(&ctx.domain(Curve::DOMAIN_STRING_HASH_ID), inps)
+}
+
+/// hashes identities into G2, for deployments that keep public keys in G1
+pub fn hash_to_g2(inp: &[u8]) -> G2 {
+    hash_to_g2_domain(DOMAIN_STRING_HASH_ID_G2, inp)
+}
+
+pub fn hash_to_g2_domain(dom: &[u8], inp: &[u8]) -> G2 {
+    hash_to_g2_on::<Curve>(dom, inp)
+}
+
+/// hashes an identity of the game ctx into G2
+pub fn hash_to_g2_ctx(ctx: &GameContext, inp: &[u8]) -> G2 {
+    hash_to_g2_ctx_on::<Curve>(ctx, inp)
+}
+
+/// hash_to_g1_domain on the curve E rather than on Curve
+pub fn hash_to_g1_on<E: CurveConfig>(dom: &[u8], inp: &[u8]) -> E::G1 {
+    let hasher = E::G1Hasher::new(dom).expect("failed to create hasher");
+    hasher.hash(inp).expect("failed to hash").into()
+}
+
+/// hash_to_g1_on of every one of inps, with one hasher for all of them
+pub fn hash_to_g1_many_on<E: CurveConfig, I: AsRef<[u8]>>(dom: &[u8], inps: &[I]) -> Vec<E::G1> {
+    let hasher = E::G1Hasher::new(dom).expect("failed to create hasher");
+    inps.iter()
+        .map(|inp| hasher.hash(inp.as_ref()).expect("failed to hash").into())
+        .collect()
+}
+
+/// hash_to_g1_ctx on the curve E rather than on Curve
+pub fn hash_to_g1_ctx_on<E: CurveConfig>(ctx: &GameContext, inp: &[u8]) -> E::G1 {
+    hash_to_g1_on::<E>(&ctx.domain(E::DOMAIN_STRING_HASH_ID), inp)
+}
+
+/// hash_to_g2_domain on the curve E rather than on Curve
+pub fn hash_to_g2_on<E: CurveConfig>(dom: &[u8], inp: &[u8]) -> E::G2 {
+    let hasher = E::G2Hasher::new(dom).expect("failed to create hasher");
     hasher.hash(inp).expect("failed to hash").into()
 }
+
+/// hash_to_g2_ctx on the curve E rather than on Curve
+pub fn hash_to_g2_ctx_on<E: CurveConfig>(ctx: &GameContext, inp: &[u8]) -> E::G2 {
+    hash_to_g2_on::<E>(&ctx.domain(E::DOMAIN_STRING_HASH_ID_G2), inp)
+}
+
+/// IdCache keeps H(id), and e(H(id), pk) for one pk, of the identities of one
+/// game, which are hashed and paired again every time they are encrypted to.
+/// It holds at most capacity identities, e.g. those of a deck, and computes any
+/// others afresh.
+pub struct IdCache<E: CurveConfig = Curve> {
+    ctx: GameContext,
+    capacity: usize,
+    hashes: HashMap<Vec<u8>, E::G1>,
+    /// the pk of the pairings, if any
+    pk: Option<E::G2>,
+    pairings: HashMap<Vec<u8>, PairingOutput<E>>,
+}
+
+impl<E: CurveConfig> IdCache<E> {
+    pub fn new(ctx: GameContext, capacity: usize) -> Self {
+        IdCache {
+            ctx,
+            capacity,
+            hashes: HashMap::with_capacity(capacity),
+            pk: None,
+            pairings: HashMap::with_capacity(capacity),
+        }
+    }
+
+    pub fn context(&self) -> &GameContext {
+        &self.ctx
+    }
+
+    /// hash_to_g1_ctx of id in the game of the cache
+    pub fn hash(&mut self, id: &[u8]) -> E::G1 {
+        if let Some(hash) = self.hashes.get(id) {
+            return *hash;
+        }
+        let hash = hash_to_g1_ctx_on::<E>(&self.ctx, id);
+        if self.hashes.len() < self.capacity {
+            self.hashes.insert(id.to_vec(), hash);
+        }
+        hash
+    }
+
+    /// e(H(id), pk); the pairings with another pk are dropped first
+    pub fn pairing(&mut self, id: &[u8], pk: &E::G2) -> PairingOutput<E> {
+        if self.pk != Some(*pk) {
+            self.invalidate();
+            self.pk = Some(*pk);
+        }
+        if let Some(pairing) = self.pairings.get(id) {
+            return *pairing;
+        }
+        let pairing = E::pairing(self.hash(id), pk);
+        if self.pairings.len() < self.capacity {
+            self.pairings.insert(id.to_vec(), pairing);
+        }
+        pairing
+    }
+
+    /// drops the pairings, which hold for the old pk only, when the pk changes;
+    /// the hashes are kept
+    pub fn invalidate(&mut self) {
+        self.pk = None;
+        self.pairings.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        card_id, hash_to_g1, hash_to_g1_ctx, hash_to_g1_ctx_many, hash_to_g1_ctx_on, hash_to_g1_on,
+        hash_to_g2, hash_to_g2_ctx_on, hash_to_g2_domain, hash_to_g2_on, GameContext, IdCache,
+        DOMAIN_STRING_HASH_ID, DOMAIN_STRING_HASH_ID_G2,
+    };
+    use crate::common::{Curve, CurveConfig, F, G2};
+    use ark_ec::pairing::Pairing;
+    use ark_ec::{CurveGroup, Group};
+    use ark_ff::{PrimeField, Zero};
+
+    // identities hashed under other domain strings land on other points, so a
+    // changed domain string would silently break every deployed ciphertext
+    #[cfg(feature = "bls12_381")]
+    #[test]
+    fn test_bls12_381_domain_strings_are_pinned() {
+        type E = ark_bls12_381::Bls12_381;
+        assert_eq!(
+            E::DOMAIN_STRING_HASH_ID,
+            b"SUPRA_POKER_ID-hashtoG1-with-BLS12381G1_XMD:SHA-256_SSWU_RO"
+        );
+        assert_eq!(
+            E::DOMAIN_STRING_HASH_ID_G2,
+            b"SUPRA_POKER_ID-hashtoG2-with-BLS12381G2_XMD:SHA-256_SSWU_RO"
+        );
+    }
+
+    #[cfg(feature = "bls12_377")]
+    #[test]
+    fn test_bls12_377_domain_strings_are_pinned() {
+        type E = ark_bls12_377::Bls12_377;
+        assert_eq!(
+            E::DOMAIN_STRING_HASH_ID,
+            b"SUPRA_POKER_ID-hashtoG1-with-BLS12377G1_XMD:SHA-256_SSWU_RO"
+        );
+        assert_eq!(
+            E::DOMAIN_STRING_HASH_ID_G2,
+            b"SUPRA_POKER_ID-hashtoG2-with-BLS12377G2_XMD:SHA-256_SSWU_RO"
+        );
+    }
+
+    #[cfg(feature = "bn254")]
+    #[test]
+    fn test_bn254_domain_strings_are_pinned() {
+        type E = ark_bn254::Bn254;
+        assert_eq!(
+            E::DOMAIN_STRING_HASH_ID,
+            b"SUPRA_POKER_ID-hashtoG1-with-BN254G1_XMD:SHA-256_TAI_RO"
+        );
+        assert_eq!(
+            E::DOMAIN_STRING_HASH_ID_G2,
+            b"SUPRA_POKER_ID-hashtoG2-with-BN254G2_XMD:SHA-256_TAI_RO"
+        );
+    }
+
+    fn check_hash_on<E: CurveConfig>() {
+        let ctx = GameContext::new([3u8; 32]);
+        let order = <E::ScalarField as PrimeField>::MODULUS;
+        for id in [&b""[..], b"abc", &[7u8; 100]] {
+            let h1 = hash_to_g1_ctx_on::<E>(&ctx, id);
+            assert!(!h1.is_zero() && h1.mul_bigint(order).is_zero());
+            let h2 = hash_to_g2_ctx_on::<E>(&ctx, id);
+            assert!(!h2.is_zero() && h2.mul_bigint(order).is_zero());
+        }
+        assert_ne!(
+            hash_to_g1_ctx_on::<E>(&ctx, b"abc"),
+            hash_to_g1_on::<E>(E::DOMAIN_STRING_HASH_ID, b"abc")
+        );
+    }
+
+    #[cfg(feature = "bls12_381")]
+    #[test]
+    fn test_hash_on_bls12_381() {
+        check_hash_on::<ark_bls12_381::Bls12_381>();
+    }
+
+    #[cfg(feature = "bls12_377")]
+    #[test]
+    fn test_hash_on_bls12_377() {
+        check_hash_on::<ark_bls12_377::Bls12_377>();
+    }
+
+    #[cfg(feature = "bn254")]
+    #[test]
+    fn test_hash_on_bn254() {
+        check_hash_on::<ark_bn254::Bn254>();
+    }
+
+    #[test]
+    fn test_hash_many_matches_one_by_one() {
+        let ctx = GameContext::new([4u8; 32]);
+        let ids = (0..5).map(|i| card_id(&ctx, i, 0)).collect::<Vec<_>>();
+        let hashes = hash_to_g1_ctx_many(&ctx, &ids);
+        assert_eq!(hashes.len(), ids.len());
+        for (id, h) in ids.iter().zip(hashes) {
+            assert_eq!(h, hash_to_g1_ctx(&ctx, id));
+        }
+        assert!(hash_to_g1_ctx_many::<Vec<u8>>(&ctx, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_hash_to_g2() {
+        for id in [&b""[..], b"abc", &[7u8; 100]] {
+            let h = hash_to_g2(id).into_affine();
+            assert!(h.is_on_curve());
+            assert!(h.is_in_correct_subgroup_assuming_on_curve());
+            assert_eq!(hash_to_g2(id), hash_to_g2(id));
+        }
+
+        assert_ne!(hash_to_g2(b"abc"), hash_to_g2(b"abd"));
+        assert_ne!(hash_to_g2(b"abc"), hash_to_g2_domain(b"other", b"abc"));
+        assert_eq!(
+            hash_to_g2(b"abc"),
+            hash_to_g2_on::<Curve>(<Curve as CurveConfig>::DOMAIN_STRING_HASH_ID_G2, b"abc")
+        );
+        assert_eq!(DOMAIN_STRING_HASH_ID_G2, Curve::DOMAIN_STRING_HASH_ID_G2);
+    }
+
+    #[test]
+    fn test_card_ids_are_bound_to_the_game() {
+        let (game_a, game_b) = (GameContext::new([1u8; 32]), GameContext::new([2u8; 32]));
+
+        assert_ne!(card_id(&game_a, 17, 0), card_id(&game_b, 17, 0));
+        assert_ne!(card_id(&game_a, 17, 0), card_id(&game_a, 17, 1));
+        assert_ne!(card_id(&game_a, 17, 0), card_id(&game_a, 18, 0));
+
+        // even the same identity bytes land on different points in different games
+        let id = card_id(&game_a, 17, 0);
+        assert_ne!(hash_to_g1_ctx(&game_a, &id), hash_to_g1_ctx(&game_b, &id));
+        assert_ne!(hash_to_g1_ctx(&game_a, &id), hash_to_g1(&id));
+        assert_eq!(DOMAIN_STRING_HASH_ID, Curve::DOMAIN_STRING_HASH_ID);
+        assert_eq!(
+            hash_to_g1_ctx(&game_a, &id),
+            hash_to_g1_ctx_on::<Curve>(&game_a, &id)
+        );
+        assert_ne!(
+            hash_to_g1_ctx(&game_a, &card_id(&game_a, 17, 0)),
+            hash_to_g1_ctx(&game_b, &card_id(&game_b, 17, 0))
+        );
+
+        assert_eq!(
+            GameContext::from_label("table-1"),
+            GameContext::from_label("table-1")
+        );
+        assert_ne!(
+            GameContext::from_label("table-1"),
+            GameContext::from_label("table-2")
+        );
+        assert_ne!(game_a.label("ibe_c1"), game_b.label("ibe_c1"));
+    }
+
+    #[test]
+    fn test_id_cache_hits_match_fresh_computation() {
+        let ctx = GameContext::new([5u8; 32]);
+        let pk = G2::generator() * F::from(11u64);
+        let ids: Vec<Vec<u8>> = (0..4).map(|i| card_id(&ctx, i, 0)).collect();
+        let mut cache = IdCache::new(ctx, 3);
+
+        for _ in 0..2 {
+            for id in ids.iter() {
+                let hash = hash_to_g1_ctx(&ctx, id);
+                assert_eq!(cache.hash(id), hash);
+                assert_eq!(
+                    cache.pairing(id, &pk),
+                    <Curve as Pairing>::pairing(hash, pk)
+                );
+            }
+        }
+        // the fourth identity is past the capacity, and is computed afresh every time
+        assert_eq!(cache.hashes.len(), 3);
+        assert_eq!(cache.pairings.len(), 3);
+        assert!(!cache.hashes.contains_key(&ids[3]));
+    }
+
+    #[test]
+    fn test_id_cache_is_invalidated_by_another_pk() {
+        let ctx = GameContext::new([6u8; 32]);
+        let (pk_a, pk_b) = (
+            G2::generator() * F::from(11u64),
+            G2::generator() * F::from(13u64),
+        );
+        let id = card_id(&ctx, 7, 0);
+        let fresh = |pk: G2| <Curve as Pairing>::pairing(hash_to_g1_ctx(&ctx, &id), pk);
+        let mut cache = IdCache::new(ctx, 52);
+
+        assert_eq!(cache.pairing(&id, &pk_a), fresh(pk_a));
+        assert_eq!(cache.pairing(&id, &pk_b), fresh(pk_b));
+        assert_eq!(cache.pairing(&id, &pk_a), fresh(pk_a));
+        assert_eq!(cache.pairings.len(), 1);
+
+        cache.invalidate();
+        assert!(cache.pairings.is_empty());
+        assert_eq!(cache.hashes.len(), 1);
+        assert_eq!(cache.pairing(&id, &pk_b), fresh(pk_b));
+    }
+}
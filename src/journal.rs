@@ -0,0 +1,235 @@
+//! An append-only file of what a MessagingSystem must not lose when its process
+//! restarts: the messages it sent that are not acknowledged yet, and the values it
+//! received that no receive has taken yet. Each record is a line of JSON; the file
+//! is rewritten with only what is still live once most of it is not.
+
+use serde::{Deserialize, Serialize};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::{error, fmt};
+
+use crate::address_book::Pok3rPeerId;
+
+/// JournalError explains why a journal could not be opened
+#[derive(Debug)]
+pub enum JournalError {
+    Io(io::Error),
+    /// the record on this line (counting from 1) cannot be read
+    Malformed(usize),
+    /// the journal was kept in another session
+    WrongSession {
+        expected: String,
+        found: String,
+    },
+}
+
+impl fmt::Display for JournalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            JournalError::Io(err) => write!(f, "cannot access the journal: {}", err),
+            JournalError::Malformed(line) => write!(f, "malformed journal record on line {}", line),
+            JournalError::WrongSession { expected, found } => write!(
+                f,
+                "journal of session {} cannot be reloaded in session {}",
+                found, expected
+            ),
+        }
+    }
+}
+
+impl error::Error for JournalError {}
+
+impl From<io::Error> for JournalError {
+    fn from(err: io::Error) -> Self {
+        JournalError::Io(err)
+    }
+}
+
+/// JournalRecord is one line of the journal
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(tag = "type")]
+pub(crate) enum JournalRecord {
+    /// always the first record; sequence numbers below next_seq may not be reused
+    Session { session: String, next_seq: u64 },
+    /// a message we sent, in the wire encoding and base58, that recipients have
+    /// not acknowledged yet
+    Sent {
+        seq: u64,
+        recipients: Vec<Pok3rPeerId>,
+        msg: String,
+    },
+    /// peer acknowledged our message seq
+    Acked { seq: u64, peer: Pok3rPeerId },
+    /// sender's value for handle went into a mailbox, the direct one if direct
+    Received {
+        handle: String,
+        sender: Pok3rPeerId,
+        value: String,
+        direct: bool,
+    },
+    /// a receive took sender's value for handle out of its mailbox
+    Taken {
+        handle: String,
+        sender: Pok3rPeerId,
+        direct: bool,
+    },
+}
+
+/// Journal appends records to its file, flushing each one, so that they survive
+/// the process (though not the machine) going down
+pub(crate) struct Journal {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    /// records in the file
+    records: usize,
+}
+
+impl Journal {
+    /// opens the journal at path, creating it if there is none, and returns it with
+    /// the records it holds. A last record cut short by a crash is dropped.
+    pub(crate) fn open(path: &Path) -> Result<(Journal, Vec<JournalRecord>), JournalError> {
+        let mut records = vec![];
+        if path.exists() {
+            let mut reader = BufReader::new(File::open(path)?);
+            let mut line = String::new();
+            let mut number = 0;
+            loop {
+                line.clear();
+                if reader.read_line(&mut line)? == 0 {
+                    break;
+                }
+                number += 1;
+                if !line.ends_with('\n') {
+                    // cut short while it was being written
+                    break;
+                }
+                let record =
+                    serde_json::from_str(&line).map_err(|_| JournalError::Malformed(number))?;
+                records.push(record);
+            }
+        }
+
+        let journal = Journal {
+            path: path.to_path_buf(),
+            writer: BufWriter::new(OpenOptions::new().create(true).append(true).open(path)?),
+            records: records.len(),
+        };
+        Ok((journal, records))
+    }
+
+    pub(crate) fn append(&mut self, record: &JournalRecord) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, record)?;
+        self.writer.write_all(b"\n")?;
+        self.writer.flush()?;
+        self.records += 1;
+        Ok(())
+    }
+
+    /// replaces what the journal holds with records; they are written next to it
+    /// and moved over it, so that a crash meanwhile leaves the old records intact
+    pub(crate) fn rewrite(&mut self, records: &[JournalRecord]) -> io::Result<()> {
+        let tmp_path = self.path.with_extension("tmp");
+        let mut writer = BufWriter::new(File::create(&tmp_path)?);
+        for record in records {
+            serde_json::to_writer(&mut writer, record)?;
+            writer.write_all(b"\n")?;
+        }
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        drop(writer);
+        fs::rename(&tmp_path, &self.path)?;
+
+        self.writer = BufWriter::new(OpenOptions::new().append(true).open(&self.path)?);
+        self.records = records.len();
+        Ok(())
+    }
+
+    /// records in the file, live or not
+    pub(crate) fn records(&self) -> usize {
+        self.records
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Journal, JournalError, JournalRecord};
+    use crate::transport::{InMemoryTransport, Transport};
+    use futures::future::join_all;
+    use std::io::Write;
+
+    #[test]
+    fn test_records_survive_reopening() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal-reopen");
+        let records = vec![
+            JournalRecord::Session {
+                session: String::from("table-1"),
+                next_seq: 0,
+            },
+            JournalRecord::Received {
+                handle: String::from("h"),
+                sender: String::from("peer"),
+                value: String::from("v"),
+                direct: false,
+            },
+        ];
+
+        let (mut journal, reloaded) = Journal::open(&path).unwrap();
+        assert!(reloaded.is_empty());
+        for record in records.iter() {
+            journal.append(record).unwrap();
+        }
+        drop(journal);
+        let (mut journal, reloaded) = Journal::open(&path).unwrap();
+        assert_eq!(reloaded, records);
+
+        journal.rewrite(&records[..1]).unwrap();
+        assert_eq!(journal.records(), 1);
+        drop(journal);
+
+        // a record cut short by a crash is dropped, a garbled one is refused
+        let mut file = std::fs::OpenOptions::new()
+            .append(true)
+            .open(&path)
+            .unwrap();
+        file.write_all(b"{\"type\":\"Acked\",\"se").unwrap();
+        assert_eq!(Journal::open(&path).unwrap().1, records[..1].to_vec());
+        file.write_all(b"\n").unwrap();
+        assert!(matches!(
+            Journal::open(&path),
+            Err(JournalError::Malformed(2))
+        ));
+    }
+
+    #[test]
+    fn test_journal_stays_bounded_over_many_rounds() {
+        let dir = tempfile::tempdir().unwrap();
+        let paths = (0..3)
+            .map(|i| dir.path().join(format!("journal-gc-{}", i)))
+            .collect::<Vec<_>>();
+        async_std::task::block_on(async {
+            let mut transports = InMemoryTransport::new_network(3).await;
+            for (transport, path) in transports.iter_mut().zip(paths.iter()) {
+                transport.open_journal(path).unwrap();
+            }
+            join_all(transports.iter_mut().map(|transport| async move {
+                for round in 0..300 {
+                    let handle = format!("round-{}", round);
+                    let value = format!("{}", transport.get_my_id());
+                    transport.send_to_all(&[&handle], &[&value]).await.unwrap();
+                    let values = transport.recv_from_all(&handle).await.unwrap();
+                    assert_eq!(values.len(), 2);
+                }
+                transport.flush().await.unwrap();
+            }))
+            .await;
+        });
+
+        // 300 rounds write some 2000 records, nearly all of them dead by the end
+        for path in paths.iter() {
+            let lines = std::fs::read_to_string(path).unwrap().lines().count();
+            assert!(lines < 300, "{}", lines);
+        }
+    }
+}
@@ -0,0 +1,303 @@
+//! Identities for operators: fresh ed25519 keypairs, the peer ids libp2p derives
+//! from them, key files encrypted under a passphrase, and the addr book of a set
+//! of peer ids.
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use hmac::Hmac;
+use libp2p::{identity, PeerId};
+use rand::{CryptoRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::{error, fmt};
+use zeroize::Zeroizing;
+
+use crate::address_book::{AddrBook, AddrBookError, Pok3rAddrBook, Pok3rPeerId};
+
+/// the keypair a party signs with, and that its peer id names
+pub type PeerKeypair = identity::Keypair;
+
+/// version of the key file format written by save_keypair
+const KEY_FILE_VERSION: u32 = 1;
+
+/// PBKDF2 rounds deriving the file key from the passphrase
+const KDF_ROUNDS: u32 = 200_000;
+
+/// the most rounds load_keypair runs for a file, so that a tampered one cannot
+/// keep it busy for long
+const MAX_KDF_ROUNDS: u32 = 16 * KDF_ROUNDS;
+
+#[derive(Debug)]
+pub enum KeyError {
+    Io(io::Error),
+    /// the file is not a key file written by save_keypair
+    Malformed(String),
+    /// the key file was written by a later version
+    UnsupportedVersion(u32),
+    /// the passphrase is wrong, or the file was tampered with
+    WrongPassphrase,
+}
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyError::Io(err) => write!(f, "cannot access the key file: {}", err),
+            KeyError::Malformed(reason) => write!(f, "malformed key file: {}", reason),
+            KeyError::UnsupportedVersion(version) => {
+                write!(f, "key file version {} is not supported", version)
+            }
+            KeyError::WrongPassphrase => {
+                write!(f, "wrong passphrase, or the key file was tampered with")
+            }
+        }
+    }
+}
+
+impl error::Error for KeyError {}
+
+impl From<io::Error> for KeyError {
+    fn from(err: io::Error) -> Self {
+        KeyError::Io(err)
+    }
+}
+
+/// what a key file holds, all bytes in base58
+#[derive(Serialize, Deserialize)]
+struct KeyFile {
+    version: u32,
+    /// the peer id of the keypair, in the clear so that operators can tell key
+    /// files apart; it is authenticated along with the secret key
+    peer_id: Pok3rPeerId,
+    rounds: u32,
+    salt: String,
+    nonce: String,
+    /// the 32 byte ed25519 secret key, sealed with ChaCha20-Poly1305
+    ciphertext: String,
+}
+
+/// generates an ed25519 keypair from rng, along with its base58 peer id, the
+/// form the addr book lists peers in
+pub fn generate_keypair<R: RngCore + CryptoRng>(rng: &mut R) -> (PeerKeypair, Pok3rPeerId) {
+    let mut secret = Zeroizing::new([0u8; 32]);
+    rng.fill_bytes(secret.as_mut());
+    let keypair =
+        identity::Keypair::ed25519_from_bytes(&mut *secret).expect("only errors on wrong length");
+    let peer_id = peer_id_of(&keypair);
+    (keypair, peer_id)
+}
+
+/// the base58 peer id of keypair
+pub fn peer_id_of(keypair: &PeerKeypair) -> Pok3rPeerId {
+    PeerId::from(keypair.public()).to_base58()
+}
+
+/// writes the secret key of keypair to path, encrypted under passphrase; on unix
+/// only the owner may read the file
+pub fn save_keypair(path: &Path, keypair: &PeerKeypair, passphrase: &str) -> Result<(), KeyError> {
+    let secret = keypair
+        .clone()
+        .try_into_ed25519()
+        .map_err(|_| KeyError::Malformed(String::from("only ed25519 keys are supported")))?
+        .secret();
+    let peer_id = peer_id_of(keypair);
+
+    let mut rng = rand::thread_rng();
+    let (mut salt, mut nonce) = ([0u8; 16], [0u8; 12]);
+    rng.fill_bytes(&mut salt);
+    rng.fill_bytes(&mut nonce);
+    let key = derive_key(passphrase, &salt, KDF_ROUNDS);
+    let ciphertext = ChaCha20Poly1305::new(Key::from_slice(key.as_ref()))
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: secret.as_ref(),
+                aad: peer_id.as_bytes(),
+            },
+        )
+        .expect("only errors on oversized inputs");
+
+    let file = KeyFile {
+        version: KEY_FILE_VERSION,
+        peer_id,
+        rounds: KDF_ROUNDS,
+        salt: bs58::encode(salt).into_string(),
+        nonce: bs58::encode(nonce).into_string(),
+        ciphertext: bs58::encode(ciphertext).into_string(),
+    };
+    let contents = serde_json::to_string_pretty(&file).expect("a key file always serializes");
+
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut writer = options.open(path)?;
+    writer.write_all(contents.as_bytes())?;
+    writer.write_all(b"\n")?;
+    Ok(())
+}
+
+/// reads the keypair save_keypair wrote to path under passphrase
+pub fn load_keypair(path: &Path, passphrase: &str) -> Result<PeerKeypair, KeyError> {
+    let file: KeyFile = serde_json::from_str(&fs::read_to_string(path)?)
+        .map_err(|e| KeyError::Malformed(e.to_string()))?;
+    if file.version != KEY_FILE_VERSION {
+        return Err(KeyError::UnsupportedVersion(file.version));
+    }
+    let decode = |field: &str, value: &str| {
+        bs58::decode(value)
+            .into_vec()
+            .map_err(|_| KeyError::Malformed(format!("{} is not base58", field)))
+    };
+    let salt = decode("salt", &file.salt)?;
+    let nonce = decode("nonce", &file.nonce)?;
+    let ciphertext = decode("ciphertext", &file.ciphertext)?;
+    if nonce.len() != 12 {
+        return Err(KeyError::Malformed(String::from(
+            "the nonce is not 12 bytes",
+        )));
+    }
+
+    if !(KDF_ROUNDS..=MAX_KDF_ROUNDS).contains(&file.rounds) {
+        return Err(KeyError::Malformed(format!(
+            "{} rounds is not within {}..={}",
+            file.rounds, KDF_ROUNDS, MAX_KDF_ROUNDS
+        )));
+    }
+    let key = derive_key(passphrase, &salt, file.rounds);
+    let mut secret = Zeroizing::new(
+        ChaCha20Poly1305::new(Key::from_slice(key.as_ref()))
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad: file.peer_id.as_bytes(),
+                },
+            )
+            .map_err(|_| KeyError::WrongPassphrase)?,
+    );
+    let keypair = identity::Keypair::ed25519_from_bytes(&mut *secret)
+        .map_err(|_| KeyError::Malformed(String::from("the secret key is not 32 bytes")))?;
+    if peer_id_of(&keypair) != file.peer_id {
+        return Err(KeyError::Malformed(String::from(
+            "the secret key does not match the peer id",
+        )));
+    }
+    Ok(keypair)
+}
+
+/// PBKDF2-HMAC-SHA256 of passphrase, for a 32 byte key
+fn derive_key(passphrase: &str, salt: &[u8], rounds: u32) -> Zeroizing<[u8; 32]> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    pbkdf2::pbkdf2::<Hmac<Sha256>>(passphrase.as_bytes(), salt, rounds, key.as_mut());
+    key
+}
+
+/// the addr book of peer_ids, whatever their order: node ids 1, 2, ... go to the
+/// peer ids sorted, so that every operator derives the same book
+pub fn addr_book_from_keys(peer_ids: &[Pok3rPeerId]) -> Result<Pok3rAddrBook, AddrBookError> {
+    let mut sorted = peer_ids.to_vec();
+    sorted.sort();
+    AddrBook::from_peer_ids(sorted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::address_book::{keypair_from_seed, parse_addr_book_from_file, ADDRESSES};
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+    use serde_json::json;
+
+    #[test]
+    fn test_generated_ids_load_as_an_addr_book() {
+        let mut rng = ChaCha20Rng::seed_from_u64(7);
+        let peer_ids = (0..4)
+            .map(|_| generate_keypair(&mut rng).1)
+            .collect::<Vec<Pok3rPeerId>>();
+        assert!(peer_ids
+            .iter()
+            .all(|peer_id| peer_id.starts_with("12D3KooW")));
+
+        let addr_book = addr_book_from_keys(&peer_ids).unwrap();
+        let mut reversed = peer_ids.clone();
+        reversed.reverse();
+        assert_eq!(addr_book, addr_book_from_keys(&reversed).unwrap());
+
+        // through the file format, where each peer id is validated
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keygen-addr-book.json");
+        fs::write(&path, json!({ "addr_book": addr_book }).to_string()).unwrap();
+        let parsed = parse_addr_book_from_file(&path).unwrap();
+        assert_eq!(parsed, addr_book);
+
+        let mut sorted = peer_ids.clone();
+        sorted.sort();
+        for (i, peer_id) in sorted.iter().enumerate() {
+            assert_eq!(parsed.node_of(peer_id), Some(i as u64 + 1));
+        }
+        assert!(matches!(
+            addr_book_from_keys(&[peer_ids[0].clone(), peer_ids[0].clone()]),
+            Err(AddrBookError::DuplicatePeerId(_))
+        ));
+    }
+
+    #[test]
+    fn test_derive_key_is_pbkdf2() {
+        // RFC 7914, section 11: PBKDF2-HMAC-SHA256("passwd", "salt", 1)
+        let expected = [
+            0x55, 0xac, 0x04, 0x6e, 0x56, 0xe3, 0x08, 0x9f, 0xec, 0x16, 0x91, 0xc2, 0x25, 0x44,
+            0xb6, 0x05, 0xf9, 0x41, 0x85, 0x21, 0x6d, 0xde, 0x04, 0x65, 0xe6, 0x8b, 0x9d, 0x57,
+            0xc2, 0x0d, 0xac, 0xbc,
+        ];
+        assert_eq!(*derive_key("passwd", b"salt", 1), expected);
+        assert_ne!(*derive_key("passwd", b"salt", 2), expected);
+    }
+
+    #[test]
+    fn test_builtin_peer_ids_match_their_keys() {
+        assert_eq!(peer_id_of(&keypair_from_seed(1)), ADDRESSES[0]);
+    }
+
+    #[test]
+    fn test_saved_keypair_needs_the_passphrase() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("keygen.key");
+        let (keypair, peer_id) = generate_keypair(&mut ChaCha20Rng::seed_from_u64(8));
+        save_keypair(&path, &keypair, "correct horse").unwrap();
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let secret = keypair.clone().try_into_ed25519().unwrap().secret();
+        assert!(!contents.contains(&bs58::encode(secret.as_ref()).into_string()));
+        assert_eq!(
+            peer_id_of(&load_keypair(&path, "correct horse").unwrap()),
+            peer_id
+        );
+        assert!(matches!(
+            load_keypair(&path, "battery staple"),
+            Err(KeyError::WrongPassphrase)
+        ));
+
+        // the peer id in the clear is bound to the secret key
+        let (_, other) = generate_keypair(&mut ChaCha20Rng::seed_from_u64(9));
+        fs::write(&path, contents.replace(&peer_id, &other)).unwrap();
+        assert!(matches!(
+            load_keypair(&path, "correct horse"),
+            Err(KeyError::WrongPassphrase)
+        ));
+
+        // too few rounds weaken the key, too many stall the loader
+        for rounds in [1, KDF_ROUNDS - 1, MAX_KDF_ROUNDS + 1, u32::MAX] {
+            let rounds_field = format!("\"rounds\": {}", KDF_ROUNDS);
+            let tampered = contents.replace(&rounds_field, &format!("\"rounds\": {}", rounds));
+            assert_ne!(tampered, contents);
+            fs::write(&path, tampered).unwrap();
+            assert!(matches!(
+                load_keypair(&path, "correct horse"),
+                Err(KeyError::Malformed(_))
+            ));
+        }
+    }
+}
@@ -3,16 +3,26 @@
 
 use ark_ec::{pairing::Pairing, AffineRepr, CurveGroup};
 use ark_ec::{scalar_mul::fixed_base::FixedBase, VariableBaseMSM};
-use ark_ff::{One, PrimeField, UniformRand, Zero};
+use ark_ff::{batch_inversion, BigInteger, Field, One, PrimeField, UniformRand, Zero};
 use ark_poly::DenseUVPolynomial;
-use ark_serialize::{CanonicalDeserialize, CanonicalSerialize};
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
 use ark_std::{
     marker::PhantomData,
     ops::{Div, Mul, Sub},
     vec,
 };
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::{error, fmt};
 
-use ark_std::rand::RngCore;
+use ark_std::rand::{CryptoRng, RngCore};
+use rand::rngs::OsRng;
+
+use crate::common::{Curve, Fq, Fq2, G1, G2};
+
+type G1Affine = <G1 as CurveGroup>::Affine;
+type G2Affine = <G2 as CurveGroup>::Affine;
 
 pub struct KZG10<E: Pairing, P: DenseUVPolynomial<E::ScalarField>> {
     _engine: PhantomData<E>,
@@ -27,6 +37,330 @@ pub struct UniversalParams<E: Pairing> {
     pub powers_of_h: Vec<E::G2Affine>,
 }
 
+/// ParamsError describes why universal parameters could not be loaded or saved
+#[derive(Debug)]
+pub enum ParamsError {
+    Io(io::Error),
+    Serialization(SerializationError),
+    /// the .ptau file is malformed or made for another curve
+    BadPtau(String),
+    /// the file holds fewer powers than the requested degree needs
+    TooFewPowers {
+        needed: usize,
+        available: usize,
+    },
+    /// the powers of g and h are not powers of the same trapdoor
+    Inconsistent,
+}
+
+impl fmt::Display for ParamsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParamsError::Io(err) => write!(f, "cannot access the params file: {}", err),
+            ParamsError::Serialization(err) => write!(f, "cannot decode the params: {}", err),
+            ParamsError::BadPtau(reason) => write!(f, "malformed ptau file: {}", reason),
+            ParamsError::TooFewPowers { needed, available } => write!(
+                f,
+                "params hold {} powers but {} are needed",
+                available, needed
+            ),
+            ParamsError::Inconsistent => {
+                write!(f, "params fail the pairing check on consecutive powers")
+            }
+        }
+    }
+}
+
+impl error::Error for ParamsError {}
+
+impl From<io::Error> for ParamsError {
+    fn from(err: io::Error) -> Self {
+        ParamsError::Io(err)
+    }
+}
+
+impl From<SerializationError> for ParamsError {
+    fn from(err: SerializationError) -> Self {
+        ParamsError::Serialization(err)
+    }
+}
+
+/// KzgError describes why a commitment or an opening proof could not be computed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KzgError {
+    /// the polynomial has more coefficients than the params have powers of g
+    TooFewPowers { needed: usize, available: usize },
+    /// the divisor of the opening is the zero polynomial
+    ZeroDivisor,
+    /// a multi-point opening was asked for no point, or for a point twice
+    BadPoints,
+}
+
+impl fmt::Display for KzgError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KzgError::TooFewPowers { needed, available } => write!(
+                f,
+                "params hold {} powers but the polynomial needs {}",
+                available, needed
+            ),
+            KzgError::ZeroDivisor => write!(f, "cannot divide by the zero polynomial"),
+            KzgError::BadPoints => write!(f, "the points are empty or repeat a point"),
+        }
+    }
+}
+
+impl error::Error for KzgError {}
+
+impl<E: Pairing> UniversalParams<E> {
+    /// the largest degree of a polynomial that can be committed in G1
+    pub fn max_degree(&self) -> usize {
+        self.powers_of_g.len().saturating_sub(1)
+    }
+
+    /// fails if a polynomial of degree does not fit in the powers of g
+    fn check_g1_powers(&self, degree: usize) -> Result<(), KzgError> {
+        if degree >= self.powers_of_g.len() {
+            return Err(KzgError::TooFewPowers {
+                needed: degree + 1,
+                available: self.powers_of_g.len(),
+            });
+        }
+        Ok(())
+    }
+
+    /// whether the powers of h reach degree, as needed to commit in G2
+    pub fn has_g2_powers(&self, degree: usize) -> bool {
+        self.powers_of_h.len() > degree
+    }
+
+    /// the shift D - max_degree of a degree-bound commitment, provided h^{τ^shift} is known
+    fn degree_shift(&self, max_degree: usize) -> Result<usize, ParamsError> {
+        if max_degree > self.max_degree() {
+            return Err(ParamsError::TooFewPowers {
+                needed: max_degree + 1,
+                available: self.powers_of_g.len(),
+            });
+        }
+        let shift = self.max_degree() - max_degree;
+        if !self.has_g2_powers(shift) {
+            return Err(ParamsError::TooFewPowers {
+                needed: shift + 1,
+                available: self.powers_of_h.len(),
+            });
+        }
+        Ok(shift)
+    }
+
+    /// writes the params in the compressed ark-serialize encoding
+    pub fn write_to_file(&self, path: &Path) -> Result<(), ParamsError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        self.serialize_compressed(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// keeps the powers up to max_degree
+    pub fn truncate(&mut self, max_degree: usize) -> Result<(), ParamsError> {
+        let available = std::cmp::min(self.powers_of_g.len(), self.powers_of_h.len());
+        if available < max_degree + 1 {
+            return Err(ParamsError::TooFewPowers {
+                needed: max_degree + 1,
+                available,
+            });
+        }
+        self.powers_of_g.truncate(max_degree + 1);
+        self.powers_of_h.truncate(max_degree + 1);
+        Ok(())
+    }
+
+    /// checks e(g^{τ^i}, h) == e(g^{τ^{i-1}}, h^τ), and likewise for the powers of h,
+    /// for all i at once through a linear combination drawn from rng
+    pub fn validate<R: RngCore + CryptoRng>(&self, rng: &mut R) -> Result<(), ParamsError> {
+        let g = &self.powers_of_g;
+        let h = &self.powers_of_h;
+        if g.len() < 2 || h.len() < 2 {
+            return Err(ParamsError::TooFewPowers {
+                needed: 2,
+                available: std::cmp::min(g.len(), h.len()),
+            });
+        }
+        if g[0].is_zero() || h[0].is_zero() {
+            return Err(ParamsError::Inconsistent);
+        }
+
+        let r_g: Vec<_> = (1..g.len())
+            .map(|_| E::ScalarField::rand(rng).into_bigint())
+            .collect();
+        let r_h: Vec<_> = (1..h.len())
+            .map(|_| E::ScalarField::rand(rng).into_bigint())
+            .collect();
+
+        let g_hi = <E::G1 as VariableBaseMSM>::msm_bigint(&g[1..], &r_g);
+        let g_lo = <E::G1 as VariableBaseMSM>::msm_bigint(&g[..g.len() - 1], &r_g);
+        let h_hi = <E::G2 as VariableBaseMSM>::msm_bigint(&h[1..], &r_h);
+        let h_lo = <E::G2 as VariableBaseMSM>::msm_bigint(&h[..h.len() - 1], &r_h);
+
+        if E::pairing(g_hi, h[0]) != E::pairing(g_lo, h[1])
+            || E::pairing(g[0], h_hi) != E::pairing(g[1], h_lo)
+        {
+            return Err(ParamsError::Inconsistent);
+        }
+        Ok(())
+    }
+}
+
+impl UniversalParams<Curve> {
+    /// reads params saved by write_to_file, or imported from a Powers-of-Tau (.ptau)
+    /// file in the snarkjs layout, keeps the powers up to max_degree, and validates them
+    /// with a combination drawn from the OS
+    pub fn read_from_file(path: &Path, max_degree: usize) -> Result<Self, ParamsError> {
+        let mut bytes = Vec::new();
+        BufReader::new(File::open(path)?).read_to_end(&mut bytes)?;
+        Self::from_bytes(&bytes, max_degree, &mut OsRng)
+    }
+
+    /// like read_from_file, for params already in memory; rng draws the
+    /// combination validate checks
+    pub fn from_bytes<R: RngCore + CryptoRng>(
+        bytes: &[u8],
+        max_degree: usize,
+        rng: &mut R,
+    ) -> Result<Self, ParamsError> {
+        let mut params = if bytes.starts_with(PTAU_MAGIC) {
+            read_ptau(bytes, max_degree)?
+        } else {
+            Self::deserialize_compressed(bytes)?
+        };
+        params.truncate(max_degree)?;
+        params.validate(rng)?;
+        Ok(params)
+    }
+}
+
+const PTAU_MAGIC: &[u8] = b"ptau";
+const PTAU_HEADER: u32 = 1;
+const PTAU_TAU_G1: u32 = 2;
+const PTAU_TAU_G2: u32 = 3;
+
+/// cursor over the little-endian fields of a .ptau file
+struct PtauReader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> PtauReader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], ParamsError> {
+        if self.bytes.len() - self.pos < len {
+            return Err(ParamsError::BadPtau("unexpected end of file".to_string()));
+        }
+        let taken = &self.bytes[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32, ParamsError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn u64(&mut self) -> Result<u64, ParamsError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    /// a base field element in Montgomery form, given the inverse of the Montgomery factor
+    fn fq(&mut self, n8: usize, r_inv: &Fq) -> Result<Fq, ParamsError> {
+        Ok(Fq::from_le_bytes_mod_order(self.take(n8)?) * r_inv)
+    }
+}
+
+/// reads the first max_degree + 1 powers of the tauG1 and tauG2 sections. Field
+/// elements are n8 bytes each, little-endian and in Montgomery form; points are
+/// uncompressed, with all-zero bytes for the point at infinity.
+fn read_ptau(bytes: &[u8], max_degree: usize) -> Result<UniversalParams<Curve>, ParamsError> {
+    let mut reader = PtauReader { bytes, pos: 0 };
+    reader.take(PTAU_MAGIC.len())?;
+    let _version = reader.u32()?;
+    let num_sections = reader.u32()?;
+
+    let mut sections = std::collections::HashMap::new();
+    for _ in 0..num_sections {
+        let kind = reader.u32()?;
+        let size = reader.u64()? as usize;
+        sections.insert(kind, reader.take(size)?);
+    }
+    let section = |kind: u32| {
+        sections
+            .get(&kind)
+            .map(|&bytes| PtauReader { bytes, pos: 0 })
+            .ok_or_else(|| ParamsError::BadPtau(format!("missing section {}", kind)))
+    };
+
+    let mut header = section(PTAU_HEADER)?;
+    let n8 = header.u32()? as usize;
+    if header.take(n8)? != Fq::MODULUS.to_bytes_le().as_slice() {
+        return Err(ParamsError::BadPtau(
+            "the file is for another curve".to_string(),
+        ));
+    }
+    let power = header.u32()?;
+    if power >= 32 {
+        return Err(ParamsError::BadPtau(format!(
+            "power {} is too large",
+            power
+        )));
+    }
+    let available = 1usize << power;
+    if available < max_degree + 1 {
+        return Err(ParamsError::TooFewPowers {
+            needed: max_degree + 1,
+            available,
+        });
+    }
+
+    // divides out the Montgomery factor 2^(8 * n8)
+    let r_inv = Fq::from(2u64).pow([8 * n8 as u64]).inverse().unwrap();
+
+    let mut tau_g1 = section(PTAU_TAU_G1)?;
+    let mut powers_of_g = Vec::with_capacity(max_degree + 1);
+    for _ in 0..=max_degree {
+        let (x, y) = (tau_g1.fq(n8, &r_inv)?, tau_g1.fq(n8, &r_inv)?);
+        let point = if x.is_zero() && y.is_zero() {
+            G1Affine::identity()
+        } else {
+            G1Affine::new_unchecked(x, y)
+        };
+        if !point.is_on_curve() || !point.is_in_correct_subgroup_assuming_on_curve() {
+            return Err(ParamsError::BadPtau(
+                "tauG1 holds an invalid point".to_string(),
+            ));
+        }
+        powers_of_g.push(point);
+    }
+
+    let mut tau_g2 = section(PTAU_TAU_G2)?;
+    let mut powers_of_h = Vec::with_capacity(max_degree + 1);
+    for _ in 0..=max_degree {
+        let x = Fq2::new(tau_g2.fq(n8, &r_inv)?, tau_g2.fq(n8, &r_inv)?);
+        let y = Fq2::new(tau_g2.fq(n8, &r_inv)?, tau_g2.fq(n8, &r_inv)?);
+        let point = if x.is_zero() && y.is_zero() {
+            G2Affine::identity()
+        } else {
+            G2Affine::new_unchecked(x, y)
+        };
+        if !point.is_on_curve() || !point.is_in_correct_subgroup_assuming_on_curve() {
+            return Err(ParamsError::BadPtau(
+                "tauG2 holds an invalid point".to_string(),
+            ));
+        }
+        powers_of_h.push(point);
+    }
+
+    Ok(UniversalParams {
+        powers_of_g,
+        powers_of_h,
+    })
+}
+
 impl<E, P> KZG10<E, P>
 where
     E: Pairing,
@@ -74,21 +408,27 @@ where
         value: &E::ScalarField,
         proof: &E::G1Affine,
     ) -> bool {
+        if params.powers_of_g.is_empty() || !params.has_g2_powers(1) {
+            return false;
+        }
         let g = params.powers_of_g[0];
         let h = params.powers_of_h[0];
         let beta_h = params.powers_of_h[1];
 
-        let inner = comm.into_group() - &g.mul(value);
+        let inner = comm.into_group() - g.mul(value);
         let lhs = E::pairing(inner, h);
 
-        let inner = beta_h.into_group() - &h.mul(point);
+        let inner = beta_h.into_group() - h.mul(point);
         let rhs = E::pairing(proof, inner);
 
         lhs == rhs
     }
 
-    pub fn commit_g1(params: &UniversalParams<E>, polynomial: &P) -> E::G1Affine {
+    /// the MSM runs on all cores with the parallel feature; fails if the params
+    /// hold fewer than degree + 1 powers of g
+    pub fn commit_g1(params: &UniversalParams<E>, polynomial: &P) -> Result<E::G1Affine, KzgError> {
         let d = polynomial.degree();
+        params.check_g1_powers(d)?;
 
         let plain_coeffs: Vec<<<E as Pairing>::ScalarField as PrimeField>::BigInt> =
             convert_to_bigints(polynomial.coeffs());
@@ -96,11 +436,21 @@ where
         let powers_of_g = &params.powers_of_g[..=d].to_vec();
         let commitment =
             <E::G1 as VariableBaseMSM>::msm_bigint(&powers_of_g[..], plain_coeffs.as_slice());
-        commitment.into_affine()
+        Ok(commitment.into_affine())
     }
 
-    pub fn commit_g2(params: &UniversalParams<E>, polynomial: &P) -> E::G2Affine {
+    /// fails if the params hold fewer than degree + 1 powers of h
+    pub fn commit_g2(
+        params: &UniversalParams<E>,
+        polynomial: &P,
+    ) -> Result<E::G2Affine, ParamsError> {
         let d = polynomial.degree();
+        if !params.has_g2_powers(d) {
+            return Err(ParamsError::TooFewPowers {
+                needed: d + 1,
+                available: params.powers_of_h.len(),
+            });
+        }
 
         let plain_coeffs: Vec<<<E as Pairing>::ScalarField as PrimeField>::BigInt> =
             convert_to_bigints(polynomial.coeffs());
@@ -109,14 +459,56 @@ where
         let commitment =
             <E::G2 as VariableBaseMSM>::msm_bigint(&powers_of_h[..], plain_coeffs.as_slice());
 
-        commitment.into_affine()
+        Ok(commitment.into_affine())
+    }
+
+    /// commits to X^{D - max_degree} * polynomial, where D is params.max_degree(); this
+    /// fits in the params only if polynomial has degree at most max_degree
+    pub fn commit_g1_shifted(
+        params: &UniversalParams<E>,
+        polynomial: &P,
+        max_degree: usize,
+    ) -> Result<E::G1Affine, ParamsError> {
+        let shift = params.degree_shift(max_degree)?;
+        let d = polynomial.degree();
+        if shift + d > params.max_degree() {
+            return Err(ParamsError::TooFewPowers {
+                needed: shift + d + 1,
+                available: params.powers_of_g.len(),
+            });
+        }
+
+        let plain_coeffs: Vec<<<E as Pairing>::ScalarField as PrimeField>::BigInt> =
+            convert_to_bigints(polynomial.coeffs());
+
+        let powers_of_g = &params.powers_of_g[shift..=shift + d];
+        let commitment = <E::G1 as VariableBaseMSM>::msm_bigint(powers_of_g, &plain_coeffs);
+        Ok(commitment.into_affine())
+    }
+
+    /// checks e(shifted_comm, h) == e(comm, h^{τ^{D - max_degree}}), i.e. that the
+    /// polynomial committed in comm has degree at most max_degree
+    pub fn verify_degree_bound(
+        params: &UniversalParams<E>,
+        comm: &E::G1Affine,
+        shifted_comm: &E::G1Affine,
+        max_degree: usize,
+    ) -> bool {
+        let shift = match params.degree_shift(max_degree) {
+            Ok(shift) => shift,
+            Err(_) => return false,
+        };
+
+        let lhs = E::pairing(shifted_comm, params.powers_of_h[0]);
+        let rhs = E::pairing(comm, params.powers_of_h[shift]);
+        lhs == rhs
     }
 
     pub fn compute_opening_proof(
         params: &UniversalParams<E>,
         polynomial: &P,
         point: &E::ScalarField,
-    ) -> E::G1Affine {
+    ) -> Result<E::G1Affine, KzgError> {
         let eval = polynomial.evaluate(point);
         let eval_as_poly = P::from_coefficients_vec(vec![eval]);
         let numerator = polynomial.clone().sub(&eval_as_poly);
@@ -126,6 +518,154 @@ where
 
         Self::commit_g1(params, &witness_polynomial)
     }
+
+    /// one opening proof of polynomial at all of points, with the remainder r(X) that
+    /// interpolates the evaluations: the proof commits to (polynomial - r) / Z(X), where
+    /// Z(X) vanishes on points. Fails if points is empty or repeats a point.
+    pub fn open_multi(
+        params: &UniversalParams<E>,
+        polynomial: &P,
+        points: &[E::ScalarField],
+    ) -> Result<(E::G1Affine, P), KzgError> {
+        if points.is_empty() || has_repeats(points) {
+            return Err(KzgError::BadPoints);
+        }
+
+        let values: Vec<E::ScalarField> = points.iter().map(|z| polynomial.evaluate(z)).collect();
+        let remainder = P::from_coefficients_vec(interpolate(points, &values));
+        let vanishing = P::from_coefficients_vec(vanishing_coeffs(points));
+        let quotient = &(polynomial.clone().sub(&remainder)) / &vanishing;
+
+        Ok((Self::commit_g1(params, &quotient)?, remainder))
+    }
+
+    /// checks e(comm - g^{r(τ)}, h) == e(proof, h^{Z(τ)}), where r interpolates values
+    /// at points and Z vanishes on points
+    pub fn verify_multi(
+        params: &UniversalParams<E>,
+        comm: &E::G1Affine,
+        points: &[E::ScalarField],
+        values: &[E::ScalarField],
+        proof: &E::G1Affine,
+    ) -> bool {
+        if points.is_empty() || points.len() != values.len() || has_repeats(points) {
+            return false;
+        }
+        let remainder = P::from_coefficients_vec(interpolate(points, values));
+        let vanishing = P::from_coefficients_vec(vanishing_coeffs(points));
+
+        let remainder_comm = match Self::commit_g1(params, &remainder) {
+            Ok(remainder_comm) => remainder_comm,
+            Err(_) => return false,
+        };
+        let inner = comm.into_group() - remainder_comm.into_group();
+        let lhs = E::pairing(inner, params.powers_of_h[0]);
+        let rhs = match Self::commit_g2(params, &vanishing) {
+            Ok(vanishing_h) => E::pairing(proof, vanishing_h),
+            Err(_) => return false,
+        };
+
+        lhs == rhs
+    }
+
+    /// one opening proof at point for all of polys, combined as sum_i gamma^i polys[i]
+    pub fn batch_open(
+        params: &UniversalParams<E>,
+        polys: &[P],
+        point: &E::ScalarField,
+        gamma: &E::ScalarField,
+    ) -> Result<E::G1Affine, KzgError> {
+        let mut combined = P::zero();
+        let mut power = E::ScalarField::one();
+        for poly in polys.iter() {
+            combined += (power, poly);
+            power *= gamma;
+        }
+
+        Self::compute_opening_proof(params, &combined, point)
+    }
+
+    /// checks a batch_open proof against the commitments and claimed evaluations of the polys
+    pub fn batch_verify(
+        params: &UniversalParams<E>,
+        comms: &[E::G1Affine],
+        values: &[E::ScalarField],
+        point: &E::ScalarField,
+        gamma: &E::ScalarField,
+        proof: &E::G1Affine,
+    ) -> bool {
+        if comms.len() != values.len() {
+            return false;
+        }
+
+        let mut comm = E::G1::zero();
+        let mut value = E::ScalarField::zero();
+        let mut power = E::ScalarField::one();
+        for (c, v) in comms.iter().zip(values.iter()) {
+            comm += c.mul(power);
+            value += power * v;
+            power *= gamma;
+        }
+
+        Self::verify_opening_proof(params, &comm.into_affine(), point, &value, proof)
+    }
+}
+
+fn has_repeats<F: PrimeField>(points: &[F]) -> bool {
+    let mut sorted = points.to_vec();
+    sorted.sort_unstable();
+    sorted.windows(2).any(|w| w[0] == w[1])
+}
+
+/// coefficients of prod_i (X - points[i]), lowest degree first
+fn vanishing_coeffs<F: PrimeField>(points: &[F]) -> Vec<F> {
+    let mut coeffs = vec![F::one()];
+    for z in points.iter() {
+        coeffs.insert(0, F::zero());
+        for i in 0..coeffs.len() - 1 {
+            let next = coeffs[i + 1];
+            coeffs[i] -= next * z;
+        }
+    }
+    coeffs
+}
+
+/// coefficients of the polynomial of degree below points.len() through (points[i], values[i]);
+/// the points must be distinct
+fn interpolate<F: PrimeField>(points: &[F], values: &[F]) -> Vec<F> {
+    let vanishing = vanishing_coeffs(points);
+    let mut coeffs = vec![F::zero(); points.len()];
+
+    let mut denominators: Vec<F> = points
+        .iter()
+        .enumerate()
+        .map(|(i, z_i)| {
+            points
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != i)
+                .map(|(_, z_j)| *z_i - z_j)
+                .product()
+        })
+        .collect();
+    batch_inversion(&mut denominators);
+
+    for (i, (z_i, y_i)) in points.iter().zip(values.iter()).enumerate() {
+        // Z(X) / (X - z_i) by synthetic division
+        let mut basis = vec![F::zero(); points.len()];
+        let mut carry = F::zero();
+        for k in (0..points.len()).rev() {
+            carry = vanishing[k + 1] + carry * z_i;
+            basis[k] = carry;
+        }
+
+        let scale = *y_i * denominators[i];
+
+        for (c, b) in coeffs.iter_mut().zip(basis.iter()) {
+            *c += scale * b;
+        }
+    }
+    coeffs
 }
 
 fn skip_leading_zeros_and_convert_to_bigints<F: PrimeField, P: DenseUVPolynomial<F>>(
@@ -143,3 +683,258 @@ fn convert_to_bigints<F: PrimeField>(p: &[F]) -> Vec<F::BigInt> {
     let coeffs = p.iter().map(|s| s.into_bigint()).collect::<Vec<_>>();
     coeffs
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{F, KZG};
+    use ark_poly::univariate::DensePolynomial;
+    use ark_poly::Polynomial;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    /// encodes params in the snarkjs .ptau layout, with just the sections we read
+    fn write_ptau(params: &UniversalParams<Curve>, power: u32, path: &Path) {
+        let n8 = Fq::MODULUS.to_bytes_le().len();
+        let r = Fq::from(2u64).pow([8 * n8 as u64]);
+        let fq = |x: &Fq| {
+            let mut bytes = (*x * r).into_bigint().to_bytes_le();
+            bytes.resize(n8, 0);
+            bytes
+        };
+
+        let mut header = (n8 as u32).to_le_bytes().to_vec();
+        header.extend(Fq::MODULUS.to_bytes_le());
+        header.extend(power.to_le_bytes());
+        header.extend(power.to_le_bytes());
+
+        let mut tau_g1 = Vec::new();
+        for p in params.powers_of_g.iter() {
+            tau_g1.extend(fq(&p.x));
+            tau_g1.extend(fq(&p.y));
+        }
+        let mut tau_g2 = Vec::new();
+        for p in params.powers_of_h.iter() {
+            for c in [p.x.c0, p.x.c1, p.y.c0, p.y.c1] {
+                tau_g2.extend(fq(&c));
+            }
+        }
+
+        let mut bytes = PTAU_MAGIC.to_vec();
+        bytes.extend(1u32.to_le_bytes());
+        bytes.extend(3u32.to_le_bytes());
+        for (kind, section) in [(1u32, header), (2, tau_g1), (3, tau_g2)] {
+            bytes.extend(kind.to_le_bytes());
+            bytes.extend((section.len() as u64).to_le_bytes());
+            bytes.extend(section);
+        }
+        std::fs::write(path, bytes).unwrap();
+    }
+
+    fn commit_open_verify<E: Pairing>(params: &UniversalParams<E>, degree: usize) -> bool {
+        type Kzg<E> = KZG10<E, DensePolynomial<<E as Pairing>::ScalarField>>;
+        let mut rng = StdRng::from_seed([7u8; 32]);
+        let poly = DensePolynomial::<E::ScalarField>::rand(degree, &mut rng);
+        let point = E::ScalarField::rand(&mut rng);
+
+        let comm = Kzg::<E>::commit_g1(params, &poly).unwrap();
+        let proof = Kzg::<E>::compute_opening_proof(params, &poly, &point).unwrap();
+        Kzg::<E>::verify_opening_proof(params, &comm, &point, &poly.evaluate(&point), &proof)
+    }
+
+    fn check_commit_open_on<E: Pairing>() {
+        let params = KZG10::<E, DensePolynomial<E::ScalarField>>::setup(
+            16,
+            &mut StdRng::from_seed([42u8; 32]),
+        );
+        params.validate(&mut StdRng::from_seed([43u8; 32])).unwrap();
+        assert!(commit_open_verify(&params, 16));
+    }
+
+    #[cfg(feature = "bls12_381")]
+    #[test]
+    fn test_commit_open_on_bls12_381() {
+        check_commit_open_on::<ark_bls12_381::Bls12_381>();
+    }
+
+    #[cfg(feature = "bls12_377")]
+    #[test]
+    fn test_commit_open_on_bls12_377() {
+        check_commit_open_on::<ark_bls12_377::Bls12_377>();
+    }
+
+    #[test]
+    fn test_params_file_round_trip() {
+        let params = KZG::setup(32, &mut StdRng::from_seed([42u8; 32]));
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("params.bin");
+        params.write_to_file(&path).unwrap();
+
+        let loaded = UniversalParams::read_from_file(&path, 16).unwrap();
+
+        assert_eq!(loaded.powers_of_g[..], params.powers_of_g[..17]);
+        assert_eq!(loaded.powers_of_h[..], params.powers_of_h[..17]);
+        assert!(commit_open_verify(&loaded, 16));
+    }
+
+    #[test]
+    fn test_ptau_import() {
+        let params = KZG::setup(15, &mut StdRng::from_seed([42u8; 32]));
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("params.ptau");
+        write_ptau(&params, 4, &path);
+
+        let loaded = UniversalParams::read_from_file(&path, 8).unwrap();
+        let too_long = UniversalParams::read_from_file(&path, 16);
+
+        assert_eq!(loaded.powers_of_g[..], params.powers_of_g[..9]);
+        assert_eq!(loaded.powers_of_h[..], params.powers_of_h[..9]);
+        assert!(commit_open_verify(&loaded, 8));
+        assert!(matches!(
+            too_long,
+            Err(ParamsError::TooFewPowers {
+                needed: 17,
+                available: 16
+            })
+        ));
+    }
+
+    #[test]
+    fn test_batch_open_rejects_any_altered_value() {
+        let params = KZG::setup(16, &mut StdRng::from_seed([42u8; 32]));
+        let mut rng = StdRng::from_seed([7u8; 32]);
+        let polys: Vec<DensePolynomial<F>> = (0..4)
+            .map(|_| DensePolynomial::rand(16, &mut rng))
+            .collect();
+        let (point, gamma) = (F::rand(&mut rng), F::rand(&mut rng));
+
+        let comms: Vec<_> = polys
+            .iter()
+            .map(|p| KZG::commit_g1(&params, p).unwrap())
+            .collect();
+        let values: Vec<F> = polys.iter().map(|p| p.evaluate(&point)).collect();
+        let proof = KZG::batch_open(&params, &polys, &point, &gamma).unwrap();
+
+        assert!(KZG::batch_verify(
+            &params, &comms, &values, &point, &gamma, &proof
+        ));
+        for i in 0..values.len() {
+            let mut altered = values.clone();
+            altered[i] += F::one();
+            assert!(!KZG::batch_verify(
+                &params, &comms, &altered, &point, &gamma, &proof
+            ));
+        }
+    }
+
+    #[test]
+    fn test_multi_point_opening() {
+        let params = KZG::setup(16, &mut StdRng::from_seed([42u8; 32]));
+        let mut rng = StdRng::from_seed([7u8; 32]);
+        let poly = DensePolynomial::<F>::rand(16, &mut rng);
+        let comm = KZG::commit_g1(&params, &poly).unwrap();
+
+        // a random point and two points of the size-8 FFT domain
+        let w = crate::utils::multiplicative_subgroup_of_size(8);
+        let points = vec![F::rand(&mut rng), F::one(), w];
+        let values: Vec<F> = points.iter().map(|z| poly.evaluate(z)).collect();
+
+        let (proof, remainder) = KZG::open_multi(&params, &poly, &points).unwrap();
+        for (z, y) in points.iter().zip(values.iter()) {
+            assert_eq!(remainder.evaluate(z), *y);
+        }
+        assert!(KZG::verify_multi(&params, &comm, &points, &values, &proof));
+
+        for i in 0..values.len() {
+            let mut altered = values.clone();
+            altered[i] += F::one();
+            assert!(!KZG::verify_multi(
+                &params, &comm, &points, &altered, &proof
+            ));
+        }
+        let (single, _) = KZG::open_multi(&params, &poly, &points[..1]).unwrap();
+        assert!(!KZG::verify_multi(
+            &params, &comm, &points, &values, &single
+        ));
+
+        let repeated = vec![points[0], points[1], points[0]];
+        assert_eq!(
+            KZG::open_multi(&params, &poly, &repeated).unwrap_err(),
+            KzgError::BadPoints
+        );
+        assert!(!KZG::verify_multi(
+            &params, &comm, &repeated, &values, &proof
+        ));
+    }
+
+    #[test]
+    fn test_commit_needs_enough_powers() {
+        let params = KZG::setup(8, &mut StdRng::from_seed([42u8; 32]));
+        let mut rng = StdRng::from_seed([7u8; 32]);
+        let poly = DensePolynomial::<F>::rand(12, &mut rng);
+        let point = F::rand(&mut rng);
+
+        let too_few = KzgError::TooFewPowers {
+            needed: 13,
+            available: 9,
+        };
+        assert_eq!(KZG::commit_g1(&params, &poly).unwrap_err(), too_few);
+        assert!(KZG::compute_opening_proof(&params, &poly, &point).is_err());
+
+        // opening proofs are checked against h^τ, which must be there
+        let low = DensePolynomial::<F>::rand(4, &mut rng);
+        let comm = KZG::commit_g1(&params, &low).unwrap();
+        let proof = KZG::compute_opening_proof(&params, &low, &point).unwrap();
+        let mut no_g2 = params.clone();
+        no_g2.powers_of_h.truncate(1);
+        assert!(!KZG::verify_opening_proof(
+            &no_g2,
+            &comm,
+            &point,
+            &low.evaluate(&point),
+            &proof
+        ));
+    }
+
+    #[test]
+    fn test_degree_bound() {
+        let params = KZG::setup(32, &mut StdRng::from_seed([42u8; 32]));
+        let mut rng = StdRng::from_seed([7u8; 32]);
+
+        let poly = DensePolynomial::<F>::rand(8, &mut rng);
+        let comm = KZG::commit_g1(&params, &poly).unwrap();
+        let shifted = KZG::commit_g1_shifted(&params, &poly, 8).unwrap();
+        assert!(KZG::verify_degree_bound(&params, &comm, &shifted, 8));
+
+        // a degree-12 polynomial can only be shifted for a bound of at least 12
+        let high = DensePolynomial::<F>::rand(12, &mut rng);
+        let comm = KZG::commit_g1(&params, &high).unwrap();
+        assert!(KZG::commit_g1_shifted(&params, &high, 8).is_err());
+        let shifted = KZG::commit_g1_shifted(&params, &high, 12).unwrap();
+        assert!(KZG::verify_degree_bound(&params, &comm, &shifted, 12));
+        assert!(!KZG::verify_degree_bound(&params, &comm, &shifted, 8));
+
+        // without the powers of h the bound cannot be checked
+        let mut no_g2 = params.clone();
+        no_g2.powers_of_h.truncate(2);
+        assert!(!no_g2.has_g2_powers(24));
+        assert!(matches!(
+            KZG::commit_g1_shifted(&no_g2, &poly, 8),
+            Err(ParamsError::TooFewPowers { .. })
+        ));
+        assert!(KZG::commit_g2(&no_g2, &poly).is_err());
+        assert!(KZG::commit_g2(&params, &poly).is_ok());
+    }
+
+    #[test]
+    fn test_corrupt_power_fails_validation() {
+        let mut params = KZG::setup(16, &mut StdRng::from_seed([42u8; 32]));
+        params.powers_of_g[5] = (params.powers_of_g[5] + params.powers_of_g[0]).into_affine();
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("corrupt.bin");
+        params.write_to_file(&path).unwrap();
+
+        let loaded = UniversalParams::read_from_file(&path, 16);
+
+        assert!(matches!(loaded, Err(ParamsError::Inconsistent)));
+    }
+}
@@ -1,16 +1,73 @@
+#[cfg(feature = "networking")]
+pub mod accountability;
+#[cfg(feature = "networking")]
 pub mod address_book;
+#[cfg(feature = "networking")]
+pub mod audit;
+pub mod cards;
+#[cfg(feature = "networking")]
+pub mod checkpoint;
+#[cfg(feature = "networking")]
+pub mod circuit;
 pub mod common;
+#[cfg(feature = "networking")]
+pub mod compression;
 pub mod encoding;
+pub mod error;
+#[cfg(feature = "networking")]
 pub mod evaluator;
+pub mod evm;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+#[cfg(feature = "networking")]
+pub mod game;
+pub mod group_ops;
 pub mod hash;
+#[cfg(feature = "networking")]
+pub mod journal;
+#[cfg(feature = "networking")]
+pub mod keys;
 pub mod kzg;
+#[cfg(feature = "networking")]
+pub mod metrics;
+#[cfg(feature = "networking")]
 pub mod network;
+#[cfg(feature = "networking")]
+pub mod node;
+#[cfg(feature = "networking")]
+pub mod pairwise;
+#[cfg(feature = "networking")]
+pub mod permutation;
+#[cfg(feature = "networking")]
+pub mod preprocessing;
+pub mod proofs;
+#[cfg(feature = "networking")]
+pub mod prss;
+pub mod reconstruct;
+#[cfg(feature = "networking")]
+pub mod reservoir;
+#[cfg(feature = "networking")]
+pub mod router;
+#[cfg(feature = "rpc")]
+pub mod rpc;
+pub mod secret;
 pub mod shamir;
+#[cfg(feature = "networking")]
+pub mod shared_poly;
+#[cfg(feature = "networking")]
 pub mod shuffler;
+#[cfg(all(feature = "networking", any(test, feature = "sim")))]
+pub mod simulator;
+#[cfg(feature = "networking")]
+pub mod sort;
+pub mod transcript;
+#[cfg(feature = "networking")]
+pub mod transport;
 pub mod utils;
+#[cfg(feature = "networking")]
+pub mod verifier;
+pub mod wasm;
+pub mod wire;
 
-#[cfg(not(any(feature = "bls12_381", feature = "bls12_377")))]
-compile_error!("Enable exactly one curve feature: `bls12_381` or `bls12_377`.");
-
-#[cfg(all(feature = "bls12_381", feature = "bls12_377"))]
-compile_error!("`bls12_381` and `bls12_377` are mututally exclusive features.");
+#[cfg(not(any(feature = "bls12_381", feature = "bls12_377", feature = "bn254")))]
+compile_error!("Enable at least one curve feature: `bls12_381`, `bls12_377` or `bn254`.");
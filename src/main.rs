@@ -1,17 +1,23 @@
 use async_std::task;
 use clap::Parser;
-use futures::channel::mpsc;
-use num_bigint::BigUint;
+use futures::{channel::mpsc, StreamExt};
+use libp2p::PeerId;
+use std::path::PathBuf;
 use std::thread;
 
-use pok3r::address_book::parse_addr_book_from_json;
-use pok3r::common::{EvalNetMsg, DECK_SIZE, PERM_SIZE};
+use pok3r::address_book::{
+    keypair_from_seed, parse_addr_book_from_file, parse_addr_book_from_json, Pok3rAddrBook,
+};
+use pok3r::common::{EvalNetMsg, ProtocolParams, DECK_SIZE, PERM_SIZE};
 use pok3r::evaluator::Evaluator;
+use pok3r::hash::{card_id, GameContext, IdCache};
+use pok3r::network::DEFAULT_QUEUE_CAPACITY;
 use pok3r::shuffler::{
     compute_decryption_cache, compute_decryption_key, compute_keyper_keys, compute_params,
-    compute_permutation_argument, decrypt_one_card, encrypt_and_prove, shuffle_deck,
+    compute_permutation_argument, decrypt_one_card, encrypt_and_prove, load_params, shuffle_deck,
     verify_encryption_argument, verify_permutation_argument,
 };
+use pok3r::verifier::VerifierNode;
 
 /// Simple program to greet a person
 #[derive(Parser, Debug)]
@@ -28,6 +34,40 @@ struct Args {
     /// number of parties doing the mpc
     #[clap(long)]
     parties: u64,
+
+    /// identifies the game; must be the same for all parties
+    #[clap(long, default_value = "pok3r")]
+    session: String,
+
+    /// JSON file listing the peers; defaults to the built-in peers of seeds 1..=parties
+    #[clap(long)]
+    addr_book: Option<PathBuf>,
+
+    /// KZG params, natively serialized or as a Powers-of-Tau (.ptau) file;
+    /// defaults to params from a fixed local trapdoor, fit for testing only
+    #[clap(long)]
+    params: Option<PathBuf>,
+
+    /// cards in the shuffled permutation, a power of two; must be the same for all parties
+    #[clap(long, default_value_t = PERM_SIZE)]
+    perm_size: usize,
+
+    /// cards of the deck, at most perm_size; the rest are dummy cards
+    #[clap(long, default_value_t = DECK_SIZE)]
+    deck_size: usize,
+}
+
+fn load_addr_book(args: &Args) -> Pok3rAddrBook {
+    match &args.addr_book {
+        Some(path) => parse_addr_book_from_file(path).unwrap_or_else(|err| {
+            eprintln!("{}: {}", path.display(), err);
+            std::process::exit(1)
+        }),
+        None => parse_addr_book_from_json(args.parties).unwrap_or_else(|err| {
+            eprintln!("--parties: {}", err);
+            std::process::exit(1)
+        }),
+    }
 }
 
 #[async_std::main]
@@ -35,13 +75,14 @@ async fn main() {
     let args = Args::parse();
 
     //these channels will connect the evaluator and the network daemons
-    let (mut n2e_tx, n2e_rx) = mpsc::unbounded::<EvalNetMsg>();
-    let (e2n_tx, e2n_rx) = mpsc::unbounded::<EvalNetMsg>();
+    let (mut n2e_tx, n2e_rx) = mpsc::channel::<EvalNetMsg>(DEFAULT_QUEUE_CAPACITY);
+    let (e2n_tx, e2n_rx) = mpsc::channel::<EvalNetMsg>(DEFAULT_QUEUE_CAPACITY);
 
+    let netd_addr_book = load_addr_book(&args);
     let netd_handle = thread::spawn(move || {
         let result = task::block_on(pok3r::network::run_networking_daemon(
             args.seed,
-            &parse_addr_book_from_json(args.parties),
+            &netd_addr_book,
             &mut n2e_tx,
             e2n_rx,
         ));
@@ -50,9 +91,62 @@ async fn main() {
         }
     });
 
-    let addr_book = parse_addr_book_from_json(args.parties);
-    let messaging = pok3r::network::MessagingSystem::new(&args.id, addr_book, e2n_tx, n2e_rx).await;
-    let mut mpc = Evaluator::new(messaging).await;
+    let addr_book = load_addr_book(&args);
+    let keypair = keypair_from_seed(args.seed);
+    let protocol_params =
+        ProtocolParams::new(args.perm_size, args.deck_size).unwrap_or_else(|err| {
+            eprintln!("--perm-size/--deck-size: {}", err);
+            std::process::exit(1)
+        });
+    let load_pp = || match &args.params {
+        Some(path) => load_params(path, &protocol_params).unwrap_or_else(|err| {
+            eprintln!("{}: {}", path.display(), err);
+            std::process::exit(1)
+        }),
+        None => compute_params(),
+    };
+
+    // verifiers hold no shares: they only follow what the players publish
+    if addr_book.is_verifier(&PeerId::from(keypair.public()).to_base58()) {
+        let verifier = VerifierNode::new(
+            &args.session,
+            addr_book,
+            load_pp(),
+            protocol_params,
+            None,
+            n2e_rx,
+        )
+        .unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            std::process::exit(1)
+        });
+        let mut events = Box::pin(verifier.into_stream());
+        while let Some(event) = events.next().await {
+            println!("{:?}", event);
+        }
+        netd_handle.join().unwrap();
+        return;
+    }
+
+    let publish_proofs = addr_book.verifiers().next().is_some();
+    let messaging =
+        pok3r::network::MessagingSystem::new(keypair, &args.session, addr_book, e2n_tx, n2e_rx)
+            .await
+            .unwrap_or_else(|err| {
+                eprintln!("{}", err);
+                std::process::exit(1)
+            });
+    assert_eq!(
+        messaging.id, args.id,
+        "--id does not match the key of --seed"
+    );
+    let mut mpc = Evaluator::new_with_params(messaging, protocol_params)
+        .await
+        .unwrap_or_else(|err| {
+            eprintln!("could not preprocess: {}", err);
+            std::process::exit(1)
+        });
+    mpc.set_publish_proofs(publish_proofs);
 
     //this is a hack until we figure out
     task::block_on(async {
@@ -61,44 +155,49 @@ async fn main() {
     });
 
     // KZG setup runs once
-    let pp = compute_params();
+    let pp = load_pp();
 
     // Get a random public key pk in G2 - for testing (should be generated by DKG)
     // FIXME: Implement DKG to generate the public key
     let (msk, mpk) = compute_keyper_keys();
 
     // Actual protocol
-    let card_share_handles = shuffle_deck(&mut mpc).await;
+    let card_share_handles = shuffle_deck(&mut mpc).await.unwrap();
     println!("Generated a deck of {} cards", card_share_handles.len());
 
-    let (perm_proof, alpha1) =
-        compute_permutation_argument(&pp, &mut mpc, &card_share_handles).await;
+    let (perm_proof, alpha1) = compute_permutation_argument(&pp, &mut mpc, &card_share_handles)
+        .await
+        .unwrap();
 
-    // Get random ids as byte strings
-    let ids = (0..PERM_SIZE)
-        .map(|i| BigUint::from(i as u64).to_bytes_le())
+    // Card ids are bound to the game, so that their keys reveal nothing elsewhere
+    let ctx = GameContext::from_label(&args.session);
+    let (perm_size, deck_size) = (protocol_params.perm_size(), protocol_params.deck_size());
+    let ids = (0..perm_size)
+        .map(|i| card_id(&ctx, i as u64, 0))
         .collect::<Vec<Vec<u8>>>();
 
     // Encrypt and prove
     let (ctxt, encryption_proof) = encrypt_and_prove(
         &pp,
         &mut mpc,
+        &mut IdCache::new(ctx, ids.len()),
         card_share_handles.clone(),
         perm_proof.f_com,
         alpha1,
         mpk,
         ids.clone(),
     )
-    .await;
+    .await
+    .unwrap();
 
     // decrypt all cards
-    let cache = compute_decryption_cache();
+    let cache = compute_decryption_cache(&protocol_params);
     let mut decrypted_cards = Vec::new();
-    for i in 0..PERM_SIZE {
-        let dec_key = compute_decryption_key(&ids[i], msk);
+    for (i, id) in ids.iter().enumerate() {
+        let dec_key = compute_decryption_key(&ctx, id, msk);
 
-        // ignore the first (PERM_SIZE - DECK_SIZE) cards, which are not part of deck
-        if i >= (PERM_SIZE - DECK_SIZE) {
+        // ignore the first (perm_size - deck_size) cards, which are not part of deck
+        if i >= (perm_size - deck_size) {
             let card = decrypt_one_card(i, &dec_key, &ctxt, &cache).unwrap();
             decrypted_cards.push(card);
             print!("{},", card);
@@ -106,11 +205,11 @@ async fn main() {
     }
 
     assert!(
-        verify_permutation_argument(&pp, &perm_proof),
+        verify_permutation_argument(&pp, &protocol_params, &perm_proof),
         "Permutation argument verification failed"
     );
     assert!(
-        verify_encryption_argument(&pp, &ctxt, &encryption_proof),
+        verify_encryption_argument(&pp, &protocol_params, &ctx, &ctxt, &encryption_proof),
         "Encryption proof verification failed"
     );
 
@@ -118,7 +217,7 @@ async fn main() {
     // check that decrypted cards is a permutation of 0..51
     let mut sorted_cards = decrypted_cards.clone();
     sorted_cards.sort_unstable();
-    let expected_cards: Vec<usize> = (0..DECK_SIZE).collect();
+    let expected_cards: Vec<usize> = (0..deck_size).collect();
     assert_eq!(
         sorted_cards, expected_cards,
         "Decrypted cards are not a valid permutation of 0..51"
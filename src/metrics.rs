@@ -0,0 +1,347 @@
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::common::EvalNetMsg;
+use crate::compression::expanded_size;
+
+/// MessageKind groups wire messages for the byte counters
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageKind {
+    Value,
+    BatchValue,
+    BatchBinary,
+    Direct,
+    Ack,
+    Control,
+}
+
+const NUM_MESSAGE_KINDS: usize = 6;
+
+const MESSAGE_KINDS: [MessageKind; NUM_MESSAGE_KINDS] = [
+    MessageKind::Value,
+    MessageKind::BatchValue,
+    MessageKind::BatchBinary,
+    MessageKind::Direct,
+    MessageKind::Ack,
+    MessageKind::Control,
+];
+
+impl MessageKind {
+    pub fn of(msg: &EvalNetMsg) -> Self {
+        match msg {
+            EvalNetMsg::PublishValue { .. } => MessageKind::Value,
+            EvalNetMsg::PublishBatchValue { .. } => MessageKind::BatchValue,
+            EvalNetMsg::PublishBatchBinary { .. } => MessageKind::BatchBinary,
+            EvalNetMsg::DirectValue { .. } | EvalNetMsg::DirectBatchBinary { .. } => {
+                MessageKind::Direct
+            }
+            EvalNetMsg::Ack { .. } => MessageKind::Ack,
+            EvalNetMsg::ConnectionEstablished { .. }
+            | EvalNetMsg::Greeting { .. }
+            | EvalNetMsg::Abort { .. }
+            | EvalNetMsg::Heartbeat { .. } => MessageKind::Control,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MessageKind::Value => "value",
+            MessageKind::BatchValue => "batch_value",
+            MessageKind::BatchBinary => "batch_binary",
+            MessageKind::Direct => "direct",
+            MessageKind::Ack => "ack",
+            MessageKind::Control => "control",
+        }
+    }
+}
+
+/// bytes of handles, values and binary payloads carried by msg; framing,
+/// signatures and other metadata are not counted, so that this stays cheap
+pub fn payload_bytes(msg: &EvalNetMsg) -> u64 {
+    let bytes = match msg {
+        EvalNetMsg::PublishValue { handle, value, .. }
+        | EvalNetMsg::DirectValue { handle, value, .. } => handle.len() + value.len(),
+        EvalNetMsg::PublishBatchValue {
+            handles,
+            values,
+            payload,
+            ..
+        } => {
+            handles.iter().map(|h| h.len()).sum::<usize>()
+                + values.iter().map(|v| v.len()).sum::<usize>()
+                + payload.len()
+        }
+        EvalNetMsg::PublishBatchBinary {
+            handles, payload, ..
+        }
+        | EvalNetMsg::DirectBatchBinary {
+            handles, payload, ..
+        } => handles.iter().map(|h| h.len()).sum::<usize>() + payload.len(),
+        EvalNetMsg::Ack { .. }
+        | EvalNetMsg::ConnectionEstablished { .. }
+        | EvalNetMsg::Greeting { .. }
+        | EvalNetMsg::Abort { .. }
+        | EvalNetMsg::Heartbeat { .. } => 0,
+    };
+    bytes as u64
+}
+
+/// payload_bytes of msg as it was before compression; a compressed payload counts
+/// the size its frame says it expands to
+pub fn raw_payload_bytes(msg: &EvalNetMsg) -> u64 {
+    match msg {
+        EvalNetMsg::PublishBatchValue {
+            handles,
+            codec,
+            payload,
+            ..
+        }
+        | EvalNetMsg::PublishBatchBinary {
+            handles,
+            codec,
+            payload,
+            ..
+        } if !codec.is_plain() => {
+            let handle_bytes = handles.iter().map(|h| h.len()).sum::<usize>() as u64;
+            handle_bytes + expanded_size(payload).unwrap_or(payload.len() as u64)
+        }
+        _ => payload_bytes(msg),
+    }
+}
+
+#[derive(Default)]
+struct Phases {
+    /// start times of the phases that have begun but not ended, with the rounds
+    /// and beaver triples counted by then
+    open: HashMap<String, (Instant, u64, u64)>,
+    /// total time spent in each phase that has ended at least once
+    elapsed: BTreeMap<String, Duration>,
+    /// total rounds and beaver triples of each phase that has ended at least once
+    counts: BTreeMap<String, (u64, u64)>,
+}
+
+/// Metrics counts what the protocol costs: messages and bytes on the wire,
+/// communication rounds, beaver triples, and wall-clock time per phase, along
+/// with the messages the replay protection dropped.
+/// Everything but the phases is an atomic counter, so it can stay on.
+#[derive(Default)]
+pub struct Metrics {
+    messages_sent: AtomicU64,
+    messages_received: AtomicU64,
+    bytes_sent: [AtomicU64; NUM_MESSAGE_KINDS],
+    bytes_received: [AtomicU64; NUM_MESSAGE_KINDS],
+    raw_bytes_sent: [AtomicU64; NUM_MESSAGE_KINDS],
+    raw_bytes_received: [AtomicU64; NUM_MESSAGE_KINDS],
+    rounds: AtomicU64,
+    /// set between the first send of a round and the receive that ends it
+    in_round: AtomicBool,
+    beaver_triples_consumed: AtomicU64,
+    /// value messages of rounds we had left behind
+    stale_messages: AtomicU64,
+    /// retransmitted messages, and second values of a sender for a handle in a round
+    duplicates_dropped: AtomicU64,
+    /// value messages of rounds beyond the horizon, left unacknowledged
+    messages_beyond_horizon: AtomicU64,
+    /// rounds ahead of ours whose values are kept
+    round_horizon: AtomicU64,
+    /// sends that found the queue towards the networkd full
+    sends_blocked: AtomicU64,
+    /// value messages left unacknowledged because our mailboxes held all they
+    /// may of their sender's values
+    messages_overflowed: AtomicU64,
+    /// most values waiting in the mailboxes at once
+    pending_values_high_water: AtomicU64,
+    /// messages off the network that did not decode
+    malformed_dropped: AtomicU64,
+    phases: Mutex<Phases>,
+}
+
+impl Metrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn record_sent(&self, msg: &EvalNetMsg) {
+        self.messages_sent.fetch_add(1, Ordering::Relaxed);
+        self.bytes_sent[MessageKind::of(msg) as usize]
+            .fetch_add(payload_bytes(msg), Ordering::Relaxed);
+        self.raw_bytes_sent[MessageKind::of(msg) as usize]
+            .fetch_add(raw_payload_bytes(msg), Ordering::Relaxed);
+    }
+
+    pub fn record_received(&self, msg: &EvalNetMsg) {
+        self.messages_received.fetch_add(1, Ordering::Relaxed);
+        self.bytes_received[MessageKind::of(msg) as usize]
+            .fetch_add(payload_bytes(msg), Ordering::Relaxed);
+        self.raw_bytes_received[MessageKind::of(msg) as usize]
+            .fetch_add(raw_payload_bytes(msg), Ordering::Relaxed);
+    }
+
+    /// called on every send of values; consecutive sends count as one round
+    pub fn start_round(&self) {
+        if !self.in_round.swap(true, Ordering::Relaxed) {
+            self.rounds.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// called once the values we waited for have arrived
+    pub fn end_round(&self) {
+        self.in_round.store(false, Ordering::Relaxed);
+    }
+
+    pub fn record_beaver_triples(&self, count: u64) {
+        self.beaver_triples_consumed
+            .fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn record_stale_message(&self) {
+        self.stale_messages.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_duplicate(&self) {
+        self.duplicates_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_beyond_horizon(&self) {
+        self.messages_beyond_horizon.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn set_round_horizon(&self, horizon: u64) {
+        self.round_horizon.store(horizon, Ordering::Relaxed);
+    }
+
+    pub fn record_send_blocked(&self) {
+        self.sends_blocked.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_overflow(&self) {
+        self.messages_overflowed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// called with the number of values waiting in the mailboxes whenever it grows
+    pub fn record_pending_values(&self, pending: u64) {
+        self.pending_values_high_water
+            .fetch_max(pending, Ordering::Relaxed);
+    }
+
+    pub fn record_malformed(&self) {
+        self.malformed_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn begin_phase(&self, name: &str) {
+        let mut phases = self.phases.lock().unwrap();
+        let rounds = self.rounds.load(Ordering::Relaxed);
+        let triples = self.beaver_triples_consumed.load(Ordering::Relaxed);
+        phases
+            .open
+            .insert(name.to_string(), (Instant::now(), rounds, triples));
+    }
+
+    /// adds the time, rounds and beaver triples since the matching begin_phase to
+    /// the phase; ignored if it never began
+    pub fn end_phase(&self, name: &str) {
+        let mut phases = self.phases.lock().unwrap();
+        if let Some((start, rounds, triples)) = phases.open.remove(name) {
+            *phases.elapsed.entry(name.to_string()).or_default() += start.elapsed();
+            let counts = phases.counts.entry(name.to_string()).or_default();
+            counts.0 += self.rounds.load(Ordering::Relaxed) - rounds;
+            counts.1 += self.beaver_triples_consumed.load(Ordering::Relaxed) - triples;
+        }
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        let by_kind = |counters: &[AtomicU64]| {
+            MESSAGE_KINDS
+                .iter()
+                .map(|kind| {
+                    let count = counters[*kind as usize].load(Ordering::Relaxed);
+                    (kind.name().to_string(), count)
+                })
+                .collect::<BTreeMap<String, u64>>()
+        };
+        let phases = self.phases.lock().unwrap();
+
+        MetricsSnapshot {
+            messages_sent: self.messages_sent.load(Ordering::Relaxed),
+            messages_received: self.messages_received.load(Ordering::Relaxed),
+            bytes_sent: by_kind(&self.bytes_sent[..]),
+            bytes_received: by_kind(&self.bytes_received[..]),
+            raw_bytes_sent: by_kind(&self.raw_bytes_sent[..]),
+            raw_bytes_received: by_kind(&self.raw_bytes_received[..]),
+            rounds: self.rounds.load(Ordering::Relaxed),
+            beaver_triples_consumed: self.beaver_triples_consumed.load(Ordering::Relaxed),
+            stale_messages: self.stale_messages.load(Ordering::Relaxed),
+            duplicates_dropped: self.duplicates_dropped.load(Ordering::Relaxed),
+            messages_beyond_horizon: self.messages_beyond_horizon.load(Ordering::Relaxed),
+            round_horizon: self.round_horizon.load(Ordering::Relaxed),
+            sends_blocked: self.sends_blocked.load(Ordering::Relaxed),
+            messages_overflowed: self.messages_overflowed.load(Ordering::Relaxed),
+            pending_values_high_water: self.pending_values_high_water.load(Ordering::Relaxed),
+            malformed_dropped: self.malformed_dropped.load(Ordering::Relaxed),
+            phases: phases
+                .elapsed
+                .iter()
+                .map(|(name, elapsed)| (name.clone(), elapsed.as_secs_f64()))
+                .collect(),
+            phase_rounds: phases
+                .counts
+                .iter()
+                .map(|(name, (rounds, _))| (name.clone(), *rounds))
+                .collect(),
+            phase_beaver_triples: phases
+                .counts
+                .iter()
+                .map(|(name, (_, triples))| (name.clone(), *triples))
+                .collect(),
+        }
+    }
+}
+
+/// a point-in-time copy of Metrics
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct MetricsSnapshot {
+    pub messages_sent: u64,
+    pub messages_received: u64,
+    /// payload bytes sent, by message kind
+    pub bytes_sent: BTreeMap<String, u64>,
+    /// payload bytes received, by message kind
+    pub bytes_received: BTreeMap<String, u64>,
+    /// same as bytes_sent, but counting compressed payloads at their expanded size
+    pub raw_bytes_sent: BTreeMap<String, u64>,
+    /// same as bytes_received, but counting compressed payloads at their expanded size
+    pub raw_bytes_received: BTreeMap<String, u64>,
+    pub rounds: u64,
+    pub beaver_triples_consumed: u64,
+    /// value messages dropped because they belong to a round we had left behind
+    pub stale_messages: u64,
+    /// value messages and values dropped because they were delivered already
+    pub duplicates_dropped: u64,
+    /// value messages left unacknowledged because their round was beyond the horizon
+    pub messages_beyond_horizon: u64,
+    /// how many rounds ahead of ours values are kept
+    pub round_horizon: u64,
+    /// sends that had to wait for room in the queue towards the networkd
+    pub sends_blocked: u64,
+    /// value messages left unacknowledged because their sender had too many
+    /// values waiting for us to take them
+    pub messages_overflowed: u64,
+    /// most values that waited in the mailboxes at once
+    pub pending_values_high_water: u64,
+    /// messages the networking daemon dropped because they did not decode
+    pub malformed_dropped: u64,
+    /// seconds spent in each phase
+    pub phases: BTreeMap<String, f64>,
+    /// rounds started in each phase
+    pub phase_rounds: BTreeMap<String, u64>,
+    /// beaver triples consumed in each phase
+    pub phase_beaver_triples: BTreeMap<String, u64>,
+}
+
+impl MetricsSnapshot {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(self).unwrap()
+    }
+}
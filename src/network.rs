@@ -1,19 +1,40 @@
-use futures::{channel::mpsc, future::Either, select, SinkExt, StreamExt};
+use futures::{
+    channel::mpsc,
+    future::{self, Either},
+    select,
+    stream::{self, FusedStream},
+    FutureExt, SinkExt, StreamExt,
+};
 use libp2p::{
     core::{muxing::StreamMuxerBox, transport::OrTransport, upgrade},
     gossipsub, identity, mdns, noise,
     swarm::{NetworkBehaviour, SwarmEvent},
-    tcp, yamux, PeerId, SwarmBuilder, Transport,
+    tcp, yamux, Multiaddr, PeerId, SwarmBuilder, Transport,
 };
 use libp2p_quic as quic;
-use std::collections::{hash_map::DefaultHasher, HashMap};
-use std::error::Error;
+use std::borrow::Cow;
+use std::collections::{
+    hash_map::{DefaultHasher, Entry},
+    BTreeMap, HashMap, HashSet,
+};
 use std::hash::{Hash, Hasher};
-use std::time::Duration;
+use std::io;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+use std::time::{Duration, Instant};
+use std::{error, fmt};
 
 use crate::{
-    address_book::{get_node_id_via_peer_id, Pok3rAddrBook, Pok3rPeerId},
-    common::EvalNetMsg,
+    address_book::{keypair_from_seed, public_key_of, PeerRole, Pok3rAddrBook, Pok3rPeerId},
+    common::{Codec, EvalNetMsg, PayloadKind, PeerInfo, FEATURE_BINARY_PAYLOADS, FEATURE_ZSTD},
+    compression::{self, CompressionError},
+    encoding::{encoding_tag, pack_bytes, payload_element_size, unpack_batch_values},
+    journal::{Journal, JournalError, JournalRecord},
+    metrics::Metrics,
 };
 
 // We create a custom network behaviour that combines Gossipsub and Mdns.
@@ -23,35 +44,519 @@ struct MyBehaviour {
     mdns: mdns::async_io::Behaviour,
 }
 
-fn generate_ed25519(secret_key_seed: u8) -> identity::Keypair {
-    // for now we are using a single byte as the seed
-    // this is not secure obviously,
-    // but we are not using it to make life easy
-    let mut bytes = [0u8; 32];
-    bytes[0] = secret_key_seed;
+/// topic carrying the direct values from sender to recipient; only the
+/// recipient subscribes to it, so nobody else is sent those messages
+fn direct_topic(sender: &str, recipient: &str) -> gossipsub::IdentTopic {
+    gossipsub::IdentTopic::new(format!("mpc-direct-{}-{}", sender, recipient))
+}
+
+/// first byte of a binary frame; json encodings always start with '{'
+const BINARY_FRAME_TAG: u8 = 0x00;
+
+/// serializes a message for the wire. PublishBatchBinary, DirectBatchBinary and
+/// compressed PublishBatchValue are framed as tag || u32 header length || json
+/// header || raw payload, so that the payload bytes are not blown up by json;
+/// everything else is plain json.
+pub fn encode_wire_msg(msg: &EvalNetMsg) -> Result<Vec<u8>, NetworkError> {
+    let (header, payload) = match msg {
+        EvalNetMsg::PublishBatchValue {
+            session,
+            sender,
+            seq,
+            attempt,
+            round,
+            handles,
+            values,
+            codec,
+            payload,
+            signature,
+        } if !payload.is_empty() => (
+            EvalNetMsg::PublishBatchValue {
+                session: session.clone(),
+                sender: sender.clone(),
+                seq: *seq,
+                attempt: *attempt,
+                round: *round,
+                handles: handles.clone(),
+                values: values.clone(),
+                codec: *codec,
+                payload: vec![],
+                signature: signature.clone(),
+            },
+            payload,
+        ),
+        EvalNetMsg::PublishBatchBinary {
+            session,
+            sender,
+            seq,
+            attempt,
+            round,
+            handles,
+            payload,
+            kind,
+            codec,
+            signature,
+        } => (
+            EvalNetMsg::PublishBatchBinary {
+                session: session.clone(),
+                sender: sender.clone(),
+                seq: *seq,
+                attempt: *attempt,
+                round: *round,
+                handles: handles.clone(),
+                payload: vec![],
+                kind: *kind,
+                codec: *codec,
+                signature: signature.clone(),
+            },
+            payload,
+        ),
+        EvalNetMsg::DirectBatchBinary {
+            session,
+            sender,
+            seq,
+            attempt,
+            round,
+            recipient,
+            handles,
+            payload,
+            kind,
+            signature,
+        } => (
+            EvalNetMsg::DirectBatchBinary {
+                session: session.clone(),
+                sender: sender.clone(),
+                seq: *seq,
+                attempt: *attempt,
+                round: *round,
+                recipient: recipient.clone(),
+                handles: handles.clone(),
+                payload: vec![],
+                kind: *kind,
+                signature: signature.clone(),
+            },
+            payload,
+        ),
+        _ => return serde_json::to_vec(msg).map_err(|err| NetworkError::Encoding(err.to_string())),
+    };
+
+    let header =
+        serde_json::to_vec(&header).map_err(|err| NetworkError::Encoding(err.to_string()))?;
+    let mut data = Vec::with_capacity(5 + header.len() + payload.len());
+    data.push(BINARY_FRAME_TAG);
+    data.extend_from_slice(&(header.len() as u32).to_le_bytes());
+    data.extend_from_slice(&header);
+    data.extend_from_slice(payload);
+    Ok(data)
+}
+
+/// inverse of encode_wire_msg; returns None on malformed input
+pub fn decode_wire_msg(data: &[u8]) -> Option<EvalNetMsg> {
+    if data.first() != Some(&BINARY_FRAME_TAG) {
+        return serde_json::from_slice(data).ok();
+    }
+
+    let header_len = u32::from_le_bytes(data.get(1..5)?.try_into().ok()?) as usize;
+    let header = data.get(5..5 + header_len)?;
+    match serde_json::from_slice(header).ok()? {
+        EvalNetMsg::PublishBatchValue {
+            session,
+            sender,
+            seq,
+            attempt,
+            round,
+            handles,
+            values,
+            codec,
+            signature,
+            ..
+        } => Some(EvalNetMsg::PublishBatchValue {
+            session,
+            sender,
+            seq,
+            attempt,
+            round,
+            handles,
+            values,
+            codec,
+            payload: data[5 + header_len..].to_vec(),
+            signature,
+        }),
+        EvalNetMsg::PublishBatchBinary {
+            session,
+            sender,
+            seq,
+            attempt,
+            round,
+            handles,
+            kind,
+            codec,
+            signature,
+            ..
+        } => Some(EvalNetMsg::PublishBatchBinary {
+            session,
+            sender,
+            seq,
+            attempt,
+            round,
+            handles,
+            payload: data[5 + header_len..].to_vec(),
+            kind,
+            codec,
+            signature,
+        }),
+        EvalNetMsg::DirectBatchBinary {
+            session,
+            sender,
+            seq,
+            attempt,
+            round,
+            recipient,
+            handles,
+            kind,
+            signature,
+            ..
+        } => Some(EvalNetMsg::DirectBatchBinary {
+            session,
+            sender,
+            seq,
+            attempt,
+            round,
+            recipient,
+            handles,
+            payload: data[5 + header_len..].to_vec(),
+            kind,
+            signature,
+        }),
+        _ => None,
+    }
+}
+
+/// appends a length prefixed field to buf
+fn push_field(buf: &mut Vec<u8>, field: &[u8]) {
+    buf.extend_from_slice(&(field.len() as u32).to_le_bytes());
+    buf.extend_from_slice(field);
+}
+
+/// the strings as one buffer of length prefixed fields, after their number
+fn pack_strings(values: &[String]) -> Vec<u8> {
+    let mut buf = (values.len() as u32).to_le_bytes().to_vec();
+    for value in values {
+        push_field(&mut buf, value.as_bytes());
+    }
+    buf
+}
+
+/// inverse of pack_strings; None unless all of buf is taken up by the strings
+fn unpack_strings(buf: &[u8]) -> Option<Vec<String>> {
+    let take_u32 = |buf: &[u8]| -> Option<(usize, usize)> {
+        let bytes = buf.get(..4)?;
+        Some((u32::from_le_bytes(bytes.try_into().ok()?) as usize, 4))
+    };
+    let (count, mut offset) = take_u32(buf)?;
+    // every string takes at least its length prefix, which bounds what we reserve
+    let mut values = Vec::with_capacity(count.min(buf.len() / 4));
+    for _ in 0..count {
+        let (len, prefix) = take_u32(&buf[offset..])?;
+        offset += prefix;
+        let bytes = buf.get(offset..offset.checked_add(len)?)?;
+        values.push(String::from_utf8(bytes.to_vec()).ok()?);
+        offset += len;
+    }
+    (offset == buf.len()).then_some(values)
+}
+
+/// msg with its payload expanded, if it was compressed; the payload of a peer may
+/// expand to limit bytes at most, and a binary one to no more than its values take.
+/// Signatures cover the compressed form, so check them before expanding.
+pub(crate) fn expand_msg(
+    msg: &EvalNetMsg,
+    limit: usize,
+) -> Result<Cow<'_, EvalNetMsg>, CompressionError> {
+    match msg {
+        EvalNetMsg::PublishBatchValue {
+            session,
+            sender,
+            seq,
+            attempt,
+            round,
+            handles,
+            codec: Codec::Zstd,
+            payload,
+            signature,
+            ..
+        } => {
+            let packed = compression::decompress(payload, limit)?;
+            let values = unpack_strings(&packed).ok_or(CompressionError::Malformed)?;
+            Ok(Cow::Owned(EvalNetMsg::PublishBatchValue {
+                session: session.clone(),
+                sender: sender.clone(),
+                seq: *seq,
+                attempt: *attempt,
+                round: *round,
+                handles: handles.clone(),
+                values,
+                codec: Codec::Plain,
+                payload: vec![],
+                signature: signature.clone(),
+            }))
+        }
+        EvalNetMsg::PublishBatchBinary {
+            session,
+            sender,
+            seq,
+            attempt,
+            round,
+            handles,
+            payload,
+            kind,
+            codec: Codec::Zstd,
+            signature,
+        } => {
+            let size = payload_element_size(*kind).saturating_mul(handles.len());
+            let payload = compression::decompress(payload, limit.min(size))?;
+            Ok(Cow::Owned(EvalNetMsg::PublishBatchBinary {
+                session: session.clone(),
+                sender: sender.clone(),
+                seq: *seq,
+                attempt: *attempt,
+                round: *round,
+                handles: handles.clone(),
+                payload,
+                kind: *kind,
+                codec: Codec::Plain,
+                signature: signature.clone(),
+            }))
+        }
+        _ => Ok(Cow::Borrowed(msg)),
+    }
+}
 
-    identity::Keypair::ed25519_from_bytes(bytes).expect("only errors on wrong length")
+/// the bytes the sender of a value-carrying message, an ack, an abort, a heartbeat or a peer announcement
+/// signs; each field is length prefixed so that no two different messages produce the same bytes.
+/// returns None for messages that are not signed.
+fn signed_bytes(msg: &EvalNetMsg) -> Option<Vec<u8>> {
+    let mut buf = vec![];
+    match msg {
+        EvalNetMsg::PublishValue {
+            session,
+            sender,
+            seq,
+            round,
+            handle,
+            value,
+            ..
+        } => {
+            buf.push(1);
+            push_field(&mut buf, session.as_bytes());
+            push_field(&mut buf, sender.as_bytes());
+            buf.extend_from_slice(&seq.to_le_bytes());
+            buf.extend_from_slice(&round.to_le_bytes());
+            push_field(&mut buf, handle.as_bytes());
+            push_field(&mut buf, value.as_bytes());
+        }
+        EvalNetMsg::PublishBatchValue {
+            session,
+            sender,
+            seq,
+            round,
+            handles,
+            values,
+            codec,
+            payload,
+            ..
+        } => {
+            // compressed messages have tags of their own
+            buf.push(if codec.is_plain() { 2 } else { 8 });
+            push_field(&mut buf, session.as_bytes());
+            push_field(&mut buf, sender.as_bytes());
+            buf.extend_from_slice(&seq.to_le_bytes());
+            buf.extend_from_slice(&round.to_le_bytes());
+            buf.extend_from_slice(&(handles.len() as u32).to_le_bytes());
+            for h in handles.iter() {
+                push_field(&mut buf, h.as_bytes());
+            }
+            buf.extend_from_slice(&(values.len() as u32).to_le_bytes());
+            for v in values.iter() {
+                push_field(&mut buf, v.as_bytes());
+            }
+            if !codec.is_plain() {
+                buf.push(*codec as u8);
+                push_field(&mut buf, payload);
+            }
+        }
+        EvalNetMsg::PublishBatchBinary {
+            session,
+            sender,
+            seq,
+            round,
+            handles,
+            payload,
+            kind,
+            codec,
+            ..
+        } => {
+            buf.push(if codec.is_plain() { 3 } else { 9 });
+            push_field(&mut buf, session.as_bytes());
+            push_field(&mut buf, sender.as_bytes());
+            buf.extend_from_slice(&seq.to_le_bytes());
+            buf.extend_from_slice(&round.to_le_bytes());
+            buf.extend_from_slice(&(handles.len() as u32).to_le_bytes());
+            for h in handles.iter() {
+                push_field(&mut buf, h.as_bytes());
+            }
+            buf.push(*kind as u8);
+            if !codec.is_plain() {
+                buf.push(*codec as u8);
+            }
+            push_field(&mut buf, payload);
+        }
+        EvalNetMsg::DirectValue {
+            session,
+            sender,
+            seq,
+            round,
+            recipient,
+            handle,
+            value,
+            ..
+        } => {
+            buf.push(4);
+            push_field(&mut buf, session.as_bytes());
+            push_field(&mut buf, sender.as_bytes());
+            buf.extend_from_slice(&seq.to_le_bytes());
+            buf.extend_from_slice(&round.to_le_bytes());
+            push_field(&mut buf, recipient.as_bytes());
+            push_field(&mut buf, handle.as_bytes());
+            push_field(&mut buf, value.as_bytes());
+        }
+        EvalNetMsg::Ack {
+            session,
+            sender,
+            recipient,
+            seq,
+            ..
+        } => {
+            buf.push(5);
+            push_field(&mut buf, session.as_bytes());
+            push_field(&mut buf, sender.as_bytes());
+            push_field(&mut buf, recipient.as_bytes());
+            buf.extend_from_slice(&seq.to_le_bytes());
+        }
+        EvalNetMsg::Abort {
+            session,
+            sender,
+            reason,
+            ..
+        } => {
+            buf.push(6);
+            push_field(&mut buf, session.as_bytes());
+            push_field(&mut buf, sender.as_bytes());
+            push_field(&mut buf, reason.as_bytes());
+        }
+        EvalNetMsg::DirectBatchBinary {
+            session,
+            sender,
+            seq,
+            round,
+            recipient,
+            handles,
+            payload,
+            kind,
+            ..
+        } => {
+            buf.push(7);
+            push_field(&mut buf, session.as_bytes());
+            push_field(&mut buf, sender.as_bytes());
+            buf.extend_from_slice(&seq.to_le_bytes());
+            buf.extend_from_slice(&round.to_le_bytes());
+            push_field(&mut buf, recipient.as_bytes());
+            buf.extend_from_slice(&(handles.len() as u32).to_le_bytes());
+            for h in handles.iter() {
+                push_field(&mut buf, h.as_bytes());
+            }
+            buf.push(*kind as u8);
+            push_field(&mut buf, payload);
+        }
+        EvalNetMsg::Heartbeat {
+            session,
+            sender,
+            recipient,
+            sent_at,
+            ..
+        } => {
+            buf.push(10);
+            push_field(&mut buf, session.as_bytes());
+            push_field(&mut buf, sender.as_bytes());
+            push_field(&mut buf, recipient.as_deref().unwrap_or("").as_bytes());
+            buf.extend_from_slice(&sent_at.to_le_bytes());
+        }
+        // the notice of our own networkd has no sender, and is not signed
+        EvalNetMsg::ConnectionEstablished {
+            success,
+            sender: Some(sender),
+            binary_payloads,
+            session,
+            encoding_tag,
+            info,
+            waiting,
+            ..
+        } => {
+            buf.push(11);
+            push_field(&mut buf, session.as_bytes());
+            push_field(&mut buf, sender.as_bytes());
+            buf.push(*success as u8);
+            buf.push(*binary_payloads as u8);
+            buf.push(*encoding_tag);
+            let info = serde_json::to_vec(info).expect("a PeerInfo serializes to JSON");
+            push_field(&mut buf, &info);
+            buf.push(*waiting as u8);
+        }
+        _ => return None,
+    }
+    Some(buf)
 }
 
 pub async fn run_networking_daemon(
     secret_key_seed: u8,
     addr_book: &Pok3rAddrBook,
-    tx: &mut mpsc::UnboundedSender<EvalNetMsg>,
-    rx: mpsc::UnboundedReceiver<EvalNetMsg>,
-) -> Result<(), Box<dyn Error>> {
+    tx: &mut mpsc::Sender<EvalNetMsg>,
+    rx: mpsc::Receiver<EvalNetMsg>,
+) -> Result<(), Box<dyn error::Error>> {
     run_networking_daemon_with_kill(secret_key_seed, addr_book, tx, rx, None).await
 }
 
 pub async fn run_networking_daemon_with_kill(
     secret_key_seed: u8,
     addr_book: &Pok3rAddrBook,
-    tx: &mut mpsc::UnboundedSender<EvalNetMsg>,
-    mut rx: mpsc::UnboundedReceiver<EvalNetMsg>,
+    tx: &mut mpsc::Sender<EvalNetMsg>,
+    rx: mpsc::Receiver<EvalNetMsg>,
+    rx_kill: Option<mpsc::UnboundedReceiver<()>>,
+) -> Result<(), Box<dyn error::Error>> {
+    // Listen on all interfaces and whatever port the OS assigns
+    run_networking_daemon_on(
+        keypair_from_seed(secret_key_seed),
+        "/ip4/0.0.0.0/udp/0/quic-v1".parse()?,
+        addr_book,
+        tx,
+        rx,
+        rx_kill,
+        Arc::new(Metrics::new()),
+    )
+    .await
+}
+
+/// runs the daemon as id_keys, listening on listen_addr; the messages it drops
+/// because they do not decode are counted in metrics
+pub async fn run_networking_daemon_on(
+    id_keys: identity::Keypair,
+    listen_addr: Multiaddr,
+    addr_book: &Pok3rAddrBook,
+    tx: &mut mpsc::Sender<EvalNetMsg>,
+    mut rx: mpsc::Receiver<EvalNetMsg>,
     mut rx_kill: Option<mpsc::UnboundedReceiver<()>>,
-) -> Result<(), Box<dyn Error>> {
-    // Create a random PeerId
-    //let id_keys = identity::Keypair::generate_ed25519();
-    let id_keys: identity::Keypair = generate_ed25519(secret_key_seed);
+    metrics: Arc<Metrics>,
+) -> Result<(), Box<dyn error::Error>> {
     let local_peer_id = PeerId::from(id_keys.public());
     #[cfg(feature = "print")]
     println!("Local peer id: {local_peer_id}");
@@ -59,7 +564,7 @@ pub async fn run_networking_daemon_with_kill(
     // Set up an encrypted DNS-enabled TCP Transport over the yamux protocol.
     let tcp_transport = tcp::async_io::Transport::new(tcp::Config::default().nodelay(true))
         .upgrade(upgrade::Version::V1Lazy)
-        .authenticate(noise::Config::new(&id_keys).expect("signing libp2p-noise static keypair"))
+        .authenticate(noise::Config::new(&id_keys)?)
         .multiplex(yamux::Config::default())
         .timeout(std::time::Duration::from_secs(20))
         .boxed();
@@ -83,19 +588,24 @@ pub async fn run_networking_daemon_with_kill(
         .heartbeat_interval(Duration::from_secs(10)) // This is set to aid debugging by not cluttering the log space
         .validation_mode(gossipsub::ValidationMode::Strict) // This sets the kind of message validation. The default is Strict (enforce message signing)
         .message_id_fn(message_id_fn) // content-address messages. No two messages of the same content will be propagated.
-        .build()
-        .expect("Valid config");
+        .build()?;
 
     // build a gossipsub network behaviour
     let mut gossipsub = gossipsub::Behaviour::new(
         gossipsub::MessageAuthenticity::Signed(id_keys),
         gossipsub_config,
-    )
-    .expect("Correct configuration");
+    )?;
     // Create a Gossipsub topic
     let topic = gossipsub::IdentTopic::new("mpc-test-net");
     // subscribes to our topic
     gossipsub.subscribe(&topic)?;
+    // and to the direct topics of every peer towards us
+    let local_peer_id_encoded = local_peer_id.to_base58();
+    for peer_id in addr_book.peer_ids() {
+        if !peer_id.eq(&local_peer_id_encoded) {
+            gossipsub.subscribe(&direct_topic(peer_id, &local_peer_id_encoded))?;
+        }
+    }
 
     // Create a Swarm to manage peers and events
     let mdns = mdns::async_io::Behaviour::new(mdns::Config::default(), local_peer_id)?;
@@ -109,8 +619,7 @@ pub async fn run_networking_daemon_with_kill(
     // Read full lines from stdin
     //let mut stdin = io::BufReader::new(io::stdin()).lines().fuse();
 
-    // Listen on all interfaces and whatever port the OS assigns
-    swarm.listen_on("/ip4/0.0.0.0/udp/0/quic-v1".parse()?)?;
+    swarm.listen_on(listen_addr)?;
     //swarm.listen_on("/ip4/0.0.0.0/tcp/0".parse()?)?;
 
     let mut connected_peers: Vec<PeerId> = vec![];
@@ -137,10 +646,27 @@ pub async fn run_networking_daemon_with_kill(
         select! {
             //receives requests for publishing messages from the evaluator
             msg_to_send = rx.select_next_some() => {
-                let s = serde_json::to_string(&msg_to_send).unwrap();
+                let data = match encode_wire_msg(&msg_to_send) {
+                    Ok(data) => data,
+                    #[allow(unused_variables)]
+                    Err(e) => {
+                        #[cfg(feature = "print")]
+                        println!("Encoding error: {e}");
+                        continue;
+                    }
+                };
+                let msg_topic = match &msg_to_send {
+                    EvalNetMsg::DirectValue { sender, recipient, .. }
+                    | EvalNetMsg::DirectBatchBinary { sender, recipient, .. }
+                    | EvalNetMsg::Ack { sender, recipient, .. }
+                    | EvalNetMsg::Heartbeat { sender, recipient: Some(recipient), .. } => {
+                        direct_topic(sender, recipient)
+                    }
+                    _ => topic.clone(),
+                };
                 if let Err(e) = swarm
                     .behaviour_mut().gossipsub
-                    .publish(topic.clone(), <String as AsRef<[u8]>>::as_ref(&s)) {
+                    .publish(msg_topic, data) {
                     println!("Publish error: {e:?}");
                 }
             },
@@ -153,13 +679,27 @@ pub async fn run_networking_daemon_with_kill(
                         let peer_id_encoded = peer_id.to_base58();
                         swarm.behaviour_mut().gossipsub.add_explicit_peer(&peer_id);
 
-                        if addr_book.contains_key(&peer_id_encoded) {
+                        if addr_book.contains(&peer_id_encoded) {
                             connected_peers.push(peer_id);
 
-                            if !connection_informed &&
-                                (connected_peers.len() == addr_book.len() - 1) {
+                            // a verifier is not in the addr book, so it waits for every player
+                            let expected = if addr_book.contains(&local_peer_id_encoded) {
+                                addr_book.len() - 1
+                            } else {
+                                addr_book.len()
+                            };
+                            if !connection_informed && connected_peers.len() == expected {
                                 let _r = tx.send(
-                                    EvalNetMsg::ConnectionEstablished { success: true }
+                                    EvalNetMsg::ConnectionEstablished {
+                                        success: true,
+                                        sender: None,
+                                        binary_payloads: false,
+                                        session: String::new(),
+                                        encoding_tag: 0,
+                                        info: None,
+                                        waiting: false,
+                                        signature: String::new(),
+                                    }
                                 ).await;
                                 // if let Err(err) = r {
                                 //     eprint!("network error {:?}", err);
@@ -182,11 +722,29 @@ pub async fn run_networking_daemon_with_kill(
                     message_id: _id,
                     message,
                 })) => {
-                    let msg_as_str = String::from_utf8_lossy(&message.data);
-                    let deserialized_struct = serde_json::from_str(&msg_as_str).unwrap();
-                    let r = tx.send(deserialized_struct).await;
-                    if let Err(err) = r {
-                        eprint!("network error {:?}", err);
+                    match decode_wire_msg(&message.data) {
+                        // direct values, acks and answers to heartbeats for someone
+                        // else are not our business
+                        Some(EvalNetMsg::DirectValue { recipient, .. })
+                        | Some(EvalNetMsg::DirectBatchBinary { recipient, .. })
+                        | Some(EvalNetMsg::Ack { recipient, .. })
+                        | Some(EvalNetMsg::Heartbeat { recipient: Some(recipient), .. })
+                            if !recipient.eq(&local_peer_id_encoded) => {}
+                        // an evaluator that is behind does not hold up the swarm; what it
+                        // does not acknowledge, its sender transmits again
+                        Some(deserialized_struct) => match tx.try_send(deserialized_struct) {
+                            Ok(()) => (),
+                            Err(err) if err.is_full() => {
+                                #[cfg(feature = "print")]
+                                println!("dropping message, the evaluator is behind");
+                            }
+                            Err(err) => eprint!("network error {:?}", err),
+                        },
+                        None => {
+                            metrics.record_malformed();
+                            #[cfg(feature = "print")]
+                            println!("Dropping malformed message from the network");
+                        }
                     }
                 },
                 //prints out the address this program is listening on for new connections
@@ -202,170 +760,3217 @@ pub async fn run_networking_daemon_with_kill(
     Ok(())
 }
 
+/// default time recv_from_all waits for the other parties
+pub const DEFAULT_RECV_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// default number of rounds ahead of ours whose values are kept until we get there
+pub const DEFAULT_ROUND_HORIZON: u64 = 16;
+
+/// RetransmitConfig controls how unacknowledged messages are sent again
+#[derive(Debug, Clone, Copy)]
+pub struct RetransmitConfig {
+    /// wait before the first retransmission; doubled after each one
+    pub initial_backoff: Duration,
+    /// the wait between retransmissions never grows beyond this
+    pub max_backoff: Duration,
+    /// retransmissions before giving up with DeliveryFailed
+    pub max_attempts: u32,
+}
+
+impl Default for RetransmitConfig {
+    fn default() -> Self {
+        RetransmitConfig {
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(8),
+            max_attempts: 10,
+        }
+    }
+}
+
+/// compressed payloads of peers may expand to this many bytes at most, unless
+/// CompressionConfig says otherwise
+pub const DEFAULT_MAX_EXPANDED_SIZE: usize = 64 << 20;
+
+/// CompressionConfig controls the zstd compression of the payloads of batch
+/// broadcasts; it is used only once every peer has announced that it takes zstd
+#[derive(Debug, Clone, Copy)]
+pub struct CompressionConfig {
+    /// payloads of fewer bytes go out as they are
+    pub threshold: usize,
+    /// zstd compression level
+    pub level: i32,
+    /// compressed payloads of peers that would expand beyond this are dropped
+    pub max_expanded_size: usize,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        CompressionConfig {
+            threshold: 4096,
+            level: 3,
+            max_expanded_size: DEFAULT_MAX_EXPANDED_SIZE,
+        }
+    }
+}
+
+/// default capacity of the bounded channels between an evaluator and its networkd
+pub const DEFAULT_QUEUE_CAPACITY: usize = 4096;
+
+/// QueueConfig bounds what a MessagingSystem keeps in memory for itself and
+/// for each of its peers
+#[derive(Debug, Clone, Copy)]
+pub struct QueueConfig {
+    /// our messages kept for retransmission; sends wait for acks rather than
+    /// keep more
+    pub max_unacked: usize,
+    /// values of a peer that may wait in our mailboxes; messages of the peer
+    /// beyond that go unacknowledged, so that they come again once we have
+    /// taken some
+    pub max_pending_per_peer: usize,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        QueueConfig {
+            max_unacked: 1024,
+            max_pending_per_peer: 1 << 16,
+        }
+    }
+}
+
+/// HeartbeatConfig controls the heartbeats that tell which peers are still there
+#[derive(Debug, Clone, Copy)]
+pub struct HeartbeatConfig {
+    /// wait between two heartbeats we send
+    pub interval: Duration,
+    /// a peer we have not heard from for this many intervals is dead
+    pub miss_threshold: u32,
+}
+
+impl Default for HeartbeatConfig {
+    fn default() -> Self {
+        HeartbeatConfig {
+            interval: Duration::from_secs(1),
+            miss_threshold: 5,
+        }
+    }
+}
+
+/// PeerStatus is what the heartbeats say about a peer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerStatus {
+    /// heard from within the last two intervals; rtt is the round trip time of
+    /// its last answer to one of our heartbeats
+    Alive { rtt: Option<Duration> },
+    /// silent for two intervals or more
+    Suspect,
+    /// silent for miss_threshold intervals; it stays dead until update_addr_book
+    Dead,
+}
+
+/// when we last heard from a peer
+struct PeerLiveness {
+    last_heard: Instant,
+    rtt: Option<Duration>,
+}
+
+/// records a journal may hold beyond twice the live ones before it is compacted
+const JOURNAL_SLACK: usize = 256;
+
+/// a message we sent that some recipients have not acknowledged yet
+struct PendingMessage {
+    msg: EvalNetMsg,
+    awaiting: HashSet<Pok3rPeerId>,
+    attempts: u32,
+    backoff: Duration,
+    next_retry: Instant,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NetworkError {
+    /// not every party delivered a value for handle in time
+    Timeout {
+        handle: String,
+        missing_senders: Vec<u64>,
+    },
+    /// a message claiming to come from this peer was not signed by its key
+    BadSignature(Pok3rPeerId),
+    /// our message seq was still not acknowledged by these parties after all retransmissions
+    DeliveryFailed {
+        seq: u64,
+        missing_recipients: Vec<u64>,
+    },
+    /// the party with node id by (possibly us) aborted the session
+    Aborted { by: u64, reason: String },
+    /// a receive for this handle was cancelled halfway, so the committee cannot change yet
+    ReceivePending(String),
+    /// the new addr book does not list our own peer id
+    NotInAddrBook,
+    /// no party of the addr book has this node id
+    UnknownParty(u64),
+    /// the addr book does not list this peer id as a player
+    NotAPlayer(Pok3rPeerId),
+    /// a send was given no handle, or handles and values differ in number
+    MismatchedValues { handles: usize, values: usize },
+    /// a session with this id is open already on the router
+    SessionOpen(String),
+    /// this peer announced another encoding_tag than ours, so it is built for
+    /// another curve or value format
+    EncodingMismatch {
+        peer: Pok3rPeerId,
+        expected: u8,
+        got: u8,
+    },
+    /// sender's values reached parties differently in a consistent broadcast; the
+    /// digest of what each party got from it, by node id
+    Equivocation {
+        sender: u64,
+        conflicting_digests: Vec<(u64, String)>,
+    },
+    /// this peer cannot play with us: its PeerInfo differs from ours in field
+    IncompatiblePeer {
+        peer: Pok3rPeerId,
+        field: String,
+        ours: String,
+        theirs: String,
+    },
+    /// this peer filled our mailboxes with pending values that nobody takes, so
+    /// that what we wait for from it no longer gets in
+    QueueOverflow {
+        peer: Pok3rPeerId,
+        pending: usize,
+        limit: usize,
+    },
+    /// the party with this node id missed too many heartbeats
+    PeerDown(u64),
+    /// writing the journal failed, so a restart could no longer resume from it
+    Journal(String),
+    /// a message could not be serialized for the wire
+    Encoding(String),
+    /// this addr book entry does not carry an ed25519 key, so nothing it signs
+    /// can be checked
+    NoPublicKey(Pok3rPeerId),
+}
+
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NetworkError::Timeout {
+                handle,
+                missing_senders,
+            } => write!(
+                f,
+                "timed out waiting for {} from parties {:?}",
+                handle, missing_senders
+            ),
+            NetworkError::BadSignature(sender) => {
+                write!(f, "invalid signature on a message from {}", sender)
+            }
+            NetworkError::DeliveryFailed {
+                seq,
+                missing_recipients,
+            } => write!(
+                f,
+                "message {} was not acknowledged by parties {:?}",
+                seq, missing_recipients
+            ),
+            NetworkError::Aborted { by, reason } => {
+                write!(f, "party {} aborted the session: {}", by, reason)
+            }
+            NetworkError::ReceivePending(handle) => {
+                write!(f, "the receive of {} has not completed", handle)
+            }
+            NetworkError::NotInAddrBook => write!(f, "our key is not in the addr book"),
+            NetworkError::UnknownParty(node_id) => {
+                write!(f, "no party has node id {}", node_id)
+            }
+            NetworkError::NotAPlayer(peer_id) => write!(f, "{} is not a player", peer_id),
+            NetworkError::MismatchedValues { handles, values } => write!(
+                f,
+                "cannot send {} values under {} handles",
+                values, handles
+            ),
+            NetworkError::SessionOpen(session) => {
+                write!(f, "session {} is already open", session)
+            }
+            NetworkError::EncodingMismatch {
+                peer,
+                expected,
+                got,
+            } => write!(
+                f,
+                "peer {} encodes values with tag {:#04x} instead of {:#04x}; is it built for another curve?",
+                peer, got, expected
+            ),
+            NetworkError::Equivocation {
+                sender,
+                conflicting_digests,
+            } => write!(
+                f,
+                "party {} sent different values to different parties: {:?}",
+                sender, conflicting_digests
+            ),
+            NetworkError::IncompatiblePeer {
+                peer,
+                field,
+                ours,
+                theirs,
+            } => write!(
+                f,
+                "peer {} is incompatible: its {} is {} where ours is {}",
+                peer, field, theirs, ours
+            ),
+            NetworkError::QueueOverflow {
+                peer,
+                pending,
+                limit,
+            } => write!(
+                f,
+                "peer {} has {} values waiting, and no room for more under the limit of {}",
+                peer, pending, limit
+            ),
+            NetworkError::PeerDown(node_id) => {
+                write!(f, "party {} stopped sending heartbeats", node_id)
+            }
+            NetworkError::Journal(err) => write!(f, "cannot write the journal: {}", err),
+            NetworkError::Encoding(err) => write!(f, "cannot encode a message: {}", err),
+            NetworkError::NoPublicKey(peer_id) => {
+                write!(f, "{} is not an ed25519 peer id", peer_id)
+            }
+        }
+    }
+}
+
+impl error::Error for NetworkError {}
+
+/// where a MessagingSystem takes its incoming messages from
+pub(crate) type Inbox = Pin<Box<dyn FusedStream<Item = EvalNetMsg> + Send + Sync>>;
+
 pub struct MessagingSystem {
     /// local peer id
     pub id: Pok3rPeerId,
+    /// the game (or run) we take part in; messages of other sessions are dropped
+    session_id: String,
+    /// number of messages dropped because they belong to another session
+    foreign_session_messages: u64,
+    /// signs everything we send
+    keypair: identity::Keypair,
+    /// public keys of all peers, for checking what they send
+    peer_keys: HashMap<Pok3rPeerId, identity::PublicKey>,
+    /// peers in whose name we received wrongly signed messages
+    forged_senders: HashSet<Pok3rPeerId>,
     /// information about all other peers
     pub addr_book: Pok3rAddrBook,
-    /// receiver channel from the networkd
-    rx: mpsc::UnboundedReceiver<EvalNetMsg>,
-    /// sender channel towards the networkd
-    tx: mpsc::UnboundedSender<EvalNetMsg>,
+    /// receiver channel from the networkd, or from a Router for one session
+    rx: Inbox,
+    /// bounded sender channel towards the networkd
+    tx: mpsc::Sender<EvalNetMsg>,
     /// stores incoming messages indexed by identifier and then by peer id
     mailbox: HashMap<String, HashMap<String, String>>,
+    /// same as mailbox, but for values sent only to us
+    direct_mailbox: HashMap<String, HashMap<String, String>>,
+    /// whether we accept and send PublishBatchBinary messages
+    binary_payloads: bool,
+    /// peers that have advertised support for PublishBatchBinary
+    binary_peers: HashSet<Pok3rPeerId>,
+    /// how long recv_from_all waits for the other parties
+    recv_timeout: Duration,
+    /// sequence number of the next message we send
+    next_seq: u64,
+    /// our messages that are not acknowledged by all their recipients, by seq
+    unacked: BTreeMap<u64, PendingMessage>,
+    /// sequence numbers already accepted from each peer, to ignore retransmissions
+    delivered: HashMap<Pok3rPeerId, HashSet<u64>>,
+    /// round of the evaluator, stamped on what we send; values of earlier rounds are dropped
+    round: u64,
+    /// values of rounds up to this far ahead of ours are kept; later ones go unacknowledged,
+    /// so that their senders retransmit them once we may have caught up
+    round_horizon: u64,
+    /// handles received in this round, so that another value for one of them is
+    /// dropped rather than kept for a later receive of the handle
+    received: HashSet<String>,
+    /// same as received, for the values sent only to us, by sender
+    direct_received: HashMap<Pok3rPeerId, HashSet<String>>,
+    retransmit: RetransmitConfig,
+    queues: QueueConfig,
+    /// number of values of each peer waiting in mailbox and direct_mailbox
+    pending: HashMap<Pok3rPeerId, usize>,
+    /// peers whose last message did not fit in our mailboxes
+    overflowing: HashSet<Pok3rPeerId>,
+    /// None until heartbeats are turned on; the flag stops the task sending ours
+    heartbeats: Option<(HeartbeatConfig, Arc<AtomicBool>)>,
+    /// the sent_at of our heartbeats counts from here
+    started: Instant,
+    /// when we last heard from each peer, once heartbeats are on
+    liveness: HashMap<Pok3rPeerId, PeerLiveness>,
+    /// peers that missed miss_threshold heartbeats
+    dead: HashSet<Pok3rPeerId>,
+    /// where on_peer_down streams go
+    down_subscribers: Vec<mpsc::UnboundedSender<u64>>,
+    /// None unless what is in flight is kept to survive a restart
+    journal: Option<Journal>,
+    /// why writing the journal failed; every receive fails from then on
+    journal_error: Option<String>,
+    /// None until compression is turned on
+    compression: Option<CompressionConfig>,
+    /// counters shared with the evaluator
+    metrics: Arc<Metrics>,
+    /// node id and reason of the first abort we saw (or sent) for our session
+    aborted: Option<(u64, String)>,
+    /// the first peer that announced another encoding_tag than ours, with its tag
+    mismatched_peer: Option<(Pok3rPeerId, u8)>,
+    /// what we announce in the handshake; None until it has started
+    info: Option<PeerInfo>,
+    /// what each peer announced in the handshake
+    peer_infos: HashMap<Pok3rPeerId, PeerInfo>,
+    /// handle of the receive in progress; only stays set if that receive was cancelled
+    pending_recv: Option<String>,
+    /// handles that recv_from_some returned early, with the number of values still to come
+    late_senders: HashMap<String, usize>,
+    /// the handles of late_senders that recv_quorum returned, whose late values are kept
+    quorum_handles: HashSet<String>,
+    /// values that came after recv_quorum returned, as (handle, node id, value)
+    late_values: Vec<(String, u64, String)>,
+}
+
+/// the session msg belongs to; None for the networkd's own connection notice
+pub(crate) fn session_of(msg: &EvalNetMsg) -> Option<&str> {
+    match msg {
+        EvalNetMsg::ConnectionEstablished { sender: None, .. } => None,
+        EvalNetMsg::ConnectionEstablished { session, .. }
+        | EvalNetMsg::Greeting { session, .. }
+        | EvalNetMsg::PublishValue { session, .. }
+        | EvalNetMsg::PublishBatchValue { session, .. }
+        | EvalNetMsg::PublishBatchBinary { session, .. }
+        | EvalNetMsg::DirectValue { session, .. }
+        | EvalNetMsg::DirectBatchBinary { session, .. }
+        | EvalNetMsg::Ack { session, .. }
+        | EvalNetMsg::Abort { session, .. }
+        | EvalNetMsg::Heartbeat { session, .. } => Some(session),
+    }
+}
+
+/// checks that a value-carrying message or a peer announcement was signed by the
+/// key of its sender
+pub fn check_signature(
+    msg: &EvalNetMsg,
+    peer_keys: &HashMap<Pok3rPeerId, identity::PublicKey>,
+) -> Result<(), NetworkError> {
+    let (sender, signature) = match msg {
+        EvalNetMsg::PublishValue {
+            sender, signature, ..
+        }
+        | EvalNetMsg::PublishBatchValue {
+            sender, signature, ..
+        }
+        | EvalNetMsg::PublishBatchBinary {
+            sender, signature, ..
+        }
+        | EvalNetMsg::DirectValue {
+            sender, signature, ..
+        }
+        | EvalNetMsg::DirectBatchBinary {
+            sender, signature, ..
+        }
+        | EvalNetMsg::Ack {
+            sender, signature, ..
+        }
+        | EvalNetMsg::Abort {
+            sender, signature, ..
+        }
+        | EvalNetMsg::Heartbeat {
+            sender, signature, ..
+        }
+        | EvalNetMsg::ConnectionEstablished {
+            sender: Some(sender),
+            signature,
+            ..
+        } => (sender, signature),
+        _ => return Ok(()),
+    };
+
+    let valid = match (
+        peer_keys.get(sender),
+        bs58::decode(signature).into_vec(),
+        signed_bytes(msg),
+    ) {
+        (Some(key), Ok(sig), Some(bytes)) => key.verify(&bytes, &sig),
+        _ => false,
+    };
+    if valid {
+        Ok(())
+    } else {
+        Err(NetworkError::BadSignature(sender.clone()))
+    }
+}
+
+/// the public key of every addr book entry; fails on an entry that carries none
+pub(crate) fn peer_keys_of(
+    addr_book: &Pok3rAddrBook,
+) -> Result<HashMap<Pok3rPeerId, identity::PublicKey>, NetworkError> {
+    addr_book
+        .peer_ids()
+        .map(|peer_id| match public_key_of(peer_id) {
+            Some(key) => Ok((peer_id.clone(), key)),
+            None => Err(NetworkError::NoPublicKey(peer_id.clone())),
+        })
+        .collect()
+}
+
+/// the handles of the values msg carries; none for other messages
+fn handles_of(msg: &EvalNetMsg) -> &[String] {
+    match msg {
+        EvalNetMsg::PublishValue { handle, .. } | EvalNetMsg::DirectValue { handle, .. } => {
+            std::slice::from_ref(handle)
+        }
+        EvalNetMsg::PublishBatchValue { handles, .. }
+        | EvalNetMsg::PublishBatchBinary { handles, .. }
+        | EvalNetMsg::DirectBatchBinary { handles, .. } => handles,
+        _ => &[],
+    }
+}
+
+/// fails unless a send of values under handles carries one value per handle,
+/// and at least one
+fn check_values(handles: usize, values: usize) -> Result<(), NetworkError> {
+    if handles != values || handles == 0 {
+        return Err(NetworkError::MismatchedValues { handles, values });
+    }
+    Ok(())
+}
+
+/// fills in the signature of keypair on a value-carrying message, an ack, an
+/// abort, a heartbeat or a peer announcement
+pub fn sign_msg(keypair: &identity::Keypair, mut msg: EvalNetMsg) -> EvalNetMsg {
+    let bytes = signed_bytes(&msg).expect("only messages with a sender are signed");
+    let sig = keypair.sign(&bytes).expect("ed25519 signing does not fail");
+    match &mut msg {
+        EvalNetMsg::PublishValue { signature, .. }
+        | EvalNetMsg::PublishBatchValue { signature, .. }
+        | EvalNetMsg::PublishBatchBinary { signature, .. }
+        | EvalNetMsg::DirectValue { signature, .. }
+        | EvalNetMsg::DirectBatchBinary { signature, .. }
+        | EvalNetMsg::Ack { signature, .. }
+        | EvalNetMsg::Abort { signature, .. }
+        | EvalNetMsg::Heartbeat { signature, .. }
+        | EvalNetMsg::ConnectionEstablished { signature, .. } => {
+            *signature = bs58::encode(sig).into_string();
+        }
+        _ => (),
+    }
+    msg
+}
+
+/// sends heartbeat, stamped with the milliseconds since started, each interval
+/// until stop is set or the networkd is gone. It runs apart from its
+/// MessagingSystem, so that the peers keep hearing from us while we compute;
+/// a heartbeat that finds the queue full is skipped.
+async fn send_heartbeats(
+    mut tx: mpsc::Sender<EvalNetMsg>,
+    keypair: identity::Keypair,
+    mut heartbeat: EvalNetMsg,
+    started: Instant,
+    interval: Duration,
+    metrics: Arc<Metrics>,
+    stop: Arc<AtomicBool>,
+) {
+    while !stop.load(Ordering::Relaxed) {
+        if let EvalNetMsg::Heartbeat { sent_at, .. } = &mut heartbeat {
+            *sent_at = started.elapsed().as_millis() as u64;
+        }
+        let msg = sign_msg(&keypair, heartbeat.clone());
+        metrics.record_sent(&msg);
+        if let Err(err) = tx.try_send(msg) {
+            if err.is_disconnected() {
+                return;
+            }
+        }
+        async_std::task::sleep(interval).await;
+    }
+}
+
+/// owned copies of borrowed handles or values, for a message to carry
+fn to_strings<S: AsRef<str>>(strs: &[S]) -> Vec<String> {
+    strs.iter().map(|s| s.as_ref().to_string()).collect()
 }
 
 impl MessagingSystem {
+    /// our peer id is the one of keypair, which must be in the addr book, or this
+    /// fails with NotInAddrBook; all parties of a game must use the same session_id
     pub async fn new(
-        id: &Pok3rPeerId,
+        keypair: identity::Keypair,
+        session_id: &str,
+        addr_book: Pok3rAddrBook,
+        tx: mpsc::Sender<EvalNetMsg>,
+        rx: mpsc::Receiver<EvalNetMsg>,
+    ) -> Result<Self, NetworkError> {
+        Self::new_with_capabilities(keypair, session_id, addr_book, tx, rx, true).await
+    }
+
+    /// same as new, but lets the caller decide whether to use the binary wire format
+    pub async fn new_with_capabilities(
+        keypair: identity::Keypair,
+        session_id: &str,
+        addr_book: Pok3rAddrBook,
+        tx: mpsc::Sender<EvalNetMsg>,
+        rx: mpsc::Receiver<EvalNetMsg>,
+        binary_payloads: bool,
+    ) -> Result<Self, NetworkError> {
+        Self::new_with_inbox(
+            keypair,
+            session_id,
+            addr_book,
+            tx,
+            Box::pin(rx),
+            binary_payloads,
+        )
+        .await
+    }
+
+    /// same as new_with_capabilities, with the incoming messages taken from rx
+    pub(crate) async fn new_with_inbox(
+        keypair: identity::Keypair,
+        session_id: &str,
         addr_book: Pok3rAddrBook,
-        tx: mpsc::UnboundedSender<EvalNetMsg>,
-        mut rx: mpsc::UnboundedReceiver<EvalNetMsg>,
-    ) -> Self {
+        tx: mpsc::Sender<EvalNetMsg>,
+        rx: Inbox,
+        binary_payloads: bool,
+    ) -> Result<Self, NetworkError> {
+        let id = PeerId::from(keypair.public()).to_base58();
+        if !addr_book.contains(&id) {
+            return Err(NetworkError::NotInAddrBook);
+        }
+        let peer_keys = peer_keys_of(&addr_book)?;
+
+        let mut messaging = MessagingSystem {
+            id,
+            session_id: session_id.to_string(),
+            foreign_session_messages: 0,
+            keypair,
+            peer_keys,
+            forged_senders: HashSet::new(),
+            addr_book,
+            rx,
+            tx,
+            mailbox: HashMap::new(),
+            direct_mailbox: HashMap::new(),
+            binary_payloads,
+            binary_peers: HashSet::new(),
+            recv_timeout: DEFAULT_RECV_TIMEOUT,
+            next_seq: 0,
+            unacked: BTreeMap::new(),
+            delivered: HashMap::new(),
+            round: 0,
+            round_horizon: DEFAULT_ROUND_HORIZON,
+            received: HashSet::new(),
+            direct_received: HashMap::new(),
+            retransmit: RetransmitConfig::default(),
+            queues: QueueConfig::default(),
+            pending: HashMap::new(),
+            overflowing: HashSet::new(),
+            heartbeats: None,
+            started: Instant::now(),
+            liveness: HashMap::new(),
+            dead: HashSet::new(),
+            down_subscribers: Vec::new(),
+            journal: None,
+            journal_error: None,
+            compression: None,
+            metrics: Arc::new(Metrics::new()),
+            aborted: None,
+            mismatched_peer: None,
+            info: None,
+            peer_infos: HashMap::new(),
+            pending_recv: None,
+            late_senders: HashMap::new(),
+            quorum_handles: HashSet::new(),
+            late_values: Vec::new(),
+        };
+        messaging.metrics.set_round_horizon(DEFAULT_ROUND_HORIZON);
+
         // we expect the first message from the
         // networkd to be a connection established;
         // so, here we will loop till we get that
         loop {
             //do a blocking recv on the rx channel
-            let msg: EvalNetMsg = rx.select_next_some().await;
+            let msg: EvalNetMsg = messaging.rx.select_next_some().await;
             match msg {
-                EvalNetMsg::ConnectionEstablished { success } => {
+                EvalNetMsg::ConnectionEstablished {
+                    success,
+                    sender: None,
+                    ..
+                } => {
                     if success {
                         #[cfg(feature = "print")]
                         println!("evaluator connected to the network");
                         break;
                     }
                 }
-                _ => continue,
+                // peers may already be talking to us, so keep what they sent
+                _ => messaging.process_next_message(&msg),
             }
         }
 
-        MessagingSystem {
-            id: id.clone(),
-            addr_book,
-            rx,
-            tx,
-            mailbox: HashMap::new(),
-        }
+        // let the peers know which wire formats we understand
+        let announcement = messaging.announcement(false);
+        messaging.metrics.record_sent(&announcement);
+        messaging.send(announcement).await;
+
+        Ok(messaging)
+    }
+
+    /// the node id of peer_id, which a message may name without being in the addr book
+    fn node_of(&self, peer_id: &Pok3rPeerId) -> Result<u64, NetworkError> {
+        self.addr_book
+            .node_of(peer_id)
+            .ok_or_else(|| NetworkError::NotAPlayer(peer_id.clone()))
     }
 
     pub fn get_my_id(&self) -> u64 {
-        get_node_id_via_peer_id(&self.addr_book, &self.id).unwrap()
+        self.addr_book
+            .node_of(&self.id)
+            .expect("new and update_addr_book keep us in the addr book")
+    }
+
+    pub fn session_id(&self) -> &str {
+        &self.session_id
     }
 
-    pub async fn send_to_all(
+    /// how many messages of other sessions we have dropped so far
+    pub fn foreign_session_messages(&self) -> u64 {
+        self.foreign_session_messages
+    }
+
+    /// communication counters, shared by everyone holding the returned handle
+    pub fn metrics(&self) -> Arc<Metrics> {
+        self.metrics.clone()
+    }
+
+    /// broadcasts values, one per handle; the message owns the only copies made
+    pub async fn send_to_all<H: AsRef<str>, V: AsRef<str>>(
         &mut self,
-        handles: impl AsRef<[String]>,
-        values: impl AsRef<[String]>,
-    ) {
-        assert!(handles.as_ref().len() == values.as_ref().len() && !handles.as_ref().is_empty());
+        handles: &[H],
+        values: &[V],
+    ) -> Result<(), NetworkError> {
+        check_values(handles.len(), values.len())?;
 
-        let msg = if handles.as_ref().len() > 1 {
+        let seq = self.take_seq();
+        let msg = if handles.len() > 1 {
             EvalNetMsg::PublishBatchValue {
+                session: self.session_id.clone(),
                 sender: self.id.clone(),
-                handles: handles.as_ref().to_owned(),
-                values: values.as_ref().to_owned(),
+                seq,
+                attempt: 0,
+                round: self.round,
+                handles: to_strings(handles),
+                values: to_strings(values),
+                codec: Codec::Plain,
+                payload: vec![],
+                signature: String::new(),
             }
         } else {
             EvalNetMsg::PublishValue {
+                session: self.session_id.clone(),
                 sender: self.id.clone(),
-                handle: handles.as_ref()[0].clone(),
-                value: values.as_ref()[0].clone(),
+                seq,
+                attempt: 0,
+                round: self.round,
+                handle: handles[0].as_ref().to_string(),
+                value: values[0].as_ref().to_string(),
+                signature: String::new(),
             }
         };
-        let r = self.tx.send(msg).await;
-        if let Err(err) = r {
-            eprint!("evaluator error {:?}", err);
-        }
+        let recipients = self.peers();
+        self.publish(seq, msg, recipients).await
     }
 
-    pub async fn recv_from_all(&mut self, identifier: &String) -> HashMap<u64, String> {
-        let mut messages: HashMap<u64, String> = HashMap::new();
-        let peers: Vec<Pok3rPeerId> = self.addr_book.keys().cloned().collect();
-        for peer_id in peers {
-            if self.id.eq(&peer_id) {
-                continue;
-            } // ignore self
+    /// broadcasts canonically serialized values of the given kind, using the
+    /// binary wire format if every peer supports it and base58 strings otherwise
+    pub async fn send_batch_to_all<H: AsRef<str>>(
+        &mut self,
+        handles: &[H],
+        kind: PayloadKind,
+        values: impl AsRef<[Vec<u8>]>,
+    ) -> Result<(), NetworkError> {
+        check_values(handles.len(), values.as_ref().len())?;
 
-            loop {
-                //loop over all incoming messages till we find msg from peer
-                if self.mailbox.contains_key(identifier) {
-                    let sender_exists_for_handle =
-                        self.mailbox.get(identifier).unwrap().contains_key(&peer_id);
-                    //if we already have it, break out!
-                    if sender_exists_for_handle {
-                        break;
-                    }
-                }
+        // pick up any capability announcements that arrived in the meantime
+        self.process_pending_messages();
 
-                let msg: EvalNetMsg = self.rx.select_next_some().await;
-                self.process_next_message(&msg);
+        if !self.all_peers_accept_binary() {
+            if let [value] = values.as_ref() {
+                return self
+                    .send_to_all(handles, &[bs58::encode(value).into_string()])
+                    .await;
             }
 
-            // if we got here, we can assume we have the message from peer_id
-            let msg = self
-                .mailbox
-                .get(identifier)
-                .unwrap()
-                .get(&peer_id)
-                .unwrap()
-                .clone();
-            let peer_id_as_u64 = get_node_id_via_peer_id(&self.addr_book, &peer_id).unwrap();
-
-            messages.insert(peer_id_as_u64, msg);
+            // several values go out as one packed vector
+            let seq = self.take_seq();
+            let msg = EvalNetMsg::PublishBatchValue {
+                session: self.session_id.clone(),
+                sender: self.id.clone(),
+                seq,
+                attempt: 0,
+                round: self.round,
+                handles: to_strings(handles),
+                values: vec![pack_bytes(values.as_ref())],
+                codec: Codec::Plain,
+                payload: vec![],
+                signature: String::new(),
+            };
+            let recipients = self.peers();
+            return self.publish(seq, msg, recipients).await;
         }
 
-        //clear the mailbox because we might want to use identifier again
-        self.mailbox.remove(identifier);
+        let seq = self.take_seq();
+        let msg = EvalNetMsg::PublishBatchBinary {
+            session: self.session_id.clone(),
+            sender: self.id.clone(),
+            seq,
+            attempt: 0,
+            round: self.round,
+            handles: to_strings(handles),
+            payload: values.as_ref().concat(),
+            kind,
+            codec: Codec::Plain,
+            signature: String::new(),
+        };
+        let recipients = self.peers();
+        self.publish(seq, msg, recipients).await
+    }
+
+    /// sends values to the party with node id recipient_id only
+    pub async fn send_to_party<H: AsRef<str>, V: AsRef<str>>(
+        &mut self,
+        recipient_id: u64,
+        handles: &[H],
+        values: &[V],
+    ) -> Result<(), NetworkError> {
+        check_values(handles.len(), values.len())?;
 
-        messages
+        let recipient = self.peer_of(recipient_id)?;
+        self.send_direct(&recipient, handles, values, true).await
     }
 
-    //returns the handle which
-    fn process_next_message(&mut self, msg: &EvalNetMsg) {
-        match msg {
-            EvalNetMsg::PublishValue {
-                sender,
-                handle,
-                value,
-            } => {
-                self.accept_handle_and_value_from_sender(sender, handle, value);
-            }
-            EvalNetMsg::PublishBatchValue {
-                sender,
-                handles,
-                values,
-            } => {
-                assert_eq!(handles.len(), values.len());
+    /// sends values to every peer of role: the committee gets them as from
+    /// send_to_all, and each player or verifier in direct messages of its own,
+    /// which nobody waits for it to acknowledge, as it takes no part in the session
+    pub async fn send_to_role<H: AsRef<str>, V: AsRef<str>>(
+        &mut self,
+        role: PeerRole,
+        handles: &[H],
+        values: &[V],
+    ) -> Result<(), NetworkError> {
+        if role == PeerRole::Committee {
+            return self.send_to_all(handles, values).await;
+        }
+        check_values(handles.len(), values.len())?;
 
-                for (h, v) in handles.iter().zip(values.iter()) {
-                    self.accept_handle_and_value_from_sender(sender, h, v);
-                }
-            }
-            _ => (),
+        let recipients: Vec<Pok3rPeerId> = self
+            .addr_book
+            .peer_ids_with_role(role)
+            .into_iter()
+            .cloned()
+            .collect();
+        for recipient in recipients.iter() {
+            self.send_direct(recipient, handles, values, false).await?;
         }
+        Ok(())
     }
 
-    fn accept_handle_and_value_from_sender(
+    /// sends values to the player with peer id player only, e.g. its hole cards;
+    /// as with send_to_role, nobody waits for the player to acknowledge them
+    pub async fn send_to_player<H: AsRef<str>, V: AsRef<str>>(
         &mut self,
-        sender: &String,
-        handle: &String,
-        value: &String,
-    ) {
-        // if already exists, then ignore
-        if self.mailbox.contains_key(handle) {
-            let sender_exists_for_handle = self.mailbox.get(handle).unwrap().contains_key(sender);
-            if sender_exists_for_handle {
-                return;
-            } //ignore duplicate msg!
-        } else {
-            //mailbox never got a message by this handle so lets make room for it
-            self.mailbox.insert(handle.clone(), HashMap::new());
+        player: &Pok3rPeerId,
+        handles: &[H],
+        values: &[V],
+    ) -> Result<(), NetworkError> {
+        check_values(handles.len(), values.len())?;
+        if !self.addr_book.is_player(player) {
+            return Err(NetworkError::NotAPlayer(player.clone()));
         }
+        self.send_direct(player, handles, values, false).await
+    }
 
-        self.mailbox
-            .get_mut(handle)
-            .unwrap()
-            .insert(sender.clone(), value.clone());
+    /// sends each value to recipient in a direct message; with acked, the message
+    /// is kept for retransmission until recipient acknowledges it
+    async fn send_direct<H: AsRef<str>, V: AsRef<str>>(
+        &mut self,
+        recipient: &Pok3rPeerId,
+        handles: &[H],
+        values: &[V],
+        acked: bool,
+    ) -> Result<(), NetworkError> {
+        for (handle, value) in handles.iter().zip(values) {
+            let seq = self.take_seq();
+            let msg = EvalNetMsg::DirectValue {
+                session: self.session_id.clone(),
+                sender: self.id.clone(),
+                seq,
+                attempt: 0,
+                round: self.round,
+                recipient: recipient.clone(),
+                handle: handle.as_ref().to_string(),
+                value: value.as_ref().to_string(),
+                signature: String::new(),
+            };
+            let recipients = if acked {
+                HashSet::from([recipient.clone()])
+            } else {
+                HashSet::new()
+            };
+            self.publish(seq, msg, recipients).await?;
+        }
+        Ok(())
+    }
+
+    /// sends canonically serialized values of the given kind to the party with node
+    /// id recipient_id only, in one message; falls back to send_to_party if that
+    /// party does not accept the binary wire format
+    pub async fn send_batch_to_party<H: AsRef<str>>(
+        &mut self,
+        recipient_id: u64,
+        handles: &[H],
+        kind: PayloadKind,
+        values: impl AsRef<[Vec<u8>]>,
+    ) -> Result<(), NetworkError> {
+        check_values(handles.len(), values.as_ref().len())?;
+
+        self.process_pending_messages();
+
+        let recipient = self.peer_of(recipient_id)?;
+        if !(self.binary_payloads && self.binary_peers.contains(&recipient)) {
+            let values = values
+                .as_ref()
+                .iter()
+                .map(|v| bs58::encode(v).into_string())
+                .collect::<Vec<String>>();
+            return self.send_to_party(recipient_id, handles, &values).await;
+        }
+
+        let seq = self.take_seq();
+        let msg = EvalNetMsg::DirectBatchBinary {
+            session: self.session_id.clone(),
+            sender: self.id.clone(),
+            seq,
+            attempt: 0,
+            round: self.round,
+            recipient: recipient.clone(),
+            handles: to_strings(handles),
+            payload: values.as_ref().concat(),
+            kind,
+            signature: String::new(),
+        };
+        self.publish(seq, msg, HashSet::from([recipient])).await
+    }
+
+    /// waits for the value the party with node id sender_id sent only to us under handle
+    pub async fn recv_from_party(
+        &mut self,
+        sender_id: u64,
+        handle: &str,
+    ) -> Result<String, NetworkError> {
+        self.pending_recv = Some(handle.to_string());
+        let result = self.collect_from_party(sender_id, handle).await;
+        self.pending_recv = None;
+        result
+    }
+
+    async fn collect_from_party(
+        &mut self,
+        sender_id: u64,
+        handle: &str,
+    ) -> Result<String, NetworkError> {
+        self.check_aborted()?;
+        let deadline = Instant::now() + self.recv_timeout;
+        let sender = self.peer_of(sender_id)?;
+
+        loop {
+            let value = self
+                .direct_mailbox
+                .get_mut(handle)
+                .and_then(|senders| senders.remove(&sender));
+            if let Some(value) = value {
+                self.take_pending(handle, &sender, true);
+                if self
+                    .direct_mailbox
+                    .get(handle)
+                    .is_some_and(|senders| senders.is_empty())
+                {
+                    self.direct_mailbox.remove(handle);
+                }
+                self.direct_received
+                    .entry(sender)
+                    .or_default()
+                    .insert(handle.to_string());
+                self.metrics.end_round();
+                return Ok(value);
+            }
+            if self.dead.contains(&sender) {
+                return Err(self.receive_failure(handle, vec![sender_id]));
+            }
+
+            match self.next_message(deadline).await? {
+                Some(msg) => self.process_next_message(&msg),
+                None if Instant::now() < deadline => (),
+                None => return Err(self.receive_failure(handle, vec![sender_id])),
+            }
+        }
+    }
+
+    /// tells every peer that we give up on the session; from then on all our
+    /// receives fail with NetworkError::Aborted, just like theirs will
+    pub async fn broadcast_abort(&mut self, reason: &str) {
+        let msg = self.sign(EvalNetMsg::Abort {
+            session: self.session_id.clone(),
+            sender: self.id.clone(),
+            reason: reason.to_string(),
+            signature: String::new(),
+        });
+        if self.aborted.is_none() {
+            self.aborted = Some((self.get_my_id(), reason.to_string()));
+        }
+
+        self.metrics.record_sent(&msg);
+        self.send(msg).await;
+    }
+
+    /// fails once anyone has aborted the session, or a peer turned out to
+    /// encode values differently from us
+    fn check_aborted(&self) -> Result<(), NetworkError> {
+        self.check_journal()?;
+        if let Some((peer, got)) = &self.mismatched_peer {
+            return Err(NetworkError::EncodingMismatch {
+                peer: peer.clone(),
+                expected: encoding_tag(),
+                got: *got,
+            });
+        }
+        match &self.aborted {
+            Some((by, reason)) => Err(NetworkError::Aborted {
+                by: *by,
+                reason: reason.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// sets how unacknowledged messages are retransmitted
+    pub fn set_retransmit_config(&mut self, config: RetransmitConfig) {
+        self.retransmit = config;
+    }
+
+    /// sets how much is kept for retransmission and for each peer
+    pub fn set_queue_config(&mut self, config: QueueConfig) {
+        self.queues = config;
+    }
+
+    /// turns on the compression of large batch payloads; it takes effect with the
+    /// handshake, which lets the peers know that we take compressed payloads too
+    pub fn set_compression_config(&mut self, config: CompressionConfig) {
+        self.compression = Some(config);
+    }
+
+    /// turns on heartbeats: from now on we send one to every peer each interval,
+    /// and a peer we have not heard from for miss_threshold intervals is dead, which
+    /// fails the receives that wait for it with PeerDown. Every party of the session
+    /// must turn them on, or the others find it dead.
+    pub fn set_heartbeat_config(&mut self, config: HeartbeatConfig) {
+        if let Some((_, stop)) = self.heartbeats.take() {
+            stop.store(true, Ordering::Relaxed);
+        }
+        let stop = Arc::new(AtomicBool::new(false));
+        let heartbeat = EvalNetMsg::Heartbeat {
+            session: self.session_id.clone(),
+            sender: self.id.clone(),
+            recipient: None,
+            sent_at: 0,
+            signature: String::new(),
+        };
+        async_std::task::spawn(send_heartbeats(
+            self.tx.clone(),
+            self.keypair.clone(),
+            heartbeat,
+            self.started,
+            config.interval,
+            self.metrics.clone(),
+            stop.clone(),
+        ));
+        self.heartbeats = Some((config, stop));
+
+        // peers get miss_threshold intervals from now to show up
+        let now = Instant::now();
+        for peer in self.peers() {
+            self.liveness.entry(peer).or_insert(PeerLiveness {
+                last_heard: now,
+                rtt: None,
+            });
+        }
+    }
+
+    /// keeps a journal at path of what we send until it is acknowledged, and of
+    /// what we receive until a receive takes it. If path holds the journal of an
+    /// earlier run of ours in this session, e.g. before a crash, its messages and
+    /// values are reloaded first, and what is still unacknowledged is sent again
+    /// at once. Call this before sending anything.
+    pub fn open_journal(&mut self, path: &Path) -> Result<(), JournalError> {
+        let (journal, records) = Journal::open(path)?;
+        let peers = self.peers();
+        let now = Instant::now();
+        for (i, record) in records.into_iter().enumerate() {
+            match record {
+                JournalRecord::Session { session, next_seq } => {
+                    if session != self.session_id {
+                        return Err(JournalError::WrongSession {
+                            expected: self.session_id.clone(),
+                            found: session,
+                        });
+                    }
+                    self.next_seq = self.next_seq.max(next_seq);
+                }
+                _ if i == 0 => return Err(JournalError::Malformed(1)),
+                JournalRecord::Sent {
+                    seq,
+                    recipients,
+                    msg,
+                } => {
+                    let msg = bs58::decode(&msg)
+                        .into_vec()
+                        .ok()
+                        .and_then(|data| decode_wire_msg(&data))
+                        .ok_or(JournalError::Malformed(i + 1))?;
+                    let awaiting: HashSet<Pok3rPeerId> = recipients
+                        .into_iter()
+                        .filter(|peer| peers.contains(peer))
+                        .collect();
+                    self.next_seq = self.next_seq.max(seq + 1);
+                    if !awaiting.is_empty() {
+                        self.unacked.insert(
+                            seq,
+                            PendingMessage {
+                                msg,
+                                awaiting,
+                                attempts: 0,
+                                backoff: self.retransmit.initial_backoff,
+                                next_retry: now,
+                            },
+                        );
+                    }
+                }
+                JournalRecord::Acked { seq, peer } => {
+                    self.settle(seq, &peer);
+                }
+                JournalRecord::Received {
+                    handle,
+                    sender,
+                    value,
+                    direct,
+                } => {
+                    let mailbox = match direct {
+                        true => &mut self.direct_mailbox,
+                        false => &mut self.mailbox,
+                    };
+                    if peers.contains(&sender) {
+                        if let Entry::Vacant(entry) =
+                            mailbox.entry(handle).or_default().entry(sender.clone())
+                        {
+                            entry.insert(value);
+                            self.add_pending(&sender);
+                        }
+                    }
+                }
+                JournalRecord::Taken {
+                    handle,
+                    sender,
+                    direct,
+                } => {
+                    let mailbox = match direct {
+                        true => &mut self.direct_mailbox,
+                        false => &mut self.mailbox,
+                    };
+                    let senders = match mailbox.get_mut(&handle) {
+                        Some(senders) => senders,
+                        None => continue,
+                    };
+                    if senders.remove(&sender).is_some() {
+                        if senders.is_empty() {
+                            mailbox.remove(&handle);
+                        }
+                        self.take_pending(&handle, &sender, direct);
+                    }
+                }
+            }
+        }
+
+        self.journal = Some(journal);
+        self.compact_journal()?;
+        Ok(())
+    }
+
+    /// appends the record to the journal, if we keep one; once that fails, every
+    /// receive fails with NetworkError::Journal
+    fn journal(&mut self, record: impl FnOnce() -> JournalRecord) {
+        if let Some(journal) = self.journal.as_mut() {
+            if let Err(err) = journal.append(&record()) {
+                self.journal_error.get_or_insert(err.to_string());
+            }
+        }
+    }
+
+    fn check_journal(&self) -> Result<(), NetworkError> {
+        match &self.journal_error {
+            Some(err) => Err(NetworkError::Journal(err.clone())),
+            None => Ok(()),
+        }
+    }
+
+    /// rewrites the journal with what is still live: our unacknowledged messages
+    /// and the values no receive has taken
+    fn compact_journal(&mut self) -> io::Result<()> {
+        if self.journal.is_none() {
+            return Ok(());
+        }
+        let mut records = vec![JournalRecord::Session {
+            session: self.session_id.clone(),
+            next_seq: self.next_seq,
+        }];
+        for (seq, pending) in self.unacked.iter() {
+            let mut recipients: Vec<Pok3rPeerId> = pending.awaiting.iter().cloned().collect();
+            recipients.sort();
+            records.push(JournalRecord::Sent {
+                seq: *seq,
+                recipients,
+                msg: bs58::encode(
+                    encode_wire_msg(&pending.msg)
+                        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?,
+                )
+                .into_string(),
+            });
+        }
+        for (direct, mailbox) in [(false, &self.mailbox), (true, &self.direct_mailbox)] {
+            for (handle, senders) in mailbox.iter() {
+                for (sender, value) in senders.iter() {
+                    records.push(JournalRecord::Received {
+                        handle: handle.clone(),
+                        sender: sender.clone(),
+                        value: value.clone(),
+                        direct,
+                    });
+                }
+            }
+        }
+        self.journal.as_mut().unwrap().rewrite(&records)
+    }
+
+    /// compacts the journal once most of its records are no longer live, which
+    /// keeps it bounded
+    fn collect_journal(&mut self) {
+        let compact = self.journal.as_ref().is_some_and(|journal| {
+            let live = 1 + self.unacked.len() + self.pending.values().sum::<usize>();
+            journal.records() > 2 * live + JOURNAL_SLACK
+        });
+        if compact {
+            if let Err(err) = self.compact_journal() {
+                self.journal_error.get_or_insert(err.to_string());
+            }
+        }
+    }
+
+    /// a fresh MessagingSystem of the same party over the same links, as if our
+    /// process had crashed and been started again; it holds nothing but what it
+    /// reloads from a journal
+    pub(crate) async fn restart(mut self) -> Result<MessagingSystem, NetworkError> {
+        let rx = std::mem::replace(&mut self.rx, Box::pin(stream::empty()));
+        // the networkd of the new process connects again
+        let connected = EvalNetMsg::ConnectionEstablished {
+            success: true,
+            sender: None,
+            binary_payloads: false,
+            session: String::new(),
+            encoding_tag: 0,
+            info: None,
+            waiting: false,
+            signature: String::new(),
+        };
+        let rx = stream::once(future::ready(connected)).chain(rx);
+        Self::new_with_inbox(
+            self.keypair.clone(),
+            &self.session_id,
+            self.addr_book.clone(),
+            self.tx.clone(),
+            Box::pin(rx),
+            self.binary_payloads,
+        )
+        .await
+    }
+
+    /// what the heartbeats say about every peer, by node id, once what has
+    /// arrived is processed; empty while heartbeats are off
+    pub fn peer_status(&mut self) -> HashMap<u64, PeerStatus> {
+        self.process_pending_messages();
+        self.watch_peers();
+        let interval = match &self.heartbeats {
+            Some((config, _)) => config.interval,
+            None => return HashMap::new(),
+        };
+        self.liveness
+            .iter()
+            .filter_map(|(peer, liveness)| {
+                let status = if self.dead.contains(peer) {
+                    PeerStatus::Dead
+                } else if liveness.last_heard.elapsed() >= 2 * interval {
+                    PeerStatus::Suspect
+                } else {
+                    PeerStatus::Alive { rtt: liveness.rtt }
+                };
+                Some((self.addr_book.node_of(peer)?, status))
+            })
+            .collect()
+    }
+
+    /// a stream of the node ids of peers as they are found dead, starting with
+    /// those found already; peers are only found dead while we wait for messages
+    pub fn on_peer_down(&mut self) -> mpsc::UnboundedReceiver<u64> {
+        let (tx, rx) = mpsc::unbounded();
+        for node_id in self.dead_nodes() {
+            let _ = tx.unbounded_send(node_id);
+        }
+        self.down_subscribers.push(tx);
+        rx
+    }
+
+    /// node ids of the dead peers, in ascending order
+    fn dead_nodes(&self) -> Vec<u64> {
+        let mut node_ids: Vec<u64> = self
+            .dead
+            .iter()
+            .filter_map(|peer| self.addr_book.node_of(peer))
+            .collect();
+        node_ids.sort_unstable();
+        node_ids
+    }
+
+    /// notes that sender is still there, with the round trip time of its answer
+    /// to our heartbeat, if this is one
+    fn hear_from(&mut self, sender: &str, rtt: Option<Duration>) {
+        if let Some(liveness) = self.liveness.get_mut(sender) {
+            liveness.last_heard = Instant::now();
+            if rtt.is_some() {
+                liveness.rtt = rtt;
+            }
+        }
+    }
+
+    /// when the first of the living peers dies unless we hear from it before
+    fn next_death(&self) -> Option<Instant> {
+        let (config, _) = self.heartbeats.as_ref()?;
+        self.liveness
+            .iter()
+            .filter(|(peer, _)| !self.dead.contains(*peer))
+            .map(|(_, liveness)| liveness.last_heard + config.interval * config.miss_threshold)
+            .min()
+    }
+
+    /// marks the peers we have not heard from for miss_threshold intervals dead and
+    /// tells the subscribers of on_peer_down; returns whether any died. Only sound
+    /// once what has arrived is processed.
+    fn watch_peers(&mut self) -> bool {
+        match self.next_death() {
+            Some(death) if death <= Instant::now() => (),
+            _ => return false,
+        }
+        let (config, _) = self.heartbeats.as_ref().unwrap();
+        let silence = config.interval * config.miss_threshold;
+        let died: Vec<Pok3rPeerId> = self
+            .liveness
+            .iter()
+            .filter(|(peer, liveness)| {
+                !self.dead.contains(*peer) && liveness.last_heard.elapsed() >= silence
+            })
+            .map(|(peer, _)| peer.clone())
+            .collect();
+        let mut node_ids: Vec<u64> = died
+            .iter()
+            .filter_map(|peer| self.addr_book.node_of(peer))
+            .collect();
+        node_ids.sort_unstable();
+        for node_id in node_ids {
+            #[cfg(feature = "print")]
+            println!("party {} stopped sending heartbeats", node_id);
+            self.down_subscribers
+                .retain(|subscriber| subscriber.unbounded_send(node_id).is_ok());
+        }
+        self.dead.extend(died);
+        true
+    }
+
+    /// fails with the receive_failure of identifier once count values can no
+    /// longer come in for it, since too many of the missing senders are dead
+    fn check_senders_alive(&self, identifier: &str, count: usize) -> Result<(), NetworkError> {
+        if self.dead.is_empty() {
+            return Ok(());
+        }
+        let received = self
+            .mailbox
+            .get(identifier)
+            .map_or(0, |senders| senders.len());
+        let missing_senders = self.missing_senders(identifier);
+        let alive = missing_senders
+            .iter()
+            .filter(|node_id| !self.is_dead(**node_id))
+            .count();
+        if received + alive < count {
+            return Err(self.receive_failure(identifier, missing_senders));
+        }
+        Ok(())
+    }
+
+    fn is_dead(&self, node_id: u64) -> bool {
+        self.addr_book
+            .peer_of(node_id)
+            .is_some_and(|peer| self.dead.contains(peer))
+    }
+
+    /// keeps processing incoming messages and retransmitting until everything we
+    /// sent is acknowledged. Then it lingers until the peers have been quiet for
+    /// twice the maximum backoff, so that peers whose acks from us got lost can
+    /// still retransmit and receive them. Call this before going away.
+    pub async fn flush(&mut self) -> Result<(), NetworkError> {
+        while !self.unacked.is_empty() {
+            let deadline = Instant::now() + self.retransmit.max_backoff;
+            if let Some(msg) = self.next_message(deadline).await? {
+                self.process_next_message(&msg);
+            }
+        }
+
+        loop {
+            let deadline = Instant::now() + 2 * self.retransmit.max_backoff;
+            match self.next_message(deadline).await? {
+                Some(msg) => self.process_next_message(&msg),
+                None => return Ok(()),
+            }
+        }
+    }
+
+    /// waits until deadline for the next message from the networkd, retransmitting
+    /// whatever falls due in the meantime; returns None once the deadline has passed
+    /// or a peer has been found dead, and fails as soon as the session has been aborted
+    async fn next_message(
+        &mut self,
+        deadline: Instant,
+    ) -> Result<Option<EvalNetMsg>, NetworkError> {
+        loop {
+            self.check_aborted()?;
+            let mut wake_up = match self.unacked.values().map(|p| p.next_retry).min() {
+                Some(next_retry) => next_retry.min(deadline),
+                None => deadline,
+            };
+            if let Some(death) = self.next_death() {
+                wake_up = wake_up.min(death);
+            }
+            let remaining = wake_up.saturating_duration_since(Instant::now());
+            match async_std::future::timeout(remaining, self.rx.next()).await {
+                Ok(Some(msg)) => return Ok(Some(msg)),
+                // every sender is gone, e.g. peers that finished before us, so
+                // nothing more arrives until the retries are due
+                Ok(None) => async_std::task::sleep(remaining).await,
+                Err(_) => (),
+            }
+            // nothing is left in the inbox, so what we heard from the peers is current
+            if self.watch_peers() {
+                return Ok(None);
+            }
+            self.retransmit_due().await?;
+            if Instant::now() >= deadline {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// sends again every message whose retry time has come, doubling its backoff
+    async fn retransmit_due(&mut self) -> Result<(), NetworkError> {
+        let now = Instant::now();
+        let due: Vec<u64> = self
+            .unacked
+            .iter()
+            .filter(|(_, pending)| pending.next_retry <= now)
+            .map(|(seq, _)| *seq)
+            .collect();
+
+        for seq in due {
+            let pending = self.unacked.get_mut(&seq).unwrap();
+            if pending.attempts >= self.retransmit.max_attempts {
+                let mut missing_recipients: Vec<u64> = pending
+                    .awaiting
+                    .iter()
+                    .filter_map(|peer_id| self.addr_book.node_of(peer_id))
+                    .collect();
+                missing_recipients.sort_unstable();
+                self.unacked.remove(&seq);
+                return Err(NetworkError::DeliveryFailed {
+                    seq,
+                    missing_recipients,
+                });
+            }
+
+            pending.attempts += 1;
+            pending.backoff = (pending.backoff * 2).min(self.retransmit.max_backoff);
+            pending.next_retry = now + pending.backoff;
+            // a fresh attempt number keeps gossipsub from discarding it as a duplicate
+            match &mut pending.msg {
+                EvalNetMsg::PublishValue { attempt, .. }
+                | EvalNetMsg::PublishBatchValue { attempt, .. }
+                | EvalNetMsg::PublishBatchBinary { attempt, .. }
+                | EvalNetMsg::DirectValue { attempt, .. }
+                | EvalNetMsg::DirectBatchBinary { attempt, .. } => *attempt = pending.attempts,
+                _ => (),
+            }
+            let msg = pending.msg.clone();
+
+            #[cfg(feature = "print")]
+            println!(
+                "retransmitting message {} (attempt {})",
+                seq, pending.attempts
+            );
+            self.metrics.record_sent(&msg);
+            self.send(msg).await;
+        }
+        Ok(())
+    }
+
+    /// every peer in the addr book except us
+    fn peers(&self) -> HashSet<Pok3rPeerId> {
+        self.addr_book
+            .peer_ids()
+            .filter(|peer_id| !self.id.eq(*peer_id))
+            .cloned()
+            .collect()
+    }
+
+    /// the peer id of the party with node id node_id
+    fn peer_of(&self, node_id: u64) -> Result<Pok3rPeerId, NetworkError> {
+        self.addr_book
+            .peer_of(node_id)
+            .cloned()
+            .ok_or(NetworkError::UnknownParty(node_id))
+    }
+
+    fn take_seq(&mut self) -> u64 {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        seq
+    }
+
+    /// signs and sends msg, keeping it for retransmission until every recipient
+    /// acknowledged it; with max_unacked messages kept already, it first waits
+    /// for acks, which fails like a receive if the session is aborted or a
+    /// message cannot be delivered
+    async fn publish(
+        &mut self,
+        seq: u64,
+        msg: EvalNetMsg,
+        recipients: HashSet<Pok3rPeerId>,
+    ) -> Result<(), NetworkError> {
+        while self.unacked.len() >= self.queues.max_unacked {
+            let deadline = Instant::now() + self.retransmit.max_backoff;
+            if let Some(msg) = self.next_message(deadline).await? {
+                self.process_next_message(&msg);
+            }
+        }
+
+        let msg = self.sign(self.compress(msg));
+        if !recipients.is_empty() {
+            // journaled before it goes out, so that a restart sends it again
+            if self.journal.is_some() {
+                let data = encode_wire_msg(&msg)?;
+                self.journal(|| {
+                    let mut recipients: Vec<Pok3rPeerId> = recipients.iter().cloned().collect();
+                    recipients.sort();
+                    JournalRecord::Sent {
+                        seq,
+                        recipients,
+                        msg: bs58::encode(data).into_string(),
+                    }
+                });
+            }
+            self.check_journal()?;
+            self.unacked.insert(
+                seq,
+                PendingMessage {
+                    msg: msg.clone(),
+                    awaiting: recipients,
+                    attempts: 0,
+                    backoff: self.retransmit.initial_backoff,
+                    next_retry: Instant::now() + self.retransmit.initial_backoff,
+                },
+            );
+        }
+
+        self.metrics.start_round();
+        self.metrics.record_sent(&msg);
+        self.send(msg).await;
+        Ok(())
+    }
+
+    /// hands msg to the networkd, waiting for room while its queue is full
+    async fn send(&mut self, msg: EvalNetMsg) {
+        let msg = match self.tx.try_send(msg) {
+            Ok(()) => return,
+            Err(err) if err.is_full() => err.into_inner(),
+            Err(err) => {
+                eprint!("evaluator error {:?}", err);
+                return;
+            }
+        };
+        self.metrics.record_send_blocked();
+        if let Err(err) = self.tx.send(msg).await {
+            eprint!("evaluator error {:?}", err);
+        }
+    }
+
+    /// hands msg to the networkd unless its queue is full; for announcements and
+    /// acks, which are sent again when the peer retransmits
+    fn try_send(&mut self, msg: EvalNetMsg) {
+        match self.tx.try_send(msg) {
+            Ok(()) => (),
+            Err(err) if err.is_full() => self.metrics.record_send_blocked(),
+            Err(err) => eprint!("evaluator error {:?}", err),
+        }
+    }
+
+    /// what we tell the peers about ourselves, signed; with waiting, they answer
+    /// with theirs
+    fn announcement(&self, waiting: bool) -> EvalNetMsg {
+        self.sign(EvalNetMsg::ConnectionEstablished {
+            success: true,
+            sender: Some(self.id.clone()),
+            binary_payloads: self.binary_payloads,
+            session: self.session_id.clone(),
+            encoding_tag: encoding_tag(),
+            info: self.info.clone(),
+            waiting,
+            signature: String::new(),
+        })
+    }
+
+    fn announce(&mut self, waiting: bool) {
+        let announcement = self.announcement(waiting);
+        self.metrics.record_sent(&announcement);
+        self.try_send(announcement);
+    }
+
+    /// exchanges PeerInfo with every peer of the addr book, announcing ours (plus
+    /// binary payloads, if we take them) until all of them have answered. Fails
+    /// with IncompatiblePeer on the first peer, by node id, that cannot play with
+    /// us, and with a Timeout naming those that did not answer in time.
+    pub async fn handshake(&mut self, info: &PeerInfo) -> Result<(), NetworkError> {
+        let mut info = info.clone();
+        if self.binary_payloads {
+            info = info.with_feature(FEATURE_BINARY_PAYLOADS);
+        }
+        if self.compression.is_some() {
+            info = info.with_feature(FEATURE_ZSTD);
+        }
+        self.info = Some(info);
+
+        let deadline = Instant::now() + self.recv_timeout;
+        let mut backoff = self.retransmit.initial_backoff;
+        loop {
+            self.announce(true);
+            let next_announcement = (Instant::now() + backoff).min(deadline);
+            loop {
+                if self.check_peer_infos()? {
+                    return Ok(());
+                }
+                match self.next_message(next_announcement).await? {
+                    Some(msg) => self.process_next_message(&msg),
+                    None => break,
+                }
+            }
+            if Instant::now() >= deadline {
+                let missing_senders = self
+                    .addr_book
+                    .peer_ids()
+                    .filter(|peer| !self.id.eq(*peer) && !self.peer_infos.contains_key(*peer))
+                    .filter_map(|peer| self.addr_book.node_of(peer))
+                    .collect();
+                return Err(NetworkError::Timeout {
+                    handle: String::from("handshake"),
+                    missing_senders,
+                });
+            }
+            backoff = (backoff * 2).min(self.retransmit.max_backoff);
+        }
+    }
+
+    /// whether every peer has announced its PeerInfo; fails on the first one that
+    /// is not compatible with ours
+    fn check_peer_infos(&self) -> Result<bool, NetworkError> {
+        let ours = self.info.as_ref().expect("handshake sets our info first");
+        let mut complete = true;
+        for peer in self.addr_book.peer_ids().filter(|peer| !self.id.eq(*peer)) {
+            match self.peer_infos.get(peer) {
+                Some(theirs) => {
+                    if let Some((field, ours, theirs)) = ours.mismatch(theirs) {
+                        return Err(NetworkError::IncompatiblePeer {
+                            peer: peer.clone(),
+                            field: field.to_string(),
+                            ours,
+                            theirs,
+                        });
+                    }
+                }
+                None => complete = false,
+            }
+        }
+        Ok(complete)
+    }
+
+    /// what peer announced in the handshake, if it has
+    pub fn peer_info(&self, peer: &Pok3rPeerId) -> Option<&PeerInfo> {
+        self.peer_infos.get(peer)
+    }
+
+    /// tells sender that we got its message seq
+    fn acknowledge(&mut self, sender: &Pok3rPeerId, seq: u64) {
+        let ack = self.sign(EvalNetMsg::Ack {
+            session: self.session_id.clone(),
+            sender: self.id.clone(),
+            recipient: sender.clone(),
+            seq,
+            signature: String::new(),
+        });
+        self.metrics.record_sent(&ack);
+        self.try_send(ack);
+    }
+
+    /// signs bytes with our identity key
+    pub fn sign_bytes(&self, bytes: &[u8]) -> Vec<u8> {
+        self.keypair
+            .sign(bytes)
+            .expect("ed25519 signing does not fail")
+    }
+
+    /// fills in our signature on a value-carrying message, an ack, an abort, a
+    /// heartbeat or an announcement
+    fn sign(&self, msg: EvalNetMsg) -> EvalNetMsg {
+        sign_msg(&self.keypair, msg)
+    }
+
+    /// explains why the given senders did not deliver in time; a forged
+    /// message in the name of one of them takes precedence over a timeout, and
+    /// so do a dead sender and one whose messages no longer fit in our mailboxes
+    fn receive_failure(&self, handle: &str, missing_senders: Vec<u64>) -> NetworkError {
+        for node_id in missing_senders.iter() {
+            if let Some(peer_id) = self.addr_book.peer_of(*node_id) {
+                if self.forged_senders.contains(peer_id) {
+                    return NetworkError::BadSignature(peer_id.clone());
+                }
+                if self.dead.contains(peer_id) {
+                    return NetworkError::PeerDown(*node_id);
+                }
+                if self.overflowing.contains(peer_id) {
+                    return NetworkError::QueueOverflow {
+                        peer: peer_id.clone(),
+                        pending: self.pending.get(peer_id).copied().unwrap_or(0),
+                        limit: self.queues.max_pending_per_peer,
+                    };
+                }
+            }
+        }
+        NetworkError::Timeout {
+            handle: handle.to_string(),
+            missing_senders,
+        }
+    }
+
+    /// processes whatever the networkd has already delivered, without blocking
+    fn process_pending_messages(&mut self) {
+        while let Some(Some(msg)) = self.rx.next().now_or_never() {
+            self.process_next_message(&msg);
+        }
+    }
+
+    /// compresses the payload of a batch broadcast if it is large, every peer takes
+    /// zstd, and it gets smaller
+    fn compress(&self, mut msg: EvalNetMsg) -> EvalNetMsg {
+        let config = match self.compression {
+            Some(config) if self.all_peers_accept_zstd() => config,
+            _ => return msg,
+        };
+        match &mut msg {
+            EvalNetMsg::PublishBatchValue {
+                values,
+                codec,
+                payload,
+                ..
+            } if codec.is_plain() => {
+                let packed = pack_strings(values);
+                if packed.len() >= config.threshold {
+                    let compressed = compression::compress(&packed, config.level);
+                    if compressed.len() < packed.len() {
+                        values.clear();
+                        *codec = Codec::Zstd;
+                        *payload = compressed;
+                    }
+                }
+            }
+            EvalNetMsg::PublishBatchBinary { payload, codec, .. }
+                if codec.is_plain() && payload.len() >= config.threshold =>
+            {
+                let compressed = compression::compress(payload, config.level);
+                if compressed.len() < payload.len() {
+                    *codec = Codec::Zstd;
+                    *payload = compressed;
+                }
+            }
+            _ => (),
+        }
+        msg
+    }
+
+    fn all_peers_accept_zstd(&self) -> bool {
+        self.addr_book.peer_ids().all(|peer_id| {
+            peer_id.eq(&self.id)
+                || self
+                    .peer_infos
+                    .get(peer_id)
+                    .is_some_and(|info| info.has_feature(FEATURE_ZSTD))
+        })
+    }
+
+    fn all_peers_accept_binary(&self) -> bool {
+        self.binary_payloads
+            && self
+                .addr_book
+                .peer_ids()
+                .all(|peer_id| peer_id.eq(&self.id) || self.binary_peers.contains(peer_id))
+    }
+
+    /// switches to a new committee between hands, e.g. once a peer has left for good;
+    /// whatever we hold for departed peers is dropped, and nothing is sent to them anymore
+    pub fn update_addr_book(&mut self, addr_book: Pok3rAddrBook) -> Result<(), NetworkError> {
+        if let Some(handle) = &self.pending_recv {
+            return Err(NetworkError::ReceivePending(handle.clone()));
+        }
+        if !addr_book.contains(&self.id) {
+            return Err(NetworkError::NotInAddrBook);
+        }
+        let peer_keys = peer_keys_of(&addr_book)?;
+
+        for senders in self
+            .mailbox
+            .values_mut()
+            .chain(self.direct_mailbox.values_mut())
+        {
+            senders.retain(|peer_id, _| addr_book.contains(peer_id));
+        }
+        self.mailbox.retain(|_, senders| !senders.is_empty());
+        self.direct_mailbox.retain(|_, senders| !senders.is_empty());
+        for pending in self.unacked.values_mut() {
+            pending
+                .awaiting
+                .retain(|peer_id| addr_book.contains(peer_id));
+        }
+        self.unacked
+            .retain(|_, pending| !pending.awaiting.is_empty());
+        self.binary_peers
+            .retain(|peer_id| addr_book.contains(peer_id));
+        self.forged_senders
+            .retain(|peer_id| addr_book.contains(peer_id));
+        self.delivered
+            .retain(|peer_id, _| addr_book.contains(peer_id));
+        self.direct_received
+            .retain(|peer_id, _| addr_book.contains(peer_id));
+        self.peer_infos
+            .retain(|peer_id, _| addr_book.contains(peer_id));
+        self.pending
+            .retain(|peer_id, _| addr_book.contains(peer_id));
+        self.overflowing
+            .retain(|peer_id| addr_book.contains(peer_id));
+        self.liveness
+            .retain(|peer_id, _| addr_book.contains(peer_id));
+        // a fresh committee starts with everyone alive
+        self.dead.clear();
+        if self.heartbeats.is_some() {
+            let now = Instant::now();
+            for peer_id in addr_book.peer_ids().filter(|peer_id| !self.id.eq(*peer_id)) {
+                self.liveness.insert(
+                    peer_id.clone(),
+                    PeerLiveness {
+                        last_heard: now,
+                        rtt: None,
+                    },
+                );
+            }
+        }
+
+        self.peer_keys = peer_keys;
+        self.addr_book = addr_book;
+        if let Err(err) = self.compact_journal() {
+            self.journal_error.get_or_insert(err.to_string());
+        }
+        Ok(())
+    }
+
+    /// sets how long recv_from_all waits for the other parties before giving up
+    pub fn set_recv_timeout(&mut self, timeout: Duration) {
+        self.recv_timeout = timeout;
+    }
+
+    pub fn round(&self) -> u64 {
+        self.round
+    }
+
+    /// moves on to round, which what we send from now on carries; values of earlier
+    /// rounds are dropped from then on. Rounds never go back, so a smaller one is ignored.
+    pub fn set_round(&mut self, round: u64) {
+        if round > self.round {
+            self.round = round;
+            self.received.clear();
+            self.direct_received.clear();
+        }
+    }
+
+    /// sets how many rounds ahead of ours values are kept until we get there
+    pub fn set_round_horizon(&mut self, horizon: u64) {
+        self.round_horizon = horizon;
+        self.metrics.set_round_horizon(horizon);
+    }
+
+    pub async fn recv_from_all(
+        &mut self,
+        identifier: &str,
+    ) -> Result<HashMap<u64, String>, NetworkError> {
+        let deadline = Instant::now() + self.recv_timeout;
+        self.recv_from_all_with_deadline(identifier, deadline).await
+    }
+
+    /// same as recv_from_all, but gives up at the given deadline
+    /// instead of after the configured timeout
+    pub async fn recv_from_all_with_deadline(
+        &mut self,
+        identifier: &str,
+        deadline: Instant,
+    ) -> Result<HashMap<u64, String>, NetworkError> {
+        self.pending_recv = Some(identifier.to_string());
+        let result = self.collect_from_all(identifier, deadline).await;
+        self.pending_recv = None;
+        result
+    }
+
+    async fn collect_from_all(
+        &mut self,
+        identifier: &str,
+        deadline: Instant,
+    ) -> Result<HashMap<u64, String>, NetworkError> {
+        self.check_aborted()?;
+        let mut messages: HashMap<u64, String> = HashMap::new();
+        let peers: Vec<Pok3rPeerId> = self.addr_book.peer_ids().cloned().collect();
+        for peer_id in peers {
+            if self.id.eq(&peer_id) {
+                continue;
+            } // ignore self
+
+            loop {
+                //loop over all incoming messages till we find msg from peer
+                let sender_exists_for_handle = self
+                    .mailbox
+                    .get(identifier)
+                    .is_some_and(|senders| senders.contains_key(&peer_id));
+                //if we already have it, break out!
+                if sender_exists_for_handle {
+                    break;
+                }
+                if self.dead.contains(&peer_id) {
+                    let missing_senders = self.missing_senders(identifier);
+                    return Err(self.receive_failure(identifier, missing_senders));
+                }
+
+                match self.next_message(deadline).await? {
+                    Some(msg) => self.process_next_message(&msg),
+                    None if Instant::now() < deadline => (),
+                    None => {
+                        let missing_senders = self.missing_senders(identifier);
+                        return Err(self.receive_failure(identifier, missing_senders));
+                    }
+                }
+            }
+
+            // if we got here, we can assume we have the message from peer_id
+            let msg = self
+                .mailbox
+                .get(identifier)
+                .and_then(|senders| senders.get(&peer_id))
+                .cloned()
+                .unwrap_or_default();
+            let peer_id_as_u64 = self.node_of(&peer_id)?;
+
+            messages.insert(peer_id_as_u64, msg);
+        }
+
+        //clear the mailbox because we might want to use identifier again, in a later round
+        if let Some((identifier, senders)) = self.mailbox.remove_entry(identifier) {
+            for sender in senders.keys() {
+                self.take_pending(&identifier, sender, false);
+            }
+            self.received.insert(identifier);
+        }
+
+        self.metrics.end_round();
+        Ok(messages)
+    }
+
+    /// same as recv_from_all for every one of identifiers, but waits on the networkd
+    /// once for all of them, and returns the values of each identifier by node id
+    pub async fn recv_many_from_all<I: AsRef<str>>(
+        &mut self,
+        identifiers: &[I],
+    ) -> Result<HashMap<String, HashMap<u64, String>>, NetworkError> {
+        let deadline = Instant::now() + self.recv_timeout;
+        self.pending_recv = identifiers.first().map(|id| id.as_ref().to_string());
+        let result = self.collect_many_from_all(identifiers, deadline).await;
+        self.pending_recv = None;
+        result
+    }
+
+    async fn collect_many_from_all<I: AsRef<str>>(
+        &mut self,
+        identifiers: &[I],
+        deadline: Instant,
+    ) -> Result<HashMap<String, HashMap<u64, String>>, NetworkError> {
+        self.check_aborted()?;
+        let peers = self.peers();
+        for identifier in identifiers.iter().map(AsRef::as_ref) {
+            while !self
+                .mailbox
+                .get(identifier)
+                .is_some_and(|senders| peers.iter().all(|peer_id| senders.contains_key(peer_id)))
+            {
+                self.check_senders_alive(identifier, peers.len())?;
+                match self.next_message(deadline).await? {
+                    Some(msg) => self.process_next_message(&msg),
+                    None if Instant::now() < deadline => (),
+                    None => {
+                        let missing_senders = self.missing_senders(identifier);
+                        return Err(self.receive_failure(identifier, missing_senders));
+                    }
+                }
+            }
+        }
+
+        // the mailbox keys move into the result, rather than being copied
+        let mut messages = HashMap::with_capacity(identifiers.len());
+        for identifier in identifiers.iter().map(AsRef::as_ref) {
+            let (identifier, senders) = self
+                .mailbox
+                .remove_entry(identifier)
+                .unwrap_or_else(|| (identifier.to_string(), HashMap::new()));
+            for sender in senders.keys() {
+                self.take_pending(&identifier, sender, false);
+            }
+            self.received.insert(identifier.to_string());
+            let values = senders
+                .into_iter()
+                .filter(|(peer_id, _)| peers.contains(peer_id))
+                .map(|(peer_id, value)| Ok((self.node_of(&peer_id)?, value)))
+                .collect::<Result<_, NetworkError>>()?;
+            messages.insert(identifier, values);
+        }
+
+        self.metrics.end_round();
+        Ok(messages)
+    }
+
+    /// same as recv_from_all, but returns as soon as count of the other parties have
+    /// sent their value for identifier; the values that arrive after that are dropped
+    pub async fn recv_from_some(
+        &mut self,
+        identifier: &str,
+        count: usize,
+    ) -> Result<HashMap<u64, String>, NetworkError> {
+        let deadline = Instant::now() + self.recv_timeout;
+        self.pending_recv = Some(identifier.to_string());
+        let result = self.collect_from_some(identifier, count, deadline).await;
+        self.pending_recv = None;
+        result
+    }
+
+    /// same as recv_from_some, but returns once quorum of the other parties have sent
+    /// their value for identifier, and keeps the values that arrive after that for
+    /// take_late_values
+    pub async fn recv_quorum(
+        &mut self,
+        identifier: &str,
+        quorum: usize,
+    ) -> Result<HashMap<u64, String>, NetworkError> {
+        let result = self.recv_from_some(identifier, quorum).await;
+        if self.late_senders.contains_key(identifier) {
+            self.quorum_handles.insert(identifier.to_string());
+        }
+        result
+    }
+
+    /// the values that came after recv_quorum returned, as (handle, node id, value),
+    /// since the last call
+    pub fn take_late_values(&mut self) -> Vec<(String, u64, String)> {
+        std::mem::take(&mut self.late_values)
+    }
+
+    /// processes incoming messages until every value recv_quorum returned without
+    /// has come, or until deadline
+    pub async fn wait_for_late_values(&mut self, deadline: Instant) -> Result<(), NetworkError> {
+        while !self.quorum_handles.is_empty() {
+            match self.next_message(deadline).await? {
+                Some(msg) => self.process_next_message(&msg),
+                None => break,
+            }
+        }
+        Ok(())
+    }
+
+    async fn collect_from_some(
+        &mut self,
+        identifier: &str,
+        count: usize,
+        deadline: Instant,
+    ) -> Result<HashMap<u64, String>, NetworkError> {
+        self.check_aborted()?;
+        while self
+            .mailbox
+            .get(identifier)
+            .map_or(0, |senders| senders.len())
+            < count
+        {
+            self.check_senders_alive(identifier, count)?;
+            match self.next_message(deadline).await? {
+                Some(msg) => self.process_next_message(&msg),
+                None if Instant::now() < deadline => (),
+                None => {
+                    let missing_senders = self.missing_senders(identifier);
+                    return Err(self.receive_failure(identifier, missing_senders));
+                }
+            }
+        }
+
+        let senders = self.mailbox.remove(identifier).unwrap_or_default();
+        for sender in senders.keys() {
+            self.take_pending(identifier, sender, false);
+        }
+        self.received.insert(identifier.to_string());
+        let late = self.peers().len().saturating_sub(senders.len());
+        if late > 0 {
+            self.late_senders.insert(identifier.to_string(), late);
+        }
+        let messages = senders
+            .into_iter()
+            .map(|(peer_id, value)| Ok((self.node_of(&peer_id)?, value)))
+            .collect::<Result<_, NetworkError>>()?;
+
+        self.metrics.end_round();
+        Ok(messages)
+    }
+
+    /// node ids of the peers we have not yet heard from for identifier, in ascending order
+    fn missing_senders(&self, identifier: &str) -> Vec<u64> {
+        let mut missing: Vec<u64> = self
+            .addr_book
+            .iter_ordered()
+            .filter(|peer| !self.id.eq(&peer.peer_id))
+            .filter(|peer| match self.mailbox.get(identifier) {
+                Some(senders) => !senders.contains_key(&peer.peer_id),
+                None => true,
+            })
+            .map(|peer| peer.node_id)
+            .collect();
+        missing.sort_unstable();
+        missing
+    }
+
+    //returns the handle which
+    fn process_next_message(&mut self, msg: &EvalNetMsg) {
+        self.metrics.record_received(msg);
+
+        // messages of another game may reuse our handles, so they must not get in
+        if session_of(msg).is_some_and(|s| !self.session_id.eq(s)) {
+            self.foreign_session_messages += 1;
+            return;
+        }
+
+        // a relay that does not filter may hand us what is meant for someone else
+        if let EvalNetMsg::DirectValue { recipient, .. }
+        | EvalNetMsg::DirectBatchBinary { recipient, .. }
+        | EvalNetMsg::Ack { recipient, .. }
+        | EvalNetMsg::Heartbeat {
+            recipient: Some(recipient),
+            ..
+        } = msg
+        {
+            if !self.id.eq(recipient) {
+                return;
+            }
+        }
+
+        // drop anything not signed by the peer it claims to come from
+        if let Err(NetworkError::BadSignature(sender)) = check_signature(msg, &self.peer_keys) {
+            #[cfg(feature = "print")]
+            println!(
+                "dropping message with bad signature claiming to be from {}",
+                sender
+            );
+            self.forged_senders.insert(sender);
+            return;
+        }
+
+        match msg {
+            EvalNetMsg::Abort { sender, reason, .. } => {
+                #[cfg(feature = "print")]
+                println!("{} aborted the session: {}", sender, reason);
+                if self.aborted.is_none() {
+                    // an abort from outside the addr book is dropped
+                    if let Some(by) = self.addr_book.node_of(sender) {
+                        self.aborted = Some((by, reason.clone()));
+                    }
+                }
+                return;
+            }
+            EvalNetMsg::Heartbeat {
+                sender,
+                recipient,
+                sent_at,
+                ..
+            } => {
+                match recipient {
+                    // the answer to one of ours
+                    Some(_) => {
+                        let sent_at = Duration::from_millis(*sent_at);
+                        let rtt = self.started.elapsed().saturating_sub(sent_at);
+                        self.hear_from(sender, Some(rtt));
+                    }
+                    None => {
+                        let answer = self.sign(EvalNetMsg::Heartbeat {
+                            session: self.session_id.clone(),
+                            sender: self.id.clone(),
+                            recipient: Some(sender.clone()),
+                            sent_at: *sent_at,
+                            signature: String::new(),
+                        });
+                        self.metrics.record_sent(&answer);
+                        self.try_send(answer);
+                        self.hear_from(sender, None);
+                    }
+                }
+                return;
+            }
+            EvalNetMsg::Ack { sender, seq, .. } => {
+                self.hear_from(sender, None);
+                if self.settle(*seq, sender) {
+                    self.journal(|| JournalRecord::Acked {
+                        seq: *seq,
+                        peer: sender.clone(),
+                    });
+                    self.collect_journal();
+                }
+                return;
+            }
+            EvalNetMsg::PublishValue {
+                sender, seq, round, ..
+            }
+            | EvalNetMsg::PublishBatchValue {
+                sender, seq, round, ..
+            }
+            | EvalNetMsg::PublishBatchBinary {
+                sender, seq, round, ..
+            }
+            | EvalNetMsg::DirectValue {
+                sender, seq, round, ..
+            }
+            | EvalNetMsg::DirectBatchBinary {
+                sender, seq, round, ..
+            } => {
+                self.hear_from(sender, None);
+                if *round > self.round.saturating_add(self.round_horizon) {
+                    // too far ahead to keep; unacknowledged, it comes again later
+                    self.metrics.record_beyond_horizon();
+                    return;
+                }
+                let late = handles_of(msg)
+                    .iter()
+                    .any(|handle| self.late_senders.contains_key(handle));
+                let stale = *round < self.round && !late;
+                let delivered = self
+                    .delivered
+                    .get(sender)
+                    .is_some_and(|seqs| seqs.contains(seq));
+                if !stale && !delivered && !self.has_room(sender, handles_of(msg).len()) {
+                    // unacknowledged, it comes again once we have taken some of
+                    // what the sender has waiting
+                    self.overflowing.insert(sender.clone());
+                    self.metrics.record_overflow();
+                    return;
+                }
+                self.overflowing.remove(sender);
+                if stale {
+                    // a replay, or a value of a round we have finished without it;
+                    // acked, like every copy, since the ack of an earlier one may
+                    // have been lost
+                    self.acknowledge(sender, *seq);
+                    self.metrics.record_stale_message();
+                    return;
+                }
+                if !self
+                    .delivered
+                    .entry(sender.clone())
+                    .or_default()
+                    .insert(*seq)
+                {
+                    // a retransmission of something we already have
+                    self.acknowledge(sender, *seq);
+                    self.metrics.record_duplicate();
+                    return;
+                }
+                // the values are kept, and journaled, before we ack them
+                self.accept_values(msg);
+                if self.journal_error.is_none() {
+                    self.acknowledge(sender, *seq);
+                }
+                return;
+            }
+            _ => (),
+        }
+        self.accept_values(msg);
+    }
+
+    /// takes recipient off those that must still acknowledge our message seq;
+    /// returns whether it was one of them
+    fn settle(&mut self, seq: u64, recipient: &str) -> bool {
+        let pending = match self.unacked.get_mut(&seq) {
+            Some(pending) => pending,
+            None => return false,
+        };
+        let awaited = pending.awaiting.remove(recipient);
+        if pending.awaiting.is_empty() {
+            self.unacked.remove(&seq);
+        }
+        awaited
+    }
+
+    /// puts the values msg carries in the mailboxes, and takes note of what a
+    /// connection notice of a peer says
+    fn accept_values(&mut self, msg: &EvalNetMsg) {
+        // nothing past this point sees a compressed payload
+        let limit = self
+            .compression
+            .map_or(DEFAULT_MAX_EXPANDED_SIZE, |config| config.max_expanded_size);
+        let expanded = match expand_msg(msg, limit) {
+            Ok(expanded) => expanded,
+            Err(_err) => {
+                #[cfg(feature = "print")]
+                println!("dropping compressed payload: {}", _err);
+                return;
+            }
+        };
+        match expanded.as_ref() {
+            EvalNetMsg::ConnectionEstablished {
+                sender: Some(sender),
+                binary_payloads,
+                encoding_tag: tag,
+                info,
+                waiting,
+                ..
+            } => {
+                if *tag != encoding_tag() {
+                    #[cfg(feature = "print")]
+                    println!("{} encodes values with tag {:#04x}", sender, tag);
+                    if self.mismatched_peer.is_none() {
+                        self.mismatched_peer = Some((sender.clone(), *tag));
+                    }
+                    return;
+                }
+                // peers of earlier releases only announce the flag
+                let binary = match info {
+                    Some(info) => info.has_feature(FEATURE_BINARY_PAYLOADS),
+                    None => *binary_payloads,
+                };
+                if binary {
+                    self.binary_peers.insert(sender.clone());
+                }
+                if let Some(info) = info {
+                    self.peer_infos.insert(sender.clone(), info.clone());
+                }
+                // a peer still in its handshake needs ours, even if we are past it
+                if *waiting && self.info.is_some() {
+                    self.announce(false);
+                }
+            }
+            EvalNetMsg::PublishValue {
+                sender,
+                round,
+                handle,
+                value,
+                ..
+            } => {
+                self.accept_handle_and_value_from_sender(sender, *round, handle, value.clone());
+            }
+            EvalNetMsg::PublishBatchValue {
+                sender,
+                round,
+                handles,
+                values,
+                ..
+            } => {
+                let values = match unpack_batch_values(handles.len(), values) {
+                    Ok(values) => values,
+                    Err(_) => {
+                        #[cfg(feature = "print")]
+                        println!("dropping malformed batch from {}", sender);
+                        return;
+                    }
+                };
+                for (h, v) in handles.iter().zip(values) {
+                    self.accept_handle_and_value_from_sender(sender, *round, h, v);
+                }
+            }
+            EvalNetMsg::PublishBatchBinary {
+                sender,
+                round,
+                handles,
+                payload,
+                kind,
+                ..
+            } => {
+                let size = payload_element_size(*kind);
+                if payload.len() != size * handles.len() {
+                    #[cfg(feature = "print")]
+                    println!("dropping binary batch with bad length from {}", sender);
+                    return;
+                }
+
+                // the mailbox holds base58 strings, which is what the decoders expect
+                for (h, v) in handles.iter().zip(payload.chunks(size)) {
+                    let value = bs58::encode(v).into_string();
+                    self.accept_handle_and_value_from_sender(sender, *round, h, value);
+                }
+            }
+            EvalNetMsg::DirectValue {
+                sender,
+                round,
+                handle,
+                value,
+                ..
+            } => {
+                self.accept_direct_value(sender, *round, handle, value);
+            }
+            EvalNetMsg::DirectBatchBinary {
+                sender,
+                round,
+                handles,
+                payload,
+                kind,
+                ..
+            } => {
+                let size = payload_element_size(*kind);
+                if payload.len() != size * handles.len() {
+                    #[cfg(feature = "print")]
+                    println!(
+                        "dropping direct binary batch with bad length from {}",
+                        sender
+                    );
+                    return;
+                }
+
+                for (h, v) in handles.iter().zip(payload.chunks(size)) {
+                    let value = bs58::encode(v).into_string();
+                    self.accept_direct_value(sender, *round, h, &value);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn accept_direct_value(&mut self, sender: &str, round: u64, handle: &str, value: &str) {
+        let received = self
+            .direct_received
+            .get(sender)
+            .is_some_and(|handles| handles.contains(handle));
+        if round == self.round && received {
+            self.metrics.record_duplicate();
+            return;
+        }
+        let senders = self.direct_mailbox.entry(handle.to_string()).or_default();
+        if let Entry::Vacant(entry) = senders.entry(sender.to_string()) {
+            entry.insert(value.to_string());
+            self.add_pending(sender);
+            self.journal(|| JournalRecord::Received {
+                handle: handle.to_string(),
+                sender: sender.to_string(),
+                value: value.to_string(),
+                direct: true,
+            });
+        }
+    }
+
+    /// whether that many more values of sender fit in our mailboxes; a peer with
+    /// nothing waiting always gets in, however large its batch
+    fn has_room(&self, sender: &str, values: usize) -> bool {
+        let pending = self.pending.get(sender).copied().unwrap_or(0);
+        pending == 0 || pending + values <= self.queues.max_pending_per_peer
+    }
+
+    /// counts a value of sender that went into a mailbox
+    fn add_pending(&mut self, sender: &str) {
+        match self.pending.get_mut(sender) {
+            Some(pending) => *pending += 1,
+            None => {
+                self.pending.insert(sender.to_string(), 1);
+            }
+        }
+        let total = self.pending.values().sum::<usize>();
+        self.metrics.record_pending_values(total as u64);
+    }
+
+    /// counts a value of sender that a receive took from a mailbox, the direct one
+    /// if direct, and journals that it is gone
+    fn take_pending(&mut self, handle: &str, sender: &str, direct: bool) {
+        if let Some(pending) = self.pending.get_mut(sender) {
+            *pending -= 1;
+            if *pending == 0 {
+                self.pending.remove(sender);
+            }
+        }
+        if self.journal.is_some() {
+            self.journal(|| JournalRecord::Taken {
+                handle: handle.to_string(),
+                sender: sender.to_string(),
+                direct,
+            });
+            self.collect_journal();
+        }
+    }
+
+    fn accept_handle_and_value_from_sender(
+        &mut self,
+        sender: &String,
+        round: u64,
+        handle: &String,
+        value: String,
+    ) {
+        // the value comes after recv_from_some no longer needed it
+        if let Some(late) = self.late_senders.get_mut(handle) {
+            *late -= 1;
+            let done = *late == 0;
+            if done {
+                self.late_senders.remove(handle);
+            }
+            if self.quorum_handles.contains(handle) {
+                if done {
+                    self.quorum_handles.remove(handle);
+                }
+                if let Some(node_id) = self.addr_book.node_of(sender) {
+                    self.late_values.push((handle.clone(), node_id, value));
+                }
+            }
+            return;
+        }
+
+        if round == self.round && self.received.contains(handle) {
+            self.metrics.record_duplicate();
+            return;
+        }
+
+        // if already exists, then ignore
+        let sender_exists_for_handle = self
+            .mailbox
+            .get(handle)
+            .is_some_and(|senders| senders.contains_key(sender));
+        if sender_exists_for_handle {
+            self.metrics.record_duplicate();
+            return;
+        } //ignore duplicate msg!
+
+        self.journal(|| JournalRecord::Received {
+            handle: handle.clone(),
+            sender: sender.clone(),
+            value: value.clone(),
+            direct: false,
+        });
+        // the entry is made on the first value for handle
+        self.mailbox
+            .entry(handle.clone())
+            .or_default()
+            .insert(sender.clone(), value);
+        self.add_pending(sender);
+    }
+}
+
+impl Drop for MessagingSystem {
+    fn drop(&mut self) {
+        if let Some((_, stop)) = &self.heartbeats {
+            stop.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        check_signature, decode_wire_msg, encode_wire_msg, CompressionConfig, MessagingSystem,
+        NetworkError, QueueConfig, DEFAULT_QUEUE_CAPACITY,
+    };
+    use crate::address_book::{
+        keypair_from_seed, parse_addr_book_from_json, PeerRole, Pok3rPeer, ADDRESSES,
+    };
+    use crate::common::{
+        Codec, EvalNetMsg, PayloadKind, PeerInfo, ProtocolParams, F, FEATURE_BINARY_PAYLOADS,
+        FEATURE_ZSTD,
+    };
+    use crate::compression;
+    use crate::encoding::{
+        decode_bs58_str_as_f, encode_as_bytes, encode_f_as_bs58_str, encoding_tag, pack_bytes,
+    };
+    use ark_std::UniformRand;
+    use futures::channel::mpsc;
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::time::{Duration, Instant};
+
+    struct TestNet {
+        parties: Vec<MessagingSystem>,
+        outgoing: Vec<mpsc::Receiver<EvalNetMsg>>,
+        incoming: Vec<mpsc::Sender<EvalNetMsg>>,
+    }
+
+    impl TestNet {
+        async fn new(binary_capabilities: &[bool]) -> Self {
+            let n = binary_capabilities.len();
+            let mut net = TestNet {
+                parties: vec![],
+                outgoing: vec![],
+                incoming: vec![],
+            };
+            for (i, binary) in binary_capabilities.iter().enumerate() {
+                let (mut n2e_tx, n2e_rx) = mpsc::channel::<EvalNetMsg>(DEFAULT_QUEUE_CAPACITY);
+                let (e2n_tx, e2n_rx) = mpsc::channel::<EvalNetMsg>(DEFAULT_QUEUE_CAPACITY);
+                n2e_tx
+                    .try_send(EvalNetMsg::ConnectionEstablished {
+                        success: true,
+                        sender: None,
+                        binary_payloads: false,
+                        session: String::new(),
+                        encoding_tag: 0,
+                        info: None,
+                        waiting: false,
+                        signature: String::new(),
+                    })
+                    .unwrap();
+                let messaging = MessagingSystem::new_with_capabilities(
+                    keypair_from_seed(i as u8 + 1),
+                    "test",
+                    parse_addr_book_from_json(n as u64).unwrap(),
+                    e2n_tx,
+                    n2e_rx,
+                    *binary,
+                )
+                .await
+                .unwrap();
+                net.parties.push(messaging);
+                net.outgoing.push(e2n_rx);
+                net.incoming.push(n2e_tx);
+            }
+            net.pump();
+            net
+        }
+
+        /// drains party i's outbox without delivering it
+        fn drain(&mut self, i: usize) -> Vec<EvalNetMsg> {
+            let mut msgs = vec![];
+            while let Ok(Some(msg)) = self.outgoing[i].try_next() {
+                msgs.push(msg);
+            }
+            msgs
+        }
+
+        /// delivers msg from party i to everyone else, through the wire codec
+        fn deliver(&mut self, i: usize, msg: &EvalNetMsg) {
+            let data = encode_wire_msg(msg).unwrap();
+            for (j, tx) in self.incoming.iter_mut().enumerate() {
+                if i != j {
+                    tx.try_send(decode_wire_msg(&data).unwrap()).unwrap();
+                }
+            }
+        }
+
+        fn pump(&mut self) {
+            for i in 0..self.parties.len() {
+                for msg in self.drain(i) {
+                    self.deliver(i, &msg);
+                }
+            }
+        }
+    }
+
+    async fn check_reconstruction(binary_capabilities: &[bool], expect_binary: bool) {
+        let mut net = TestNet::new(binary_capabilities).await;
+        let handles = [String::from("x"), String::from("y")];
+
+        for i in 0..net.parties.len() {
+            let shares = [F::from(i as u64 + 1), F::from(10 * (i as u64 + 1))];
+            let values = [encode_as_bytes(&shares[0]), encode_as_bytes(&shares[1])];
+            net.parties[i]
+                .send_batch_to_all(&handles, PayloadKind::Scalar, values)
+                .await
+                .unwrap();
+
+            for msg in net.drain(i) {
+                if let EvalNetMsg::PublishBatchValue { values, .. } = &msg {
+                    // one packed vector rather than a value per handle
+                    assert_eq!(values.len(), 1);
+                }
+                if !matches!(msg, EvalNetMsg::Ack { .. }) {
+                    let is_binary = matches!(msg, EvalNetMsg::PublishBatchBinary { .. });
+                    assert_eq!(is_binary, expect_binary);
+                }
+                net.deliver(i, &msg);
+            }
+        }
+
+        let n = net.parties.len() as u64;
+        for party in net.parties.iter_mut() {
+            let my_id = party.get_my_id();
+            for (handle, scale) in handles.iter().zip([1u64, 10]) {
+                let received = party.recv_from_all(handle).await.unwrap();
+                assert_eq!(received.len() as u64, n - 1);
+
+                let mut sum = F::from(scale * my_id);
+                for value in received.values() {
+                    sum += decode_bs58_str_as_f(value).unwrap();
+                }
+                assert_eq!(sum, F::from(scale * n * (n + 1) / 2));
+            }
+        }
+    }
+
+    #[test]
+    fn test_binary_peers_use_binary_payloads() {
+        async_std::task::block_on(check_reconstruction(&[true, true, true], true));
+    }
+
+    #[test]
+    fn test_binary_and_legacy_peers_interoperate() {
+        async_std::task::block_on(check_reconstruction(&[true, false, true], false));
+    }
+
+    #[test]
+    fn test_forged_sender_is_rejected() {
+        async_std::task::block_on(async {
+            let mut net = TestNet::new(&[true, true, true, true]).await;
+            let handle = String::from("x");
+
+            // party 1 claims to be party 2, but can only sign with its own key
+            let forged = net.parties[0].sign(EvalNetMsg::PublishValue {
+                session: String::from("test"),
+                sender: ADDRESSES[1].to_string(),
+                seq: 0,
+                attempt: 0,
+                round: 0,
+                handle: handle.clone(),
+                value: String::from("forged"),
+                signature: String::new(),
+            });
+            net.deliver(0, &forged);
+
+            for i in [0, 3] {
+                net.parties[i]
+                    .send_to_all(&[&handle], &[format!("{}", i + 1)])
+                    .await
+                    .unwrap();
+            }
+            net.pump();
+
+            let deadline = Instant::now() + Duration::from_millis(200);
+            assert_eq!(
+                net.parties[2]
+                    .recv_from_all_with_deadline(&handle, deadline)
+                    .await,
+                Err(NetworkError::BadSignature(ADDRESSES[1].to_string()))
+            );
+
+            // the genuine value of party 2 is still accepted
+            net.parties[1]
+                .send_to_all(&[&handle], &["2"])
+                .await
+                .unwrap();
+            net.pump();
+            let received = net.parties[2].recv_from_all(&handle).await.unwrap();
+            assert_eq!(received[&2], "2");
+        });
+    }
+
+    #[test]
+    fn test_bad_sends_and_strangers_are_errors() {
+        async_std::task::block_on(async {
+            let mut net = TestNet::new(&[true, true]).await;
+            let handle = String::from("x");
+
+            assert_eq!(
+                net.parties[0].send_to_all::<_, &str>(&[&handle], &[]).await,
+                Err(NetworkError::MismatchedValues {
+                    handles: 1,
+                    values: 0
+                })
+            );
+            assert_eq!(
+                net.parties[0].send_to_party(7, &[&handle], &["1"]).await,
+                Err(NetworkError::UnknownParty(7))
+            );
+            assert_eq!(
+                net.parties[0].recv_from_party(7, &handle).await,
+                Err(NetworkError::UnknownParty(7))
+            );
+
+            // a keypair the addr book does not list
+            let (_n2e_tx, n2e_rx) = mpsc::channel::<EvalNetMsg>(DEFAULT_QUEUE_CAPACITY);
+            let (e2n_tx, _e2n_rx) = mpsc::channel::<EvalNetMsg>(DEFAULT_QUEUE_CAPACITY);
+            let stranger = MessagingSystem::new(
+                keypair_from_seed(9),
+                "test",
+                parse_addr_book_from_json(2).unwrap(),
+                e2n_tx,
+                n2e_rx,
+            )
+            .await;
+            assert!(matches!(stranger, Err(NetworkError::NotInAddrBook)));
+
+            // an entry whose peer id carries no key is refused, and leaves the
+            // addr book as it was
+            let mut addr_book = net.parties[0].addr_book.clone();
+            addr_book.insert(Pok3rPeer {
+                peer_id: String::from("keyless"),
+                node_id: 3,
+                multiaddr: None,
+                role: PeerRole::Committee,
+            });
+            assert_eq!(
+                net.parties[0].update_addr_book(addr_book),
+                Err(NetworkError::NoPublicKey(String::from("keyless")))
+            );
+            assert_eq!(net.parties[0].addr_book.len(), 2);
+        });
+    }
+
+    #[test]
+    fn test_values_are_bound_to_their_round() {
+        async_std::task::block_on(async {
+            let mut net = TestNet::new(&[true, true]).await;
+            net.parties[1].set_round_horizon(2);
+            let acks = |msgs: &[EvalNetMsg]| {
+                msgs.iter()
+                    .filter(|msg| matches!(msg, EvalNetMsg::Ack { .. }))
+                    .count()
+            };
+            let wait = || Instant::now() + Duration::from_millis(100);
+
+            // round 5 is beyond the horizon of round 0, so it stays unacknowledged
+            net.parties[0].set_round(5);
+            net.parties[0].send_to_all(&["x"], &["far"]).await.unwrap();
+            let sent = net.drain(0);
+            net.deliver(0, &sent[0]);
+            let x = String::from("x");
+            assert!(net.parties[1]
+                .recv_from_all_with_deadline(&x, wait())
+                .await
+                .is_err());
+            assert_eq!(acks(&net.drain(1)), 0);
+
+            // until a retransmission comes once we have caught up
+            net.parties[1].set_round(5);
+            net.deliver(0, &sent[0]);
+            assert_eq!(net.parties[1].recv_from_all(&x).await.unwrap()[&1], "far");
+            assert_eq!(acks(&net.drain(1)), 1);
+
+            // a second value for a handle in the same round is dropped, even
+            // once the first one has been received
+            net.parties[0]
+                .send_to_all(&["x"], &["again"])
+                .await
+                .unwrap();
+            for msg in net.drain(0) {
+                net.deliver(0, &msg);
+            }
+            assert!(net.parties[1]
+                .recv_from_all_with_deadline(&x, wait())
+                .await
+                .is_err());
+
+            // and so is a value of a round we have left behind
+            net.parties[0].send_to_all(&["y"], &["old"]).await.unwrap();
+            net.parties[1].set_round(6);
+            for msg in net.drain(0) {
+                net.deliver(0, &msg);
+            }
+            let y = String::from("y");
+            assert!(net.parties[1]
+                .recv_from_all_with_deadline(&y, wait())
+                .await
+                .is_err());
+            assert_eq!(acks(&net.drain(1)), 2);
+
+            let metrics = net.parties[1].metrics().snapshot();
+            assert_eq!(metrics.round_horizon, 2);
+            assert_eq!(metrics.messages_beyond_horizon, 1);
+            assert_eq!(metrics.duplicates_dropped, 1);
+            assert_eq!(metrics.stale_messages, 1);
+        });
+    }
+
+    #[test]
+    fn test_peer_of_another_curve_is_rejected() {
+        async_std::task::block_on(async {
+            let mut net = TestNet::new(&[true, true]).await;
+
+            // what party 1 would announce if it were built with the other curve feature
+            let other_curve = encoding_tag() ^ 0x03;
+            let announcement = net.parties[0].sign(EvalNetMsg::ConnectionEstablished {
+                success: true,
+                sender: Some(net.parties[0].id.clone()),
+                binary_payloads: true,
+                session: String::from("test"),
+                encoding_tag: other_curve,
+                info: None,
+                waiting: false,
+                signature: String::new(),
+            });
+            net.deliver(0, &announcement);
+
+            let deadline = Instant::now() + Duration::from_millis(200);
+            assert_eq!(
+                net.parties[1]
+                    .recv_from_all_with_deadline(&String::from("x"), deadline)
+                    .await,
+                Err(NetworkError::EncodingMismatch {
+                    peer: ADDRESSES[0].to_string(),
+                    expected: encoding_tag(),
+                    got: other_curve,
+                })
+            );
+        });
+    }
+
+    #[test]
+    fn test_peer_of_another_perm_size_is_rejected() {
+        async_std::task::block_on(async {
+            let params = ProtocolParams::default();
+            let announcement = |net: &TestNet, info: PeerInfo| {
+                net.parties[1].sign(EvalNetMsg::ConnectionEstablished {
+                    success: true,
+                    sender: Some(net.parties[1].id.clone()),
+                    binary_payloads: true,
+                    session: String::from("test"),
+                    encoding_tag: encoding_tag(),
+                    info: Some(info),
+                    waiting: true,
+                    signature: String::new(),
+                })
+            };
+
+            let mut net = TestNet::new(&[true, true]).await;
+            let smaller = PeerInfo {
+                perm_size: 32,
+                deck_size: 26,
+                ..PeerInfo::new(&params)
+            };
+            net.deliver(1, &announcement(&net, smaller));
+            assert_eq!(
+                net.parties[0].handshake(&PeerInfo::new(&params)).await,
+                Err(NetworkError::IncompatiblePeer {
+                    peer: ADDRESSES[1].to_string(),
+                    field: String::from("perm_size"),
+                    ours: String::from("64"),
+                    theirs: String::from("32"),
+                })
+            );
+
+            // a peer that agrees is accepted, and is told what we take in return
+            let mut net = TestNet::new(&[true, true]).await;
+            let info = PeerInfo::new(&params).with_feature(FEATURE_BINARY_PAYLOADS);
+            net.deliver(1, &announcement(&net, info.clone()));
+            net.parties[0]
+                .handshake(&PeerInfo::new(&params))
+                .await
+                .unwrap();
+            assert_eq!(net.parties[0].peer_info(&net.parties[1].id), Some(&info));
+            let announced = net.drain(0).into_iter().any(|msg| {
+                matches!(
+                    msg,
+                    EvalNetMsg::ConnectionEstablished { info: Some(ours), .. } if ours == info
+                )
+            });
+            assert!(announced);
+        });
+    }
+
+    #[test]
+    fn test_spoofed_announcement_is_ignored() {
+        async_std::task::block_on(async {
+            let params = ProtocolParams::default();
+            let mut net = TestNet::new(&[true, true, true]).await;
+
+            // party 0 announces another curve and other sizes in the name of party 1
+            let spoofed = net.parties[0].sign(EvalNetMsg::ConnectionEstablished {
+                success: true,
+                sender: Some(net.parties[1].id.clone()),
+                binary_payloads: true,
+                session: String::from("test"),
+                encoding_tag: encoding_tag() ^ 0x03,
+                info: Some(PeerInfo {
+                    perm_size: 32,
+                    deck_size: 26,
+                    ..PeerInfo::new(&params)
+                }),
+                waiting: false,
+                signature: String::new(),
+            });
+            net.deliver(0, &spoofed);
+
+            // the genuine announcements of both still get party 2 through its handshake
+            let info = PeerInfo::new(&params);
+            for i in 0..2 {
+                net.parties[i].info = Some(info.clone());
+                let announcement = net.parties[i].announcement(false);
+                net.deliver(i, &announcement);
+            }
+            net.parties[2].handshake(&info).await.unwrap();
+            assert_eq!(net.parties[2].peer_info(&net.parties[1].id), Some(&info));
+        });
+    }
+
+    #[test]
+    fn test_large_batches_are_compressed() {
+        async_std::task::block_on(async {
+            let mut net = TestNet::new(&[true, true]).await;
+            let params = ProtocolParams::default();
+            let info = PeerInfo::new(&params)
+                .with_feature(FEATURE_BINARY_PAYLOADS)
+                .with_feature(FEATURE_ZSTD);
+            for i in 0..2 {
+                net.parties[i].set_compression_config(CompressionConfig {
+                    threshold: 1024,
+                    max_expanded_size: 1 << 20,
+                    ..CompressionConfig::default()
+                });
+                let announcement = net.parties[1 - i].sign(EvalNetMsg::ConnectionEstablished {
+                    success: true,
+                    sender: Some(net.parties[1 - i].id.clone()),
+                    binary_payloads: true,
+                    session: String::from("test"),
+                    encoding_tag: encoding_tag(),
+                    info: Some(info.clone()),
+                    waiting: false,
+                    signature: String::new(),
+                });
+                net.deliver(1 - i, &announcement);
+                net.parties[i]
+                    .handshake(&PeerInfo::new(&params))
+                    .await
+                    .unwrap();
+                net.drain(i);
+            }
+
+            // shares of a few constants repeat, random shares do not compress
+            let mut rng = StdRng::seed_from_u64(7);
+            let repeating: Vec<Vec<u8>> = (0..256u64)
+                .map(|i| encode_as_bytes(&F::from(i % 4)))
+                .collect();
+            let random: Vec<Vec<u8>> = (0..256)
+                .map(|_| encode_as_bytes(&F::rand(&mut rng)))
+                .collect();
+            let cases = [
+                (repeating.clone(), true, Codec::Zstd),
+                (random, true, Codec::Plain),
+                (repeating, false, Codec::Zstd),
+            ];
+            for (case, (values, binary, codec)) in cases.into_iter().enumerate() {
+                let handles: Vec<String> = (0..256).map(|i| format!("{}/{}", case, i)).collect();
+                let encoded: Vec<String> = values
+                    .iter()
+                    .map(|v| bs58::encode(v).into_string())
+                    .collect();
+                if binary {
+                    net.parties[0]
+                        .send_batch_to_all(&handles, PayloadKind::Scalar, &values)
+                        .await
+                        .unwrap();
+                } else {
+                    net.parties[0]
+                        .send_to_all(&handles, &encoded)
+                        .await
+                        .unwrap();
+                }
+                for msg in net.drain(0) {
+                    match &msg {
+                        EvalNetMsg::PublishBatchBinary { codec: sent, .. }
+                        | EvalNetMsg::PublishBatchValue { codec: sent, .. } => {
+                            assert_eq!(*sent, codec)
+                        }
+                        _ => (),
+                    }
+                    net.deliver(0, &msg);
+                }
+
+                let received = net.parties[1].recv_many_from_all(&handles).await.unwrap();
+                for (handle, value) in handles.iter().zip(encoded) {
+                    assert_eq!(received[handle][&1], value);
+                }
+            }
+
+            let sent = net.parties[0].metrics().snapshot();
+            let received = net.parties[1].metrics().snapshot();
+            for kind in ["batch_binary", "batch_value"] {
+                assert!(sent.raw_bytes_sent[kind] > sent.bytes_sent[kind]);
+                assert!(received.raw_bytes_received[kind] > received.bytes_received[kind]);
+            }
+
+            // a payload that would expand beyond the limit is dropped unopened
+            let bomb = net.parties[0].sign(EvalNetMsg::PublishBatchValue {
+                session: String::from("test"),
+                sender: net.parties[0].id.clone(),
+                seq: 1000,
+                attempt: 0,
+                round: 0,
+                handles: vec![String::from("u"), String::from("v")],
+                values: vec![],
+                codec: Codec::Zstd,
+                payload: compression::compress(&vec![0u8; 2 << 20], 3),
+                signature: String::new(),
+            });
+            net.deliver(0, &bomb);
+            let deadline = Instant::now() + Duration::from_millis(200);
+            assert!(matches!(
+                net.parties[1]
+                    .recv_from_all_with_deadline(&String::from("u"), deadline)
+                    .await,
+                Err(NetworkError::Timeout { .. })
+            ));
+        });
+    }
+
+    #[test]
+    fn test_flooding_peer_overflows_the_queue() {
+        async_std::task::block_on(async {
+            let mut net = TestNet::new(&[true, true]).await;
+            net.parties[1].set_queue_config(QueueConfig {
+                max_pending_per_peer: 4,
+                ..QueueConfig::default()
+            });
+
+            // party 0 sends values nobody asks for, then the one party 1 waits for
+            let flood: Vec<String> = (0..4).map(|i| format!("unsolicited{}", i)).collect();
+            for handle in flood.iter() {
+                net.parties[0].send_to_all(&[handle], &["v"]).await.unwrap();
+            }
+            net.parties[0].send_to_all(&["x"], &["x"]).await.unwrap();
+            let sent = net.drain(0);
+            for msg in sent.iter() {
+                net.deliver(0, msg);
+            }
+
+            let deadline = Instant::now() + Duration::from_millis(200);
+            assert_eq!(
+                net.parties[1]
+                    .recv_from_all_with_deadline(&String::from("x"), deadline)
+                    .await,
+                Err(NetworkError::QueueOverflow {
+                    peer: net.parties[0].id.clone(),
+                    pending: 4,
+                    limit: 4,
+                })
+            );
+            let acks = net
+                .drain(1)
+                .into_iter()
+                .filter(|msg| matches!(msg, EvalNetMsg::Ack { .. }))
+                .count();
+            assert_eq!(acks, 4);
+            assert_eq!(net.parties[1].metrics().snapshot().messages_overflowed, 1);
+            assert_eq!(
+                net.parties[1]
+                    .metrics()
+                    .snapshot()
+                    .pending_values_high_water,
+                4
+            );
+
+            // once one of them is taken, the retransmission of x gets in
+            net.parties[1].recv_from_all(&flood[0]).await.unwrap();
+            net.deliver(0, sent.last().unwrap());
+            let received = net.parties[1]
+                .recv_from_all(&String::from("x"))
+                .await
+                .unwrap();
+            assert_eq!(received[&1], "x");
+        });
+    }
+
+    #[test]
+    fn test_per_value_batches_are_still_accepted() {
+        async_std::task::block_on(async {
+            let mut net = TestNet::new(&[false, false]).await;
+            let handles: Vec<String> = vec![String::from("x"), String::from("y")];
+            let values: Vec<String> = (1..3)
+                .map(|i| encode_f_as_bs58_str(&F::from(i as u64)))
+                .collect();
+
+            // the format of earlier releases, one value per handle
+            net.parties[0].send_to_all(&handles, &values).await.unwrap();
+            // a packed vector holding one value too few is dropped as a whole
+            let short = net.parties[0].sign(EvalNetMsg::PublishBatchValue {
+                session: String::from("test"),
+                sender: net.parties[0].id.clone(),
+                seq: 1000,
+                attempt: 0,
+                round: 0,
+                handles: vec![String::from("u"), String::from("v")],
+                values: vec![pack_bytes(&[encode_as_bytes(&F::from(3u64))])],
+                codec: Codec::Plain,
+                payload: vec![],
+                signature: String::new(),
+            });
+            net.deliver(0, &short);
+            net.pump();
+
+            let received = net.parties[1].recv_from_all(&handles[1]).await.unwrap();
+            assert_eq!(received[&1], values[1]);
+            net.parties[1].set_recv_timeout(Duration::from_millis(50));
+            assert!(net.parties[1]
+                .recv_from_all(&String::from("u"))
+                .await
+                .is_err());
+        });
+    }
+
+    #[test]
+    fn test_batch_signature_verifies() {
+        async_std::task::block_on(async {
+            let net = TestNet::new(&[true, true]).await;
+            let handles: Vec<String> = (0..64).map(|i| format!("h{}", i)).collect();
+            let values: Vec<String> = (0..64)
+                .map(|i| encode_f_as_bs58_str(&F::from(i as u64)))
+                .collect();
+            let msg = net.parties[0].sign(EvalNetMsg::PublishBatchValue {
+                session: String::from("test"),
+                sender: net.parties[0].id.clone(),
+                seq: 0,
+                attempt: 0,
+                round: 0,
+                handles,
+                values,
+                codec: Codec::Plain,
+                payload: vec![],
+                signature: String::new(),
+            });
+
+            // how long the check takes is measured by the signature bench
+            assert!(check_signature(&msg, &net.parties[1].peer_keys).is_ok());
+        });
     }
 }
@@ -0,0 +1,877 @@
+//! A party run as a process of its own. The node joins the session its config
+//! file names, then takes commands, one per line, and answers each with one line
+//! of JSON:
+//!
+//! ```text
+//! shuffle             shuffles and commits to the deck, with the permutation proof
+//! deal <player>       deals the next card of the deck, encrypted to player
+//! reveal <positions>  opens the cards at positions to everyone
+//! showdown            opens every card dealt and not opened yet
+//! quit                waits for the other parties to have our messages, and leaves
+//! ```
+//!
+//! Every party must be given the same commands in the same order. Proofs,
+//! commitments and dealt cards come base58 encoded, in their ProofEncoding or
+//! as encode_as_bytes lays them out. An interrupt aborts the session for all.
+
+use async_std::task;
+use futures::{
+    channel::mpsc, io::AsyncWriteExt, pin_mut, select, AsyncWrite, FutureExt, Stream, StreamExt,
+};
+use libp2p::Multiaddr;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::{env, error, fmt, fs, io, thread};
+
+use crate::address_book::{load_keypair_from_file, parse_addr_book_from_file, AddrBookError};
+use crate::cards::Card;
+use crate::common::{
+    Curve, EvalNetMsg, ProtocolParams, ProtocolParamsError, ResourceEstimate, DECK_SIZE, F, G2,
+    PERM_SIZE,
+};
+use crate::encoding::{decode_bs58_str_as_g2, encode_as_bytes, ProofEncoding};
+use crate::evaluator::{BuildError, Evaluator, EvaluatorError};
+use crate::hash::{GameContext, IdCache};
+use crate::keys::{load_keypair, KeyError};
+use crate::kzg::{ParamsError, UniversalParams};
+use crate::metrics::{Metrics, MetricsSnapshot};
+use crate::network::{
+    run_networking_daemon_on, MessagingSystem, NetworkError, DEFAULT_QUEUE_CAPACITY,
+};
+use crate::preprocessing::PreprocessingError;
+use crate::shuffler::{
+    compute_keyper_keys, compute_params, compute_permutation_argument, deal_card, deal_resources,
+    load_params, permutation_resources, reveal_public_cards, shuffle_deck, shuffle_resources,
+    DealError, PublicCard, ShuffledDeck,
+};
+use crate::transport::Transport;
+use crate::utils;
+
+/// where a node listens unless its config says otherwise
+pub const DEFAULT_LISTEN_ADDR: &str = "/ip4/0.0.0.0/udp/0/quic-v1";
+
+/// NodeConfig is what a node reads from its TOML config file. Relative paths
+/// are taken from the directory of the file.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NodeConfig {
+    /// file holding the base58 encoded ed25519 secret key of the node, or the
+    /// key file pok3r-keygen encrypted if passphrase_env is set
+    pub keypair: PathBuf,
+    /// environment variable holding the passphrase of an encrypted keypair file
+    pub passphrase_env: Option<String>,
+    /// JSON addr book listing every party of the session
+    pub addr_book: PathBuf,
+    #[serde(default = "default_listen")]
+    pub listen: String,
+    /// identifies the game; must be the same for all parties
+    pub session: String,
+    /// the players cards are dealt to, numbered from 0
+    pub players: usize,
+    #[serde(default = "default_deck_size")]
+    pub deck_size: usize,
+    #[serde(default = "default_perm_size")]
+    pub perm_size: usize,
+    /// preprocessing exported for this node; without it, the node preprocesses
+    /// with the other parties on start
+    pub preprocessing: Option<PathBuf>,
+    /// KZG params, natively serialized or as a .ptau file; defaults to params
+    /// from a fixed local trapdoor, fit for testing only
+    pub params: Option<PathBuf>,
+    /// base58 master public key cards are dealt under; a node refuses to start
+    /// without it unless test_keyper_key is set
+    pub mpk: Option<String>,
+    /// deal under the key of a fixed seed whose secret anyone can derive, fit for
+    /// testing only
+    #[serde(default)]
+    pub test_keyper_key: bool,
+    /// messages that may wait in each direction between the evaluator and the
+    /// networkd
+    #[serde(default = "default_queue_capacity")]
+    pub queue_capacity: usize,
+}
+
+fn default_listen() -> String {
+    String::from(DEFAULT_LISTEN_ADDR)
+}
+
+pub(crate) fn default_deck_size() -> usize {
+    DECK_SIZE
+}
+
+pub(crate) fn default_perm_size() -> usize {
+    PERM_SIZE
+}
+
+fn default_queue_capacity() -> usize {
+    DEFAULT_QUEUE_CAPACITY
+}
+
+impl NodeConfig {
+    pub fn read(path: &Path) -> Result<Self, NodeError> {
+        let contents = fs::read_to_string(path)?;
+        let mut config: NodeConfig =
+            toml::from_str(&contents).map_err(|e| NodeError::Config(e.to_string()))?;
+        if let Some(dir) = path.parent() {
+            config.resolve_paths(dir);
+        }
+        Ok(config)
+    }
+
+    fn resolve_paths(&mut self, dir: &Path) {
+        self.keypair = dir.join(&self.keypair);
+        self.addr_book = dir.join(&self.addr_book);
+        if let Some(path) = self.preprocessing.as_mut() {
+            *path = dir.join(&*path);
+        }
+        if let Some(path) = self.params.as_mut() {
+            *path = dir.join(&*path);
+        }
+    }
+
+    pub fn protocol_params(&self) -> Result<ProtocolParams, NodeError> {
+        Ok(ProtocolParams::new(self.perm_size, self.deck_size)?)
+    }
+
+    /// the master public key cards are dealt under
+    pub fn keyper_key(&self) -> Result<G2, NodeError> {
+        match (&self.mpk, self.test_keyper_key) {
+            (Some(_), true) => Err(NodeError::Config(String::from(
+                "mpk and test_keyper_key are exclusive",
+            ))),
+            (Some(mpk), false) => {
+                decode_bs58_str_as_g2(mpk).map_err(|err| NodeError::Config(format!("mpk: {}", err)))
+            }
+            (None, true) => Ok(compute_keyper_keys().1),
+            (None, false) => Err(NodeError::Config(String::from(
+                "mpk is not set; set test_keyper_key to deal under the test key",
+            ))),
+        }
+    }
+}
+
+/// NodeError describes why a node could not start, or why a command was refused
+#[derive(Debug)]
+pub enum NodeError {
+    Io(io::Error),
+    /// the config file is not well-formed
+    Config(String),
+    AddrBook(AddrBookError),
+    Key(KeyError),
+    Params(ParamsError),
+    ProtocolParams(ProtocolParamsError),
+    Preprocessing(PreprocessingError),
+    Network(NetworkError),
+    Build(BuildError),
+    Evaluator(EvaluatorError),
+    Deal(DealError),
+    UnknownCommand(String),
+    /// positions and players are numbers
+    BadArgument(String),
+    /// the deck must be shuffled before cards are dealt or revealed
+    NotShuffled,
+    /// the node shuffles one deck
+    AlreadyShuffled,
+    UnknownPlayer {
+        player: usize,
+        players: usize,
+    },
+    /// every card of the deck was dealt or revealed
+    DeckExhausted,
+    /// the value opened at position is not one of the deck
+    NotACard(usize),
+}
+
+impl fmt::Display for NodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NodeError::Io(err) => write!(f, "i/o error: {}", err),
+            NodeError::Config(reason) => write!(f, "bad config: {}", reason),
+            NodeError::AddrBook(err) => write!(f, "{}", err),
+            NodeError::Key(err) => write!(f, "{}", err),
+            NodeError::Params(err) => write!(f, "{}", err),
+            NodeError::ProtocolParams(err) => write!(f, "{}", err),
+            NodeError::Preprocessing(err) => write!(f, "{}", err),
+            NodeError::Network(err) => write!(f, "{}", err),
+            NodeError::Build(err) => write!(f, "{}", err),
+            NodeError::Evaluator(err) => write!(f, "{}", err),
+            NodeError::Deal(err) => write!(f, "{}", err),
+            NodeError::UnknownCommand(line) => write!(f, "unknown command: {}", line),
+            NodeError::BadArgument(arg) => write!(f, "{} is not a number", arg),
+            NodeError::NotShuffled => write!(f, "the deck is not shuffled yet"),
+            NodeError::AlreadyShuffled => write!(f, "the deck was shuffled already"),
+            NodeError::UnknownPlayer { player, players } => {
+                write!(f, "player {} is not one of the {} players", player, players)
+            }
+            NodeError::DeckExhausted => write!(f, "no cards are left in the deck"),
+            NodeError::NotACard(position) => {
+                write!(f, "the value at position {} is not a card", position)
+            }
+        }
+    }
+}
+
+impl error::Error for NodeError {}
+
+impl From<io::Error> for NodeError {
+    fn from(err: io::Error) -> Self {
+        NodeError::Io(err)
+    }
+}
+
+impl From<AddrBookError> for NodeError {
+    fn from(err: AddrBookError) -> Self {
+        NodeError::AddrBook(err)
+    }
+}
+
+impl From<KeyError> for NodeError {
+    fn from(err: KeyError) -> Self {
+        NodeError::Key(err)
+    }
+}
+
+impl From<ParamsError> for NodeError {
+    fn from(err: ParamsError) -> Self {
+        NodeError::Params(err)
+    }
+}
+
+impl From<ProtocolParamsError> for NodeError {
+    fn from(err: ProtocolParamsError) -> Self {
+        NodeError::ProtocolParams(err)
+    }
+}
+
+impl From<PreprocessingError> for NodeError {
+    fn from(err: PreprocessingError) -> Self {
+        NodeError::Preprocessing(err)
+    }
+}
+
+impl From<NetworkError> for NodeError {
+    fn from(err: NetworkError) -> Self {
+        NodeError::Network(err)
+    }
+}
+
+impl From<BuildError> for NodeError {
+    fn from(err: BuildError) -> Self {
+        NodeError::Build(err)
+    }
+}
+
+impl From<EvaluatorError> for NodeError {
+    fn from(err: EvaluatorError) -> Self {
+        NodeError::Evaluator(err)
+    }
+}
+
+impl From<DealError> for NodeError {
+    fn from(err: DealError) -> Self {
+        NodeError::Deal(err)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Command {
+    Shuffle,
+    Deal(usize),
+    Reveal(Vec<usize>),
+    Showdown,
+    Quit,
+}
+
+/// commands are a name followed by numbers, separated by spaces or commas
+impl FromStr for Command {
+    type Err = NodeError;
+
+    fn from_str(line: &str) -> Result<Self, NodeError> {
+        let mut words = line
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|word| !word.is_empty());
+        let name = words.next().unwrap_or_default();
+        let numbers = words
+            .map(|word| {
+                word.parse::<usize>()
+                    .map_err(|_| NodeError::BadArgument(word.to_string()))
+            })
+            .collect::<Result<Vec<usize>, NodeError>>()?;
+
+        match (name, numbers.as_slice()) {
+            ("shuffle", []) => Ok(Command::Shuffle),
+            ("deal", [player]) => Ok(Command::Deal(*player)),
+            ("reveal", [_, ..]) => Ok(Command::Reveal(numbers)),
+            ("showdown", []) => Ok(Command::Showdown),
+            ("quit", []) => Ok(Command::Quit),
+            _ => Err(NodeError::UnknownCommand(line.trim().to_string())),
+        }
+    }
+}
+
+/// the identity cards are dealt to player under
+pub fn player_id(player: usize) -> Vec<u8> {
+    format!("player-{}", player).into_bytes()
+}
+
+/// preprocessing consumed by a node: the shuffle with its permutation argument,
+/// and enough for every card of the deck to be dealt
+pub fn resources(params: &ProtocolParams) -> ResourceEstimate {
+    (0..params.deck_size()).fold(
+        shuffle_resources(params) + permutation_resources(params),
+        |acc, _| acc + deal_resources(),
+    )
+}
+
+fn bs58_of(bytes: Vec<u8>) -> String {
+    bs58::encode(bytes).into_string()
+}
+
+/// Node plays one deck of a session: it shuffles it, deals and reveals its cards
+pub struct Node<T: Transport> {
+    evaluator: Evaluator<T>,
+    pp: UniversalParams<Curve>,
+    /// the identities of the session, hashed and paired as cards are dealt
+    id_cache: IdCache,
+    /// the key cards are dealt under
+    pk: G2,
+    players: usize,
+    deck: Option<ShuffledDeck>,
+    /// the positions dealt so far, in order, with the player each went to
+    dealt: Vec<(usize, usize)>,
+}
+
+impl<T: Transport> Node<T> {
+    pub fn new(
+        evaluator: Evaluator<T>,
+        pp: UniversalParams<Curve>,
+        ctx: GameContext,
+        pk: G2,
+        players: usize,
+    ) -> Self {
+        let id_cache = IdCache::new(ctx, evaluator.params().deck_size());
+        Node {
+            evaluator,
+            pp,
+            id_cache,
+            pk,
+            players,
+            deck: None,
+            dealt: Vec::new(),
+        }
+    }
+
+    /// runs command, and returns the event reporting its outcome
+    pub async fn execute(&mut self, command: &Command) -> Result<Value, NodeError> {
+        match command {
+            Command::Shuffle => self.shuffle().await,
+            Command::Deal(player) => self.deal(*player).await,
+            Command::Reveal(positions) => {
+                let cards = self.reveal(positions).await?;
+                Ok(json!({ "event": "revealed", "cards": cards }))
+            }
+            Command::Showdown => self.showdown().await,
+            Command::Quit => {
+                self.evaluator.flush().await?;
+                Ok(json!({ "event": "quit" }))
+            }
+        }
+    }
+
+    async fn shuffle(&mut self) -> Result<Value, NodeError> {
+        if self.deck.is_some() {
+            return Err(NodeError::AlreadyShuffled);
+        }
+        let cards = shuffle_deck(&mut self.evaluator).await?;
+        let (proof, alpha1) =
+            compute_permutation_argument(&self.pp, &mut self.evaluator, &cards).await?;
+        let params = *self.evaluator.params();
+        self.deck = Some(ShuffledDeck::new(params, cards, proof.f_com, alpha1)?);
+
+        Ok(json!({
+            "event": "shuffled",
+            "commitment": bs58_of(encode_as_bytes(&proof.f_com)),
+            "proof": proof.to_bs58(),
+        }))
+    }
+
+    async fn deal(&mut self, player: usize) -> Result<Value, NodeError> {
+        if player >= self.players {
+            return Err(NodeError::UnknownPlayer {
+                player,
+                players: self.players,
+            });
+        }
+        let deck = self.deck.as_mut().ok_or(NodeError::NotShuffled)?;
+        // the dummy cards fill the first positions of the permutation
+        let params = *deck.params();
+        let position = (params.perm_size() - params.deck_size()..params.perm_size())
+            .find(|position| !deck.is_dealt(*position) && !deck.is_revealed(*position))
+            .ok_or(NodeError::DeckExhausted)?;
+
+        let dealt = deal_card(
+            &self.pp,
+            &mut self.evaluator,
+            &mut self.id_cache,
+            deck,
+            position,
+            &player_id(player),
+            self.pk,
+        )
+        .await?;
+        self.dealt.push((position, player));
+
+        Ok(json!({
+            "event": "dealt",
+            "player": player,
+            "position": position,
+            "card": dealt.to_bs58(),
+        }))
+    }
+
+    async fn reveal(&mut self, positions: &[usize]) -> Result<Vec<Value>, NodeError> {
+        let deck = self.deck.as_mut().ok_or(NodeError::NotShuffled)?;
+        let params = *deck.params();
+        let cards = reveal_public_cards(&self.pp, &mut self.evaluator, deck, positions).await?;
+        cards
+            .iter()
+            .map(|card| public_card_event(&params, card))
+            .collect()
+    }
+
+    async fn showdown(&mut self) -> Result<Value, NodeError> {
+        let deck = self.deck.as_ref().ok_or(NodeError::NotShuffled)?;
+        let hidden = self
+            .dealt
+            .iter()
+            .copied()
+            .filter(|(position, _)| !deck.is_revealed(*position))
+            .collect::<Vec<(usize, usize)>>();
+        let positions = hidden
+            .iter()
+            .map(|(position, _)| *position)
+            .collect::<Vec<usize>>();
+
+        let mut cards = self.reveal(&positions).await?;
+        for (card, (_, player)) in cards.iter_mut().zip(hidden) {
+            card["player"] = json!(player);
+        }
+        Ok(json!({ "event": "showdown", "cards": cards }))
+    }
+
+    /// runs the commands of input, one per line, and writes the event of each to
+    /// output, until input ends or a quit command; a command that fails is
+    /// reported as an error event. The first item of interrupts aborts the session,
+    /// and with it any command under way.
+    pub async fn run<I, W, S>(
+        &mut self,
+        input: I,
+        output: &mut W,
+        interrupts: S,
+    ) -> Result<(), NodeError>
+    where
+        I: Stream<Item = io::Result<String>> + Unpin,
+        W: AsyncWrite + Unpin,
+        S: Stream<Item = ()> + Unpin,
+    {
+        let mut input = input.fuse();
+        let mut interrupts = interrupts.fuse();
+        loop {
+            let line = select! {
+                line = input.next() => line,
+                _ = interrupts.select_next_some() => return self.interrupted(output).await,
+            };
+            let command = match line {
+                Some(line) => line?.parse::<Command>(),
+                None => Ok(Command::Quit),
+            };
+            if let Ok(Command::Quit) = command {
+                let event = self.execute(&Command::Quit).await;
+                return emit(output, &event_or_error(event)).await;
+            }
+
+            let event = match command {
+                Ok(command) => {
+                    let execution = self.execute(&command).fuse();
+                    pin_mut!(execution);
+                    select! {
+                        event = execution => Some(event),
+                        _ = interrupts.select_next_some() => None,
+                    }
+                }
+                Err(err) => Some(Err(err)),
+            };
+            match event {
+                Some(event) => emit(output, &event_or_error(event)).await?,
+                None => return self.interrupted(output).await,
+            }
+        }
+    }
+
+    /// the communication and preprocessing counters of the evaluator
+    pub fn metrics(&self) -> Value {
+        json!(self.evaluator.metrics())
+    }
+
+    /// the audit transcript recorded so far, which is empty unless the evaluator
+    /// was given enable_audit
+    pub fn transcript(&self) -> Value {
+        let records = self
+            .evaluator
+            .audit_log()
+            .map(|log| log.records())
+            .unwrap_or_default();
+        json!({ "records": records })
+    }
+
+    async fn interrupted<W: AsyncWrite + Unpin>(
+        &mut self,
+        output: &mut W,
+    ) -> Result<(), NodeError> {
+        self.evaluator.abort("interrupted").await;
+        emit(output, &json!({ "event": "aborted" })).await
+    }
+}
+
+impl Node<MessagingSystem> {
+    /// joins the session of config over the network. The networking daemon runs on
+    /// a thread of its own, until the Daemon returned with the node is stopped.
+    pub async fn start(config: &NodeConfig) -> Result<(Self, Daemon), NodeError> {
+        let params = config.protocol_params()?;
+        let pk = config.keyper_key()?;
+        let keypair = match &config.passphrase_env {
+            Some(var) => {
+                let passphrase =
+                    env::var(var).map_err(|_| NodeError::Config(format!("{} is not set", var)))?;
+                load_keypair(&config.keypair, &passphrase)?
+            }
+            None => load_keypair_from_file(&config.keypair)?,
+        };
+        let addr_book = parse_addr_book_from_file(&config.addr_book)?;
+        let listen_addr: Multiaddr = config
+            .listen
+            .parse()
+            .map_err(|_| NodeError::Config(format!("{} is not a multiaddr", config.listen)))?;
+        let pp = match &config.params {
+            Some(path) => load_params(path, &params)?,
+            None => compute_params(),
+        };
+
+        // these channels connect the evaluator and the networking daemon
+        let (mut n2e_tx, n2e_rx) = mpsc::channel::<EvalNetMsg>(config.queue_capacity);
+        let (e2n_tx, e2n_rx) = mpsc::channel::<EvalNetMsg>(config.queue_capacity);
+        let (kill_tx, kill_rx) = mpsc::unbounded::<()>();
+        let (daemon_keypair, daemon_addr_book) = (keypair.clone(), addr_book.clone());
+        let daemon_metrics = Arc::new(Metrics::new());
+        let metrics = daemon_metrics.clone();
+        thread::spawn(move || {
+            let result = task::block_on(run_networking_daemon_on(
+                daemon_keypair,
+                listen_addr,
+                &daemon_addr_book,
+                &mut n2e_tx,
+                e2n_rx,
+                Some(kill_rx),
+                metrics,
+            ));
+            if let Err(err) = result {
+                eprintln!("networking error: {}", err);
+            }
+        });
+
+        let publish_proofs = addr_book.verifiers().next().is_some();
+        let messaging =
+            MessagingSystem::new(keypair, &config.session, addr_book, e2n_tx, n2e_rx).await?;
+        let mut evaluator = match &config.preprocessing {
+            Some(path) => Evaluator::new_from_preprocessing_for(messaging, path, params).await?,
+            None => Evaluator::new_for(messaging, params, resources(&params), 0).await?,
+        };
+        evaluator.set_publish_proofs(publish_proofs);
+
+        let ctx = GameContext::from_label(&config.session);
+        let node = Node::new(evaluator, pp, ctx, pk, config.players);
+        let daemon = Daemon {
+            kill: kill_tx,
+            metrics: daemon_metrics,
+        };
+        Ok((node, daemon))
+    }
+}
+
+/// Daemon is the networking daemon of a node
+pub struct Daemon {
+    kill: mpsc::UnboundedSender<()>,
+    metrics: Arc<Metrics>,
+}
+
+impl Daemon {
+    /// what the daemon counted, such as the messages it dropped as malformed
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.metrics.snapshot()
+    }
+
+    /// tells the daemon to stop, once it has sent what it was handed
+    pub fn stop(self) {
+        let _ = self.kill.unbounded_send(());
+    }
+}
+
+fn public_card_event(params: &ProtocolParams, card: &PublicCard) -> Result<Value, NodeError> {
+    let index = card_index(params, &card.value).ok_or(NodeError::NotACard(card.position))?;
+    Ok(json!({
+        "position": card.position,
+        "card": index,
+        "name": Card::from_index(index).map(|card| card.to_string()),
+        "proof": bs58_of(encode_as_bytes(&card.proof)),
+        "was_dealt": card.was_dealt,
+    }))
+}
+
+/// the index in 0..deck_size of the card value encodes
+fn card_index(params: &ProtocolParams, value: &F) -> Option<usize> {
+    utils::domain_info(params.perm_size())
+        .powers
+        .iter()
+        .position(|power| power == value)
+        .filter(|index| *index < params.deck_size())
+}
+
+pub(crate) fn error_event(err: &dyn fmt::Display) -> Value {
+    json!({ "event": "error", "message": err.to_string() })
+}
+
+fn event_or_error(event: Result<Value, NodeError>) -> Value {
+    event.unwrap_or_else(|err| error_event(&err))
+}
+
+async fn emit<W: AsyncWrite + Unpin>(output: &mut W, event: &Value) -> Result<(), NodeError> {
+    let mut line = event.to_string();
+    line.push('\n');
+    output.write_all(line.as_bytes()).await?;
+    output.flush().await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{player_id, resources, Command, Node, NodeConfig, NodeError};
+    use crate::common::{DealtCard, PermutationProof, ProtocolParams, G1};
+    use crate::encoding::{decode_from_bytes, encode_as_bs58_str, ProofEncoding};
+    use crate::evaluator::Evaluator;
+    use crate::hash::GameContext;
+    use crate::shuffler::{compute_keyper_keys, compute_params, verify_dealt_card};
+    use crate::transport::InMemoryTransport;
+    use futures::{
+        future::{join_all, try_join_all},
+        stream,
+    };
+    use serde_json::Value;
+    use std::collections::HashSet;
+    use std::io;
+    use std::path::{Path, PathBuf};
+
+    /// runs script at each of the parties, in process, and returns what they output
+    fn run_script(params: ProtocolParams, scripts: &[&str], interrupted: &[bool]) -> Vec<String> {
+        let pp = compute_params();
+        let (_, pk) = compute_keyper_keys();
+        let ctx = GameContext::from_label("node-test");
+
+        async_std::task::block_on(async {
+            let transports = InMemoryTransport::new_network(scripts.len()).await;
+            let evaluators = try_join_all(
+                transports
+                    .into_iter()
+                    .map(|transport| Evaluator::new_for(transport, params, resources(&params), 0)),
+            )
+            .await
+            .unwrap();
+
+            join_all(evaluators.into_iter().zip(scripts).zip(interrupted).map(
+                |((evaluator, script), interrupted)| {
+                    let mut node = Node::new(evaluator, pp.clone(), ctx, pk, 2);
+                    let lines = script
+                        .lines()
+                        .map(|line| Ok(line.to_string()))
+                        .collect::<Vec<io::Result<String>>>();
+                    async move {
+                        let mut output = Vec::new();
+                        if *interrupted {
+                            node.run(stream::pending(), &mut output, stream::iter([()]))
+                                .await
+                                .unwrap();
+                        } else {
+                            node.run(stream::iter(lines), &mut output, stream::pending())
+                                .await
+                                .unwrap();
+                        }
+                        String::from_utf8(output).unwrap()
+                    }
+                },
+            ))
+            .await
+        })
+    }
+
+    fn events(output: &str) -> Vec<Value> {
+        output
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_commands() {
+        assert_eq!("shuffle".parse::<Command>().unwrap(), Command::Shuffle);
+        assert_eq!(" deal 1 ".parse::<Command>().unwrap(), Command::Deal(1));
+        assert_eq!(
+            "reveal 10, 11 12".parse::<Command>().unwrap(),
+            Command::Reveal(vec![10, 11, 12])
+        );
+        assert_eq!("showdown".parse::<Command>().unwrap(), Command::Showdown);
+        assert!(matches!(
+            "deal one".parse::<Command>(),
+            Err(NodeError::BadArgument(arg)) if arg == "one"
+        ));
+        assert!(matches!(
+            "reveal".parse::<Command>(),
+            Err(NodeError::UnknownCommand(_))
+        ));
+        assert!(matches!(
+            "deal 1 2".parse::<Command>(),
+            Err(NodeError::UnknownCommand(_))
+        ));
+    }
+
+    #[test]
+    fn test_config_paths_are_relative_to_the_file() {
+        let mut config: NodeConfig = toml::from_str(
+            r#"
+            keypair = "node1.key"
+            addr_book = "/etc/pok3r/addr_book.json"
+            session = "table-1"
+            players = 2
+            preprocessing = "node1.pre"
+            "#,
+        )
+        .unwrap();
+        config.resolve_paths(Path::new("/srv/node1"));
+
+        assert_eq!(config.keypair, PathBuf::from("/srv/node1/node1.key"));
+        assert_eq!(config.addr_book, PathBuf::from("/etc/pok3r/addr_book.json"));
+        assert_eq!(
+            config.preprocessing,
+            Some(PathBuf::from("/srv/node1/node1.pre"))
+        );
+        assert_eq!(config.params, None);
+        assert_eq!(config.passphrase_env, None);
+        assert_eq!(config.listen, super::DEFAULT_LISTEN_ADDR);
+        assert!(config.protocol_params().is_ok());
+
+        assert!(toml::from_str::<NodeConfig>("keypair = \"k\"\nplayers = 2").is_err());
+    }
+
+    #[test]
+    fn test_config_needs_a_keyper_key() {
+        let mut config: NodeConfig = toml::from_str(
+            r#"
+            keypair = "node1.key"
+            addr_book = "addr_book.json"
+            session = "table-1"
+            players = 2
+            "#,
+        )
+        .unwrap();
+        assert!(matches!(config.keyper_key(), Err(NodeError::Config(_))));
+
+        config.test_keyper_key = true;
+        let (_, pk) = compute_keyper_keys();
+        assert_eq!(config.keyper_key().unwrap(), pk);
+
+        config.mpk = Some(encode_as_bs58_str(&pk));
+        assert!(matches!(config.keyper_key(), Err(NodeError::Config(_))));
+
+        config.test_keyper_key = false;
+        assert_eq!(config.keyper_key().unwrap(), pk);
+
+        config.mpk = Some(String::from("not a key"));
+        assert!(matches!(config.keyper_key(), Err(NodeError::Config(_))));
+    }
+
+    #[test]
+    fn test_nodes_play_a_scripted_hand() {
+        let params = ProtocolParams::new(16, 10).unwrap();
+        let script = "shuffle\ndeal 0\ndeal 1\ndeal 0\ndeal 1\nreveal 10,11\n\
+                      deal 5\nfold\nshowdown\nquit\nshuffle\n";
+        let outputs = run_script(params, &[script; 3], &[false; 3]);
+
+        // everything the parties output is public, so they all output the same
+        assert!(outputs.iter().all(|output| *output == outputs[0]));
+        let events = events(&outputs[0]);
+        let kinds = events
+            .iter()
+            .map(|event| event["event"].as_str().unwrap())
+            .collect::<Vec<&str>>();
+        assert_eq!(
+            kinds,
+            [
+                "shuffled", "dealt", "dealt", "dealt", "dealt", "revealed", "error", "error",
+                "showdown", "quit"
+            ]
+        );
+
+        let pp = compute_params();
+        let ctx = GameContext::from_label("node-test");
+        let bytes = |value: &Value| bs58::decode(value.as_str().unwrap()).into_vec().unwrap();
+        let proof = PermutationProof::from_bytes(&bytes(&events[0]["proof"])).unwrap();
+        let commitment: G1 = decode_from_bytes(&bytes(&events[0]["commitment"])).unwrap();
+        assert!(proof.f_com == commitment && proof.verify(&pp, &params));
+
+        // the padding takes positions 0..6, so dealing starts at 6
+        for (i, event) in events[1..5].iter().enumerate() {
+            assert_eq!(event["position"], 6 + i);
+            assert_eq!(event["player"], i % 2);
+            let dealt = DealtCard::from_bytes(&bytes(&event["card"])).unwrap();
+            assert_eq!(dealt.recipient, player_id(i % 2));
+            assert!(verify_dealt_card(&pp, &params, &ctx, &commitment, &dealt));
+        }
+
+        let revealed = events[5]["cards"].as_array().unwrap();
+        let shown = events[8]["cards"].as_array().unwrap();
+        assert_eq!(revealed.len(), 2);
+        assert_eq!(shown.len(), 4);
+        for (i, card) in shown.iter().enumerate() {
+            assert_eq!(card["position"], 6 + i);
+            assert_eq!(card["player"], i % 2);
+            assert_eq!(card["was_dealt"], true);
+        }
+        let cards = revealed
+            .iter()
+            .chain(shown)
+            .map(|card| card["card"].as_u64().unwrap())
+            .collect::<HashSet<u64>>();
+        assert_eq!(cards.len(), 6);
+        assert!(cards.iter().all(|card| *card < 10));
+    }
+
+    #[test]
+    fn test_interrupt_aborts_the_session() {
+        let params = ProtocolParams::new(16, 10).unwrap();
+        let outputs = run_script(params, &["shuffle"; 3], &[false, true, false]);
+
+        assert_eq!(
+            events(&outputs[1]),
+            [serde_json::json!({ "event": "aborted" })]
+        );
+        for output in [&outputs[0], &outputs[2]] {
+            let events = events(output);
+            assert_eq!(events[0]["event"], "error");
+            assert_eq!(
+                events[0]["message"],
+                "party 2 aborted the session: interrupted"
+            );
+        }
+    }
+}
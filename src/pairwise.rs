@@ -0,0 +1,198 @@
+//! Pairwise keys: every two parties of a committee agree on a key by
+//! Diffie-Hellman in G1, over ephemeral keys announced on the signed broadcast
+//! channel. Direct topics can be subscribed to by anyone, so the shares one party
+//! sends another over them are masked with a pad only the pair can derive; the
+//! signatures of the transport already tell who sent them.
+
+use ark_ec::Group;
+use ark_ff::{PrimeField, Zero};
+use ark_std::UniformRand;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use zeroize::Zeroize;
+
+use crate::common::CurveConfig;
+use crate::encoding::{decode_bs58_str_as, encode_as_bs58_str, encode_as_bytes, PayloadValue};
+use crate::evaluator::EvaluatorError;
+use crate::secret::zeroize_f;
+use crate::transport::Transport;
+
+/// PairwiseKeys holds the key we share with each other party of a committee
+#[derive(Clone)]
+pub struct PairwiseKeys {
+    my_id: u64,
+    /// the key shared with each other party, by node id
+    keys: BTreeMap<u64, [u8; 32]>,
+}
+
+impl PairwiseKeys {
+    pub fn new(my_id: u64, keys: BTreeMap<u64, [u8; 32]>) -> Self {
+        PairwiseKeys { my_id, keys }
+    }
+
+    /// broadcasts a fresh ephemeral key under handle, which must be the same at
+    /// every party and never used before, and derives the key of every pair from
+    /// the ephemeral keys of the others, in G1 of E
+    pub async fn setup<E: CurveConfig, T: Transport, R: RngCore>(
+        messaging: &mut T,
+        handle: &str,
+        rng: &mut R,
+    ) -> Result<Self, EvaluatorError> {
+        let my_id = messaging.get_my_id();
+        let mut secret = E::ScalarField::rand(rng);
+        let public = E::G1::generator() * secret;
+        messaging
+            .send_to_all(&[handle], &[encode_as_bs58_str(&public)])
+            .await?;
+        let announced = messaging.recv_from_all(handle).await?;
+
+        let mut keys = BTreeMap::new();
+        for (node_id, value) in announced {
+            let theirs = match decode_bs58_str_as::<E::G1>(&value) {
+                Ok(point) if !point.is_zero() => point,
+                _ => {
+                    zeroize_f(&mut secret);
+                    return Err(EvaluatorError::MalformedKey { node_id });
+                }
+            };
+            let shared = theirs * secret;
+            keys.insert(
+                node_id,
+                pair_key(messaging.session_id(), my_id, node_id, &shared),
+            );
+        }
+        zeroize_f(&mut secret);
+
+        Ok(PairwiseKeys::new(my_id, keys))
+    }
+
+    pub fn my_id(&self) -> u64 {
+        self.my_id
+    }
+
+    pub fn keys(&self) -> &BTreeMap<u64, [u8; 32]> {
+        &self.keys
+    }
+
+    /// a seed for purpose that only we and node_id can derive; None if we share
+    /// no key with node_id
+    pub fn derive(&self, node_id: u64, purpose: &str) -> Option<[u8; 32]> {
+        let key = self.keys.get(&node_id)?;
+        let mut hasher = Sha256::new();
+        hasher.update(b"pok3r-pairwise-derive");
+        hasher.update(key);
+        hasher.update(purpose.as_bytes());
+        Some(hasher.finalize().into())
+    }
+
+    /// value masked for recipient under handle; every handle must carry one value
+    /// per sender and recipient, as the pad is the same for all of them. None if
+    /// we share no key with recipient
+    pub fn mask<F: PrimeField>(&self, recipient: u64, handle: &str, value: &F) -> Option<F> {
+        Some(*value + self.pad::<F>(self.my_id, recipient, handle)?)
+    }
+
+    /// the value sender masked for us under handle; None if we share no key with
+    /// sender
+    pub fn unmask<F: PrimeField>(&self, sender: u64, handle: &str, masked: &F) -> Option<F> {
+        Some(*masked - self.pad::<F>(sender, self.my_id, handle)?)
+    }
+
+    /// drawn from a stream seeded by a hash of the key, bound to the direction so
+    /// that what two parties send each other under a handle gets distinct pads;
+    /// much cheaper than reducing a wide hash, and pads go with every share dealt
+    fn pad<F: PrimeField>(&self, sender: u64, recipient: u64, handle: &str) -> Option<F> {
+        let other = if sender == self.my_id {
+            recipient
+        } else {
+            sender
+        };
+        let mut hasher = Sha256::new();
+        hasher.update(b"pok3r-pairwise-pad");
+        hasher.update(self.keys.get(&other)?);
+        hasher.update(sender.to_le_bytes());
+        hasher.update(recipient.to_le_bytes());
+        hasher.update(handle.as_bytes());
+        let mut stream = ChaCha20Rng::from_seed(hasher.finalize().into());
+        Some(F::rand(&mut stream))
+    }
+}
+
+/// every pad and seed still to be derived depends on the keys
+impl Drop for PairwiseKeys {
+    fn drop(&mut self) {
+        self.keys.values_mut().for_each(Zeroize::zeroize);
+    }
+}
+
+/// the key of the pair of a and b, the same whichever of them derives it
+fn pair_key<G: PayloadValue>(session_id: &str, a: u64, b: u64, shared: &G) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(b"pok3r-pairwise");
+    hasher.update((session_id.len() as u64).to_le_bytes());
+    hasher.update(session_id.as_bytes());
+    hasher.update(a.min(b).to_le_bytes());
+    hasher.update(a.max(b).to_le_bytes());
+    let mut shared = encode_as_bytes(shared);
+    hasher.update(&shared);
+    shared.zeroize();
+    hasher.finalize().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PairwiseKeys;
+    use crate::common::{Curve, F};
+    use crate::transport::InMemoryTransport;
+    use futures::future::join_all;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn setup(n: usize) -> Vec<PairwiseKeys> {
+        async_std::task::block_on(async {
+            let transports = InMemoryTransport::new_network(n).await;
+            join_all(
+                transports
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, mut transport)| async move {
+                        let mut rng = StdRng::from_seed([i as u8; 32]);
+                        PairwiseKeys::setup::<Curve, _, _>(&mut transport, "pairwise", &mut rng)
+                            .await
+                            .unwrap()
+                    }),
+            )
+            .await
+        })
+    }
+
+    #[test]
+    fn test_pairs_agree_on_distinct_keys() {
+        let keys = setup(3);
+        assert_eq!(keys[0].keys()[&2], keys[1].keys()[&1]);
+        assert_eq!(keys[0].keys()[&3], keys[2].keys()[&1]);
+        assert_eq!(keys[1].keys()[&3], keys[2].keys()[&2]);
+        assert_ne!(keys[0].keys()[&2], keys[0].keys()[&3]);
+        assert_eq!(keys[0].derive(2, "prss"), keys[1].derive(1, "prss"));
+        assert_ne!(keys[0].derive(2, "prss"), keys[0].derive(2, "other"));
+        assert_eq!(keys[0].derive(1, "prss"), None);
+    }
+
+    #[test]
+    fn test_masked_values_unmask_only_for_their_recipient_and_handle() {
+        let keys = setup(3);
+        let value = F::from(42u64);
+        let masked = keys[0].mask(2, "x", &value).unwrap();
+        assert_ne!(masked, value);
+
+        assert_eq!(keys[1].unmask(1, "x", &masked), Some(value));
+        // the third party, another handle, another claimed sender, or the other
+        // direction get something else
+        assert_ne!(keys[2].unmask(1, "x", &masked), Some(value));
+        assert_ne!(keys[1].unmask(1, "y", &masked), Some(value));
+        assert_ne!(keys[1].unmask(3, "x", &masked), Some(value));
+        assert_ne!(keys[1].mask(1, "x", &value), Some(masked));
+        assert_eq!(keys[0].mask(1, "x", &value), None);
+    }
+}
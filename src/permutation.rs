@@ -0,0 +1,262 @@
+//! Secret-shared random permutations, the core of the shuffle, as a gadget of its
+//! own. A permutation σ of n elements is shared as the wires ω^σ(0), ..,
+//! ω^σ(n-1), where ω generates the domain of the n.next_power_of_two() roots of
+//! unity; applying it to shared values moves the value at j to every i with
+//! σ(i) = j.
+
+use ark_poly::EvaluationDomain;
+use ark_std::One;
+
+use crate::circuit::CircuitBuilder;
+use crate::common::{CurveConfig, ProtocolParams, ResourceEstimate};
+use crate::evaluator::{Evaluator, EvaluatorError};
+use crate::shuffler::{sample_deck, shuffle_resources};
+use crate::transport::Transport;
+use crate::utils;
+use crate::wire::Wire;
+
+/// the params sample_deck draws a permutation of n elements for: the padding of
+/// the domain past n takes the first positions, and is dropped
+fn permutation_params(n: usize) -> ProtocolParams {
+    ProtocolParams::new(n.next_power_of_two(), n).expect("a permutation has at least 2 elements")
+}
+
+/// whether a permutation of n elements fits an evaluator whose domain has max roots
+fn fits(n: usize, max: usize) -> bool {
+    (2..=max).contains(&n)
+}
+
+/// preprocessing consumed by random_shared_permutation for n elements, on an
+/// evaluator for params
+pub fn random_permutation_resources(params: &ProtocolParams, n: usize) -> ResourceEstimate {
+    let sampled = permutation_params(n);
+    let squarings = params.log_perm_size() - sampled.log_perm_size();
+    let extra = ResourceEstimate {
+        // the exponentiations of batch_ran_64 are in the evaluator's domain, and
+        // its roots are squared down to ours
+        triples: 2 * squarings * sampled.num_samples(),
+        rand_sharings: 0,
+    };
+    shuffle_resources(&sampled) + extra
+}
+
+/// preprocessing consumed by apply_shared_permutation, for a permutation of n
+/// elements and m values
+pub fn apply_permutation_resources(n: usize, m: usize) -> ResourceEstimate {
+    ResourceEstimate {
+        // the powers of each element below the size of the domain, and their
+        // products with the values
+        triples: n * (n.next_power_of_two() - 2) + n * m,
+        rand_sharings: 0,
+    }
+}
+
+/// returns handles to a uniformly random permutation of the first n roots of the
+/// domain of n.next_power_of_two(), known to nobody; n must be at least 2 and fit
+/// the domain of the evaluator. The intermediates are freed.
+pub async fn random_shared_permutation<E: CurveConfig, C: CircuitBuilder<E>>(
+    evaluator: &mut C,
+    n: usize,
+) -> Result<Vec<Wire>, EvaluatorError> {
+    let max = evaluator.params().perm_size();
+    if !fits(n, max) {
+        return Err(EvaluatorError::BadPermutationSize { size: n, max });
+    }
+    let params = permutation_params(n);
+    evaluator.assert_resources_remaining(&random_permutation_resources(evaluator.params(), n))?;
+    evaluator.begin_scope();
+    let result = sample_deck(evaluator, &params)
+        .await
+        .map(|mut wires| wires.split_off(params.perm_size() - n));
+    if let Ok(perm) = &result {
+        perm.iter().for_each(|h| evaluator.keep(h));
+    }
+    evaluator.end_scope();
+    result
+}
+
+/// permutes values under perm, a permutation as random_shared_permutation returns
+/// it: output i gets values[j] for perm[i] = ω^j, and zero when j is past the
+/// values, which must be at least one and no more than perm. The intermediates are
+/// freed.
+pub async fn apply_shared_permutation<T: Transport, E: CurveConfig>(
+    evaluator: &mut Evaluator<T, E>,
+    perm: &[Wire],
+    values: &[Wire],
+) -> Result<Vec<Wire>, EvaluatorError> {
+    let max = evaluator.params().perm_size();
+    if !fits(perm.len(), max) {
+        return Err(EvaluatorError::BadPermutationSize {
+            size: perm.len(),
+            max,
+        });
+    }
+    if values.is_empty() || values.len() > perm.len() {
+        return Err(EvaluatorError::LengthMismatch {
+            expected: perm.len(),
+            actual: values.len(),
+        });
+    }
+    evaluator.assert_resources_remaining(&apply_permutation_resources(perm.len(), values.len()))?;
+
+    evaluator.begin_scope();
+    let result = apply_in_scope(evaluator, perm, values).await;
+    if let Ok(permuted) = &result {
+        permuted.iter().for_each(|h| evaluator.keep(h));
+    }
+    evaluator.end_scope();
+    result
+}
+
+async fn apply_in_scope<T: Transport, E: CurveConfig>(
+    evaluator: &mut Evaluator<T, E>,
+    perm: &[Wire],
+    values: &[Wire],
+) -> Result<Vec<Wire>, EvaluatorError> {
+    let size = perm.len().next_power_of_two();
+
+    // σ_i^k for k < size, a round per doubling: σ_i^k = σ_i^⌈k/2⌉ σ_i^⌊k/2⌋
+    let one = evaluator.fixed_wire_handle(E::ScalarField::one());
+    let mut powers = vec![vec![one; perm.len()], perm.to_vec()];
+    while powers.len() < size {
+        let known = powers.len();
+        let (mut xs, mut ys) = (vec![], vec![]);
+        for k in known..size.min(2 * known - 1) {
+            xs.extend_from_slice(&powers[k - k / 2]);
+            ys.extend_from_slice(&powers[k / 2]);
+        }
+        let products = evaluator.batch_mult(&xs, &ys).await?;
+        powers.extend(products.chunks(perm.len()).map(|power| power.to_vec()));
+    }
+
+    // L_j(σ_i) = 1/size sum_k ω^-jk σ_i^k is the inverse FFT of the powers, and
+    // output i is sum_j L_j(σ_i) values_j
+    let domain = &utils::domain_info_of::<E::ScalarField>(size).domain;
+    let shares = values
+        .iter()
+        .map(|value| evaluator.get_wire(value))
+        .collect::<Vec<E::ScalarField>>();
+    let mut xs = Vec::with_capacity(perm.len() * values.len());
+    let mut ys = Vec::with_capacity(perm.len() * values.len());
+    for i in 0..perm.len() {
+        let powers_i = powers
+            .iter()
+            .map(|power| evaluator.get_wire(&power[i]))
+            .collect::<Vec<E::ScalarField>>();
+        let lagrange_i = domain.ifft(&powers_i);
+        xs.extend_from_slice(&shares);
+        ys.extend_from_slice(&lagrange_i[..values.len()]);
+    }
+    let products = evaluator.batch_mult_shares(&xs, &ys).await?;
+    Ok(products
+        .chunks(values.len())
+        .map(|terms| {
+            let share: E::ScalarField = terms.iter().sum();
+            evaluator.wire_from_share(share)
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        apply_permutation_resources, apply_shared_permutation, random_permutation_resources,
+        random_shared_permutation,
+    };
+    use crate::common::{ProtocolParams, F};
+    use crate::evaluator::{Evaluator, EvaluatorError};
+    use crate::transport::InMemoryTransport;
+    use crate::utils;
+    use crate::wire::Wire;
+    use futures::future::{join_all, try_join_all};
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_shared_permutation_is_a_bijection_and_applies() {
+        let params = ProtocolParams::new(16, 10).unwrap();
+        for n in [4usize, 11, 16] {
+            let resources =
+                random_permutation_resources(&params, n) + apply_permutation_resources(n, n);
+            let outputs = async_std::task::block_on(async {
+                let transports = InMemoryTransport::new_network(3).await;
+                let evaluators = try_join_all(
+                    transports
+                        .into_iter()
+                        .map(|transport| Evaluator::new_for(transport, params, resources, 0)),
+                )
+                .await
+                .unwrap();
+
+                join_all(evaluators.into_iter().map(|mut evaluator| async move {
+                    let perm = random_shared_permutation(&mut evaluator, n).await.unwrap();
+                    let values = (0..n as u64)
+                        .map(|j| evaluator.fixed_wire_handle(F::from(j)))
+                        .collect::<Vec<Wire>>();
+                    let permuted = apply_shared_permutation(&mut evaluator, &perm, &values)
+                        .await
+                        .unwrap();
+                    let opened_perm = evaluator.batch_output_wire(&perm).await.unwrap();
+                    let opened = evaluator.batch_output_wire(&permuted).await.unwrap();
+                    (opened_perm, opened)
+                }))
+                .await
+            });
+
+            let powers = &utils::domain_info(n.next_power_of_two()).powers;
+            for (perm, permuted) in outputs.iter() {
+                assert_eq!(perm, &outputs[0].0);
+                let indices = perm
+                    .iter()
+                    .map(|root| powers[..n].iter().position(|power| power == root).unwrap())
+                    .collect::<HashSet<usize>>();
+                assert_eq!(indices.len(), n);
+
+                // applied to 0..n, the permutation opens to the indices of its roots
+                assert_eq!(permuted.len(), n);
+                for (root, value) in perm.iter().zip(permuted) {
+                    let j = powers.iter().position(|power| power == root).unwrap();
+                    assert_eq!(*value, F::from(j as u64));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_permutation_size_must_fit_the_domain() {
+        let params = ProtocolParams::new(16, 10).unwrap();
+        async_std::task::block_on(async {
+            let transports = InMemoryTransport::new_network(3).await;
+            let resources = random_permutation_resources(&params, 2);
+            let outputs = join_all(transports.into_iter().map(|transport| async move {
+                let mut evaluator = Evaluator::new_for(transport, params, resources, 0)
+                    .await
+                    .unwrap();
+                let too_large = random_shared_permutation(&mut evaluator, 17).await;
+                let too_small = random_shared_permutation(&mut evaluator, 1).await;
+                let perm = vec![evaluator.fixed_wire_handle(F::from(1u64)); 2];
+                let values = vec![evaluator.fixed_wire_handle(F::from(1u64)); 3];
+                let too_many = apply_shared_permutation(&mut evaluator, &perm, &values).await;
+                (too_large.err(), too_small.err(), too_many.err())
+            }))
+            .await;
+
+            for (too_large, too_small, too_many) in outputs {
+                assert_eq!(
+                    too_large,
+                    Some(EvaluatorError::BadPermutationSize { size: 17, max: 16 })
+                );
+                assert_eq!(
+                    too_small,
+                    Some(EvaluatorError::BadPermutationSize { size: 1, max: 16 })
+                );
+                assert_eq!(
+                    too_many,
+                    Some(EvaluatorError::LengthMismatch {
+                        expected: 2,
+                        actual: 3
+                    })
+                );
+            }
+        });
+    }
+}
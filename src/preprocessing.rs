@@ -0,0 +1,531 @@
+//! Checks on preprocessing material that does not come with its own guarantees,
+//! e.g. triples read from a file or handed out by a dealer, and the file format
+//! used to generate preprocessing ahead of time.
+
+use ark_ff::PrimeField;
+use ark_serialize::{CanonicalDeserialize, CanonicalSerialize, SerializationError};
+use ark_std::Zero;
+use rand::Rng;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::{error, fmt};
+
+use crate::common::{Curve, CurveConfig, ProtocolParams, ProtocolParamsError};
+use crate::evaluator::{Evaluator, EvaluatorError};
+use crate::network::NetworkError;
+use crate::secret::Scrub;
+use crate::transcript::Transcript;
+use crate::transport::Transport;
+
+/// deals count random triples as a trusted dealer would: returns, for each of the
+/// n parties, its additive shares of every triple
+pub fn deal_triples<F: PrimeField, R: Rng>(
+    n: usize,
+    count: usize,
+    rng: &mut R,
+) -> Vec<Vec<(F, F, F)>> {
+    let mut shares = vec![Vec::with_capacity(count); n];
+    for _ in 0..count {
+        let (a, b) = (F::rand(rng), F::rand(rng));
+        let mut sums = (F::zero(), F::zero(), F::zero());
+        for party in shares.iter_mut().skip(1) {
+            let share = (F::rand(rng), F::rand(rng), F::rand(rng));
+            sums = (sums.0 + share.0, sums.1 + share.1, sums.2 + share.2);
+            party.push(share);
+        }
+        shares[0].push((a - sums.0, b - sums.1, a * b - sums.2));
+    }
+    shares
+}
+
+/// checks that our shares of triples, together with those of the other parties, are
+/// triples with c = a.b, by sacrificing every second triple: for each pair (a, b, c),
+/// (a', b', c') and a public challenge r, parties open ρ = r.a - a' and σ = b - b',
+/// and then r.c - c' - σ.a' - ρ.b' - σ.ρ, which is zero for good triples and nonzero
+/// except with probability 1/|F| otherwise. Returns the first triple of every pair;
+/// fails with the index of the first pair that does not pass.
+///
+/// The challenges come from a coin tossed jointly once the triples are fixed: a
+/// dealer that knew them could offset c' by r times its offset of c, and pass.
+pub async fn verify_triples<T: Transport, E: CurveConfig>(
+    evaluator: &mut Evaluator<T, E>,
+    triples: &[(E::ScalarField, E::ScalarField, E::ScalarField)],
+) -> Result<Vec<(E::ScalarField, E::ScalarField, E::ScalarField)>, EvaluatorError> {
+    assert!(triples.len() % 2 == 0, "triples are sacrificed in pairs");
+    let pairs: Vec<_> = triples.chunks(2).map(|pair| (&pair[0], &pair[1])).collect();
+
+    let coins = evaluator.joint_coin().await?;
+    let mut transcript = Transcript::new(b"pok3r-sacrifice");
+    transcript.append_bytes(b"session", evaluator.session_id().as_bytes());
+    transcript.append_bytes(b"pairs", &(pairs.len() as u64).to_le_bytes());
+    for (node_id, coin) in coins.iter() {
+        transcript.append_bytes(b"coin", &node_id.to_le_bytes());
+        transcript.append_bytes(b"coin", coin);
+    }
+    let rs = transcript.challenge_fs_on::<E>(b"r", pairs.len());
+
+    // ρ and σ of every pair, interleaved
+    let masked = pairs
+        .iter()
+        .zip(rs.iter())
+        .flat_map(|(((a, b, _), (a_, b_, _)), r)| [*r * a - a_, *b - b_])
+        .collect::<Vec<E::ScalarField>>();
+    let opened = evaluator.open_shares(&masked).await?;
+
+    let adds_constants = evaluator.adds_constants();
+    let checks = pairs
+        .iter()
+        .zip(rs.iter())
+        .zip(opened.chunks(2))
+        .map(|((((_, _, c), (a_, b_, c_)), r), rho_sigma)| {
+            let (rho, sigma) = (rho_sigma[0], rho_sigma[1]);
+            let check = *r * c - c_ - sigma * a_ - rho * b_;
+            if adds_constants {
+                check - sigma * rho
+            } else {
+                check
+            }
+        })
+        .collect::<Vec<E::ScalarField>>();
+    let opened = evaluator.open_shares(&checks).await?;
+
+    match opened.iter().position(|check| !check.is_zero()) {
+        Some(index) => Err(EvaluatorError::TripleCheckFailed { index }),
+        None => Ok(pairs.into_iter().map(|(triple, _)| *triple).collect()),
+    }
+}
+
+/// version of the layout written by PreprocessingFile::write
+const FORMAT_VERSION: u32 = 2;
+
+/// PreprocessingError describes why preprocessing could not be exported or loaded
+#[derive(Debug)]
+pub enum PreprocessingError {
+    Io(io::Error),
+    Serialization(SerializationError),
+    /// the file was written in another layout
+    UnsupportedVersion(u32),
+    /// the file holds shares over the scalar field of another curve
+    WrongCurve {
+        expected: u8,
+        found: u8,
+    },
+    /// the file was loaded before, so its material may already have been used
+    Consumed,
+    /// the file holds the shares of another party
+    WrongParty {
+        expected: u64,
+        found: u64,
+    },
+    /// the file was made for a committee of another size
+    WrongCommitteeSize {
+        expected: usize,
+        found: usize,
+    },
+    /// the file was made for another session
+    WrongSession {
+        expected: String,
+        found: String,
+    },
+    /// the deck and permutation sizes in the file are not valid params
+    Params(ProtocolParamsError),
+    /// the file was made for other deck and permutation sizes
+    WrongParams {
+        expected: ProtocolParams,
+        found: ProtocolParams,
+    },
+    /// the handshake with the other parties failed
+    Network(NetworkError),
+}
+
+impl fmt::Display for PreprocessingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PreprocessingError::Io(err) => {
+                write!(f, "cannot access the preprocessing file: {}", err)
+            }
+            PreprocessingError::Serialization(err) => {
+                write!(f, "cannot decode the preprocessing: {}", err)
+            }
+            PreprocessingError::UnsupportedVersion(version) => {
+                write!(f, "unsupported preprocessing format version {}", version)
+            }
+            PreprocessingError::WrongCurve { expected, found } => write!(
+                f,
+                "preprocessing on curve {} cannot be used on curve {}",
+                found, expected
+            ),
+            PreprocessingError::Consumed => {
+                write!(f, "the preprocessing file was already consumed")
+            }
+            PreprocessingError::WrongParty { expected, found } => write!(
+                f,
+                "preprocessing of party {} cannot be used by party {}",
+                found, expected
+            ),
+            PreprocessingError::WrongCommitteeSize { expected, found } => write!(
+                f,
+                "preprocessing for {} parties cannot be used by {} parties",
+                found, expected
+            ),
+            PreprocessingError::WrongSession { expected, found } => write!(
+                f,
+                "preprocessing of session {} cannot be used in session {}",
+                found, expected
+            ),
+            PreprocessingError::Params(err) => write!(f, "bad params in preprocessing: {}", err),
+            PreprocessingError::WrongParams { expected, found } => write!(
+                f,
+                "preprocessing for {} cards permuted over {} cannot be used for {} over {}",
+                found.deck_size(),
+                found.perm_size(),
+                expected.deck_size(),
+                expected.perm_size()
+            ),
+            PreprocessingError::Network(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl error::Error for PreprocessingError {}
+
+impl From<io::Error> for PreprocessingError {
+    fn from(err: io::Error) -> Self {
+        PreprocessingError::Io(err)
+    }
+}
+
+impl From<SerializationError> for PreprocessingError {
+    fn from(err: SerializationError) -> Self {
+        PreprocessingError::Serialization(err)
+    }
+}
+
+impl From<ProtocolParamsError> for PreprocessingError {
+    fn from(err: ProtocolParamsError) -> Self {
+        PreprocessingError::Params(err)
+    }
+}
+
+impl From<NetworkError> for PreprocessingError {
+    fn from(err: NetworkError) -> Self {
+        PreprocessingError::Network(err)
+    }
+}
+
+/// the preprocessing of one party, as written after the format version and the
+/// curve id
+#[derive(CanonicalSerialize, CanonicalDeserialize)]
+pub(crate) struct PreprocessingFile<E: CurveConfig = Curve> {
+    /// the watermark set on loading, after which the file holds no material
+    pub consumed: bool,
+    pub node_id: u64,
+    pub num_parties: u64,
+    pub session_id: Vec<u8>,
+    pub threshold: Option<u64>,
+    pub perm_size: u64,
+    pub deck_size: u64,
+    /// how many triples and random sharings the exporting evaluator had used,
+    /// for bookkeeping only: the file holds just the ones after those
+    pub triples_consumed: u64,
+    pub rand_sharings_consumed: u64,
+    pub beaver_triples: Vec<(E::ScalarField, E::ScalarField, E::ScalarField)>,
+    pub rand_sharings: Vec<E::ScalarField>,
+}
+
+/// the beaver triples and random sharings a file holds
+type Material<F> = (Vec<(F, F, F)>, Vec<F>);
+
+impl<E: CurveConfig> PreprocessingFile<E> {
+    pub(crate) fn write(&self, path: &Path) -> Result<(), PreprocessingError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        FORMAT_VERSION.serialize_compressed(&mut writer)?;
+        E::CURVE_ID.serialize_compressed(&mut writer)?;
+        self.serialize_compressed(&mut writer)?;
+        writer.flush()?;
+        Ok(())
+    }
+
+    /// reads a file written by write, refusing one that was already consumed
+    pub(crate) fn read(path: &Path) -> Result<Self, PreprocessingError> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let version = u32::deserialize_compressed(&mut reader)?;
+        if version != FORMAT_VERSION {
+            return Err(PreprocessingError::UnsupportedVersion(version));
+        }
+        let curve_id = u8::deserialize_compressed(&mut reader)?;
+        if curve_id != E::CURVE_ID {
+            return Err(PreprocessingError::WrongCurve {
+                expected: E::CURVE_ID,
+                found: curve_id,
+            });
+        }
+        let file = Self::deserialize_compressed(&mut reader)?;
+        if file.consumed {
+            return Err(PreprocessingError::Consumed);
+        }
+        Ok(file)
+    }
+
+    /// checks that the file was made for the party node_id of a committee of
+    /// num_parties in session_id
+    pub(crate) fn check_binding(
+        &self,
+        node_id: u64,
+        num_parties: usize,
+        session_id: &str,
+    ) -> Result<(), PreprocessingError> {
+        if self.node_id != node_id {
+            return Err(PreprocessingError::WrongParty {
+                expected: node_id,
+                found: self.node_id,
+            });
+        }
+        if self.num_parties as usize != num_parties {
+            return Err(PreprocessingError::WrongCommitteeSize {
+                expected: num_parties,
+                found: self.num_parties as usize,
+            });
+        }
+        if self.session_id != session_id.as_bytes() {
+            return Err(PreprocessingError::WrongSession {
+                expected: session_id.to_string(),
+                found: String::from_utf8_lossy(&self.session_id).into_owned(),
+            });
+        }
+        Ok(())
+    }
+
+    /// takes the material out and writes the file back marked as consumed, before
+    /// any of it is used, so that the same triples are never loaded twice
+    pub(crate) fn consume(
+        &mut self,
+        path: &Path,
+    ) -> Result<Material<E::ScalarField>, PreprocessingError> {
+        let beaver_triples = std::mem::take(&mut self.beaver_triples);
+        let rand_sharings = std::mem::take(&mut self.rand_sharings);
+        self.consumed = true;
+        self.write(path)?;
+        Ok((beaver_triples, rand_sharings))
+    }
+}
+
+impl<E: CurveConfig> Drop for PreprocessingFile<E> {
+    fn drop(&mut self) {
+        self.beaver_triples.scrub();
+        self.rand_sharings.scrub();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{deal_triples, verify_triples, PreprocessingError, PreprocessingFile};
+    use crate::common::{Curve, ProtocolParams, ResourceEstimate, F};
+    use crate::evaluator::{Evaluator, EvaluatorError};
+    use crate::simulator::build_parties;
+    use crate::transcript::Transcript;
+    use crate::transport::InMemoryTransport;
+    use futures::future::join_all;
+    use rand::{rngs::StdRng, SeedableRng};
+    use std::path::PathBuf;
+
+    /// the evaluators of n parties, with as many triples and random sharings
+    async fn parties_with_triples(n: usize, triples: usize) -> Vec<Evaluator<InMemoryTransport>> {
+        let resources = ResourceEstimate {
+            triples,
+            rand_sharings: triples,
+        };
+        build_parties(n, |transport| {
+            Evaluator::new_for(transport, ProtocolParams::default(), resources, 0)
+        })
+        .await
+    }
+
+    /// what verify_triples returns at every party
+    type Sacrificed = Vec<Result<Vec<(F, F, F)>, EvaluatorError>>;
+
+    fn sacrifice(shares: Vec<Vec<(F, F, F)>>) -> Sacrificed {
+        sacrifice_with(shares, |_, _| ())
+    }
+
+    /// sacrifice, once corrupt has changed the shares knowing the session id
+    fn sacrifice_with(
+        mut shares: Vec<Vec<(F, F, F)>>,
+        corrupt: impl FnOnce(&str, &mut Vec<Vec<(F, F, F)>>),
+    ) -> Sacrificed {
+        async_std::task::block_on(async {
+            let evaluators = parties_with_triples(shares.len(), 0).await;
+            corrupt(evaluators[0].session_id(), &mut shares);
+
+            join_all(
+                evaluators
+                    .into_iter()
+                    .zip(shares)
+                    .map(|(mut evaluator, triples)| async move {
+                        verify_triples(&mut evaluator, &triples).await
+                    }),
+            )
+            .await
+        })
+    }
+
+    #[test]
+    fn test_honest_triples_pass() {
+        let shares = deal_triples(3, 16, &mut StdRng::from_seed([3u8; 32]));
+        let kept = sacrifice(shares.clone());
+        for (kept, dealt) in kept.into_iter().zip(shares) {
+            let kept = kept.unwrap();
+            assert_eq!(kept.len(), 8);
+            assert_eq!(kept[3], dealt[6]);
+        }
+    }
+
+    #[test]
+    fn test_corrupt_triple_is_detected() {
+        let mut shares = deal_triples(3, 16, &mut StdRng::from_seed([3u8; 32]));
+        shares[1][9].2 += F::from(1u64);
+        for result in sacrifice(shares) {
+            assert_eq!(result, Err(EvaluatorError::TripleCheckFailed { index: 4 }));
+        }
+    }
+
+    #[test]
+    fn test_dealer_cannot_offset_a_pair_by_a_predicted_challenge() {
+        let shares = deal_triples(3, 16, &mut StdRng::from_seed([3u8; 32]));
+        let results = sacrifice_with(shares, |session_id, shares| {
+            // the challenges as anyone could compute them before the triples were
+            // checked; offsetting c by e and c' by r.e cancels out in r.c - c'
+            let mut transcript = Transcript::new(b"pok3r-sacrifice");
+            transcript.append_bytes(b"session", session_id.as_bytes());
+            transcript.append_bytes(b"pairs", &8u64.to_le_bytes());
+            let r = transcript.challenge_fs(b"r", 8)[2];
+            let e = F::from(5u64);
+            shares[1][4].2 += e;
+            shares[1][5].2 += r * e;
+        });
+        for result in results {
+            assert_eq!(result, Err(EvaluatorError::TripleCheckFailed { index: 2 }));
+        }
+    }
+
+    #[test]
+    fn test_exported_preprocessing_round_trips() {
+        async_std::task::block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let paths: Vec<PathBuf> = (1..=3)
+                .map(|i| dir.path().join(format!("preprocessing-{}", i)))
+                .collect();
+            let mut exporters = parties_with_triples(3, 4).await;
+            for (evaluator, path) in exporters.iter_mut().zip(paths.iter()) {
+                evaluator.export_preprocessing(path).unwrap();
+                assert_eq!(evaluator.remaining(), ResourceEstimate::default());
+            }
+
+            let transports = InMemoryTransport::new_network(3).await;
+            let evaluators = join_all(
+                transports
+                    .into_iter()
+                    .zip(paths.iter())
+                    .map(|(transport, path)| {
+                        Evaluator::<_>::new_from_preprocessing(transport, path)
+                    }),
+            )
+            .await;
+            let outputs = join_all(evaluators.into_iter().map(|evaluator| async move {
+                let mut evaluator = evaluator.unwrap();
+                assert_eq!(evaluator.remaining().triples, 4);
+                let x = evaluator.fixed_wire_handle(F::from(6u64));
+                let y = evaluator.fixed_wire_handle(F::from(7u64));
+                let z = evaluator.mult(&x, &y).await.unwrap();
+                evaluator.output_wire(&z).await.unwrap()
+            }))
+            .await;
+            assert!(outputs.into_iter().all(|z| z == F::from(42u64)));
+
+            // a second load would reuse the triples
+            let transport = InMemoryTransport::new_network(3).await.remove(0);
+            let result = Evaluator::<_>::new_from_preprocessing(transport, &paths[0]).await;
+            assert!(matches!(result, Err(PreprocessingError::Consumed)));
+        });
+    }
+
+    #[cfg(all(feature = "bls12_381", feature = "bls12_377"))]
+    #[test]
+    fn test_preprocessing_of_another_curve_is_refused() {
+        use crate::common::CurveConfig;
+        use crate::evaluator::EvaluatorBuilder;
+        use ark_bls12_377::Bls12_377;
+        use ark_bls12_381::Bls12_381;
+        use futures::future::try_join_all;
+
+        async_std::task::block_on(async {
+            let transports = InMemoryTransport::new_network(3).await;
+            let mut evaluators = try_join_all(transports.into_iter().map(|transport| {
+                EvaluatorBuilder::new()
+                    .preprocessing(ResourceEstimate {
+                        triples: 2,
+                        rand_sharings: 2,
+                    })
+                    .build_on::<Bls12_377, _>(transport)
+            }))
+            .await
+            .unwrap();
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("preprocessing-377");
+            evaluators[0].export_preprocessing(&path).unwrap();
+
+            let transport = InMemoryTransport::new_network(3).await.remove(0);
+            let result = Evaluator::<_, Bls12_381>::new_from_preprocessing(transport, &path).await;
+            assert!(matches!(
+                result,
+                Err(PreprocessingError::WrongCurve { expected, found })
+                    if expected == Bls12_381::CURVE_ID && found == Bls12_377::CURVE_ID
+            ));
+            assert!(PreprocessingFile::<Bls12_377>::read(&path).is_ok());
+        });
+    }
+
+    #[test]
+    fn test_preprocessing_of_another_committee_size_is_rejected() {
+        async_std::task::block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("preprocessing-n4");
+            let mut evaluators = parties_with_triples(4, 2).await;
+            evaluators[0].export_preprocessing(&path).unwrap();
+
+            let transport = InMemoryTransport::new_network(5).await.remove(0);
+            let result = Evaluator::<_>::new_from_preprocessing(transport, &path).await;
+            assert!(matches!(
+                result,
+                Err(PreprocessingError::WrongCommitteeSize {
+                    expected: 5,
+                    found: 4
+                })
+            ));
+            // rejecting the file does not consume it
+            assert!(PreprocessingFile::<Curve>::read(&path).is_ok());
+        });
+    }
+
+    #[test]
+    fn test_preprocessing_for_another_deck_is_rejected_before_consuming() {
+        async_std::task::block_on(async {
+            let dir = tempfile::tempdir().unwrap();
+            let path = dir.path().join("preprocessing-default");
+            let mut evaluators = parties_with_triples(3, 2).await;
+            evaluators[0].export_preprocessing(&path).unwrap();
+
+            let expected = ProtocolParams::new(16, 10).unwrap();
+            let transport = InMemoryTransport::new_network(3).await.remove(0);
+            let result =
+                Evaluator::<_>::new_from_preprocessing_for(transport, &path, expected).await;
+            assert!(matches!(
+                result,
+                Err(PreprocessingError::WrongParams { expected: e, found })
+                    if e == expected && found == ProtocolParams::default()
+            ));
+            assert!(PreprocessingFile::<Curve>::read(&path).is_ok());
+        });
+    }
+}
@@ -0,0 +1,781 @@
+//! Checks of the proofs the shuffler publishes. They need nothing but the public
+//! params and the proofs, so they build without the networking feature, as for
+//! verifying shuffles in a browser, and involve no clock, thread or ambient RNG.
+
+use ark_ec::pairing::PairingOutput;
+use ark_ec::{AffineRepr, CurveGroup, Group};
+use ark_ff::Field;
+use ark_poly::{univariate::DensePolynomial, DenseUVPolynomial, Polynomial};
+use ark_serialize::CanonicalSerialize;
+use ark_std::{One, Zero};
+use num_bigint::BigUint;
+use std::ops::{Add, Mul};
+use std::{error, fmt};
+
+use crate::common::{
+    Ciphertext, CurveConfig, DealtCard, EncryptionProof, KzgOn, PermutationProof,
+    PermutationProofV2, ProtocolParams,
+};
+use crate::hash::{hash_to_g1_ctx_on, player_card_id, GameContext};
+use crate::kzg::{KzgError, UniversalParams};
+use crate::transcript::{FsHasher, Transcript};
+use crate::utils;
+
+/// ProofError describes which check of a proof failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProofError {
+    /// the params are too small to recompute the public commitments
+    Kzg(KzgError),
+    /// the statement holds actual values where the params call for expected
+    WrongSize { expected: usize, actual: usize },
+    /// the opening proof of this evaluation does not verify
+    Opening(&'static str),
+    /// the opened values do not satisfy this relation
+    Constraint(&'static str),
+    /// the encryption proof carries no sigma proof
+    MissingSigmaProof,
+    /// the card is not opened against the commitment of the deck
+    CommitmentMismatch,
+    /// the position is not one of the permutation
+    PositionOutOfRange { position: u64, perm_size: usize },
+}
+
+impl fmt::Display for ProofError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProofError::Kzg(err) => write!(f, "cannot recompute the commitments: {}", err),
+            ProofError::WrongSize { expected, actual } => {
+                write!(
+                    f,
+                    "expected {} values, the proof holds {}",
+                    expected, actual
+                )
+            }
+            ProofError::Opening(what) => write!(f, "the opening of {} does not verify", what),
+            ProofError::Constraint(what) => write!(f, "the check of {} fails", what),
+            ProofError::MissingSigmaProof => write!(f, "the sigma proof is missing"),
+            ProofError::CommitmentMismatch => {
+                write!(f, "the card is opened against another commitment")
+            }
+            ProofError::PositionOutOfRange {
+                position,
+                perm_size,
+            } => write!(
+                f,
+                "position {} is out of a permutation of {}",
+                position, perm_size
+            ),
+        }
+    }
+}
+
+impl error::Error for ProofError {}
+
+impl From<KzgError> for ProofError {
+    fn from(err: KzgError) -> Self {
+        ProofError::Kzg(err)
+    }
+}
+
+impl<E: CurveConfig> PermutationProof<E> {
+    /// checks that f_com commits to a permutation of params.perm_size() cards
+    pub fn verify(&self, pp: &UniversalParams<E>, params: &ProtocolParams) -> bool {
+        self.check(pp, params).is_ok()
+    }
+
+    /// same as verify, but tells which check failed
+    pub fn check(
+        &self,
+        pp: &UniversalParams<E>,
+        params: &ProtocolParams,
+    ) -> Result<(), ProofError> {
+        check_permutation_argument(pp, params, self)
+    }
+}
+
+impl<E: CurveConfig> PermutationProofV2<E> {
+    /// checks that f_com commits to a permutation of params.perm_size() cards
+    pub fn verify(&self, pp: &UniversalParams<E>, params: &ProtocolParams) -> bool {
+        self.check(pp, params).is_ok()
+    }
+
+    /// same as verify, but tells which check failed
+    pub fn check(
+        &self,
+        pp: &UniversalParams<E>,
+        params: &ProtocolParams,
+    ) -> Result<(), ProofError> {
+        check_permutation_argument_v2(pp, params, self)
+    }
+}
+
+impl<E: CurveConfig> EncryptionProof<E> {
+    /// checks that ctxt encrypts the cards card_commitment holds, each to its id
+    pub fn verify(
+        &self,
+        pp: &UniversalParams<E>,
+        params: &ProtocolParams,
+        ctx: &GameContext,
+        ctxt: &Ciphertext<E>,
+    ) -> bool {
+        self.check(pp, params, ctx, ctxt).is_ok()
+    }
+
+    /// same as verify, but tells which check failed
+    pub fn check(
+        &self,
+        pp: &UniversalParams<E>,
+        params: &ProtocolParams,
+        ctx: &GameContext,
+        ctxt: &Ciphertext<E>,
+    ) -> Result<(), ProofError> {
+        check_encryption_argument(pp, params, ctx, ctxt, self)
+    }
+}
+
+/// fails unless the opening proof of comm at point to value verifies
+fn check_opening<E: CurveConfig>(
+    pp: &UniversalParams<E>,
+    what: &'static str,
+    comm: &E::G1,
+    point: &E::ScalarField,
+    value: &E::ScalarField,
+    proof: &E::G1,
+) -> Result<(), ProofError> {
+    if KzgOn::<E>::verify_opening_proof(pp, &comm.into_affine(), point, value, &proof.into_affine())
+    {
+        Ok(())
+    } else {
+        Err(ProofError::Opening(what))
+    }
+}
+
+/// the challenge γ for a batch opening of comms to values at one point
+pub(crate) fn batch_opening_challenge<E: CurveConfig>(
+    comms: &[E::G1],
+    values: &[E::ScalarField],
+    hasher: FsHasher,
+) -> E::ScalarField {
+    let mut transcript = proof_transcript(b"pok3r-batch-opening", hasher);
+    for comm in comms.iter() {
+        transcript.append_g1(b"comm", comm);
+    }
+    for value in values.iter() {
+        transcript.append_f(b"value", value);
+    }
+
+    transcript.challenge_f_on::<E>(b"gamma")
+}
+
+/// the transcript of a proof; with the legacy_transcript feature the challenges
+/// are derived as before transcripts were introduced, to check old proofs
+pub(crate) fn proof_transcript(domain: &[u8], hasher: FsHasher) -> Transcript {
+    if cfg!(feature = "legacy_transcript") {
+        Transcript::legacy()
+    } else {
+        Transcript::with_hasher(domain, hasher)
+    }
+}
+
+/// the public values both verifiers derive from the commitments: v(X), the
+/// challenges hash1 and hash2, and the commitment to g(X)
+#[allow(clippy::type_complexity)]
+fn permutation_challenges<E: CurveConfig>(
+    pp: &UniversalParams<E>,
+    f_com: &E::G1,
+    q_com: &E::G1,
+    t_com: &E::G1,
+    hasher: FsHasher,
+    perm_size: usize,
+) -> Result<
+    (
+        DensePolynomial<E::ScalarField>,
+        E::ScalarField,
+        E::ScalarField,
+        E::G1,
+    ),
+    ProofError,
+> {
+    // Compute v(X) from powers of w
+    let v_evals: Vec<E::ScalarField> = utils::domain_info_of::<E::ScalarField>(perm_size)
+        .powers
+        .clone();
+
+    let v = utils::interpolate_poly_over_mult_subgroup(&v_evals);
+    let v_com = KzgOn::<E>::commit_g1(pp, &v)?;
+
+    // Compute hash1 and hash2
+    let mut transcript = proof_transcript(b"pok3r-permutation", hasher);
+    transcript.append_g1::<E::G1>(b"v_com", &v_com.into());
+    transcript.append_g1(b"f_com", f_com);
+
+    let hash1 = transcript.challenge_f_on::<E>(b"y1");
+
+    // Compute g_com from f_com
+    let const_y1 = DensePolynomial::from_coefficients_vec(vec![hash1]);
+    let const_com_y1 = KzgOn::<E>::commit_g1(pp, &const_y1)?;
+
+    let g_com = *f_com + const_com_y1.into();
+
+    transcript.append_g1(b"q_com", q_com);
+    transcript.append_g1(b"t_com", t_com);
+    transcript.append_g1(b"g_com", &g_com);
+
+    let hash2 = transcript.challenge_f_on::<E>(b"y2");
+
+    Ok((v, hash1, hash2, g_com))
+}
+
+/// the checks on the opened evaluations y1..y5, once their proofs are verified
+fn check_permutation_evaluations<E: CurveConfig>(
+    ys: [E::ScalarField; 5],
+    v: &DensePolynomial<E::ScalarField>,
+    hash1: E::ScalarField,
+    hash2: E::ScalarField,
+    perm_size: usize,
+) -> Result<(), ProofError> {
+    let [y1, y2, y3, y4, y5] = ys;
+
+    // y1 = t(w^63)
+    // y2 = t(hash2)
+    // y3 = t(hash2 / w)
+    // y4 = g(hash2)
+    // y5 = q(hash2)
+    // Check 1 : y2 * (v(hash2) + hash1) - y3 * y4 = y5 * (hash2^k - 1)
+    let tmp1 = y2 * (v.evaluate(&hash2) + hash1);
+    let tmp2 = y3 * y4;
+    let tmp3 = y5 * (hash2.pow([perm_size as u64]) - E::ScalarField::one());
+
+    if tmp1 - tmp2 != tmp3 {
+        return Err(ProofError::Constraint("the grand product"));
+    }
+
+    // Check 2 : y1 = 1
+    if y1 != E::ScalarField::one() {
+        return Err(ProofError::Constraint("t(w^-1) = 1"));
+    }
+
+    Ok(())
+}
+
+pub fn verify_permutation_argument<E: CurveConfig>(
+    pp: &UniversalParams<E>,
+    params: &ProtocolParams,
+    perm_proof: &PermutationProof<E>,
+) -> bool {
+    check_permutation_argument(pp, params, perm_proof).is_ok()
+}
+
+/// same as verify_permutation_argument, but tells which check failed
+pub fn check_permutation_argument<E: CurveConfig>(
+    pp: &UniversalParams<E>,
+    params: &ProtocolParams,
+    perm_proof: &PermutationProof<E>,
+) -> Result<(), ProofError> {
+    let perm_size = params.perm_size();
+
+    let domain = utils::domain_info_of::<E::ScalarField>(perm_size);
+    let (w, w63) = (domain.generator, domain.powers[perm_size - 1]);
+
+    let (v, hash1, hash2, g_com) = permutation_challenges(
+        pp,
+        &perm_proof.f_com,
+        &perm_proof.q_com,
+        &perm_proof.t_com,
+        perm_proof.hasher,
+        perm_size,
+    )?;
+
+    // Check all evaluation proofs
+    let t_com = &perm_proof.t_com;
+    check_opening(pp, "t(w^-1)", t_com, &w63, &perm_proof.y1, &perm_proof.pi_1)?;
+    check_opening(pp, "t(y)", t_com, &hash2, &perm_proof.y2, &perm_proof.pi_2)?;
+    check_opening(
+        pp,
+        "t(y / w)",
+        t_com,
+        &(hash2 / w),
+        &perm_proof.y3,
+        &perm_proof.pi_3,
+    )?;
+    check_opening(pp, "g(y)", &g_com, &hash2, &perm_proof.y4, &perm_proof.pi_4)?;
+    check_opening(
+        pp,
+        "q(y)",
+        &perm_proof.q_com,
+        &hash2,
+        &perm_proof.y5,
+        &perm_proof.pi_5,
+    )?;
+
+    let ys = [
+        perm_proof.y1,
+        perm_proof.y2,
+        perm_proof.y3,
+        perm_proof.y4,
+        perm_proof.y5,
+    ];
+    check_permutation_evaluations::<E>(ys, &v, hash1, hash2, perm_size)
+}
+
+/// verifies a compute_permutation_argument_v2 proof with three pairing checks
+pub fn verify_permutation_argument_v2<E: CurveConfig>(
+    pp: &UniversalParams<E>,
+    params: &ProtocolParams,
+    perm_proof: &PermutationProofV2<E>,
+) -> bool {
+    check_permutation_argument_v2(pp, params, perm_proof).is_ok()
+}
+
+/// same as verify_permutation_argument_v2, but tells which check failed
+pub fn check_permutation_argument_v2<E: CurveConfig>(
+    pp: &UniversalParams<E>,
+    params: &ProtocolParams,
+    perm_proof: &PermutationProofV2<E>,
+) -> Result<(), ProofError> {
+    let perm_size = params.perm_size();
+
+    let domain = utils::domain_info_of::<E::ScalarField>(perm_size);
+    let (w, w63) = (domain.generator, domain.powers[perm_size - 1]);
+
+    let (v, hash1, hash2, g_com) = permutation_challenges(
+        pp,
+        &perm_proof.f_com,
+        &perm_proof.q_com,
+        &perm_proof.t_com,
+        perm_proof.hasher,
+        perm_size,
+    )?;
+
+    let t_com = &perm_proof.t_com;
+    check_opening(pp, "t(w^-1)", t_com, &w63, &perm_proof.y1, &perm_proof.pi_1)?;
+    check_opening(
+        pp,
+        "t(y / w)",
+        t_com,
+        &(hash2 / w),
+        &perm_proof.y3,
+        &perm_proof.pi_3,
+    )?;
+
+    // t, g and q are all opened at hash2
+    let comms = [perm_proof.t_com, g_com, perm_proof.q_com];
+    let values = [perm_proof.y2, perm_proof.y4, perm_proof.y5];
+    let gamma = batch_opening_challenge::<E>(&comms, &values, perm_proof.hasher);
+    if !KzgOn::<E>::batch_verify(
+        pp,
+        &E::G1::normalize_batch(&comms),
+        &values,
+        &hash2,
+        &gamma,
+        &perm_proof.pi_batch.into_affine(),
+    ) {
+        return Err(ProofError::Opening("t, g and q at y"));
+    }
+
+    let ys = [
+        perm_proof.y1,
+        perm_proof.y2,
+        perm_proof.y3,
+        perm_proof.y4,
+        perm_proof.y5,
+    ];
+    check_permutation_evaluations::<E>(ys, &v, hash1, hash2, perm_size)
+}
+
+pub fn verify_encryption_argument<E: CurveConfig>(
+    pp: &UniversalParams<E>,
+    params: &ProtocolParams,
+    ctx: &GameContext,
+    ctxt: &Ciphertext<E>,
+    proof: &EncryptionProof<E>,
+) -> bool {
+    check_encryption_argument(pp, params, ctx, ctxt, proof).is_ok()
+}
+
+/// same as verify_encryption_argument, but tells which check failed
+pub fn check_encryption_argument<E: CurveConfig>(
+    pp: &UniversalParams<E>,
+    params: &ProtocolParams,
+    ctx: &GameContext,
+    ctxt: &Ciphertext<E>,
+    proof: &EncryptionProof<E>,
+) -> Result<(), ProofError> {
+    let perm_size = params.perm_size();
+    for actual in [ctxt.1.len(), proof.ids.len()] {
+        if actual != perm_size {
+            return Err(ProofError::WrongSize {
+                expected: perm_size,
+                actual,
+            });
+        }
+    }
+    let sigma_proof = proof
+        .sigma_proof
+        .as_ref()
+        .ok_or(ProofError::MissingSigmaProof)?;
+
+    // Common first element of all ciphertexts
+    let c1 = ctxt.0;
+
+    // Compute delta
+    let mut bytes = Vec::new();
+    let mut c1_bytes = Vec::new();
+    let mut c2_bytes = Vec::new();
+
+    c1.serialize_uncompressed(&mut c1_bytes).unwrap();
+    bytes.extend_from_slice(&c1_bytes);
+
+    for i in 0..perm_size {
+        ctxt.1[i].serialize_uncompressed(&mut c2_bytes).unwrap();
+        bytes.extend_from_slice(&c2_bytes);
+    }
+
+    // Add alpha1 ciphertext to the hash
+    proof
+        .hiding_ciphertext
+        .serialize_uncompressed(&mut c2_bytes)
+        .unwrap();
+    bytes.extend_from_slice(&c2_bytes);
+
+    let delta = utils::fs_hash(vec![&bytes], 1)[0];
+
+    // Check evaluation proof
+    check_opening(
+        pp,
+        "the cards at delta",
+        &proof.card_commitment,
+        &delta,
+        &proof.card_poly_eval,
+        &proof.eval_proof,
+    )?;
+
+    // Compute e_batch
+    let lagrange_delta: Vec<E::ScalarField> = (0..perm_size)
+        .map(|i| utils::compute_lagrange_basis(i as u64, perm_size as u64).evaluate(&delta))
+        .collect();
+
+    let mut batch_h = E::G1::zero();
+    for (id, lagrange) in proof.ids.iter().zip(lagrange_delta.iter()) {
+        let hash_id = hash_to_g1_ctx_on::<E>(ctx, id.as_ref());
+        batch_h = batch_h.add(hash_id.mul(*lagrange));
+    }
+    // Add the contribution from the hiding term (multiplied with (delta^perm_size - 1))
+    let hash_id = hash_to_g1_ctx_on::<E>(ctx, &BigUint::from(123_u64).to_bytes_le());
+    batch_h = batch_h.add(
+        hash_id.mul(utils::compute_power(&delta, perm_size as u64) - E::ScalarField::from(1u64)),
+    );
+
+    let e_batch = E::pairing(batch_h, proof.pk);
+
+    // Check that prod_i c2_i^Li(delta) * alpha1_c2*(delta*perm_size - 1) = g^f(delta) * t
+    let mut lhs = PairingOutput::<E>::zero();
+    for (c2, lagrange) in ctxt.1.iter().zip(lagrange_delta.iter()) {
+        lhs += c2.mul(lagrange);
+    }
+    lhs += proof
+        .hiding_ciphertext
+        .mul(utils::compute_power(&delta, perm_size as u64) - E::ScalarField::from(1u64));
+
+    let mut rhs = PairingOutput::<E>::generator().mul(proof.card_poly_eval);
+    rhs = rhs.add(proof.t);
+
+    if !lhs.eq(&rhs) {
+        return Err(ProofError::Constraint("the ciphertexts at delta"));
+    }
+
+    // Check sigma proof
+    // Compute hash to get eta
+    let mut transcript = proof_transcript(b"pok3r-sigma", proof.hasher);
+    transcript.append_g2(b"a1", &sigma_proof.a1);
+    transcript.append_gt(b"a2", &sigma_proof.a2);
+
+    let eta = transcript.challenge_fs_on::<E>(b"eta", 1);
+
+    // Check statement 1
+    let lhs = E::G2::generator().mul(sigma_proof.y);
+    let rhs = c1.mul(eta[0]).add(sigma_proof.a1);
+
+    if !lhs.eq(&rhs) {
+        return Err(ProofError::Constraint("the sigma proof of c1"));
+    }
+
+    // Check statement 2
+    let lhs = e_batch.mul(sigma_proof.y);
+    let rhs = proof.t.mul(eta[0]).add(sigma_proof.a2);
+
+    if !lhs.eq(&rhs) {
+        return Err(ProofError::Constraint("the sigma proof of t"));
+    }
+
+    Ok(())
+}
+
+/// checks that value is the card deck_commitment holds at position
+pub fn verify_public_card<E: CurveConfig>(
+    pp: &UniversalParams<E>,
+    params: &ProtocolParams,
+    deck_commitment: &E::G1,
+    position: usize,
+    value: &E::ScalarField,
+    proof: &E::G1,
+) -> bool {
+    check_public_card(pp, params, deck_commitment, position, value, proof).is_ok()
+}
+
+/// same as verify_public_card, but tells which check failed
+pub fn check_public_card<E: CurveConfig>(
+    pp: &UniversalParams<E>,
+    params: &ProtocolParams,
+    deck_commitment: &E::G1,
+    position: usize,
+    value: &E::ScalarField,
+    proof: &E::G1,
+) -> Result<(), ProofError> {
+    let perm_size = params.perm_size();
+    if position >= perm_size {
+        return Err(ProofError::PositionOutOfRange {
+            position: position as u64,
+            perm_size,
+        });
+    }
+    let point = utils::domain_info_of::<E::ScalarField>(perm_size).powers[position];
+    check_opening(pp, "the card", deck_commitment, &point, value, proof)
+}
+
+/// (x^perm_size - 1) / (x - point), whose commitment opens the hiding term of the
+/// deck commitment at point; only the shuffler opens the deck
+#[cfg(feature = "networking")]
+pub(crate) fn hiding_quotient<F: ark_ff::FftField>(
+    perm_size: usize,
+    point: F,
+) -> DensePolynomial<F> {
+    use ark_poly::univariate::DenseOrSparsePolynomial;
+
+    let divisor = DensePolynomial::from_coefficients_vec(vec![-point, F::one()]);
+    let (quotient, _) = DenseOrSparsePolynomial::divide_with_q_and_r(
+        &(&utils::compute_vanishing_poly(perm_size)).into(),
+        &(&divisor).into(),
+    )
+    .expect("X - point is not zero");
+    quotient
+}
+
+/// checks that dealt encrypts the card deck_commitment holds at its position, to
+/// the identity of its recipient
+pub fn verify_dealt_card<E: CurveConfig>(
+    pp: &UniversalParams<E>,
+    params: &ProtocolParams,
+    ctx: &GameContext,
+    deck_commitment: &E::G1,
+    dealt: &DealtCard<E>,
+) -> bool {
+    check_dealt_card(pp, params, ctx, deck_commitment, dealt).is_ok()
+}
+
+/// same as verify_dealt_card, but tells which check failed
+pub fn check_dealt_card<E: CurveConfig>(
+    pp: &UniversalParams<E>,
+    params: &ProtocolParams,
+    ctx: &GameContext,
+    deck_commitment: &E::G1,
+    dealt: &DealtCard<E>,
+) -> Result<(), ProofError> {
+    let perm_size = params.perm_size();
+    if dealt.card_commitment != *deck_commitment {
+        return Err(ProofError::CommitmentMismatch);
+    }
+    if dealt.position >= perm_size as u64 {
+        return Err(ProofError::PositionOutOfRange {
+            position: dealt.position,
+            perm_size,
+        });
+    }
+    let point = utils::domain_info_of::<E::ScalarField>(perm_size).powers[dealt.position as usize];
+    let id = player_card_id(ctx, &dealt.recipient, dealt.position);
+    let (g, h, divisor_h) = deal_bases(pp, point)?;
+
+    let e_id = E::pairing(hash_to_g1_ctx_on::<E>(ctx, &id), dealt.pk);
+    let base = E::pairing(g, h);
+    let d = E::pairing(g, divisor_h);
+    // base^m d^-s, for the card m and the blinding s of the opening proof
+    let k = E::pairing(dealt.card_commitment, h) - E::pairing(dealt.eval_proof, divisor_h);
+
+    let eta = deal_challenge(dealt);
+    let proof = &dealt.proof;
+    if E::G2::generator().mul(proof.y_r) != proof.a1 + dealt.c1.mul(eta) {
+        return Err(ProofError::Constraint("the deal proof of c1"));
+    }
+    if PairingOutput::<E>::generator().mul(proof.y_m) + e_id.mul(proof.y_r)
+        != proof.a2 + dealt.c2.mul(eta)
+    {
+        return Err(ProofError::Constraint("the deal proof of c2"));
+    }
+    if base.mul(proof.y_m) - d.mul(proof.y_s) != proof.a3 + k.mul(eta) {
+        return Err(ProofError::Constraint("the deal proof of the opening"));
+    }
+    Ok(())
+}
+
+/// g and h of the KZG params, and h^(τ - point) that opening proofs at point
+/// are paired with; fails if the params stop short of h^τ
+#[allow(clippy::type_complexity)]
+pub(crate) fn deal_bases<E: CurveConfig>(
+    pp: &UniversalParams<E>,
+    point: E::ScalarField,
+) -> Result<(E::G1, E::G2, E::G2), KzgError> {
+    if pp.powers_of_g.is_empty() || !pp.has_g2_powers(1) {
+        return Err(KzgError::TooFewPowers {
+            needed: 2,
+            available: pp.powers_of_h.len(),
+        });
+    }
+    let g = pp.powers_of_g[0].into_group();
+    let h = pp.powers_of_h[0].into_group();
+    let divisor_h = pp.powers_of_h[1].into_group() - h.mul(point);
+    Ok((g, h, divisor_h))
+}
+
+/// the challenge of a DealProof, hashed from everything but the responses
+pub(crate) fn deal_challenge<E: CurveConfig>(dealt: &DealtCard<E>) -> E::ScalarField {
+    let mut transcript = proof_transcript(b"pok3r-deal", dealt.hasher);
+    transcript.append_g1(b"commitment", &dealt.card_commitment);
+    transcript.append_bytes(b"position", &dealt.position.to_le_bytes());
+    transcript.append_bytes(b"recipient", &dealt.recipient);
+    transcript.append_g2(b"pk", &dealt.pk);
+    transcript.append_g2(b"c1", &dealt.c1);
+    transcript.append_gt(b"c2", &dealt.c2);
+    transcript.append_g1(b"eval_proof", &dealt.eval_proof);
+    transcript.append_g2(b"a1", &dealt.proof.a1);
+    transcript.append_gt(b"a2", &dealt.proof.a2);
+    transcript.append_gt(b"a3", &dealt.proof.a3);
+    transcript.challenge_f_on::<E>(b"eta")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::common::{DealProof, Gt, SigmaProof, F, G1, G2, KZG};
+    use ark_std::UniformRand;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn random_dealt_card(rng: &mut StdRng, commitment: G1, position: u64) -> DealtCard {
+        DealtCard {
+            position,
+            recipient: b"alice".to_vec(),
+            pk: G2::rand(rng),
+            card_commitment: commitment,
+            c1: G2::rand(rng),
+            c2: Gt::rand(rng),
+            eval_proof: G1::rand(rng),
+            proof: DealProof {
+                a1: G2::rand(rng),
+                a2: Gt::rand(rng),
+                a3: Gt::rand(rng),
+                y_m: F::rand(rng),
+                y_r: F::rand(rng),
+                y_s: F::rand(rng),
+            },
+            hasher: FsHasher::default(),
+        }
+    }
+
+    #[test]
+    fn test_checks_name_the_failure() {
+        let mut rng = StdRng::from_seed([3u8; 32]);
+        let params = ProtocolParams::new(16, 10).unwrap();
+        let pp = KZG::setup(32, &mut rng);
+        let ctx = GameContext::from_label("errors");
+        let deck = G1::rand(&mut rng);
+
+        let other = G1::rand(&mut rng);
+        let card = random_dealt_card(&mut rng, other, 0);
+        assert_eq!(
+            check_dealt_card(&pp, &params, &ctx, &deck, &card),
+            Err(ProofError::CommitmentMismatch)
+        );
+        let card = random_dealt_card(&mut rng, deck, 16);
+        assert_eq!(
+            check_dealt_card(&pp, &params, &ctx, &deck, &card),
+            Err(ProofError::PositionOutOfRange {
+                position: 16,
+                perm_size: 16
+            })
+        );
+        let card = random_dealt_card(&mut rng, deck, 3);
+        assert_eq!(
+            check_dealt_card(&pp, &params, &ctx, &deck, &card),
+            Err(ProofError::Constraint("the deal proof of c1"))
+        );
+        assert!(!verify_dealt_card(&pp, &params, &ctx, &deck, &card));
+
+        let (value, proof) = (F::rand(&mut rng), G1::rand(&mut rng));
+        assert_eq!(
+            check_public_card(&pp, &params, &deck, 3, &value, &proof),
+            Err(ProofError::Opening("the card"))
+        );
+
+        let mut encryption = EncryptionProof {
+            pk: G2::rand(&mut rng),
+            ids: vec![vec![0u8]; 16],
+            card_commitment: deck,
+            card_poly_eval: F::rand(&mut rng),
+            eval_proof: G1::rand(&mut rng),
+            hiding_ciphertext: Gt::rand(&mut rng),
+            t: Gt::rand(&mut rng),
+            sigma_proof: None,
+            hasher: FsHasher::default(),
+        };
+        let ctxt: Ciphertext = (G2::rand(&mut rng), vec![Gt::rand(&mut rng); 16]);
+        assert_eq!(
+            encryption.check(&pp, &params, &ctx, &(ctxt.0, vec![])),
+            Err(ProofError::WrongSize {
+                expected: 16,
+                actual: 0
+            })
+        );
+        assert_eq!(
+            encryption.check(&pp, &params, &ctx, &ctxt),
+            Err(ProofError::MissingSigmaProof)
+        );
+        encryption.sigma_proof = Some(SigmaProof {
+            a1: G2::rand(&mut rng),
+            a2: Gt::rand(&mut rng),
+            y: F::rand(&mut rng),
+        });
+        assert_eq!(
+            encryption.check(&pp, &params, &ctx, &ctxt),
+            Err(ProofError::Opening("the cards at delta"))
+        );
+
+        // params too small to commit to v(X) = X
+        let permutation = PermutationProof {
+            y1: F::one(),
+            y2: F::rand(&mut rng),
+            y3: F::rand(&mut rng),
+            y4: F::rand(&mut rng),
+            y5: F::rand(&mut rng),
+            pi_1: G1::rand(&mut rng),
+            pi_2: G1::rand(&mut rng),
+            pi_3: G1::rand(&mut rng),
+            pi_4: G1::rand(&mut rng),
+            pi_5: G1::rand(&mut rng),
+            f_com: deck,
+            q_com: G1::rand(&mut rng),
+            t_com: G1::rand(&mut rng),
+            hasher: FsHasher::default(),
+        };
+        let small = KZG::setup(0, &mut rng);
+        assert_eq!(
+            permutation.check(&small, &params),
+            Err(ProofError::Kzg(KzgError::TooFewPowers {
+                needed: 2,
+                available: 1
+            }))
+        );
+        assert_eq!(
+            permutation.check(&pp, &params),
+            Err(ProofError::Opening("t(w^-1)"))
+        );
+    }
+}
@@ -0,0 +1,126 @@
+//! Pseudo-random secret sharing: once every pair of parties shares a seed, each
+//! party derives its additive share of as many pseudo-random values as it likes,
+//! without talking to anyone.
+
+use ark_ff::PrimeField;
+use sha2::{Digest, Sha512};
+use std::collections::BTreeMap;
+use zeroize::Zeroize;
+
+use crate::pairwise::PairwiseKeys;
+
+/// Prss holds the seeds we share with the other parties of a committee. The
+/// counter-th value is r = Σ over pairs {i, j} of PRF(s_ij, counter, i) +
+/// PRF(s_ij, counter, j), where party i contributes the terms that carry its id,
+/// so r stays hidden from anyone who misses the seed of some pair, i.e. as long
+/// as two parties are honest.
+#[derive(Clone)]
+pub struct Prss {
+    my_id: u64,
+    /// the seed shared with each other party, by node id
+    seeds: BTreeMap<u64, [u8; 32]>,
+}
+
+impl Prss {
+    pub fn new(my_id: u64, seeds: BTreeMap<u64, [u8; 32]>) -> Self {
+        Prss { my_id, seeds }
+    }
+
+    /// the seed of each pair, derived from the key the pair agreed on, so that
+    /// it never crosses the network
+    pub fn from_keys(keys: &PairwiseKeys) -> Self {
+        let seeds = keys
+            .keys()
+            .keys()
+            .filter_map(|&node_id| Some((node_id, keys.derive(node_id, "prss")?)))
+            .collect();
+        Prss::new(keys.my_id(), seeds)
+    }
+
+    pub fn seeds(&self) -> &BTreeMap<u64, [u8; 32]> {
+        &self.seeds
+    }
+
+    /// our share of the counter-th pseudo-random value; every counter must be
+    /// used for one value only
+    pub fn next_sharing<F: PrimeField>(&self, counter: u64) -> F {
+        self.seeds
+            .values()
+            .map(|seed| prf::<F>(seed, counter, self.my_id))
+            .sum()
+    }
+}
+
+/// the seeds make every value still to be drawn, and outlive those in the
+/// reservoirs they fill
+impl Drop for Prss {
+    fn drop(&mut self) {
+        self.seeds.values_mut().for_each(Zeroize::zeroize);
+    }
+}
+
+/// 512 bits of hash reduced mod the field order, so that the bias is negligible
+fn prf<F: PrimeField>(seed: &[u8; 32], counter: u64, owner: u64) -> F {
+    let mut hasher = Sha512::new();
+    hasher.update(b"pok3r-prss");
+    hasher.update(seed);
+    hasher.update(counter.to_le_bytes());
+    hasher.update(owner.to_le_bytes());
+    F::from_le_bytes_mod_order(&hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Prss;
+    use crate::common::{Curve, F};
+    use crate::pairwise::PairwiseKeys;
+    use crate::transport::InMemoryTransport;
+    use futures::future::join_all;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn setup(n: usize, seed: u8) -> Vec<Prss> {
+        async_std::task::block_on(async {
+            let transports = InMemoryTransport::new_network(n).await;
+            join_all(
+                transports
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, mut transport)| async move {
+                        let mut rng = StdRng::from_seed([seed + i as u8; 32]);
+                        let keys = PairwiseKeys::setup::<Curve, _, _>(
+                            &mut transport,
+                            "pairwise",
+                            &mut rng,
+                        )
+                        .await
+                        .unwrap();
+                        Prss::from_keys(&keys)
+                    }),
+            )
+            .await
+        })
+    }
+
+    fn open(prss: &[Prss], counter: u64) -> F {
+        prss.iter().map(|p| p.next_sharing::<F>(counter)).sum()
+    }
+
+    #[test]
+    fn test_sums_match_only_with_the_same_seeds() {
+        let (a, b, c) = (setup(4, 1), setup(4, 1), setup(4, 10));
+        for counter in 0..4 {
+            assert_eq!(open(&a, counter), open(&b, counter));
+            assert_ne!(open(&a, counter), open(&c, counter));
+        }
+    }
+
+    #[test]
+    fn test_draws_are_stable_for_a_counter() {
+        let prss = setup(3, 1);
+        for p in prss.iter() {
+            assert_eq!(p.next_sharing::<F>(7), p.next_sharing::<F>(7));
+            assert_ne!(p.next_sharing::<F>(7), p.next_sharing::<F>(8));
+        }
+        assert_ne!(open(&prss, 7), open(&prss, 8));
+    }
+}
@@ -0,0 +1,252 @@
+//! Recovering opened values from the parties' shares, for scalars as well as for
+//! shared exponents applied to group elements. The shares must have been decoded
+//! with the checks of the encoding module, so that group elements are on the
+//! curve and in the prime-order subgroup.
+
+use ark_ec::pairing::{Pairing, PairingOutput};
+use ark_ec::short_weierstrass::{Projective, SWCurveConfig};
+use ark_ff::{Fp, FpConfig, PrimeField};
+use ark_std::Zero;
+use std::collections::{HashMap, HashSet};
+use std::ops::Add;
+use std::{error, fmt};
+
+use crate::shamir;
+
+/// what an opening is made of: a scalar, or a shared exponent applied to a group
+/// element, along with the field the sharing is over
+pub trait Shared: Zero + Copy + Add<Output = Self> {
+    type Scalar: PrimeField;
+
+    /// the value times a Lagrange coefficient, i.e. raised to it for a group element
+    fn scale(self, coeff: Self::Scalar) -> Self;
+}
+
+impl<P: FpConfig<N>, const N: usize> Shared for Fp<P, N> {
+    type Scalar = Self;
+
+    fn scale(self, coeff: Self) -> Self {
+        self * coeff
+    }
+}
+
+impl<P: SWCurveConfig> Shared for Projective<P> {
+    type Scalar = P::ScalarField;
+
+    fn scale(self, coeff: P::ScalarField) -> Self {
+        self * coeff
+    }
+}
+
+impl<E: Pairing> Shared for PairingOutput<E> {
+    type Scalar = E::ScalarField;
+
+    fn scale(self, coeff: E::ScalarField) -> Self {
+        self * coeff
+    }
+}
+
+/// why the shares of an opening do not fit the parties expected to send them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ReconstructError {
+    /// the party with node id sent more than one share
+    DuplicateSender { node_id: u64 },
+    /// some expected parties sent nothing, or parties that were not expected sent
+    /// something; node ids in increasing order
+    UnexpectedSenders { missing: Vec<u64>, extra: Vec<u64> },
+    /// a degree-t sharing needs t + 1 shares
+    NotEnoughShares { have: usize, need: usize },
+}
+
+impl fmt::Display for ReconstructError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReconstructError::DuplicateSender { node_id } => {
+                write!(f, "party {} sent more than one share", node_id)
+            }
+            ReconstructError::UnexpectedSenders { missing, extra } => write!(
+                f,
+                "shares missing from parties {:?}, unexpected from parties {:?}",
+                missing, extra
+            ),
+            ReconstructError::NotEnoughShares { have, need } => {
+                write!(f, "{} shares, {} needed", have, need)
+            }
+        }
+    }
+}
+
+impl error::Error for ReconstructError {}
+
+/// additive shares are summed
+pub fn reconstruct_additive<'a, V>(shares: impl IntoIterator<Item = &'a V>) -> V
+where
+    V: 'a + Zero + Copy + Add<Output = V>,
+{
+    shares
+        .into_iter()
+        .fold(V::zero(), |acc, share| acc + *share)
+}
+
+/// the first t + 1 shares by node id of a degree-t sharing are interpolated at zero;
+/// fewer than t + 1 shares do not determine the value
+pub fn reconstruct_threshold<V>(shares: &HashMap<u64, V>, t: u64) -> Result<V, ReconstructError>
+where
+    V: Shared,
+{
+    if shares.len() <= t as usize {
+        return Err(ReconstructError::NotEnoughShares {
+            have: shares.len(),
+            need: t as usize + 1,
+        });
+    }
+    let mut node_ids = shares.keys().copied().collect::<Vec<u64>>();
+    node_ids.sort_unstable();
+    node_ids.truncate(t as usize + 1);
+
+    let coeffs = shamir::lagrange_coeffs_at_zero::<V::Scalar>(&node_ids);
+    Ok(node_ids
+        .iter()
+        .zip(coeffs)
+        .fold(V::zero(), |acc, (id, coeff)| acc + shares[id].scale(coeff)))
+}
+
+/// recovers a shared value (or a shared exponent applied to a group element):
+/// additive shares if threshold is None, degree-t Shamir shares if Some(t)
+pub fn reconstruct<V>(
+    shares: &HashMap<u64, V>,
+    threshold: Option<u64>,
+) -> Result<V, ReconstructError>
+where
+    V: Shared,
+{
+    match threshold {
+        None => Ok(reconstruct_additive(shares.values())),
+        Some(t) => reconstruct_threshold(shares, t),
+    }
+}
+
+/// same as reconstruct, but first checks the senders against expected: each may
+/// send once, nobody else may send, and every one of them must have sent for
+/// additive shares, or at least t + 1 of them for degree-t shares
+pub fn reconstruct_checked<V>(
+    shares: &[(u64, V)],
+    threshold: Option<u64>,
+    expected: &[u64],
+) -> Result<V, ReconstructError>
+where
+    V: Shared,
+{
+    let mut by_sender = HashMap::with_capacity(shares.len());
+    for (node_id, share) in shares {
+        if by_sender.insert(*node_id, *share).is_some() {
+            return Err(ReconstructError::DuplicateSender { node_id: *node_id });
+        }
+    }
+    reconstruct_checked_by_sender(&by_sender, threshold, expected)
+}
+
+/// reconstruct_checked for shares already keyed by sender, as openings collect
+/// them; expected must not list a node id twice
+pub fn reconstruct_checked_by_sender<V>(
+    shares: &HashMap<u64, V>,
+    threshold: Option<u64>,
+    expected: &[u64],
+) -> Result<V, ReconstructError>
+where
+    V: Shared,
+{
+    let has_extra = shares.keys().any(|id| !expected.contains(id));
+    let has_missing = threshold.is_none() && expected.iter().any(|id| !shares.contains_key(id));
+    if has_extra || has_missing {
+        let expected: HashSet<u64> = expected.iter().copied().collect();
+        let mut missing: Vec<u64> = expected
+            .iter()
+            .filter(|id| !shares.contains_key(id))
+            .copied()
+            .collect();
+        let mut extra: Vec<u64> = shares
+            .keys()
+            .filter(|id| !expected.contains(id))
+            .copied()
+            .collect();
+        missing.sort_unstable();
+        extra.sort_unstable();
+        return Err(ReconstructError::UnexpectedSenders { missing, extra });
+    }
+    reconstruct(shares, threshold)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{reconstruct_checked, reconstruct_threshold, ReconstructError};
+    use crate::common::{F, G1};
+    use crate::shamir;
+    use ark_ec::Group;
+    use rand::thread_rng;
+
+    #[test]
+    fn test_reconstruct_checked_valid_short_and_duplicated_sets() {
+        // additive shares of 10 in the exponent of the generator
+        let g = G1::generator();
+        let shares: Vec<(u64, G1)> = [2u64, 3, 5]
+            .iter()
+            .enumerate()
+            .map(|(i, x)| (i as u64 + 1, g * F::from(*x)))
+            .collect();
+        let committee = [1, 2, 3];
+        assert_eq!(
+            reconstruct_checked(&shares, None, &committee),
+            Ok(g * F::from(10u64))
+        );
+
+        assert_eq!(
+            reconstruct_checked(&shares[..2], None, &committee),
+            Err(ReconstructError::UnexpectedSenders {
+                missing: vec![3],
+                extra: vec![]
+            })
+        );
+        assert_eq!(
+            reconstruct_checked(&shares, None, &[1, 2]),
+            Err(ReconstructError::UnexpectedSenders {
+                missing: vec![],
+                extra: vec![3]
+            })
+        );
+
+        let mut duplicated = shares.clone();
+        duplicated.push((2, g));
+        assert_eq!(
+            reconstruct_checked(&duplicated, None, &committee),
+            Err(ReconstructError::DuplicateSender { node_id: 2 })
+        );
+    }
+
+    #[test]
+    fn test_reconstruct_checked_threshold() {
+        let secret = F::from(42u64);
+        let shares: Vec<(u64, F)> = shamir::share(&secret, (2, 4), &mut thread_rng())
+            .into_iter()
+            .enumerate()
+            .map(|(i, (_, y))| (i as u64 + 1, y))
+            .collect();
+        let committee = [1, 2, 3, 4];
+
+        assert_eq!(
+            reconstruct_checked(&shares[1..3], Some(1), &committee),
+            Ok(secret)
+        );
+        assert_eq!(
+            reconstruct_checked(&shares[..1], Some(1), &committee),
+            Err(ReconstructError::NotEnoughShares { have: 1, need: 2 })
+        );
+
+        // a single share would otherwise be taken for the secret
+        let short = shares[..1].iter().copied().collect();
+        assert_eq!(
+            reconstruct_threshold(&short, 1),
+            Err(ReconstructError::NotEnoughShares { have: 1, need: 2 })
+        );
+    }
+}
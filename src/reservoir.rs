@@ -0,0 +1,228 @@
+//! Preprocessed values made ahead of their use by a background thread.
+
+use std::collections::VecDeque;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+use crate::secret::Scrub;
+
+/// how many values the background thread makes before it lets go of the pool
+const CHUNK: usize = 64;
+
+/// ReservoirStatus tells how far along a reservoir is
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ReservoirStatus {
+    /// made, and not taken yet
+    pub ready: usize,
+    /// not made yet
+    pub pending: usize,
+    /// taken so far
+    pub taken: usize,
+}
+
+struct Pool<V: Scrub> {
+    generate: Box<dyn FnMut() -> V + Send>,
+    ready: VecDeque<V>,
+    pending: usize,
+    taken: usize,
+}
+
+impl<V: Scrub + Clone> Pool<V> {
+    fn make(&mut self, count: usize) {
+        let count = count.min(self.pending);
+        for _ in 0..count {
+            let value = (self.generate)();
+            self.ready.push_back(value);
+        }
+        self.pending -= count;
+    }
+
+    /// moves the first count ready values out, scrubbing the slots they leave
+    fn take_ready(&mut self, count: usize) -> Vec<V> {
+        let values = self
+            .ready
+            .iter_mut()
+            .take(count)
+            .map(|slot| {
+                let value = slot.clone();
+                slot.scrub();
+                value
+            })
+            .collect();
+        self.ready.drain(..count);
+        values
+    }
+}
+
+/// the values made and never taken; those still pending die with generate
+impl<V: Scrub> Drop for Pool<V> {
+    fn drop(&mut self) {
+        self.ready.scrub();
+    }
+}
+
+/// Reservoir hands out the first total values of generate, in order. It makes
+/// bootstrap of them up front, after which a background thread keeps up to
+/// high_water of them ready; take makes whatever is missing itself rather than
+/// wait. Which values come out only depends on how many were taken before, never
+/// on timing, so parties taking the same counts get shares of the same values.
+/// Values are scrubbed from the reservoir as they are taken, and those never
+/// taken when it is dropped.
+pub struct Reservoir<V: Scrub> {
+    pool: Arc<Mutex<Pool<V>>>,
+    /// wakes the background thread up, which exits once this is dropped; in a
+    /// mutex so that reservoirs stay Sync
+    refill: Mutex<mpsc::Sender<()>>,
+}
+
+impl<V: Scrub + Clone + Send + 'static> Reservoir<V> {
+    pub fn new(
+        generate: impl FnMut() -> V + Send + 'static,
+        total: usize,
+        bootstrap: usize,
+        high_water: usize,
+    ) -> Self {
+        let mut pool = Pool {
+            generate: Box::new(generate),
+            ready: VecDeque::new(),
+            pending: total,
+            taken: 0,
+        };
+        pool.make(bootstrap);
+        let pool = Arc::new(Mutex::new(pool));
+
+        let (refill, requests) = mpsc::channel::<()>();
+        let background = Arc::clone(&pool);
+        thread::spawn(move || {
+            while requests.recv().is_ok() {
+                loop {
+                    let mut pool = background.lock().unwrap();
+                    if pool.ready.len() >= high_water || pool.pending == 0 {
+                        break;
+                    }
+                    pool.make(CHUNK);
+                }
+            }
+        });
+        let _ = refill.send(());
+
+        Reservoir {
+            pool,
+            refill: Mutex::new(refill),
+        }
+    }
+
+    /// the next count values; fewer only once all of them have been taken
+    pub fn take(&self, count: usize) -> Vec<V> {
+        let mut pool = self.pool.lock().unwrap();
+        if pool.ready.len() < count {
+            let missing = count - pool.ready.len();
+            pool.make(missing);
+        }
+        let count = count.min(pool.ready.len());
+        pool.taken += count;
+        let values = pool.take_ready(count);
+        drop(pool);
+
+        let _ = self.refill.lock().unwrap().send(());
+        values
+    }
+
+    /// copies of every value not taken yet, making the pending ones first; the
+    /// values stay in the reservoir
+    pub fn unused(&self) -> Vec<V> {
+        let mut pool = self.pool.lock().unwrap();
+        let pending = pool.pending;
+        pool.make(pending);
+        pool.ready.iter().cloned().collect()
+    }
+
+    pub fn status(&self) -> ReservoirStatus {
+        let pool = self.pool.lock().unwrap();
+        ReservoirStatus {
+            ready: pool.ready.len(),
+            pending: pool.pending,
+            taken: pool.taken,
+        }
+    }
+}
+
+/// scrubs the ready values right away rather than once the background thread
+/// lets go of the pool; what it makes until then is scrubbed with the pool
+impl<V: Scrub> Drop for Reservoir<V> {
+    fn drop(&mut self) {
+        if let Ok(mut pool) = self.pool.lock() {
+            pool.ready.scrub();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Reservoir, ReservoirStatus};
+    use crate::common::F;
+    use crate::secret::Scrub;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    fn counter() -> impl FnMut() -> F + Send {
+        let mut next = 0u64;
+        move || {
+            next += 1;
+            F::from(next - 1)
+        }
+    }
+
+    /// counts how many times values were scrubbed
+    #[derive(Clone)]
+    struct Canary(Arc<AtomicUsize>);
+
+    impl Scrub for Canary {
+        fn scrub(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_values_come_out_in_order_whatever_the_timing() {
+        let eager = Reservoir::new(counter(), 1000, 10, 500);
+        let lazy = Reservoir::new(counter(), 1000, 0, 0);
+        // let the background thread of eager run ahead
+        thread::sleep(Duration::from_millis(50));
+        assert!(eager.status().ready >= 10);
+
+        let mut taken = Vec::new();
+        for count in [1, 7, 300, 2, 690] {
+            let values = eager.take(count);
+            assert_eq!(values, lazy.take(count));
+            taken.extend(values);
+        }
+        assert_eq!(taken, (0..1000u64).map(F::from).collect::<Vec<F>>());
+
+        assert!(eager.take(5).is_empty());
+        assert_eq!(
+            eager.status(),
+            ReservoirStatus {
+                ready: 0,
+                pending: 0,
+                taken: 1000
+            }
+        );
+    }
+    #[test]
+    fn test_taken_and_leftover_values_are_scrubbed() {
+        let scrubbed = Arc::new(AtomicUsize::new(0));
+        let canary = Canary(Arc::clone(&scrubbed));
+        let reservoir = Reservoir::new(move || canary.clone(), 10, 10, 0);
+
+        // the slots the taken values leave are scrubbed, not the values handed out
+        let taken = reservoir.take(4);
+        assert_eq!(taken.len(), 4);
+        assert_eq!(scrubbed.load(Ordering::SeqCst), 4);
+
+        drop(reservoir);
+        assert_eq!(scrubbed.load(Ordering::SeqCst), 10);
+    }
+}
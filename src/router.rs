@@ -0,0 +1,516 @@
+//! Several games over one network connection: a Router takes what the networkd
+//! delivers and hands every message to the session it is tagged with, and each
+//! session gets a SessionTransport of its own, which an Evaluator runs on.
+
+use async_trait::async_trait;
+use futures::{channel::mpsc, StreamExt};
+use libp2p::identity;
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::address_book::{PeerRole, Pok3rAddrBook, Pok3rPeerId};
+use crate::common::{EvalNetMsg, PayloadKind, PeerInfo};
+use crate::journal::JournalError;
+use crate::metrics::Metrics;
+use crate::network::{session_of, HeartbeatConfig, MessagingSystem, NetworkError, PeerStatus};
+use crate::transport::Transport;
+
+/// how many messages a session may have waiting before the router drops its
+/// new ones
+pub const DEFAULT_SESSION_QUEUE_LEN: usize = 4096;
+
+#[derive(Default)]
+struct Sessions {
+    queues: HashMap<String, mpsc::Sender<EvalNetMsg>>,
+    /// whether the networkd has connected; sessions opened later are told at once
+    connected: bool,
+}
+
+/// Router owns the channels to the networkd and demultiplexes what comes in by
+/// session id. Every session has a bounded queue: when a slow session lets its
+/// queue fill up, its new messages are dropped (and retransmitted by their
+/// senders, since we do not acknowledge them) instead of holding up the others.
+/// Messages for sessions that are not open are dropped too.
+pub struct Router {
+    keypair: identity::Keypair,
+    addr_book: Pok3rAddrBook,
+    /// bounded sender channel towards the networkd, shared by all sessions
+    tx: mpsc::Sender<EvalNetMsg>,
+    sessions: Arc<Mutex<Sessions>>,
+    queue_len: usize,
+    /// messages dropped because their session was full or not open
+    dropped: Arc<AtomicU64>,
+}
+
+impl Router {
+    /// our peer id is the one of keypair, which must be in the addr book; every
+    /// session runs with the same parties
+    pub fn new(
+        keypair: identity::Keypair,
+        addr_book: Pok3rAddrBook,
+        tx: mpsc::Sender<EvalNetMsg>,
+        rx: mpsc::Receiver<EvalNetMsg>,
+    ) -> Self {
+        Self::new_with_queue_len(keypair, addr_book, tx, rx, DEFAULT_SESSION_QUEUE_LEN)
+    }
+
+    /// same as new, with queue_len messages allowed to wait for each session
+    pub fn new_with_queue_len(
+        keypair: identity::Keypair,
+        addr_book: Pok3rAddrBook,
+        tx: mpsc::Sender<EvalNetMsg>,
+        rx: mpsc::Receiver<EvalNetMsg>,
+        queue_len: usize,
+    ) -> Self {
+        assert!(queue_len > 0, "sessions need room for at least one message");
+        let sessions = Arc::new(Mutex::new(Sessions::default()));
+        let dropped = Arc::new(AtomicU64::new(0));
+        async_std::task::spawn(demultiplex(rx, sessions.clone(), dropped.clone()));
+        Router {
+            keypair,
+            addr_book,
+            tx,
+            sessions,
+            queue_len,
+            dropped,
+        }
+    }
+
+    /// starts taking the messages of session_id, which must not be open already;
+    /// returns once the networkd has connected. The session is closed when the
+    /// returned transport is dropped.
+    pub async fn open_session(&self, session_id: &str) -> Result<SessionTransport, NetworkError> {
+        let (mut queue, inbox) = mpsc::channel(self.queue_len);
+        {
+            let mut sessions = self.sessions.lock().unwrap();
+            if sessions.queues.contains_key(session_id) {
+                return Err(NetworkError::SessionOpen(session_id.to_string()));
+            }
+            if sessions.connected {
+                queue.try_send(connected_notice()).unwrap();
+            }
+            sessions.queues.insert(session_id.to_string(), queue);
+        }
+
+        let messaging = MessagingSystem::new_with_inbox(
+            self.keypair.clone(),
+            session_id,
+            self.addr_book.clone(),
+            self.tx.clone(),
+            Box::pin(inbox),
+            true,
+        )
+        .await
+        .inspect_err(|_| {
+            self.sessions.lock().unwrap().queues.remove(session_id);
+        })?;
+        Ok(SessionTransport {
+            messaging,
+            sessions: self.sessions.clone(),
+        })
+    }
+
+    /// ids of the sessions currently open
+    pub fn open_sessions(&self) -> Vec<String> {
+        let sessions = self.sessions.lock().unwrap();
+        sessions.queues.keys().cloned().collect()
+    }
+
+    /// how many messages were dropped because their session was full or not open
+    pub fn dropped_messages(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+/// what the networkd sends once it is connected
+fn connected_notice() -> EvalNetMsg {
+    EvalNetMsg::ConnectionEstablished {
+        success: true,
+        sender: None,
+        binary_payloads: false,
+        session: String::new(),
+        encoding_tag: 0,
+        info: None,
+        waiting: false,
+        signature: String::new(),
+    }
+}
+
+/// hands every message from the networkd to the queue of its session, without
+/// ever waiting on one
+async fn demultiplex(
+    mut rx: mpsc::Receiver<EvalNetMsg>,
+    sessions: Arc<Mutex<Sessions>>,
+    dropped: Arc<AtomicU64>,
+) {
+    while let Some(msg) = rx.next().await {
+        let mut sessions = sessions.lock().unwrap();
+        let session = match session_of(&msg) {
+            Some(session) => session.to_string(),
+            None => {
+                if let EvalNetMsg::ConnectionEstablished { success: true, .. } = msg {
+                    sessions.connected = true;
+                }
+                for queue in sessions.queues.values_mut() {
+                    let _ = queue.try_send(msg.clone());
+                }
+                continue;
+            }
+        };
+        let closed = match sessions.queues.get_mut(&session) {
+            Some(queue) => match queue.try_send(msg) {
+                Ok(()) => false,
+                Err(err) => {
+                    dropped.fetch_add(1, Ordering::Relaxed);
+                    err.is_disconnected()
+                }
+            },
+            None => {
+                dropped.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+        };
+        if closed {
+            sessions.queues.remove(&session);
+        }
+    }
+}
+
+/// the transport of one session of a Router; messages it sends are tagged with
+/// its session id
+pub struct SessionTransport {
+    messaging: MessagingSystem,
+    sessions: Arc<Mutex<Sessions>>,
+}
+
+impl SessionTransport {
+    /// sets how long recv_from_all waits for the other parties before giving up
+    pub fn set_recv_timeout(&mut self, timeout: Duration) {
+        self.messaging.set_recv_timeout(timeout);
+    }
+
+    /// sets how many rounds ahead of ours values are kept until we get there
+    pub fn set_round_horizon(&mut self, horizon: u64) {
+        self.messaging.set_round_horizon(horizon);
+    }
+
+    /// turns on heartbeats, which every party of the session must do
+    pub fn set_heartbeat_config(&mut self, config: HeartbeatConfig) {
+        self.messaging.set_heartbeat_config(config);
+    }
+
+    /// keeps a journal of the session at path, reloading what it holds; see
+    /// MessagingSystem::open_journal
+    pub fn open_journal(&mut self, path: &Path) -> Result<(), JournalError> {
+        self.messaging.open_journal(path)
+    }
+}
+
+/// closes the session, leaving the other sessions of the router alone
+impl Drop for SessionTransport {
+    fn drop(&mut self) {
+        if let Ok(mut sessions) = self.sessions.lock() {
+            sessions.queues.remove(self.messaging.session_id());
+        }
+    }
+}
+
+#[async_trait]
+impl Transport for SessionTransport {
+    fn get_my_id(&self) -> u64 {
+        self.messaging.get_my_id()
+    }
+
+    fn addr_book(&self) -> &Pok3rAddrBook {
+        &self.messaging.addr_book
+    }
+
+    fn session_id(&self) -> &str {
+        self.messaging.session_id()
+    }
+
+    fn metrics(&self) -> Arc<Metrics> {
+        self.messaging.metrics()
+    }
+
+    fn sign(&self, bytes: &[u8]) -> Vec<u8> {
+        self.messaging.sign_bytes(bytes)
+    }
+
+    async fn send_to_all<H, V>(&mut self, handles: &[H], values: &[V]) -> Result<(), NetworkError>
+    where
+        H: AsRef<str> + Sync,
+        V: AsRef<str> + Sync,
+    {
+        self.messaging.send_to_all(handles, values).await
+    }
+
+    async fn send_to_party<H, V>(
+        &mut self,
+        recipient_id: u64,
+        handles: &[H],
+        values: &[V],
+    ) -> Result<(), NetworkError>
+    where
+        H: AsRef<str> + Sync,
+        V: AsRef<str> + Sync,
+    {
+        self.messaging
+            .send_to_party(recipient_id, handles, values)
+            .await
+    }
+
+    async fn send_to_role<H, V>(
+        &mut self,
+        role: PeerRole,
+        handles: &[H],
+        values: &[V],
+    ) -> Result<(), NetworkError>
+    where
+        H: AsRef<str> + Sync,
+        V: AsRef<str> + Sync,
+    {
+        self.messaging.send_to_role(role, handles, values).await
+    }
+
+    async fn send_to_player<H, V>(
+        &mut self,
+        player: &Pok3rPeerId,
+        handles: &[H],
+        values: &[V],
+    ) -> Result<(), NetworkError>
+    where
+        H: AsRef<str> + Sync,
+        V: AsRef<str> + Sync,
+    {
+        self.messaging.send_to_player(player, handles, values).await
+    }
+
+    async fn send_batch_to_party<H: AsRef<str> + Sync>(
+        &mut self,
+        recipient_id: u64,
+        handles: &[H],
+        kind: PayloadKind,
+        values: &[Vec<u8>],
+    ) -> Result<(), NetworkError> {
+        self.messaging
+            .send_batch_to_party(recipient_id, handles, kind, values)
+            .await
+    }
+
+    async fn send_batch_to_all<H: AsRef<str> + Sync>(
+        &mut self,
+        handles: &[H],
+        kind: PayloadKind,
+        values: &[Vec<u8>],
+    ) -> Result<(), NetworkError> {
+        self.messaging
+            .send_batch_to_all(handles, kind, values)
+            .await
+    }
+
+    async fn recv_from_all(
+        &mut self,
+        identifier: &str,
+    ) -> Result<HashMap<u64, String>, NetworkError> {
+        self.messaging.recv_from_all(identifier).await
+    }
+
+    async fn recv_many_from_all<I: AsRef<str> + Sync>(
+        &mut self,
+        identifiers: &[I],
+    ) -> Result<HashMap<String, HashMap<u64, String>>, NetworkError> {
+        self.messaging.recv_many_from_all(identifiers).await
+    }
+
+    async fn recv_from_all_with_deadline(
+        &mut self,
+        identifier: &str,
+        deadline: Instant,
+    ) -> Result<HashMap<u64, String>, NetworkError> {
+        self.messaging
+            .recv_from_all_with_deadline(identifier, deadline)
+            .await
+    }
+
+    async fn recv_from_some(
+        &mut self,
+        identifier: &str,
+        count: usize,
+    ) -> Result<HashMap<u64, String>, NetworkError> {
+        self.messaging.recv_from_some(identifier, count).await
+    }
+
+    async fn recv_quorum(
+        &mut self,
+        identifier: &str,
+        quorum: usize,
+    ) -> Result<HashMap<u64, String>, NetworkError> {
+        self.messaging.recv_quorum(identifier, quorum).await
+    }
+
+    fn take_late_values(&mut self) -> Vec<(String, u64, String)> {
+        self.messaging.take_late_values()
+    }
+
+    async fn wait_for_late_values(&mut self, deadline: Instant) -> Result<(), NetworkError> {
+        self.messaging.wait_for_late_values(deadline).await
+    }
+
+    async fn recv_from_party(
+        &mut self,
+        sender_id: u64,
+        handle: &str,
+    ) -> Result<String, NetworkError> {
+        self.messaging.recv_from_party(sender_id, handle).await
+    }
+
+    async fn flush(&mut self) -> Result<(), NetworkError> {
+        self.messaging.flush().await
+    }
+
+    fn set_round(&mut self, round: u64) {
+        self.messaging.set_round(round)
+    }
+
+    async fn handshake(&mut self, info: &PeerInfo) -> Result<(), NetworkError> {
+        self.messaging.handshake(info).await
+    }
+
+    async fn broadcast_abort(&mut self, reason: &str) {
+        self.messaging.broadcast_abort(reason).await
+    }
+
+    fn update_addr_book(&mut self, addr_book: Pok3rAddrBook) -> Result<(), NetworkError> {
+        self.messaging.update_addr_book(addr_book)
+    }
+
+    fn peer_status(&mut self) -> HashMap<u64, PeerStatus> {
+        self.messaging.peer_status()
+    }
+
+    fn on_peer_down(&mut self) -> mpsc::UnboundedReceiver<u64> {
+        self.messaging.on_peer_down()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Router;
+    use crate::address_book::{keypair_from_seed, AddrBook, Pok3rPeerId};
+    use crate::common::{EvalNetMsg, F};
+    use crate::evaluator::Evaluator;
+    use crate::network::{decode_wire_msg, encode_wire_msg, NetworkError, DEFAULT_QUEUE_CAPACITY};
+    use crate::transport::Transport;
+    use crate::wire::Wire;
+    use futures::future::{join_all, try_join_all};
+    use futures::{channel::mpsc, StreamExt};
+    use libp2p::PeerId;
+
+    /// n routers, each behind a relay playing the role of its networkd
+    fn connected_routers(n: usize) -> Vec<Router> {
+        let keypairs: Vec<_> = (0..n).map(|i| keypair_from_seed(i as u8 + 1)).collect();
+        let peer_ids: Vec<Pok3rPeerId> = keypairs
+            .iter()
+            .map(|keypair| PeerId::from(keypair.public()).to_base58())
+            .collect();
+        let addr_book = AddrBook::from_peer_ids(peer_ids.clone()).unwrap();
+
+        let (mut n2r_txs, n2r_rxs): (Vec<_>, Vec<_>) = (0..n)
+            .map(|_| mpsc::channel::<EvalNetMsg>(DEFAULT_QUEUE_CAPACITY))
+            .unzip();
+        let mut routers = vec![];
+        for (k, (keypair, n2r_rx)) in keypairs.into_iter().zip(n2r_rxs).enumerate() {
+            let (r2n_tx, mut r2n_rx) = mpsc::channel::<EvalNetMsg>(DEFAULT_QUEUE_CAPACITY);
+            let mut peers: Vec<(Pok3rPeerId, mpsc::Sender<EvalNetMsg>)> = (0..n)
+                .filter(|j| *j != k)
+                .map(|j| (peer_ids[j].clone(), n2r_txs[j].clone()))
+                .collect();
+            async_std::task::spawn(async move {
+                while let Some(msg) = r2n_rx.next().await {
+                    let data = encode_wire_msg(&msg).unwrap();
+                    for (peer_id, tx) in peers.iter_mut() {
+                        if let EvalNetMsg::DirectValue { recipient, .. }
+                        | EvalNetMsg::DirectBatchBinary { recipient, .. }
+                        | EvalNetMsg::Ack { recipient, .. }
+                        | EvalNetMsg::Heartbeat {
+                            recipient: Some(recipient),
+                            ..
+                        } = &msg
+                        {
+                            if !recipient.eq(peer_id) {
+                                continue;
+                            }
+                        }
+                        let _ = tx.try_send(decode_wire_msg(&data).unwrap());
+                    }
+                }
+            });
+            routers.push(Router::new(keypair, addr_book.clone(), r2n_tx, n2r_rx));
+        }
+        for tx in n2r_txs.iter_mut() {
+            tx.try_send(super::connected_notice()).unwrap();
+        }
+        routers
+    }
+
+    /// opens x * r for 8 values x of the table, checking the results
+    async fn mult_round<T: Transport>(evaluator: &mut Evaluator<T>, table: u64) -> bool {
+        let xs = (0..8u64)
+            .map(|i| evaluator.fixed_wire_handle(F::from(100 * table + i)))
+            .collect::<Vec<Wire>>();
+        let rs = (0..8).map(|_| evaluator.ran()).collect::<Vec<Wire>>();
+        let xrs = evaluator.batch_mult(&xs, &rs).await.unwrap();
+        let opened_xrs = evaluator.batch_output_wire(&xrs).await.unwrap();
+        let opened_rs = evaluator.batch_output_wire(&rs).await.unwrap();
+        (0..8).all(|i| opened_xrs[i] == F::from(100 * table + i as u64) * opened_rs[i])
+    }
+
+    #[test]
+    fn test_two_tables_over_one_router() {
+        async_std::task::block_on(async {
+            let routers = connected_routers(3);
+            let tables = ["table-1", "table-2"];
+            let transports =
+                try_join_all(tables.iter().flat_map(|table| {
+                    routers.iter().map(move |router| router.open_session(table))
+                }))
+                .await
+                .unwrap();
+            assert_eq!(routers[0].open_sessions().len(), 2);
+            assert!(matches!(
+                routers[0].open_session("table-1").await,
+                Err(NetworkError::SessionOpen(session)) if session == "table-1"
+            ));
+
+            let mut evaluators = try_join_all(
+                transports
+                    .into_iter()
+                    .enumerate()
+                    .map(|(k, transport)| Evaluator::new_with_seed(transport, [k as u8 + 1; 32])),
+            )
+            .await
+            .unwrap();
+
+            // both tables at once
+            let done = join_all(
+                evaluators
+                    .iter_mut()
+                    .enumerate()
+                    .map(|(k, evaluator)| mult_round(evaluator, k as u64 / 3)),
+            )
+            .await;
+            assert!(done.into_iter().all(|ok| ok));
+
+            // closing the first table leaves the second one running
+            let mut second = evaluators.split_off(3);
+            drop(evaluators);
+            assert_eq!(routers[0].open_sessions(), vec![String::from("table-2")]);
+            let done = join_all(second.iter_mut().map(|evaluator| mult_round(evaluator, 1))).await;
+            assert!(done.into_iter().all(|ok| ok));
+        });
+    }
+}
@@ -0,0 +1,442 @@
+//! An HTTP control surface for parties embedded in a server. An orchestrator
+//! creates sessions and drives them with
+//!
+//! ```text
+//! POST /session                 {"session", "players", "perm_size", "deck_size"}
+//! POST /session/:id/shuffle
+//! POST /session/:id/deal        {"player"}
+//! POST /session/:id/reveal      {"positions"}
+//! GET  /session/:id/metrics
+//! GET  /session/:id/transcript
+//! ```
+//!
+//! which answer with the JSON events of pok3r::node. Each session is owned by a
+//! thread of its own, which takes the requests against it one at a time.
+
+use async_std::task;
+use async_trait::async_trait;
+use futures::channel::{mpsc, oneshot};
+use futures::StreamExt;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use tide::{Body, Response, StatusCode};
+
+use crate::common::{Curve, ProtocolParams, G2};
+use crate::evaluator::Evaluator;
+use crate::hash::GameContext;
+use crate::kzg::UniversalParams;
+use crate::node::{
+    self, default_deck_size, default_perm_size, error_event, Command, Node, NodeError,
+};
+use crate::router::{Router, SessionTransport};
+use crate::shuffler::DealError;
+use crate::transport::Transport;
+
+/// Connector opens the transport of each session the server is asked to create
+#[async_trait]
+pub trait Connector: Send + Sync + 'static {
+    type Transport: Transport + 'static;
+
+    async fn connect(&self, session: &str) -> Result<Self::Transport, String>;
+}
+
+/// every session runs over the one network connection of the router
+#[async_trait]
+impl Connector for Router {
+    type Transport = SessionTransport;
+
+    async fn connect(&self, session: &str) -> Result<SessionTransport, String> {
+        self.open_session(session)
+            .await
+            .map_err(|err| err.to_string())
+    }
+}
+
+/// the body of POST /session
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NewSession {
+    /// identifies the game; must be the same for all parties
+    pub session: String,
+    pub players: usize,
+    #[serde(default = "default_perm_size")]
+    pub perm_size: usize,
+    #[serde(default = "default_deck_size")]
+    pub deck_size: usize,
+}
+
+#[derive(Deserialize)]
+struct Deal {
+    player: usize,
+}
+
+#[derive(Deserialize)]
+struct Reveal {
+    positions: Vec<usize>,
+}
+
+enum SessionRequest {
+    Command(Command),
+    Metrics,
+    Transcript,
+}
+
+type Reply = oneshot::Sender<Result<Value, NodeError>>;
+type Requests = mpsc::UnboundedSender<(SessionRequest, Reply)>;
+
+/// Sessions is the state of the server: the sessions it runs, each through the
+/// channel to the thread that owns it
+pub struct Sessions<C: Connector> {
+    connector: C,
+    pp: UniversalParams<Curve>,
+    /// the key cards are dealt under
+    pk: G2,
+    /// None while the session is being created
+    open: Mutex<HashMap<String, Option<Requests>>>,
+}
+
+pub type RpcServer<C> = tide::Server<Arc<Sessions<C>>>;
+
+/// the HTTP server of a party, whose sessions run over the transports connector
+/// opens; serve it with listen
+pub fn server<C: Connector>(connector: C, pp: UniversalParams<Curve>, pk: G2) -> RpcServer<C> {
+    let mut app = tide::with_state(Arc::new(Sessions {
+        connector,
+        pp,
+        pk,
+        open: Mutex::new(HashMap::new()),
+    }));
+    app.at("/session").post(create_session::<C>);
+    app.at("/session/:id/shuffle").post(shuffle::<C>);
+    app.at("/session/:id/deal").post(deal::<C>);
+    app.at("/session/:id/reveal").post(reveal::<C>);
+    app.at("/session/:id/metrics").get(metrics::<C>);
+    app.at("/session/:id/transcript").get(transcript::<C>);
+    app
+}
+
+impl<C: Connector> Sessions<C> {
+    /// claims id for a session being created; fails if it is taken
+    fn reserve(&self, id: &str) -> bool {
+        let mut open = self.open.lock().unwrap();
+        if open.contains_key(id) {
+            return false;
+        }
+        open.insert(id.to_string(), None);
+        true
+    }
+
+    /// runs the session on a thread of its own, once every party has joined it
+    /// and preprocessing is done
+    async fn start(&self, new: &NewSession, params: ProtocolParams) -> Result<(), String> {
+        let transport = self.connector.connect(&new.session).await?;
+        let (requests, rx) = mpsc::unbounded();
+        let (ready_tx, ready_rx) = oneshot::channel();
+        let pp = self.pp.clone();
+        let (pk, players) = (self.pk, new.players);
+        let ctx = GameContext::from_label(&new.session);
+        thread::spawn(move || {
+            task::block_on(async move {
+                let mut evaluator = match Evaluator::new_for(
+                    transport,
+                    params,
+                    node::resources(&params),
+                    0,
+                )
+                .await
+                {
+                    Ok(evaluator) => evaluator,
+                    Err(err) => {
+                        let _ = ready_tx.send(Err(err.to_string()));
+                        return;
+                    }
+                };
+                evaluator.enable_audit();
+                let _ = ready_tx.send(Ok(()));
+                serve(Node::new(evaluator, pp, ctx, pk, players), rx).await;
+            })
+        });
+        ready_rx
+            .await
+            .map_err(|_| String::from("the session stopped while starting"))??;
+
+        self.open
+            .lock()
+            .unwrap()
+            .insert(new.session.clone(), Some(requests));
+        Ok(())
+    }
+
+    fn requests(&self, id: &str) -> Option<Requests> {
+        self.open.lock().unwrap().get(id).cloned().flatten()
+    }
+}
+
+/// answers the requests against a session, in the order they come
+async fn serve<T: Transport>(
+    mut node: Node<T>,
+    mut requests: mpsc::UnboundedReceiver<(SessionRequest, Reply)>,
+) {
+    while let Some((request, reply)) = requests.next().await {
+        let result = match request {
+            SessionRequest::Command(command) => node.execute(&command).await,
+            SessionRequest::Metrics => Ok(node.metrics()),
+            SessionRequest::Transcript => Ok(node.transcript()),
+        };
+        let _ = reply.send(result);
+    }
+}
+
+fn respond(status: StatusCode, event: &Value) -> tide::Result {
+    Ok(Response::builder(status)
+        .body(Body::from_json(event)?)
+        .build())
+}
+
+/// failures of the session itself are the server's; the rest are the client's
+fn status_of(err: &NodeError) -> StatusCode {
+    match err {
+        NodeError::Io(_)
+        | NodeError::Evaluator(_)
+        | NodeError::Deal(DealError::Evaluator(_))
+        | NodeError::NotACard(_) => StatusCode::InternalServerError,
+        _ => StatusCode::BadRequest,
+    }
+}
+
+async fn create_session<C: Connector>(mut req: tide::Request<Arc<Sessions<C>>>) -> tide::Result {
+    let new: NewSession = req.body_json().await?;
+    let params = match ProtocolParams::new(new.perm_size, new.deck_size) {
+        Ok(params) => params,
+        Err(err) => return respond(StatusCode::BadRequest, &error_event(&err)),
+    };
+    let sessions = req.state();
+    // refused here rather than by the session, which would fail only once every
+    // party has joined and preprocessed
+    if new.players == 0 {
+        return respond(
+            StatusCode::BadRequest,
+            &error_event(&"a session needs at least one player"),
+        );
+    }
+    if sessions.pp.max_degree() < 2 * params.perm_size() {
+        let message = format!(
+            "the params of degree {} are too small for a permutation of {}",
+            sessions.pp.max_degree(),
+            params.perm_size()
+        );
+        return respond(StatusCode::BadRequest, &error_event(&message));
+    }
+    if !sessions.reserve(&new.session) {
+        let message = format!("session {} exists already", new.session);
+        return respond(StatusCode::Conflict, &error_event(&message));
+    }
+
+    match sessions.start(&new, params).await {
+        Ok(()) => respond(
+            StatusCode::Created,
+            &json!({ "event": "created", "session": new.session }),
+        ),
+        Err(err) => {
+            sessions.open.lock().unwrap().remove(&new.session);
+            respond(StatusCode::InternalServerError, &error_event(&err))
+        }
+    }
+}
+
+/// hands request to the session named in the path of req, and answers with its outcome
+async fn call<C: Connector>(
+    req: &tide::Request<Arc<Sessions<C>>>,
+    request: SessionRequest,
+) -> tide::Result {
+    let id = req.param("id")?;
+    let requests = match req.state().requests(id) {
+        Some(requests) => requests,
+        None => {
+            let message = format!("no session {}", id);
+            return respond(StatusCode::NotFound, &error_event(&message));
+        }
+    };
+
+    let (reply, outcome) = oneshot::channel();
+    if requests.unbounded_send((request, reply)).is_err() {
+        return respond(
+            StatusCode::InternalServerError,
+            &error_event(&"the session has stopped"),
+        );
+    }
+    match outcome.await {
+        Ok(Ok(event)) => respond(StatusCode::Ok, &event),
+        Ok(Err(err)) => respond(status_of(&err), &error_event(&err)),
+        Err(_) => respond(
+            StatusCode::InternalServerError,
+            &error_event(&"the session has stopped"),
+        ),
+    }
+}
+
+async fn shuffle<C: Connector>(req: tide::Request<Arc<Sessions<C>>>) -> tide::Result {
+    call(&req, SessionRequest::Command(Command::Shuffle)).await
+}
+
+async fn deal<C: Connector>(mut req: tide::Request<Arc<Sessions<C>>>) -> tide::Result {
+    let Deal { player } = req.body_json().await?;
+    call(&req, SessionRequest::Command(Command::Deal(player))).await
+}
+
+async fn reveal<C: Connector>(mut req: tide::Request<Arc<Sessions<C>>>) -> tide::Result {
+    let Reveal { positions } = req.body_json().await?;
+    call(&req, SessionRequest::Command(Command::Reveal(positions))).await
+}
+
+async fn metrics<C: Connector>(req: tide::Request<Arc<Sessions<C>>>) -> tide::Result {
+    call(&req, SessionRequest::Metrics).await
+}
+
+async fn transcript<C: Connector>(req: tide::Request<Arc<Sessions<C>>>) -> tide::Result {
+    call(&req, SessionRequest::Transcript).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{server, Connector, RpcServer};
+    use crate::common::{DealtCard, PermutationProof, ProtocolParams, G1};
+    use crate::encoding::{decode_from_bytes, ProofEncoding};
+    use crate::hash::GameContext;
+    use crate::shuffler::{compute_keyper_keys, compute_params, verify_dealt_card};
+    use crate::transport::InMemoryTransport;
+    use async_trait::async_trait;
+    use futures::future::join_all;
+    use serde_json::{json, Value};
+    use std::sync::Mutex;
+    use tide::http::{Method, Request, Response, Url};
+    use tide::{Body, StatusCode};
+
+    /// hands out the transport of a party in a network made in advance, once
+    struct InMemoryConnector(Mutex<Option<InMemoryTransport>>);
+
+    #[async_trait]
+    impl Connector for InMemoryConnector {
+        type Transport = InMemoryTransport;
+
+        async fn connect(&self, _session: &str) -> Result<InMemoryTransport, String> {
+            self.0
+                .lock()
+                .unwrap()
+                .take()
+                .ok_or_else(|| String::from("the party is in a session already"))
+        }
+    }
+
+    async fn call(
+        app: &RpcServer<InMemoryConnector>,
+        method: Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, Value) {
+        let url = Url::parse(&format!("http://party{}", path)).unwrap();
+        let mut req = Request::new(method, url);
+        if let Some(body) = body {
+            req.set_body(Body::from_json(&body).unwrap());
+        }
+        let mut res: Response = app.respond(req).await.unwrap();
+        (res.status(), res.body_json().await.unwrap())
+    }
+
+    /// makes the same call at every party at once
+    async fn call_all(
+        apps: &[RpcServer<InMemoryConnector>],
+        method: Method,
+        path: &str,
+        body: Option<Value>,
+    ) -> Vec<(StatusCode, Value)> {
+        join_all(apps.iter().map(|app| call(app, method, path, body.clone()))).await
+    }
+
+    #[test]
+    fn test_three_parties_driven_over_rpc() {
+        let params = ProtocolParams::new(16, 10).unwrap();
+        let pp = compute_params();
+        let (_, pk) = compute_keyper_keys();
+        let bytes = |value: &Value| bs58::decode(value.as_str().unwrap()).into_vec().unwrap();
+
+        async_std::task::block_on(async {
+            let apps = InMemoryTransport::new_network(3)
+                .await
+                .into_iter()
+                .map(|transport| {
+                    let connector = InMemoryConnector(Mutex::new(Some(transport)));
+                    server(connector, pp.clone(), pk)
+                })
+                .collect::<Vec<_>>();
+
+            // nothing runs before the session is created, and bad params are refused
+            let (status, _) = call(&apps[0], Method::Post, "/session/hand/shuffle", None).await;
+            assert_eq!(status, StatusCode::NotFound);
+            let bad = json!({ "session": "hand", "players": 2, "perm_size": 12 });
+            let (status, event) = call(&apps[0], Method::Post, "/session", Some(bad)).await;
+            assert_eq!(status, StatusCode::BadRequest);
+            assert_eq!(event["event"], "error");
+            let bad = json!({ "session": "hand", "players": 0 });
+            let (status, _) = call(&apps[0], Method::Post, "/session", Some(bad)).await;
+            assert_eq!(status, StatusCode::BadRequest);
+            // beyond the degree of the params of the server
+            let bad = json!({ "session": "hand", "players": 2, "perm_size": 1024 });
+            let (status, event) = call(&apps[0], Method::Post, "/session", Some(bad)).await;
+            assert_eq!(status, StatusCode::BadRequest);
+            assert!(event["message"].as_str().unwrap().contains("too small"));
+
+            let new = json!({ "session": "hand", "players": 2, "perm_size": 16, "deck_size": 10 });
+            for (status, _) in call_all(&apps, Method::Post, "/session", Some(new.clone())).await {
+                assert_eq!(status, StatusCode::Created);
+            }
+            let (status, _) = call(&apps[0], Method::Post, "/session", Some(new)).await;
+            assert_eq!(status, StatusCode::Conflict);
+
+            let shuffled = call_all(&apps, Method::Post, "/session/hand/shuffle", None).await;
+            assert!(shuffled.iter().all(|outcome| *outcome == shuffled[0]));
+            let (status, event) = &shuffled[0];
+            assert_eq!(*status, StatusCode::Ok);
+            let proof = PermutationProof::from_bytes(&bytes(&event["proof"])).unwrap();
+            let commitment: G1 = decode_from_bytes(&bytes(&event["commitment"])).unwrap();
+            assert!(proof.f_com == commitment && proof.verify(&pp, &params));
+
+            let deal = Some(json!({ "player": 1 }));
+            let dealt = call_all(&apps, Method::Post, "/session/hand/deal", deal).await;
+            let (status, event) = &dealt[0];
+            assert_eq!(*status, StatusCode::Ok);
+            let card = DealtCard::from_bytes(&bytes(&event["card"])).unwrap();
+            let ctx = GameContext::from_label("hand");
+            assert!(verify_dealt_card(&pp, &params, &ctx, &commitment, &card));
+
+            // a refused request leaves the session as it was
+            let deal = Some(json!({ "player": 2 }));
+            for (status, _) in call_all(&apps, Method::Post, "/session/hand/deal", deal).await {
+                assert_eq!(status, StatusCode::BadRequest);
+            }
+            let reveal = Some(json!({ "positions": [10, 11] }));
+            let revealed = call_all(&apps, Method::Post, "/session/hand/reveal", reveal).await;
+            assert!(revealed.iter().all(|outcome| *outcome == revealed[0]));
+            assert_eq!(revealed[0].1["cards"].as_array().unwrap().len(), 2);
+
+            for (status, metrics) in
+                call_all(&apps, Method::Get, "/session/hand/metrics", None).await
+            {
+                assert_eq!(status, StatusCode::Ok);
+                assert!(metrics.is_object());
+            }
+            for (status, transcript) in
+                call_all(&apps, Method::Get, "/session/hand/transcript", None).await
+            {
+                assert_eq!(status, StatusCode::Ok);
+                let records = transcript["records"].as_array().unwrap();
+                assert!(records
+                    .iter()
+                    .any(|record| record["entry"].get("PermutationProof").is_some()));
+            }
+        });
+    }
+}
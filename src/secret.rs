@@ -0,0 +1,160 @@
+//! Scrubbing of secret shares: triples, random sharings and wire shares are
+//! overwritten with zeros once they are dropped, so that a core dump, swap, or a
+//! read past a buffer elsewhere in the process does not expose them afterwards.
+//! Copies the allocator made while a vector or map grew are out of reach.
+
+use ark_ff::{Field, Fp, FpConfig};
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::ops::{Deref, DerefMut};
+
+/// overwrites the limbs of value with zeros, with writes the compiler may not
+/// elide even though value is never read again
+pub fn zeroize_f<F: Field>(value: &mut F) {
+    value.zeroize();
+}
+
+/// something holding secret field elements, which scrub overwrites with zeros;
+/// containers are emptied as well
+pub trait Scrub {
+    fn scrub(&mut self);
+}
+
+/// the scalar field of every curve
+impl<P: FpConfig<N>, const N: usize> Scrub for Fp<P, N> {
+    fn scrub(&mut self) {
+        zeroize_f(self);
+    }
+}
+
+impl<A: Scrub, B: Scrub, C: Scrub> Scrub for (A, B, C) {
+    fn scrub(&mut self) {
+        self.0.scrub();
+        self.1.scrub();
+        self.2.scrub();
+    }
+}
+
+impl<K, V: Scrub> Scrub for (K, V) {
+    fn scrub(&mut self) {
+        self.1.scrub();
+    }
+}
+
+impl<T: Scrub> Scrub for Vec<T> {
+    fn scrub(&mut self) {
+        self.iter_mut().for_each(Scrub::scrub);
+        self.clear();
+    }
+}
+
+impl<T: Scrub> Scrub for VecDeque<T> {
+    fn scrub(&mut self) {
+        self.iter_mut().for_each(Scrub::scrub);
+        self.clear();
+    }
+}
+
+impl<K: Eq + Hash, V: Scrub> Scrub for HashMap<K, V> {
+    fn scrub(&mut self) {
+        self.values_mut().for_each(Scrub::scrub);
+        self.clear();
+    }
+}
+
+/// owns secret values, and scrubs them when dropped
+#[derive(Clone, Default)]
+pub struct Secret<T: Scrub>(T);
+
+impl<T: Scrub> Secret<T> {
+    pub fn new(value: T) -> Self {
+        Secret(value)
+    }
+
+    /// moves the values out, leaving an empty container to be dropped
+    pub fn take(&mut self) -> T
+    where
+        T: Default,
+    {
+        std::mem::take(&mut self.0)
+    }
+}
+
+impl<T: Scrub> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Secret(value)
+    }
+}
+
+impl<T: Scrub> Deref for Secret<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.0
+    }
+}
+
+impl<T: Scrub> DerefMut for Secret<T> {
+    fn deref_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: Scrub> Drop for Secret<T> {
+    fn drop(&mut self) {
+        self.0.scrub();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{zeroize_f, Scrub, Secret};
+    use crate::common::F;
+    use ark_std::Zero;
+    use std::cell::Cell;
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    /// counts how many times it was scrubbed
+    struct Canary(Rc<Cell<usize>>);
+
+    impl Scrub for Canary {
+        fn scrub(&mut self) {
+            self.0.set(self.0.get() + 1);
+        }
+    }
+
+    #[test]
+    fn test_scrub_overwrites_and_empties() {
+        let mut x = F::from(42u64);
+        zeroize_f(&mut x);
+        assert!(x.is_zero());
+
+        let mut triple = (F::from(1u64), F::from(2u64), F::from(3u64));
+        triple.scrub();
+        assert!(triple.0.is_zero() && triple.1.is_zero() && triple.2.is_zero());
+
+        let mut shares: HashMap<u64, F> = (0..4u64).map(|i| (i, F::from(i + 1))).collect();
+        shares.scrub();
+        assert!(shares.is_empty());
+    }
+
+    #[test]
+    fn test_secret_scrubs_when_dropped() {
+        let scrubbed = Rc::new(Cell::new(0));
+        let mut secret = Secret::new(vec![Canary(scrubbed.clone()), Canary(scrubbed.clone())]);
+        secret.push(Canary(scrubbed.clone()));
+        assert_eq!(secret.len(), 3);
+        drop(secret);
+        assert_eq!(scrubbed.get(), 3);
+
+        // what take moved out is no longer the secret's to scrub
+        let scrubbed = Rc::new(Cell::new(0));
+        let mut secret = Secret::new(vec![Canary(scrubbed.clone())]);
+        let taken = secret.take();
+        drop(secret);
+        assert_eq!(scrubbed.get(), 0);
+        drop(Secret::from(taken));
+        assert_eq!(scrubbed.get(), 1);
+    }
+}
@@ -1,12 +1,51 @@
 #![allow(dead_code)]
 
+use ark_ff::PrimeField;
 use ark_poly::{univariate::DensePolynomial, Polynomial};
-use ark_std::UniformRand;
 use rand::Rng;
+use std::collections::HashSet;
+use std::{error, fmt};
 
-use crate::common::F;
+use crate::secret::zeroize_f;
+use crate::utils;
 
-pub fn share<R: Rng>(secret: &F, access: (u64, u64), rng: &mut R) -> Vec<(F, F)> {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ShamirError {
+    /// interpolation needs at least two shares
+    NotEnoughShares(usize),
+    /// two shares claim the same evaluation point
+    DuplicateId(u64),
+    /// evaluation point 0 holds the secret and is never a share
+    ZeroId,
+    /// a packed sharing of degree needed - 1 needs at least needed parties
+    TooFewParties { needed: u64, parties: u64 },
+    /// share_packed was given a different number of secrets than the pack size
+    PackSize { expected: u64, got: usize },
+}
+
+impl fmt::Display for ShamirError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ShamirError::NotEnoughShares(count) => {
+                write!(f, "cannot reconstruct from {} share(s)", count)
+            }
+            ShamirError::DuplicateId(id) => write!(f, "more than one share for id {}", id),
+            ShamirError::ZeroId => write!(f, "share ids must be nonzero"),
+            ShamirError::TooFewParties { needed, parties } => write!(
+                f,
+                "packed sharing needs {} parties, only {} take part",
+                needed, parties
+            ),
+            ShamirError::PackSize { expected, got } => {
+                write!(f, "expected {} secrets to pack, got {}", expected, got)
+            }
+        }
+    }
+}
+
+impl error::Error for ShamirError {}
+
+pub fn share<F: PrimeField, R: Rng>(secret: &F, access: (u64, u64), rng: &mut R) -> Vec<(F, F)> {
     let (t, n) = access;
 
     // let us sample a random degree t-1 polynomial.
@@ -16,13 +55,14 @@ pub fn share<R: Rng>(secret: &F, access: (u64, u64), rng: &mut R) -> Vec<(F, F)>
     coeffs[0] = *secret;
 
     // we now have all the right coefficients to define the polynomial
-    let poly = DensePolynomial { coeffs };
+    let mut poly = DensePolynomial { coeffs };
 
     // Shamir shares are just evaluations of our polynomial above
-
-    (1..=n)
+    let shares = (1..=n)
         .map(|x| (F::from(x), poly.evaluate(&F::from(x))))
-        .collect()
+        .collect();
+    poly.coeffs.iter_mut().for_each(zeroize_f);
+    shares
 }
 
 /*
@@ -31,42 +71,170 @@ pub fn share<R: Rng>(secret: &F, access: (u64, u64), rng: &mut R) -> Vec<(F, F)>
  * shares contains the polynomial points { (x,y) }, where x is
  * some field element, and y is the polynomial evaluation at x.
  */
-pub fn recover(shares: &Vec<(F, F)>) -> F {
+pub fn recover<F: PrimeField>(shares: &[(F, F)]) -> F {
     let xs: Vec<F> = shares.iter().map(|(x, _)| *x).collect();
     let ys: Vec<F> = shares.iter().map(|(_, y)| *y).collect();
 
     // compute lagrange coefficients w.r.t. x = 0.
     // we choose x = 0 because we encoded our secret at f(0)
-    let lagrange_coeffs = lagrange_coefficients(&xs[..], F::from(0));
+    let lagrange_coeffs = lagrange_coefficients(&xs[..], F::zero());
 
     //secret f(0) as a field element
     let secret = ys
         .iter()
         .zip(lagrange_coeffs.iter())
-        .fold(F::from(0), |acc, (a, b)| acc + (a * b));
+        .fold(F::zero(), |acc, (a, b)| acc + (*a * b));
 
     secret
 }
 
+/// the point of the i-th secret of a packed sharing; the shares live at 1, 2, ...
+/// so the secrets and the randomness go to 0, -1, -2, ...
+fn packing_point<F: PrimeField>(i: u64) -> F {
+    -F::from(i)
+}
+
+/// packs k secrets into one sharing among n parties: a random polynomial of degree
+/// t + k - 1 takes the secrets at k fixed points and random values at t others, and
+/// party i gets its value at i. Any t parties learn nothing, any t + k reconstruct.
+pub fn share_packed<F: PrimeField, R: Rng>(
+    secrets: &[F],
+    access: (u64, u64, u64),
+    rng: &mut R,
+) -> Result<Vec<(F, F)>, ShamirError> {
+    let (t, n, k) = access;
+    if secrets.len() as u64 != k {
+        return Err(ShamirError::PackSize {
+            expected: k,
+            got: secrets.len(),
+        });
+    }
+    if t + k > n {
+        return Err(ShamirError::TooFewParties {
+            needed: t + k,
+            parties: n,
+        });
+    }
+
+    let xs: Vec<F> = (0..t + k).map(packing_point).collect();
+    let mut ys = secrets.to_vec();
+    ys.extend((0..t).map(|_| F::rand(rng)));
+
+    Ok((1..=n)
+        .map(|x| {
+            let x = F::from(x);
+            let y = lagrange_coefficients(&xs, x)
+                .iter()
+                .zip(ys.iter())
+                .fold(F::zero(), |acc, (l, y)| acc + *l * y);
+            (x, y)
+        })
+        .collect())
+}
+
+/// recovers the k secrets of a packed sharing with t random points from any t + k
+/// of its shares, given as (id, share)
+pub fn reconstruct_packed<F: PrimeField>(
+    shares: &[(u64, F)],
+    t: u64,
+    k: u64,
+) -> Result<Vec<F>, ShamirError> {
+    if (shares.len() as u64) < t + k {
+        return Err(ShamirError::NotEnoughShares(shares.len()));
+    }
+    let mut seen = HashSet::new();
+    for (id, _) in shares {
+        if *id == 0 {
+            return Err(ShamirError::ZeroId);
+        }
+        if !seen.insert(*id) {
+            return Err(ShamirError::DuplicateId(*id));
+        }
+    }
+
+    let mut shares = shares.to_vec();
+    shares.sort_unstable_by_key(|(id, _)| *id);
+    shares.truncate((t + k) as usize);
+    let xs: Vec<F> = shares.iter().map(|(id, _)| F::from(*id)).collect();
+
+    Ok((0..k)
+        .map(|i| {
+            lagrange_coefficients(&xs, packing_point(i))
+                .iter()
+                .zip(shares.iter())
+                .fold(F::zero(), |acc, (l, (_, y))| acc + *l * y)
+        })
+        .collect())
+}
+
+/// reconstructs the secret from shares given as (id, share), where id is the
+/// (nonzero) point at which the sharing polynomial was evaluated
+pub fn reconstruct<F: PrimeField>(shares: &[(u64, F)]) -> Result<F, ShamirError> {
+    if shares.len() < 2 {
+        return Err(ShamirError::NotEnoughShares(shares.len()));
+    }
+    let mut seen = HashSet::new();
+    for (id, _) in shares {
+        if *id == 0 {
+            return Err(ShamirError::ZeroId);
+        }
+        if !seen.insert(*id) {
+            return Err(ShamirError::DuplicateId(*id));
+        }
+    }
+
+    let ids: Vec<u64> = shares.iter().map(|(id, _)| *id).collect();
+    let secret = shares
+        .iter()
+        .zip(lagrange_coeffs_at_zero::<F>(&ids))
+        .fold(F::zero(), |acc, ((_, y), coeff)| acc + coeff * y);
+    Ok(secret)
+}
+
+/// Lagrange coefficients at zero for the given share ids, so that the secret is
+/// the inner product of these with the shares; also works in the exponent.
+/// The ids must be distinct and nonzero.
+pub fn lagrange_coeffs_at_zero<F: PrimeField>(ids: &[u64]) -> Vec<F> {
+    let xs: Vec<F> = ids.iter().map(|id| F::from(*id)).collect();
+    lagrange_coefficients(&xs, F::zero())
+}
+
+/// same as lagrange_coeffs_at_zero, but for the share of id x, so that a degree
+/// len - 1 sharing through the given shares can be checked against another share
+pub fn lagrange_coeffs_at<F: PrimeField>(ids: &[u64], x: u64) -> Vec<F> {
+    let xs: Vec<F> = ids.iter().map(|id| F::from(*id)).collect();
+    lagrange_coefficients(&xs, F::from(x))
+}
+
 /*
  * Naive lagrange interpolation over the input x-coordinates.
  * This method computes the lagrange coefficients, which should
  * be used to compute an inner product with the y-coordinates.
  * reference: https://en.wikipedia.org/wiki/Lagrange_polynomial
 */
-fn lagrange_coefficients(xs: &[F], x: F) -> Vec<F> {
-    let mut output = Vec::new();
+fn lagrange_coefficients<F: PrimeField>(xs: &[F], x: F) -> Vec<F> {
+    let mut numerators = Vec::new();
+    let mut denominators = Vec::new();
 
     for (i, &x_i) in xs.iter().enumerate() {
-        let mut l_i = F::from(1);
+        let mut num = F::one();
+        let mut den = F::one();
         for (j, &x_j) in xs.iter().enumerate() {
             if i != j {
-                l_i *= (x - x_j) / (x_i - x_j);
+                num *= x - x_j;
+                den *= x_i - x_j;
             }
         }
-        output.push(l_i);
+        numerators.push(num);
+        denominators.push(den);
     }
-    output
+
+    utils::batch_invert(&mut denominators).expect("x-coordinates must be distinct");
+    numerators
+        .iter()
+        .zip(denominators.iter())
+        .map(|(num, den_inv)| *num * den_inv)
+        .collect()
 }
 
 #[cfg(test)]
@@ -77,7 +245,25 @@ mod tests {
     use rand::Rng;
     use rand_chacha::rand_core::SeedableRng;
 
-    use super::{recover, share};
+    use super::{
+        lagrange_coeffs_at_zero, reconstruct, reconstruct_packed, recover, share, share_packed,
+        ShamirError,
+    };
+
+    /// all subsets of 0..n with k elements
+    fn subsets(n: usize, k: usize) -> Vec<Vec<usize>> {
+        if k == 0 {
+            return vec![vec![]];
+        }
+        (k - 1..n)
+            .flat_map(|last| {
+                subsets(last, k - 1).into_iter().map(move |mut subset| {
+                    subset.push(last);
+                    subset
+                })
+            })
+            .collect()
+    }
 
     #[test]
     fn test_shamir_correctness() {
@@ -97,4 +283,97 @@ mod tests {
 
         assert_eq!(secret, recovered);
     }
+
+    #[test]
+    fn test_reconstruct_from_every_subset() {
+        let mut rng = thread_rng();
+
+        for _ in 0..8 {
+            let n = rng.gen_range(3..=7u64);
+            // share uses t coefficients, so any t shares determine the secret
+            let t = rng.gen_range(2..=n);
+            let secret = F::rand(&mut rng);
+            let shares = share(&secret, (t, n), &mut rng)
+                .into_iter()
+                .enumerate()
+                .map(|(i, (_, y))| (i as u64 + 1, y))
+                .collect::<Vec<(u64, F)>>();
+
+            for subset in subsets(n as usize, t as usize) {
+                let chosen = subset.iter().map(|&i| shares[i]).collect::<Vec<(u64, F)>>();
+                assert_eq!(reconstruct(&chosen), Ok(secret));
+            }
+            // with one share fewer, interpolation lands anywhere but on the secret
+            for subset in subsets(n as usize, t as usize - 1) {
+                let chosen = subset.iter().map(|&i| shares[i]).collect::<Vec<(u64, F)>>();
+                if chosen.len() >= 2 {
+                    assert_ne!(reconstruct(&chosen), Ok(secret));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_reconstruct_rejects_bad_ids() {
+        let (a, b) = (F::from(3u64), F::from(5u64));
+        assert_eq!(reconstruct::<F>(&[]), Err(ShamirError::NotEnoughShares(0)));
+        assert_eq!(reconstruct(&[(1, a)]), Err(ShamirError::NotEnoughShares(1)));
+        assert_eq!(
+            reconstruct(&[(1, a), (2, b), (1, b)]),
+            Err(ShamirError::DuplicateId(1))
+        );
+        assert_eq!(reconstruct(&[(0, a), (2, b)]), Err(ShamirError::ZeroId));
+    }
+
+    #[test]
+    fn test_lagrange_coeffs_sum_to_one() {
+        // interpolating the constant polynomial 1 gives 1
+        let coeffs = lagrange_coeffs_at_zero(&[2, 3, 7, 11]);
+        assert_eq!(coeffs.iter().sum::<F>(), F::from(1u64));
+    }
+
+    #[test]
+    fn test_packed_round_trip() {
+        let mut rng = thread_rng();
+        let secrets = (0..8).map(|_| F::rand(&mut rng)).collect::<Vec<F>>();
+
+        let shares = share_packed(&secrets, (3, 16, 8), &mut rng)
+            .unwrap()
+            .into_iter()
+            .enumerate()
+            .map(|(i, (_, y))| (i as u64 + 1, y))
+            .collect::<Vec<(u64, F)>>();
+        assert_eq!(shares.len(), 16);
+        assert_eq!(reconstruct_packed(&shares, 3, 8), Ok(secrets.clone()));
+        assert_eq!(reconstruct_packed(&shares[5..], 3, 8), Ok(secrets));
+        assert_eq!(
+            reconstruct_packed(&shares[..15], 3, 8).ok(),
+            reconstruct_packed(&shares[1..], 3, 8).ok()
+        );
+        assert_eq!(
+            reconstruct_packed(&shares[..10], 3, 8),
+            Err(ShamirError::NotEnoughShares(10))
+        );
+    }
+
+    #[test]
+    fn test_packed_degree_must_fit() {
+        let mut rng = thread_rng();
+        let secrets = (0..64).map(|_| F::rand(&mut rng)).collect::<Vec<F>>();
+
+        assert_eq!(
+            share_packed(&secrets, (1, 16, 64), &mut rng),
+            Err(ShamirError::TooFewParties {
+                needed: 65,
+                parties: 16
+            })
+        );
+        assert_eq!(
+            share_packed(&secrets[..7], (1, 16, 8), &mut rng),
+            Err(ShamirError::PackSize {
+                expected: 8,
+                got: 7
+            })
+        );
+    }
 }
@@ -0,0 +1,242 @@
+//! Polynomials with secret-shared coefficients. A SharedPoly keeps our share of
+//! the coefficients; since commitments, evaluations and opening proofs are
+//! linear in the coefficients, each party computes them on its share, and the
+//! parties open the results the way they open wires.
+
+use ark_poly::{univariate::DensePolynomial, GeneralEvaluationDomain, Polynomial};
+use std::ops::{Mul, Sub};
+
+use crate::common::{Curve, CurveConfig, KzgOn};
+use crate::evaluator::{Evaluator, EvaluatorError};
+use crate::kzg::{KzgError, UniversalParams};
+use crate::transport::Transport;
+use crate::utils;
+use crate::wire::Wire;
+
+/// our share of a polynomial whose coefficients are secret-shared
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SharedPoly<E: CurveConfig = Curve> {
+    share: DensePolynomial<E::ScalarField>,
+}
+
+impl<E: CurveConfig> SharedPoly<E> {
+    /// the polynomial taking the values of handles on the multiplicative subgroup
+    /// of size handles.len(), in order; the number of handles must be a power of two
+    pub fn from_wires<T: Transport>(evaluator: &Evaluator<T, E>, handles: &[Wire]) -> Self {
+        let shares = handles
+            .iter()
+            .map(|h| evaluator.get_wire(h))
+            .collect::<Vec<E::ScalarField>>();
+        SharedPoly {
+            share: utils::interpolate_poly_over_mult_subgroup(&shares),
+        }
+    }
+
+    /// wraps our share of a polynomial computed locally
+    pub fn from_share(share: DensePolynomial<E::ScalarField>) -> Self {
+        SharedPoly { share }
+    }
+
+    /// our share of the coefficients
+    pub fn share(&self) -> &DensePolynomial<E::ScalarField> {
+        &self.share
+    }
+
+    pub fn degree(&self) -> usize {
+        self.share.degree()
+    }
+
+    /// the shared value f(z), on a fresh wire
+    pub fn eval_wire<T: Transport>(
+        &self,
+        evaluator: &mut Evaluator<T, E>,
+        z: E::ScalarField,
+    ) -> Wire {
+        evaluator.share_poly_eval(&self.share, z)
+    }
+
+    /// the shared values f(z) for every one of points, in order
+    pub fn eval_wires<T: Transport>(
+        &self,
+        evaluator: &mut Evaluator<T, E>,
+        points: &[E::ScalarField],
+    ) -> Vec<Wire> {
+        evaluator.share_poly_eval_many(&self.share, points)
+    }
+
+    /// our share of the KZG commitment to f
+    pub fn commit_share(&self, pp: &UniversalParams<E>) -> Result<E::G1, KzgError> {
+        Ok(KzgOn::<E>::commit_g1(pp, &self.share)?.into())
+    }
+
+    /// the KZG commitment to f, opened among all parties under label
+    pub async fn commit<T: Transport>(
+        &self,
+        evaluator: &mut Evaluator<T, E>,
+        pp: &UniversalParams<E>,
+        label: &str,
+    ) -> Result<E::G1, EvaluatorError> {
+        evaluator.commit_shared_poly(pp, &self.share, label).await
+    }
+
+    /// our share of the proof that f opens to f(z) at z
+    pub fn proof_share(
+        &self,
+        pp: &UniversalParams<E>,
+        z: E::ScalarField,
+    ) -> Result<E::G1, KzgError> {
+        Ok(KzgOn::<E>::compute_opening_proof(pp, &self.share, &z)?.into())
+    }
+
+    /// opens f(z) together with its KZG opening proof, under label
+    pub async fn open_at<T: Transport>(
+        &self,
+        evaluator: &mut Evaluator<T, E>,
+        pp: &UniversalParams<E>,
+        z: E::ScalarField,
+        label: &str,
+    ) -> Result<(E::ScalarField, E::G1), EvaluatorError> {
+        evaluator
+            .open_shared_poly_at(pp, &self.share, z, label)
+            .await
+    }
+
+    /// the product of two shared polynomials, at the cost of one triple for each
+    /// of the 2 * perm_size roots of unity it is evaluated on
+    pub async fn mul<T: Transport>(
+        &self,
+        evaluator: &mut Evaluator<T, E>,
+        other: &SharedPoly<E>,
+    ) -> Result<SharedPoly<E>, EvaluatorError> {
+        let share = evaluator
+            .share_poly_mult(self.share.clone(), other.share.clone())
+            .await?;
+        Ok(SharedPoly { share })
+    }
+
+    /// the product with a public polynomial, which needs no interaction
+    pub fn mul_public(&self, poly: &DensePolynomial<E::ScalarField>) -> SharedPoly<E> {
+        SharedPoly {
+            share: poly.mul(&self.share),
+        }
+    }
+
+    /// the difference of two shared polynomials
+    pub fn sub(&self, other: &SharedPoly<E>) -> SharedPoly<E> {
+        SharedPoly {
+            share: (&self.share).sub(&other.share),
+        }
+    }
+
+    /// f(X / ω)
+    pub fn div_ω(&self, ω: &E::ScalarField) -> SharedPoly<E> {
+        SharedPoly {
+            share: utils::poly_domain_div_ω(&self.share, ω),
+        }
+    }
+
+    /// the quotient of f by the vanishing polynomial of domain; the remainder of
+    /// our share is dropped, only the one of the opened polynomial must be zero
+    pub fn div_by_vanishing_poly(
+        &self,
+        domain: GeneralEvaluationDomain<E::ScalarField>,
+    ) -> SharedPoly<E> {
+        let (quotient, _) = self
+            .share
+            .divide_by_vanishing_poly(domain)
+            .expect("dividing by a vanishing polynomial does not fail");
+        SharedPoly { share: quotient }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SharedPoly;
+    use crate::common::{F, G1, KZG};
+    use crate::evaluator::Evaluator;
+    use crate::transport::InMemoryTransport;
+    use crate::utils;
+    use ark_poly::Polynomial;
+    use ark_std::UniformRand;
+    use futures::future::{join_all, try_join_all};
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_interpolation_and_evaluation_round_trip() {
+        async_std::task::block_on(async {
+            let transports = InMemoryTransport::new_network(3).await;
+            let evaluators = try_join_all(transports.into_iter().map(Evaluator::new))
+                .await
+                .unwrap();
+            let z = F::rand(&mut StdRng::from_seed([3u8; 32]));
+
+            let outputs = join_all(evaluators.into_iter().map(|mut evaluator| async move {
+                let handles = (0..8).map(|_| evaluator.ran()).collect::<Vec<_>>();
+                let poly = SharedPoly::from_wires(&evaluator, &handles);
+                assert!(poly.degree() < 8);
+
+                let powers = utils::domain_info(8).powers.clone();
+                let at_roots = poly.eval_wires(&mut evaluator, &powers);
+                let at_z = poly.eval_wire(&mut evaluator, z);
+                let values = evaluator.batch_output_wire(&handles).await.unwrap();
+                let evals = evaluator.batch_output_wire(&at_roots).await.unwrap();
+                let value_at_z = evaluator.output_wire(&at_z).await.unwrap();
+                (values, evals, value_at_z)
+            }))
+            .await;
+
+            for (values, evals, value_at_z) in outputs {
+                assert_eq!(values, evals);
+                let clear = utils::interpolate_poly_over_mult_subgroup(&values);
+                assert_eq!(clear.evaluate(&z), value_at_z);
+            }
+        });
+    }
+
+    #[test]
+    fn test_distributed_commitment_matches_the_clear_one() {
+        let pp = KZG::setup(128, &mut StdRng::from_seed([1u8; 32]));
+        async_std::task::block_on(async {
+            let transports = InMemoryTransport::new_network(3).await;
+            let evaluators = try_join_all(transports.into_iter().map(Evaluator::new))
+                .await
+                .unwrap();
+            let z = F::rand(&mut StdRng::from_seed([4u8; 32]));
+
+            let outputs = join_all(evaluators.into_iter().map(|mut evaluator| {
+                let pp = pp.clone();
+                async move {
+                    let fs = (0..8).map(|_| evaluator.ran()).collect::<Vec<_>>();
+                    let gs = (0..8).map(|_| evaluator.ran()).collect::<Vec<_>>();
+                    let f = SharedPoly::from_wires(&evaluator, &fs);
+                    let g = SharedPoly::from_wires(&evaluator, &gs);
+
+                    let com = f.commit(&mut evaluator, &pp, "f").await.unwrap();
+                    let (value, proof) = f.open_at(&mut evaluator, &pp, z, "f").await.unwrap();
+                    let fg = f.mul(&mut evaluator, &g).await.unwrap();
+                    let (fg_value, _) = fg.open_at(&mut evaluator, &pp, z, "fg").await.unwrap();
+
+                    let f_values = evaluator.batch_output_wire(&fs).await.unwrap();
+                    let g_values = evaluator.batch_output_wire(&gs).await.unwrap();
+                    (com, value, proof, fg_value, f_values, g_values)
+                }
+            }))
+            .await;
+
+            for (com, value, proof, fg_value, f_values, g_values) in outputs {
+                let f = utils::interpolate_poly_over_mult_subgroup(&f_values);
+                let g = utils::interpolate_poly_over_mult_subgroup(&g_values);
+                assert_eq!(com, G1::from(KZG::commit_g1(&pp, &f).unwrap()));
+                assert_eq!(value, f.evaluate(&z));
+                assert!(KZG::verify_opening_proof(
+                    &pp,
+                    &com.into(),
+                    &z,
+                    &value,
+                    &proof.into()
+                ));
+                assert_eq!(fg_value, value * g.evaluate(&z));
+            }
+        });
+    }
+}
@@ -1,5 +1,4 @@
-use ark_ec::{pairing::Pairing, CurveGroup, Group};
-use ark_ff::Field;
+use ark_ec::{pairing::Pairing, Group};
 use ark_poly::{
     univariate::{DenseOrSparsePolynomial, DensePolynomial},
     DenseUVPolynomial, EvaluationDomain, GeneralEvaluationDomain, Polynomial,
@@ -10,25 +9,50 @@ use num_bigint::BigUint;
 use rand::{rngs::StdRng, SeedableRng};
 use std::{
     collections::HashSet,
+    error, fmt,
     ops::{Add, Mul, Sub},
+    path::Path,
     vec,
 };
 
+use crate::address_book::Pok3rPeerId;
+use crate::audit::AuditEntry;
+use crate::circuit::CircuitBuilder;
 use crate::common::{
-    Ciphertext, Curve, EncryptionProof, Gt, PermutationProof, SigmaProof, DECK_SIZE, F, G1, G2,
-    NUM_SAMPLES, PERM_SIZE,
+    Ciphertext, Curve, CurveConfig, DealProof, DealtCard, DeckSpec, EncryptionProof, Gt, KzgOn,
+    PermutationProof, PermutationProofV2, ProtocolParams, ProtocolParamsError, ReshuffleProof,
+    ResourceEstimate, SigmaProof, F, G1, G2, KZG,
 };
-use crate::evaluator::Evaluator;
-use crate::hash::hash_to_g1;
-use crate::kzg::{UniversalParams, KZG10};
+use crate::encoding::ProofEncoding;
+use crate::evaluator::{Evaluator, EvaluatorError};
+use crate::hash::{hash_to_g1_ctx, player_card_id, GameContext, IdCache};
+use crate::kzg::{KzgError, ParamsError, UniversalParams};
+use crate::permutation::{apply_permutation_resources, apply_shared_permutation};
+use crate::proofs::{
+    batch_opening_challenge, deal_bases, deal_challenge, hiding_quotient, proof_transcript,
+};
+use crate::shared_poly::SharedPoly;
+use crate::transport::Transport;
 use crate::utils;
+use crate::wire::Wire;
 
-type KZG = crate::kzg::KZG10<Curve, DensePolynomial<<Curve as Pairing>::ScalarField>>;
+pub use crate::proofs::{
+    verify_dealt_card, verify_encryption_argument, verify_permutation_argument,
+    verify_permutation_argument_v2, verify_public_card,
+};
 
 pub fn compute_params() -> UniversalParams<Curve> {
     KZG::setup(1024, &mut StdRng::from_seed([42u8; 32]))
 }
 
+/// loads params from a native or .ptau file, keeping the degree the shuffler needs
+pub fn load_params(
+    path: &Path,
+    params: &ProtocolParams,
+) -> Result<UniversalParams<Curve>, ParamsError> {
+    UniversalParams::read_from_file(path, 2 * params.perm_size())
+}
+
 pub fn compute_keyper_keys() -> (F, G2) {
     let mut seeded_rng = StdRng::from_seed([42u8; 32]);
     let msk = F::rand(&mut seeded_rng);
@@ -37,24 +61,146 @@ pub fn compute_keyper_keys() -> (F, G2) {
     (msk, mpk)
 }
 
-pub fn compute_decryption_key(card_id: &[u8], msk: F) -> G1 {
-    let hash_id = hash_to_g1(card_id);
+/// the identity key revealing card_id of the game ctx
+pub fn compute_decryption_key(ctx: &GameContext, card_id: &[u8], msk: F) -> G1 {
+    let hash_id = hash_to_g1_ctx(ctx, card_id);
 
     hash_id * msk
 }
 
-pub fn compute_decryption_cache() -> Vec<Gt> {
-    let w = utils::multiplicative_subgroup_of_size(PERM_SIZE as u64);
-    let w_powers = (0..PERM_SIZE)
-        .map(|i| utils::compute_power(&w, i as u64))
-        .collect::<Vec<F>>();
+pub fn compute_decryption_cache(params: &ProtocolParams) -> Vec<Gt> {
+    let w_powers = &utils::domain_info(params.perm_size()).powers;
 
     let cache: Vec<Gt> = w_powers.iter().map(|x| Gt::generator() * x).collect();
 
     cache
 }
 
-pub async fn shuffle_deck(evaluator: &mut Evaluator) -> Vec<String> {
+/// the handle the permutation argument opens the commitment to the deck under
+pub const PERM_COMMITMENT_HANDLE: &str = "perm_f";
+
+/// a card dealt to a player reaches it under this prefix and its position
+pub const DEALT_HANDLE_PREFIX: &str = "dealt/";
+
+/// preprocessing consumed by shuffle_deck, the permutation argument and
+/// encrypt_and_prove, run one after the other
+pub fn resources(params: &ProtocolParams) -> ResourceEstimate {
+    shuffle_resources(params) + permutation_resources(params) + encryption_resources()
+}
+
+pub fn shuffle_resources(params: &ProtocolParams) -> ResourceEstimate {
+    let dummies = params.perm_size() - params.deck_size();
+    let samples = params.num_samples();
+    ResourceEstimate {
+        // an inversion per dummy card, and per sample an inversion plus the
+        // squarings of the exponentiation to the perm_size
+        triples: dummies + (params.log_perm_size() + 1) * samples,
+        // the key, and a mask per inversion and per sample
+        rand_sharings: 1 + dummies + 2 * samples,
+    }
+}
+
+pub fn permutation_resources(params: &ProtocolParams) -> ResourceEstimate {
+    let p = params.perm_size();
+    ResourceEstimate {
+        triples: 8 * p + 2,
+        // r_0..r_p with their inversion masks, alpha1 and alpha2
+        rand_sharings: 2 * (p + 1) + 2,
+    }
+}
+
+/// preprocessing consumed by deal_card, per card
+pub fn deal_resources() -> ResourceEstimate {
+    ResourceEstimate {
+        triples: 0,
+        // the encryption mask, the blinding of the opening proof, and the
+        // sigma protocol nonces of the card, the mask and the blinding
+        rand_sharings: 5,
+    }
+}
+
+/// preprocessing consumed by reshuffle_remaining, from a deck of params to a deck
+/// of new_params
+pub fn reshuffle_resources(
+    params: &ProtocolParams,
+    new_params: &ProtocolParams,
+) -> ResourceEstimate {
+    let (p, m) = (new_params.perm_size(), new_params.deck_size());
+    let squarings = params.log_perm_size() - new_params.log_perm_size();
+    let extra = ResourceEstimate {
+        // the roots of the session's domain are squared down to the new one, with
+        // the exponentiations of batch_ran_64 counted in the session's domain too;
+        // the polynomial product of the argument is over the session's domain; and
+        // the two products of the multiset check with its mask
+        triples: 2 * squarings * new_params.num_samples()
+            + 2 * (params.perm_size() - p)
+            + 2 * (m - 1)
+            + 1,
+        // the hiding term of the commitment and the mask of the check
+        rand_sharings: 2,
+    };
+    shuffle_resources(new_params)
+        + permutation_resources(new_params)
+        + apply_permutation_resources(p, m)
+        + extra
+}
+
+/// the params of a deck of the remaining cards left, in the smallest domain that
+/// fits them
+pub fn reshuffle_params(remaining: usize) -> Result<ProtocolParams, ProtocolParamsError> {
+    ProtocolParams::new(remaining.next_power_of_two().max(2), remaining)
+}
+
+pub fn encryption_resources() -> ResourceEstimate {
+    ResourceEstimate {
+        triples: 0,
+        // the encryption mask and the sigma protocol nonce
+        rand_sharings: 2,
+    }
+}
+
+/// shuffle_deck for the deck of spec; the card at a position is worth
+/// spec.value_of(opened card). The permutation argument on the result proves a
+/// permutation of the roots of unity, hence that the multiset of card values is the
+/// one of spec, repeats included. Fails if the evaluator was built for other
+/// params than those of spec.
+pub async fn shuffle_deck_with_spec<E: CurveConfig, C: CircuitBuilder<E>>(
+    evaluator: &mut C,
+    spec: &DeckSpec,
+) -> Result<Vec<Wire>, EvaluatorError> {
+    if *evaluator.params() != spec.params() {
+        return Err(EvaluatorError::WrongDeck {
+            expected: spec.params(),
+            found: *evaluator.params(),
+        });
+    }
+    shuffle_deck(evaluator).await
+}
+
+/// returns handles to a secret permutation of the deck; the intermediates are freed
+pub async fn shuffle_deck<E: CurveConfig, C: CircuitBuilder<E>>(
+    evaluator: &mut C,
+) -> Result<Vec<Wire>, EvaluatorError> {
+    let params = *evaluator.params();
+    evaluator.begin_scope();
+    let result = sample_deck(evaluator, &params).await;
+    if let Ok(card_share_handles) = &result {
+        card_share_handles.iter().for_each(|h| evaluator.keep(h));
+    }
+    evaluator.end_scope();
+    result
+}
+
+/// draws a permutation for params, whose perm_size may be smaller than the one of
+/// the evaluator
+pub(crate) async fn sample_deck<E: CurveConfig, C: CircuitBuilder<E>>(
+    evaluator: &mut C,
+    params: &ProtocolParams,
+) -> Result<Vec<Wire>, EvaluatorError> {
+    let (perm_size, deck_size) = (params.perm_size(), params.deck_size());
+    let num_samples = params.num_samples();
+    evaluator.assert_resources_remaining(&shuffle_resources(params))?;
+
     //step 1: parties invoke F_RAN to obtain [sk]
     let sk = evaluator.ran();
 
@@ -63,41 +209,42 @@ pub async fn shuffle_deck(evaluator: &mut Evaluator) -> Vec<String> {
     //stores set of card prfs encountered
     let mut prfs = HashSet::new();
 
-    // Compute prfs for cards 52 to 63 and add to prfs first
+    // Compute prfs for the dummy cards deck_size..perm_size and add to prfs first
     // So that the positions of these cards are fixed in the permutation
 
-    let ω = utils::multiplicative_subgroup_of_size(PERM_SIZE as u64);
-    let powers_of_ω = (0..PERM_SIZE)
-        .map(|i| utils::compute_power(&ω, i as u64))
-        .collect::<Vec<F>>();
+    let powers_of_ω = &utils::domain_info_of::<E::ScalarField>(perm_size).powers;
 
     // y_i = g^{1 / (sk + w_i)}
-    let denoms = (DECK_SIZE..PERM_SIZE)
+    let denoms = (deck_size..perm_size)
         .map(|i| evaluator.clear_add(&sk, powers_of_ω[i]))
-        .collect::<Vec<String>>();
+        .collect::<Vec<Wire>>();
 
-    let t_is = evaluator.batch_inv(&denoms).await;
+    let t_is = evaluator.batch_inv(&denoms).await?;
 
-    let y_is = evaluator.batch_output_wire_in_exponent(&t_is).await;
+    let y_is = evaluator.batch_output_wire_in_exponent(&t_is).await?;
 
-    // first include the cards 52..63 within the prf set and return set
-    for i in 0..(PERM_SIZE - DECK_SIZE) {
+    // first include the dummy cards within the prf set and return set
+    for i in 0..(perm_size - deck_size) {
         prfs.insert(y_is[i]);
-        let handle = evaluator.fixed_wire_handle(powers_of_ω[i + DECK_SIZE]);
+        let handle = evaluator.fixed_wire_handle(powers_of_ω[i + deck_size]);
         card_share_handles.push(handle.clone());
     }
 
-    // collect NUM_SAMPLES worth of random cards
-    let c_is = evaluator.batch_ran_64(NUM_SAMPLES).await;
+    // collect num_samples worth of random cards; a random root of the evaluator's
+    // domain, raised to the ratio of the domains, is a random root of ours
+    let mut c_is = evaluator.batch_ran_64(num_samples).await?;
+    for _ in params.log_perm_size()..evaluator.params().log_perm_size() {
+        c_is = evaluator.batch_mult(&c_is, &c_is).await?;
+    }
 
-    let t_is = (0..NUM_SAMPLES)
+    let t_is = (0..num_samples)
         .map(|i| evaluator.add(&c_is[i], &sk))
-        .collect::<Vec<String>>();
+        .collect::<Vec<Wire>>();
 
-    let t_is = evaluator.batch_inv(&t_is).await;
-    let y_is = evaluator.batch_output_wire_in_exponent(&t_is).await;
+    let t_is = evaluator.batch_inv(&t_is).await?;
+    let y_is = evaluator.batch_output_wire_in_exponent(&t_is).await?;
 
-    for i in 0..NUM_SAMPLES {
+    for i in 0..num_samples {
         //add card if it hasnt been seen before
         if !prfs.contains(&y_is[i]) {
             prfs.insert(y_is[i]);
@@ -105,227 +252,346 @@ pub async fn shuffle_deck(evaluator: &mut Evaluator) -> Vec<String> {
         }
     }
 
-    // Assert that the length is 64
-    assert_eq!(
-        card_share_handles.len(),
-        PERM_SIZE,
-        "We don't have enough cards - try again"
+    // we need a card for every position of the permutation
+    if card_share_handles.len() < perm_size {
+        return Err(EvaluatorError::TooFewCards {
+            drawn: card_share_handles.len(),
+            needed: perm_size,
+        });
+    }
+
+    Ok(card_share_handles)
+}
+
+/// our shares of a permutation argument, before the proofs are added up and the
+/// evaluations opened
+struct PermutationShares<E: CurveConfig> {
+    /// shares of the opening proofs of t(ω^63), t(y2), t(y2/ω), g(y2) and q(y2)
+    pi_shares: Vec<E::G1>,
+    /// handles to the shares of the matching evaluations
+    h_ys: Vec<Wire>,
+    f_com: E::G1,
+    g_com: E::G1,
+    q_com: E::G1,
+    t_com: E::G1,
+    alpha1: Wire,
+}
+
+pub async fn compute_permutation_argument<T: Transport, E: CurveConfig>(
+    pp: &UniversalParams<E>,
+    evaluator: &mut Evaluator<T, E>,
+    card_share_handles: &[Wire],
+) -> Result<(PermutationProof<E>, Wire), EvaluatorError> {
+    let params = *evaluator.params();
+    let (permutation_argument, alpha1) =
+        prove_permutation(pp, evaluator, &params, card_share_handles).await?;
+    let entry = AuditEntry::permutation_proof(&permutation_argument);
+    evaluator.publish_proof(&entry).await?;
+    evaluator.record_audit(entry);
+
+    Ok((permutation_argument, alpha1))
+}
+
+/// the permutation argument for a permutation of params, without publishing it
+async fn prove_permutation<T: Transport, E: CurveConfig>(
+    pp: &UniversalParams<E>,
+    evaluator: &mut Evaluator<T, E>,
+    params: &ProtocolParams,
+    card_share_handles: &[Wire],
+) -> Result<(PermutationProof<E>, Wire), EvaluatorError> {
+    let shares = permutation_argument_shares(pp, evaluator, params, card_share_handles).await?;
+
+    let pi_is = evaluator
+        .batch_add_g1_elements_from_all_parties(
+            &shares.pi_shares,
+            &[
+                String::from("pi_1"),
+                String::from("pi_2"),
+                String::from("pi_3"),
+                String::from("pi_4"),
+                String::from("pi_5"),
+            ],
+        )
+        .await?;
+
+    let permutation_argument = PermutationProof {
+        y1: evaluator.output_wire(&shares.h_ys[0]).await?,
+        y2: evaluator.output_wire(&shares.h_ys[1]).await?,
+        y3: evaluator.output_wire(&shares.h_ys[2]).await?,
+        y4: evaluator.output_wire(&shares.h_ys[3]).await?,
+        y5: evaluator.output_wire(&shares.h_ys[4]).await?,
+        pi_1: pi_is[0],
+        pi_2: pi_is[1],
+        pi_3: pi_is[2],
+        pi_4: pi_is[3],
+        pi_5: pi_is[4],
+        f_com: shares.f_com,
+        q_com: shares.q_com,
+        t_com: shares.t_com,
+        hasher: evaluator.fs_hasher(),
+    };
+    evaluator.free_wires(&shares.h_ys);
+
+    Ok((permutation_argument, shares.alpha1))
+}
+
+/// same argument as compute_permutation_argument, but the three openings at y2 are
+/// aggregated into one proof. Opening proofs are linear in the polynomial, so the
+/// aggregated proof is pi_2 + γ pi_4 + γ^2 pi_5, with γ hashed from the commitments
+/// and the opened evaluations.
+pub async fn compute_permutation_argument_v2<T: Transport, E: CurveConfig>(
+    pp: &UniversalParams<E>,
+    evaluator: &mut Evaluator<T, E>,
+    card_share_handles: &[Wire],
+) -> Result<(PermutationProofV2<E>, Wire), EvaluatorError> {
+    let params = *evaluator.params();
+    let shares = permutation_argument_shares(pp, evaluator, &params, card_share_handles).await?;
+    let ys = evaluator.batch_output_wire(&shares.h_ys).await?;
+
+    let gamma = batch_opening_challenge::<E>(
+        &[shares.t_com, shares.g_com, shares.q_com],
+        &[ys[1], ys[3], ys[4]],
+        evaluator.fs_hasher(),
     );
+    let pi_batch_share =
+        shares.pi_shares[1] + shares.pi_shares[3] * gamma + shares.pi_shares[4] * gamma * gamma;
+
+    let pi_is = evaluator
+        .batch_add_g1_elements_from_all_parties(
+            &[shares.pi_shares[0], shares.pi_shares[2], pi_batch_share],
+            &[
+                String::from("pi_1"),
+                String::from("pi_3"),
+                String::from("pi_batch"),
+            ],
+        )
+        .await?;
+
+    let permutation_argument = PermutationProofV2 {
+        y1: ys[0],
+        y2: ys[1],
+        y3: ys[2],
+        y4: ys[3],
+        y5: ys[4],
+        pi_1: pi_is[0],
+        pi_3: pi_is[1],
+        pi_batch: pi_is[2],
+        f_com: shares.f_com,
+        q_com: shares.q_com,
+        t_com: shares.t_com,
+        hasher: evaluator.fs_hasher(),
+    };
+    evaluator.free_wires(&shares.h_ys);
+    let entry = AuditEntry::permutation_proof_v2(&permutation_argument);
+    evaluator.publish_proof(&entry).await?;
+    evaluator.record_audit(entry);
+
+    Ok((permutation_argument, shares.alpha1))
+}
 
-    card_share_handles.clone()
+/// runs permutation_argument_shares_in_scope, and frees everything but the
+/// evaluations and alpha1
+async fn permutation_argument_shares<T: Transport, E: CurveConfig>(
+    pp: &UniversalParams<E>,
+    evaluator: &mut Evaluator<T, E>,
+    params: &ProtocolParams,
+    card_share_handles: &[Wire],
+) -> Result<PermutationShares<E>, EvaluatorError> {
+    evaluator.begin_scope();
+    let result =
+        permutation_argument_shares_in_scope(pp, evaluator, params, card_share_handles).await;
+    if let Ok(shares) = &result {
+        shares.h_ys.iter().for_each(|h| evaluator.keep(h));
+        evaluator.keep(&shares.alpha1);
+    }
+    evaluator.end_scope();
+    result
 }
 
-pub async fn compute_permutation_argument(
-    pp: &UniversalParams<Curve>,
-    evaluator: &mut Evaluator,
-    card_share_handles: &Vec<String>,
-) -> (PermutationProof, String) {
+async fn permutation_argument_shares_in_scope<T: Transport, E: CurveConfig>(
+    pp: &UniversalParams<E>,
+    evaluator: &mut Evaluator<T, E>,
+    params: &ProtocolParams,
+    card_share_handles: &[Wire],
+) -> Result<PermutationShares<E>, EvaluatorError> {
+    let perm_size = params.perm_size();
+    if card_share_handles.len() != perm_size {
+        return Err(EvaluatorError::LengthMismatch {
+            expected: perm_size,
+            actual: card_share_handles.len(),
+        });
+    }
+    evaluator.assert_resources_remaining(&permutation_resources(params))?;
+
     // Compute r_i and r_i^-1
-    let r_is = (0..PERM_SIZE + 1)
+    let r_is = (0..perm_size + 1)
         .map(|_i| evaluator.ran())
-        .collect::<Vec<String>>();
+        .collect::<Vec<Wire>>();
 
-    let r_inv_is = evaluator.batch_inv(&r_is).await;
+    let r_inv_is = evaluator.batch_inv(&r_is).await?;
 
     // Compute b_i from r_i and r_i^-1; b_i = r_i / r_0 for i in 0..65
     let b_is = evaluator
         .batch_mult(
-            &vec![r_inv_is[0].clone(); PERM_SIZE],
-            &r_is[1..PERM_SIZE + 1],
+            &vec![r_inv_is[0].clone(); perm_size],
+            &r_is[1..perm_size + 1],
         )
-        .await;
+        .await?;
 
     // 8: Interpret the vector fi as evaluations of a polynomial f(X).
-    let f_name = String::from("perm_f");
-    let card_share_values = card_share_handles
-        .iter()
-        .map(|x| evaluator.get_wire(x))
-        .collect::<Vec<F>>();
-    let f_share = utils::interpolate_poly_over_mult_subgroup(&card_share_values);
-    let f_share_com = KZG10::commit_g1(pp, &f_share);
+    let f_name = String::from(PERM_COMMITMENT_HANDLE);
+    let f_share = SharedPoly::from_wires(evaluator, card_share_handles);
+    let f_share_com = f_share.commit_share(pp)?;
 
-    // Commit to hiding polynomials [alpha1,alpha2]*(x^PERM_SIZE - 1)
+    // Commit to hiding polynomials [alpha1,alpha2]*(x^perm_size - 1)
     let alpha1 = evaluator.ran();
     let alpha2 = evaluator.ran();
 
-    let vanishing_poly = utils::compute_vanishing_poly(PERM_SIZE);
+    let vanishing_poly = utils::compute_vanishing_poly(perm_size);
     let alpha1_vanish_poly_share_com =
-        KZG10::commit_g1(pp, &vanishing_poly).mul(evaluator.get_wire(&alpha1));
+        KzgOn::<E>::commit_g1(pp, &vanishing_poly)?.mul(evaluator.get_wire(&alpha1));
     let alpha2_vanish_poly_share_com =
-        KZG10::commit_g1(pp, &vanishing_poly).mul(evaluator.get_wire(&alpha2));
+        KzgOn::<E>::commit_g1(pp, &vanishing_poly)?.mul(evaluator.get_wire(&alpha2));
 
-    // Commit to f(X) + alpha1 * (x^PERM_SIZE - 1)
+    // Commit to f(X) + alpha1 * (x^perm_size - 1)
     // Note that the polynomial itself isn't being changed, just the commitment.
 
     let hiding_f_com = f_share_com + alpha1_vanish_poly_share_com;
     let f_com = evaluator
         .add_g1_elements_from_all_parties(&hiding_f_com, &f_name)
-        .await;
+        .await?;
 
     // 9: Define the degree-64 polynomial v(X) such that the evaluation vector is (1, ω, . . . , ω63)
     // This polynomial is the unpermuted vector of cards
-    let ω = utils::multiplicative_subgroup_of_size(PERM_SIZE as u64);
-    let v_evals: Vec<F> = (0..PERM_SIZE)
-        .map(|i| utils::compute_power(&ω, i as u64))
-        .collect();
+    let ω = utils::domain_info_of::<E::ScalarField>(perm_size).generator;
+    let v_evals: Vec<E::ScalarField> = utils::domain_info_of::<E::ScalarField>(perm_size)
+        .powers
+        .clone();
     let v = utils::interpolate_poly_over_mult_subgroup(&v_evals);
 
     // Commit to v(X) which is the public polynomial
-    let v_com = KZG10::commit_g1(pp, &v);
+    let v_com = KzgOn::<E>::commit_g1(pp, &v)?;
 
     // 12: Parties locally compute γ1 = FSHash(C,V )
     // Hash v_com and f_com to obtain randomness for batching
-    let mut v_bytes = Vec::new();
-    let mut f_bytes = Vec::new();
+    let mut transcript = proof_transcript(b"pok3r-permutation", evaluator.fs_hasher());
+    transcript.append_g1::<E::G1>(b"v_com", &v_com.into());
+    transcript.append_g1(b"f_com", &f_com);
 
-    v_com.serialize_uncompressed(&mut v_bytes).unwrap();
-    f_com.serialize_uncompressed(&mut f_bytes).unwrap();
-
-    let y1 = utils::fs_hash(vec![&v_bytes, &f_bytes], 1)[0];
+    let y1 = transcript.challenge_f_on::<E>(b"y1");
 
     // 13: Locally compute g(X) shares from f(X) shares
-    let mut g_eval_shares = vec![];
-    let mut h_g_shares = vec![];
-    for i in 0..PERM_SIZE {
-        // Get a handle for g_i for later
-        h_g_shares.push(evaluator.clear_add(&card_share_handles[i], y1));
-
-        let g_share_i = evaluator.get_wire(&h_g_shares[i].clone());
-        g_eval_shares.push(g_share_i);
-    }
-
-    let g_share_poly = utils::interpolate_poly_over_mult_subgroup(&g_eval_shares.clone());
+    let h_g_shares = card_share_handles
+        .iter()
+        .map(|h| evaluator.clear_add(h, y1))
+        .collect::<Vec<Wire>>();
+    let g_share_poly = SharedPoly::from_wires(evaluator, &h_g_shares);
 
-    // Commit to g(X) - the hiding variant derived from f(X): just add alpha1 * (x^PERM_SIZE - 1)
-    let g_share_com = KZG10::commit_g1(pp, &g_share_poly);
+    // Commit to g(X) - the hiding variant derived from f(X): just add alpha1 * (x^perm_size - 1)
+    let g_share_com = g_share_poly.commit_share(pp)?;
     let hiding_g_com = g_share_com + alpha1_vanish_poly_share_com;
     let g_com = evaluator
         .add_g1_elements_from_all_parties(&hiding_g_com, &String::from("perm_g"))
-        .await;
+        .await?;
 
     // 14: Compute h(X) = v(X) + y1
     let mut h_evals = vec![];
-    for i in 0..PERM_SIZE {
-        let h_i = v_evals[i] + y1;
+    for v_i in v_evals.iter().take(perm_size) {
+        let h_i = *v_i + y1;
         h_evals.push(h_i);
     }
     let h_poly = utils::interpolate_poly_over_mult_subgroup(&h_evals);
 
     // Compute s_i' and t_i'
 
-    let h_h_inv_g_is = (0..PERM_SIZE)
-        .map(|i| {
-            let h_inv_i = h_evals[i].inverse().unwrap();
-            let h_g_i = &h_g_shares[i];
-            evaluator.scale(h_g_i, h_inv_i)
-        })
-        .collect::<Vec<String>>();
+    let mut h_inv_evals = h_evals.clone();
+    utils::batch_invert(&mut h_inv_evals).expect("h(X) vanishes on the domain");
+
+    let h_h_inv_g_is = (0..perm_size)
+        .map(|i| evaluator.mul_public(&h_g_shares[i], h_inv_evals[i]))
+        .collect::<Vec<Wire>>();
 
     let h_s_prime_is = evaluator
-        .batch_mult(&r_is[0..PERM_SIZE], &h_h_inv_g_is)
-        .await;
+        .batch_mult(&r_is[0..perm_size], &h_h_inv_g_is)
+        .await?;
     let h_t_prime_is = evaluator
-        .batch_mult(&r_inv_is[1..PERM_SIZE + 1], &h_s_prime_is)
-        .await;
+        .batch_mult(&r_inv_is[1..perm_size + 1], &h_s_prime_is)
+        .await?;
 
-    let t_prime_is = evaluator.batch_output_wire(&h_t_prime_is).await;
+    let t_prime_is = evaluator.batch_output_wire(&h_t_prime_is).await?;
 
     // Locally compute t_i
     // 20: for i ← 0 . . . 63 do
     // 21: Parties locally compute [ti]p ← [bi]p · ∏ij=0 t′j
     // 22: end for
     let mut t_is = vec![];
-    for i in 0..PERM_SIZE {
-        // let tmp = product of t'_i from 0 to i
-        let mut tmp = F::one();
-        for j in 0..(i + 1) {
-            tmp *= t_prime_is[j];
-        }
+    // product of t'_j from 0 to i
+    let mut tmp = E::ScalarField::one();
+    for (b_i, t_prime_i) in b_is.iter().zip(t_prime_is.iter()).take(perm_size) {
+        tmp *= t_prime_i;
 
         // Multiply by b_i to remove random masks
-        let t_i = evaluator.scale(&b_is[i], tmp);
+        let t_i = evaluator.mul_public(b_i, tmp);
 
-        t_is.push((t_i.clone(), evaluator.get_wire(&t_i)));
+        t_is.push(t_i);
     }
 
     // Commit to t(X)
-    let t_shares: &Vec<F> = &t_is.clone().into_iter().map(|x| x.1).collect();
-    let t_share_poly = utils::interpolate_poly_over_mult_subgroup(t_shares);
-    let t_share_com = KZG10::commit_g1(pp, &t_share_poly);
+    let t_share_poly = SharedPoly::from_wires(evaluator, &t_is);
+    let t_share_com = t_share_poly.commit_share(pp)?;
 
     // Make sure t_com is hiding as well
     let hiding_t_com = t_share_com + alpha2_vanish_poly_share_com;
     let t_com = evaluator
         .add_g1_elements_from_all_parties(&hiding_t_com, &String::from("t"))
-        .await;
+        .await?;
 
-    let tx_by_omega_share_poly = utils::poly_domain_div_ω(&t_share_poly, &ω);
+    let tx_by_omega_share_poly = t_share_poly.div_ω(&ω);
 
     // Need to show that t(X) / t(X/ω) = g(X) / h(X)
     // 24: Compute [d(X)] as [d(X)] = h(X) * [t(X)] − [g(X) * t(X/ω)]
-    let h_t_share_poly = h_poly.mul(&t_share_poly);
-    let g_tx_by_omega_share_poly = evaluator
-        .share_poly_mult(g_share_poly.clone(), tx_by_omega_share_poly.clone())
-        .await;
+    let h_t_share_poly = t_share_poly.mul_public(&h_poly);
+    let g_tx_by_omega_share_poly = g_share_poly.mul(evaluator, &tx_by_omega_share_poly).await?;
 
     let d_share_poly = h_t_share_poly.sub(&g_tx_by_omega_share_poly);
 
     // Compute q(X) and r(X) as quotient and remainder of d(X) / (X^64 - 1)
     // TOASSERT - Reconstructed r(X) should be 0
-    let domain = GeneralEvaluationDomain::<F>::new(PERM_SIZE).unwrap();
-    let (q_share_poly, _) = d_share_poly.divide_by_vanishing_poly(domain).unwrap();
+    let domain = GeneralEvaluationDomain::<E::ScalarField>::new(perm_size).unwrap();
+    let q_share_poly = d_share_poly.div_by_vanishing_poly(domain);
 
     // Commit to q(X) - with all the extra terms from the hiding polynomials
-    // q'(x) = q(x) - alpha1 * alpha2 * (x^PERM_SIZE - 1) + alpha2 * h(x) - alpha1 * t(x/w) - alpha2 * g(x)
+    // q'(x) = q(x) - alpha1 * alpha2 * (x^perm_size - 1) + alpha2 * h(x) - alpha1 * t(x/w) - alpha2 * g(x)
 
-    let q_share_com = KZG10::commit_g1(pp, &q_share_poly);
+    let q_share_com = q_share_poly.commit_share(pp)?;
 
-    // Computing alpha1 * alpha2 * (x^PERM_SIZE - 1)
-    let h_alpha1_alpha2 = evaluator.mult(&alpha1, &alpha2).await;
+    // Computing alpha1 * alpha2 * (x^perm_size - 1)
+    let h_alpha1_alpha2 = evaluator.mult(&alpha1, &alpha2).await?;
     let alpha1_alpha2_vanish_poly_share_com =
-        KZG10::commit_g1(pp, &vanishing_poly).mul(evaluator.get_wire(&h_alpha1_alpha2));
+        KzgOn::<E>::commit_g1(pp, &vanishing_poly)?.mul(evaluator.get_wire(&h_alpha1_alpha2));
 
     // Computing alpha2 * h(x)
-    let alpha2_h_share_poly = h_poly.mul(evaluator.get_wire(&alpha2));
-    let alpha2_h_share_poly_com = KZG10::commit_g1(pp, &alpha2_h_share_poly);
+    let alpha2_h_share_poly = SharedPoly::from_share(h_poly.mul(evaluator.get_wire(&alpha2)));
+    let alpha2_h_share_poly_com = alpha2_h_share_poly.commit_share(pp)?;
 
     // Computing alpha1 * t(x/w)
     // First batch mult t_is with alpha1
     let h_alpha1_t_is = evaluator
-        .batch_mult(
-            &t_is
-                .clone()
-                .into_iter()
-                .map(|x| x.0)
-                .collect::<Vec<String>>(),
-            &vec![alpha1.clone(); PERM_SIZE],
-        )
-        .await;
-
-    let alpha1_t_is = h_alpha1_t_is
-        .into_iter()
-        .map(|handle| evaluator.get_wire(&handle))
-        .collect::<Vec<F>>();
+        .batch_mult(&t_is, &vec![alpha1.clone(); perm_size])
+        .await?;
 
     // Then compute alpha1 * t(x/w)
-    let alpha1_t_share_poly = utils::interpolate_poly_over_mult_subgroup(&alpha1_t_is);
-    let alpha1_t_by_w_share_poly = utils::poly_domain_div_ω(&alpha1_t_share_poly, &ω);
-
-    let alpha1_t_by_w_share_poly_com = KZG10::commit_g1(pp, &alpha1_t_by_w_share_poly);
+    let alpha1_t_by_w_share_poly = SharedPoly::from_wires(evaluator, &h_alpha1_t_is).div_ω(&ω);
+    let alpha1_t_by_w_share_poly_com = alpha1_t_by_w_share_poly.commit_share(pp)?;
 
     // Computing alpha2 * g(x)
     let h_alpha2_g_is = evaluator
-        .batch_mult(&h_g_shares, &vec![alpha2.clone(); PERM_SIZE])
-        .await;
-
-    let alpha2_g_is = h_alpha2_g_is
-        .into_iter()
-        .map(|handle| evaluator.get_wire(&handle))
-        .collect::<Vec<F>>();
+        .batch_mult(&h_g_shares, &vec![alpha2.clone(); perm_size])
+        .await?;
 
     // Compute alpha2 * g(x)
-    let alpha2_g_share_poly = utils::interpolate_poly_over_mult_subgroup(&alpha2_g_is);
-    let alpha2_g_share_poly_com = KZG10::commit_g1(pp, &alpha2_g_share_poly);
+    let alpha2_g_share_poly = SharedPoly::from_wires(evaluator, &h_alpha2_g_is);
+    let alpha2_g_share_poly_com = alpha2_g_share_poly.commit_share(pp)?;
 
     let hiding_q_share_com = q_share_com + alpha2_h_share_poly_com
         - alpha1_alpha2_vanish_poly_share_com
@@ -334,58 +600,53 @@ pub async fn compute_permutation_argument(
 
     let q_com = evaluator
         .add_g1_elements_from_all_parties(&hiding_q_share_com, &String::from("perm_q"))
-        .await;
+        .await?;
 
-    // Compute y2 = hash(v_com, f_com, q_com, t_com, g_com)
-    let mut v_bytes = Vec::new();
-    let mut f_bytes = Vec::new();
-    let mut q_bytes = Vec::new();
-    let mut t_bytes = Vec::new();
-    let mut g_bytes = Vec::new();
+    // Compute y2 = hash(v_com, f_com, y1, q_com, t_com, g_com)
+    transcript.append_g1(b"q_com", &q_com);
+    transcript.append_g1(b"t_com", &t_com);
+    transcript.append_g1(b"g_com", &g_com);
 
-    v_com.serialize_uncompressed(&mut v_bytes).unwrap();
-    f_com.serialize_uncompressed(&mut f_bytes).unwrap();
-    q_com.serialize_uncompressed(&mut q_bytes).unwrap();
-    t_com.serialize_uncompressed(&mut t_bytes).unwrap();
-    g_com.serialize_uncompressed(&mut g_bytes).unwrap();
-
-    let y2 = utils::fs_hash(vec![&v_bytes, &f_bytes, &q_bytes, &t_bytes, &g_bytes], 1)[0];
+    let y2 = transcript.challenge_f_on::<E>(b"y2");
 
     // Compute polyevals and proofs
-    let w = utils::multiplicative_subgroup_of_size(PERM_SIZE as u64);
-    let w63 = utils::compute_power(&w, PERM_SIZE as u64 - 1);
+    let w = utils::domain_info_of::<E::ScalarField>(perm_size).generator;
+    let w63 = utils::domain_info_of::<E::ScalarField>(perm_size).powers[perm_size - 1];
+
+    // Evaluate t(x) at w^63, y2 and y2 / w
+    let h_t_evals = t_share_poly.eval_wires(evaluator, &[w63, y2, y2 / w]);
 
     // Evaluate t(x) at w^63
-    let h_y1 = evaluator.share_poly_eval(&t_share_poly, w63);
+    let h_y1 = h_t_evals[0].clone();
     // No adjustment from hiding term
     // let h_hiding_y1 = evaluator.scale(&alpha2, vanishing_poly.evaluate(&w63));
     // let h_y1 = evaluator.add(&h_y1, &h_hiding_y1);
 
     // Evaluate t(x) at y2
-    let h_y2_orig = evaluator.share_poly_eval(&t_share_poly, y2);
+    let h_y2_orig = &h_t_evals[1];
     // Adjustment from hiding term
     let h_hiding_y2 = evaluator.scale(&alpha2, vanishing_poly.evaluate(&y2));
-    let h_y2 = evaluator.add(&h_y2_orig, &h_hiding_y2);
+    let h_y2 = evaluator.add(h_y2_orig, &h_hiding_y2);
 
     // Evaluate t(x) at y2 / w
-    let h_y3_orig = evaluator.share_poly_eval(&t_share_poly, y2 / w);
+    let h_y3_orig = &h_t_evals[2];
     // Adjustment from hiding term
     let h_hiding_y3 = evaluator.scale(&alpha2, vanishing_poly.evaluate(&(y2 / w)));
-    let h_y3 = evaluator.add(&h_y3_orig, &h_hiding_y3);
+    let h_y3 = evaluator.add(h_y3_orig, &h_hiding_y3);
 
     // Evaluate g(x) at y2
-    let h_y4_orig = evaluator.share_poly_eval(&g_share_poly, y2);
+    let h_y4_orig = g_share_poly.eval_wire(evaluator, y2);
     // Adjustment from hiding term
     let h_hiding_y4 = evaluator.scale(&alpha1, vanishing_poly.evaluate(&y2));
     let h_y4 = evaluator.add(&h_y4_orig, &h_hiding_y4);
 
     // Evaluate q(x) at y2
-    let h_y5_orig = evaluator.share_poly_eval(&q_share_poly, y2);
+    let h_y5_orig = q_share_poly.eval_wire(evaluator, y2);
     // Adjustments from hiding terms
     let h_hiding_y5_1 = evaluator.scale(&h_alpha1_alpha2, vanishing_poly.evaluate(&y2));
-    let h_hiding_y5_2 = evaluator.share_poly_eval(&alpha2_h_share_poly, y2);
-    let h_hiding_y5_3 = evaluator.share_poly_eval(&alpha1_t_by_w_share_poly, y2);
-    let h_hiding_y5_4 = evaluator.share_poly_eval(&alpha2_g_share_poly, y2);
+    let h_hiding_y5_2 = alpha2_h_share_poly.eval_wire(evaluator, y2);
+    let h_hiding_y5_3 = alpha1_t_by_w_share_poly.eval_wire(evaluator, y2);
+    let h_hiding_y5_4 = alpha2_g_share_poly.eval_wire(evaluator, y2);
 
     let temp1 = evaluator.add(&h_hiding_y5_3, &h_hiding_y5_4);
     let temp2 = evaluator.sub(&h_y5_orig, &temp1);
@@ -396,289 +657,172 @@ pub async fn compute_permutation_argument(
     let pi_s = evaluator
         .batch_eval_proof_with_share_poly(
             pp,
-            &vec![
-                t_share_poly.clone(),
-                t_share_poly.clone(),
-                t_share_poly.clone(),
-                g_share_poly.clone(),
-                q_share_poly.clone(),
+            &[
+                t_share_poly.share().clone(),
+                t_share_poly.share().clone(),
+                t_share_poly.share().clone(),
+                g_share_poly.share().clone(),
+                q_share_poly.share().clone(),
             ],
-            &vec![w63, y2, y2 / w, y2, y2],
+            &[w63, y2, y2 / w, y2, y2],
         )
-        .await;
+        .await?;
 
     // Adjustments to proofs from hiding terms
     // pi_1
-    let mut divisor = DensePolynomial::from_coefficients_vec(vec![-w63, F::from(1)]);
+    let mut divisor = DensePolynomial::from_coefficients_vec(vec![-w63, E::ScalarField::one()]);
     let (mut quotient, _) =
         DenseOrSparsePolynomial::divide_with_q_and_r(&(&vanishing_poly).into(), &(&divisor).into())
             .unwrap();
 
-    let pi_poly = KZG10::commit_g1(pp, &quotient);
+    let pi_poly = KzgOn::<E>::commit_g1(pp, &quotient)?;
     let pi_1 = pi_s[0] + pi_poly.mul(evaluator.get_wire(&alpha2));
 
     // pi_2
-    divisor = DensePolynomial::from_coefficients_vec(vec![-y2, F::from(1)]);
+    divisor = DensePolynomial::from_coefficients_vec(vec![-y2, E::ScalarField::one()]);
     (quotient, _) =
         DenseOrSparsePolynomial::divide_with_q_and_r(&(&vanishing_poly).into(), &(&divisor).into())
             .unwrap();
 
-    let pi_poly = KZG10::commit_g1(pp, &quotient);
+    let pi_poly = KzgOn::<E>::commit_g1(pp, &quotient)?;
     let pi_2 = pi_s[1] + pi_poly.mul(evaluator.get_wire(&alpha2));
 
     // pi_3
-    divisor = DensePolynomial::from_coefficients_vec(vec![-(y2 / w), F::from(1)]);
+    divisor = DensePolynomial::from_coefficients_vec(vec![-(y2 / w), E::ScalarField::one()]);
     (quotient, _) =
         DenseOrSparsePolynomial::divide_with_q_and_r(&(&vanishing_poly).into(), &(&divisor).into())
             .unwrap();
 
-    let pi_poly = KZG10::commit_g1(pp, &quotient);
+    let pi_poly = KzgOn::<E>::commit_g1(pp, &quotient)?;
     let pi_3 = pi_s[2] + pi_poly.mul(evaluator.get_wire(&alpha2));
 
     // pi_4
-    divisor = DensePolynomial::from_coefficients_vec(vec![-y2, F::from(1)]);
+    divisor = DensePolynomial::from_coefficients_vec(vec![-y2, E::ScalarField::one()]);
     (quotient, _) =
         DenseOrSparsePolynomial::divide_with_q_and_r(&(&vanishing_poly).into(), &(&divisor).into())
             .unwrap();
 
-    let pi_poly = KZG10::commit_g1(pp, &quotient);
+    let pi_poly = KzgOn::<E>::commit_g1(pp, &quotient)?;
     let pi_4 = pi_s[3] + pi_poly.mul(evaluator.get_wire(&alpha1));
 
     // pi_5
-    divisor = DensePolynomial::from_coefficients_vec(vec![-y2, F::from(1)]);
+    divisor = DensePolynomial::from_coefficients_vec(vec![-y2, E::ScalarField::one()]);
     let (quotient_1, _) =
         DenseOrSparsePolynomial::divide_with_q_and_r(&(&vanishing_poly).into(), &(&divisor).into())
             .unwrap();
 
-    let pi_poly_1 = KZG10::commit_g1(pp, &quotient_1);
+    let pi_poly_1 = KzgOn::<E>::commit_g1(pp, &quotient_1)?;
     let mut pi_5 = pi_s[4] - pi_poly_1.mul(evaluator.get_wire(&h_alpha1_alpha2));
 
-    let (quotient_2, _) = DenseOrSparsePolynomial::divide_with_q_and_r(
-        &(&alpha2_h_share_poly).into(),
-        &(&divisor).into(),
-    )
-    .unwrap();
-
-    let pi_poly_2 = KZG10::commit_g1(pp, &quotient_2);
-    pi_5 += pi_poly_2;
-
-    let (quotient_3, _) = DenseOrSparsePolynomial::divide_with_q_and_r(
-        &(&alpha1_t_by_w_share_poly).into(),
-        &(&divisor).into(),
-    )
-    .unwrap();
-
-    let pi_poly_3 = KZG10::commit_g1(pp, &quotient_3);
-    pi_5 -= pi_poly_3;
-
-    let (quotient_4, _) = DenseOrSparsePolynomial::divide_with_q_and_r(
-        &(&alpha2_g_share_poly).into(),
-        &(&divisor).into(),
-    )
-    .unwrap();
-
-    let pi_poly_4 = KZG10::commit_g1(pp, &quotient_4);
-    pi_5 -= pi_poly_4;
-
-    let pi_is = evaluator
-        .batch_add_g1_elements_from_all_parties(
-            &vec![pi_1, pi_2, pi_3, pi_4, pi_5],
-            &[
-                String::from("pi_1"),
-                String::from("pi_2"),
-                String::from("pi_3"),
-                String::from("pi_4"),
-                String::from("pi_5"),
-            ],
-        )
-        .await;
+    pi_5 += alpha2_h_share_poly.proof_share(pp, y2)?;
+    pi_5 -= alpha1_t_by_w_share_poly.proof_share(pp, y2)?;
+    pi_5 -= alpha2_g_share_poly.proof_share(pp, y2)?;
 
-    let permutation_argument = PermutationProof {
-        y1: evaluator.output_wire(&h_y1).await,
-        y2: evaluator.output_wire(&h_y2).await,
-        y3: evaluator.output_wire(&h_y3).await,
-        y4: evaluator.output_wire(&h_y4).await,
-        y5: evaluator.output_wire(&h_y5).await,
-        pi_1: pi_is[0],
-        pi_2: pi_is[1],
-        pi_3: pi_is[2],
-        pi_4: pi_is[3],
-        pi_5: pi_is[4],
+    Ok(PermutationShares {
+        pi_shares: vec![pi_1, pi_2, pi_3, pi_4, pi_5],
+        h_ys: vec![h_y1, h_y2, h_y3, h_y4, h_y5],
         f_com,
+        g_com,
         q_com,
         t_com,
-    };
-
-    (permutation_argument, alpha1)
+        alpha1,
+    })
 }
 
-pub fn verify_permutation_argument(
-    pp: &UniversalParams<Curve>,
-    perm_proof: &PermutationProof,
-) -> bool {
-    let mut b = true;
-
-    // Compute v(X) from powers of w
-    let w = utils::multiplicative_subgroup_of_size(PERM_SIZE as u64);
-    let w63 = utils::compute_power(&w, PERM_SIZE as u64 - 1);
-
-    let v_evals: Vec<F> = (0..PERM_SIZE)
-        .map(|i| utils::compute_power(&w, i as u64))
-        .collect();
-
-    let v = utils::interpolate_poly_over_mult_subgroup(&v_evals);
-    let v_com = KZG10::commit_g1(pp, &v);
-
-    // Compute hash1 and hash2
-    let mut v_bytes = Vec::new();
-    let mut f_bytes = Vec::new();
-    let mut q_bytes = Vec::new();
-    let mut t_bytes = Vec::new();
-    let mut g_bytes = Vec::new();
-
-    v_com.serialize_uncompressed(&mut v_bytes).unwrap();
-    perm_proof
-        .f_com
-        .serialize_uncompressed(&mut f_bytes)
-        .unwrap();
-
-    let hash1 = utils::fs_hash(vec![&v_bytes, &f_bytes], 1)[0];
-
-    // Compute g_com from f_com
-    let const_y1 = DensePolynomial::from_coefficients_vec(vec![hash1]);
-    let const_com_y1 = KZG10::commit_g1(pp, &const_y1);
-
-    let g_com = perm_proof.f_com + const_com_y1;
-
-    perm_proof
-        .q_com
-        .serialize_uncompressed(&mut q_bytes)
-        .unwrap();
-    perm_proof
-        .t_com
-        .serialize_uncompressed(&mut t_bytes)
-        .unwrap();
-    g_com.serialize_uncompressed(&mut g_bytes).unwrap();
-
-    let hash2 = utils::fs_hash(vec![&v_bytes, &f_bytes, &q_bytes, &t_bytes, &g_bytes], 1)[0];
-
-    // Check all evaluation proofs
-    b &= KZG::verify_opening_proof(
-        pp,
-        &perm_proof.t_com.into_affine(),
-        &w63,
-        &perm_proof.y1,
-        &perm_proof.pi_1.into_affine(),
-    );
-
-    b &= KZG::verify_opening_proof(
-        pp,
-        &perm_proof.t_com.into_affine(),
-        &hash2,
-        &perm_proof.y2,
-        &perm_proof.pi_2.into_affine(),
-    );
-
-    b &= KZG::verify_opening_proof(
-        pp,
-        &perm_proof.t_com.into_affine(),
-        &(hash2 / w),
-        &perm_proof.y3,
-        &perm_proof.pi_3.into_affine(),
-    );
-
-    b &= KZG::verify_opening_proof(
-        pp,
-        &g_com.into_affine(),
-        &(hash2),
-        &perm_proof.y4,
-        &perm_proof.pi_4.into_affine(),
-    );
-
-    b &= KZG::verify_opening_proof(
+/// Produces ciphertexts and links the card commitment to the ciphertexts; the
+/// identities are hashed and paired through cache, the one of their game
+#[allow(clippy::too_many_arguments)]
+pub async fn encrypt_and_prove<T: Transport, E: CurveConfig>(
+    pp: &UniversalParams<E>,
+    evaluator: &mut Evaluator<T, E>,
+    cache: &mut IdCache<E>,
+    card_handles: Vec<Wire>,
+    card_commitment: E::G1, // C = g^{\sum_i card_handles_i L_i(x) + alpha1 * (x^perm_size - 1)}
+    alpha1: Wire,
+    pk: E::G2,
+    ids: Vec<Vec<u8>>,
+) -> Result<(Ciphertext<E>, EncryptionProof<E>), EvaluatorError> {
+    evaluator.begin_scope();
+    let result = encrypt_and_prove_in_scope(
         pp,
-        &perm_proof.q_com.into_affine(),
-        &hash2,
-        &perm_proof.y5,
-        &perm_proof.pi_5.into_affine(),
-    );
-
-    // Check 0 : b = 1
-    if !b {
-        println!("VerifyPerm - Check 0 failed");
-    }
-
-    // y1 = t(w^63)
-    // y2 = t(hash2)
-    // y3 = t(hash2 / w)
-    // y4 = g(hash2)
-    // y5 = q(hash2)
-    // Check 1 : y2 * (v(hash2) + hash1) - y3 * y4 = y5 * (hash2^k - 1)
-    let tmp1 = perm_proof.y2 * (v.evaluate(&hash2) + hash1);
-    let tmp2 = perm_proof.y3 * perm_proof.y4;
-    let tmp3 = perm_proof.y5 * (hash2.pow([PERM_SIZE as u64]) - F::one());
-
-    b &= tmp1 - tmp2 == tmp3;
-
-    if tmp1 - tmp2 != tmp3 {
-        println!("VerifyPerm - Check 1 failed");
-    }
-
-    // Check 2 : y1 = 1
-    b &= perm_proof.y1 == F::one();
-
-    if perm_proof.y1 != F::one() {
-        println!("VerifyPerm - Check 2 failed");
+        evaluator,
+        cache,
+        card_handles,
+        card_commitment,
+        alpha1,
+        pk,
+        ids,
+    )
+    .await;
+    evaluator.end_scope();
+    if let Ok((ctxt, proof)) = &result {
+        let entry = AuditEntry::encryption_proof(cache.context(), ctxt, proof);
+        evaluator.publish_proof(&entry).await?;
+        evaluator.record_audit(entry);
     }
-
-    b
+    result
 }
 
-/// Produces ciphertexts and links the card commitment to the ciphertexts
-pub async fn encrypt_and_prove(
-    pp: &UniversalParams<Curve>,
-    evaluator: &mut Evaluator,
-    card_handles: Vec<String>,
-    card_commitment: G1, // C = g^{\sum_i card_handles_i L_i(x) + alpha1 * (x^PERM_SIZE - 1)}
-    alpha1: String,
-    pk: G2,
+#[allow(clippy::too_many_arguments)]
+async fn encrypt_and_prove_in_scope<T: Transport, E: CurveConfig>(
+    pp: &UniversalParams<E>,
+    evaluator: &mut Evaluator<T, E>,
+    cache: &mut IdCache<E>,
+    card_handles: Vec<Wire>,
+    card_commitment: E::G1,
+    alpha1: Wire,
+    pk: E::G2,
     ids: Vec<Vec<u8>>,
-) -> (Ciphertext, EncryptionProof) {
-    // Get all cards from card handles
-    let mut cards = vec![];
-    for h in card_handles.clone() {
-        cards.push(evaluator.get_wire(&h));
+) -> Result<(Ciphertext<E>, EncryptionProof<E>), EvaluatorError> {
+    let perm_size = evaluator.params().perm_size();
+    if ids.len() != perm_size {
+        return Err(EvaluatorError::LengthMismatch {
+            expected: perm_size,
+            actual: ids.len(),
+        });
     }
+    evaluator.assert_resources_remaining(&encryption_resources())?;
+    let ctx = *cache.context();
 
     // Sample common randomness for encryption
     let r = evaluator.ran();
 
     // Encrypt the cards to ids with the same pk
     let (c1, c2s) = evaluator
-        .batch_dist_ibe_encrypt_with_common_mask(&card_handles, &r, &pk, ids.as_slice())
-        .await;
+        .batch_dist_ibe_encrypt_with_common_mask(cache, &card_handles, &r, &pk, ids.as_slice())
+        .await?;
 
     // Encrypt an extra "card" with alpha1
     // This id can be anything (different from the others), it will never be opened.
     let (_, alpha1_c2) = evaluator
-        .dist_ibe_encrypt(&alpha1, &r, &pk, BigUint::from(123_u64).to_bytes_le())
-        .await;
+        .dist_ibe_encrypt(
+            cache,
+            &alpha1,
+            &r,
+            &pk,
+            BigUint::from(123_u64).to_bytes_le(),
+        )
+        .await?;
 
     // Hash all the encryptions to get randomness for batching
     let mut bytes = Vec::new();
     let mut c1_bytes = Vec::new();
     let mut c2_bytes = Vec::new();
 
-    c1.serialize_uncompressed(&mut c1_bytes).unwrap();
+    c1.serialize_uncompressed(&mut c1_bytes)
+        .expect("serializing into a Vec does not fail");
     bytes.extend_from_slice(&c1_bytes);
 
-    for i in 0..PERM_SIZE {
-        c2s[i].serialize_uncompressed(&mut c2_bytes).unwrap();
+    for c2 in c2s.iter().take(perm_size) {
+        c2.serialize_uncompressed(&mut c2_bytes)
+            .expect("serializing into a Vec does not fail");
         bytes.extend_from_slice(&c2_bytes);
     }
 
     // Add alpha1 ciphertext to the hash
-    alpha1_c2.serialize_uncompressed(&mut c2_bytes).unwrap();
+    alpha1_c2
+        .serialize_uncompressed(&mut c2_bytes)
+        .expect("serializing into a Vec does not fail");
     bytes.extend_from_slice(&c2_bytes);
 
     // define delta
@@ -686,63 +830,61 @@ pub async fn encrypt_and_prove(
 
     // Evaluate the card commitment at delta and produce opening proof
     // Modified to take into account the hiding term
-    let card_poly = utils::interpolate_poly_over_mult_subgroup(&cards);
-    let vanishing_poly = utils::compute_vanishing_poly(PERM_SIZE);
+    let card_poly = SharedPoly::from_wires(evaluator, &card_handles);
+    let vanishing_poly = utils::compute_vanishing_poly(perm_size);
 
     // Evaluate polynomial at delta, taking into account the hiding term
-    let h_poly_eval_orig = evaluator.share_poly_eval(&card_poly, delta);
+    let h_poly_eval_orig = card_poly.eval_wire(evaluator, delta);
     let h_hiding = evaluator.scale(&alpha1, vanishing_poly.evaluate(&delta));
 
     let h_poly_eval = evaluator.add(&h_poly_eval_orig, &h_hiding);
-    let poly_eval = evaluator.output_wire(&h_poly_eval).await;
+    let poly_eval = evaluator.output_wire(&h_poly_eval).await?;
 
     // Produce opening proof - share
-    let pi_orig = evaluator
-        .eval_proof_with_share_poly(pp, card_poly, delta)
-        .await;
+    let pi_orig = card_poly.proof_share(pp, delta)?;
 
     // divisor(x) = x - delta for the KZG opening proof
-    let divisor = DensePolynomial::from_coefficients_vec(vec![-delta, F::from(1)]);
+    let divisor = DensePolynomial::from_coefficients_vec(vec![-delta, E::ScalarField::one()]);
     let (quotient, _) =
         DenseOrSparsePolynomial::divide_with_q_and_r(&(&vanishing_poly).into(), &(&divisor).into())
             .unwrap();
 
-    let pi_poly = KZG10::commit_g1(pp, &quotient);
+    let pi_poly = KzgOn::<E>::commit_g1(pp, &quotient)?;
     let pi_share = pi_orig + pi_poly.mul(evaluator.get_wire(&alpha1));
 
     // reconstruct the quotient polynomial
     let pi = evaluator
-        .add_g1_elements_from_all_parties(&pi_share, &String::from("new_enc_prove_pi"))
-        .await;
+        .add_g1_elements_from_all_parties(&pi_share, &ctx.label("new_enc_prove_pi"))
+        .await?;
 
     // Batch the pairing bases
     // Evaluate lagrange basis at delta
     let mut lagrange_delta = Vec::new();
-    for i in 0..PERM_SIZE {
+    for i in 0..perm_size {
         lagrange_delta
-            .push(utils::compute_lagrange_basis(i as u64, PERM_SIZE as u64).evaluate(&delta));
+            .push(utils::compute_lagrange_basis(i as u64, perm_size as u64).evaluate(&delta));
     }
 
     // Computing E = prod_i e_i^Li(delta)
-    let mut batch_h = G1::zero();
-    for i in 0..PERM_SIZE {
-        let hash_id = hash_to_g1(ids[i].as_ref());
-        batch_h = batch_h.add(hash_id.mul(lagrange_delta[i]));
+    let mut batch_h = E::G1::zero();
+    for (id, l_i) in ids[..perm_size].iter().zip(&lagrange_delta) {
+        batch_h = batch_h.add(cache.hash(id).mul(*l_i));
     }
-    // Add the contribution from the hiding term (multiplied with (delta^PERM_SIZE - 1))
-    let hash_id = hash_to_g1(&BigUint::from(123_u64).to_bytes_le());
-    batch_h = batch_h.add(hash_id.mul(utils::compute_power(&delta, PERM_SIZE as u64) - F::from(1)));
+    // Add the contribution from the hiding term (multiplied with (delta^perm_size - 1))
+    let hash_id = cache.hash(&BigUint::from(123_u64).to_bytes_le());
+    batch_h = batch_h
+        .add(hash_id.mul(utils::compute_power(&delta, perm_size as u64) - E::ScalarField::one()));
 
-    let e_batch = <Curve as Pairing>::pairing(batch_h, pk);
+    let e_batch = E::pairing(batch_h, pk);
 
     // Compute t = e_batch^r
     let t = evaluator
         .exp_and_reveal_gt(
             vec![e_batch],
             vec![r.clone()],
-            &String::from("new_enc_prove_t"),
+            &ctx.label("new_enc_prove_t"),
         )
-        .await;
+        .await?;
 
     // Sigma protocol to show that t = e_batch^r and c1 = g^r
     // Message 1
@@ -752,31 +894,30 @@ pub async fn encrypt_and_prove(
     let z = evaluator.ran();
     let a1 = evaluator
         .exp_and_reveal_g2(
-            vec![G2::generator()],
+            vec![E::G2::generator()],
             vec![z.clone()],
-            &String::from("new_enc_prove_a1"),
+            &ctx.label("new_enc_prove_a1"),
         )
-        .await;
+        .await?;
     let a2 = evaluator
         .exp_and_reveal_gt(
             vec![e_batch],
             vec![z.clone()],
-            &String::from("new_enc_prove_a2"),
+            &ctx.label("new_enc_prove_a2"),
         )
-        .await;
+        .await?;
 
     // Message 2 - FS Hash of a1,a2
-    let (mut a1_bytes, mut a2_bytes): (Vec<u8>, Vec<u8>) = (Vec::new(), Vec::new());
+    let mut transcript = proof_transcript(b"pok3r-sigma", evaluator.fs_hasher());
+    transcript.append_g2(b"a1", &a1);
+    transcript.append_gt(b"a2", &a2);
 
-    a1.serialize_uncompressed(&mut a1_bytes).unwrap();
-    a2.serialize_uncompressed(&mut a2_bytes).unwrap();
-
-    let eta = utils::fs_hash(vec![&a1_bytes, &a2_bytes], 1);
+    let eta = transcript.challenge_fs_on::<E>(b"eta", 1);
 
     // Message 3
     let mut h_y = evaluator.scale(&r, eta[0]);
     h_y = evaluator.add(&h_y, &z);
-    let y = evaluator.output_wire(&h_y).await;
+    let y = evaluator.output_wire(&h_y).await?;
 
     let sigma_proof = SigmaProof { a1, a2, y };
 
@@ -789,129 +930,610 @@ pub async fn encrypt_and_prove(
         hiding_ciphertext: alpha1_c2,
         t,
         sigma_proof: Some(sigma_proof),
+        hasher: evaluator.fs_hasher(),
     };
 
     let ctxt = (c1, c2s);
 
-    (ctxt, encryption_proof)
+    Ok((ctxt, encryption_proof))
 }
 
-pub fn verify_encryption_argument(
-    pp: &UniversalParams<Curve>,
-    ctxt: &Ciphertext,
-    proof: &EncryptionProof,
-) -> bool {
-    // Common first element of all ciphertexts
-    let c1 = ctxt.0;
+/// DealError describes why a card could not be dealt
+#[derive(Debug)]
+pub enum DealError {
+    Evaluator(EvaluatorError),
+    /// the card at this position was dealt already
+    AlreadyDealt(usize),
+    /// the card at this position was revealed already
+    AlreadyRevealed(usize),
+    /// the position is asked for more than once
+    DuplicatePosition(usize),
+    /// the position holds padding rather than a card
+    Padding(usize),
+    /// every card of the deck was dealt or revealed
+    NoCardsLeft,
+    /// the position is not one of the permutation
+    PositionOutOfRange {
+        position: usize,
+        perm_size: usize,
+    },
+    /// a deck needs a card for every position of the permutation
+    WrongDeckSize {
+        cards: usize,
+        perm_size: usize,
+    },
+}
 
-    // Compute delta
-    let mut bytes = Vec::new();
-    let mut c1_bytes = Vec::new();
-    let mut c2_bytes = Vec::new();
+impl fmt::Display for DealError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DealError::Evaluator(err) => write!(f, "evaluator error: {}", err),
+            DealError::AlreadyDealt(position) => {
+                write!(f, "the card at position {} was dealt already", position)
+            }
+            DealError::AlreadyRevealed(position) => {
+                write!(f, "the card at position {} was revealed already", position)
+            }
+            DealError::DuplicatePosition(position) => {
+                write!(f, "position {} is asked for twice", position)
+            }
+            DealError::Padding(position) => {
+                write!(f, "position {} holds padding, not a card", position)
+            }
+            DealError::NoCardsLeft => write!(f, "no cards are left in the deck"),
+            DealError::PositionOutOfRange {
+                position,
+                perm_size,
+            } => write!(
+                f,
+                "position {} is out of a permutation of {}",
+                position, perm_size
+            ),
+            DealError::WrongDeckSize { cards, perm_size } => write!(
+                f,
+                "{} cards do not fill a permutation of {}",
+                cards, perm_size
+            ),
+        }
+    }
+}
 
-    c1.serialize_uncompressed(&mut c1_bytes).unwrap();
-    bytes.extend_from_slice(&c1_bytes);
+impl error::Error for DealError {}
 
-    for i in 0..PERM_SIZE {
-        ctxt.1[i].serialize_uncompressed(&mut c2_bytes).unwrap();
-        bytes.extend_from_slice(&c2_bytes);
+impl From<EvaluatorError> for DealError {
+    fn from(err: EvaluatorError) -> Self {
+        DealError::Evaluator(err)
     }
+}
 
-    // Add alpha1 ciphertext to the hash
-    proof
-        .hiding_ciphertext
-        .serialize_uncompressed(&mut c2_bytes)
-        .unwrap();
-    bytes.extend_from_slice(&c2_bytes);
+/// ShuffledDeck is a shuffled permutation with the commitment the permutation
+/// argument made to it; it remembers which positions were dealt or revealed
+pub struct ShuffledDeck<E: CurveConfig = Curve> {
+    pub cards: Vec<Wire>,
+    pub commitment: E::G1,
+    params: ProtocolParams,
+    /// the handle the commitment was opened under
+    commitment_handle: String,
+    /// the hiding term of the commitment
+    alpha1: Wire,
+    dealt: HashSet<usize>,
+    revealed: HashSet<usize>,
+}
 
-    let delta = utils::fs_hash(vec![&bytes], 1)[0];
+impl<E: CurveConfig> ShuffledDeck<E> {
+    /// cards, a permutation of params, with the commitment (f_com) and alpha1
+    /// compute_permutation_argument returned for them; fails unless there is a card
+    /// for every position of params
+    pub fn new(
+        params: ProtocolParams,
+        cards: Vec<Wire>,
+        commitment: E::G1,
+        alpha1: Wire,
+    ) -> Result<Self, DealError> {
+        if cards.len() != params.perm_size() {
+            return Err(DealError::WrongDeckSize {
+                cards: cards.len(),
+                perm_size: params.perm_size(),
+            });
+        }
+        Ok(ShuffledDeck {
+            cards,
+            commitment,
+            params,
+            commitment_handle: String::from(PERM_COMMITMENT_HANDLE),
+            alpha1,
+            dealt: HashSet::new(),
+            revealed: HashSet::new(),
+        })
+    }
 
-    // Check evaluation proof
-    if !KZG::verify_opening_proof(
-        pp,
-        &proof.card_commitment.into_affine(),
-        &delta,
-        &proof.card_poly_eval,
-        &proof.eval_proof.into_affine(),
-    ) {
-        return false;
+    /// the sizes of the deck, which verifiers of its proofs must use
+    pub fn params(&self) -> &ProtocolParams {
+        &self.params
     }
 
-    // Compute e_batch
-    let mut lagrange_delta = Vec::new();
-    for i in 0..PERM_SIZE {
-        lagrange_delta
-            .push(utils::compute_lagrange_basis(i as u64, PERM_SIZE as u64).evaluate(&delta));
+    pub fn is_dealt(&self, position: usize) -> bool {
+        self.dealt.contains(&position)
     }
 
-    let mut batch_h = G1::zero();
-    for i in 0..PERM_SIZE {
-        let hash_id = hash_to_g1(proof.ids[i].as_ref());
-        batch_h = batch_h.add(hash_id.mul(lagrange_delta[i]));
+    pub fn is_revealed(&self, position: usize) -> bool {
+        self.revealed.contains(&position)
     }
-    // Add the contribution from the hiding term (multiplied with (delta^PERM_SIZE - 1))
-    let hash_id = hash_to_g1(&BigUint::from(123_u64).to_bytes_le());
-    batch_h = batch_h.add(hash_id.mul(utils::compute_power(&delta, PERM_SIZE as u64) - F::from(1)));
+}
+
+/// PublicCard is a card revealed to everyone, with the opening proof of the deck
+/// commitment at its position
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PublicCard<E: CurveConfig = Curve> {
+    pub position: usize,
+    pub value: E::ScalarField,
+    pub proof: E::G1,
+    /// the card had been dealt to a player before, as hole cards are at showdown
+    pub was_dealt: bool,
+}
+
+/// encrypts the card at position of deck to recipient alone, under the identity
+/// player_card_id(ctx, recipient, position) of the game ctx of cache, and proves
+/// that the plaintext is what the deck commitment holds at ω^position; each
+/// position is dealt once
+pub async fn deal_card<T: Transport, E: CurveConfig>(
+    pp: &UniversalParams<E>,
+    evaluator: &mut Evaluator<T, E>,
+    cache: &mut IdCache<E>,
+    deck: &mut ShuffledDeck<E>,
+    position: usize,
+    recipient: &[u8],
+    pk: E::G2,
+) -> Result<DealtCard<E>, DealError> {
+    let perm_size = deck.params.perm_size();
+    if position >= perm_size {
+        return Err(DealError::PositionOutOfRange {
+            position,
+            perm_size,
+        });
+    }
+    if position < perm_size - deck.params.deck_size() {
+        return Err(DealError::Padding(position));
+    }
+    if deck.is_dealt(position) {
+        return Err(DealError::AlreadyDealt(position));
+    }
+    evaluator.assert_resources_remaining(&deal_resources())?;
+
+    evaluator.begin_scope();
+    let result = deal_card_in_scope(pp, evaluator, cache, deck, position, recipient, pk).await;
+    evaluator.end_scope();
+    let dealt = result?;
+    deck.dealt.insert(position);
+    Ok(dealt)
+}
 
-    let e_batch = <Curve as Pairing>::pairing(batch_h, proof.pk);
+/// deals the card at position of deck to the player of the addr book with peer id
+/// player, as deal_card does with the peer id as recipient, and sends it the
+/// dealt card under DEALT_HANDLE_PREFIX and position; no other peer gets it
+pub async fn deal_card_to_player<T: Transport, E: CurveConfig>(
+    pp: &UniversalParams<E>,
+    evaluator: &mut Evaluator<T, E>,
+    cache: &mut IdCache<E>,
+    deck: &mut ShuffledDeck<E>,
+    position: usize,
+    player: &Pok3rPeerId,
+    pk: E::G2,
+) -> Result<DealtCard<E>, DealError> {
+    let dealt = deal_card(pp, evaluator, cache, deck, position, player.as_bytes(), pk).await?;
+    let handle = format!("{}{}", DEALT_HANDLE_PREFIX, position);
+    evaluator
+        .send_to_player(player, &handle, &dealt.to_bs58())
+        .await
+        .map_err(EvaluatorError::from)?;
+    Ok(dealt)
+}
+
+async fn deal_card_in_scope<T: Transport, E: CurveConfig>(
+    pp: &UniversalParams<E>,
+    evaluator: &mut Evaluator<T, E>,
+    cache: &mut IdCache<E>,
+    deck: &ShuffledDeck<E>,
+    position: usize,
+    recipient: &[u8],
+    pk: E::G2,
+) -> Result<DealtCard<E>, EvaluatorError> {
+    let perm_size = deck.params.perm_size();
+    let card = deck.cards[position].clone();
+    let point = utils::domain_info_of::<E::ScalarField>(perm_size).powers[position];
+    let ctx = *cache.context();
+    let id = player_card_id(&ctx, recipient, position as u64);
+    let (g, h, divisor_h) = deal_bases(pp, point)?;
+
+    let r = evaluator.ran();
+    let s = evaluator.ran();
+    let z_m = evaluator.ran();
+    let z_r = evaluator.ran();
+    let z_s = evaluator.ran();
+
+    let (c1, c2s) = evaluator
+        .batch_dist_ibe_encrypt_with_common_mask(
+            cache,
+            std::slice::from_ref(&card),
+            &r,
+            &pk,
+            std::slice::from_ref(&id),
+        )
+        .await?;
+
+    // our share of the opening proof of the hiding commitment at ω^position,
+    // plus g^[s] so that the opened proof does not give the card away
+    let card_poly = SharedPoly::from_wires(evaluator, &deck.cards);
+    let pi_card = card_poly.proof_share(pp, point)?;
+    let pi_share = pi_card
+        + KzgOn::<E>::commit_g1(pp, &hiding_quotient(perm_size, point))?
+            .mul(evaluator.get_wire(&deck.alpha1))
+        + g.mul(evaluator.get_wire(&s));
+    let label = |name: &str| ctx.label(&format!("deal_{}_{}", name, card.as_str()));
+    let eval_proof = evaluator
+        .add_g1_elements_from_all_parties(&pi_share, &label("pi"))
+        .await?;
+
+    let e_id = cache.pairing(&id, &pk);
+    let base = E::pairing(g, h);
+    let d = E::pairing(g, divisor_h);
+    let a1 = evaluator
+        .exp_and_reveal_g2(vec![E::G2::generator()], vec![z_r.clone()], &label("a1"))
+        .await?;
+    let g_t = evaluator.gt_generator_table().base();
+    let a2 = evaluator
+        .exp_and_reveal_gt(
+            vec![g_t, e_id],
+            vec![z_m.clone(), z_r.clone()],
+            &label("a2"),
+        )
+        .await?;
+    let a3 = evaluator
+        .exp_and_reveal_gt(vec![base, -d], vec![z_m.clone(), z_s.clone()], &label("a3"))
+        .await?;
+
+    let mut dealt = DealtCard {
+        position: position as u64,
+        recipient: recipient.to_vec(),
+        pk,
+        card_commitment: deck.commitment,
+        c1,
+        c2: c2s[0],
+        eval_proof,
+        proof: DealProof {
+            a1,
+            a2,
+            a3,
+            y_m: E::ScalarField::zero(),
+            y_r: E::ScalarField::zero(),
+            y_s: E::ScalarField::zero(),
+        },
+        hasher: evaluator.fs_hasher(),
+    };
+    let eta = deal_challenge(&dealt);
 
-    // Check that prod_i c2_i^Li(delta) * alpha1_c2*(delta*PERM_SIZE - 1) = g^f(delta) * t
-    let mut lhs = Gt::zero();
-    for i in 0..PERM_SIZE {
-        lhs += ctxt.1[i].mul(lagrange_delta[i]);
+    let mut responses = vec![];
+    for (witness, nonce) in [(&card, &z_m), (&r, &z_r), (&s, &z_s)] {
+        let scaled = evaluator.scale(witness, eta);
+        responses.push(evaluator.add(&scaled, nonce));
     }
-    lhs += proof
-        .hiding_ciphertext
-        .mul(utils::compute_power(&delta, PERM_SIZE as u64) - F::from(1));
+    let ys = evaluator.batch_output_wire(&responses).await?;
+    dealt.proof.y_m = ys[0];
+    dealt.proof.y_r = ys[1];
+    dealt.proof.y_s = ys[2];
+    Ok(dealt)
+}
 
-    let mut rhs = Gt::generator().mul(proof.card_poly_eval);
-    rhs = rhs.add(proof.t);
+/// opens the cards at positions to everyone, each with a proof that it is the card
+/// the deck commitment holds there; cards dealt before may be revealed, and are
+/// flagged as such
+pub async fn reveal_public_cards<T: Transport, E: CurveConfig>(
+    pp: &UniversalParams<E>,
+    evaluator: &mut Evaluator<T, E>,
+    deck: &mut ShuffledDeck<E>,
+    positions: &[usize],
+) -> Result<Vec<PublicCard<E>>, DealError> {
+    let perm_size = deck.params.perm_size();
+    let padding = perm_size - deck.params.deck_size();
+    let mut seen = HashSet::new();
+    for position in positions.iter().copied() {
+        if position >= perm_size {
+            return Err(DealError::PositionOutOfRange {
+                position,
+                perm_size,
+            });
+        }
+        if position < padding {
+            return Err(DealError::Padding(position));
+        }
+        if !seen.insert(position) {
+            return Err(DealError::DuplicatePosition(position));
+        }
+        if deck.is_revealed(position) {
+            return Err(DealError::AlreadyRevealed(position));
+        }
+    }
 
-    if !lhs.eq(&rhs) {
-        return false;
+    evaluator.begin_scope();
+    let result = reveal_public_cards_in_scope(pp, evaluator, deck, positions).await;
+    evaluator.end_scope();
+    let opened = result?;
+
+    // a proof that fails is pinned on the party whose shares do not verify, as
+    // far as enable_accountability allows
+    for (position, (value, proof)) in positions.iter().zip(opened.iter()) {
+        if !verify_public_card(pp, &deck.params, &deck.commitment, *position, value, proof) {
+            let card = &deck.cards[*position];
+            let proof_handle = reveal_proof_handle(card);
+            let point = utils::domain_info_of::<E::ScalarField>(perm_size).powers[*position];
+            let err = evaluator
+                .blame_opening_proof(
+                    pp,
+                    &deck.commitment_handle,
+                    card.as_str(),
+                    &proof_handle,
+                    point,
+                )
+                .unwrap_or(EvaluatorError::InvalidProof {
+                    handle: proof_handle,
+                });
+            return Err(err.into());
+        }
     }
+    deck.revealed.extend(positions.iter().copied());
+
+    Ok(positions
+        .iter()
+        .zip(opened)
+        .map(|(position, (value, proof))| PublicCard {
+            position: *position,
+            value,
+            proof,
+            was_dealt: deck.is_dealt(*position),
+        })
+        .collect())
+}
 
-    // Check sigma proof
-    // Compute hash to get eta
-    let (mut a1_bytes, mut a2_bytes): (Vec<u8>, Vec<u8>) = (Vec::new(), Vec::new());
+async fn reveal_public_cards_in_scope<T: Transport, E: CurveConfig>(
+    pp: &UniversalParams<E>,
+    evaluator: &mut Evaluator<T, E>,
+    deck: &ShuffledDeck<E>,
+    positions: &[usize],
+) -> Result<Vec<(E::ScalarField, E::G1)>, EvaluatorError> {
+    let perm_size = deck.params.perm_size();
+    let powers = &utils::domain_info_of::<E::ScalarField>(perm_size).powers;
+    let points = positions
+        .iter()
+        .map(|position| powers[*position])
+        .collect::<Vec<E::ScalarField>>();
+
+    let card_poly = SharedPoly::from_wires(evaluator, &deck.cards);
+    let pi_cards = evaluator
+        .batch_eval_proof_with_share_poly(
+            pp,
+            &vec![card_poly.share().clone(); points.len()],
+            &points,
+        )
+        .await?;
 
-    proof
-        .sigma_proof
-        .as_ref()
-        .unwrap()
-        .a1
-        .serialize_uncompressed(&mut a1_bytes)
-        .unwrap();
-    proof
-        .sigma_proof
-        .as_ref()
-        .unwrap()
-        .a2
-        .serialize_uncompressed(&mut a2_bytes)
-        .unwrap();
+    // add the opening of the hiding term alpha1 * (x^perm_size - 1)
+    let alpha1 = evaluator.get_wire(&deck.alpha1);
+    let pi_shares = pi_cards
+        .iter()
+        .zip(points.iter())
+        .map(|(pi, point)| {
+            let hiding = KzgOn::<E>::commit_g1(pp, &hiding_quotient(perm_size, *point))?;
+            Ok(*pi + hiding.mul(alpha1))
+        })
+        .collect::<Result<Vec<E::G1>, KzgError>>()?;
+    let labels = positions
+        .iter()
+        .map(|position| reveal_proof_handle(&deck.cards[*position]))
+        .collect::<Vec<String>>();
+    let proofs = evaluator
+        .batch_add_g1_elements_from_all_parties(&pi_shares, &labels)
+        .await?;
 
-    let eta = utils::fs_hash(vec![&a1_bytes, &a2_bytes], 1);
+    let wires = positions
+        .iter()
+        .map(|position| deck.cards[*position].clone())
+        .collect::<Vec<Wire>>();
+    let values = evaluator.batch_output_wire(&wires).await?;
 
-    // Check statement 1
-    let lhs = G2::generator().mul(proof.sigma_proof.as_ref().unwrap().y);
-    let rhs = c1.mul(eta[0]).add(proof.sigma_proof.as_ref().unwrap().a1);
+    Ok(values.into_iter().zip(proofs).collect())
+}
 
-    if !lhs.eq(&rhs) {
-        return false;
+/// shuffles again the cards of deck that are neither at dealt_positions nor dealt
+/// or revealed through the deck, into a deck over the smallest domain that fits
+/// them. A fresh permutation σ of the new domain is drawn and proven, and the card
+/// at position i of the new deck is sum_j card_j L_j(σ_i) over the Lagrange basis,
+/// that is the card left that σ_i points to. The parties then check that the new
+/// cards are the ones left, as products of (γ - card) under a challenge γ hashed
+/// from both commitments, and open the difference of the products under a mask.
+pub async fn reshuffle_remaining<T: Transport, E: CurveConfig>(
+    pp: &UniversalParams<E>,
+    evaluator: &mut Evaluator<T, E>,
+    deck: &ShuffledDeck<E>,
+    dealt_positions: &[usize],
+) -> Result<(ShuffledDeck<E>, ReshuffleProof<E>), DealError> {
+    let perm_size = deck.params.perm_size();
+    let padding = perm_size - deck.params.deck_size();
+    let mut dealt = HashSet::new();
+    for position in dealt_positions.iter().copied() {
+        if position >= perm_size {
+            return Err(DealError::PositionOutOfRange {
+                position,
+                perm_size,
+            });
+        }
+        if position < padding {
+            return Err(DealError::Padding(position));
+        }
+        if !dealt.insert(position) {
+            return Err(DealError::DuplicatePosition(position));
+        }
+    }
+    let positions = (padding..perm_size)
+        .filter(|p| !dealt.contains(p) && !deck.is_dealt(*p) && !deck.is_revealed(*p))
+        .collect::<Vec<usize>>();
+    if positions.is_empty() {
+        return Err(DealError::NoCardsLeft);
+    }
+    let new_params =
+        reshuffle_params(positions.len()).expect("the cards left fit in the domain of the deck");
+    let params = *evaluator.params();
+    evaluator.assert_resources_remaining(&reshuffle_resources(&params, &new_params))?;
+
+    evaluator.begin_scope();
+    let result = reshuffle_in_scope(pp, evaluator, deck, &positions, &new_params).await;
+    if let Ok((cards, alpha1, _)) = &result {
+        cards.iter().for_each(|h| evaluator.keep(h));
+        evaluator.keep(alpha1);
     }
+    evaluator.end_scope();
+    let (cards, alpha1, proof) = result?;
+
+    let entry = AuditEntry::reshuffle_proof(&proof);
+    evaluator
+        .publish_proof(&entry)
+        .await
+        .map_err(EvaluatorError::from)?;
+    evaluator.record_audit(entry);
+
+    let commitment_handle = reshuffle_commitment_handle(&cards);
+    let mut new_deck = ShuffledDeck::new(new_params, cards, proof.commitment, alpha1)?;
+    new_deck.commitment_handle = commitment_handle;
+    Ok((new_deck, proof))
+}
+
+async fn reshuffle_in_scope<T: Transport, E: CurveConfig>(
+    pp: &UniversalParams<E>,
+    evaluator: &mut Evaluator<T, E>,
+    deck: &ShuffledDeck<E>,
+    positions: &[usize],
+    new_params: &ProtocolParams,
+) -> Result<(Vec<Wire>, Wire, ReshuffleProof<E>), EvaluatorError> {
+    let (perm_size, num_cards) = (new_params.perm_size(), new_params.deck_size());
+
+    let sigma = sample_deck(evaluator, new_params).await?;
+    let (permutation, _) = prove_permutation(pp, evaluator, new_params, &sigma).await?;
+
+    // the padding of σ points past the cards left, which it leaves at zero
+    let old_cards = positions
+        .iter()
+        .map(|position| deck.cards[*position].clone())
+        .collect::<Vec<Wire>>();
+    let cards = apply_shared_permutation(evaluator, &sigma, &old_cards).await?;
+
+    // commit to the new cards, hidden by alpha1 * (x^perm_size - 1)
+    let alpha1 = evaluator.ran();
+    let card_poly = SharedPoly::from_wires(evaluator, &cards);
+    let com_share = card_poly.commit_share(pp)?
+        + KzgOn::<E>::commit_g1(pp, &utils::compute_vanishing_poly(perm_size))?
+            .mul(evaluator.get_wire(&alpha1));
+    let commitment = evaluator
+        .add_g1_elements_from_all_parties(&com_share, &reshuffle_commitment_handle(&cards))
+        .await?;
+
+    let mut proof = ReshuffleProof {
+        positions: positions.iter().map(|p| *p as u64).collect(),
+        old_commitment: deck.commitment,
+        commitment,
+        permutation,
+        masked_difference: E::ScalarField::zero(),
+        hasher: evaluator.fs_hasher(),
+    };
+    let gamma = reshuffle_challenge(&proof);
 
-    // Check statement 2
-    let lhs = e_batch.mul(proof.sigma_proof.as_ref().unwrap().y);
-    let rhs = proof
-        .t
-        .mul(eta[0])
-        .add(proof.sigma_proof.as_ref().unwrap().a2);
+    let old_factors = positions
+        .iter()
+        .map(|position| {
+            let negated = evaluator.mul_public(&deck.cards[*position], -E::ScalarField::one());
+            evaluator.clear_add(&negated, gamma)
+        })
+        .collect::<Vec<Wire>>();
+    let new_factors = cards[perm_size - num_cards..]
+        .iter()
+        .map(|card| {
+            let negated = evaluator.mul_public(card, -E::ScalarField::one());
+            evaluator.clear_add(&negated, gamma)
+        })
+        .collect::<Vec<Wire>>();
+    let products = batch_products(evaluator, vec![old_factors, new_factors]).await?;
+    let difference = evaluator.sub(&products[0], &products[1]);
+    let mask = evaluator.ran();
+    let masked = evaluator.mult(&mask, &difference).await?;
+    proof.masked_difference = evaluator.output_wire(&masked).await?;
+
+    Ok((cards, alpha1, proof))
+}
 
-    if !lhs.eq(&rhs) {
-        return false;
+/// the product of each of factors, multiplied pairwise so that all of them take
+/// log rounds
+async fn batch_products<T: Transport, E: CurveConfig>(
+    evaluator: &mut Evaluator<T, E>,
+    mut factors: Vec<Vec<Wire>>,
+) -> Result<Vec<Wire>, EvaluatorError> {
+    while factors.iter().any(|f| f.len() > 1) {
+        let mut xs = vec![];
+        let mut ys = vec![];
+        for pair in factors.iter().flat_map(|f| f.chunks_exact(2)) {
+            xs.push(pair[0].clone());
+            ys.push(pair[1].clone());
+        }
+        let mut products = evaluator.batch_mult(&xs, &ys).await?.into_iter();
+        factors = factors
+            .into_iter()
+            .map(|f| {
+                let mut next = f
+                    .chunks_exact(2)
+                    .map(|_| products.next().unwrap())
+                    .collect::<Vec<Wire>>();
+                next.extend(f.chunks_exact(2).remainder().iter().cloned());
+                next
+            })
+            .collect();
     }
+    Ok(factors.into_iter().map(|mut f| f.pop().unwrap()).collect())
+}
+
+/// the challenge γ of the multiset check of a reshuffle
+fn reshuffle_challenge<E: CurveConfig>(proof: &ReshuffleProof<E>) -> E::ScalarField {
+    let mut transcript = proof_transcript(b"pok3r-reshuffle", proof.hasher);
+    let positions = proof
+        .positions
+        .iter()
+        .flat_map(|p| p.to_le_bytes())
+        .collect::<Vec<u8>>();
+    transcript.append_bytes(b"positions", &positions);
+    transcript.append_g1(b"old_commitment", &proof.old_commitment);
+    transcript.append_g1(b"commitment", &proof.commitment);
+    transcript.append_g1(b"sigma_com", &proof.permutation.f_com);
+
+    transcript.challenge_f_on::<E>(b"gamma")
+}
+
+/// checks the permutation of a reshuffle, and that the multiset check passed
+pub fn verify_reshuffle<E: CurveConfig>(
+    pp: &UniversalParams<E>,
+    proof: &ReshuffleProof<E>,
+) -> bool {
+    let params = match reshuffle_params(proof.positions.len()) {
+        Ok(params) => params,
+        Err(_) => return false,
+    };
+    let distinct = proof.positions.iter().collect::<HashSet<&u64>>().len();
+    distinct == proof.positions.len()
+        && verify_permutation_argument(pp, &params, &proof.permutation)
+        && proof.masked_difference.is_zero()
+}
+
+fn reveal_proof_handle(card: &Wire) -> String {
+    format!("reveal_pi_{}", card.as_str())
+}
 
-    true
+fn reshuffle_commitment_handle(cards: &[Wire]) -> String {
+    format!("reshuffle_f_{}", cards[0].as_str())
 }
 
 /// Estimating time to decrypt one card at game time
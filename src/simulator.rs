@@ -0,0 +1,2120 @@
+//! In-process multi-party simulator: runs n evaluators wired together over
+//! in-memory channels and collects what each party returns.
+
+use futures::future::{join_all, try_join_all};
+use rand::{rngs::StdRng, SeedableRng};
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+use crate::common::ProtocolParams;
+use crate::evaluator::{Evaluator, EvaluatorBuilder};
+use crate::transport::InMemoryTransport;
+
+pub type SimEvaluator = Evaluator<InMemoryTransport>;
+
+/// environment variable that, if set, fixes the seed used by run_parties
+pub const SEED_ENV_VAR: &str = "POK3R_SIM_SEED";
+
+/// runs f for each of n parties and returns the per-party outputs, ordered by node id.
+/// The seed is taken from POK3R_SIM_SEED if set, and random otherwise; it is printed
+/// so that a failing run can be replayed with run_parties_with_seed.
+pub fn run_parties<Fut>(n: usize, f: impl Fn(SimEvaluator) -> Fut) -> Vec<Fut::Output>
+where
+    Fut: Future,
+{
+    run_parties_with_seed(n, sim_seed(), f)
+}
+
+/// the seed run_parties uses: POK3R_SIM_SEED if set, and random otherwise
+pub fn sim_seed() -> u64 {
+    let seed = match std::env::var(SEED_ENV_VAR) {
+        Ok(s) => s.parse().expect("POK3R_SIM_SEED must be a u64"),
+        Err(_) => rand::random::<u64>(),
+    };
+    println!("simulator seed: {}", seed);
+    seed
+}
+
+/// same as run_parties, with every party's local randomness derived from seed.
+/// Parties are polled on the calling thread so that interleavings are reproducible.
+pub fn run_parties_with_seed<Fut>(
+    n: usize,
+    seed: u64,
+    f: impl Fn(SimEvaluator) -> Fut,
+) -> Vec<Fut::Output>
+where
+    Fut: Future,
+{
+    run_parties_with_params(n, seed, ProtocolParams::default(), f)
+}
+
+/// same as run_parties_with_seed, for decks and permutations of the sizes of params
+pub fn run_parties_with_params<Fut>(
+    n: usize,
+    seed: u64,
+    params: ProtocolParams,
+    f: impl Fn(SimEvaluator) -> Fut,
+) -> Vec<Fut::Output>
+where
+    Fut: Future,
+{
+    async_std::task::block_on(async {
+        let transports = InMemoryTransport::new_network(n).await;
+        let evaluators = build_on(transports, |i, transport| {
+            Evaluator::new_with_seed_and_params(transport, party_seed(seed, i), params)
+        })
+        .await;
+
+        join_all(evaluators.into_iter().map(f)).await
+    })
+}
+
+/// the evaluators of an in-memory network of n parties, each built from its
+/// transport by build, ordered by node id; for callers that already run on an
+/// executor and need settings of their own
+pub async fn build_parties<Fut, E>(
+    n: usize,
+    mut build: impl FnMut(InMemoryTransport) -> Fut,
+) -> Vec<SimEvaluator>
+where
+    Fut: Future<Output = Result<SimEvaluator, E>>,
+    E: fmt::Debug,
+{
+    let transports = InMemoryTransport::new_network(n).await;
+    build_on(transports, |_, transport| build(transport)).await
+}
+
+/// runs f for each of the parties build_parties builds, and returns the per-party
+/// outputs ordered by node id
+pub async fn run_built_parties<Fut, E, Out>(
+    n: usize,
+    build: impl FnMut(InMemoryTransport) -> Fut,
+    f: impl FnMut(SimEvaluator) -> Out,
+) -> Vec<Out::Output>
+where
+    Fut: Future<Output = Result<SimEvaluator, E>>,
+    E: fmt::Debug,
+    Out: Future,
+{
+    join_all(build_parties(n, build).await.into_iter().map(f)).await
+}
+
+/// builds the evaluator of each of transports from its index and the transport
+async fn build_on<Fut, E>(
+    transports: Vec<InMemoryTransport>,
+    mut build: impl FnMut(usize, InMemoryTransport) -> Fut,
+) -> Vec<SimEvaluator>
+where
+    Fut: Future<Output = Result<SimEvaluator, E>>,
+    E: fmt::Debug,
+{
+    let builds = transports
+        .into_iter()
+        .enumerate()
+        .map(|(i, transport)| build(i, transport));
+    try_join_all(builds)
+        .await
+        .expect("cannot preprocess for the simulated parties")
+}
+
+/// how the in-memory links between simulated parties deliver messages
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Latency {
+    /// at once, so that a run measures computation alone
+    #[default]
+    None,
+    /// each message is held for the duration first, so that a run also pays for
+    /// its rounds
+    Fixed(Duration),
+}
+
+/// n parties over links with latency, each built by builder with its local
+/// randomness derived from seed and already preprocessed; benchmarks set them up
+/// outside of what they time, then hand them to run_prepared
+pub fn setup_parties(
+    n: usize,
+    seed: u64,
+    latency: Latency,
+    builder: impl Fn() -> EvaluatorBuilder,
+) -> Vec<SimEvaluator> {
+    async_std::task::block_on(async {
+        let transports = match latency {
+            Latency::None => InMemoryTransport::new_network(n).await,
+            Latency::Fixed(latency) => {
+                InMemoryTransport::new_network_with_latency(n, latency).await
+            }
+        };
+        build_on(transports, |i, transport| {
+            builder()
+                .rng(StdRng::from_seed(party_seed(seed, i)))
+                .build(transport)
+        })
+        .await
+    })
+}
+
+/// runs f for each of parties, polled on the calling thread, and returns the
+/// per-party outputs in the order of parties
+pub fn run_prepared<T, Fut>(parties: Vec<T>, f: impl Fn(T) -> Fut) -> Vec<Fut::Output>
+where
+    Fut: Future,
+{
+    async_std::task::block_on(join_all(parties.into_iter().map(f)))
+}
+
+fn party_seed(seed: u64, party_index: usize) -> [u8; 32] {
+    let mut bytes = [0u8; 32];
+    bytes[..8].copy_from_slice(&seed.to_le_bytes());
+    bytes[8..16].copy_from_slice(&(party_index as u64).to_le_bytes());
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        party_seed, run_parties, run_parties_with_params, run_prepared, setup_parties, sim_seed,
+        Latency, SimEvaluator,
+    };
+    use crate::accountability::Evidence;
+    use crate::address_book::{PeerRole, Pok3rAddrBook, Pok3rPeerId};
+    use crate::circuit::{CircuitBuilder, ClearEvaluator};
+    use crate::common::{
+        Curve, DealtCard, DeckSpec, EvalNetMsg, PayloadKind, PeerInfo, PermutationProof,
+        ProtocolParams, ResourceEstimate, F, G1, PERM_SIZE,
+    };
+    use crate::encoding::{encode_as_bytes, ProofEncoding};
+    use crate::evaluator::{
+        Evaluator, EvaluatorBuilder, EvaluatorError, OpenMode, QuorumPolicy, PROOF_HANDLE_PREFIX,
+    };
+    use crate::hash::{card_id, player_card_id, GameContext, IdCache};
+    use crate::kzg::UniversalParams;
+    use crate::metrics::Metrics;
+    use crate::network::{HeartbeatConfig, NetworkError, PeerStatus, RetransmitConfig};
+    use crate::shuffler::{
+        compute_decryption_cache, compute_decryption_key, compute_keyper_keys, compute_params,
+        compute_permutation_argument, compute_permutation_argument_v2, deal_card,
+        deal_card_to_player, deal_resources, decrypt_one_card, encrypt_and_prove,
+        encryption_resources, permutation_resources, reshuffle_params, reshuffle_remaining,
+        reshuffle_resources, resources, reveal_public_cards, shuffle_deck, shuffle_deck_with_spec,
+        shuffle_resources, verify_dealt_card, verify_encryption_argument,
+        verify_permutation_argument, verify_permutation_argument_v2, verify_public_card,
+        verify_reshuffle, DealError, PublicCard, ShuffledDeck, DEALT_HANDLE_PREFIX,
+        PERM_COMMITMENT_HANDLE,
+    };
+    use crate::transcript::FsHasher;
+    use crate::transport::{InMemoryTransport, LinkFilter, Transport};
+    use crate::utils;
+    use crate::wasm;
+    use crate::wire::Wire;
+    use ark_serialize::CanonicalSerialize;
+    use ark_std::{One, Zero};
+    use async_trait::async_trait;
+    use futures::future::{join_all, try_join_all};
+    use futures::StreamExt;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha20Rng;
+    use std::collections::{HashMap, HashSet};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_prepared_parties_agree_with_and_without_latency() {
+        let resources = ResourceEstimate {
+            triples: 8,
+            rand_sharings: 0,
+        };
+        let outputs = [Latency::None, Latency::Fixed(Duration::from_millis(1))].map(|latency| {
+            let parties = setup_parties(3, 9, latency, || {
+                EvaluatorBuilder::new().preprocessing(resources)
+            });
+            assert!(parties.iter().all(|p| p.remaining().triples >= 8));
+            run_prepared(parties, |mut evaluator| async move {
+                let xs = (0..8u64)
+                    .map(|i| evaluator.fixed_wire_handle(F::from(i)))
+                    .collect::<Vec<Wire>>();
+                let ys = (0..8u64)
+                    .map(|i| evaluator.fixed_wire_handle(F::from(i + 2)))
+                    .collect::<Vec<Wire>>();
+                let zs = evaluator.batch_mult(&xs, &ys).await.unwrap();
+                evaluator.batch_output_wire(&zs).await.unwrap()
+            })
+        });
+
+        let expected = (0..8u64).map(|i| F::from(i * (i + 2))).collect::<Vec<F>>();
+        for parties in outputs.iter() {
+            assert!(parties.iter().all(|opened| *opened == expected));
+        }
+    }
+
+    #[test]
+    fn test_sim_batch_mult() {
+        let outputs = run_parties(4, |mut evaluator| async move {
+            let xs = (0..32u64)
+                .map(|i| evaluator.fixed_wire_handle(F::from(i + 1)))
+                .collect::<Vec<Wire>>();
+            let rs = (0..32).map(|_| evaluator.ran()).collect::<Vec<Wire>>();
+
+            let xrs = evaluator.batch_mult(&xs, &rs).await.unwrap();
+            let opened_xrs = evaluator.batch_output_wire(&xrs).await.unwrap();
+            let opened_rs = evaluator.batch_output_wire(&rs).await.unwrap();
+            (opened_xrs, opened_rs)
+        });
+
+        for (xrs, rs) in outputs.iter() {
+            assert_eq!(*xrs, outputs[0].0);
+            for i in 0..32 {
+                assert_eq!(xrs[i], F::from(i as u64 + 1) * rs[i]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sim_batch_mult_with_48_parties() {
+        // just the triples the products take, as the parties make them jointly
+        let resources = ResourceEstimate {
+            triples: 8,
+            rand_sharings: 0,
+        };
+        let parties = setup_parties(48, sim_seed(), Latency::None, || {
+            EvaluatorBuilder::new().preprocessing(resources)
+        });
+        let outputs = run_prepared(parties, |mut evaluator| async move {
+            let xs = (0..8u64)
+                .map(|i| evaluator.fixed_wire_handle(F::from(i)))
+                .collect::<Vec<Wire>>();
+            let ys = (0..8u64)
+                .map(|i| evaluator.fixed_wire_handle(F::from(i + 1)))
+                .collect::<Vec<Wire>>();
+
+            let zs = evaluator.batch_mult(&xs, &ys).await.unwrap();
+            evaluator.batch_output_wire(&zs).await.unwrap()
+        });
+
+        assert_eq!(outputs.len(), 48);
+        let expected = (0..8u64).map(|i| F::from(i * (i + 1))).collect::<Vec<F>>();
+        for output in outputs {
+            assert_eq!(output, expected);
+        }
+    }
+
+    /// opens a, b and c of every triple an evaluator preprocessed
+    async fn open_all_triples(mut evaluator: SimEvaluator) -> Vec<(F, F, F)> {
+        let triples = evaluator.batch_beaver(evaluator.remaining().triples);
+        let handles: Vec<Wire> = triples
+            .iter()
+            .flat_map(|(a, b, c)| [a.clone(), b.clone(), c.clone()])
+            .collect();
+        let opened = evaluator.batch_output_wire(&handles).await.unwrap();
+        opened.chunks(3).map(|t| (t[0], t[1], t[2])).collect()
+    }
+
+    #[test]
+    fn test_sim_preprocessed_triples_multiply() {
+        let params = ProtocolParams::new(16, 10).unwrap();
+        for n in [3, 5] {
+            let outputs = run_parties_with_params(n, sim_seed(), params, open_all_triples);
+            assert!(!outputs[0].is_empty());
+            for triples in outputs.iter() {
+                assert_eq!(*triples, outputs[0]);
+                assert!(triples.iter().all(|(a, b, c)| *c == *a * *b));
+            }
+        }
+
+        // two parties cannot make triples jointly, and take them from a dealer
+        let parties = setup_parties(2, sim_seed(), Latency::None, || {
+            EvaluatorBuilder::new().params(params).dealer(2)
+        });
+        let outputs = run_prepared(parties, open_all_triples);
+        assert!(!outputs[0].is_empty());
+        for triples in outputs.iter() {
+            assert_eq!(*triples, outputs[0]);
+            assert!(triples.iter().all(|(a, b, c)| *c == *a * *b));
+        }
+
+        let outputs = async_std::task::block_on(async {
+            let transports = InMemoryTransport::new_network(4).await;
+            let evaluators = join_all(transports.into_iter().map(|transport| {
+                EvaluatorBuilder::new()
+                    .params(params)
+                    .threshold(1)
+                    .build(transport)
+            }))
+            .await;
+            join_all(
+                evaluators
+                    .into_iter()
+                    .map(|evaluator| open_all_triples(evaluator.unwrap())),
+            )
+            .await
+        });
+        for triples in outputs.iter() {
+            assert_eq!(*triples, outputs[0]);
+            assert!(triples.iter().all(|(a, b, c)| *c == *a * *b));
+        }
+    }
+
+    #[test]
+    fn test_sim_batch_inv() {
+        let outputs = run_parties(4, |mut evaluator| async move {
+            let xs = (0..32).map(|_| evaluator.ran()).collect::<Vec<Wire>>();
+            let x_invs = evaluator.batch_inv(&xs).await.unwrap();
+
+            let opened_xs = evaluator.batch_output_wire(&xs).await.unwrap();
+            let opened_x_invs = evaluator.batch_output_wire(&x_invs).await.unwrap();
+            (opened_xs, opened_x_invs)
+        });
+
+        for (xs, x_invs) in outputs.iter() {
+            for i in 0..32 {
+                assert_eq!(xs[i] * x_invs[i], F::from(1));
+            }
+        }
+    }
+
+    #[test]
+    fn test_sim_tables_sharing_a_router_stay_apart() {
+        let outputs = async_std::task::block_on(async {
+            let networks =
+                InMemoryTransport::new_overlapping_networks(3, &["table-1", "table-2"]).await;
+            let evaluators =
+                try_join_all(networks.into_iter().enumerate().flat_map(|(t, network)| {
+                    network.into_iter().enumerate().map(move |(i, transport)| {
+                        Evaluator::new_with_seed(transport, party_seed(t as u64, i))
+                    })
+                }))
+                .await
+                .unwrap();
+
+            // both tables run the same circuit, hence use the same gate counters,
+            // on different inputs
+            join_all(
+                evaluators
+                    .into_iter()
+                    .enumerate()
+                    .map(|(k, mut evaluator)| async move {
+                        let table = (k / 3) as u64;
+                        let xs = (0..8u64)
+                            .map(|i| evaluator.fixed_wire_handle(F::from(100 * table + i)))
+                            .collect::<Vec<Wire>>();
+                        let rs = (0..8).map(|_| evaluator.ran()).collect::<Vec<Wire>>();
+                        let xrs = evaluator.batch_mult(&xs, &rs).await.unwrap();
+                        let opened_xrs = evaluator.batch_output_wire(&xrs).await.unwrap();
+                        let opened_rs = evaluator.batch_output_wire(&rs).await.unwrap();
+                        (table, xs[0].clone(), opened_xrs, opened_rs)
+                    }),
+            )
+            .await
+        });
+
+        assert_ne!(outputs[0].1, outputs[3].1);
+        for (table, _, xrs, rs) in outputs.iter() {
+            for i in 0..8 {
+                assert_eq!(xrs[i], F::from(100 * table + i as u64) * rs[i]);
+            }
+        }
+    }
+
+    /// exercises every gate of CircuitBuilder on inputs, and opens the results
+    async fn reference_circuit<C: CircuitBuilder>(
+        circuit: &mut C,
+        inputs: &[F],
+    ) -> (Vec<F>, Vec<G1>) {
+        let xs: Vec<Wire> = inputs
+            .iter()
+            .map(|x| circuit.fixed_wire_handle(*x))
+            .collect();
+        let ys: Vec<Wire> = xs.iter().rev().cloned().collect();
+
+        circuit.begin_scope();
+        let sums: Vec<Wire> = xs.iter().zip(&ys).map(|(x, y)| circuit.add(x, y)).collect();
+        let diffs: Vec<Wire> = xs
+            .iter()
+            .zip(&ys)
+            .map(|(x, y)| {
+                let d = circuit.sub(x, y);
+                circuit.clear_add(&d, F::from(1000u64))
+            })
+            .collect();
+        let products = circuit.batch_mult(&sums, &diffs).await.unwrap();
+        let scaled: Vec<Wire> = products
+            .iter()
+            .map(|p| circuit.scale(p, F::from(7u64)))
+            .collect();
+        let invs = circuit.batch_inv(&scaled).await.unwrap();
+        let square = circuit.mult(&invs[0], &invs[0]).await.unwrap();
+        let bits: Vec<Wire> = (0..xs.len() as u64)
+            .map(|i| circuit.fixed_wire_handle(F::from(i % 2)))
+            .collect();
+        let selected = circuit.batch_select(&bits, &sums, &diffs).await.unwrap();
+        let (firsts, seconds) = circuit.batch_obliv_swap(&bits, &xs, &ys).await.unwrap();
+        let mut results = invs;
+        results.push(square);
+        results.extend(selected.into_iter().chain(firsts).chain(seconds));
+        results.iter().for_each(|r| circuit.keep(r));
+        circuit.end_scope();
+
+        let opened = circuit.batch_output_wire(&results).await.unwrap();
+        let in_exponent = circuit
+            .batch_output_wire_in_exponent(&results)
+            .await
+            .unwrap();
+        (opened, in_exponent)
+    }
+
+    #[test]
+    fn test_sim_public_maps_are_local() {
+        // output 0 is 2 x0 + 3 x2, output 1 is empty, output 2 is -x1
+        let rows = vec![
+            vec![(0, F::from(2u64)), (2, F::from(3u64))],
+            vec![],
+            vec![(1, -F::one())],
+        ];
+        let outputs = run_parties(3, |mut evaluator| {
+            let rows = rows.clone();
+            async move {
+                let xs = (1..=3u64)
+                    .map(|i| evaluator.fixed_wire_handle(F::from(i)))
+                    .collect::<Vec<Wire>>();
+                let masks = evaluator.batch_zero_share(3).await.unwrap();
+                let xs = xs
+                    .iter()
+                    .zip(&masks)
+                    .map(|(x, mask)| evaluator.add(x, mask))
+                    .collect::<Vec<Wire>>();
+
+                let before = evaluator.metrics();
+                let mut results = evaluator.apply_public_matrix(&rows, &xs);
+                results.extend(evaluator.apply_public_permutation(&[2, 0, 1], &xs));
+                results.push(evaluator.mul_public(&xs[1], F::from(5u64)));
+                let after = evaluator.metrics();
+                (
+                    before,
+                    after,
+                    evaluator.batch_output_wire(&results).await.unwrap(),
+                )
+            }
+        });
+
+        for (before, after, opened) in outputs {
+            assert_eq!(before, after);
+            let expected = vec![
+                F::from(11u64),
+                F::zero(),
+                -F::from(2u64),
+                F::from(3u64),
+                F::from(1u64),
+                F::from(2u64),
+                F::from(10u64),
+            ];
+            assert_eq!(opened, expected);
+        }
+    }
+
+    #[test]
+    fn test_sim_select_takes_a_triple_and_a_round() {
+        let outputs = run_parties(3, |mut evaluator| async move {
+            let bits = [0u64, 1, 1, 0, 1]
+                .iter()
+                .map(|b| evaluator.fixed_wire_handle(F::from(*b)))
+                .collect::<Vec<Wire>>();
+            let xs = (0..5u64)
+                .map(|i| evaluator.fixed_wire_handle(F::from(10 + i)))
+                .collect::<Vec<Wire>>();
+            let ys = (0..5u64)
+                .map(|i| evaluator.fixed_wire_handle(F::from(20 + i)))
+                .collect::<Vec<Wire>>();
+
+            let before = evaluator.metrics();
+            let selected = evaluator.batch_select(&bits, &xs, &ys).await.unwrap();
+            let after_select = evaluator.metrics();
+            let (firsts, seconds) = evaluator
+                .obliv_swap(&bits[1], &xs[1], &ys[1])
+                .await
+                .unwrap();
+            let after_swap = evaluator.metrics();
+            let opened = evaluator.batch_output_wire(&selected).await.unwrap();
+            let swapped = evaluator
+                .batch_output_wire(&[firsts, seconds])
+                .await
+                .unwrap();
+            (before, after_select, after_swap, opened, swapped)
+        });
+
+        for (before, after_select, after_swap, opened, swapped) in outputs {
+            assert_eq!(
+                after_select.beaver_triples_consumed - before.beaver_triples_consumed,
+                5
+            );
+            assert_eq!(after_select.rounds - before.rounds, 1);
+            assert_eq!(
+                after_swap.beaver_triples_consumed - after_select.beaver_triples_consumed,
+                1
+            );
+            assert_eq!(opened, [20u64, 11, 12, 23, 14].map(F::from));
+            assert_eq!(swapped, [21u64, 11].map(F::from));
+        }
+    }
+
+    #[test]
+    fn test_sim_matches_clear_evaluator() {
+        let inputs: Vec<F> = (0..16u64).map(|i| F::from(3 * i + 1)).collect();
+
+        let mut clear = ClearEvaluator::new(ProtocolParams::default());
+        let expected = async_std::task::block_on(reference_circuit(&mut clear, &inputs));
+
+        let outputs = run_parties(3, |mut evaluator| {
+            let inputs = inputs.clone();
+            async move { reference_circuit(&mut evaluator, &inputs).await }
+        });
+        for output in outputs {
+            assert_eq!(output, expected);
+        }
+    }
+
+    /// shuffles, proves and encrypts a deck, then checks the proofs and that every
+    /// card of the deck decrypts exactly once; returns the params and the
+    /// permutation proofs
+    fn shuffle_end_to_end(
+        params: ProtocolParams,
+    ) -> (UniversalParams<Curve>, Vec<PermutationProof>) {
+        let (perm_size, deck_size) = (params.perm_size(), params.deck_size());
+        let pp = compute_params();
+        let (msk, mpk) = compute_keyper_keys();
+        let ctx = GameContext::new([5u8; 32]);
+        let ids = (0..perm_size)
+            .map(|i| card_id(&ctx, i as u64, 0))
+            .collect::<Vec<Vec<u8>>>();
+
+        let outputs = run_parties_with_params(4, sim_seed(), params, |mut evaluator| {
+            let pp = pp.clone();
+            let ids = ids.clone();
+            async move {
+                let card_share_handles = shuffle_deck(&mut evaluator).await.unwrap();
+                let (perm_proof, alpha1) =
+                    compute_permutation_argument(&pp, &mut evaluator, &card_share_handles)
+                        .await
+                        .unwrap();
+                let (ctxt, encryption_proof) = encrypt_and_prove(
+                    &pp,
+                    &mut evaluator,
+                    &mut IdCache::new(ctx, ids.len()),
+                    card_share_handles,
+                    perm_proof.f_com,
+                    alpha1,
+                    mpk,
+                    ids,
+                )
+                .await
+                .unwrap();
+                (perm_proof, ctxt, encryption_proof)
+            }
+        });
+
+        let cache = compute_decryption_cache(&params);
+        for (perm_proof, ctxt, encryption_proof) in outputs.iter() {
+            assert!(verify_permutation_argument(&pp, &params, perm_proof));
+            assert!(verify_encryption_argument(
+                &pp,
+                &params,
+                &ctx,
+                ctxt,
+                encryption_proof
+            ));
+
+            let mut cards = (perm_size - deck_size..perm_size)
+                .map(|i| {
+                    let dec_key = compute_decryption_key(&ctx, &ids[i], msk);
+                    decrypt_one_card(i, &dec_key, ctxt, &cache).unwrap()
+                })
+                .collect::<Vec<usize>>();
+            cards.sort_unstable();
+            assert_eq!(cards, (0..deck_size).collect::<Vec<usize>>());
+
+            // the key for the same card index of another game reveals nothing
+            let other = GameContext::new([6u8; 32]);
+            let i = perm_size - 1;
+            let other_key = compute_decryption_key(&other, &card_id(&other, i as u64, 0), msk);
+            assert_eq!(decrypt_one_card(i, &other_key, ctxt, &cache), None);
+        }
+        let perm_proofs = outputs.into_iter().map(|(perm_proof, _, _)| perm_proof);
+        (pp, perm_proofs.collect())
+    }
+
+    #[test]
+    fn test_sim_shuffle_end_to_end() {
+        let (pp, perm_proofs) = shuffle_end_to_end(ProtocolParams::default());
+
+        // as a browser checks them, from bytes
+        let mut pp_bytes = Vec::new();
+        pp.serialize_compressed(&mut pp_bytes).unwrap();
+        for perm_proof in perm_proofs.iter() {
+            let commitment = encode_as_bytes(&perm_proof.f_com);
+            assert!(wasm::verify_shuffle(
+                &perm_proof.to_bytes(),
+                &commitment,
+                &pp_bytes
+            ));
+        }
+    }
+
+    #[test]
+    fn test_sim_shuffle_small_deck() {
+        shuffle_end_to_end(ProtocolParams::new(16, 10).unwrap());
+    }
+
+    #[test]
+    fn test_sim_shuffle_double_deck() {
+        shuffle_end_to_end(ProtocolParams::new(128, 104).unwrap());
+    }
+
+    /// shuffles the deck of spec and checks that the opened cards are worth the
+    /// values of spec, repeats included, with the padding left out
+    fn shuffle_deck_spec(spec: DeckSpec) {
+        let params = spec.params();
+        let pp = compute_params();
+
+        let outputs = run_parties_with_params(3, sim_seed(), params, |mut evaluator| {
+            let pp = pp.clone();
+            let spec = spec.clone();
+            async move {
+                let cards = shuffle_deck_with_spec(&mut evaluator, &spec).await.unwrap();
+                let (perm_proof, _) = compute_permutation_argument(&pp, &mut evaluator, &cards)
+                    .await
+                    .unwrap();
+                let opened = evaluator.batch_output_wire(&cards).await.unwrap();
+                (perm_proof, opened)
+            }
+        });
+
+        let mut expected = spec.card_values().to_vec();
+        expected.sort();
+        for (perm_proof, opened) in outputs.iter() {
+            assert!(verify_permutation_argument(&pp, &params, perm_proof));
+
+            let mut values = opened
+                .iter()
+                .map(|card| spec.value_of(card).unwrap())
+                .filter(|value| *value != DeckSpec::SENTINEL)
+                .collect::<Vec<F>>();
+            values.sort();
+            assert_eq!(values, expected);
+        }
+    }
+
+    #[test]
+    fn test_sim_shuffle_36_card_deck() {
+        shuffle_deck_spec(DeckSpec::new((1..=36).map(F::from).collect()).unwrap());
+    }
+
+    #[test]
+    fn test_sim_shuffle_double_deck_spec() {
+        shuffle_deck_spec(DeckSpec::standard().repeated(2).unwrap());
+    }
+
+    #[test]
+    fn test_shuffle_refuses_a_spec_of_another_deck() {
+        let spec = DeckSpec::new((1..=36).map(F::from).collect()).unwrap();
+        let mut evaluator = ClearEvaluator::new(ProtocolParams::default());
+        let result = async_std::task::block_on(shuffle_deck_with_spec(&mut evaluator, &spec));
+        assert!(matches!(
+            result,
+            Err(EvaluatorError::WrongDeck { expected, found })
+                if expected == spec.params() && found == ProtocolParams::default()
+        ));
+    }
+
+    /// shuffles, proves and encrypts a small deck on evaluators over E, whichever
+    /// curve Curve is, and checks the proofs and their encoding under E
+    #[cfg(all(feature = "bls12_381", feature = "bls12_377"))]
+    fn shuffle_on<E: crate::common::CurveConfig>() {
+        use crate::common::{CurveConfig, KzgOn};
+        use ark_ec::Group;
+        use ark_std::UniformRand;
+        use rand::rngs::StdRng;
+
+        let params = ProtocolParams::new(16, 10).unwrap();
+        let mut rng = StdRng::from_seed([7u8; 32]);
+        let pp = KzgOn::<E>::setup(128, &mut rng);
+        let mpk = E::G2::generator() * E::ScalarField::rand(&mut rng);
+        let ctx = GameContext::new([5u8; 32]);
+        let ids = (0..params.perm_size())
+            .map(|i| card_id(&ctx, i as u64, 0))
+            .collect::<Vec<Vec<u8>>>();
+
+        let outputs = async_std::task::block_on(async {
+            let transports = InMemoryTransport::new_network(3).await;
+            let evaluators = try_join_all(transports.into_iter().map(|transport| {
+                EvaluatorBuilder::new()
+                    .params(params)
+                    .build_on::<E, _>(transport)
+            }))
+            .await
+            .unwrap();
+
+            join_all(evaluators.into_iter().map(|mut evaluator| {
+                let pp = pp.clone();
+                let ids = ids.clone();
+                async move {
+                    let cards = shuffle_deck(&mut evaluator).await.unwrap();
+                    let (perm_proof, alpha1) =
+                        compute_permutation_argument(&pp, &mut evaluator, &cards)
+                            .await
+                            .unwrap();
+                    let opened = evaluator.batch_output_wire(&cards).await.unwrap();
+                    let (ctxt, encryption_proof) = encrypt_and_prove(
+                        &pp,
+                        &mut evaluator,
+                        &mut IdCache::new(ctx, ids.len()),
+                        cards,
+                        perm_proof.f_com,
+                        alpha1,
+                        mpk,
+                        ids,
+                    )
+                    .await
+                    .unwrap();
+                    (perm_proof, opened, ctxt, encryption_proof)
+                }
+            }))
+            .await
+        });
+
+        let mut roots = utils::domain_info_of::<E::ScalarField>(params.perm_size())
+            .powers
+            .clone();
+        roots.sort();
+        for (perm_proof, opened, ctxt, encryption_proof) in outputs {
+            assert!(verify_permutation_argument(&pp, &params, &perm_proof));
+            assert!(verify_encryption_argument(
+                &pp,
+                &params,
+                &ctx,
+                &ctxt,
+                &encryption_proof
+            ));
+            let bytes = perm_proof.to_bytes();
+            let decoded = PermutationProof::<E>::from_bytes(&bytes).unwrap();
+            assert!(verify_permutation_argument(&pp, &params, &decoded));
+            // the tag of the curve keeps a proof from being read on another one
+            if E::CURVE_ID != <Curve as CurveConfig>::CURVE_ID {
+                assert!(PermutationProof::<Curve>::from_bytes(&bytes).is_err());
+            }
+
+            let mut opened = opened;
+            opened.sort();
+            assert_eq!(opened, roots);
+        }
+    }
+
+    #[cfg(all(feature = "bls12_381", feature = "bls12_377"))]
+    #[test]
+    fn test_sim_shuffle_on_bls12_381() {
+        shuffle_on::<ark_bls12_381::Bls12_381>();
+    }
+
+    #[cfg(all(feature = "bls12_381", feature = "bls12_377"))]
+    #[test]
+    fn test_sim_shuffle_on_bls12_377() {
+        shuffle_on::<ark_bls12_377::Bls12_377>();
+    }
+
+    #[test]
+    fn test_sim_proofs_with_poseidon_challenges() {
+        let params = ProtocolParams::default();
+        let pp = compute_params();
+        let (_, mpk) = compute_keyper_keys();
+        let ctx = GameContext::new([5u8; 32]);
+        let ids = (0..PERM_SIZE)
+            .map(|i| card_id(&ctx, i as u64, 0))
+            .collect::<Vec<Vec<u8>>>();
+
+        let outputs = run_parties(4, |mut evaluator| {
+            let pp = pp.clone();
+            let ids = ids.clone();
+            async move {
+                evaluator.set_fs_hasher(FsHasher::Poseidon);
+                let card_share_handles = shuffle_deck(&mut evaluator).await.unwrap();
+                let (perm_proof, alpha1) =
+                    compute_permutation_argument(&pp, &mut evaluator, &card_share_handles)
+                        .await
+                        .unwrap();
+                let (ctxt, encryption_proof) = encrypt_and_prove(
+                    &pp,
+                    &mut evaluator,
+                    &mut IdCache::new(ctx, ids.len()),
+                    card_share_handles,
+                    perm_proof.f_com,
+                    alpha1,
+                    mpk,
+                    ids,
+                )
+                .await
+                .unwrap();
+                (perm_proof, ctxt, encryption_proof)
+            }
+        });
+
+        for (perm_proof, ctxt, encryption_proof) in outputs.iter() {
+            assert_eq!(perm_proof.hasher, FsHasher::Poseidon);
+            assert!(verify_permutation_argument(&pp, &params, perm_proof));
+            assert!(verify_encryption_argument(
+                &pp,
+                &params,
+                &ctx,
+                ctxt,
+                encryption_proof
+            ));
+
+            // the challenges depend on the recorded hasher
+            let mut relabelled = perm_proof.clone();
+            relabelled.hasher = FsHasher::Sha256;
+            assert!(!verify_permutation_argument(&pp, &params, &relabelled));
+        }
+    }
+
+    #[test]
+    fn test_sim_batched_permutation_argument() {
+        let params = ProtocolParams::default();
+        let pp = compute_params();
+
+        let outputs = run_parties(4, |mut evaluator| {
+            let pp = pp.clone();
+            async move {
+                let card_share_handles = shuffle_deck(&mut evaluator).await.unwrap();
+                let (perm_proof, _) =
+                    compute_permutation_argument_v2(&pp, &mut evaluator, &card_share_handles)
+                        .await
+                        .unwrap();
+                perm_proof
+            }
+        });
+
+        for perm_proof in outputs.iter() {
+            assert!(verify_permutation_argument_v2(&pp, &params, perm_proof));
+
+            for i in 0..5 {
+                let mut altered = perm_proof.clone();
+                let y = match i {
+                    0 => &mut altered.y1,
+                    1 => &mut altered.y2,
+                    2 => &mut altered.y3,
+                    3 => &mut altered.y4,
+                    _ => &mut altered.y5,
+                };
+                *y += F::from(1);
+                assert!(!verify_permutation_argument_v2(&pp, &params, &altered));
+            }
+        }
+    }
+
+    #[test]
+    fn test_sim_shuffle_stays_within_resource_estimate() {
+        let params = ProtocolParams::new(16, 10).unwrap();
+        let pp = compute_params();
+        let (_, mpk) = compute_keyper_keys();
+        let ctx = GameContext::new([5u8; 32]);
+        let ids = (0..params.perm_size())
+            .map(|i| card_id(&ctx, i as u64, 0))
+            .collect::<Vec<Vec<u8>>>();
+
+        let outputs = run_parties_with_params(4, sim_seed(), params, |mut evaluator| {
+            let pp = pp.clone();
+            let ids = ids.clone();
+            async move {
+                let preprocessed = evaluator.remaining();
+                let mut consumed = vec![evaluator.consumed()];
+                let card_share_handles = shuffle_deck(&mut evaluator).await.unwrap();
+                consumed.push(evaluator.consumed());
+                let (perm_proof, alpha1) =
+                    compute_permutation_argument(&pp, &mut evaluator, &card_share_handles)
+                        .await
+                        .unwrap();
+                consumed.push(evaluator.consumed());
+                let _ = encrypt_and_prove(
+                    &pp,
+                    &mut evaluator,
+                    &mut IdCache::new(ctx, ids.len()),
+                    card_share_handles,
+                    perm_proof.f_com,
+                    alpha1,
+                    mpk,
+                    ids,
+                )
+                .await
+                .unwrap();
+                consumed.push(evaluator.consumed());
+                (preprocessed, consumed)
+            }
+        });
+
+        let estimates = [
+            ResourceEstimate::default(),
+            shuffle_resources(&params),
+            shuffle_resources(&params) + permutation_resources(&params),
+            resources(&params),
+        ];
+        assert_eq!(estimates[3], estimates[2] + encryption_resources());
+        for (preprocessed, consumed) in outputs.iter() {
+            assert_eq!(*preprocessed, resources(&params));
+            for (estimate, consumed) in estimates.iter().zip(consumed.iter()) {
+                assert!(estimate.covers(consumed), "{} over {}", consumed, estimate);
+            }
+        }
+    }
+
+    #[test]
+    fn test_sim_deal_card_to_one_player() {
+        let params = ProtocolParams::new(16, 10).unwrap();
+        let pp = compute_params();
+        let (msk, mpk) = compute_keyper_keys();
+        let ctx = GameContext::new([6u8; 32]);
+        let position = 12;
+
+        let outputs = async_std::task::block_on(async {
+            let transports = InMemoryTransport::new_network(3).await;
+            let evaluators = try_join_all(transports.into_iter().map(|transport| {
+                Evaluator::new_for(transport, params, resources(&params) + deal_resources(), 0)
+            }))
+            .await
+            .unwrap();
+
+            join_all(evaluators.into_iter().map(|mut evaluator| {
+                let pp = pp.clone();
+                async move {
+                    let mut cache = IdCache::new(ctx, params.deck_size());
+                    let cards = shuffle_deck(&mut evaluator).await.unwrap();
+                    let (perm_proof, alpha1) =
+                        compute_permutation_argument(&pp, &mut evaluator, &cards)
+                            .await
+                            .unwrap();
+                    let mut deck =
+                        ShuffledDeck::new(params, cards, perm_proof.f_com, alpha1).unwrap();
+                    let dealt = deal_card(&pp, &mut evaluator, &mut cache, &mut deck, position, b"alice", mpk)
+                        .await
+                        .unwrap();
+                    assert!(matches!(
+                        deal_card(&pp, &mut evaluator, &mut cache, &mut deck, position, b"alice", mpk).await,
+                        Err(DealError::AlreadyDealt(p)) if p == position
+                    ));
+
+                    let card = evaluator.output_wire(&deck.cards[position]).await.unwrap();
+                    (dealt, card)
+                }
+            }))
+            .await
+        });
+
+        let cache = compute_decryption_cache(&params);
+        let powers = &utils::domain_info(params.perm_size()).powers;
+        let id = player_card_id(&ctx, b"alice", position as u64);
+        for (dealt, card) in outputs.iter() {
+            assert!(verify_dealt_card(
+                &pp,
+                &params,
+                &ctx,
+                &dealt.card_commitment,
+                dealt
+            ));
+
+            // only the key of alice's identity opens it, to the card at the position
+            let key = compute_decryption_key(&ctx, &id, msk);
+            let index = decrypt_one_card(0, &key, &dealt.ciphertext(), &cache).unwrap();
+            assert_eq!(powers[index], *card);
+
+            let mut moved = dealt.clone();
+            moved.position += 1;
+            assert!(!verify_dealt_card(
+                &pp,
+                &params,
+                &ctx,
+                &dealt.card_commitment,
+                &moved
+            ));
+        }
+    }
+
+    #[test]
+    fn test_sim_dealt_cards_reach_only_their_player() {
+        let params = ProtocolParams::new(16, 10).unwrap();
+        let pp = compute_params();
+        let (msk, mpk) = compute_keyper_keys();
+        let ctx = GameContext::new([7u8; 32]);
+        let positions = [12usize, 13];
+
+        async_std::task::block_on(async {
+            let (transports, player_rxs, mut verifier_rxs) =
+                InMemoryTransport::new_network_with_roles(3, 2, 1).await;
+            let addr_book = transports[0].addr_book().clone();
+            assert_eq!(addr_book.len(), 3);
+            let players = addr_book
+                .players()
+                .map(|peer| peer.peer_id.clone())
+                .collect::<Vec<Pok3rPeerId>>();
+            assert_eq!(players.len(), 2);
+            let member = addr_book.peer_ids().next().unwrap().clone();
+
+            let resources = resources(&params) + deal_resources() + deal_resources();
+            let mut evaluators = try_join_all(
+                transports
+                    .into_iter()
+                    .map(|transport| Evaluator::new_for(transport, params, resources, 0)),
+            )
+            .await
+            .unwrap();
+            evaluators
+                .iter_mut()
+                .for_each(|evaluator| evaluator.set_publish_proofs(true));
+
+            join_all(evaluators.into_iter().map(|mut evaluator| {
+                let (pp, players, member) = (pp.clone(), players.clone(), member.clone());
+                async move {
+                    let mut cache = IdCache::new(ctx, params.deck_size());
+                    let cards = shuffle_deck(&mut evaluator).await.unwrap();
+                    let (perm_proof, alpha1) =
+                        compute_permutation_argument(&pp, &mut evaluator, &cards)
+                            .await
+                            .unwrap();
+                    let mut deck =
+                        ShuffledDeck::new(params, cards, perm_proof.f_com, alpha1).unwrap();
+                    for (player, position) in players.iter().zip(positions) {
+                        deal_card_to_player(
+                            &pp,
+                            &mut evaluator,
+                            &mut cache,
+                            &mut deck,
+                            position,
+                            player,
+                            mpk,
+                        )
+                        .await
+                        .unwrap();
+                    }
+                    // committee members are not players
+                    assert!(matches!(
+                        evaluator.send_to_player(&member, "x", "y").await,
+                        Err(NetworkError::NotAPlayer(_))
+                    ));
+                }
+            }))
+            .await;
+
+            // each committee member sent each player its own card, and nothing else
+            let cache = compute_decryption_cache(&params);
+            for ((player, position), mut rx) in players.iter().zip(positions).zip(player_rxs) {
+                let mut dealt = vec![];
+                while dealt.len() < 3 {
+                    match rx.next().await.unwrap() {
+                        EvalNetMsg::DirectValue {
+                            recipient,
+                            handle,
+                            value,
+                            ..
+                        } => {
+                            assert_eq!(&recipient, player);
+                            assert_eq!(handle, format!("{}{}", DEALT_HANDLE_PREFIX, position));
+                            dealt.push(DealtCard::from_bs58(&value).unwrap());
+                        }
+                        _ => panic!("a player got more than its direct values"),
+                    }
+                }
+                // the relays close the tap once the committee is gone
+                assert!(rx.next().await.is_none());
+
+                let own = player_card_id(&ctx, player.as_bytes(), position as u64);
+                let key = compute_decryption_key(&ctx, &own, msk);
+                for card in dealt.iter() {
+                    assert_eq!(card.recipient, player.as_bytes());
+                    assert!(verify_dealt_card(
+                        &pp,
+                        &params,
+                        &ctx,
+                        &card.card_commitment,
+                        card
+                    ));
+                    assert!(decrypt_one_card(0, &key, &card.ciphertext(), &cache).is_some());
+                }
+                assert!(dealt
+                    .iter()
+                    .all(|card| card.to_bs58() == dealt[0].to_bs58()));
+            }
+
+            // the verifier got the permutation argument of every committee member
+            let mut verifier_rx = verifier_rxs.pop().unwrap();
+            let mut provers = HashSet::new();
+            while provers.len() < 3 {
+                match verifier_rx.next().await.unwrap() {
+                    EvalNetMsg::DirectValue { sender, handle, .. } => {
+                        assert!(handle.starts_with(PROOF_HANDLE_PREFIX));
+                        provers.insert(sender);
+                    }
+                    _ => continue,
+                }
+            }
+            while let Some(msg) = verifier_rx.next().await {
+                assert!(!matches!(
+                    msg,
+                    EvalNetMsg::DirectValue { handle, .. } if handle.starts_with(DEALT_HANDLE_PREFIX)
+                ));
+            }
+        });
+    }
+
+    #[test]
+    fn test_sim_reveal_public_cards() {
+        let params = ProtocolParams::new(16, 10).unwrap();
+        let pp = compute_params();
+        let (_, mpk) = compute_keyper_keys();
+        let ctx = GameContext::new([7u8; 32]);
+        let positions = [7, 9, 11];
+
+        let outputs = async_std::task::block_on(async {
+            let transports = InMemoryTransport::new_network(3).await;
+            let evaluators = try_join_all(transports.into_iter().map(|transport| {
+                Evaluator::new_for(transport, params, resources(&params) + deal_resources(), 0)
+            }))
+            .await
+            .unwrap();
+
+            join_all(evaluators.into_iter().map(|mut evaluator| {
+                let pp = pp.clone();
+                async move {
+                    let mut cache = IdCache::new(ctx, params.deck_size());
+                    let cards = shuffle_deck(&mut evaluator).await.unwrap();
+                    let (perm_proof, alpha1) =
+                        compute_permutation_argument(&pp, &mut evaluator, &cards)
+                            .await
+                            .unwrap();
+                    let mut deck =
+                        ShuffledDeck::new(params, cards, perm_proof.f_com, alpha1).unwrap();
+                    deal_card(&pp, &mut evaluator, &mut cache, &mut deck, 9, b"alice", mpk)
+                        .await
+                        .unwrap();
+
+                    assert!(matches!(
+                        reveal_public_cards(&pp, &mut evaluator, &mut deck, &[7, 8, 7]).await,
+                        Err(DealError::DuplicatePosition(7))
+                    ));
+                    assert!(matches!(
+                        reveal_public_cards(&pp, &mut evaluator, &mut deck, &[16]).await,
+                        Err(DealError::PositionOutOfRange { position: 16, .. })
+                    ));
+                    let revealed = reveal_public_cards(&pp, &mut evaluator, &mut deck, &positions)
+                        .await
+                        .unwrap();
+                    assert!(matches!(
+                        reveal_public_cards(&pp, &mut evaluator, &mut deck, &[11]).await,
+                        Err(DealError::AlreadyRevealed(11))
+                    ));
+                    (deck.commitment, revealed)
+                }
+            }))
+            .await
+        });
+
+        for (commitment, revealed) in outputs.iter() {
+            assert_eq!(revealed, &outputs[0].1);
+            for (card, position) in revealed.iter().zip(positions) {
+                assert_eq!(card.position, position);
+                assert_eq!(card.was_dealt, position == 9);
+                assert!(verify_public_card(
+                    &pp,
+                    &params,
+                    commitment,
+                    card.position,
+                    &card.value,
+                    &card.proof
+                ));
+
+                // a forged card does not open the commitment
+                assert!(!verify_public_card(
+                    &pp,
+                    &params,
+                    commitment,
+                    card.position,
+                    &(card.value + F::from(1u64)),
+                    &card.proof
+                ));
+            }
+        }
+    }
+
+    #[test]
+    fn test_sim_reshuffle_remaining_cards() {
+        let params = ProtocolParams::new(32, 20).unwrap();
+        let new_params = reshuffle_params(10).unwrap();
+        let pp = compute_params();
+        let (msk, mpk) = compute_keyper_keys();
+        let ctx = GameContext::new([8u8; 32]);
+        let deals = (0..20).fold(ResourceEstimate::default(), |acc, _| acc + deal_resources());
+        let needed = resources(&params) + deals + reshuffle_resources(&params, &new_params);
+
+        let outputs = async_std::task::block_on(async {
+            let transports = InMemoryTransport::new_network(3).await;
+            let evaluators = try_join_all(
+                transports
+                    .into_iter()
+                    .map(|transport| Evaluator::new_for(transport, params, needed, 0)),
+            )
+            .await
+            .unwrap();
+
+            join_all(evaluators.into_iter().map(|mut evaluator| {
+                let pp = pp.clone();
+                async move {
+                    let mut cache = IdCache::new(ctx, params.deck_size());
+                    let cards = shuffle_deck(&mut evaluator).await.unwrap();
+                    let (perm_proof, alpha1) =
+                        compute_permutation_argument(&pp, &mut evaluator, &cards)
+                            .await
+                            .unwrap();
+                    let mut deck =
+                        ShuffledDeck::new(params, cards, perm_proof.f_com, alpha1).unwrap();
+                    let mut dealt = vec![];
+                    for position in 12..22 {
+                        let card = deal_card(
+                            &pp,
+                            &mut evaluator,
+                            &mut cache,
+                            &mut deck,
+                            position,
+                            b"alice",
+                            mpk,
+                        )
+                        .await
+                        .unwrap();
+                        dealt.push(card);
+                    }
+
+                    let (mut new_deck, proof) =
+                        reshuffle_remaining(&pp, &mut evaluator, &deck, &[])
+                            .await
+                            .unwrap();
+                    assert_eq!(*new_deck.params(), new_params);
+                    for position in 6..16 {
+                        let card = deal_card(
+                            &pp,
+                            &mut evaluator,
+                            &mut cache,
+                            &mut new_deck,
+                            position,
+                            b"bob",
+                            mpk,
+                        )
+                        .await
+                        .unwrap();
+                        dealt.push(card);
+                    }
+                    (proof, new_deck.commitment, dealt)
+                }
+            }))
+            .await
+        });
+
+        let cache = compute_decryption_cache(&params);
+        for (proof, new_commitment, dealt) in outputs.iter() {
+            assert!(verify_reshuffle(&pp, proof));
+            assert_eq!(proof.positions, (22..32).collect::<Vec<u64>>());
+
+            let mut indices = vec![];
+            for (k, card) in dealt.iter().enumerate() {
+                let (card_params, commitment) = if k < 10 {
+                    (params, proof.old_commitment)
+                } else {
+                    (new_params, *new_commitment)
+                };
+                assert!(verify_dealt_card(
+                    &pp,
+                    &card_params,
+                    &ctx,
+                    &commitment,
+                    card
+                ));
+
+                let id = player_card_id(&ctx, &card.recipient, card.position);
+                let key = compute_decryption_key(&ctx, &id, msk);
+                indices.push(decrypt_one_card(0, &key, &card.ciphertext(), &cache).unwrap());
+            }
+
+            // every card of the deck was dealt exactly once, across both decks
+            indices.sort_unstable();
+            assert_eq!(indices, (0..20).collect::<Vec<usize>>());
+        }
+    }
+
+    #[test]
+    fn test_sim_runs_out_of_preprocessing_before_the_phase() {
+        let params = ProtocolParams::new(16, 10).unwrap();
+        let pp = compute_params();
+
+        let outputs = async_std::task::block_on(async {
+            let transports = InMemoryTransport::new_network(3).await;
+            let evaluators = try_join_all(transports.into_iter().map(|transport| {
+                Evaluator::new_for(transport, params, shuffle_resources(&params), 4)
+            }))
+            .await
+            .unwrap();
+
+            join_all(evaluators.into_iter().map(|mut evaluator| {
+                let pp = pp.clone();
+                async move {
+                    let card_share_handles = shuffle_deck(&mut evaluator).await.unwrap();
+                    let consumed = evaluator.consumed();
+                    let result =
+                        compute_permutation_argument(&pp, &mut evaluator, &card_share_handles)
+                            .await;
+                    (result.err(), consumed, evaluator.consumed())
+                }
+            }))
+            .await
+        });
+
+        for (err, before, after) in outputs {
+            match err {
+                Some(EvaluatorError::OutOfPreprocessing { needed, .. }) => {
+                    assert_eq!(needed, permutation_resources(&params))
+                }
+                other => panic!("expected OutOfPreprocessing, got {:?}", other),
+            }
+            // nothing was consumed by the phase that could not complete
+            assert_eq!(before, after);
+        }
+    }
+
+    #[test]
+    fn test_sim_wire_count_stays_bounded_over_many_shuffles() {
+        const ROUNDS: usize = 10;
+        let params = ProtocolParams::new(16, 10).unwrap();
+        let resources = (0..ROUNDS).fold(ResourceEstimate::default(), |acc, _| {
+            acc + shuffle_resources(&params)
+        });
+
+        let outputs = async_std::task::block_on(async {
+            let transports = InMemoryTransport::new_network(3).await;
+            let evaluators = try_join_all(
+                transports
+                    .into_iter()
+                    .map(|transport| Evaluator::new_for(transport, params, resources, 0)),
+            )
+            .await
+            .unwrap();
+
+            join_all(evaluators.into_iter().map(|mut evaluator| async move {
+                let mut wire_counts = Vec::new();
+                for _ in 0..ROUNDS {
+                    let card_share_handles = shuffle_deck(&mut evaluator).await.unwrap();
+                    wire_counts.push(evaluator.wire_count());
+                    evaluator.free_wires(&card_share_handles);
+                }
+                wire_counts
+            }))
+            .await
+        });
+
+        for wire_counts in outputs {
+            // only the deck of the current round is alive
+            assert!(wire_counts.iter().all(|c| *c == params.perm_size()));
+        }
+    }
+
+    /// an InMemoryTransport that flips the lowest bit of the share it broadcasts
+    /// under the handle in target, if any
+    struct BitFlipper {
+        inner: InMemoryTransport,
+        target: Arc<Mutex<Option<String>>>,
+        /// whether the share goes out twice, first as it is and then flipped, for
+        /// the links to pick which party gets which
+        equivocate: bool,
+    }
+
+    impl BitFlipper {
+        fn is_target(&self, handle: &str) -> bool {
+            self.target.lock().unwrap().as_deref() == Some(handle)
+        }
+
+        fn targets_any<H: AsRef<str>>(&self, handles: &[H]) -> bool {
+            self.equivocate && handles.iter().any(|handle| self.is_target(handle.as_ref()))
+        }
+
+        fn flip(bytes: &[u8]) -> Vec<u8> {
+            // the first byte is the tag, the next the lowest of the value
+            let mut bytes = bytes.to_vec();
+            bytes[1] ^= 1;
+            bytes
+        }
+    }
+
+    #[async_trait]
+    impl Transport for BitFlipper {
+        fn get_my_id(&self) -> u64 {
+            self.inner.get_my_id()
+        }
+
+        fn addr_book(&self) -> &Pok3rAddrBook {
+            self.inner.addr_book()
+        }
+
+        fn session_id(&self) -> &str {
+            self.inner.session_id()
+        }
+
+        fn metrics(&self) -> Arc<Metrics> {
+            self.inner.metrics()
+        }
+
+        fn sign(&self, bytes: &[u8]) -> Vec<u8> {
+            self.inner.sign(bytes)
+        }
+
+        async fn send_to_all<H, V>(
+            &mut self,
+            handles: &[H],
+            values: &[V],
+        ) -> Result<(), NetworkError>
+        where
+            H: AsRef<str> + Sync,
+            V: AsRef<str> + Sync,
+        {
+            let flipped = handles
+                .iter()
+                .zip(values)
+                .map(|(handle, value)| {
+                    let value = value.as_ref();
+                    if !self.is_target(handle.as_ref()) {
+                        return value.to_string();
+                    }
+                    // a committed opening is the share and its salt
+                    let (share, salt) = match value.split_once('.') {
+                        Some((share, salt)) => (share, Some(salt)),
+                        None => (value, None),
+                    };
+                    let share = bs58::decode(share).into_vec().unwrap();
+                    let share = bs58::encode(Self::flip(&share)).into_string();
+                    match salt {
+                        Some(salt) => format!("{}.{}", share, salt),
+                        None => share,
+                    }
+                })
+                .collect::<Vec<String>>();
+            if self.targets_any(handles) {
+                self.inner.send_to_all(handles, values).await?;
+            }
+            self.inner.send_to_all(handles, &flipped).await
+        }
+
+        async fn send_to_party<H, V>(
+            &mut self,
+            recipient_id: u64,
+            handles: &[H],
+            values: &[V],
+        ) -> Result<(), NetworkError>
+        where
+            H: AsRef<str> + Sync,
+            V: AsRef<str> + Sync,
+        {
+            self.inner
+                .send_to_party(recipient_id, handles, values)
+                .await
+        }
+
+        async fn send_to_role<H, V>(
+            &mut self,
+            role: PeerRole,
+            handles: &[H],
+            values: &[V],
+        ) -> Result<(), NetworkError>
+        where
+            H: AsRef<str> + Sync,
+            V: AsRef<str> + Sync,
+        {
+            self.inner.send_to_role(role, handles, values).await
+        }
+
+        async fn send_to_player<H, V>(
+            &mut self,
+            player: &Pok3rPeerId,
+            handles: &[H],
+            values: &[V],
+        ) -> Result<(), NetworkError>
+        where
+            H: AsRef<str> + Sync,
+            V: AsRef<str> + Sync,
+        {
+            self.inner.send_to_player(player, handles, values).await
+        }
+
+        async fn send_batch_to_party<H: AsRef<str> + Sync>(
+            &mut self,
+            recipient_id: u64,
+            handles: &[H],
+            kind: PayloadKind,
+            values: &[Vec<u8>],
+        ) -> Result<(), NetworkError> {
+            self.inner
+                .send_batch_to_party(recipient_id, handles, kind, values)
+                .await
+        }
+
+        async fn send_batch_to_all<H: AsRef<str> + Sync>(
+            &mut self,
+            handles: &[H],
+            kind: PayloadKind,
+            values: &[Vec<u8>],
+        ) -> Result<(), NetworkError> {
+            let flipped = handles
+                .iter()
+                .zip(values)
+                .map(|(handle, value)| {
+                    if self.is_target(handle.as_ref()) {
+                        Self::flip(value)
+                    } else {
+                        value.clone()
+                    }
+                })
+                .collect::<Vec<Vec<u8>>>();
+            if self.targets_any(handles) {
+                self.inner.send_batch_to_all(handles, kind, values).await?;
+            }
+            self.inner.send_batch_to_all(handles, kind, &flipped).await
+        }
+
+        async fn recv_from_all(
+            &mut self,
+            identifier: &str,
+        ) -> Result<HashMap<u64, String>, NetworkError> {
+            self.inner.recv_from_all(identifier).await
+        }
+
+        async fn recv_many_from_all<I: AsRef<str> + Sync>(
+            &mut self,
+            identifiers: &[I],
+        ) -> Result<HashMap<String, HashMap<u64, String>>, NetworkError> {
+            self.inner.recv_many_from_all(identifiers).await
+        }
+
+        async fn recv_from_all_with_deadline(
+            &mut self,
+            identifier: &str,
+            deadline: Instant,
+        ) -> Result<HashMap<u64, String>, NetworkError> {
+            self.inner
+                .recv_from_all_with_deadline(identifier, deadline)
+                .await
+        }
+
+        async fn recv_from_some(
+            &mut self,
+            identifier: &str,
+            count: usize,
+        ) -> Result<HashMap<u64, String>, NetworkError> {
+            self.inner.recv_from_some(identifier, count).await
+        }
+
+        async fn recv_quorum(
+            &mut self,
+            identifier: &str,
+            quorum: usize,
+        ) -> Result<HashMap<u64, String>, NetworkError> {
+            self.inner.recv_quorum(identifier, quorum).await
+        }
+
+        fn take_late_values(&mut self) -> Vec<(String, u64, String)> {
+            self.inner.take_late_values()
+        }
+
+        async fn wait_for_late_values(&mut self, deadline: Instant) -> Result<(), NetworkError> {
+            self.inner.wait_for_late_values(deadline).await
+        }
+
+        async fn recv_from_party(
+            &mut self,
+            sender_id: u64,
+            handle: &str,
+        ) -> Result<String, NetworkError> {
+            self.inner.recv_from_party(sender_id, handle).await
+        }
+
+        async fn flush(&mut self) -> Result<(), NetworkError> {
+            self.inner.flush().await
+        }
+
+        fn set_round(&mut self, round: u64) {
+            self.inner.set_round(round)
+        }
+
+        async fn handshake(&mut self, info: &PeerInfo) -> Result<(), NetworkError> {
+            self.inner.handshake(info).await
+        }
+
+        async fn broadcast_abort(&mut self, reason: &str) {
+            self.inner.broadcast_abort(reason).await
+        }
+
+        fn update_addr_book(&mut self, addr_book: Pok3rAddrBook) -> Result<(), NetworkError> {
+            self.inner.update_addr_book(addr_book)
+        }
+    }
+
+    /// shuffles, proves the permutation and reveals position 12; if flip_card, the
+    /// target is set to the wire of that card once the deck is shuffled
+    async fn shuffle_and_reveal(
+        pp: &UniversalParams<Curve>,
+        evaluator: &mut Evaluator<BitFlipper>,
+        target: Option<Arc<Mutex<Option<String>>>>,
+        flip_card: bool,
+    ) -> Result<Vec<PublicCard>, DealError> {
+        let params = *evaluator.params();
+        let cards = shuffle_deck(evaluator).await?;
+        if let (Some(target), true) = (target, flip_card) {
+            *target.lock().unwrap() = Some(cards[12].to_string());
+        }
+        let (perm_proof, alpha1) = compute_permutation_argument(pp, evaluator, &cards).await?;
+        let mut deck = ShuffledDeck::new(params, cards, perm_proof.f_com, alpha1)?;
+        reveal_public_cards(pp, evaluator, &mut deck, &[12]).await
+    }
+
+    /// runs shuffle_and_reveal with party 2 flipping a bit of its share under
+    /// first_target, and returns the evidence the honest parties hold against it
+    fn blame_bit_flip(
+        mode: OpenMode,
+        first_target: Option<&str>,
+        flip_card: bool,
+    ) -> Vec<Evidence> {
+        let params = ProtocolParams::new(16, 10).unwrap();
+        let pp = compute_params();
+
+        let outputs = async_std::task::block_on(async {
+            let transports = InMemoryTransport::new_network(3).await;
+            let targets = transports
+                .iter()
+                .map(|_| Arc::new(Mutex::new(None)))
+                .collect::<Vec<_>>();
+            *targets[1].lock().unwrap() = first_target.map(String::from);
+            let evaluators = try_join_all(transports.into_iter().zip(targets.iter()).map(
+                |(inner, target)| {
+                    let transport = BitFlipper {
+                        inner,
+                        target: target.clone(),
+                        equivocate: false,
+                    };
+                    Evaluator::new_for(transport, params, resources(&params), 0)
+                },
+            ))
+            .await
+            .unwrap();
+
+            join_all(
+                evaluators
+                    .into_iter()
+                    .zip(targets)
+                    .map(|(mut evaluator, target)| {
+                        let pp = pp.clone();
+                        async move {
+                            evaluator.set_open_mode(mode);
+                            evaluator.enable_accountability();
+                            let cheater = evaluator.node_id() == 2;
+                            let result = shuffle_and_reveal(
+                                &pp,
+                                &mut evaluator,
+                                Some(target).filter(|_| cheater),
+                                flip_card,
+                            )
+                            .await;
+                            if result.is_err() {
+                                evaluator.abort("misbehavior").await;
+                            }
+                            (cheater, result)
+                        }
+                    }),
+            )
+            .await
+        });
+
+        outputs
+            .into_iter()
+            .filter(|(cheater, _)| !cheater)
+            .map(|(_, result)| match result {
+                Err(DealError::Evaluator(EvaluatorError::Misbehavior { node_id, evidence })) => {
+                    assert_eq!(node_id, 2);
+                    assert!(evidence.verify(&pp));
+                    *evidence
+                }
+                other => panic!("expected Misbehavior, got {:?}", other.map(|_| ())),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sim_blames_the_party_flipping_a_bit() {
+        // a committed opening that does not match the commitment
+        for evidence in blame_bit_flip(OpenMode::Committed, Some(PERM_COMMITMENT_HANDLE), false) {
+            assert!(matches!(evidence, Evidence::CommitmentMismatch { .. }));
+            assert_eq!(evidence.handle(), PERM_COMMITMENT_HANDLE);
+        }
+        for evidence in blame_bit_flip(OpenMode::Committed, None, true) {
+            assert!(matches!(evidence, Evidence::CommitmentMismatch { .. }));
+        }
+
+        // a card value that opens fine, but no longer matches its proof
+        for evidence in blame_bit_flip(OpenMode::Plain, None, true) {
+            assert!(matches!(evidence, Evidence::OpeningShare { .. }));
+        }
+    }
+
+    /// opens a random wire, alone or as the second of four, with party 3 getting a
+    /// flipped share of it to party 1 and its real share to party 2; returns what
+    /// every party makes of the opening, by node id
+    fn open_with_equivocation(
+        consistent: bool,
+        batch: bool,
+    ) -> Vec<(u64, Result<F, EvaluatorError>)> {
+        let targets = (0..3)
+            .map(|_| Arc::new(Mutex::new(None)))
+            .collect::<Vec<Arc<Mutex<Option<String>>>>>();
+        let link: LinkFilter = {
+            let target = targets[2].clone();
+            let first_seq = Mutex::new(None);
+            Arc::new(move |sender, recipient, msg: &EvalNetMsg| {
+                let (seq, handles) = match msg {
+                    EvalNetMsg::PublishValue { seq, handle, .. } => (seq, vec![handle]),
+                    EvalNetMsg::PublishBatchValue { seq, handles, .. }
+                    | EvalNetMsg::PublishBatchBinary { seq, handles, .. } => {
+                        (seq, handles.iter().collect())
+                    }
+                    _ => return true,
+                };
+                let target = target.lock().unwrap();
+                if sender != 3
+                    || !handles
+                        .iter()
+                        .any(|h| Some(h.as_str()) == target.as_deref())
+                {
+                    return true;
+                }
+                // the first of the two messages holds the real share
+                let first = *first_seq.lock().unwrap().get_or_insert(*seq);
+                (recipient == 1) == (*seq != first)
+            })
+        };
+
+        async_std::task::block_on(async {
+            let transports = InMemoryTransport::new_network_with_link_filter(3, link).await;
+            let evaluators = try_join_all(transports.into_iter().zip(targets.iter()).map(
+                |(inner, target)| {
+                    let transport = BitFlipper {
+                        inner,
+                        target: target.clone(),
+                        equivocate: true,
+                    };
+                    EvaluatorBuilder::new()
+                        .preprocessing(ResourceEstimate {
+                            triples: 0,
+                            rand_sharings: 4,
+                        })
+                        .build(transport)
+                },
+            ))
+            .await
+            .unwrap();
+
+            join_all(evaluators.into_iter().zip(targets.iter()).map(
+                |(mut evaluator, target)| async move {
+                    evaluator.set_consistent_openings(consistent);
+                    let wires = (0..4).map(|_| evaluator.ran()).collect::<Vec<Wire>>();
+                    if evaluator.node_id() == 3 {
+                        *target.lock().unwrap() = Some(wires[1].to_string());
+                    }
+                    let result = if batch {
+                        evaluator.batch_output_wire(&wires).await.map(|v| v[1])
+                    } else {
+                        evaluator.output_wire(&wires[1]).await
+                    };
+                    (evaluator.node_id(), result)
+                },
+            ))
+            .await
+        })
+    }
+
+    #[test]
+    fn test_sim_consistent_openings_detect_equivocation() {
+        for batch in [false, true] {
+            // without the echo round, parties 1 and 2 silently open different values
+            let outputs = open_with_equivocation(false, batch);
+            assert_ne!(
+                outputs[0].1.as_ref().unwrap(),
+                outputs[1].1.as_ref().unwrap()
+            );
+
+            let outputs = open_with_equivocation(true, batch);
+            for (node_id, result) in outputs.iter().filter(|(node_id, _)| *node_id != 3) {
+                match result {
+                    Err(EvaluatorError::Network(NetworkError::Equivocation {
+                        sender,
+                        conflicting_digests,
+                    })) => {
+                        assert_eq!(*sender, 3);
+                        let ids = conflicting_digests
+                            .iter()
+                            .map(|(id, _)| *id)
+                            .collect::<Vec<u64>>();
+                        assert_eq!(ids, vec![1, 2]);
+                        assert_ne!(conflicting_digests[0].1, conflicting_digests[1].1);
+                    }
+                    other => panic!(
+                        "party {} expected an equivocation, got {:?}",
+                        node_id, other
+                    ),
+                }
+            }
+        }
+    }
+
+    /// opens the constants 7 and 9 with threshold 1 among four parties, the fourth
+    /// of which shows up late with its share of 7 replaced by that of late_value;
+    /// returns how long each opening took the other parties, what they opened, and
+    /// their flags once the late shares are in
+    fn open_with_a_slow_party(
+        policy: QuorumPolicy,
+        late_value: u64,
+    ) -> Vec<(Duration, Vec<F>, Vec<Evidence>)> {
+        const SLOW: Duration = Duration::from_millis(500);
+
+        async_std::task::block_on(async {
+            let transports = InMemoryTransport::new_network(4).await;
+            let evaluators = try_join_all(
+                transports
+                    .into_iter()
+                    .map(|transport| Evaluator::new_with_threshold(transport, 1)),
+            )
+            .await
+            .unwrap();
+
+            let outputs = join_all(evaluators.into_iter().map(|mut evaluator| async move {
+                evaluator.set_quorum_policy(policy);
+                let slow = evaluator.node_id() == 4;
+                if slow {
+                    async_std::task::sleep(SLOW).await;
+                }
+                let x = evaluator.fixed_wire_handle(F::from(if slow { late_value } else { 7 }));
+                let y = evaluator.fixed_wire_handle(F::from(9u64));
+
+                let start = Instant::now();
+                let mut opened = vec![evaluator.output_wire(&x).await.unwrap()];
+                opened.extend(evaluator.batch_output_wire(&[y]).await.unwrap());
+                let elapsed = start.elapsed();
+
+                let flags = evaluator
+                    .check_late_shares(Duration::from_secs(5))
+                    .await
+                    .unwrap()
+                    .to_vec();
+                (slow, elapsed, opened, flags)
+            }))
+            .await;
+
+            outputs
+                .into_iter()
+                .filter(|(slow, ..)| !slow)
+                .map(|(_, elapsed, opened, flags)| {
+                    assert!(policy == QuorumPolicy::All || elapsed < SLOW);
+                    (elapsed, opened, flags)
+                })
+                .collect()
+        })
+    }
+
+    #[test]
+    fn test_sim_threshold_opens_without_the_slow_party() {
+        for (_, opened, flags) in open_with_a_slow_party(QuorumPolicy::Threshold, 7) {
+            assert_eq!(opened, vec![F::from(7u64), F::from(9u64)]);
+            assert!(flags.is_empty());
+        }
+
+        // waiting for everyone takes as long as the slowest party
+        for (elapsed, opened, flags) in open_with_a_slow_party(QuorumPolicy::All, 7) {
+            assert!(elapsed >= Duration::from_millis(400));
+            assert_eq!(opened, vec![F::from(7u64), F::from(9u64)]);
+            assert!(flags.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_sim_inconsistent_late_share_is_flagged() {
+        let pp = compute_params();
+        for policy in [QuorumPolicy::Threshold, QuorumPolicy::All] {
+            for (_, opened, flags) in open_with_a_slow_party(policy, 8) {
+                // the opening goes on with the first two shares
+                assert_eq!(opened, vec![F::from(7u64), F::from(9u64)]);
+                assert_eq!(flags.len(), 1);
+                match &flags[0] {
+                    Evidence::InconsistentShare { node_id, .. } => assert_eq!(*node_id, 4),
+                    other => panic!("expected InconsistentShare, got {:?}", other),
+                }
+                assert!(flags[0].verify(&pp));
+            }
+        }
+    }
+
+    /// every party's transcript of a small circuit among 4 parties, each drawing its
+    /// randomness from ChaCha seeded from seed
+    fn seeded_transcripts(seed: u64) -> Vec<String> {
+        async_std::task::block_on(async {
+            let transports = InMemoryTransport::new_network(4).await;
+            let evaluators =
+                try_join_all(transports.into_iter().enumerate().map(|(i, transport)| {
+                    Evaluator::new_with_rng(transport, ChaCha20Rng::from_seed(party_seed(seed, i)))
+                }))
+                .await
+                .unwrap();
+
+            join_all(evaluators.into_iter().map(|mut evaluator| async move {
+                evaluator.enable_audit();
+                let x = evaluator.fixed_wire_handle(F::from(3u64));
+                let r = evaluator.ran();
+                let xr = evaluator.mult(&x, &r).await.unwrap();
+                evaluator.batch_output_wire(&[r, xr]).await.unwrap();
+                let zeros = evaluator.batch_zero_share(2).await.unwrap();
+                evaluator.batch_output_wire(&zeros).await.unwrap();
+                serde_json::to_string(evaluator.audit_log().unwrap().records()).unwrap()
+            }))
+            .await
+        })
+    }
+
+    #[test]
+    fn test_sim_seeded_runs_are_reproducible() {
+        let transcripts = seeded_transcripts(7);
+        assert_eq!(seeded_transcripts(7), transcripts);
+        assert_ne!(seeded_transcripts(8), transcripts);
+    }
+
+    #[test]
+    fn test_sim_dead_peer_fails_opens_fast() {
+        const INTERVAL: Duration = Duration::from_millis(200);
+        let results =
+            async_std::task::block_on(async {
+                let mut transports = InMemoryTransport::new_network(3).await;
+                for transport in transports.iter_mut() {
+                    transport.set_heartbeat_config(HeartbeatConfig {
+                        interval: INTERVAL,
+                        miss_threshold: 2,
+                    });
+                }
+                let mut evaluators =
+                    try_join_all(transports.into_iter().enumerate().map(|(i, transport)| {
+                        Evaluator::new_with_seed(transport, party_seed(1, i))
+                    }))
+                    .await
+                    .unwrap();
+
+                // party 3 crashes
+                drop(evaluators.pop());
+                let crashed = Instant::now();
+
+                join_all(evaluators.into_iter().map(|mut evaluator| async move {
+                    let mut down = evaluator.on_peer_down();
+                    let r = evaluator.ran();
+                    let first = evaluator.output_wire(&r).await;
+                    let detected = crashed.elapsed();
+                    let event = down.next().await;
+
+                    let s = evaluator.ran();
+                    let started = Instant::now();
+                    let second = evaluator.output_wire(&s).await;
+                    let failed_after = started.elapsed();
+                    let status = evaluator.peer_status();
+                    (first, detected, event, second, failed_after, status)
+                }))
+                .await
+            });
+
+        for (i, (first, detected, event, second, failed_after, status)) in
+            results.into_iter().enumerate()
+        {
+            assert!(matches!(
+                first,
+                Err(EvaluatorError::Network(NetworkError::PeerDown(3)))
+            ));
+            assert!(detected < 3 * INTERVAL, "found dead after {:?}", detected);
+            assert_eq!(event, Some(3));
+
+            // later opens do not wait for it
+            assert!(matches!(
+                second,
+                Err(EvaluatorError::Network(NetworkError::PeerDown(3)))
+            ));
+            assert!(failed_after < INTERVAL);
+
+            assert_eq!(status[&3], PeerStatus::Dead);
+            let other = if i == 0 { 2 } else { 1 };
+            assert!(matches!(status[&other], PeerStatus::Alive { rtt: Some(_) }));
+        }
+    }
+
+    #[test]
+    fn test_sim_restarted_party_resumes_the_opening() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("journal");
+        // while cut, nothing goes between node ids 2 and 3
+        let cut = Arc::new(AtomicBool::new(false));
+        let link: LinkFilter = {
+            let cut = cut.clone();
+            Arc::new(move |sender, recipient, _: &EvalNetMsg| {
+                !(cut.load(Ordering::Relaxed) && matches!((sender, recipient), (2, 3) | (3, 2)))
+            })
+        };
+        let retransmit = RetransmitConfig {
+            initial_backoff: Duration::from_millis(20),
+            max_backoff: Duration::from_millis(160),
+            max_attempts: 100,
+        };
+
+        let outputs = async_std::task::block_on(async {
+            let mut transports = InMemoryTransport::new_network_with_link_filter(3, link).await;
+            for transport in transports.iter_mut() {
+                transport.set_retransmit_config(retransmit);
+            }
+            transports[1].open_journal(&path).unwrap();
+            transports[1].set_recv_timeout(Duration::from_secs(2));
+            let evaluators = try_join_all(transports.into_iter().map(Evaluator::new))
+                .await
+                .unwrap();
+            cut.store(true, Ordering::Relaxed);
+
+            join_all(evaluators.into_iter().map(|mut evaluator| {
+                let (cut, path) = (cut.clone(), path.clone());
+                async move {
+                    let wires = (0..4).map(|_| evaluator.ran()).collect::<Vec<Wire>>();
+                    if evaluator.node_id() != 2 {
+                        let opened = evaluator.batch_output_wire(&wires).await.unwrap();
+                        evaluator.flush().await.unwrap();
+                        return opened;
+                    }
+
+                    // node id 2 sends its shares and gets those of node id 1, but
+                    // not those of node id 3, before it goes down
+                    let snapshot = evaluator.checkpoint();
+                    assert!(matches!(
+                        evaluator.batch_output_wire(&wires).await,
+                        Err(EvaluatorError::Network(NetworkError::Timeout { .. }))
+                    ));
+                    let mut transport = evaluator.into_messaging().restart().await;
+                    transport.set_retransmit_config(retransmit);
+                    transport.open_journal(&path).unwrap();
+                    let mut evaluator = Evaluator::restore(transport, snapshot).unwrap();
+
+                    // node id 1 is done with the opening and sends nothing again,
+                    // so its shares must come from the journal
+                    cut.store(false, Ordering::Relaxed);
+                    evaluator.batch_output_wire(&wires).await.unwrap()
+                }
+            }))
+            .await
+        });
+
+        assert_eq!(outputs[0], outputs[1]);
+        assert_eq!(outputs[1], outputs[2]);
+    }
+}
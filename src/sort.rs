@@ -0,0 +1,529 @@
+//! Oblivious shuffles and sorts of shared vectors. shuffle_wires moves values
+//! under a random shared permutation; sort_wires runs Batcher's odd-even merge
+//! sort, whose compare-and-swaps compare keys of a known bit length with
+//! less_than and swap with batch_obliv_swap, a multiplication per value. Both count their rounds
+//! and triples in the phases of the metrics.
+
+use ark_ff::{BigInteger, Field, PrimeField};
+use ark_std::{One, Zero};
+
+use crate::circuit::{select_resources, CircuitBuilder};
+use crate::common::{ProtocolParams, ResourceEstimate, F};
+use crate::evaluator::{Evaluator, EvaluatorError};
+use crate::permutation::{
+    apply_permutation_resources, apply_shared_permutation, random_permutation_resources,
+    random_shared_permutation,
+};
+use crate::transport::Transport;
+use crate::utils;
+use crate::wire::Wire;
+
+/// statistical security of the masks less_than opens its inputs under
+const MASK_SECURITY: usize = 40;
+
+/// the phase of the metrics shuffle_wires counts its rounds and triples in
+pub const SHUFFLE_WIRES_PHASE: &str = "shuffle_wires";
+
+/// the phase of the metrics sort_wires counts its rounds and triples in
+pub const SORT_WIRES_PHASE: &str = "sort_wires";
+
+/// the widest keys less_than compares: their difference, masked, must not wrap
+/// around the field
+pub fn max_key_bits() -> usize {
+    F::MODULUS_BIT_SIZE as usize - MASK_SECURITY - 2
+}
+
+/// multiplications of the prefix-or over bits bits, a doubling at a time
+fn prefix_or_mults(bits: usize) -> usize {
+    let mut step = 1;
+    let mut mults = 0;
+    while step < bits {
+        mults += bits - step;
+        step *= 2;
+    }
+    mults
+}
+
+/// preprocessing consumed by less_than for keys of bits bits, per comparison
+pub fn less_than_resources(bits: usize) -> ResourceEstimate {
+    let mask_bits = bits + MASK_SECURITY + 1;
+    ResourceEstimate {
+        // a squaring per random bit of the mask, and the prefix-or of the low bits
+        triples: mask_bits + prefix_or_mults(bits),
+        rand_sharings: mask_bits,
+    }
+}
+
+/// the compare-and-swaps of Batcher's odd-even merge sort of n values, a layer of
+/// disjoint pairs (i, j), i < j, at a time; the network for the next power of two,
+/// without the pairs past n, which would hold values larger than all others
+pub fn sorting_network(n: usize) -> Vec<Vec<(usize, usize)>> {
+    let size = n.next_power_of_two();
+    let mut layers = vec![];
+    let mut p = 1;
+    while p < size {
+        let mut k = p;
+        while k >= 1 {
+            let mut layer = vec![];
+            let mut j = k % p;
+            while j + k < size {
+                for i in 0..k.min(size - j - k) {
+                    let (a, b) = (i + j, i + j + k);
+                    if a / (2 * p) == b / (2 * p) && b < n {
+                        layer.push((a, b));
+                    }
+                }
+                j += 2 * k;
+            }
+            if !layer.is_empty() {
+                layers.push(layer);
+            }
+            k /= 2;
+        }
+        p *= 2;
+    }
+    layers
+}
+
+/// preprocessing consumed by sort_wires for n keys of bits bits, each with a payload
+pub fn sort_resources(n: usize, bits: usize) -> ResourceEstimate {
+    let pairs: usize = sorting_network(n).iter().map(Vec::len).sum();
+    let per_pair = less_than_resources(bits) + select_resources(2);
+    ResourceEstimate {
+        triples: pairs * per_pair.triples,
+        rand_sharings: pairs * per_pair.rand_sharings,
+    }
+}
+
+/// preprocessing consumed by shuffle_wires for n values, on an evaluator for params
+pub fn shuffle_wires_resources(params: &ProtocolParams, n: usize) -> ResourceEstimate {
+    if n < 2 {
+        return ResourceEstimate::default();
+    }
+    random_permutation_resources(params, n) + apply_permutation_resources(n, n)
+}
+
+/// returns handles to len random shared bits: a random [a] is squared and opened,
+/// and a / sqrt(a^2) is a random ±1; the rare zero is drawn again
+async fn batch_random_bits<T: Transport>(
+    evaluator: &mut Evaluator<T>,
+    len: usize,
+) -> Result<Vec<Wire>, EvaluatorError> {
+    let half = F::from(2u64).inverse().expect("the field is not binary");
+    let mut bits = Vec::with_capacity(len);
+    while bits.len() < len {
+        let randoms = (bits.len()..len)
+            .map(|_| evaluator.ran())
+            .collect::<Vec<Wire>>();
+        let squares = evaluator.batch_mult(&randoms, &randoms).await?;
+        let squares = evaluator.batch_output_wire(&squares).await?;
+        for (random, square) in randoms.iter().zip(squares) {
+            // every party picks the same root
+            if let Some(root) = utils::compute_root(&square).filter(|root| !root.is_zero()) {
+                let sign = evaluator.scale(random, root.inverse().unwrap());
+                let shifted = evaluator.clear_add(&sign, F::one());
+                bits.push(evaluator.scale(&shifted, half));
+            }
+        }
+    }
+    Ok(bits)
+}
+
+/// returns handles to the bits [xs[i] < ys[i]], for values below 2^bits. The
+/// difference is shifted to s in [1, 2^(bits+1)), whose top bit says x >= y;
+/// s mod 2^bits comes from opening s under a mask of random bits, and comparing
+/// the low bits of the opening to the ones of the mask.
+pub async fn batch_less_than<T: Transport>(
+    evaluator: &mut Evaluator<T>,
+    xs: &[Wire],
+    ys: &[Wire],
+    bits: usize,
+) -> Result<Vec<Wire>, EvaluatorError> {
+    if xs.len() != ys.len() {
+        return Err(EvaluatorError::LengthMismatch {
+            expected: xs.len(),
+            actual: ys.len(),
+        });
+    }
+    if bits == 0 || bits > max_key_bits() {
+        return Err(EvaluatorError::KeyTooWide {
+            bits,
+            max: max_key_bits(),
+        });
+    }
+    if xs.is_empty() {
+        return Ok(vec![]);
+    }
+    let per_comparison = less_than_resources(bits);
+    evaluator.assert_resources_remaining(&ResourceEstimate {
+        triples: xs.len() * per_comparison.triples,
+        rand_sharings: xs.len() * per_comparison.rand_sharings,
+    })?;
+
+    evaluator.begin_scope();
+    let result = less_than_in_scope(evaluator, xs, ys, bits).await;
+    if let Ok(lts) = &result {
+        lts.iter().for_each(|h| evaluator.keep(h));
+    }
+    evaluator.end_scope();
+    result
+}
+
+async fn less_than_in_scope<T: Transport>(
+    evaluator: &mut Evaluator<T>,
+    xs: &[Wire],
+    ys: &[Wire],
+    bits: usize,
+) -> Result<Vec<Wire>, EvaluatorError> {
+    let mask_bits = bits + MASK_SECURITY + 1;
+    let shift = F::from(2u64).pow([bits as u64]);
+    let random_bits = batch_random_bits(evaluator, xs.len() * mask_bits).await?;
+    let masks = random_bits.chunks(mask_bits).collect::<Vec<&[Wire]>>();
+
+    // s = x - y + 2^bits, opened under r = sum_i 2^i r_i
+    let mut shifted = Vec::with_capacity(xs.len());
+    let mut masked = Vec::with_capacity(xs.len());
+    for ((x, y), mask) in xs.iter().zip(ys).zip(masks.iter()) {
+        let difference = evaluator.sub(x, y);
+        let s = evaluator.clear_add(&difference, shift);
+        let mut share = evaluator.get_wire(&s);
+        let mut power = F::one();
+        for bit in mask.iter() {
+            share += evaluator.get_wire(bit) * power;
+            power.double_in_place();
+        }
+        shifted.push(s);
+        masked.push(evaluator.wire_from_share(share));
+    }
+    let opened = evaluator.batch_output_wire(&masked).await?;
+    let opened = opened.iter().map(|c| c.into_bigint()).collect::<Vec<_>>();
+
+    // the bits where the opening and the mask differ; the prefix-or from the top
+    // then marks everything below their highest difference
+    let mut differ = Vec::with_capacity(xs.len() * bits);
+    for (c, mask) in opened.iter().zip(masks.iter()) {
+        for (i, bit) in mask[..bits].iter().enumerate() {
+            differ.push(if c.get_bit(i) {
+                let negated = evaluator.scale(bit, -F::one());
+                evaluator.clear_add(&negated, F::one())
+            } else {
+                bit.clone()
+            });
+        }
+    }
+    let mut prefix = differ;
+    let mut step = 1;
+    while step < bits {
+        let (mut lower, mut upper) = (vec![], vec![]);
+        for k in 0..xs.len() {
+            for i in 0..bits - step {
+                lower.push(prefix[k * bits + i].clone());
+                upper.push(prefix[k * bits + i + step].clone());
+            }
+        }
+        let products = evaluator.batch_mult(&lower, &upper).await?;
+        let mut products = products.into_iter();
+        for k in 0..xs.len() {
+            for i in 0..bits - step {
+                // a or b = a + b - ab
+                let sum =
+                    evaluator.add(&lower[k * (bits - step) + i], &upper[k * (bits - step) + i]);
+                prefix[k * bits + i] = evaluator.sub(&sum, &products.next().unwrap());
+            }
+        }
+        step *= 2;
+    }
+
+    let inverse_shift = shift.inverse().expect("a power of two is not zero");
+    let mut lts = Vec::with_capacity(xs.len());
+    for (k, (c, mask)) in opened.iter().zip(masks.iter()).enumerate() {
+        // the highest difference is where the prefix-or steps up, and the low bits
+        // of the opening are below the mask if the mask holds a one there
+        let mut below = F::zero();
+        let mut low_opened = F::zero();
+        let mut low_mask = F::zero();
+        let mut power = F::one();
+        for i in 0..bits {
+            let step_up = evaluator.get_wire(&prefix[k * bits + i])
+                - match i + 1 < bits {
+                    true => evaluator.get_wire(&prefix[k * bits + i + 1]),
+                    false => F::zero(),
+                };
+            if !c.get_bit(i) {
+                below += step_up;
+            }
+            if c.get_bit(i) {
+                low_opened += power;
+            }
+            low_mask += evaluator.get_wire(&mask[i]) * power;
+            power.double_in_place();
+        }
+        // s mod 2^bits = (c mod 2^bits) - (r mod 2^bits) + 2^bits [c < r mod 2^bits]
+        let low = evaluator.constant_share(low_opened) - low_mask + below * shift;
+        // the top bit of s is (s - s mod 2^bits) / 2^bits, and x < y when it is zero
+        let top = (evaluator.get_wire(&shifted[k]) - low) * inverse_shift;
+        let lt = evaluator.constant_share(F::one()) - top;
+        lts.push(evaluator.wire_from_share(lt));
+    }
+    Ok(lts)
+}
+
+/// returns handles to values moved under a random shared permutation, which
+/// nobody learns; for fewer than two values, the values themselves
+pub async fn shuffle_wires<T: Transport>(
+    evaluator: &mut Evaluator<T>,
+    values: &[Wire],
+) -> Result<Vec<Wire>, EvaluatorError> {
+    if values.len() < 2 {
+        return Ok(values.to_vec());
+    }
+    evaluator.counters().begin_phase(SHUFFLE_WIRES_PHASE);
+    evaluator.begin_scope();
+    let result = match random_shared_permutation(evaluator, values.len()).await {
+        Ok(perm) => apply_shared_permutation(evaluator, &perm, values).await,
+        Err(err) => Err(err),
+    };
+    if let Ok(shuffled) = &result {
+        shuffled.iter().for_each(|h| evaluator.keep(h));
+    }
+    evaluator.end_scope();
+    evaluator.counters().end_phase(SHUFFLE_WIRES_PHASE);
+    result
+}
+
+/// sorts keys, below 2^bits, in increasing order, each payload moving along with
+/// its key; returns handles to the sorted keys and payloads. Equal keys may come
+/// out in any order.
+pub async fn sort_wires<T: Transport>(
+    evaluator: &mut Evaluator<T>,
+    keys: &[Wire],
+    payloads: &[Wire],
+    bits: usize,
+) -> Result<(Vec<Wire>, Vec<Wire>), EvaluatorError> {
+    if keys.len() != payloads.len() {
+        return Err(EvaluatorError::LengthMismatch {
+            expected: keys.len(),
+            actual: payloads.len(),
+        });
+    }
+    evaluator.assert_resources_remaining(&sort_resources(keys.len(), bits))?;
+
+    evaluator.counters().begin_phase(SORT_WIRES_PHASE);
+    evaluator.begin_scope();
+    let result = sort_in_scope(evaluator, keys, payloads, bits).await;
+    if let Ok((keys, payloads)) = &result {
+        keys.iter().chain(payloads).for_each(|h| evaluator.keep(h));
+    }
+    evaluator.end_scope();
+    evaluator.counters().end_phase(SORT_WIRES_PHASE);
+    result
+}
+
+async fn sort_in_scope<T: Transport>(
+    evaluator: &mut Evaluator<T>,
+    keys: &[Wire],
+    payloads: &[Wire],
+    bits: usize,
+) -> Result<(Vec<Wire>, Vec<Wire>), EvaluatorError> {
+    let mut keys = keys.to_vec();
+    let mut payloads = payloads.to_vec();
+    for layer in sorting_network(keys.len()) {
+        let firsts = layer
+            .iter()
+            .map(|(i, _)| keys[*i].clone())
+            .collect::<Vec<Wire>>();
+        let seconds = layer
+            .iter()
+            .map(|(_, j)| keys[*j].clone())
+            .collect::<Vec<Wire>>();
+        let swaps = batch_less_than(evaluator, &seconds, &firsts, bits).await?;
+
+        // the keys and the payloads of the layer swap together, in one round
+        let (mut selectors, mut firsts, mut seconds) = (vec![], vec![], vec![]);
+        for ((i, j), swap) in layer.iter().zip(&swaps) {
+            for values in [&keys, &payloads] {
+                selectors.push(swap.clone());
+                firsts.push(values[*i].clone());
+                seconds.push(values[*j].clone());
+            }
+        }
+        let (firsts, seconds) = evaluator
+            .batch_obliv_swap(&selectors, &firsts, &seconds)
+            .await?;
+        for (k, (i, j)) in layer.iter().enumerate() {
+            for (m, values) in [&mut keys, &mut payloads].into_iter().enumerate() {
+                values[*i] = firsts[2 * k + m].clone();
+                values[*j] = seconds[2 * k + m].clone();
+            }
+        }
+    }
+    Ok((keys, payloads))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        batch_less_than, less_than_resources, shuffle_wires, shuffle_wires_resources,
+        sort_resources, sort_wires, sorting_network, SHUFFLE_WIRES_PHASE, SORT_WIRES_PHASE,
+    };
+    use crate::common::{ProtocolParams, ResourceEstimate, F};
+    use crate::evaluator::Evaluator;
+    use crate::simulator::run_built_parties;
+    use crate::wire::Wire;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    #[test]
+    fn test_sorting_network_sorts_every_bit_vector() {
+        // the 0-1 principle: a network sorting every vector of bits sorts everything
+        for n in 1..=9usize {
+            let network = sorting_network(n);
+            for layer in network.iter() {
+                let mut seen = vec![false; n];
+                for (i, j) in layer.iter() {
+                    assert!(i < j && *j < n);
+                    assert!(!seen[*i] && !seen[*j]);
+                    seen[*i] = true;
+                    seen[*j] = true;
+                }
+            }
+            for word in 0..(1u32 << n) {
+                let mut values = (0..n).map(|i| (word >> i) & 1).collect::<Vec<u32>>();
+                for (i, j) in network.iter().flatten() {
+                    if values[*j] < values[*i] {
+                        values.swap(*i, *j);
+                    }
+                }
+                assert!(values.windows(2).all(|pair| pair[0] <= pair[1]));
+            }
+        }
+        let pairs: usize = sorting_network(64).iter().map(Vec::len).sum();
+        assert_eq!(pairs, 543);
+    }
+
+    #[test]
+    fn test_less_than_at_the_edges() {
+        let bits = 8;
+        let pairs = [
+            (0u64, 0u64),
+            (0, 1),
+            (1, 0),
+            (0, 255),
+            (255, 0),
+            (254, 255),
+            (255, 255),
+            (17, 17),
+        ];
+        let per_comparison = less_than_resources(bits);
+        let resources = ResourceEstimate {
+            triples: pairs.len() * per_comparison.triples,
+            rand_sharings: pairs.len() * per_comparison.rand_sharings,
+        };
+        let build =
+            |transport| Evaluator::new_for(transport, ProtocolParams::default(), resources, 0);
+        let outputs =
+            async_std::task::block_on(run_built_parties(3, build, |mut evaluator| async move {
+                let xs = pairs
+                    .iter()
+                    .map(|(x, _)| evaluator.fixed_wire_handle(F::from(*x)))
+                    .collect::<Vec<Wire>>();
+                let ys = pairs
+                    .iter()
+                    .map(|(_, y)| evaluator.fixed_wire_handle(F::from(*y)))
+                    .collect::<Vec<Wire>>();
+                let lts = batch_less_than(&mut evaluator, &xs, &ys, bits)
+                    .await
+                    .unwrap();
+                evaluator.batch_output_wire(&lts).await.unwrap()
+            }));
+
+        let expected = pairs
+            .iter()
+            .map(|(x, y)| F::from((x < y) as u64))
+            .collect::<Vec<F>>();
+        assert!(outputs.iter().all(|lts| *lts == expected));
+    }
+
+    #[test]
+    fn test_sort_wires_matches_a_plaintext_sort() {
+        let bits = 8;
+        let mut rng = StdRng::seed_from_u64(11);
+        for n in [8usize, 64] {
+            let keys = (0..n)
+                .map(|_| rng.gen_range(0..256u64))
+                .collect::<Vec<u64>>();
+            let resources = sort_resources(n, bits);
+            let build =
+                |transport| Evaluator::new_for(transport, ProtocolParams::default(), resources, 0);
+            let outputs =
+                async_std::task::block_on(run_built_parties(3, build, |mut evaluator| {
+                    let keys = keys.clone();
+                    async move {
+                        let key_wires = keys
+                            .iter()
+                            .map(|key| evaluator.fixed_wire_handle(F::from(*key)))
+                            .collect::<Vec<Wire>>();
+                        // the payload of a key is its index
+                        let payloads = (0..n as u64)
+                            .map(|i| evaluator.fixed_wire_handle(F::from(i)))
+                            .collect::<Vec<Wire>>();
+                        let (sorted, moved) =
+                            sort_wires(&mut evaluator, &key_wires, &payloads, bits)
+                                .await
+                                .unwrap();
+                        let sorted = evaluator.batch_output_wire(&sorted).await.unwrap();
+                        let moved = evaluator.batch_output_wire(&moved).await.unwrap();
+                        (sorted, moved, evaluator.metrics())
+                    }
+                }));
+
+            let mut expected = keys.clone();
+            expected.sort();
+            let expected = expected.into_iter().map(F::from).collect::<Vec<F>>();
+            for (sorted, moved, metrics) in outputs.iter() {
+                assert_eq!(*sorted, expected);
+                let mut indices = moved
+                    .iter()
+                    .map(|index| (0..n).find(|i| F::from(*i as u64) == *index).unwrap())
+                    .collect::<Vec<usize>>();
+                for (index, key) in indices.iter().zip(sorted) {
+                    assert_eq!(F::from(keys[*index]), *key);
+                }
+                indices.sort();
+                assert_eq!(indices, (0..n).collect::<Vec<usize>>());
+
+                assert_eq!(
+                    metrics.phase_beaver_triples[SORT_WIRES_PHASE] as usize,
+                    sort_resources(n, bits).triples
+                );
+                assert!(metrics.phase_rounds[SORT_WIRES_PHASE] >= sorting_network(n).len() as u64);
+            }
+        }
+    }
+
+    #[test]
+    fn test_shuffle_wires_keeps_the_values() {
+        let params = ProtocolParams::new(16, 10).unwrap();
+        let n = 12;
+        let resources = shuffle_wires_resources(&params, n);
+        let build = |transport| Evaluator::new_for(transport, params, resources, 0);
+        let outputs =
+            async_std::task::block_on(run_built_parties(3, build, |mut evaluator| async move {
+                let values = (0..n as u64)
+                    .map(|i| evaluator.fixed_wire_handle(F::from(100 + i)))
+                    .collect::<Vec<Wire>>();
+                let shuffled = shuffle_wires(&mut evaluator, &values).await.unwrap();
+                let opened = evaluator.batch_output_wire(&shuffled).await.unwrap();
+                (opened, evaluator.metrics())
+            }));
+
+        for (opened, metrics) in outputs.iter() {
+            assert_eq!(opened, &outputs[0].0);
+            let mut values = opened.clone();
+            values.sort();
+            let expected = (0..n as u64).map(|i| F::from(100 + i)).collect::<Vec<F>>();
+            assert_eq!(values, expected);
+            assert!(metrics.phase_rounds[SHUFFLE_WIRES_PHASE] > 0);
+            assert!(metrics.phase_beaver_triples[SHUFFLE_WIRES_PHASE] > 0);
+        }
+    }
+}
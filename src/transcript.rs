@@ -0,0 +1,294 @@
+use ark_crypto_primitives::crh::sha256::Sha256;
+use ark_ec::pairing::{Pairing, PairingOutput};
+use ark_ec::CurveGroup;
+use ark_ff::field_hashers::{DefaultFieldHasher, HashToField};
+use ark_ff::PrimeField;
+use ark_serialize::{
+    CanonicalDeserialize, CanonicalSerialize, Compress, SerializationError, Valid, Validate,
+};
+use std::io::{Read, Write};
+
+use crate::common::{Curve, CurveConfig, F};
+use crate::utils;
+
+/// domain separation tag of the field hasher behind the challenges
+const TRANSCRIPT_DST: &[u8] = b"pok3r-transcript";
+
+/// most challenges one call of the field hasher derives
+const SHA256_BLOCK: usize = 128;
+
+/// FsHasher selects the hash behind the Fiat-Shamir challenges. Proofs record it,
+/// so that verifiers derive the same challenges as the prover.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FsHasher {
+    /// DefaultFieldHasher over SHA-256
+    #[default]
+    Sha256,
+    /// the Poseidon sponge of utils::fs_hash_poseidon, for verifiers inside a SNARK
+    Poseidon,
+}
+
+impl CanonicalSerialize for FsHasher {
+    fn serialize_with_mode<W: Write>(
+        &self,
+        writer: W,
+        compress: Compress,
+    ) -> Result<(), SerializationError> {
+        (*self as u8).serialize_with_mode(writer, compress)
+    }
+
+    fn serialized_size(&self, _compress: Compress) -> usize {
+        1
+    }
+}
+
+impl Valid for FsHasher {
+    fn check(&self) -> Result<(), SerializationError> {
+        Ok(())
+    }
+}
+
+impl CanonicalDeserialize for FsHasher {
+    fn deserialize_with_mode<R: Read>(
+        reader: R,
+        compress: Compress,
+        validate: Validate,
+    ) -> Result<Self, SerializationError> {
+        match u8::deserialize_with_mode(reader, compress, validate)? {
+            0 => Ok(FsHasher::Sha256),
+            1 => Ok(FsHasher::Poseidon),
+            _ => Err(SerializationError::InvalidData),
+        }
+    }
+}
+
+/// Transcript accumulates the public messages of a proof and derives Fiat-Shamir
+/// challenges from them. Every item is written as its label and its value, each
+/// prefixed by its length, so that items cannot be reordered or re-sliced without
+/// changing the challenges, and every challenge is appended back to the transcript.
+pub struct Transcript {
+    bytes: Vec<u8>,
+    hasher: FsHasher,
+    /// reproduce utils::fs_hash over the plain concatenation of the values
+    legacy: bool,
+}
+
+impl Transcript {
+    /// starts a transcript for the proof system named by domain
+    pub fn new(domain: &[u8]) -> Self {
+        Self::with_hasher(domain, FsHasher::Sha256)
+    }
+
+    /// same as new, but the challenges are derived with hasher
+    pub fn with_hasher(domain: &[u8], hasher: FsHasher) -> Self {
+        let mut transcript = Transcript {
+            bytes: Vec::new(),
+            hasher,
+            legacy: false,
+        };
+        transcript.append_bytes(b"domain", domain);
+        transcript
+    }
+
+    /// a transcript that derives the same challenges as the earlier ad-hoc fs_hash
+    /// calls: labels are ignored, values are concatenated, and challenges are not
+    /// appended. Only for checking proofs made before transcripts existed.
+    pub fn legacy() -> Self {
+        Transcript {
+            bytes: Vec::new(),
+            hasher: FsHasher::Sha256,
+            legacy: true,
+        }
+    }
+
+    pub fn append_bytes(&mut self, label: &[u8], value: &[u8]) {
+        if !self.legacy {
+            self.bytes
+                .extend_from_slice(&(label.len() as u64).to_le_bytes());
+            self.bytes.extend_from_slice(label);
+            self.bytes
+                .extend_from_slice(&(value.len() as u64).to_le_bytes());
+        }
+        self.bytes.extend_from_slice(value);
+    }
+
+    // values of any curve, each named after its kind
+    pub fn append_f<S: PrimeField>(&mut self, label: &[u8], value: &S) {
+        self.append_serialized(label, value);
+    }
+
+    pub fn append_g1<G: CurveGroup>(&mut self, label: &[u8], value: &G) {
+        self.append_serialized(label, value);
+    }
+
+    pub fn append_g2<G: CurveGroup>(&mut self, label: &[u8], value: &G) {
+        self.append_serialized(label, value);
+    }
+
+    pub fn append_gt<E: Pairing>(&mut self, label: &[u8], value: &PairingOutput<E>) {
+        self.append_serialized(label, value);
+    }
+
+    fn append_serialized<T: CanonicalSerialize>(&mut self, label: &[u8], value: &T) {
+        let mut bytes = Vec::new();
+        value.serialize_uncompressed(&mut bytes).unwrap();
+        self.append_bytes(label, &bytes);
+    }
+
+    pub fn challenge_f(&mut self, label: &[u8]) -> F {
+        self.challenge_f_on::<Curve>(label)
+    }
+
+    /// n challenges bound to everything appended so far
+    pub fn challenge_fs(&mut self, label: &[u8], n: usize) -> Vec<F> {
+        self.challenge_fs_on::<Curve>(label, n)
+    }
+
+    /// challenge_f in the scalar field of E
+    pub fn challenge_f_on<E: CurveConfig>(&mut self, label: &[u8]) -> E::ScalarField {
+        self.challenge_fs_on::<E>(label, 1)[0]
+    }
+
+    /// challenge_fs in the scalar field of E
+    pub fn challenge_fs_on<E: CurveConfig>(
+        &mut self,
+        label: &[u8],
+        n: usize,
+    ) -> Vec<E::ScalarField> {
+        if self.legacy {
+            return utils::fs_hash(vec![&self.bytes], n);
+        }
+
+        self.append_bytes(b"challenge", label);
+        let challenges: Vec<E::ScalarField> = match self.hasher {
+            FsHasher::Sha256 => {
+                let hasher = <DefaultFieldHasher<Sha256> as HashToField<E::ScalarField>>::new(
+                    TRANSCRIPT_DST,
+                );
+                if n <= SHA256_BLOCK {
+                    hasher.hash_to_field(&self.bytes, n)
+                } else {
+                    // the expander stops at 255 digests, so longer runs come in
+                    // blocks, each over the transcript and its block index
+                    (0..n)
+                        .step_by(SHA256_BLOCK)
+                        .flat_map(|start| {
+                            let mut bytes = self.bytes.clone();
+                            bytes.extend_from_slice(&((start / SHA256_BLOCK) as u64).to_le_bytes());
+                            hasher.hash_to_field(&bytes, SHA256_BLOCK.min(n - start))
+                        })
+                        .collect()
+                }
+            }
+            FsHasher::Poseidon => {
+                utils::fs_hash_poseidon_on::<E>(&utils::bytes_to_fields(&self.bytes), n)
+            }
+        };
+        for challenge in challenges.iter() {
+            self.append_f(label, challenge);
+        }
+        challenges
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FsHasher, Transcript};
+    use crate::common::{F, G1};
+    use crate::utils;
+    use ark_ec::Group;
+    use ark_serialize::CanonicalSerialize;
+
+    #[test]
+    fn test_reslicing_changes_the_challenge() {
+        let mut a = Transcript::new(b"test");
+        a.append_bytes(b"x", &[1, 2]);
+        a.append_bytes(b"y", &[3]);
+
+        let mut b = Transcript::new(b"test");
+        b.append_bytes(b"x", &[1]);
+        b.append_bytes(b"y", &[2, 3]);
+
+        assert_ne!(a.challenge_f(b"c"), b.challenge_f(b"c"));
+
+        // the old derivation could not tell them apart
+        let mut a = Transcript::legacy();
+        a.append_bytes(b"x", &[1, 2]);
+        a.append_bytes(b"y", &[3]);
+        let mut b = Transcript::legacy();
+        b.append_bytes(b"x", &[1]);
+        b.append_bytes(b"y", &[2, 3]);
+        assert_eq!(a.challenge_f(b"c"), b.challenge_f(b"c"));
+    }
+
+    #[test]
+    fn test_reordering_changes_the_challenge() {
+        let g = G1::generator();
+        let h = g + g;
+
+        let mut a = Transcript::new(b"test");
+        a.append_g1(b"g", &g);
+        a.append_g1(b"h", &h);
+
+        let mut b = Transcript::new(b"test");
+        b.append_g1(b"h", &h);
+        b.append_g1(b"g", &g);
+
+        assert_ne!(a.challenge_f(b"c"), b.challenge_f(b"c"));
+    }
+
+    #[test]
+    fn test_challenges_are_chained() {
+        let mut a = Transcript::new(b"test");
+        a.append_f(b"x", &F::from(5u64));
+        let first = a.challenge_f(b"c");
+        let second = a.challenge_f(b"c");
+        assert_ne!(first, second);
+
+        let mut other_domain = Transcript::new(b"other");
+        other_domain.append_f(b"x", &F::from(5u64));
+        assert_ne!(first, other_domain.challenge_f(b"c"));
+    }
+
+    #[test]
+    fn test_many_challenges_are_derived_in_blocks() {
+        let challenges = |n| {
+            let mut transcript = Transcript::new(b"test");
+            transcript.append_bytes(b"x", &[1]);
+            transcript.challenge_fs(b"c", n)
+        };
+        let many = challenges(1000);
+        assert_eq!(many.len(), 1000);
+        assert_ne!(many[..128], many[128..256]);
+        assert_eq!(challenges(1000), many);
+    }
+
+    #[test]
+    fn test_hashers_derive_different_challenges() {
+        let challenge = |hasher| {
+            let mut transcript = Transcript::with_hasher(b"test", hasher);
+            transcript.append_f(b"x", &F::from(5u64));
+            transcript.challenge_fs(b"c", 2)
+        };
+        let (sha, poseidon) = (challenge(FsHasher::Sha256), challenge(FsHasher::Poseidon));
+        assert_eq!(poseidon, challenge(FsHasher::Poseidon));
+        assert_ne!(sha, poseidon);
+        assert_ne!(poseidon[0], poseidon[1]);
+    }
+
+    #[test]
+    fn test_legacy_matches_fs_hash() {
+        let g = G1::generator();
+        let (mut g_bytes, mut x_bytes) = (Vec::new(), Vec::new());
+        g.serialize_uncompressed(&mut g_bytes).unwrap();
+        F::from(7u64).serialize_uncompressed(&mut x_bytes).unwrap();
+
+        let mut transcript = Transcript::legacy();
+        transcript.append_g1(b"g", &g);
+        transcript.append_f(b"x", &F::from(7u64));
+        assert_eq!(
+            transcript.challenge_fs(b"c", 2),
+            utils::fs_hash(vec![&g_bytes, &x_bytes], 2)
+        );
+    }
+}
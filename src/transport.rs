@@ -0,0 +1,1346 @@
+use async_trait::async_trait;
+use futures::{channel::mpsc, StreamExt};
+use libp2p::{identity, PeerId};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::address_book::{keypair_from_seed, AddrBook, PeerRole, Pok3rAddrBook, Pok3rPeerId};
+use crate::common::{EvalNetMsg, PayloadKind, PeerInfo};
+use crate::journal::JournalError;
+use crate::metrics::Metrics;
+use crate::network::{
+    decode_wire_msg, encode_wire_msg, HeartbeatConfig, MessagingSystem, NetworkError, PeerStatus,
+    QueueConfig, RetransmitConfig, DEFAULT_QUEUE_CAPACITY,
+};
+
+/// Transport is what the Evaluator needs from the network: broadcasting
+/// values under a handle and collecting every other party's value for it
+#[async_trait]
+pub trait Transport: Send {
+    /// our node id, between 1 and the size of the addr book
+    fn get_my_id(&self) -> u64;
+
+    /// information about all parties, including ourselves
+    fn addr_book(&self) -> &Pok3rAddrBook;
+
+    /// the game (or run) this transport carries messages for
+    fn session_id(&self) -> &str;
+
+    /// communication counters of this transport
+    fn metrics(&self) -> Arc<Metrics>;
+
+    /// signs bytes with our identity key, the one behind our peer id
+    fn sign(&self, bytes: &[u8]) -> Vec<u8>;
+
+    /// broadcasts values, one per handle; fails if they differ in number
+    async fn send_to_all<H, V>(&mut self, handles: &[H], values: &[V]) -> Result<(), NetworkError>
+    where
+        H: AsRef<str> + Sync,
+        V: AsRef<str> + Sync;
+
+    /// sends values to the party with node id recipient_id, and nobody else; fails
+    /// if no party has that node id
+    async fn send_to_party<H, V>(
+        &mut self,
+        recipient_id: u64,
+        handles: &[H],
+        values: &[V],
+    ) -> Result<(), NetworkError>
+    where
+        H: AsRef<str> + Sync,
+        V: AsRef<str> + Sync;
+
+    /// sends values to every peer of the addr book with role, e.g. proofs to the
+    /// verifiers; for the committee, this is send_to_all
+    async fn send_to_role<H, V>(
+        &mut self,
+        role: PeerRole,
+        handles: &[H],
+        values: &[V],
+    ) -> Result<(), NetworkError>
+    where
+        H: AsRef<str> + Sync,
+        V: AsRef<str> + Sync;
+
+    /// sends values to the player with peer id player, and nobody else; fails if
+    /// the addr book does not list player as a player
+    async fn send_to_player<H, V>(
+        &mut self,
+        player: &Pok3rPeerId,
+        handles: &[H],
+        values: &[V],
+    ) -> Result<(), NetworkError>
+    where
+        H: AsRef<str> + Sync,
+        V: AsRef<str> + Sync;
+
+    /// sends canonically serialized values of the given kind to the party with node
+    /// id recipient_id only; transports without a binary format fall back to base58
+    async fn send_batch_to_party<H: AsRef<str> + Sync>(
+        &mut self,
+        recipient_id: u64,
+        handles: &[H],
+        _kind: PayloadKind,
+        values: &[Vec<u8>],
+    ) -> Result<(), NetworkError> {
+        let values = values
+            .iter()
+            .map(|v| bs58::encode(v).into_string())
+            .collect::<Vec<String>>();
+        self.send_to_party(recipient_id, handles, &values).await
+    }
+
+    /// broadcasts canonically serialized values of the given kind;
+    /// transports without a binary format fall back to base58 strings
+    async fn send_batch_to_all<H: AsRef<str> + Sync>(
+        &mut self,
+        handles: &[H],
+        _kind: PayloadKind,
+        values: &[Vec<u8>],
+    ) -> Result<(), NetworkError> {
+        let values = values
+            .iter()
+            .map(|v| bs58::encode(v).into_string())
+            .collect::<Vec<String>>();
+        self.send_to_all(handles, &values).await
+    }
+
+    /// waits until every other party has sent a value for identifier, and returns
+    /// those values indexed by node id, or a timeout naming the parties that did not
+    async fn recv_from_all(
+        &mut self,
+        identifier: &str,
+    ) -> Result<HashMap<u64, String>, NetworkError>;
+
+    /// recv_from_all for every one of identifiers, keyed by identifier; transports
+    /// that can wait for all of them at once should override this
+    async fn recv_many_from_all<I: AsRef<str> + Sync>(
+        &mut self,
+        identifiers: &[I],
+    ) -> Result<HashMap<String, HashMap<u64, String>>, NetworkError> {
+        let mut messages = HashMap::with_capacity(identifiers.len());
+        for identifier in identifiers {
+            let identifier = identifier.as_ref().to_string();
+            let values = self.recv_from_all(&identifier).await?;
+            messages.insert(identifier, values);
+        }
+        Ok(messages)
+    }
+
+    /// same as recv_from_all, but gives up at the given deadline
+    async fn recv_from_all_with_deadline(
+        &mut self,
+        identifier: &str,
+        deadline: Instant,
+    ) -> Result<HashMap<u64, String>, NetworkError>;
+
+    /// same as recv_from_all, but returns once count of the other parties have sent
+    async fn recv_from_some(
+        &mut self,
+        identifier: &str,
+        count: usize,
+    ) -> Result<HashMap<u64, String>, NetworkError>;
+
+    /// same as recv_from_some, but the values that come later are kept for
+    /// take_late_values; transports that cannot keep them drop them
+    async fn recv_quorum(
+        &mut self,
+        identifier: &str,
+        quorum: usize,
+    ) -> Result<HashMap<u64, String>, NetworkError> {
+        self.recv_from_some(identifier, quorum).await
+    }
+
+    /// the values that came after recv_quorum returned, as (handle, node id, value)
+    fn take_late_values(&mut self) -> Vec<(String, u64, String)> {
+        Vec::new()
+    }
+
+    /// waits until every value recv_quorum returned without has come, or deadline
+    async fn wait_for_late_values(&mut self, _deadline: Instant) -> Result<(), NetworkError> {
+        Ok(())
+    }
+
+    /// waits for the value the party with node id sender_id sent only to us
+    async fn recv_from_party(
+        &mut self,
+        sender_id: u64,
+        handle: &str,
+    ) -> Result<String, NetworkError>;
+
+    /// broadcasts value under handle and collects every other party's value for
+    /// it, as send_to_all and recv_from_all do, and then checks with echo_received
+    /// that every party got the same values
+    async fn broadcast_consistent(
+        &mut self,
+        handle: &str,
+        value: &str,
+    ) -> Result<HashMap<u64, String>, NetworkError> {
+        let handle = handle.to_string();
+        self.send_to_all(&[&handle], &[value]).await?;
+        let values = self.recv_from_all(&handle).await?;
+        let mut received = HashMap::from([(handle.clone(), values)]);
+        self.echo_received(&[&handle], &received).await?;
+        Ok(received.remove(&handle).unwrap_or_default())
+    }
+
+    /// the echo round of a consistent broadcast: sends every party a digest of what
+    /// each sender sent us under handles, and compares the digests the others send
+    /// back with ours. Fails with NetworkError::Equivocation if the values of a
+    /// sender did not reach every party alike; received must hold the values of
+    /// the same senders at every party, e.g. those of all of them.
+    async fn echo_received<I: AsRef<str> + Sync>(
+        &mut self,
+        handles: &[I],
+        received: &HashMap<String, HashMap<u64, String>>,
+    ) -> Result<(), NetworkError> {
+        let echo_handle = match handles.first() {
+            Some(handle) => format!("{}/echo", handle.as_ref()),
+            None => return Ok(()),
+        };
+        let digests = echo_digests(handles, received);
+        self.send_to_all(&[&echo_handle], &[encode_echo(&digests)])
+            .await?;
+        let echoes = self.recv_from_all(&echo_handle).await?;
+        check_echoes(self.get_my_id(), &digests, &echoes)
+    }
+
+    /// makes sure everything we sent has been delivered;
+    /// transports with reliable delivery have nothing to do
+    async fn flush(&mut self) -> Result<(), NetworkError> {
+        Ok(())
+    }
+
+    /// moves on to the given round of the evaluator; what we send from then on
+    /// carries it, and values of earlier rounds are dropped. Transports that
+    /// cannot replay messages have nothing to do.
+    fn set_round(&mut self, _round: u64) {}
+
+    /// exchanges PeerInfo with every party and fails unless all of them can play
+    /// with us; transports whose parties are all the same build have nothing to do
+    async fn handshake(&mut self, _info: &PeerInfo) -> Result<(), NetworkError> {
+        Ok(())
+    }
+
+    /// replaces the parties we talk to; fails if a receive was left unfinished
+    fn update_addr_book(&mut self, addr_book: Pok3rAddrBook) -> Result<(), NetworkError>;
+
+    /// tells every peer that we give up on the session; their pending and
+    /// later receives fail with NetworkError::Aborted
+    async fn broadcast_abort(&mut self, reason: &str);
+
+    /// what the heartbeats say about every other party, by node id; transports
+    /// without heartbeats know nothing
+    fn peer_status(&mut self) -> HashMap<u64, PeerStatus> {
+        HashMap::new()
+    }
+
+    /// node ids of the parties as they are found dead; transports without
+    /// heartbeats never find any, so their stream ends at once
+    fn on_peer_down(&mut self) -> mpsc::UnboundedReceiver<u64> {
+        mpsc::unbounded().1
+    }
+}
+
+/// the digest of the values every sender sent us under handles, in their order
+fn echo_digests<I: AsRef<str>>(
+    handles: &[I],
+    received: &HashMap<String, HashMap<u64, String>>,
+) -> BTreeMap<u64, String> {
+    let mut hashers: BTreeMap<u64, Sha256> = BTreeMap::new();
+    for handle in handles.iter().map(AsRef::as_ref) {
+        for (sender, value) in received.get(handle).into_iter().flatten() {
+            let hasher = hashers.entry(*sender).or_default();
+            for field in [handle.as_bytes(), value.as_bytes()] {
+                hasher.update((field.len() as u64).to_le_bytes());
+                hasher.update(field);
+            }
+        }
+    }
+    hashers
+        .into_iter()
+        .map(|(sender, hasher)| (sender, bs58::encode(hasher.finalize()).into_string()))
+        .collect()
+}
+
+/// an echo is sender:digest for every sender, separated by commas
+fn encode_echo(digests: &BTreeMap<u64, String>) -> String {
+    digests
+        .iter()
+        .map(|(sender, digest)| format!("{}:{}", sender, digest))
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+/// the digests of an echo; malformed entries are left out
+fn decode_echo(echo: &str) -> BTreeMap<u64, String> {
+    echo.split(',')
+        .filter_map(|entry| {
+            let (sender, digest) = entry.split_once(':')?;
+            Some((sender.parse().ok()?, digest.to_string()))
+        })
+        .collect()
+}
+
+/// compares our digest of every sender with those of the echoes, by node id of
+/// the echoing party; nobody echoes its own values
+fn check_echoes(
+    my_id: u64,
+    digests: &BTreeMap<u64, String>,
+    echoes: &HashMap<u64, String>,
+) -> Result<(), NetworkError> {
+    let echoes: BTreeMap<u64, BTreeMap<u64, String>> = echoes
+        .iter()
+        .map(|(echoer, echo)| (*echoer, decode_echo(echo)))
+        .collect();
+    for (sender, digest) in digests {
+        let mut seen = BTreeMap::from([(my_id, digest.clone())]);
+        for (echoer, echo) in echoes.iter() {
+            if let Some(theirs) = echo.get(sender) {
+                seen.insert(*echoer, theirs.clone());
+            }
+        }
+        if seen.values().any(|theirs| theirs != digest) {
+            return Err(NetworkError::Equivocation {
+                sender: *sender,
+                conflicting_digests: seen.into_iter().collect(),
+            });
+        }
+    }
+    Ok(())
+}
+
+#[async_trait]
+impl Transport for MessagingSystem {
+    fn get_my_id(&self) -> u64 {
+        MessagingSystem::get_my_id(self)
+    }
+
+    fn addr_book(&self) -> &Pok3rAddrBook {
+        &self.addr_book
+    }
+
+    fn session_id(&self) -> &str {
+        MessagingSystem::session_id(self)
+    }
+
+    fn metrics(&self) -> Arc<Metrics> {
+        MessagingSystem::metrics(self)
+    }
+
+    fn sign(&self, bytes: &[u8]) -> Vec<u8> {
+        MessagingSystem::sign_bytes(self, bytes)
+    }
+
+    async fn send_to_all<H, V>(&mut self, handles: &[H], values: &[V]) -> Result<(), NetworkError>
+    where
+        H: AsRef<str> + Sync,
+        V: AsRef<str> + Sync,
+    {
+        MessagingSystem::send_to_all(self, handles, values).await
+    }
+
+    async fn send_to_party<H, V>(
+        &mut self,
+        recipient_id: u64,
+        handles: &[H],
+        values: &[V],
+    ) -> Result<(), NetworkError>
+    where
+        H: AsRef<str> + Sync,
+        V: AsRef<str> + Sync,
+    {
+        MessagingSystem::send_to_party(self, recipient_id, handles, values).await
+    }
+
+    async fn send_to_role<H, V>(
+        &mut self,
+        role: PeerRole,
+        handles: &[H],
+        values: &[V],
+    ) -> Result<(), NetworkError>
+    where
+        H: AsRef<str> + Sync,
+        V: AsRef<str> + Sync,
+    {
+        MessagingSystem::send_to_role(self, role, handles, values).await
+    }
+
+    async fn send_to_player<H, V>(
+        &mut self,
+        player: &Pok3rPeerId,
+        handles: &[H],
+        values: &[V],
+    ) -> Result<(), NetworkError>
+    where
+        H: AsRef<str> + Sync,
+        V: AsRef<str> + Sync,
+    {
+        MessagingSystem::send_to_player(self, player, handles, values).await
+    }
+
+    async fn send_batch_to_party<H: AsRef<str> + Sync>(
+        &mut self,
+        recipient_id: u64,
+        handles: &[H],
+        kind: PayloadKind,
+        values: &[Vec<u8>],
+    ) -> Result<(), NetworkError> {
+        MessagingSystem::send_batch_to_party(self, recipient_id, handles, kind, values).await
+    }
+
+    async fn send_batch_to_all<H: AsRef<str> + Sync>(
+        &mut self,
+        handles: &[H],
+        kind: PayloadKind,
+        values: &[Vec<u8>],
+    ) -> Result<(), NetworkError> {
+        MessagingSystem::send_batch_to_all(self, handles, kind, values).await
+    }
+
+    async fn recv_from_all(
+        &mut self,
+        identifier: &str,
+    ) -> Result<HashMap<u64, String>, NetworkError> {
+        MessagingSystem::recv_from_all(self, identifier).await
+    }
+
+    async fn recv_many_from_all<I: AsRef<str> + Sync>(
+        &mut self,
+        identifiers: &[I],
+    ) -> Result<HashMap<String, HashMap<u64, String>>, NetworkError> {
+        MessagingSystem::recv_many_from_all(self, identifiers).await
+    }
+
+    async fn recv_from_all_with_deadline(
+        &mut self,
+        identifier: &str,
+        deadline: Instant,
+    ) -> Result<HashMap<u64, String>, NetworkError> {
+        MessagingSystem::recv_from_all_with_deadline(self, identifier, deadline).await
+    }
+
+    async fn recv_from_some(
+        &mut self,
+        identifier: &str,
+        count: usize,
+    ) -> Result<HashMap<u64, String>, NetworkError> {
+        MessagingSystem::recv_from_some(self, identifier, count).await
+    }
+
+    async fn recv_quorum(
+        &mut self,
+        identifier: &str,
+        quorum: usize,
+    ) -> Result<HashMap<u64, String>, NetworkError> {
+        MessagingSystem::recv_quorum(self, identifier, quorum).await
+    }
+
+    fn take_late_values(&mut self) -> Vec<(String, u64, String)> {
+        MessagingSystem::take_late_values(self)
+    }
+
+    async fn wait_for_late_values(&mut self, deadline: Instant) -> Result<(), NetworkError> {
+        MessagingSystem::wait_for_late_values(self, deadline).await
+    }
+
+    async fn recv_from_party(
+        &mut self,
+        sender_id: u64,
+        handle: &str,
+    ) -> Result<String, NetworkError> {
+        MessagingSystem::recv_from_party(self, sender_id, handle).await
+    }
+
+    async fn flush(&mut self) -> Result<(), NetworkError> {
+        MessagingSystem::flush(self).await
+    }
+
+    fn set_round(&mut self, round: u64) {
+        MessagingSystem::set_round(self, round)
+    }
+
+    async fn handshake(&mut self, info: &PeerInfo) -> Result<(), NetworkError> {
+        MessagingSystem::handshake(self, info).await
+    }
+
+    async fn broadcast_abort(&mut self, reason: &str) {
+        MessagingSystem::broadcast_abort(self, reason).await
+    }
+
+    fn update_addr_book(&mut self, addr_book: Pok3rAddrBook) -> Result<(), NetworkError> {
+        MessagingSystem::update_addr_book(self, addr_book)
+    }
+
+    fn peer_status(&mut self) -> HashMap<u64, PeerStatus> {
+        MessagingSystem::peer_status(self)
+    }
+
+    fn on_peer_down(&mut self) -> mpsc::UnboundedReceiver<u64> {
+        MessagingSystem::on_peer_down(self)
+    }
+}
+
+/// decides, from the node ids of its sender and of a recipient, whether a message
+/// of an InMemoryTransport reaches that recipient
+pub type LinkFilter = Arc<dyn Fn(u64, u64, &EvalNetMsg) -> bool + Send + Sync>;
+
+/// what the party with node id j receives, in order, in place of a message the
+/// party with node id i sent it; nothing drops the message, and several copies
+/// deliver them all, e.g. to replay what i sent earlier
+pub type LinkTamper = Arc<dyn Fn(u64, u64, &EvalNetMsg) -> Vec<EvalNetMsg> + Send + Sync>;
+
+/// where an in-memory relay hands messages over: the bounded queue of a party,
+/// like a networkd would, or the tap of a verifier, which keeps everything, or
+/// of a player, which only gets what is sent to it directly
+enum Relay {
+    Party(mpsc::Sender<EvalNetMsg>),
+    Verifier(mpsc::UnboundedSender<EvalNetMsg>),
+    Player(mpsc::UnboundedSender<EvalNetMsg>),
+}
+
+impl Relay {
+    /// a party whose queue is full does not acknowledge what gets dropped, so
+    /// its sender transmits it again; one that is gone can no longer receive anything
+    fn deliver(&mut self, msg: EvalNetMsg) {
+        match self {
+            Relay::Party(tx) => {
+                let _ = tx.try_send(msg);
+            }
+            Relay::Verifier(tx) | Relay::Player(tx) => {
+                let _ = tx.unbounded_send(msg);
+            }
+        }
+    }
+
+    /// whether msg is for this end at all: players follow no broadcast
+    fn follows(&self, msg: &EvalNetMsg) -> bool {
+        match self {
+            Relay::Player(_) => matches!(
+                msg,
+                EvalNetMsg::DirectValue { .. } | EvalNetMsg::DirectBatchBinary { .. }
+            ),
+            _ => true,
+        }
+    }
+}
+
+/// InMemoryTransport connects parties living in the same process. Each party
+/// runs the regular MessagingSystem, but instead of a networkd its messages are
+/// relayed (through the wire codec) to the other parties over channels.
+pub struct InMemoryTransport {
+    messaging: MessagingSystem,
+}
+
+impl InMemoryTransport {
+    /// returns n connected transports, where the i-th one has node id i + 1
+    pub async fn new_network(n: usize) -> Vec<InMemoryTransport> {
+        Self::new_overlapping_networks(n, &["in-memory"])
+            .await
+            .pop()
+            .unwrap()
+    }
+
+    /// same as new_network, but the link of every party holds each of its messages
+    /// for latency before passing it on, as if they were sent over a slow network
+    pub async fn new_network_with_latency(n: usize, latency: Duration) -> Vec<InMemoryTransport> {
+        Self::build_networks(n, &["in-memory"], 0.0, 0, latency)
+            .await
+            .pop()
+            .unwrap()
+    }
+
+    /// returns n transports for each of the sessions, all sharing one medium, like
+    /// the same peers running several games at once: every message reaches every
+    /// transport of every session, and it is up to the receivers to keep them apart
+    pub async fn new_overlapping_networks(
+        n: usize,
+        sessions: &[&str],
+    ) -> Vec<Vec<InMemoryTransport>> {
+        Self::build_networks(n, sessions, 0.0, 0, Duration::ZERO).await
+    }
+
+    /// same as new_network, but every message towards each party is dropped with
+    /// the given probability; seed makes the drops reproducible
+    pub async fn new_lossy_network(
+        n: usize,
+        drop_probability: f64,
+        seed: u64,
+    ) -> Vec<InMemoryTransport> {
+        Self::build_networks(n, &["in-memory"], drop_probability, seed, Duration::ZERO)
+            .await
+            .pop()
+            .unwrap()
+    }
+
+    /// same as new_network, but a message of the party with node id i reaches the
+    /// one with node id j only if link(i, j, msg) holds, e.g. for a party to get
+    /// a value to some of the others and another value to the rest
+    pub async fn new_network_with_link_filter(
+        n: usize,
+        link: LinkFilter,
+    ) -> Vec<InMemoryTransport> {
+        let tamper: LinkTamper = Arc::new(move |sender, recipient, msg| {
+            if link(sender, recipient, msg) {
+                vec![msg.clone()]
+            } else {
+                vec![]
+            }
+        });
+        Self::new_tampered_network(n, tamper).await
+    }
+
+    /// same as new_network, but what the party with node id i sends the one with
+    /// node id j is replaced by tamper(i, j, msg) on the way; the copies keep the
+    /// signatures of their originals, so tamper can drop, repeat or replay
+    /// messages, but not forge them
+    pub async fn new_tampered_network(n: usize, tamper: LinkTamper) -> Vec<InMemoryTransport> {
+        Self::build_networks_with_taps(
+            n,
+            &["in-memory"],
+            0.0,
+            0,
+            Duration::ZERO,
+            (0, 0),
+            Some(tamper),
+        )
+        .await
+        .0
+        .pop()
+        .unwrap()
+    }
+
+    /// same as new_network, with v verifiers listed in the addr book as well;
+    /// returns the n transports of the players, and the channel on which each
+    /// verifier receives what the players broadcast
+    pub async fn new_network_with_verifiers(
+        n: usize,
+        v: usize,
+    ) -> (
+        Vec<InMemoryTransport>,
+        Vec<mpsc::UnboundedReceiver<EvalNetMsg>>,
+    ) {
+        let (transports, _, verifiers) = Self::new_network_with_roles(n, 0, v).await;
+        (transports, verifiers)
+    }
+
+    /// same as new_network_with_verifiers, with p players listed in the addr book
+    /// as well, numbered after the committee; returns the n transports of the
+    /// committee, the channel on which each player receives what is sent to it
+    /// directly, and the channel of each verifier
+    pub async fn new_network_with_roles(
+        n: usize,
+        p: usize,
+        v: usize,
+    ) -> (
+        Vec<InMemoryTransport>,
+        Vec<mpsc::UnboundedReceiver<EvalNetMsg>>,
+        Vec<mpsc::UnboundedReceiver<EvalNetMsg>>,
+    ) {
+        let (mut networks, players, verifiers) =
+            Self::build_networks_with_taps(n, &["in-memory"], 0.0, 0, Duration::ZERO, (p, v), None)
+                .await;
+        (networks.pop().unwrap(), players, verifiers)
+    }
+
+    async fn build_networks(
+        n: usize,
+        sessions: &[&str],
+        drop_probability: f64,
+        seed: u64,
+        latency: Duration,
+    ) -> Vec<Vec<InMemoryTransport>> {
+        Self::build_networks_with_taps(n, sessions, drop_probability, seed, latency, (0, 0), None)
+            .await
+            .0
+    }
+
+    /// taps holds the number of players and of verifiers, which follow the first
+    /// session only
+    async fn build_networks_with_taps(
+        n: usize,
+        sessions: &[&str],
+        drop_probability: f64,
+        seed: u64,
+        latency: Duration,
+        taps: (usize, usize),
+        tamper: Option<LinkTamper>,
+    ) -> (
+        Vec<Vec<InMemoryTransport>>,
+        Vec<mpsc::UnboundedReceiver<EvalNetMsg>>,
+        Vec<mpsc::UnboundedReceiver<EvalNetMsg>>,
+    ) {
+        let (players, verifiers) = taps;
+        assert!(
+            n + players + verifiers < 256,
+            "in-memory parties are derived from u8 seeds"
+        );
+        // party i uses the keypair of seed i + 1, player j the one of seed n + j + 1,
+        // and verifier j the one of seed n + players + j + 1
+        let keypairs: Vec<identity::Keypair> =
+            (0..n).map(|i| keypair_from_seed(i as u8 + 1)).collect();
+        let peer_ids: Vec<Pok3rPeerId> = keypairs
+            .iter()
+            .map(|keypair| PeerId::from(keypair.public()).to_base58())
+            .collect();
+        let mut addr_book = AddrBook::from_peer_ids(peer_ids.clone()).unwrap();
+        let mut tap_txs = vec![];
+        let mut player_rxs = vec![];
+        let mut verifier_rxs = vec![];
+        for j in 0..players + verifiers {
+            let keypair = keypair_from_seed((n + j) as u8 + 1);
+            let peer_id = PeerId::from(keypair.public()).to_base58();
+            let (tx, rx) = mpsc::unbounded::<EvalNetMsg>();
+            if j < players {
+                addr_book.add_player(peer_id.clone(), None);
+                tap_txs.push((peer_id, PeerRole::Player, tx));
+                player_rxs.push(rx);
+            } else {
+                addr_book.add_verifier(peer_id.clone(), None);
+                tap_txs.push((peer_id, PeerRole::Verifier, tx));
+                verifier_rxs.push(rx);
+            }
+        }
+
+        let endpoints = n * sessions.len();
+        let mut n2e_txs = vec![];
+        let mut n2e_rxs = vec![];
+        let mut e2n_txs = vec![];
+        let mut e2n_rxs = vec![];
+        for _ in 0..endpoints {
+            let (mut n2e_tx, n2e_rx) = mpsc::channel::<EvalNetMsg>(DEFAULT_QUEUE_CAPACITY);
+            let (e2n_tx, e2n_rx) = mpsc::channel::<EvalNetMsg>(DEFAULT_QUEUE_CAPACITY);
+            n2e_tx
+                .try_send(EvalNetMsg::ConnectionEstablished {
+                    success: true,
+                    sender: None,
+                    binary_payloads: false,
+                    session: String::new(),
+                    encoding_tag: 0,
+                    info: None,
+                    waiting: false,
+                    signature: String::new(),
+                })
+                .unwrap();
+            n2e_txs.push(n2e_tx);
+            n2e_rxs.push(n2e_rx);
+            e2n_txs.push(e2n_tx);
+            e2n_rxs.push(e2n_rx);
+        }
+
+        // one relay per endpoint, playing the role of its networkd;
+        // endpoint k is party k % n of session k / n
+        for (k, mut e2n_rx) in e2n_rxs.into_iter().enumerate() {
+            // with the node id of every party; players and verifiers have none
+            let mut peers: Vec<(String, Option<u64>, Relay)> = n2e_txs
+                .iter()
+                .enumerate()
+                .filter(|(j, _)| *j != k)
+                .map(|(j, tx)| {
+                    (
+                        peer_ids[j % n].clone(),
+                        Some((j % n) as u64 + 1),
+                        Relay::Party(tx.clone()),
+                    )
+                })
+                .collect();
+            if k < n {
+                peers.extend(tap_txs.iter().map(|(peer_id, role, tx)| {
+                    let relay = match role {
+                        PeerRole::Player => Relay::Player(tx.clone()),
+                        _ => Relay::Verifier(tx.clone()),
+                    };
+                    (peer_id.clone(), None, relay)
+                }));
+            }
+            let mut rng = StdRng::seed_from_u64(seed.wrapping_add(k as u64));
+            let sender_id = (k % n) as u64 + 1;
+            let tamper = tamper.clone();
+            async_std::task::spawn(async move {
+                while let Some(msg) = e2n_rx.next().await {
+                    if !latency.is_zero() {
+                        async_std::task::sleep(latency).await;
+                    }
+                    // what cannot be encoded never gets out, as on the network
+                    let data = match encode_wire_msg(&msg) {
+                        Ok(data) => data,
+                        Err(_) => continue,
+                    };
+                    for (peer_id, node_id, relay) in peers.iter_mut() {
+                        if !relay.follows(&msg) {
+                            continue;
+                        }
+                        // direct values, acks and answers to heartbeats only go
+                        // to their recipient
+                        if let EvalNetMsg::DirectValue { recipient, .. }
+                        | EvalNetMsg::DirectBatchBinary { recipient, .. }
+                        | EvalNetMsg::Ack { recipient, .. }
+                        | EvalNetMsg::Heartbeat {
+                            recipient: Some(recipient),
+                            ..
+                        } = &msg
+                        {
+                            if !recipient.eq(peer_id) {
+                                continue;
+                            }
+                        }
+                        if rng.gen_bool(drop_probability) {
+                            continue;
+                        }
+                        let received = decode_wire_msg(&data).unwrap();
+                        let received = match (&tamper, node_id) {
+                            (Some(tamper), Some(node_id)) => tamper(sender_id, *node_id, &received),
+                            _ => vec![received],
+                        };
+                        for msg in received {
+                            relay.deliver(msg);
+                        }
+                    }
+                }
+            });
+        }
+
+        let mut networks: Vec<Vec<InMemoryTransport>> = vec![];
+        for (k, (e2n_tx, n2e_rx)) in e2n_txs.into_iter().zip(n2e_rxs).enumerate() {
+            if k % n == 0 {
+                networks.push(vec![]);
+            }
+            let messaging = MessagingSystem::new(
+                keypairs[k % n].clone(),
+                sessions[k / n],
+                addr_book.clone(),
+                e2n_tx,
+                n2e_rx,
+            )
+            .await
+            .expect("every in-memory party is in the addr book");
+            networks
+                .last_mut()
+                .unwrap()
+                .push(InMemoryTransport { messaging });
+        }
+        (networks, player_rxs, verifier_rxs)
+    }
+
+    /// sets how unacknowledged messages are retransmitted
+    pub fn set_retransmit_config(&mut self, config: RetransmitConfig) {
+        self.messaging.set_retransmit_config(config);
+    }
+
+    /// sets how much is kept for retransmission and for each peer
+    pub fn set_queue_config(&mut self, config: QueueConfig) {
+        self.messaging.set_queue_config(config);
+    }
+
+    /// turns on heartbeats, which every party of the network must do
+    pub fn set_heartbeat_config(&mut self, config: HeartbeatConfig) {
+        self.messaging.set_heartbeat_config(config);
+    }
+
+    /// keeps a journal at path, reloading what it holds; see
+    /// MessagingSystem::open_journal
+    pub fn open_journal(&mut self, path: &Path) -> Result<(), JournalError> {
+        self.messaging.open_journal(path)
+    }
+
+    /// the same party after its process crashed and was started again: it keeps
+    /// its links, but nothing it held except what it reloads from a journal
+    pub async fn restart(self) -> InMemoryTransport {
+        let messaging = self
+            .messaging
+            .restart()
+            .await
+            .expect("a restarted party stays in the addr book");
+        InMemoryTransport { messaging }
+    }
+
+    /// how many messages of other sessions this transport has dropped
+    pub fn foreign_session_messages(&self) -> u64 {
+        self.messaging.foreign_session_messages()
+    }
+
+    /// sets how long recv_from_all waits for the other parties before giving up
+    pub fn set_recv_timeout(&mut self, timeout: Duration) {
+        self.messaging.set_recv_timeout(timeout);
+    }
+
+    /// sets how many rounds ahead of ours values are kept until we get there
+    pub fn set_round_horizon(&mut self, horizon: u64) {
+        self.messaging.set_round_horizon(horizon);
+    }
+}
+
+#[async_trait]
+impl Transport for InMemoryTransport {
+    fn get_my_id(&self) -> u64 {
+        self.messaging.get_my_id()
+    }
+
+    fn addr_book(&self) -> &Pok3rAddrBook {
+        &self.messaging.addr_book
+    }
+
+    fn session_id(&self) -> &str {
+        self.messaging.session_id()
+    }
+
+    fn metrics(&self) -> Arc<Metrics> {
+        self.messaging.metrics()
+    }
+
+    fn sign(&self, bytes: &[u8]) -> Vec<u8> {
+        self.messaging.sign_bytes(bytes)
+    }
+
+    async fn send_to_all<H, V>(&mut self, handles: &[H], values: &[V]) -> Result<(), NetworkError>
+    where
+        H: AsRef<str> + Sync,
+        V: AsRef<str> + Sync,
+    {
+        self.messaging.send_to_all(handles, values).await
+    }
+
+    async fn send_to_party<H, V>(
+        &mut self,
+        recipient_id: u64,
+        handles: &[H],
+        values: &[V],
+    ) -> Result<(), NetworkError>
+    where
+        H: AsRef<str> + Sync,
+        V: AsRef<str> + Sync,
+    {
+        self.messaging
+            .send_to_party(recipient_id, handles, values)
+            .await
+    }
+
+    async fn send_to_role<H, V>(
+        &mut self,
+        role: PeerRole,
+        handles: &[H],
+        values: &[V],
+    ) -> Result<(), NetworkError>
+    where
+        H: AsRef<str> + Sync,
+        V: AsRef<str> + Sync,
+    {
+        self.messaging.send_to_role(role, handles, values).await
+    }
+
+    async fn send_to_player<H, V>(
+        &mut self,
+        player: &Pok3rPeerId,
+        handles: &[H],
+        values: &[V],
+    ) -> Result<(), NetworkError>
+    where
+        H: AsRef<str> + Sync,
+        V: AsRef<str> + Sync,
+    {
+        self.messaging.send_to_player(player, handles, values).await
+    }
+
+    async fn send_batch_to_party<H: AsRef<str> + Sync>(
+        &mut self,
+        recipient_id: u64,
+        handles: &[H],
+        kind: PayloadKind,
+        values: &[Vec<u8>],
+    ) -> Result<(), NetworkError> {
+        self.messaging
+            .send_batch_to_party(recipient_id, handles, kind, values)
+            .await
+    }
+
+    async fn send_batch_to_all<H: AsRef<str> + Sync>(
+        &mut self,
+        handles: &[H],
+        kind: PayloadKind,
+        values: &[Vec<u8>],
+    ) -> Result<(), NetworkError> {
+        self.messaging
+            .send_batch_to_all(handles, kind, values)
+            .await
+    }
+
+    async fn recv_from_all(
+        &mut self,
+        identifier: &str,
+    ) -> Result<HashMap<u64, String>, NetworkError> {
+        self.messaging.recv_from_all(identifier).await
+    }
+
+    async fn recv_many_from_all<I: AsRef<str> + Sync>(
+        &mut self,
+        identifiers: &[I],
+    ) -> Result<HashMap<String, HashMap<u64, String>>, NetworkError> {
+        self.messaging.recv_many_from_all(identifiers).await
+    }
+
+    async fn recv_from_all_with_deadline(
+        &mut self,
+        identifier: &str,
+        deadline: Instant,
+    ) -> Result<HashMap<u64, String>, NetworkError> {
+        self.messaging
+            .recv_from_all_with_deadline(identifier, deadline)
+            .await
+    }
+
+    async fn recv_from_some(
+        &mut self,
+        identifier: &str,
+        count: usize,
+    ) -> Result<HashMap<u64, String>, NetworkError> {
+        self.messaging.recv_from_some(identifier, count).await
+    }
+
+    async fn recv_quorum(
+        &mut self,
+        identifier: &str,
+        quorum: usize,
+    ) -> Result<HashMap<u64, String>, NetworkError> {
+        self.messaging.recv_quorum(identifier, quorum).await
+    }
+
+    fn take_late_values(&mut self) -> Vec<(String, u64, String)> {
+        self.messaging.take_late_values()
+    }
+
+    async fn wait_for_late_values(&mut self, deadline: Instant) -> Result<(), NetworkError> {
+        self.messaging.wait_for_late_values(deadline).await
+    }
+
+    async fn recv_from_party(
+        &mut self,
+        sender_id: u64,
+        handle: &str,
+    ) -> Result<String, NetworkError> {
+        self.messaging.recv_from_party(sender_id, handle).await
+    }
+
+    async fn flush(&mut self) -> Result<(), NetworkError> {
+        self.messaging.flush().await
+    }
+
+    fn set_round(&mut self, round: u64) {
+        self.messaging.set_round(round)
+    }
+
+    async fn handshake(&mut self, info: &PeerInfo) -> Result<(), NetworkError> {
+        self.messaging.handshake(info).await
+    }
+
+    async fn broadcast_abort(&mut self, reason: &str) {
+        self.messaging.broadcast_abort(reason).await
+    }
+
+    fn update_addr_book(&mut self, addr_book: Pok3rAddrBook) -> Result<(), NetworkError> {
+        self.messaging.update_addr_book(addr_book)
+    }
+
+    fn peer_status(&mut self) -> HashMap<u64, PeerStatus> {
+        self.messaging.peer_status()
+    }
+
+    fn on_peer_down(&mut self) -> mpsc::UnboundedReceiver<u64> {
+        self.messaging.on_peer_down()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{InMemoryTransport, Transport};
+    use crate::common::{EvalNetMsg, PayloadKind, F};
+    use crate::encoding::encode_as_bytes;
+    use crate::network::{NetworkError, QueueConfig};
+    use futures::future::join_all;
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_silent_peer_times_out() {
+        async_std::task::block_on(async {
+            let mut transports = InMemoryTransport::new_network(4).await;
+            let handle = String::from("silent");
+
+            // party 3 never sends its value
+            for transport in transports.iter_mut() {
+                if transport.get_my_id() != 3 {
+                    let value = format!("{}", transport.get_my_id());
+                    transport
+                        .send_to_all(std::slice::from_ref(&handle), &[value])
+                        .await
+                        .unwrap();
+                }
+            }
+
+            let deadline = Instant::now() + Duration::from_millis(200);
+            let results = join_all(
+                transports
+                    .iter_mut()
+                    .filter(|t| t.get_my_id() != 3)
+                    .map(|t| t.recv_from_all_with_deadline(&handle, deadline)),
+            )
+            .await;
+
+            for result in results {
+                assert_eq!(
+                    result,
+                    Err(NetworkError::Timeout {
+                        handle: handle.clone(),
+                        missing_senders: vec![3],
+                    })
+                );
+            }
+
+            // party 3 itself hears from everyone
+            transports[2].set_recv_timeout(Duration::from_millis(200));
+            let received = transports[2].recv_from_all(&handle).await.unwrap();
+            assert_eq!(received.len(), 3);
+        });
+    }
+
+    #[test]
+    fn test_direct_value_reaches_only_recipient() {
+        async_std::task::block_on(async {
+            let mut transports = InMemoryTransport::new_network(4).await;
+            let handle = String::from("hole_card");
+
+            transports[0]
+                .send_to_party(3, std::slice::from_ref(&handle), &[String::from("secret")])
+                .await
+                .unwrap();
+
+            transports[1].set_recv_timeout(Duration::from_millis(200));
+            assert_eq!(
+                transports[1].recv_from_party(1, &handle).await,
+                Err(NetworkError::Timeout {
+                    handle: handle.clone(),
+                    missing_senders: vec![1],
+                })
+            );
+
+            // the direct value must not show up as a broadcast either
+            let deadline = Instant::now() + Duration::from_millis(200);
+            assert!(transports[1]
+                .recv_from_all_with_deadline(&handle, deadline)
+                .await
+                .is_err());
+
+            let value = transports[2].recv_from_party(1, &handle).await.unwrap();
+            assert_eq!(value, "secret");
+        });
+    }
+
+    #[test]
+    fn test_direct_binary_batch_reaches_only_recipient() {
+        async_std::task::block_on(async {
+            let mut transports = InMemoryTransport::new_network(3).await;
+            let handles: Vec<String> = (0..3).map(|i| format!("share-{}", i)).collect();
+            let values: Vec<Vec<u8>> = (0..3u64).map(|i| encode_as_bytes(&F::from(i))).collect();
+
+            transports[0]
+                .send_batch_to_party(3, &handles, PayloadKind::Scalar, &values)
+                .await
+                .unwrap();
+
+            transports[1].set_recv_timeout(Duration::from_millis(200));
+            assert!(transports[1].recv_from_party(1, &handles[0]).await.is_err());
+
+            for (handle, value) in handles.iter().zip(values.iter()) {
+                let received = transports[2].recv_from_party(1, handle).await.unwrap();
+                assert_eq!(received, bs58::encode(value).into_string());
+            }
+        });
+    }
+
+    #[test]
+    fn test_overlapping_sessions_stay_apart() {
+        async_std::task::block_on(async {
+            let mut networks = InMemoryTransport::new_overlapping_networks(3, &["a", "b"]).await;
+            // both games use the same handle, with different values
+            let handle = String::from("x");
+
+            for (s, network) in networks.iter_mut().enumerate() {
+                for transport in network.iter_mut() {
+                    let value = format!("{}-{}", s, transport.get_my_id());
+                    transport
+                        .send_to_all(std::slice::from_ref(&handle), &[value])
+                        .await
+                        .unwrap();
+                }
+            }
+
+            for (s, network) in networks.iter_mut().enumerate() {
+                for transport in network.iter_mut() {
+                    let received = transport.recv_from_all(&handle).await.unwrap();
+                    assert_eq!(received.len(), 2);
+                    for (id, value) in received {
+                        assert_eq!(value, format!("{}-{}", s, id));
+                    }
+                }
+            }
+
+            // once everything in flight is processed, every party has
+            // dropped at least the 3 values of the other session
+            let deadline = Instant::now() + Duration::from_millis(200);
+            for transport in networks.iter_mut().flatten() {
+                let drain = String::from("drain");
+                assert!(transport
+                    .recv_from_all_with_deadline(&drain, deadline)
+                    .await
+                    .is_err());
+                assert!(transport.foreign_session_messages() >= 3);
+            }
+        });
+    }
+
+    #[test]
+    fn test_recv_many_from_all() {
+        async_std::task::block_on(async {
+            let mut transports = InMemoryTransport::new_network(3).await;
+            let handles = (0..5).map(|i| format!("h{}", i)).collect::<Vec<String>>();
+
+            for transport in transports.iter_mut() {
+                let values = handles
+                    .iter()
+                    .map(|h| format!("{}-{}", h, transport.get_my_id()))
+                    .collect::<Vec<String>>();
+                transport.send_to_all(&handles, &values).await.unwrap();
+            }
+
+            for transport in transports.iter_mut() {
+                let received = transport.recv_many_from_all(&handles).await.unwrap();
+                assert_eq!(received.len(), handles.len());
+                for handle in handles.iter() {
+                    let values = &received[handle];
+                    assert_eq!(values.len(), 2);
+                    for (id, value) in values {
+                        assert_ne!(*id, transport.get_my_id());
+                        assert_eq!(*value, format!("{}-{}", handle, id));
+                    }
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn test_consistent_broadcast_agrees() {
+        async_std::task::block_on(async {
+            let transports = InMemoryTransport::new_network(4).await;
+            let results = join_all(transports.into_iter().map(|mut transport| async move {
+                let value = format!("{}", transport.get_my_id());
+                let received = transport.broadcast_consistent("c", &value).await;
+                (transport.get_my_id(), received.unwrap())
+            }))
+            .await;
+
+            for (my_id, received) in results {
+                assert_eq!(received.len(), 3);
+                for (id, value) in received {
+                    assert_ne!(id, my_id);
+                    assert_eq!(value, format!("{}", id));
+                }
+            }
+        });
+    }
+
+    #[test]
+    fn test_consistent_broadcast_detects_equivocation() {
+        // party 4 gets "b" to party 1 alone, and "a" to the others
+        let link = Arc::new(|sender: u64, recipient: u64, msg: &EvalNetMsg| match msg {
+            EvalNetMsg::PublishValue { handle, value, .. } if sender == 4 && handle == "c" => {
+                (recipient == 1) == (value == "b")
+            }
+            _ => true,
+        });
+        let results = async_std::task::block_on(async {
+            let transports = InMemoryTransport::new_network_with_link_filter(4, link).await;
+            join_all(transports.into_iter().map(|mut transport| async move {
+                let handle = String::from("c");
+                if transport.get_my_id() != 4 {
+                    return transport.broadcast_consistent(&handle, "x").await;
+                }
+                transport.send_to_all(&[&handle], &["a"]).await?;
+                transport.send_to_all(&[&handle], &["b"]).await?;
+                let received = transport.recv_from_all(&handle).await?;
+                let received = [(handle.clone(), received)].into_iter().collect();
+                transport.echo_received(&[&handle], &received).await?;
+                Ok(received[&handle].clone())
+            }))
+            .await
+        });
+
+        // every honest party blames party 4, and sees the same digests
+        let errors = results[..3]
+            .iter()
+            .map(|result| match result {
+                Err(NetworkError::Equivocation {
+                    sender,
+                    conflicting_digests,
+                }) => (*sender, conflicting_digests.clone()),
+                other => panic!("expected an equivocation, got {:?}", other),
+            })
+            .collect::<Vec<_>>();
+        for (sender, digests) in errors.iter() {
+            assert_eq!(*sender, 4);
+            assert_eq!(
+                digests.iter().map(|(id, _)| *id).collect::<Vec<u64>>(),
+                vec![1, 2, 3]
+            );
+            assert_ne!(digests[0].1, digests[1].1);
+            assert_eq!(digests[1].1, digests[2].1);
+            assert_eq!(*digests, errors[0].1);
+        }
+    }
+
+    #[test]
+    fn test_slow_consumer_keeps_memory_bounded() {
+        const MESSAGES: usize = 100_000;
+        const WINDOW: usize = 64;
+
+        async_std::task::block_on(async {
+            let mut transports = InMemoryTransport::new_network(2).await;
+            for transport in transports.iter_mut() {
+                transport.set_queue_config(QueueConfig {
+                    max_unacked: WINDOW,
+                    ..QueueConfig::default()
+                });
+            }
+            let mut consumer = transports.pop().unwrap();
+            let mut producer = transports.pop().unwrap();
+
+            let produce = async move {
+                for i in 0..MESSAGES {
+                    producer
+                        .send_to_all(&[format!("m{}", i)], &[i.to_string()])
+                        .await
+                        .unwrap();
+                }
+                producer
+            };
+            let consume = async move {
+                for i in 0..MESSAGES {
+                    let received = consumer.recv_from_all(&format!("m{}", i)).await.unwrap();
+                    assert_eq!(received[&1], i.to_string());
+                    if i % 1000 == 0 {
+                        async_std::task::sleep(Duration::from_millis(5)).await;
+                    }
+                }
+                consumer
+            };
+            let (_producer, consumer) = futures::join!(produce, consume);
+
+            // the producer waited for acks instead of getting ahead of the consumer
+            let metrics = consumer.metrics().snapshot();
+            assert!(metrics.pending_values_high_water <= WINDOW as u64);
+            assert_eq!(metrics.messages_overflowed, 0);
+        });
+    }
+}
@@ -1,15 +1,22 @@
 #![allow(dead_code)]
 
 use ark_crypto_primitives::crh::sha256::Sha256;
+use ark_crypto_primitives::sponge::{
+    poseidon::{find_poseidon_ark_and_mds, PoseidonConfig, PoseidonSponge},
+    CryptographicSponge,
+};
 use ark_ff::field_hashers::{DefaultFieldHasher, HashToField};
-use ark_ff::Field;
+use ark_ff::{FftField, Field, PrimeField};
 use ark_poly::{
     univariate::DensePolynomial, EvaluationDomain, Evaluations, GeneralEvaluationDomain,
     Polynomial, Radix2EvaluationDomain,
 };
-use ark_std::{ops::Sub, UniformRand};
+use rand::Rng;
+use std::any::{Any, TypeId};
+use std::sync::Mutex;
 
-use crate::common::F;
+use crate::common::{Curve, CurveConfig, F};
+use crate::secret::zeroize_f;
 
 macro_rules! requires_power_of_2 {
     ($x:expr) => {
@@ -17,36 +24,166 @@ macro_rules! requires_power_of_2 {
     };
 }
 
+/// values computed on first use for a type and a size; they are leaked, as only a
+/// handful are ever made
+type Cache = Mutex<Vec<(TypeId, usize, &'static (dyn Any + Send + Sync))>>;
+
+fn cached<K: Any, T: Any + Send + Sync>(
+    cache: &Cache,
+    size: usize,
+    make: impl FnOnce() -> T,
+) -> &'static T {
+    let key = TypeId::of::<K>();
+    let mut entries = cache.lock().unwrap();
+    if let Some((_, _, value)) = entries.iter().find(|(k, n, _)| *k == key && *n == size) {
+        return value.downcast_ref().unwrap();
+    }
+
+    let value: &'static T = Box::leak(Box::new(make()));
+    entries.push((key, size, value));
+    value
+}
+
+/// a multiplicative subgroup with its evaluation domain and all of its elements
+pub struct DomainInfo<F: FftField = crate::common::F> {
+    pub generator: F,
+    pub domain: Radix2EvaluationDomain<F>,
+    /// generator^i for i in 0..size
+    pub powers: Vec<F>,
+}
+
+static DOMAINS: Cache = Mutex::new(Vec::new());
+
+/// returns the subgroup of input size n, computing it on first use
+pub fn domain_info(n: usize) -> &'static DomainInfo {
+    domain_info_of::<F>(n)
+}
+
+/// domain_info in the field F rather than in the scalar field of Curve
+pub fn domain_info_of<F: FftField>(n: usize) -> &'static DomainInfo<F> {
+    requires_power_of_2!(n);
+    cached::<F, _>(&DOMAINS, n, || {
+        let domain = Radix2EvaluationDomain::<F>::new(n).unwrap();
+        DomainInfo {
+            generator: domain.group_gen,
+            domain,
+            powers: domain.elements().collect(),
+        }
+    })
+}
+
 /// returns a generator of the multiplicative subgroup of input size n
 pub fn multiplicative_subgroup_of_size(n: u64) -> F {
-    requires_power_of_2!(n);
-    let domain = Radix2EvaluationDomain::<F>::new(n as usize).unwrap();
-    domain.group_gen
+    domain_info(n as usize).generator
 }
 
 /// returns lagrange basis polynomial for index i
-pub fn compute_lagrange_basis(i: u64, n: u64) -> DensePolynomial<F> {
+pub fn compute_lagrange_basis<F: FftField>(i: u64, n: u64) -> DensePolynomial<F> {
     let mut evals = vec![];
     for j in 0..n {
         if i == j {
-            evals.push(F::from(1));
+            evals.push(F::from(1u64));
         } else {
-            evals.push(F::from(0));
+            evals.push(F::from(0u64));
         }
     }
     self::interpolate_poly_over_mult_subgroup(&evals)
 }
 
+/// returns L_i(z) for all i in 0..n, the lagrange basis of the subgroup of size n,
+/// in O(n) by the barycentric formula L_i(z) = ω^i (z^n - 1) / (n (z - ω^i))
+pub fn lagrange_basis_at<F: FftField>(z: F, n: u64) -> Vec<F> {
+    let info = domain_info_of::<F>(n as usize);
+
+    let z_n_minus_1 = z.pow([n]) - F::one();
+    if z_n_minus_1.is_zero() {
+        // z is in the domain, where the basis is an indicator
+        return info.powers.iter().map(|w| F::from(*w == z)).collect();
+    }
+
+    let mut denominators: Vec<F> = info.powers.iter().map(|w| z - w).collect();
+    batch_invert(&mut denominators).unwrap();
+
+    let scale = z_n_minus_1 * info.domain.size_inv;
+    info.powers
+        .iter()
+        .zip(denominators)
+        .map(|(w, den_inv)| *w * scale * den_inv)
+        .collect()
+}
+
+/// evaluates f at the n elements of the subgroup of size n, in order, with an FFT
+pub fn evaluate_over_domain<F: FftField>(f: &DensePolynomial<F>, n: u64) -> Vec<F> {
+    // X^n = 1 on the domain, so higher coefficients fold onto lower ones
+    let mut coeffs = vec![F::zero(); n as usize];
+    for (i, coeff) in f.coeffs.iter().enumerate() {
+        coeffs[i % n as usize] += coeff;
+    }
+    domain_info_of::<F>(n as usize).domain.fft(&coeffs)
+}
+
+/// evaluates the polynomial with the given coefficients at shift * ω^i, where ω generates
+/// the subgroup of size coeffs.len() rounded up to a power of 2
+pub fn coset_fft<F: FftField>(coeffs: &[F], shift: F) -> Vec<F> {
+    let m = coeffs.len().next_power_of_two();
+    let mut shifted = Vec::with_capacity(m);
+    let mut shift_pow = F::one();
+    for coeff in coeffs.iter() {
+        shifted.push(*coeff * shift_pow);
+        shift_pow *= shift;
+    }
+    domain_info_of::<F>(m).domain.fft(&shifted)
+}
+
+/// inverse of coset_fft: the coefficients of the polynomial taking evals at shift * ω^i
+pub fn coset_ifft<F: FftField>(evals: &[F], shift: F) -> Vec<F> {
+    let mut coeffs = domain_info_of::<F>(evals.len()).domain.ifft(evals);
+    let shift_inv = shift.inverse().unwrap();
+    let mut shift_pow = F::one();
+    for coeff in coeffs.iter_mut() {
+        *coeff *= shift_pow;
+        shift_pow *= shift_inv;
+    }
+    coeffs
+}
+
+/// divides evaluations at shift * ω^i (as from coset_fft) by X^n - 1, which does not
+/// vanish on the coset as long as shift^n is not an m-th root of unity
+pub fn divide_by_vanishing_on_coset<F: FftField>(
+    numerator_evals_on_coset: &[F],
+    n: u64,
+    shift: F,
+) -> Vec<F> {
+    let info = domain_info_of::<F>(numerator_evals_on_coset.len());
+    let mut denominators: Vec<F> = info
+        .powers
+        .iter()
+        .map(|w| vanishing_poly_eval(n, shift * w))
+        .collect();
+    batch_invert(&mut denominators).expect("X^n - 1 vanishes on the coset");
+
+    numerator_evals_on_coset
+        .iter()
+        .zip(denominators)
+        .map(|(num, den_inv)| *num * den_inv)
+        .collect()
+}
+
+/// returns z^n - 1, the vanishing polynomial of the subgroup of size n at z
+pub fn vanishing_poly_eval<F: Field>(n: u64, z: F) -> F {
+    z.pow([n]) - F::one()
+}
+
 /// returns t(X) = X^n - 1
-pub fn compute_vanishing_poly(n: usize) -> DensePolynomial<F> {
+pub fn compute_vanishing_poly<F: Field>(n: usize) -> DensePolynomial<F> {
     let mut coeffs = vec![];
     for i in 0..n + 1 {
         if i == 0 {
-            coeffs.push(F::from(0) - F::from(1)); // -1
+            coeffs.push(F::from(0u64) - F::from(1u64)); // -1
         } else if i == n {
-            coeffs.push(F::from(1)); // X^n
+            coeffs.push(F::from(1u64)); // X^n
         } else {
-            coeffs.push(F::from(0));
+            coeffs.push(F::from(0u64));
         }
     }
     DensePolynomial { coeffs }
@@ -55,48 +192,143 @@ pub fn compute_vanishing_poly(n: usize) -> DensePolynomial<F> {
 /// interpolate polynomial which evaluates to points in v
 /// the domain is the powers of n-th root of unity, where n is size of v
 /// assumes n is a power of 2
-pub fn interpolate_poly_over_mult_subgroup(v: &Vec<F>) -> DensePolynomial<F> {
+pub fn interpolate_poly_over_mult_subgroup<F: FftField>(v: &[F]) -> DensePolynomial<F> {
     let n = v.len();
-    let mut evals = vec![];
-    for i in 0..n {
-        evals.push(v[i]);
-    }
-
     let domain = GeneralEvaluationDomain::<F>::new(n).unwrap();
-    let eval_form = Evaluations::from_vec_and_domain(evals, domain);
+    let eval_form = Evaluations::from_vec_and_domain(v.to_vec(), domain);
     eval_form.interpolate()
 }
 
-pub fn compute_additive_shares(value: &F, num_shares: usize) -> Vec<F> {
-    let mut sum = F::from(0);
+/// num_shares values that add up to value, all but the last drawn from rng
+pub fn compute_additive_shares<F: Field, R: Rng>(
+    value: &F,
+    num_shares: usize,
+    rng: &mut R,
+) -> Vec<F> {
+    let mut sum = F::from(0u64);
     let mut shares = vec![];
     for _ in 1..num_shares {
-        let r = F::rand(&mut rand::thread_rng());
+        let r = F::rand(rng);
         //let r_bs58 = bs58::encode(utils::field_to_bytes(&r)).into_string();
         shares.push(r);
         sum += r;
     }
     shares.push(value.sub(&sum));
+    zeroize_f(&mut sum);
 
     shares
 }
 
-pub fn compute_root(x: &F) -> F {
-    x.sqrt().unwrap()
+/// inverts every value in place with a single field inversion (Montgomery's trick);
+/// if a value is zero, returns the index of the first one and leaves values as they were
+pub fn batch_invert<F: Field>(values: &mut [F]) -> Result<(), usize> {
+    if let Some(index) = values.iter().position(|v| v.is_zero()) {
+        return Err(index);
+    }
+
+    // prefix[i] is the product of the values before i
+    let mut prefix = Vec::with_capacity(values.len());
+    let mut product = F::one();
+    for v in values.iter() {
+        prefix.push(product);
+        product *= v;
+    }
+
+    // walking backwards, inv is the inverse of the product of the values up to i
+    let mut inv = product.inverse().unwrap();
+    for (v, p) in values.iter_mut().zip(prefix).rev() {
+        let next = inv * *v;
+        *v = inv * p;
+        inv = next;
+    }
+    Ok(())
+}
+
+/// returns the smaller of the two square roots of x, so that every party picks
+/// the same one, or None if x is not a quadratic residue
+pub fn compute_root<F: PrimeField>(x: &F) -> Option<F> {
+    x.sqrt().map(|r| std::cmp::min(r, -r))
 }
 
-pub fn compute_power(x: &F, n: u64) -> F {
+pub fn compute_power<F: Field>(x: &F, n: u64) -> F {
     x.pow([n])
 }
 
-pub fn fs_hash(x: Vec<&[u8]>, num_output: usize) -> Vec<F> {
+pub fn fs_hash<F: PrimeField>(x: Vec<&[u8]>, num_output: usize) -> Vec<F> {
     let hasher = <DefaultFieldHasher<Sha256> as HashToField<F>>::new(b"pok3r");
 
     hasher.hash_to_field(&x.concat(), num_output)
 }
 
+// Poseidon over the scalar field with width 3 (rate 2, capacity 1)
+pub const POSEIDON_ALPHA: u64 = <Curve as CurveConfig>::POSEIDON_ALPHA;
+pub const POSEIDON_PARTIAL_ROUNDS: usize = <Curve as CurveConfig>::POSEIDON_PARTIAL_ROUNDS;
+pub const POSEIDON_FULL_ROUNDS: usize = 8;
+pub const POSEIDON_RATE: usize = 2;
+
+static POSEIDON_CONFIGS: Cache = Mutex::new(Vec::new());
+
+/// returns the Poseidon parameters, deriving the round constants and the MDS
+/// matrix from the Grain LFSR of the Poseidon paper on first use
+pub fn poseidon_config() -> &'static PoseidonConfig<F> {
+    poseidon_config_on::<Curve>()
+}
+
+/// poseidon_config over the scalar field of E
+pub fn poseidon_config_on<E: CurveConfig>() -> &'static PoseidonConfig<E::ScalarField> {
+    cached::<E, _>(&POSEIDON_CONFIGS, POSEIDON_RATE, || {
+        let (ark, mds) = find_poseidon_ark_and_mds::<E::ScalarField>(
+            E::ScalarField::MODULUS_BIT_SIZE as u64,
+            POSEIDON_RATE,
+            POSEIDON_FULL_ROUNDS as u64,
+            E::POSEIDON_PARTIAL_ROUNDS as u64,
+            0,
+        );
+        PoseidonConfig::new(
+            POSEIDON_FULL_ROUNDS,
+            E::POSEIDON_PARTIAL_ROUNDS,
+            E::POSEIDON_ALPHA,
+            mds,
+            ark,
+            POSEIDON_RATE,
+            1,
+        )
+    })
+}
+
+/// same as fs_hash but with the Poseidon sponge, which is cheap to verify inside
+/// a SNARK; the number of inputs is absorbed first so that inputs of different
+/// lengths never collide
+pub fn fs_hash_poseidon(inputs: &[F], num_output: usize) -> Vec<F> {
+    fs_hash_poseidon_on::<Curve>(inputs, num_output)
+}
+
+/// fs_hash_poseidon over the scalar field of E
+pub fn fs_hash_poseidon_on<E: CurveConfig>(
+    inputs: &[E::ScalarField],
+    num_output: usize,
+) -> Vec<E::ScalarField> {
+    let mut sponge = PoseidonSponge::new(poseidon_config_on::<E>());
+    sponge.absorb(&E::ScalarField::from_le_bytes_mod_order(b"pok3r"));
+    sponge.absorb(&E::ScalarField::from(inputs.len() as u64));
+    sponge.absorb(&inputs.to_vec());
+    sponge.squeeze_field_elements::<E::ScalarField>(num_output)
+}
+
+/// packs bytes into field elements, as many bytes per element as always fit,
+/// followed by the number of bytes
+pub fn bytes_to_fields<F: PrimeField>(bytes: &[u8]) -> Vec<F> {
+    let chunk_size = (F::MODULUS_BIT_SIZE as usize - 1) / 8;
+    let mut fields = bytes
+        .chunks(chunk_size)
+        .map(F::from_le_bytes_mod_order)
+        .collect::<Vec<F>>();
+    fields.push(F::from(bytes.len() as u64));
+    fields
+}
+
 //computes f(x/ω)
-pub fn poly_domain_div_ω(f: &DensePolynomial<F>, ω: &F) -> DensePolynomial<F> {
+pub fn poly_domain_div_ω<F: Field>(f: &DensePolynomial<F>, ω: &F) -> DensePolynomial<F> {
     let mut new_poly = f.clone();
     for i in 1..(f.degree() + 1) {
         //we don't touch the zeroth coefficient
@@ -108,9 +340,20 @@ pub fn poly_domain_div_ω(f: &DensePolynomial<F>, ω: &F) -> DensePolynomial<F>
 
 #[cfg(test)]
 mod tests {
-    use super::multiplicative_subgroup_of_size;
+    use super::{
+        batch_invert, bytes_to_fields, compute_lagrange_basis, compute_power, compute_root,
+        compute_vanishing_poly, coset_fft, coset_ifft, divide_by_vanishing_on_coset, domain_info,
+        evaluate_over_domain, fs_hash_poseidon, lagrange_basis_at, multiplicative_subgroup_of_size,
+        poseidon_config, vanishing_poly_eval, POSEIDON_ALPHA, POSEIDON_FULL_ROUNDS,
+        POSEIDON_PARTIAL_ROUNDS, POSEIDON_RATE,
+    };
     use crate::common::F;
-    use ark_ff::Field;
+    use ark_ff::{FftField, Field, PrimeField, Zero};
+    use ark_poly::{univariate::DensePolynomial, DenseUVPolynomial, Polynomial};
+    use ark_poly::{EvaluationDomain, GeneralEvaluationDomain};
+    use ark_std::UniformRand;
+    use num_bigint::BigUint;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
 
     #[test]
     fn test_multiplicative_subgroup_of_size() {
@@ -131,4 +374,168 @@ mod tests {
             assert_ne!(ω_pow_i, one);
         }
     }
+
+    #[test]
+    fn test_batch_invert() {
+        let mut rng = StdRng::from_seed([11u8; 32]);
+        for size in [1, 2, 3, 17, 256, 4096] {
+            let values: Vec<F> = (0..size).map(|_| F::rand(&mut rng)).collect();
+            let mut inverted = values.clone();
+            batch_invert(&mut inverted).unwrap();
+            for (v, inv) in values.iter().zip(inverted.iter()) {
+                assert_eq!(*inv, v.inverse().unwrap());
+            }
+
+            // zeros are reported by their first index, and nothing is inverted
+            let mut with_zeros = values.clone();
+            let first = rng.gen_range(0..size);
+            with_zeros[first] = F::zero();
+            with_zeros[rng.gen_range(first..size)] = F::zero();
+            let before = with_zeros.clone();
+            assert_eq!(batch_invert(&mut with_zeros), Err(first));
+            assert_eq!(with_zeros, before);
+        }
+    }
+
+    #[test]
+    fn test_compute_root() {
+        let mut rng = StdRng::from_seed([13u8; 32]);
+        assert_eq!(compute_root(&F::zero()), Some(F::zero()));
+
+        for _ in 0..32 {
+            let x = F::rand(&mut rng);
+            let root = compute_root(&(x * x)).unwrap();
+            assert_eq!(root * root, x * x);
+            assert!(root == x || root == -x);
+            // the choice does not depend on which root we started from
+            assert_eq!(root, compute_root(&(-x * -x)).unwrap());
+            assert!(root <= -root);
+        }
+
+        // a generator of the multiplicative group is never a square
+        let non_residue = F::GENERATOR;
+        assert!(compute_root(&non_residue).is_none());
+        let x = F::rand(&mut rng);
+        assert!(compute_root(&(non_residue * x * x)).is_none());
+    }
+
+    #[test]
+    fn test_cached_domain_powers() {
+        for n in [64, 128] {
+            let info = domain_info(n);
+            assert!(std::ptr::eq(info, domain_info(n)));
+
+            let ω = multiplicative_subgroup_of_size(n as u64);
+            let fresh = (0..n)
+                .map(|i| compute_power(&ω, i as u64))
+                .collect::<Vec<F>>();
+            assert_eq!(info.powers, fresh);
+        }
+    }
+
+    #[test]
+    fn test_lagrange_basis_at() {
+        let mut rng = StdRng::from_seed([17u8; 32]);
+        for n in [2u64, 8, 64] {
+            let basis = (0..n)
+                .map(|i| compute_lagrange_basis(i, n))
+                .collect::<Vec<DensePolynomial<F>>>();
+
+            let ω = multiplicative_subgroup_of_size(n);
+            for z in [F::rand(&mut rng), F::rand(&mut rng), ω, F::from(1u64)] {
+                let expected = basis.iter().map(|l| l.evaluate(&z)).collect::<Vec<F>>();
+                assert_eq!(lagrange_basis_at(z, n), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_evaluate_over_domain() {
+        let mut rng = StdRng::from_seed([19u8; 32]);
+        for (n, degree) in [(8u64, 5), (64, 63), (64, 130)] {
+            let f = DensePolynomial::<F>::rand(degree, &mut rng);
+            let expected = domain_info(n as usize)
+                .powers
+                .iter()
+                .map(|w| f.evaluate(w))
+                .collect::<Vec<F>>();
+            assert_eq!(evaluate_over_domain(&f, n), expected);
+        }
+    }
+
+    #[test]
+    fn test_coset_quotient_matches_division() {
+        let mut rng = StdRng::from_seed([23u8; 32]);
+        let shift = F::GENERATOR;
+
+        for n in [8u64, 64] {
+            let z_h = compute_vanishing_poly(n as usize);
+            let a = DensePolynomial::<F>::rand(n as usize - 1, &mut rng);
+            let b = DensePolynomial::<F>::rand(n as usize - 1, &mut rng);
+            let numerator = &(&a * &b) - &(&a + &b);
+
+            // remove the remainder so that numerator is divisible by X^n - 1
+            let domain = GeneralEvaluationDomain::<F>::new(n as usize).unwrap();
+            let (expected, remainder) = numerator.divide_by_vanishing_poly(domain).unwrap();
+            let divisible = &numerator - &remainder;
+
+            let mut coeffs = divisible.coeffs.clone();
+            coeffs.resize(2 * n as usize, F::zero());
+            let evals = coset_fft(&coeffs, shift);
+            assert_eq!(
+                evals[1],
+                divisible.evaluate(&(shift * domain_info(2 * n as usize).powers[1]))
+            );
+
+            let quotient_evals = divide_by_vanishing_on_coset(&evals, n, shift);
+            let quotient =
+                DensePolynomial::from_coefficients_vec(coset_ifft(&quotient_evals, shift));
+            assert_eq!(quotient, expected);
+            assert_eq!(&quotient * &z_h, divisible);
+
+            let z = F::rand(&mut rng);
+            assert_eq!(vanishing_poly_eval(n, z), z_h.evaluate(&z));
+        }
+    }
+
+    #[test]
+    fn test_poseidon_config() {
+        let config = poseidon_config();
+        let width = POSEIDON_RATE + 1;
+        assert_eq!(
+            config.ark.len(),
+            POSEIDON_FULL_ROUNDS + POSEIDON_PARTIAL_ROUNDS
+        );
+        assert!(config.ark.iter().all(|round| round.len() == width));
+        assert_eq!(config.alpha, POSEIDON_ALPHA);
+
+        // x^alpha is a permutation of the field iff alpha is coprime to r - 1
+        let r_minus_1: BigUint = F::MODULUS.into();
+        let r_minus_1 = r_minus_1 - 1u32;
+        assert_ne!(r_minus_1 % POSEIDON_ALPHA, BigUint::from(0u32));
+
+        // the MDS matrix is a Cauchy matrix 1 / (x_i + y_j), so the difference
+        // of the inverses of two columns is the same on every row
+        assert_eq!(config.mds.len(), width);
+        let inv = |i: usize, j: usize| config.mds[i][j].inverse().unwrap();
+        for j in 1..width {
+            for i in 1..width {
+                assert_eq!(inv(i, j) - inv(i, 0), inv(0, j) - inv(0, 0));
+            }
+        }
+    }
+
+    #[test]
+    fn test_fs_hash_poseidon() {
+        let inputs = [F::from(1u64), F::from(2u64)];
+        let outputs = fs_hash_poseidon(&inputs, 3);
+        assert_eq!(outputs.len(), 3);
+        assert_eq!(outputs, fs_hash_poseidon(&inputs, 3));
+        assert_ne!(outputs[0], outputs[1]);
+
+        // a trailing zero input or byte still changes the hash
+        let padded = [F::from(1u64), F::from(2u64), F::zero()];
+        assert_ne!(fs_hash_poseidon(&padded, 1)[0], outputs[0]);
+        assert_ne!(bytes_to_fields::<F>(&[1]), bytes_to_fields::<F>(&[1, 0]));
+    }
 }
@@ -0,0 +1,440 @@
+//! Verifiers follow a game without holding shares: they rebuild every value the
+//! players open from the shares the players broadcast, and check the proofs the
+//! players publish, as the game goes.
+
+use futures::{stream, Stream, StreamExt};
+use libp2p::identity;
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use crate::address_book::{PeerRole, Pok3rAddrBook, Pok3rPeerId};
+use crate::audit::{self, AuditEntry};
+use crate::common::{Curve, EvalNetMsg, Gt, ProtocolParams, F, G1, G2};
+use crate::encoding::{
+    decode_bs58_str_as_f, decode_bs58_str_as_g1, decode_bs58_str_as_g2, decode_bs58_str_as_gt,
+    payload_element_size, unpack_batch_values,
+};
+use crate::evaluator::{open_commitment, PROOF_HANDLE_PREFIX};
+use crate::kzg::UniversalParams;
+use crate::network::{
+    check_signature, expand_msg, peer_keys_of, Inbox, NetworkError, DEFAULT_MAX_EXPANDED_SIZE,
+};
+use crate::reconstruct::reconstruct_checked_by_sender;
+
+/// a value the players opened, of the kind of its shares
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpenedValue {
+    Scalar(F),
+    G1(G1),
+    G2(G2),
+    Gt(Gt),
+}
+
+impl OpenedValue {
+    /// decodes a share, telling its kind from its length
+    fn decode(value: &str) -> Option<Self> {
+        decode_bs58_str_as_f(value)
+            .map(OpenedValue::Scalar)
+            .or_else(|_| decode_bs58_str_as_g1(value).map(OpenedValue::G1))
+            .or_else(|_| decode_bs58_str_as_g2(value).map(OpenedValue::G2))
+            .or_else(|_| decode_bs58_str_as_gt(value).map(OpenedValue::Gt))
+            .ok()
+    }
+}
+
+/// GameEvent is what a verifier observes
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GameEvent {
+    /// the players opened value under handle
+    Opened { handle: String, value: OpenedValue },
+    /// the proof published under handle verifies
+    ProofVerified { handle: String, entry: AuditEntry },
+    /// what sender published under handle is not a valid proof
+    InvalidProof { handle: String, sender: u64 },
+}
+
+/// VerifierNode follows the broadcasts of a game from the channel of its networkd.
+/// It holds no shares and sends nothing, so the players never wait on it. Values
+/// opened with batch_output_wire_packed are not followed.
+pub struct VerifierNode {
+    session_id: String,
+    addr_book: Pok3rAddrBook,
+    peer_keys: HashMap<Pok3rPeerId, identity::PublicKey>,
+    pp: UniversalParams<Curve>,
+    params: ProtocolParams,
+    threshold: Option<u64>,
+    rx: Inbox,
+    /// shares received so far, by handle and sender
+    shares: HashMap<String, HashMap<u64, OpenedValue>>,
+    /// commitments of OpenMode::Committed, by the handle they commit to and sender
+    commitments: HashMap<String, HashMap<u64, String>>,
+    /// handles opened or proven already; whatever else comes for them is dropped
+    done: HashSet<String>,
+    events: VecDeque<GameEvent>,
+}
+
+impl VerifierNode {
+    /// follows the game session_id among the players of addr_book, whose values
+    /// are shared with degree threshold Shamir sharings if set, and additively if not;
+    /// rx delivers what the networkd receives; fails if an addr book entry carries
+    /// no key to check signatures with
+    pub fn new(
+        session_id: &str,
+        addr_book: Pok3rAddrBook,
+        pp: UniversalParams<Curve>,
+        params: ProtocolParams,
+        threshold: Option<u64>,
+        rx: impl Stream<Item = EvalNetMsg> + Send + Sync + 'static,
+    ) -> Result<Self, NetworkError> {
+        let peer_keys = peer_keys_of(&addr_book)?;
+        Ok(VerifierNode {
+            session_id: session_id.to_string(),
+            addr_book,
+            peer_keys,
+            pp,
+            params,
+            threshold,
+            rx: Box::pin(rx.fuse()),
+            shares: HashMap::new(),
+            commitments: HashMap::new(),
+            done: HashSet::new(),
+            events: VecDeque::new(),
+        })
+    }
+
+    /// waits for the next event; returns None once the networkd is gone
+    pub async fn next_event(&mut self) -> Option<GameEvent> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Some(event);
+            }
+            let msg = self.rx.next().await?;
+            self.process_message(&msg);
+        }
+    }
+
+    /// the events of the game, as they happen
+    pub fn into_stream(self) -> impl Stream<Item = GameEvent> {
+        stream::unfold(self, |mut node| async move {
+            let event = node.next_event().await?;
+            Some((event, node))
+        })
+    }
+
+    fn process_message(&mut self, msg: &EvalNetMsg) {
+        let (session, sender) = match msg {
+            EvalNetMsg::PublishValue {
+                session, sender, ..
+            }
+            | EvalNetMsg::PublishBatchValue {
+                session, sender, ..
+            }
+            | EvalNetMsg::PublishBatchBinary {
+                session, sender, ..
+            } => (session, sender),
+            // proofs come to verifiers directly
+            EvalNetMsg::DirectValue {
+                session,
+                sender,
+                recipient,
+                handle,
+                ..
+            } if handle.starts_with(PROOF_HANDLE_PREFIX)
+                && self.addr_book.role_of(recipient) == Some(PeerRole::Verifier) =>
+            {
+                (session, sender)
+            }
+            // other direct values are secret, and acks and greetings tell us nothing
+            _ => return,
+        };
+        if !self.session_id.eq(session) || check_signature(msg, &self.peer_keys).is_err() {
+            return;
+        }
+        let sender = match self.addr_book.node_of(sender) {
+            Some(node_id) => node_id,
+            None => return,
+        };
+        let msg = match expand_msg(msg, DEFAULT_MAX_EXPANDED_SIZE) {
+            Ok(msg) => msg,
+            Err(_) => return,
+        };
+
+        match msg.as_ref() {
+            EvalNetMsg::PublishValue { handle, value, .. }
+            | EvalNetMsg::DirectValue { handle, value, .. } => {
+                self.accept(sender, handle, value);
+            }
+            EvalNetMsg::PublishBatchValue {
+                handles, values, ..
+            } => {
+                let values = match unpack_batch_values(handles.len(), values) {
+                    Ok(values) => values,
+                    Err(_) => return,
+                };
+                for (h, v) in handles.iter().zip(values.iter()) {
+                    self.accept(sender, h, v);
+                }
+            }
+            EvalNetMsg::PublishBatchBinary {
+                handles,
+                payload,
+                kind,
+                ..
+            } => {
+                let size = payload_element_size(*kind);
+                if payload.len() != size * handles.len() {
+                    return;
+                }
+                for (h, v) in handles.iter().zip(payload.chunks(size)) {
+                    self.accept(sender, h, &bs58::encode(v).into_string());
+                }
+            }
+            _ => (),
+        }
+    }
+
+    fn accept(&mut self, sender: u64, handle: &str, value: &str) {
+        if self.done.contains(handle) {
+            return;
+        }
+        if handle.starts_with(PROOF_HANDLE_PREFIX) {
+            self.check_proof(sender, handle, value);
+            return;
+        }
+        if let Some(committed) = handle.strip_suffix("/commit") {
+            self.commitments
+                .entry(committed.to_string())
+                .or_default()
+                .insert(sender, value.to_string());
+            return;
+        }
+
+        // a committed opening counts only if it matches what its sender committed to
+        let share = if value.contains('.') {
+            let commitment = self
+                .commitments
+                .get(handle)
+                .and_then(|commitments| commitments.get(&sender));
+            match commitment.and_then(|commitment| open_commitment(value, commitment)) {
+                Some(share) => OpenedValue::decode(&share),
+                None => None,
+            }
+        } else {
+            OpenedValue::decode(value)
+        };
+        let share = match share {
+            Some(share) => share,
+            None => return,
+        };
+
+        let shares = self.shares.entry(handle.to_string()).or_default();
+        shares.insert(sender, share);
+        let needed = match self.threshold {
+            Some(t) => t as usize + 1,
+            None => self.addr_book.len(),
+        };
+        if shares.len() < needed {
+            return;
+        }
+
+        let shares = self.shares.remove(handle).unwrap();
+        self.commitments.remove(handle);
+        self.done.insert(handle.to_string());
+        let committee = self
+            .addr_book
+            .iter_ordered()
+            .map(|peer| peer.node_id)
+            .collect::<Vec<u64>>();
+        if let Some(value) = reconstruct_opened(&shares, self.threshold, &committee) {
+            self.events.push_back(GameEvent::Opened {
+                handle: handle.to_string(),
+                value,
+            });
+        }
+    }
+
+    /// every player publishes the same proof, so the first one that verifies settles it
+    fn check_proof(&mut self, sender: u64, handle: &str, value: &str) {
+        let entry = bs58::decode(value)
+            .into_vec()
+            .ok()
+            .and_then(|bytes| serde_json::from_slice::<AuditEntry>(&bytes).ok());
+        let valid = match &entry {
+            Some(AuditEntry::Opening { .. }) | None => false,
+            Some(entry) => {
+                audit::verify_entry(entry, &self.addr_book, &self.pp, &self.params, 0).is_ok()
+            }
+        };
+        let event = match entry {
+            Some(entry) if valid => {
+                self.done.insert(handle.to_string());
+                GameEvent::ProofVerified {
+                    handle: handle.to_string(),
+                    entry,
+                }
+            }
+            _ => GameEvent::InvalidProof {
+                handle: handle.to_string(),
+                sender,
+            },
+        };
+        self.events.push_back(event);
+    }
+}
+
+/// reconstructs shares that are all of the same kind and all from the committee
+fn reconstruct_opened(
+    shares: &HashMap<u64, OpenedValue>,
+    threshold: Option<u64>,
+    committee: &[u64],
+) -> Option<OpenedValue> {
+    fn of_kind<V>(
+        shares: &HashMap<u64, OpenedValue>,
+        pick: impl Fn(&OpenedValue) -> Option<V>,
+    ) -> Option<HashMap<u64, V>> {
+        shares
+            .iter()
+            .map(|(sender, share)| pick(share).map(|share| (*sender, share)))
+            .collect()
+    }
+
+    match shares.values().next()? {
+        OpenedValue::Scalar(_) => of_kind(shares, |share| match share {
+            OpenedValue::Scalar(x) => Some(*x),
+            _ => None,
+        })
+        .and_then(|shares| reconstruct_checked_by_sender(&shares, threshold, committee).ok())
+        .map(OpenedValue::Scalar),
+        OpenedValue::G1(_) => of_kind(shares, |share| match share {
+            OpenedValue::G1(x) => Some(*x),
+            _ => None,
+        })
+        .and_then(|shares| reconstruct_checked_by_sender(&shares, threshold, committee).ok())
+        .map(OpenedValue::G1),
+        OpenedValue::G2(_) => of_kind(shares, |share| match share {
+            OpenedValue::G2(x) => Some(*x),
+            _ => None,
+        })
+        .and_then(|shares| reconstruct_checked_by_sender(&shares, threshold, committee).ok())
+        .map(OpenedValue::G2),
+        OpenedValue::Gt(_) => of_kind(shares, |share| match share {
+            OpenedValue::Gt(x) => Some(*x),
+            _ => None,
+        })
+        .and_then(|shares| reconstruct_checked_by_sender(&shares, threshold, committee).ok())
+        .map(OpenedValue::Gt),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GameEvent, OpenedValue, VerifierNode};
+    use crate::audit::AuditEntry;
+    use crate::common::{ProtocolParams, F};
+    use crate::evaluator::Evaluator;
+    use crate::hash::{card_id, GameContext, IdCache};
+    use crate::shuffler::{
+        compute_keyper_keys, compute_params, compute_permutation_argument, encrypt_and_prove,
+        shuffle_deck,
+    };
+    use crate::transport::{InMemoryTransport, Transport};
+    use futures::future::{join_all, try_join_all};
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    #[test]
+    fn test_verifier_sees_the_community_cards_and_checks_the_proofs() {
+        let params = ProtocolParams::new(16, 10).unwrap();
+        let pp = compute_params();
+        let (_, mpk) = compute_keyper_keys();
+        let ctx = GameContext::new([7u8; 32]);
+        let ids = (0..params.perm_size())
+            .map(|i| card_id(&ctx, i as u64, 0))
+            .collect::<Vec<Vec<u8>>>();
+
+        async_std::task::block_on(async {
+            let (transports, mut verifier_rxs) =
+                InMemoryTransport::new_network_with_verifiers(4, 1).await;
+            let addr_book = transports[0].addr_book().clone();
+            let mut verifier = VerifierNode::new(
+                transports[0].session_id(),
+                addr_book,
+                pp.clone(),
+                params,
+                None,
+                verifier_rxs.pop().unwrap(),
+            )
+            .unwrap();
+
+            let evaluators =
+                try_join_all(transports.into_iter().enumerate().map(|(i, transport)| {
+                    Evaluator::new_with_seed_and_params(transport, [i as u8 + 1; 32], params)
+                }))
+                .await
+                .unwrap();
+            let outputs = join_all(evaluators.into_iter().map(|mut evaluator| {
+                let pp = pp.clone();
+                let ids = ids.clone();
+                async move {
+                    evaluator.set_publish_proofs(true);
+                    let cards = shuffle_deck(&mut evaluator).await.unwrap();
+                    let (perm_proof, alpha1) =
+                        compute_permutation_argument(&pp, &mut evaluator, &cards)
+                            .await
+                            .unwrap();
+                    let _ = encrypt_and_prove(
+                        &pp,
+                        &mut evaluator,
+                        &mut IdCache::new(ctx, ids.len()),
+                        cards.clone(),
+                        perm_proof.f_com,
+                        alpha1,
+                        mpk,
+                        ids,
+                    )
+                    .await
+                    .unwrap();
+
+                    let community = &cards[..5];
+                    let values = evaluator.batch_output_wire(community).await.unwrap();
+                    community
+                        .iter()
+                        .map(|wire| wire.to_string())
+                        .zip(values)
+                        .collect::<Vec<(String, F)>>()
+                }
+            }))
+            .await;
+            for other in outputs.iter().skip(1) {
+                assert_eq!(other, &outputs[0]);
+            }
+            let community = &outputs[0];
+
+            let mut opened = HashMap::new();
+            let mut proofs = vec![];
+            let follow = async {
+                while community.iter().any(|(h, _)| !opened.contains_key(h)) || proofs.len() < 2 {
+                    match verifier.next_event().await.unwrap() {
+                        GameEvent::Opened { handle, value } => {
+                            opened.insert(handle, value);
+                        }
+                        GameEvent::ProofVerified { entry, .. } => proofs.push(entry),
+                        GameEvent::InvalidProof { handle, sender } => {
+                            panic!("proof {} of party {} does not verify", handle, sender)
+                        }
+                    }
+                }
+            };
+            async_std::future::timeout(Duration::from_secs(60), follow)
+                .await
+                .unwrap();
+
+            for (handle, value) in community {
+                assert_eq!(opened[handle], OpenedValue::Scalar(*value));
+            }
+            assert!(matches!(proofs[0], AuditEntry::PermutationProof { .. }));
+            assert!(matches!(proofs[1], AuditEntry::EncryptionProof { .. }));
+        });
+    }
+}
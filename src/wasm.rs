@@ -0,0 +1,125 @@
+//! The checks web clients run, on the bytes a server hands them. With the wasm
+//! feature they are exported through wasm-bindgen; natively they are plain
+//! functions, so the tests call exactly what the browser calls.
+
+use rand::rngs::OsRng;
+#[cfg(feature = "wasm")]
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::common::{Curve, PermutationProof, ProtocolParams, G1};
+use crate::encoding::{decode_from_bytes, ProofEncoding};
+use crate::kzg::UniversalParams;
+
+/// checks that proof_bytes, a PermutationProof in its ProofEncoding, proves that
+/// commitment_bytes, an encoded G1 element, commits to a shuffle of the default
+/// deck. params_bytes are the KZG params, compressed as write_to_file saves them
+/// or as a .ptau file; they are validated before use. Anything malformed fails.
+#[cfg_attr(feature = "wasm", wasm_bindgen)]
+pub fn verify_shuffle(proof_bytes: &[u8], commitment_bytes: &[u8], params_bytes: &[u8]) -> bool {
+    let params = ProtocolParams::default();
+    let proof = match PermutationProof::from_bytes(proof_bytes) {
+        Ok(proof) => proof,
+        Err(_) => return false,
+    };
+    let commitment: G1 = match decode_from_bytes(commitment_bytes) {
+        Ok(commitment) => commitment,
+        Err(_) => return false,
+    };
+    let pp = match UniversalParams::<Curve>::from_bytes(
+        params_bytes,
+        2 * params.perm_size(),
+        &mut OsRng,
+    ) {
+        Ok(pp) => pp,
+        Err(_) => return false,
+    };
+    proof.f_com == commitment && proof.verify(&pp, &params)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::verify_shuffle;
+    use crate::common::{Curve, PermutationProof, F, G1};
+    use crate::encoding::{encode_as_bytes, ProofEncoding};
+    use crate::kzg::KZG10;
+    use crate::transcript::FsHasher;
+    use ark_poly::univariate::DensePolynomial;
+    use ark_serialize::CanonicalSerialize;
+    use ark_std::UniformRand;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    #[test]
+    fn test_verify_shuffle_rejects_bad_input() {
+        let mut rng = StdRng::from_seed([9u8; 32]);
+        let pp = KZG10::<Curve, DensePolynomial<F>>::setup(128, &mut rng);
+        let mut pp_bytes = Vec::new();
+        pp.serialize_compressed(&mut pp_bytes).unwrap();
+
+        let proof: PermutationProof = PermutationProof {
+            y1: F::rand(&mut rng),
+            y2: F::rand(&mut rng),
+            y3: F::rand(&mut rng),
+            y4: F::rand(&mut rng),
+            y5: F::rand(&mut rng),
+            pi_1: G1::rand(&mut rng),
+            pi_2: G1::rand(&mut rng),
+            pi_3: G1::rand(&mut rng),
+            pi_4: G1::rand(&mut rng),
+            pi_5: G1::rand(&mut rng),
+            f_com: G1::rand(&mut rng),
+            q_com: G1::rand(&mut rng),
+            t_com: G1::rand(&mut rng),
+            hasher: FsHasher::Poseidon,
+        };
+        let proof_bytes = proof.to_bytes();
+        let commitment = encode_as_bytes(&proof.f_com);
+
+        // a proof that does not hold, then the same against another commitment
+        assert!(!verify_shuffle(&proof_bytes, &commitment, &pp_bytes));
+        let other = encode_as_bytes(&G1::rand(&mut rng));
+        assert!(!verify_shuffle(&proof_bytes, &other, &pp_bytes));
+
+        // truncated proofs, commitments and params
+        assert!(!verify_shuffle(&proof_bytes[..10], &commitment, &pp_bytes));
+        assert!(!verify_shuffle(&proof_bytes, &commitment[1..], &pp_bytes));
+        assert!(!verify_shuffle(&proof_bytes, &commitment, &pp_bytes[..100]));
+        assert!(!verify_shuffle(&[], &[], &[]));
+
+        // params of too low a degree for the default deck
+        let small = KZG10::<Curve, DensePolynomial<F>>::setup(16, &mut rng);
+        let mut small_bytes = Vec::new();
+        small.serialize_compressed(&mut small_bytes).unwrap();
+        assert!(!verify_shuffle(&proof_bytes, &commitment, &small_bytes));
+    }
+
+    #[cfg(feature = "networking")]
+    #[test]
+    fn test_verify_shuffle_accepts_a_real_shuffle() {
+        use crate::shuffler::{compute_params, compute_permutation_argument, shuffle_deck};
+        use crate::simulator::run_parties_with_seed;
+
+        let pp = compute_params();
+        let mut proofs = run_parties_with_seed(3, 13, |mut evaluator| {
+            let pp = pp.clone();
+            async move {
+                let cards = shuffle_deck(&mut evaluator).await.unwrap();
+                let (proof, _) = compute_permutation_argument(&pp, &mut evaluator, &cards)
+                    .await
+                    .unwrap();
+                proof
+            }
+        });
+        let proof = proofs.remove(0);
+        let mut pp_bytes = Vec::new();
+        pp.serialize_compressed(&mut pp_bytes).unwrap();
+        let proof_bytes = proof.to_bytes();
+        let commitment = encode_as_bytes(&proof.f_com);
+
+        assert!(verify_shuffle(&proof_bytes, &commitment, &pp_bytes));
+
+        // y1, the first scalar after the version and curve bytes, off by one bit
+        let mut flipped = proof_bytes.clone();
+        flipped[2] ^= 1;
+        assert!(!verify_shuffle(&flipped, &commitment, &pp_bytes));
+    }
+}
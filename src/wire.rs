@@ -0,0 +1,104 @@
+use std::borrow::Borrow;
+use std::fmt;
+use std::sync::Arc;
+
+/// Wire is a handle to a secret-shared value held by an Evaluator. Clones share the
+/// label instead of copying it, and the label is what goes over the network, so
+/// its string form is the same bs58 label handles used to be.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Wire(Arc<str>);
+
+impl Wire {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for Wire {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl From<&str> for Wire {
+    fn from(label: &str) -> Self {
+        Wire(Arc::from(label))
+    }
+}
+
+impl From<String> for Wire {
+    fn from(label: String) -> Self {
+        Wire(Arc::from(label))
+    }
+}
+
+impl From<&String> for Wire {
+    fn from(label: &String) -> Self {
+        Wire::from(label.as_str())
+    }
+}
+
+impl AsRef<str> for Wire {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+/// lets maps keyed by wires be queried with a plain label
+impl Borrow<str> for Wire {
+    fn borrow(&self) -> &str {
+        &self.0
+    }
+}
+
+/// separates the parts of derived_label; it is not a bs58 character, so it never
+/// shows up inside a wire label
+const LABEL_SEPARATOR: char = '/';
+
+/// the identifier of a value derived from wires, e.g. revealed from them; unlike
+/// plain concatenation, different names or wires never give the same identifier
+pub fn derived_label(name: &str, wires: &[&Wire]) -> String {
+    debug_assert!(!name.contains(LABEL_SEPARATOR));
+    let mut label = name.to_string();
+    for wire in wires {
+        label.push(LABEL_SEPARATOR);
+        label.push_str(wire.as_str());
+    }
+    label
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{derived_label, Wire};
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_wire_keeps_the_label() {
+        let label = String::from("3vQB7B6MrGQZaxCuFg4oh");
+        let wire = Wire::from(&label);
+        assert_eq!(wire.to_string(), label);
+        assert_eq!(wire, Wire::from(label.as_str()));
+
+        let copy = wire.clone();
+        assert!(std::ptr::eq(copy.as_str(), wire.as_str()));
+
+        let mut shares = HashMap::new();
+        shares.insert(wire, 7u64);
+        assert_eq!(shares.get(label.as_str()), Some(&7));
+    }
+
+    #[test]
+    fn test_derived_labels_are_unambiguous() {
+        let (ab, c) = (Wire::from("ab"), Wire::from("c"));
+        let (a, bc) = (Wire::from("a"), Wire::from("bc"));
+        assert_ne!(
+            derived_label("x", &[&ab, &c]),
+            derived_label("x", &[&a, &bc])
+        );
+        assert_ne!(
+            derived_label("x", &[&ab]),
+            derived_label("xa", &[&Wire::from("b")])
+        );
+        assert_eq!(derived_label("ibe_c1", &[&ab, &c]), "ibe_c1/ab/c");
+    }
+}